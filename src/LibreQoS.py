@@ -1913,9 +1913,10 @@ def refreshShapers():
                                 return sqmFixupRate(rate, base)
                             if chosen == 'none':
                                 return ''
-                            if chosen == 'fq_codel':
-                                return 'fq_codel'
-                            if chosen == 'cake':
+                            if chosen in ('fq_codel', 'fq_pie', 'sfq'):
+                                return chosen
+                            # Named profiles (cake:<name>) are expanded by the Bakery
+                            if chosen == 'cake' or chosen.startswith('cake:'):
                                 cake_base = base if base.startswith('cake') else 'cake diffserv4'
                                 return sqmFixupRate(rate, cake_base)
                             return sqmFixupRate(rate, base)
@@ -162,6 +162,22 @@ pub enum BakeryCommands {
         /// New maximum (ceiling) upload rate in Mbps.
         upload_bandwidth_max: f32,
     },
+    /// Change an existing circuit's HTB rates live without a rebuild.
+    ///
+    /// The circuit keeps its class and qdisc handles; its queues are rebuilt
+    /// in place at the new rates, following the lazy queue mode.
+    ChangeCircuitSpeedLive {
+        /// Unique identifier for the target circuit.
+        circuit_hash: i64,
+        /// New minimum (guaranteed) download rate in Mbps.
+        download_bandwidth_min: f32,
+        /// New minimum (guaranteed) upload rate in Mbps.
+        upload_bandwidth_min: f32,
+        /// New maximum (ceiling) download rate in Mbps.
+        download_bandwidth_max: f32,
+        /// New maximum (ceiling) upload rate in Mbps.
+        upload_bandwidth_max: f32,
+    },
     /// Begin a batch of changes; subsequent commands are queued until commit.
     StartBatch,
     /// Commit the current batch, diffing and applying queued changes.
@@ -1349,4 +1365,72 @@ mod tests {
         );
         MQ_CREATED.store(false, std::sync::atomic::Ordering::Relaxed);
     }
+
+    #[test]
+    fn add_circuit_expands_named_sqm_profiles_per_direction() {
+        let mut cfg = Config::default();
+        cfg.queues.sqm_profiles = vec![
+            lqos_config::SqmProfile {
+                name: "dsl".to_string(),
+                diffserv: Some(lqos_config::CakeDiffserv::Diffserv4),
+                nat: true,
+                overhead: Some(44),
+                atm: Some(lqos_config::CakeAtmMode::Atm),
+                ..Default::default()
+            },
+            lqos_config::SqmProfile {
+                name: "fibre".to_string(),
+                ack_filter: true,
+                rtt_ms: Some(50),
+                ..Default::default()
+            },
+        ];
+        let config = Arc::new(cfg);
+        let sqm_args = |sqm_override: &str, rate: f32| {
+            let commands = BakeryCommands::AddCircuit {
+                circuit_hash: 43,
+                circuit_name: None,
+                site_name: None,
+                parent_class_id: crate::TcHandle::from_u32(0x10020),
+                up_parent_class_id: crate::TcHandle::from_u32(0x20020),
+                class_minor: 0x22,
+                download_bandwidth_min: 1.0,
+                upload_bandwidth_min: 1.0,
+                download_bandwidth_max: rate,
+                upload_bandwidth_max: rate,
+                class_major: 0x1,
+                up_class_major: 0x2,
+                down_qdisc_handle: Some(0x9002),
+                up_qdisc_handle: Some(0x9003),
+                ip_addresses: "192.0.2.43/32".to_string(),
+                sqm_override: Some(sqm_override.to_string()),
+            }
+            .to_commands(&config, ExecutionMode::Builder)
+            .expect("add_circuit should emit commands");
+            commands
+                .into_iter()
+                .filter(|cmd| cmd[0] == "qdisc")
+                .map(|cmd| {
+                    let kind = cmd
+                        .iter()
+                        .position(|arg| arg == "cake")
+                        .expect("cake qdisc");
+                    cmd[kind..].join(" ")
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sqm_args("cake:dsl/cake:fibre", 2.0),
+            vec![
+                "cake diffserv4 nat overhead 44 atm rtt 180ms",
+                "cake ack-filter rtt 50ms",
+            ]
+        );
+        // Unknown profiles fall back to plain cake
+        assert_eq!(
+            sqm_args("cake:missing", 100.0),
+            vec!["cake diffserv4", "cake diffserv4"]
+        );
+    }
 }
@@ -23,6 +23,7 @@ mod commands;
 mod diff;
 mod qdisc_handles;
 mod queue_math;
+mod tc_netlink;
 mod utils;
 
 use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
@@ -57,8 +58,9 @@ pub use commands::{
     RuntimeNodeOperationStatus as BakeryRuntimeNodeOperationStatus,
 };
 use lqos_bus::{
-    BusRequest, BusResponse, InsightLicenseSummary, LibreqosBusClient, TcHandle, UrgentSeverity,
-    UrgentSource,
+    BakeryEvent, BusRequest, BusResponse, BusTopic, BusUpdate, InsightLicenseSummary,
+    LibreqosBusClient, TcHandle, UrgentSeverity, UrgentSource, has_bus_subscribers,
+    publish_bus_update,
 };
 use lqos_config::{
     CircuitIdentityGroupInput, ClassIdentityPlannerConstraints, Config, LazyQueueMode,
@@ -945,6 +947,16 @@ pub const INFRA_QDISC_ESTIMATED_MEMORY_BYTES: u64 = 16 * 1024;
 /// This remains intentionally lower than CAKE, but is biased high enough to
 /// prefer false-positive safety blocks over OOM risk on large reloads.
 pub const FQ_CODEL_QDISC_ESTIMATED_MEMORY_BYTES: u64 = 64 * 1024;
+/// Conservative estimated kernel-memory cost of an `fq_pie` leaf qdisc.
+///
+/// fq_pie allocates the same 1024-flow table as fq_codel, but each flow
+/// carries its own PIE state, so the weight is roughly double.
+pub const FQ_PIE_QDISC_ESTIMATED_MEMORY_BYTES: u64 = 128 * 1024;
+/// Conservative estimated kernel-memory cost of an `sfq` leaf qdisc.
+///
+/// sfq keeps a fixed 128-slot table with no per-flow AQM state, which makes
+/// it the low-memory choice for very large circuit counts.
+pub const SFQ_QDISC_ESTIMATED_MEMORY_BYTES: u64 = 16 * 1024;
 /// Conservative estimated kernel-memory cost of a `cake` leaf qdisc.
 ///
 /// Tuned upward after live production capture showed summed CAKE runtime memory
@@ -996,6 +1008,10 @@ pub struct BakeryCapacityInterfaceSnapshot {
     pub cake_qdiscs: usize,
     /// Planned `fq_codel` leaf qdiscs for that interface.
     pub fq_codel_qdiscs: usize,
+    /// Planned `fq_pie` leaf qdiscs for that interface.
+    pub fq_pie_qdiscs: usize,
+    /// Planned `sfq` leaf qdiscs for that interface.
+    pub sfq_qdiscs: usize,
     /// Estimated kernel memory cost for that interface's planned qdiscs.
     pub estimated_memory_bytes: u64,
 }
@@ -1415,6 +1431,16 @@ fn push_bakery_event_with_site_name(
         site_name,
         summary,
     };
+    if has_bus_subscribers(BusTopic::Bakery) {
+        publish_bus_update(BusUpdate::BakeryEvent(BakeryEvent {
+            ts: entry.ts,
+            event: entry.event.clone(),
+            status: entry.status.clone(),
+            site_hash: entry.site_hash,
+            site_name: entry.site_name.clone(),
+            summary: entry.summary.clone(),
+        }));
+    }
     let mut state = telemetry_state().write();
     state.activity.push_front(entry);
     while state.activity.len() > BAKERY_EVENT_LIMIT {
@@ -1735,6 +1761,10 @@ pub struct QdiscInterfaceEstimate {
     pub cake_qdiscs: usize,
     /// Planned fq_codel leaf qdiscs for the interface.
     pub fq_codel_qdiscs: usize,
+    /// Planned fq_pie leaf qdiscs for the interface.
+    pub fq_pie_qdiscs: usize,
+    /// Planned sfq leaf qdiscs for the interface.
+    pub sfq_qdiscs: usize,
     /// Estimated kernel memory cost for the interface's planned qdiscs.
     pub estimated_memory_bytes: u64,
 }
@@ -1778,6 +1808,8 @@ enum PlannedQdiscKind {
     Infra,
     Cake,
     FqCodel,
+    FqPie,
+    Sfq,
 }
 
 fn planned_qdisc_kind(argv: &[String]) -> Option<PlannedQdiscKind> {
@@ -1788,21 +1820,17 @@ fn planned_qdisc_kind(argv: &[String]) -> Option<PlannedQdiscKind> {
         return None;
     }
 
-    if argv.iter().any(|arg| arg == "cake") {
-        return Some(if planned_qdisc_is_leaf(argv) {
-            PlannedQdiscKind::Cake
-        } else {
-            PlannedQdiscKind::Infra
-        });
-    }
-    if argv.iter().any(|arg| arg == "fq_codel") {
-        return Some(if planned_qdisc_is_leaf(argv) {
-            PlannedQdiscKind::FqCodel
-        } else {
-            PlannedQdiscKind::Infra
-        });
+    let leaf_kind = argv.iter().find_map(|arg| match arg.as_str() {
+        "cake" => Some(PlannedQdiscKind::Cake),
+        "fq_codel" => Some(PlannedQdiscKind::FqCodel),
+        "fq_pie" => Some(PlannedQdiscKind::FqPie),
+        "sfq" => Some(PlannedQdiscKind::Sfq),
+        _ => None,
+    });
+    match leaf_kind {
+        Some(kind) if planned_qdisc_is_leaf(argv) => Some(kind),
+        _ => Some(PlannedQdiscKind::Infra),
     }
-    Some(PlannedQdiscKind::Infra)
 }
 
 fn planned_qdisc_is_leaf(argv: &[String]) -> bool {
@@ -1821,6 +1849,8 @@ fn qdisc_kind_estimated_memory_bytes(kind: PlannedQdiscKind) -> u64 {
         PlannedQdiscKind::Infra => INFRA_QDISC_ESTIMATED_MEMORY_BYTES,
         PlannedQdiscKind::Cake => CAKE_QDISC_ESTIMATED_MEMORY_BYTES,
         PlannedQdiscKind::FqCodel => FQ_CODEL_QDISC_ESTIMATED_MEMORY_BYTES,
+        PlannedQdiscKind::FqPie => FQ_PIE_QDISC_ESTIMATED_MEMORY_BYTES,
+        PlannedQdiscKind::Sfq => SFQ_QDISC_ESTIMATED_MEMORY_BYTES,
     }
 }
 
@@ -1874,6 +1904,8 @@ pub fn estimate_full_reload_auto_qdisc_budget(
                             infra_qdiscs: 0,
                             cake_qdiscs: 0,
                             fq_codel_qdiscs: 0,
+                            fq_pie_qdiscs: 0,
+                            sfq_qdiscs: 0,
                             estimated_memory_bytes: 0,
                         });
                 detail.planned_qdiscs += 1;
@@ -1881,6 +1913,8 @@ pub fn estimate_full_reload_auto_qdisc_budget(
                     PlannedQdiscKind::Infra => detail.infra_qdiscs += 1,
                     PlannedQdiscKind::Cake => detail.cake_qdiscs += 1,
                     PlannedQdiscKind::FqCodel => detail.fq_codel_qdiscs += 1,
+                    PlannedQdiscKind::FqPie => detail.fq_pie_qdiscs += 1,
+                    PlannedQdiscKind::Sfq => detail.sfq_qdiscs += 1,
                 }
                 detail.estimated_memory_bytes = detail
                     .estimated_memory_bytes
@@ -3908,6 +3942,24 @@ fn bakery_main(rx: Receiver<BakeryCommands>, tx: Sender<BakeryCommands>) {
                     &mut sites,
                 );
             }
+            BakeryCommands::ChangeCircuitSpeedLive {
+                circuit_hash,
+                download_bandwidth_min,
+                upload_bandwidth_min,
+                download_bandwidth_max,
+                upload_bandwidth_max,
+            } => {
+                handle_change_circuit_speed_live(
+                    circuit_hash,
+                    download_bandwidth_min,
+                    upload_bandwidth_min,
+                    download_bandwidth_max,
+                    upload_bandwidth_max,
+                    &mut circuits,
+                    &live_circuits,
+                    &migrations,
+                );
+            }
             BakeryCommands::StormGuardAdjustment {
                 dry_run,
                 interface_name,
@@ -4484,53 +4536,11 @@ fn handle_commit_batch(
                     }
                     let was_activated = live_circuits.contains_key(circuit_hash);
                     // Fallback: immediate safe update
-                    match config.queues.lazy_queues.as_ref() {
-                        None | Some(LazyQueueMode::No) => {
-                            if let Some(prune) = enriched_cmd.to_prune(&config, true) {
-                                immediate_commands.extend(prune);
-                            }
-                            if let Some(add) =
-                                enriched_cmd.to_commands(&config, ExecutionMode::Builder)
-                            {
-                                immediate_commands.extend(add);
-                            }
-                        }
-                        Some(LazyQueueMode::Htb) => {
-                            if was_activated {
-                                if let Some(prune) = enriched_cmd.to_prune(&config, false) {
-                                    immediate_commands.extend(prune);
-                                }
-                                if let Some(add_htb) =
-                                    enriched_cmd.to_commands(&config, ExecutionMode::Builder)
-                                {
-                                    immediate_commands.extend(add_htb);
-                                }
-                                if let Some(add_qdisc) =
-                                    enriched_cmd.to_commands(&config, ExecutionMode::LiveUpdate)
-                                {
-                                    immediate_commands.extend(add_qdisc);
-                                }
-                            } else if let Some(add_htb) =
-                                enriched_cmd.to_commands(&config, ExecutionMode::Builder)
-                            {
-                                immediate_commands.extend(add_htb);
-                            }
-                        }
-                        Some(LazyQueueMode::Full) => {
-                            if was_activated {
-                                if let Some(prune) = enriched_cmd.to_prune(&config, true) {
-                                    immediate_commands.extend(prune);
-                                }
-                                if let Some(add_all) =
-                                    enriched_cmd.to_commands(&config, ExecutionMode::LiveUpdate)
-                                {
-                                    immediate_commands.extend(add_all);
-                                }
-                            } else {
-                                // No TC ops
-                            }
-                        }
-                    }
+                    immediate_commands.extend(circuit_speed_update_commands(
+                        &enriched_cmd,
+                        &config,
+                        was_activated,
+                    ));
                     circuits.insert(*circuit_hash, enriched_cmd);
                 }
             }
@@ -5118,6 +5128,173 @@ fn handle_change_site_speed_live(
     }
 }
 
+/// Builds the prune-and-rebuild sequence that applies a circuit's new rates,
+/// honouring lazy queue mode. The qdisc is removed before the class changes to
+/// avoid the kernel deadlock seen with in-place HTB changes under cake.
+fn circuit_speed_update_commands(
+    cmd: &BakeryCommands,
+    config: &Arc<Config>,
+    was_activated: bool,
+) -> Vec<Vec<String>> {
+    let mut commands = Vec::new();
+    match config.queues.lazy_queues.as_ref() {
+        None | Some(LazyQueueMode::No) => {
+            if let Some(prune) = cmd.to_prune(config, true) {
+                commands.extend(prune);
+            }
+            if let Some(add) = cmd.to_commands(config, ExecutionMode::Builder) {
+                commands.extend(add);
+            }
+        }
+        Some(LazyQueueMode::Htb) => {
+            if was_activated {
+                if let Some(prune) = cmd.to_prune(config, false) {
+                    commands.extend(prune);
+                }
+                if let Some(add_htb) = cmd.to_commands(config, ExecutionMode::Builder) {
+                    commands.extend(add_htb);
+                }
+                if let Some(add_qdisc) = cmd.to_commands(config, ExecutionMode::LiveUpdate) {
+                    commands.extend(add_qdisc);
+                }
+            } else if let Some(add_htb) = cmd.to_commands(config, ExecutionMode::Builder) {
+                commands.extend(add_htb);
+            }
+        }
+        Some(LazyQueueMode::Full) => {
+            if was_activated {
+                if let Some(prune) = cmd.to_prune(config, true) {
+                    commands.extend(prune);
+                }
+                if let Some(add_all) = cmd.to_commands(config, ExecutionMode::LiveUpdate) {
+                    commands.extend(add_all);
+                }
+            } else {
+                // No TC ops
+            }
+        }
+    }
+    commands
+}
+
+/// Returns a copy of an `AddCircuit` command with replacement rates, keeping
+/// the existing class and qdisc handles.
+fn circuit_with_rates(
+    circuit: &BakeryCommands,
+    download_bandwidth_min: f32,
+    upload_bandwidth_min: f32,
+    download_bandwidth_max: f32,
+    upload_bandwidth_max: f32,
+) -> Option<BakeryCommands> {
+    let BakeryCommands::AddCircuit {
+        circuit_hash,
+        circuit_name,
+        site_name,
+        parent_class_id,
+        up_parent_class_id,
+        class_minor,
+        class_major,
+        up_class_major,
+        down_qdisc_handle,
+        up_qdisc_handle,
+        ip_addresses,
+        sqm_override,
+        ..
+    } = circuit
+    else {
+        return None;
+    };
+    Some(BakeryCommands::AddCircuit {
+        circuit_hash: *circuit_hash,
+        circuit_name: circuit_name.clone(),
+        site_name: site_name.clone(),
+        parent_class_id: *parent_class_id,
+        up_parent_class_id: *up_parent_class_id,
+        class_minor: *class_minor,
+        download_bandwidth_min,
+        upload_bandwidth_min,
+        download_bandwidth_max,
+        upload_bandwidth_max,
+        class_major: *class_major,
+        up_class_major: *up_class_major,
+        down_qdisc_handle: *down_qdisc_handle,
+        up_qdisc_handle: *up_qdisc_handle,
+        ip_addresses: ip_addresses.clone(),
+        sqm_override: sqm_override.clone(),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_change_circuit_speed_live(
+    circuit_hash: i64,
+    download_bandwidth_min: f32,
+    upload_bandwidth_min: f32,
+    download_bandwidth_max: f32,
+    upload_bandwidth_max: f32,
+    circuits: &mut HashMap<i64, Arc<BakeryCommands>>,
+    live_circuits: &HashMap<i64, u64>,
+    migrations: &HashMap<i64, Migration>,
+) {
+    let Ok(config) = lqos_config::load_config() else {
+        error!("Failed to load configuration, skipping ChangeCircuitSpeedLive.");
+        return;
+    };
+    let Some(circuit_arc) = circuits.get(&circuit_hash) else {
+        info!(
+            "ChangeCircuitSpeedLive received for unknown circuit: {}",
+            circuit_hash
+        );
+        return;
+    };
+    if migrations.contains_key(&circuit_hash) {
+        info!(
+            "Skipping live circuit speed change for circuit {} because a migration is pending.",
+            circuit_hash
+        );
+        return;
+    }
+    let upload_bandwidth_min = if upload_bandwidth_min >= (upload_bandwidth_max - 0.5) {
+        upload_bandwidth_max - 1.0
+    } else {
+        upload_bandwidth_min
+    };
+    let download_bandwidth_min = if download_bandwidth_min >= (download_bandwidth_max - 0.5) {
+        download_bandwidth_max - 1.0
+    } else {
+        download_bandwidth_min
+    };
+    let Some(updated) = circuit_with_rates(
+        circuit_arc.as_ref(),
+        download_bandwidth_min,
+        upload_bandwidth_min,
+        download_bandwidth_max,
+        upload_bandwidth_max,
+    ) else {
+        debug!(
+            "ChangeCircuitSpeedLive received a non-circuit command: {:?}",
+            circuit_arc
+        );
+        return;
+    };
+    let updated = Arc::new(updated);
+    if let Some(reason) = live_tree_mutation_blocker_for_config(&config) {
+        let summary = format!(
+            "Skipping live circuit speed change for circuit {} because {}.",
+            circuit_hash, reason
+        );
+        info!("{summary}");
+        push_bakery_event("live_circuit_speed_skipped", "info", summary);
+        circuits.insert(circuit_hash, updated);
+        return;
+    }
+    let commands =
+        circuit_speed_update_commands(&updated, &config, live_circuits.contains_key(&circuit_hash));
+    if !commands.is_empty() {
+        execute_and_record_live_change(&commands, "changing circuit speed live");
+    }
+    circuits.insert(circuit_hash, updated);
+}
+
 fn site_class_handles(site: &BakeryCommands) -> Option<(TcHandle, TcHandle)> {
     let BakeryCommands::AddSite {
         parent_class_id,
@@ -12055,6 +12232,56 @@ mod tests {
         );
     }
 
+    #[test]
+    fn qdisc_budget_estimate_weights_fq_pie_and_sfq_leaf_qdiscs() {
+        let config = Arc::new(Config::default());
+        let queue = vec![
+            BakeryCommands::MqSetup {
+                queues_available: 1,
+                stick_offset: 0,
+            },
+            BakeryCommands::AddCircuit {
+                circuit_hash: 3,
+                circuit_name: None,
+                site_name: None,
+                parent_class_id: TcHandle::from_u32(0x10001),
+                up_parent_class_id: TcHandle::from_u32(0x20001),
+                class_minor: 0x21,
+                download_bandwidth_min: 10.0,
+                upload_bandwidth_min: 10.0,
+                download_bandwidth_max: 100.0,
+                upload_bandwidth_max: 100.0,
+                class_major: 0x100,
+                up_class_major: 0x200,
+                down_qdisc_handle: Some(0x9002),
+                up_qdisc_handle: Some(0x9003),
+                ip_addresses: "192.0.2.2/32".to_string(),
+                sqm_override: Some("fq_pie/sfq".to_string()),
+            },
+        ];
+
+        let estimate = estimate_full_reload_auto_qdisc_budget(&config, &queue);
+        // Download is shaped on the network-facing interface.
+        let down = estimate
+            .interface_details
+            .get("eth1")
+            .expect("download interface estimate");
+        let up = estimate
+            .interface_details
+            .get("eth0")
+            .expect("upload interface estimate");
+
+        assert_eq!(
+            (down.fq_pie_qdiscs, down.sfq_qdiscs, down.cake_qdiscs),
+            (1, 0, 0)
+        );
+        assert_eq!((up.fq_pie_qdiscs, up.sfq_qdiscs, up.cake_qdiscs), (0, 1, 0));
+        assert_eq!(
+            down.estimated_memory_bytes - up.estimated_memory_bytes,
+            FQ_PIE_QDISC_ESTIMATED_MEMORY_BYTES - SFQ_QDISC_ESTIMATED_MEMORY_BYTES
+        );
+    }
+
     #[test]
     fn kind_switch_rotates_only_changed_direction_and_reuses_old_handle() {
         let config = test_config_with_runtime_dir("kind-switch");
@@ -13276,4 +13503,69 @@ mod tests {
         assert_eq!(*rebuilt_down, *original_down + 1);
         assert_eq!(*rebuilt_up, *original_up + 1);
     }
+
+    #[test]
+    fn change_circuit_speed_live_keeps_handles_and_clamps_minimums() {
+        let _guard = bakery_test_lock().lock().expect("bakery test lock");
+        reset_bakery_test_state();
+
+        let mut circuits = HashMap::new();
+        let mut original = mk_add_circuit(7, "192.0.2.7/32").as_ref().clone();
+        if let BakeryCommands::AddCircuit {
+            down_qdisc_handle,
+            up_qdisc_handle,
+            ..
+        } = &mut original
+        {
+            *down_qdisc_handle = Some(0x9001);
+            *up_qdisc_handle = Some(0x9002);
+        }
+        circuits.insert(7, Arc::new(original));
+
+        // The shaping tree is inactive in tests, so only runtime state changes.
+        handle_change_circuit_speed_live(
+            7,
+            20.0,
+            500.0,
+            250.0,
+            50.0,
+            &mut circuits,
+            &HashMap::new(),
+            &HashMap::new(),
+        );
+
+        let Some(BakeryCommands::AddCircuit {
+            download_bandwidth_min,
+            upload_bandwidth_min,
+            download_bandwidth_max,
+            upload_bandwidth_max,
+            class_minor,
+            down_qdisc_handle,
+            up_qdisc_handle,
+            ..
+        }) = circuits.get(&7).map(|cmd| cmd.as_ref())
+        else {
+            panic!("circuit should remain tracked");
+        };
+        assert_eq!(*download_bandwidth_min, 20.0);
+        assert_eq!(*upload_bandwidth_min, 49.0);
+        assert_eq!(*download_bandwidth_max, 250.0);
+        assert_eq!(*upload_bandwidth_max, 50.0);
+        assert_eq!(*class_minor, 0x10);
+        assert_eq!(*down_qdisc_handle, Some(0x9001));
+        assert_eq!(*up_qdisc_handle, Some(0x9002));
+
+        // Unknown circuits are ignored rather than created.
+        handle_change_circuit_speed_live(
+            8,
+            1.0,
+            1.0,
+            10.0,
+            10.0,
+            &mut circuits,
+            &HashMap::new(),
+            &HashMap::new(),
+        );
+        assert!(!circuits.contains_key(&8));
+    }
 }
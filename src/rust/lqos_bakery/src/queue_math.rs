@@ -1,10 +1,12 @@
 use std::sync::Arc;
-use tracing::debug;
+use tracing::{debug, warn};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum SqmKind {
     Cake,
     FqCodel,
+    FqPie,
+    Sfq,
 }
 
 pub(crate) fn sqm_as_vec(config: &Arc<lqos_config::Config>) -> Vec<String> {
@@ -151,8 +153,13 @@ pub(crate) fn sqm_rate_fixup(rate: f32, config: &Arc<lqos_config::Config>) -> Ve
 /// Build SQM token vector for a circuit given an optional per-circuit override.
 /// - None: use config default with cake low-rate RTT fixups (existing behavior)
 /// - Some("fq_codel"): use fq_codel
+/// - Some("fq_pie"): use fq_pie with kernel defaults
+/// - Some("sfq"): use sfq, a low-memory option for very large circuit counts
 /// - Some("cake"): use config default if it starts with "cake", otherwise fallback to
 ///   "cake diffserv4"; then apply low-rate RTT fixups.
+/// - Some("cake:<profile>"): use the named `queues.sqm_profiles` entry, applying
+///   low-rate RTT fixups unless the profile sets its own RTT. Unknown profiles
+///   fall back to plain "cake".
 pub(crate) fn sqm_tokens_for(
     rate: f32,
     config: &Arc<lqos_config::Config>,
@@ -168,6 +175,8 @@ pub(crate) fn sqm_tokens_for(
             sqm_rate_fixup(rate, config)
         }
         Some("fq_codel") => vec!["fq_codel".to_string()],
+        Some("fq_pie") => vec!["fq_pie".to_string()],
+        Some("sfq") => vec!["sfq".to_string()],
         Some("cake") => {
             let default = &config.queues.default_sqm;
             let mut base = if default.starts_with("cake") {
@@ -175,30 +184,46 @@ pub(crate) fn sqm_tokens_for(
             } else {
                 vec!["cake".to_string(), "diffserv4".to_string()]
             };
-            // If RTT already specified, leave as-is; otherwise apply low-rate fixups
-            let has_rtt = base.iter().any(|s| s == "rtt");
-            if !has_rtt {
-                // Mirror the thresholds used in sqm_rate_fixup
-                if rate <= 1.0 {
-                    base.push("rtt".to_string());
-                    base.push("300ms".to_string());
-                } else if rate <= 2.0 {
-                    base.push("rtt".to_string());
-                    base.push("180ms".to_string());
-                } else if rate <= 3.0 {
-                    base.push("rtt".to_string());
-                    base.push("140ms".to_string());
-                } else if rate <= 4.0 {
-                    base.push("rtt".to_string());
-                    base.push("120ms".to_string());
-                }
-            }
+            push_low_rate_rtt(rate, &mut base);
+            base
+        }
+        Some(token) if token.starts_with("cake:") => {
+            let name = &token["cake:".len()..];
+            let Some(profile) = config.queues.sqm_profile(name) else {
+                warn!("SQM profile '{name}' is not configured; using plain cake");
+                return sqm_tokens_for(rate, config, &Some("cake".to_string()));
+            };
+            let mut base = vec!["cake".to_string()];
+            base.extend(profile.cake_tokens());
+            push_low_rate_rtt(rate, &mut base);
             base
         }
         Some(_) => sqm_rate_fixup(rate, config), // defensive fallback
     }
 }
 
+/// Appends the low-rate RTT fixups used by `sqm_rate_fixup`, unless an RTT is
+/// already present.
+fn push_low_rate_rtt(rate: f32, base: &mut Vec<String>) {
+    if base.iter().any(|s| s == "rtt") {
+        return;
+    }
+    // Mirror the thresholds used in sqm_rate_fixup
+    let rtt = if rate <= 1.0 {
+        "300ms"
+    } else if rate <= 2.0 {
+        "180ms"
+    } else if rate <= 3.0 {
+        "140ms"
+    } else if rate <= 4.0 {
+        "120ms"
+    } else {
+        return;
+    };
+    base.push("rtt".to_string());
+    base.push(rtt.to_string());
+}
+
 pub(crate) fn effective_sqm_kind(
     rate: f32,
     config: &Arc<lqos_config::Config>,
@@ -209,6 +234,8 @@ pub(crate) fn effective_sqm_kind(
         .map(String::as_str)
     {
         Some("fq_codel") => SqmKind::FqCodel,
+        Some("fq_pie") => SqmKind::FqPie,
+        Some("sfq") => SqmKind::Sfq,
         _ => SqmKind::Cake,
     }
 }
@@ -0,0 +1,564 @@
+//! Translates Bakery `tc` argument lists into netlink changes, so batches can
+//! be applied without forking `tc -batch` and parsing its stderr.
+//!
+//! Only the vocabulary Bakery emits is understood: `mq`, `htb` qdiscs and
+//! classes, the common `cake`, `fq_codel` and `fq_pie` options, and a bare
+//! `sfq`. Anything else, such as an unusual `default_sqm` keyword, is reported
+//! as unsupported and the caller hands the chunk to `tc` instead. Encodings follow what `tc` itself
+//! sends, so the kernel ends up in the same state either way.
+
+use lqos_bus::TcHandle;
+use lqos_netlink::{TC_H_ROOT, TcChange, TcObject, TcVerb, push_attribute};
+
+// `TCA_HTB_*`
+const TCA_HTB_PARMS: u16 = 1;
+const TCA_HTB_INIT: u16 = 2;
+const TCA_HTB_DIRECT_QLEN: u16 = 5;
+const TCA_HTB_RATE64: u16 = 6;
+const TCA_HTB_CEIL64: u16 = 7;
+const TC_HTB_PROTOVER: u32 = 3;
+const HTB_DEFAULT_R2Q: u32 = 10;
+/// `tc` sizes HTB bursts from a 1600 byte MTU unless told otherwise.
+const HTB_MTU: u64 = 1600;
+const TC_LINKLAYER_ETHERNET: u8 = 1;
+
+/// `tc` expresses HTB buffers as transmit times in psched ticks. The kernel
+/// clock has ticked every 64ns for many years, and reports 1GHz as its HZ.
+const TIME_UNITS_PER_SEC: f64 = 1_000_000.0;
+const PSCHED_TICKS_PER_USEC: f64 = 1000.0 / 64.0;
+const PSCHED_HZ: u64 = 1_000_000_000;
+
+// `TCA_CAKE_*`
+const TCA_CAKE_BASE_RATE64: u16 = 2;
+const TCA_CAKE_DIFFSERV_MODE: u16 = 3;
+const TCA_CAKE_ATM: u16 = 4;
+const TCA_CAKE_FLOW_MODE: u16 = 5;
+const TCA_CAKE_OVERHEAD: u16 = 6;
+const TCA_CAKE_RTT: u16 = 7;
+const TCA_CAKE_TARGET: u16 = 8;
+const TCA_CAKE_NAT: u16 = 11;
+const TCA_CAKE_RAW: u16 = 12;
+const TCA_CAKE_WASH: u16 = 13;
+const TCA_CAKE_MPU: u16 = 14;
+const TCA_CAKE_INGRESS: u16 = 15;
+const TCA_CAKE_ACK_FILTER: u16 = 16;
+const TCA_CAKE_SPLIT_GSO: u16 = 17;
+
+// `TCA_FQ_CODEL_*`
+const TCA_FQ_CODEL_TARGET: u16 = 1;
+const TCA_FQ_CODEL_LIMIT: u16 = 2;
+const TCA_FQ_CODEL_INTERVAL: u16 = 3;
+const TCA_FQ_CODEL_ECN: u16 = 4;
+const TCA_FQ_CODEL_FLOWS: u16 = 5;
+const TCA_FQ_CODEL_QUANTUM: u16 = 6;
+
+// `TCA_FQ_PIE_*`
+const TCA_FQ_PIE_LIMIT: u16 = 1;
+const TCA_FQ_PIE_FLOWS: u16 = 2;
+const TCA_FQ_PIE_TARGET: u16 = 3;
+const TCA_FQ_PIE_TUPDATE: u16 = 4;
+const TCA_FQ_PIE_QUANTUM: u16 = 7;
+const TCA_FQ_PIE_ECN: u16 = 10;
+const TCA_FQ_PIE_BYTEMODE: u16 = 11;
+
+/// Translates one `tc` command, or explains why it cannot be.
+pub(crate) fn translate_command(command: &[String]) -> Result<TcChange, String> {
+    let mut args = command.iter().map(String::as_str);
+    let object = match args.next() {
+        Some("qdisc") => TcObject::Qdisc,
+        Some("class") => TcObject::Class,
+        other => return Err(format!("unsupported tc object {other:?}")),
+    };
+    let verb = match args.next() {
+        Some("add") => TcVerb::Add,
+        Some("replace") => TcVerb::Replace,
+        Some("change") => TcVerb::Change,
+        Some("del") | Some("delete") => TcVerb::Delete,
+        other => return Err(format!("unsupported tc verb {other:?}")),
+    };
+
+    let mut interface = None;
+    let mut handle = 0;
+    let mut parent = 0;
+    let mut kind = None;
+    while let Some(arg) = args.next() {
+        match arg {
+            "dev" => interface = Some(next_arg(&mut args, arg)?.to_string()),
+            "root" => parent = TC_H_ROOT,
+            "parent" => parent = parse_handle(next_arg(&mut args, arg)?)?,
+            "handle" if object == TcObject::Qdisc => {
+                handle = parse_handle(next_arg(&mut args, arg)?)?
+            }
+            "classid" if object == TcObject::Class => {
+                handle = parse_handle(next_arg(&mut args, arg)?)?
+            }
+            _ => {
+                kind = Some(arg);
+                break;
+            }
+        }
+    }
+    let interface = interface.ok_or_else(|| "no dev given".to_string())?;
+    let options: Vec<&str> = args.collect();
+
+    let options = match (object, kind) {
+        (_, None) if verb == TcVerb::Delete => None,
+        (_, None) => return Err("no kind given".to_string()),
+        (TcObject::Qdisc, Some("mq")) => {
+            if let Some(option) = options.first() {
+                return Err(format!("unsupported mq option {option}"));
+            }
+            None
+        }
+        (TcObject::Qdisc, Some("htb")) => Some(htb_qdisc_options(&options)?),
+        (TcObject::Class, Some("htb")) => Some(htb_class_options(&options)?),
+        (TcObject::Qdisc, Some("cake")) => Some(cake_options(&options)?),
+        (TcObject::Qdisc, Some("fq_codel")) => Some(fq_codel_options(&options)?),
+        (TcObject::Qdisc, Some("fq_pie")) => Some(fq_pie_options(&options)?),
+        (TcObject::Qdisc, Some("sfq")) => {
+            // `tc` sends an all-zero `tc_sfq_qopt_v1`, which the kernel reads
+            // as "keep defaults"; omitting the options has the same effect.
+            if let Some(option) = options.first() {
+                return Err(format!("unsupported sfq option {option}"));
+            }
+            None
+        }
+        (_, Some(kind)) => return Err(format!("unsupported kind {kind}")),
+    };
+
+    Ok(TcChange {
+        object,
+        verb,
+        interface,
+        handle,
+        parent,
+        kind: kind.map(str::to_string),
+        options,
+    })
+}
+
+fn next_arg<'a>(args: &mut impl Iterator<Item = &'a str>, after: &str) -> Result<&'a str, String> {
+    args.next()
+        .ok_or_else(|| format!("missing value after {after}"))
+}
+
+fn parse_handle(raw: &str) -> Result<u32, String> {
+    TcHandle::from_string(raw)
+        .map(|handle| handle.as_u32())
+        .map_err(|_| format!("invalid handle {raw}"))
+}
+
+fn parse_u32(raw: &str) -> Result<u32, String> {
+    raw.parse().map_err(|_| format!("invalid number {raw}"))
+}
+
+/// Parses a `tc` rate (e.g. `12.5mbit`) into bytes per second.
+fn parse_rate(raw: &str) -> Result<u64, String> {
+    let split = raw
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(raw.len());
+    let (number, unit) = raw.split_at(split);
+    let value: f64 = number.parse().map_err(|_| format!("invalid rate {raw}"))?;
+    let bits_per_unit = match unit.to_ascii_lowercase().as_str() {
+        "" | "bit" => 1.0,
+        "kbit" => 1e3,
+        "mbit" => 1e6,
+        "gbit" => 1e9,
+        "tbit" => 1e12,
+        "kibit" => 1024.0,
+        "mibit" => 1024.0 * 1024.0,
+        "gibit" => 1024.0 * 1024.0 * 1024.0,
+        "bps" => 8.0,
+        "kbps" => 8e3,
+        "mbps" => 8e6,
+        "gbps" => 8e9,
+        _ => return Err(format!("unsupported rate unit in {raw}")),
+    };
+    Ok((value * bits_per_unit / 8.0) as u64)
+}
+
+/// Parses a `tc` time (e.g. `100ms`) into microseconds.
+fn parse_time_us(raw: &str) -> Result<u32, String> {
+    let split = raw
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(raw.len());
+    let (number, unit) = raw.split_at(split);
+    let value: f64 = number.parse().map_err(|_| format!("invalid time {raw}"))?;
+    let scale = match unit.to_ascii_lowercase().as_str() {
+        "s" | "sec" | "secs" => 1_000_000.0,
+        "ms" | "msec" | "msecs" => 1_000.0,
+        "" | "us" | "usec" | "usecs" => 1.0,
+        _ => return Err(format!("unsupported time unit in {raw}")),
+    };
+    Ok((value * scale) as u32)
+}
+
+fn htb_qdisc_options(options: &[&str]) -> Result<Vec<u8>, String> {
+    let mut r2q = HTB_DEFAULT_R2Q;
+    let mut default_class = 0;
+    let mut direct_qlen = None;
+    let mut args = options.iter().copied();
+    while let Some(arg) = args.next() {
+        let value = next_arg(&mut args, arg)?;
+        match arg {
+            // `tc` reads the default class id as hex
+            "default" => {
+                default_class = u32::from_str_radix(value.trim_start_matches("0x"), 16)
+                    .map_err(|_| format!("invalid htb default {value}"))?
+            }
+            "r2q" => r2q = parse_u32(value)?,
+            "direct_qlen" => direct_qlen = Some(parse_u32(value)?),
+            _ => return Err(format!("unsupported htb option {arg}")),
+        }
+    }
+
+    // struct tc_htb_glob: version, rate2quantum, defcls, debug, direct_pkts
+    let mut glob = Vec::with_capacity(20);
+    for value in [TC_HTB_PROTOVER, r2q, default_class, 0, 0] {
+        glob.extend_from_slice(&value.to_ne_bytes());
+    }
+    let mut encoded = Vec::new();
+    push_attribute(&mut encoded, TCA_HTB_INIT, &glob);
+    if let Some(direct_qlen) = direct_qlen {
+        push_attribute(
+            &mut encoded,
+            TCA_HTB_DIRECT_QLEN,
+            &direct_qlen.to_ne_bytes(),
+        );
+    }
+    Ok(encoded)
+}
+
+/// Transmit time for `size` bytes at `rate` bytes per second, in ticks.
+fn xmit_ticks(rate: u64, size: u64) -> u32 {
+    (TIME_UNITS_PER_SEC * (size as f64 / rate as f64) * PSCHED_TICKS_PER_USEC) as u32
+}
+
+/// `struct tc_ratespec`, as `tc` fills it for an Ethernet link.
+fn htb_ratespec(rate: u64) -> Vec<u8> {
+    let mut spec = Vec::with_capacity(12);
+    spec.push(3); // cell_log: the smallest that fits the MTU in 256 cells
+    spec.push(TC_LINKLAYER_ETHERNET);
+    spec.extend_from_slice(&0u16.to_ne_bytes()); // overhead
+    spec.extend_from_slice(&(-1i16).to_ne_bytes()); // cell_align
+    spec.extend_from_slice(&0u16.to_ne_bytes()); // mpu
+    spec.extend_from_slice(&(rate.min(u32::MAX as u64) as u32).to_ne_bytes());
+    spec
+}
+
+fn htb_class_options(options: &[&str]) -> Result<Vec<u8>, String> {
+    let mut rate = 0;
+    let mut ceil = 0;
+    let mut prio = 0;
+    let mut quantum = 0;
+    let mut args = options.iter().copied();
+    while let Some(arg) = args.next() {
+        let value = next_arg(&mut args, arg)?;
+        match arg {
+            "rate" => rate = parse_rate(value)?,
+            "ceil" => ceil = parse_rate(value)?,
+            "prio" => prio = parse_u32(value)?,
+            "quantum" => quantum = parse_u32(value)?,
+            _ => return Err(format!("unsupported htb class option {arg}")),
+        }
+    }
+    if rate == 0 {
+        return Err("htb class without a rate".to_string());
+    }
+    if ceil == 0 {
+        ceil = rate;
+    }
+    let buffer = xmit_ticks(rate, rate / PSCHED_HZ + HTB_MTU);
+    let cbuffer = xmit_ticks(ceil, ceil / PSCHED_HZ + HTB_MTU);
+
+    // struct tc_htb_opt: rate, ceil, buffer, cbuffer, quantum, level, prio
+    let mut opt = htb_ratespec(rate);
+    opt.extend(htb_ratespec(ceil));
+    for value in [buffer, cbuffer, quantum, 0, prio] {
+        opt.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    // The rate tables `tc` also sends are ignored by the kernel once the
+    // link layer is set, so they are left out.
+    let mut encoded = Vec::new();
+    if rate > u32::MAX as u64 {
+        push_attribute(&mut encoded, TCA_HTB_RATE64, &rate.to_ne_bytes());
+    }
+    if ceil > u32::MAX as u64 {
+        push_attribute(&mut encoded, TCA_HTB_CEIL64, &ceil.to_ne_bytes());
+    }
+    push_attribute(&mut encoded, TCA_HTB_PARMS, &opt);
+    Ok(encoded)
+}
+
+fn cake_options(options: &[&str]) -> Result<Vec<u8>, String> {
+    let mut encoded = Vec::new();
+    let mut args = options.iter().copied();
+    while let Some(arg) = args.next() {
+        match arg {
+            "besteffort" => push_u32(&mut encoded, TCA_CAKE_DIFFSERV_MODE, 3),
+            "diffserv3" => push_u32(&mut encoded, TCA_CAKE_DIFFSERV_MODE, 0),
+            "diffserv4" => push_u32(&mut encoded, TCA_CAKE_DIFFSERV_MODE, 1),
+            "diffserv8" => push_u32(&mut encoded, TCA_CAKE_DIFFSERV_MODE, 2),
+            "precedence" => push_u32(&mut encoded, TCA_CAKE_DIFFSERV_MODE, 4),
+            "flowblind" => push_u32(&mut encoded, TCA_CAKE_FLOW_MODE, 0),
+            "srchost" => push_u32(&mut encoded, TCA_CAKE_FLOW_MODE, 1),
+            "dsthost" => push_u32(&mut encoded, TCA_CAKE_FLOW_MODE, 2),
+            "hosts" => push_u32(&mut encoded, TCA_CAKE_FLOW_MODE, 3),
+            "flows" => push_u32(&mut encoded, TCA_CAKE_FLOW_MODE, 4),
+            "dual-srchost" => push_u32(&mut encoded, TCA_CAKE_FLOW_MODE, 5),
+            "dual-dsthost" => push_u32(&mut encoded, TCA_CAKE_FLOW_MODE, 6),
+            "triple-isolate" => push_u32(&mut encoded, TCA_CAKE_FLOW_MODE, 7),
+            "nat" => push_u32(&mut encoded, TCA_CAKE_NAT, 1),
+            "nonat" => push_u32(&mut encoded, TCA_CAKE_NAT, 0),
+            "wash" => push_u32(&mut encoded, TCA_CAKE_WASH, 1),
+            "nowash" => push_u32(&mut encoded, TCA_CAKE_WASH, 0),
+            "ingress" => push_u32(&mut encoded, TCA_CAKE_INGRESS, 1),
+            "egress" => push_u32(&mut encoded, TCA_CAKE_INGRESS, 0),
+            "split-gso" => push_u32(&mut encoded, TCA_CAKE_SPLIT_GSO, 1),
+            "no-split-gso" => push_u32(&mut encoded, TCA_CAKE_SPLIT_GSO, 0),
+            "ack-filter" => push_u32(&mut encoded, TCA_CAKE_ACK_FILTER, 1),
+            "ack-filter-aggressive" => push_u32(&mut encoded, TCA_CAKE_ACK_FILTER, 2),
+            "no-ack-filter" => push_u32(&mut encoded, TCA_CAKE_ACK_FILTER, 0),
+            "noatm" => push_u32(&mut encoded, TCA_CAKE_ATM, 0),
+            "atm" => push_u32(&mut encoded, TCA_CAKE_ATM, 1),
+            "ptm" => push_u32(&mut encoded, TCA_CAKE_ATM, 2),
+            "unlimited" => push_attribute(&mut encoded, TCA_CAKE_BASE_RATE64, &0u64.to_ne_bytes()),
+            "bandwidth" => {
+                let rate = parse_rate(next_arg(&mut args, arg)?)?;
+                push_attribute(&mut encoded, TCA_CAKE_BASE_RATE64, &rate.to_ne_bytes());
+            }
+            "rtt" => {
+                // `tc` derives the target from the RTT, as the kernel would
+                let interval = parse_time_us(next_arg(&mut args, arg)?)?.max(1);
+                push_u32(&mut encoded, TCA_CAKE_RTT, interval);
+                push_u32(&mut encoded, TCA_CAKE_TARGET, (interval / 20).max(1));
+            }
+            "overhead" => {
+                let value = next_arg(&mut args, arg)?;
+                let overhead: i32 = value
+                    .parse()
+                    .map_err(|_| format!("invalid cake overhead {value}"))?;
+                if !(-64..=256).contains(&overhead) {
+                    return Err(format!("cake overhead {overhead} out of range"));
+                }
+                push_attribute(&mut encoded, TCA_CAKE_OVERHEAD, &overhead.to_ne_bytes());
+            }
+            "mpu" => {
+                let mpu = parse_u32(next_arg(&mut args, arg)?)?;
+                push_u32(&mut encoded, TCA_CAKE_MPU, mpu);
+            }
+            "raw" => {
+                push_attribute(&mut encoded, TCA_CAKE_OVERHEAD, &0i32.to_ne_bytes());
+                push_u32(&mut encoded, TCA_CAKE_RAW, 0);
+            }
+            _ => return Err(format!("unsupported cake option {arg}")),
+        }
+    }
+    Ok(encoded)
+}
+
+fn push_u32(encoded: &mut Vec<u8>, kind: u16, value: u32) {
+    push_attribute(encoded, kind, &value.to_ne_bytes());
+}
+
+fn fq_codel_options(options: &[&str]) -> Result<Vec<u8>, String> {
+    let mut encoded = Vec::new();
+    let mut args = options.iter().copied();
+    while let Some(arg) = args.next() {
+        let (kind, value) = match arg {
+            "ecn" => (TCA_FQ_CODEL_ECN, 1),
+            "noecn" => (TCA_FQ_CODEL_ECN, 0),
+            "limit" => (TCA_FQ_CODEL_LIMIT, parse_u32(next_arg(&mut args, arg)?)?),
+            "flows" => (TCA_FQ_CODEL_FLOWS, parse_u32(next_arg(&mut args, arg)?)?),
+            "quantum" => (TCA_FQ_CODEL_QUANTUM, parse_u32(next_arg(&mut args, arg)?)?),
+            "target" => (
+                TCA_FQ_CODEL_TARGET,
+                parse_time_us(next_arg(&mut args, arg)?)?,
+            ),
+            "interval" => (
+                TCA_FQ_CODEL_INTERVAL,
+                parse_time_us(next_arg(&mut args, arg)?)?,
+            ),
+            _ => return Err(format!("unsupported fq_codel option {arg}")),
+        };
+        push_attribute(&mut encoded, kind, &value.to_ne_bytes());
+    }
+    Ok(encoded)
+}
+
+fn fq_pie_options(options: &[&str]) -> Result<Vec<u8>, String> {
+    let mut encoded = Vec::new();
+    let mut args = options.iter().copied();
+    while let Some(arg) = args.next() {
+        let (kind, value) = match arg {
+            "ecn" => (TCA_FQ_PIE_ECN, 1),
+            "noecn" => (TCA_FQ_PIE_ECN, 0),
+            "bytemode" => (TCA_FQ_PIE_BYTEMODE, 1),
+            "nobytemode" => (TCA_FQ_PIE_BYTEMODE, 0),
+            "limit" => (TCA_FQ_PIE_LIMIT, parse_u32(next_arg(&mut args, arg)?)?),
+            "flows" => (TCA_FQ_PIE_FLOWS, parse_u32(next_arg(&mut args, arg)?)?),
+            "quantum" => (TCA_FQ_PIE_QUANTUM, parse_u32(next_arg(&mut args, arg)?)?),
+            "target" => (TCA_FQ_PIE_TARGET, parse_time_us(next_arg(&mut args, arg)?)?),
+            "tupdate" => (
+                TCA_FQ_PIE_TUPDATE,
+                parse_time_us(next_arg(&mut args, arg)?)?,
+            ),
+            _ => return Err(format!("unsupported fq_pie option {arg}")),
+        };
+        push_attribute(&mut encoded, kind, &value.to_ne_bytes());
+    }
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lqos_netlink::Attributes;
+
+    fn command(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn translates_root_mq_and_htb_qdisc() {
+        let mq = translate_command(&command("qdisc replace dev eth0 root handle 7FFF: mq"))
+            .expect("mq translates");
+        assert_eq!(mq.verb, TcVerb::Replace);
+        assert_eq!(mq.parent, TC_H_ROOT);
+        assert_eq!(mq.handle, 0x7fff_0000);
+        assert_eq!(mq.options, None);
+
+        let htb = translate_command(&command(
+            "qdisc add dev eth0 parent 7FFF:0x1 handle 0x1: htb default 2",
+        ))
+        .expect("htb translates");
+        assert_eq!(htb.parent, 0x7fff_0001);
+        let options = htb.options.expect("htb options");
+        let init = Attributes::new(&options)
+            .get(TCA_HTB_INIT)
+            .expect("htb init");
+        assert_eq!(init.u32_at(0), Some(TC_HTB_PROTOVER));
+        assert_eq!(init.u32_at(8), Some(2));
+    }
+
+    #[test]
+    fn translates_htb_class_rates_like_tc() {
+        let class = translate_command(&command(
+            "class replace dev eth0 parent 0x3:0x20 classid 0x3:0x14af htb rate 12.5mbit ceil 100.0mbit prio 3 quantum 1522",
+        ))
+        .expect("class translates");
+        assert_eq!(class.object, TcObject::Class);
+        assert_eq!(class.handle, 0x0003_14af);
+        let options = class.options.expect("class options");
+        let parms = Attributes::new(&options)
+            .get(TCA_HTB_PARMS)
+            .expect("htb parms");
+        assert_eq!(parms.payload.len(), 44);
+        assert_eq!(parms.u32_at(8), Some(1_562_500));
+        assert_eq!(parms.u32_at(20), Some(12_500_000));
+        // 1600 bytes at 12.5mbit is 1024us, or 16000 ticks
+        assert_eq!(parms.u32_at(24), Some(16_000));
+        assert_eq!(parms.u32_at(32), Some(1522));
+        assert_eq!(parms.u32_at(40), Some(3));
+    }
+
+    #[test]
+    fn fast_htb_classes_carry_64_bit_rates() {
+        let class = translate_command(&command(
+            "class change dev eth0 classid 0x3:0x1 htb rate 40.0gbit ceil 40.0gbit",
+        ))
+        .expect("class translates");
+        assert_eq!(class.parent, 0);
+        let options = class.options.expect("class options");
+        let rate = Attributes::new(&options)
+            .get(TCA_HTB_RATE64)
+            .and_then(|attr| attr.u64());
+        assert_eq!(rate, Some(5_000_000_000));
+    }
+
+    #[test]
+    fn translates_cake_options() {
+        let cake = translate_command(&command(
+            "qdisc replace dev eth0 parent 0x3:0x14af handle 0x9000: cake diffserv4 ack-filter rtt 300ms",
+        ))
+        .expect("cake translates");
+        let options = cake.options.expect("cake options");
+        let attrs = Attributes::new(&options);
+        let value = |kind| attrs.get(kind).and_then(|attr| attr.u32());
+        assert_eq!(value(TCA_CAKE_DIFFSERV_MODE), Some(1));
+        assert_eq!(value(TCA_CAKE_ACK_FILTER), Some(1));
+        assert_eq!(value(TCA_CAKE_RTT), Some(300_000));
+        assert_eq!(value(TCA_CAKE_TARGET), Some(15_000));
+    }
+
+    #[test]
+    fn translates_cake_framing_options() {
+        let cake = translate_command(&command(
+            "qdisc replace dev eth0 parent 0x3:0x14af cake dual-srchost nat overhead 44 mpu 96 atm",
+        ))
+        .expect("cake translates");
+        let options = cake.options.expect("cake options");
+        let attrs = Attributes::new(&options);
+        let value = |kind| attrs.get(kind).and_then(|attr| attr.u32());
+        // Kernel ABI numbering: TCA_CAKE_ATM = 4, TCA_CAKE_MPU = 14
+        assert_eq!(value(4), Some(1));
+        assert_eq!(value(14), Some(96));
+        assert_eq!(value(TCA_CAKE_FLOW_MODE), Some(5));
+        assert_eq!(value(TCA_CAKE_NAT), Some(1));
+        assert_eq!(value(TCA_CAKE_OVERHEAD), Some(44));
+    }
+
+    #[test]
+    fn translates_fq_pie_and_bare_sfq() {
+        let fq_pie = translate_command(&command(
+            "qdisc replace dev eth0 parent 0x3:0x20 fq_pie target 15ms ecn",
+        ))
+        .expect("fq_pie translates");
+        assert_eq!(fq_pie.kind.as_deref(), Some("fq_pie"));
+        let options = fq_pie.options.expect("fq_pie options");
+        let attrs = Attributes::new(&options);
+        let value = |kind| attrs.get(kind).and_then(|attr| attr.u32());
+        assert_eq!(value(TCA_FQ_PIE_TARGET), Some(15_000));
+        assert_eq!(value(TCA_FQ_PIE_ECN), Some(1));
+
+        let bare = translate_command(&command("qdisc replace dev eth0 parent 0x3:0x20 fq_pie"))
+            .expect("bare fq_pie translates");
+        assert_eq!(bare.options, Some(Vec::new()));
+
+        let sfq = translate_command(&command("qdisc replace dev eth0 parent 0x3:0x20 sfq"))
+            .expect("sfq translates");
+        assert_eq!(sfq.kind.as_deref(), Some("sfq"));
+        assert_eq!(sfq.options, None);
+        assert!(
+            translate_command(&command("qdisc replace dev eth0 parent 1:2 sfq perturb 10"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn translates_deletes_without_kind() {
+        let delete = translate_command(&command("qdisc del dev eth0 parent 0x3:0x14af"))
+            .expect("delete translates");
+        assert_eq!(delete.verb, TcVerb::Delete);
+        assert_eq!(delete.kind, None);
+        assert_eq!(delete.options, None);
+
+        let delete = translate_command(&command("qdisc del dev eth0 handle 0x9000:"))
+            .expect("delete by handle translates");
+        assert_eq!(delete.handle, 0x9000_0000);
+        assert_eq!(delete.parent, 0);
+    }
+
+    #[test]
+    fn rejects_unknown_vocabulary() {
+        assert!(
+            translate_command(&command("qdisc replace dev eth0 parent 1:2 cake docsis")).is_err()
+        );
+        assert!(translate_command(&command("qdisc replace dev eth0 parent 1:2 sfb")).is_err());
+        assert!(
+            translate_command(&command("class add dev eth0 classid 1:2 htb burst 15k")).is_err()
+        );
+        assert!(translate_command(&command("filter add dev eth0")).is_err());
+    }
+}
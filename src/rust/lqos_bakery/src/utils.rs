@@ -1,10 +1,14 @@
+use crate::tc_netlink::translate_command;
 use lqos_bus::TcHandle;
+use lqos_netlink::{NetlinkError, TcChange, TcMessage, TcSession, TcVerb};
+use nix::errno::Errno;
 use parking_lot::Mutex;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::Path;
 use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 
@@ -23,6 +27,10 @@ static TC_IO_CADENCE_STATE: LazyLock<Mutex<TcIoCadenceState>> =
     LazyLock::new(|| Mutex::new(TcIoCadenceState::new()));
 const LIVE_TC_SNAPSHOT_MAX_AGE_MS: u64 = 250;
 const TC_IO_INTERVAL_WINDOW: usize = 128;
+/// Set once a netlink snapshot has failed, so the tc fallback is announced once.
+static NETLINK_SNAPSHOT_WARNED: AtomicBool = AtomicBool::new(false);
+/// Set once a netlink batch could not be started, for the same reason.
+static NETLINK_BATCH_WARNED: AtomicBool = AtomicBool::new(false);
 
 #[derive(Clone)]
 struct TimedClassSnapshot {
@@ -292,8 +300,43 @@ pub(crate) fn tc_io_cadence_snapshot() -> TcIoCadenceSnapshot {
     TC_IO_CADENCE_STATE.lock().snapshot()
 }
 
+fn warn_netlink_snapshot_fallback(interface: &str, error: &NetlinkError) {
+    if !NETLINK_SNAPSHOT_WARNED.swap(true, Ordering::Relaxed) {
+        warn!("Unable to snapshot {interface} over netlink ({error}); falling back to tc");
+    }
+}
+
+fn live_qdisc_entry_from_netlink(message: &TcMessage) -> LiveTcQdiscEntry {
+    LiveTcQdiscEntry {
+        kind: message.kind.clone(),
+        handle: Some(TcHandle::from_u32(message.handle)),
+        parent: (!message.is_root()).then(|| TcHandle::from_u32(message.parent)),
+        is_root: message.is_root(),
+    }
+}
+
+fn live_class_entry_from_netlink(message: &TcMessage) -> LiveTcClassEntry {
+    // `tcm_info` holds the handle of the leaf qdisc, as `tc` prints it
+    let leaf_major = (message.info >> 16) as u16;
+    LiveTcClassEntry {
+        class_id: TcHandle::from_u32(message.handle),
+        parent: (!message.is_root()).then(|| TcHandle::from_u32(message.parent)),
+        leaf_qdisc_major: (leaf_major != 0).then_some(leaf_major),
+    }
+}
+
 fn read_live_qdisc_snapshot_raw(interface: &str) -> Result<Vec<LiveTcQdiscEntry>, String> {
     record_tc_io_event();
+    match lqos_netlink::dump_qdiscs(interface) {
+        Ok(messages) => Ok(messages.iter().map(live_qdisc_entry_from_netlink).collect()),
+        Err(e) => {
+            warn_netlink_snapshot_fallback(interface, &e);
+            read_live_qdisc_snapshot_with_tc(interface)
+        }
+    }
+}
+
+fn read_live_qdisc_snapshot_with_tc(interface: &str) -> Result<Vec<LiveTcQdiscEntry>, String> {
     let output = std::process::Command::new("/sbin/tc")
         .args(["-s", "-j", "qdisc", "show", "dev", interface])
         .output()
@@ -352,6 +395,22 @@ fn read_live_class_snapshot_raw(
     interface: &str,
 ) -> Result<HashMap<TcHandle, LiveTcClassEntry>, String> {
     record_tc_io_event();
+    match lqos_netlink::dump_classes(interface) {
+        Ok(messages) => Ok(messages
+            .iter()
+            .map(live_class_entry_from_netlink)
+            .map(|entry| (entry.class_id, entry))
+            .collect()),
+        Err(e) => {
+            warn_netlink_snapshot_fallback(interface, &e);
+            read_live_class_snapshot_with_tc(interface)
+        }
+    }
+}
+
+fn read_live_class_snapshot_with_tc(
+    interface: &str,
+) -> Result<HashMap<TcHandle, LiveTcClassEntry>, String> {
     let output = std::process::Command::new("/sbin/tc")
         .args(["class", "show", "dev", interface])
         .output()
@@ -584,81 +643,21 @@ where
         }
 
         let global_line_start = completed_commands + 1;
-        let Some(lines) = write_command_file(chunk_path, chunk) else {
-            error!("Failed to write chunked commands to file for {purpose}");
-            return ExecuteResult {
-                ok: false,
-                duration_ms: started.elapsed().as_millis() as u64,
-                failure_summary: Some(format!("Failed to write commands to file for {purpose}")),
-            };
-        };
-
-        let output = match run_tc_batch(chunk_path, purpose) {
-            Ok(output) => output,
-            Err(message) => {
-                error!(message);
-                return ExecuteResult {
-                    ok: false,
-                    duration_ms: started.elapsed().as_millis() as u64,
-                    failure_summary: Some(message),
-                };
-            }
-        };
-
-        let output_str = String::from_utf8_lossy(&output.stdout)
-            .replace("Error: Exclusivity flag on, cannot modify.\n", "");
-        if !output_str.is_empty() {
-            error!("Command output for ({purpose}): {:?}", output_str.trim());
-        }
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        if output.status.success() && !stderr.trim().is_empty() {
-            if tc_success_stderr_is_harmless(stderr.trim()) {
-                debug!("Command stderr for ({purpose}): {:?}", stderr.trim());
-            } else {
-                warn!("Command stderr for ({purpose}): {:?}", stderr.trim());
+        let outcome = match plan_netlink_chunk(chunk) {
+            Some((session, changes)) => {
+                run_chunk_over_netlink(session, &changes, chunk, purpose, global_line_start)
             }
-        }
-
-        if tc_batch_failure_is_ignorable_delete_absence(&output, &lines) {
-            debug!(
-                "Bakery tolerated delete-only tc batch absence during {purpose}; targets were already gone"
-            );
-        } else if let Some(failure_summary) = summarize_tc_batch_failure(&output) {
-            let numbered = format_numbered_lines(&lines, global_line_start);
-            let chunk_line_end = global_line_start + chunk.len().saturating_sub(1);
-            let detailed = format!(
-                "Command error for ({purpose}): {}\nFailed chunk {}/{} (global lines {}-{})\nFull batch: {}\nChunk batch: {}\nChunk commands with global line numbers:\n{}",
-                failure_summary,
+            None => run_chunk_with_tc(
+                chunk,
+                purpose,
+                global_line_start,
                 completed_chunks + 1,
                 total_chunks,
-                global_line_start,
-                chunk_line_end,
-                full_path.display(),
-                chunk_path.display(),
-                numbered
-            );
-            error!(detailed);
-
-            let ts = current_timestamp();
-            let path_ts = Path::new("/tmp").join(format!("lqos_bakery_failed_{}.txt", ts));
-            if let Ok(mut f) = File::create(&path_ts) {
-                let _ = f.write_all(detailed.as_bytes());
-                let _ = f.flush();
-                error!(
-                    "Bakery wrote numbered command failure to {}",
-                    path_ts.display()
-                );
-            } else {
-                error!(
-                    "Bakery failed to write numbered command failure file: {}",
-                    path_ts.display()
-                );
-            }
-            let path_last = Path::new("/tmp/lqos_bakery_last_error.txt");
-            if let Ok(mut f) = File::create(path_last) {
-                let _ = f.write_all(detailed.as_bytes());
-                let _ = f.flush();
-            }
+                full_path,
+                chunk_path,
+            ),
+        };
+        if let Err(failure_summary) = outcome {
             return ExecuteResult {
                 ok: false,
                 duration_ms: started.elapsed().as_millis() as u64,
@@ -719,6 +718,189 @@ where
     }
 }
 
+/// Writes a detailed chunk failure where operators look for it: a
+/// timestamped file, plus the "last error" file.
+fn record_chunk_failure(detailed: &str) {
+    let ts = current_timestamp();
+    let path_ts = Path::new("/tmp").join(format!("lqos_bakery_failed_{}.txt", ts));
+    if let Ok(mut f) = File::create(&path_ts) {
+        let _ = f.write_all(detailed.as_bytes());
+        let _ = f.flush();
+        error!(
+            "Bakery wrote numbered command failure to {}",
+            path_ts.display()
+        );
+    } else {
+        error!(
+            "Bakery failed to write numbered command failure file: {}",
+            path_ts.display()
+        );
+    }
+    let path_last = Path::new("/tmp/lqos_bakery_last_error.txt");
+    if let Ok(mut f) = File::create(path_last) {
+        let _ = f.write_all(detailed.as_bytes());
+        let _ = f.flush();
+    }
+}
+
+fn run_chunk_with_tc(
+    chunk: &[Vec<String>],
+    purpose: &str,
+    global_line_start: usize,
+    chunk_number: usize,
+    total_chunks: usize,
+    full_path: &Path,
+    chunk_path: &Path,
+) -> Result<(), String> {
+    let Some(lines) = write_command_file(chunk_path, chunk) else {
+        error!("Failed to write chunked commands to file for {purpose}");
+        return Err(format!("Failed to write commands to file for {purpose}"));
+    };
+
+    let output = run_tc_batch(chunk_path, purpose).inspect_err(|message| error!(message))?;
+
+    let output_str = String::from_utf8_lossy(&output.stdout)
+        .replace("Error: Exclusivity flag on, cannot modify.\n", "");
+    if !output_str.is_empty() {
+        error!("Command output for ({purpose}): {:?}", output_str.trim());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() && !stderr.trim().is_empty() {
+        if tc_success_stderr_is_harmless(stderr.trim()) {
+            debug!("Command stderr for ({purpose}): {:?}", stderr.trim());
+        } else {
+            warn!("Command stderr for ({purpose}): {:?}", stderr.trim());
+        }
+    }
+
+    if tc_batch_failure_is_ignorable_delete_absence(&output, &lines) {
+        debug!(
+            "Bakery tolerated delete-only tc batch absence during {purpose}; targets were already gone"
+        );
+    } else if let Some(failure_summary) = summarize_tc_batch_failure(&output) {
+        let numbered = format_numbered_lines(&lines, global_line_start);
+        let chunk_line_end = global_line_start + chunk.len().saturating_sub(1);
+        let detailed = format!(
+            "Command error for ({purpose}): {}\nFailed chunk {}/{} (global lines {}-{})\nFull batch: {}\nChunk batch: {}\nChunk commands with global line numbers:\n{}",
+            failure_summary,
+            chunk_number,
+            total_chunks,
+            global_line_start,
+            chunk_line_end,
+            full_path.display(),
+            chunk_path.display(),
+            numbered
+        );
+        error!(detailed);
+        record_chunk_failure(&detailed);
+        return Err(failure_summary);
+    }
+    Ok(())
+}
+
+/// A command the kernel rejected during a netlink batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TcOperationFailure {
+    /// Line number within the whole batch, counting from 1.
+    pub(crate) line: usize,
+    /// The command, as it would appear in a `tc` batch file.
+    pub(crate) command: String,
+    /// Why it failed.
+    pub(crate) error: String,
+}
+
+impl std::fmt::Display for TcOperationFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {} ({}): {}", self.line, self.command, self.error)
+    }
+}
+
+/// Opens a netlink session for a chunk if every command in it can be sent
+/// natively. Otherwise the chunk goes to `tc`, which understands everything.
+fn plan_netlink_chunk(chunk: &[Vec<String>]) -> Option<(TcSession, Vec<TcChange>)> {
+    let mut changes = Vec::with_capacity(chunk.len());
+    for command in chunk {
+        match translate_command(command) {
+            Ok(change) => changes.push(change),
+            Err(reason) => {
+                debug!(
+                    "Bakery sending chunk through tc: {reason} in {:?}",
+                    command.join(" ")
+                );
+                return None;
+            }
+        }
+    }
+    match TcSession::open() {
+        Ok(session) => Some((session, changes)),
+        Err(e) => {
+            if !NETLINK_BATCH_WARNED.swap(true, Ordering::Relaxed) {
+                warn!("Unable to apply tc changes over netlink ({e}); falling back to tc batches");
+            }
+            None
+        }
+    }
+}
+
+/// Applies every change in order, like `tc -force -batch`: a failure does
+/// not stop later commands. Deletes of targets that are already gone are
+/// tolerated individually.
+fn apply_netlink_changes(
+    session: &mut TcSession,
+    changes: &[TcChange],
+    chunk: &[Vec<String>],
+    global_line_start: usize,
+) -> Vec<TcOperationFailure> {
+    let mut failures = Vec::new();
+    for (index, (change, command)) in changes.iter().zip(chunk).enumerate() {
+        match session.apply(change) {
+            Ok(()) => {}
+            Err(e) if change.verb == TcVerb::Delete && e.errno() == Some(Errno::ENOENT) => {
+                debug!(
+                    "Bakery tolerated delete of absent target: {}",
+                    command.join(" ")
+                );
+            }
+            Err(e) => failures.push(TcOperationFailure {
+                line: global_line_start + index,
+                command: command.join(" "),
+                error: e.to_string(),
+            }),
+        }
+    }
+    failures
+}
+
+fn run_chunk_over_netlink(
+    mut session: TcSession,
+    changes: &[TcChange],
+    chunk: &[Vec<String>],
+    purpose: &str,
+    global_line_start: usize,
+) -> Result<(), String> {
+    record_tc_io_event();
+    let failures = apply_netlink_changes(&mut session, changes, chunk, global_line_start);
+    let Some(first) = failures.first() else {
+        return Ok(());
+    };
+
+    let mut failure_summary = format!("netlink rejected {first}");
+    if failures.len() > 1 {
+        failure_summary.push_str(&format!(" (and {} more)", failures.len() - 1));
+    }
+    let mut detailed = format!(
+        "Command error for ({purpose}): {} of {} netlink operations failed\n",
+        failures.len(),
+        chunk.len()
+    );
+    for failure in &failures {
+        detailed.push_str(&format!("{failure}\n"));
+    }
+    error!(detailed);
+    record_chunk_failure(&detailed);
+    Err(failure_summary)
+}
+
 pub(crate) fn write_command_file(path: &Path, commands: &[Vec<String>]) -> Option<String> {
     let mut lines = String::new();
     let Ok(file) = File::create(path) else {
@@ -810,6 +992,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn netlink_entries_match_tc_snapshot_shape() {
+        let root = live_qdisc_entry_from_netlink(&TcMessage {
+            kind: "mq".to_string(),
+            handle: 0x7fff_0000,
+            parent: lqos_netlink::TC_H_ROOT,
+            ..Default::default()
+        });
+        assert_eq!(root.parent, None);
+        assert!(root.is_root);
+        assert_eq!(
+            root.handle,
+            Some(TcHandle::from_string("7fff:").expect("valid"))
+        );
+
+        let class = live_class_entry_from_netlink(&TcMessage {
+            kind: "htb".to_string(),
+            handle: 0x0002_1039,
+            parent: 0x0002_0003,
+            info: 0x90f1_0000,
+            ..Default::default()
+        });
+        assert_eq!(
+            class.class_id,
+            TcHandle::from_string("2:1039").expect("valid")
+        );
+        assert_eq!(
+            class.parent,
+            Some(TcHandle::from_string("2:3").expect("valid"))
+        );
+        assert_eq!(class.leaf_qdisc_major, Some(0x90f1));
+
+        let bare = live_class_entry_from_netlink(&TcMessage {
+            handle: 0x0002_0001,
+            parent: lqos_netlink::TC_H_ROOT,
+            ..Default::default()
+        });
+        assert_eq!(bare.parent, None);
+        assert_eq!(bare.leaf_qdisc_major, None);
+    }
+
     #[test]
     fn read_live_qdisc_handle_majors_collects_non_zero_handles_from_snapshot() {
         let raw = r#"
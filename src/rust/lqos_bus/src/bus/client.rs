@@ -1,55 +1,122 @@
 // SPDX-FileCopyrightText: 2025 LibreQoE support@libreqos.io
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-LibreQoS-Exception
 
-use crate::{BUS_SOCKET_PATH, BusReply, BusRequest, BusResponse, BusSession, bus::BusClientError};
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::UnixStream,
+use crate::{
+    BUS_SOCKET_PATH, BusReply, BusRequest, BusResponse, BusSession, BusTopic, BusUpdate,
+    bus::BusClientError,
 };
+use rustls::ClientConfig;
+use rustls::pki_types::ServerName;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, UnixStream};
+use tokio_rustls::TlsConnector;
 use tracing::error;
 
+use super::handshake::{CAPABILITY_SUBSCRIBE, NegotiatedProtocol, client_handshake};
 use super::protocol::{decode_reply_cbor, encode_session_cbor, read_frame, write_frame};
+use super::subscription::read_update;
+use super::tls::{load_certificates, load_private_key, load_roots};
 
-pub(crate) const MAGIC_NUMBER: [u8; 4] = [0x4C, 0x52, 0x45, 0x51]; // "LREQ"
-pub(crate) const MAGIC_RESPONSE: [u8; 4] = [0x4C, 0x52, 0x45, 0x50]; // "LREP"
+/// A connection the bus protocol can run over.
+trait BusStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> BusStream for S {}
+
+/// How to reach `lqosd` on another host over TLS.
+#[derive(Clone, Debug, Default)]
+pub struct RemoteBusTarget {
+    /// `host:port` of the remote bus listener.
+    pub address: String,
+    /// Name the server's certificate must be valid for.
+    pub server_name: String,
+    /// CA bundle (PEM) used to verify the server's certificate.
+    pub ca_file: String,
+    /// Bearer token, if authenticating with a token.
+    pub token: Option<String>,
+    /// Client certificate chain (PEM), if authenticating with a certificate.
+    pub client_cert_file: Option<String>,
+    /// Client private key (PEM), if authenticating with a certificate.
+    pub client_key_file: Option<String>,
+}
 
 /// A client for the libreqos bus, which connects to the bus socket and sends requests.
 /// The client is persistent by default, disconnecting when dropped.
 pub struct LibreqosBusClient {
-    stream: UnixStream,
+    stream: Box<dyn BusStream>,
     request_id: u64,
+    server: NegotiatedProtocol,
 }
 
 impl LibreqosBusClient {
-    /// Creates a new `LibreqosBusClient`.
+    /// Creates a new `LibreqosBusClient`, agreeing on a protocol version
+    /// with `lqosd`. Fails with `BusClientError::IncompatibleProtocol` if
+    /// the two can't talk to each other.
     pub async fn new() -> Result<Self, BusClientError> {
         let Ok(mut stream) = UnixStream::connect(BUS_SOCKET_PATH).await else {
             return Err(BusClientError::SocketNotFound);
         };
 
-        // Send the magic number to the bus
-        stream.write(&MAGIC_NUMBER).await.map_err(|_| {
-            error!("Unable to write magic number to {BUS_SOCKET_PATH} stream.");
-            BusClientError::StreamWriteError
+        let server = client_handshake(&mut stream, None).await.inspect_err(|e| {
+            error!("Bus handshake with {BUS_SOCKET_PATH} failed: {e}");
         })?;
 
-        // Read the response magic number
-        let mut buf = [0u8; 4];
-        stream.read_exact(&mut buf).await.map_err(|_| {
-            error!("Unable to read magic number from {BUS_SOCKET_PATH} stream.");
-            BusClientError::StreamReadError
-        })?;
-        if buf != MAGIC_RESPONSE {
-            error!("Received invalid magic number from {BUS_SOCKET_PATH} stream.");
-            return Err(BusClientError::StreamReadError);
-        }
+        Ok(Self {
+            stream: Box::new(stream),
+            request_id: 0,
+            server,
+        })
+    }
+
+    /// Connects to `lqosd` on another host over TLS. What the client may
+    /// do depends on the credential it presents.
+    pub async fn connect_remote(target: &RemoteBusTarget) -> Result<Self, BusClientError> {
+        let failed = BusClientError::RemoteConnectionFailed;
+        lqos_utils::rustls::ensure_rustls_crypto_provider().map_err(|e| failed(e.to_string()))?;
+        let roots = load_roots(&target.ca_file).map_err(failed)?;
+        let builder = ClientConfig::builder().with_root_certificates(roots);
+        let tls_config = match (&target.client_cert_file, &target.client_key_file) {
+            (Some(cert_file), Some(key_file)) => builder
+                .with_client_auth_cert(
+                    load_certificates(cert_file).map_err(failed)?,
+                    load_private_key(key_file).map_err(failed)?,
+                )
+                .map_err(|e| failed(e.to_string()))?,
+            _ => builder.with_no_client_auth(),
+        };
+        let server_name =
+            ServerName::try_from(target.server_name.clone()).map_err(|e| failed(e.to_string()))?;
+
+        let tcp = TcpStream::connect(&target.address)
+            .await
+            .map_err(|e| failed(format!("{}: {e}", target.address)))?;
+        let mut stream = TlsConnector::from(Arc::new(tls_config))
+            .connect(server_name, tcp)
+            .await
+            .map_err(|e| failed(format!("TLS handshake with {}: {e}", target.address)))?;
+
+        let server = client_handshake(&mut stream, target.token.as_deref())
+            .await
+            .inspect_err(|e| {
+                error!("Bus handshake with {} failed: {e}", target.address);
+            })?;
 
         Ok(Self {
-            stream,
+            stream: Box::new(stream),
             request_id: 0,
+            server,
         })
     }
 
+    /// The protocol version agreed with `lqosd`.
+    pub fn protocol_version(&self) -> u32 {
+        self.server.version
+    }
+
+    /// Does `lqosd` offer `capability` (one of the `CAPABILITY_*` names)?
+    pub fn server_supports(&self, capability: &str) -> bool {
+        self.server.supports(capability)
+    }
+
     /// Sends a request to the bus and waits for a response.
     ///
     /// ## Arguments
@@ -91,6 +158,44 @@ impl LibreqosBusClient {
         }
         Ok(response.responses)
     }
+
+    /// Turns this connection into a subscription: `lqosd` pushes an update
+    /// for everything published on `topics` until the subscription is
+    /// dropped. The connection can no longer be used for requests.
+    pub async fn subscribe(
+        mut self,
+        topics: Vec<BusTopic>,
+    ) -> Result<BusSubscription, BusClientError> {
+        if !self.server_supports(CAPABILITY_SUBSCRIBE) {
+            return Err(BusClientError::IncompatibleProtocol(
+                "lqosd does not support subscriptions".to_string(),
+            ));
+        }
+        let responses = self.request(vec![BusRequest::Subscribe { topics }]).await?;
+        match responses.first() {
+            Some(BusResponse::Ack) => Ok(BusSubscription {
+                stream: self.stream,
+            }),
+            other => {
+                error!("Subscription refused: {other:?}");
+                Err(BusClientError::DecodingError)
+            }
+        }
+    }
+}
+
+/// A bus connection receiving pushed updates. Created with
+/// `LibreqosBusClient::subscribe`; disconnects when dropped.
+pub struct BusSubscription {
+    stream: Box<dyn BusStream>,
+}
+
+impl BusSubscription {
+    /// Waits for the next update. Slow readers receive `BusUpdate::Lagged`
+    /// in place of the updates they missed.
+    pub async fn next(&mut self) -> Result<BusUpdate, BusClientError> {
+        read_update(&mut self.stream).await
+    }
 }
 
 /// Convenient wrapper for accessing the bus, for a single request-response cycle. This
@@ -0,0 +1,305 @@
+// SPDX-FileCopyrightText: 2025 LibreQoE support@libreqos.io
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-LibreQoS-Exception
+
+//! Serves one bus connection, whatever it arrived on.
+
+use crate::{BusReply, BusRequest, BusResponse, BusTopic};
+use lqos_config::BusAccess;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+use super::handshake::{
+    CAPABILITY_UNSUPPORTED_RESPONSE, HANDSHAKE_TIMEOUT, NegotiatedProtocol, server_handshake,
+};
+use super::protocol::{
+    WireRequest, decode_session_lenient, encode_reply_cbor, read_frame, write_frame,
+};
+use super::subscription::stream_updates;
+
+/// Answers a session containing `BusRequest::Subscribe`. Returns the topics
+/// to stream if the session is a valid subscription (`Subscribe` alone),
+/// along with the reply to send first.
+fn subscription_reply(requests: &[WireRequest]) -> Option<(Option<Vec<BusTopic>>, BusReply)> {
+    if !requests
+        .iter()
+        .any(|request| matches!(request, WireRequest::Known(BusRequest::Subscribe { .. })))
+    {
+        return None;
+    }
+    if let [WireRequest::Known(BusRequest::Subscribe { topics })] = requests {
+        let reply = BusReply {
+            responses: vec![BusResponse::Ack],
+        };
+        return Some((Some(topics.clone()), reply));
+    }
+    let refusal =
+        BusResponse::Fail("Subscribe must be the only request in its session".to_string());
+    let reply = BusReply {
+        responses: vec![refusal; requests.len()],
+    };
+    Some((None, reply))
+}
+
+/// The reply to a request the connection isn't allowed to make.
+fn forbidden_response(request: &BusRequest) -> BusResponse {
+    let debug = format!("{request:?}");
+    let name = debug
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .next()
+        .unwrap_or_default();
+    BusResponse::Fail(format!("Permission denied: {name} requires admin access"))
+}
+
+/// The reply to a request this build doesn't recognize. Clients that can't
+/// decode `BusResponse::Unsupported` are sent a `Fail` instead.
+fn unsupported_response(request: String, client: &NegotiatedProtocol) -> BusResponse {
+    if client.supports(CAPABILITY_UNSUPPORTED_RESPONSE) {
+        BusResponse::Unsupported { request }
+    } else {
+        BusResponse::Fail(format!("Unsupported request: {request}"))
+    }
+}
+
+/// Builds the reply to a session. Permitted requests go to the handler; the
+/// rest are refused or answered as unsupported, in their original
+/// positions. Returns the topics to stream if the session was a
+/// subscription.
+fn answer_session(
+    requests: Vec<WireRequest>,
+    client: &NegotiatedProtocol,
+    access: BusAccess,
+    handle_bus_requests: fn(&[BusRequest], &mut Vec<BusResponse>),
+) -> (Option<Vec<BusTopic>>, BusReply) {
+    if let Some(subscription) = subscription_reply(&requests) {
+        return subscription;
+    }
+    let mut permitted = Vec::with_capacity(requests.len());
+    let mut answered = Vec::with_capacity(requests.len());
+    for request in requests {
+        match request {
+            WireRequest::Known(request) if access.permits(request.required_access()) => {
+                permitted.push(request);
+                answered.push(None);
+            }
+            WireRequest::Known(request) => answered.push(Some(forbidden_response(&request))),
+            WireRequest::Unknown(name) => answered.push(Some(unsupported_response(name, client))),
+        }
+    }
+    let mut handled = Vec::with_capacity(permitted.len());
+    handle_bus_requests(&permitted, &mut handled);
+    if answered.iter().all(Option::is_none) {
+        return (None, BusReply { responses: handled });
+    }
+    let mut handled = handled.into_iter();
+    let responses = answered
+        .into_iter()
+        .map(|answer| match answer {
+            Some(answer) => answer,
+            None => handled
+                .next()
+                .unwrap_or_else(|| BusResponse::Fail("No response was generated".to_string())),
+        })
+        .collect();
+    (None, BusReply { responses })
+}
+
+/// Handshakes with a client, then answers its sessions until it hangs up.
+/// `authorize` maps the client's token (if any) to the access it is granted.
+pub(crate) async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin>(
+    mut socket: S,
+    handle_bus_requests: fn(&[BusRequest], &mut Vec<BusResponse>),
+    authorize: impl FnOnce(Option<&str>) -> Option<BusAccess>,
+) {
+    // Agree on a protocol version with the client
+    let handshake = timeout(HANDSHAKE_TIMEOUT, server_handshake(&mut socket, authorize)).await;
+    let (client, access) = match handshake {
+        Ok(Ok(Some(accepted))) => accepted,
+        Ok(Ok(None)) => return,
+        Err(_) => {
+            warn!("Bus client didn't complete the handshake in time; closing the connection.");
+            return;
+        }
+        Ok(Err(e)) => {
+            debug!("Unable to complete the bus handshake. Server remains alive.");
+            debug!("This is probably harmless.");
+            debug!("{:?}", e);
+            return;
+        }
+    };
+    debug!(
+        "Bus client speaks protocol version {}, access {access:?}",
+        client.version
+    );
+
+    loop {
+        let (request_id, request_bytes) = match read_frame(&mut socket).await {
+            Ok(frame) => frame,
+            Err(e) => {
+                debug!("Unable to read request frame from client socket.");
+                debug!("This is probably harmless.");
+                debug!("{:?}", e);
+                break;
+            }
+        };
+        if request_bytes.is_empty() {
+            warn!("Received empty request payload; closing client socket.");
+            break;
+        }
+        debug!(
+            "Received request ID: {request_id}, Size: {}",
+            request_bytes.len()
+        );
+
+        // Decode the request
+        let Ok(requests) = decode_session_lenient(&request_bytes) else {
+            warn!("Invalid data on bus connection");
+            break;
+        };
+        debug!("Received request: {:?}", requests);
+
+        // Subscriptions are served here rather than by the handler
+        let (topics, response) = answer_session(requests, &client, access, handle_bus_requests);
+
+        // Encode the response
+        let Ok(encoded_response) = encode_reply_cbor(&response) else {
+            warn!("Unable to encode response for request ID: {request_id}");
+            break;
+        };
+        debug!("Sending response for request ID: {request_id}");
+
+        // Send the response back to the client
+        if let Err(e) = write_frame(&mut socket, request_id, &encoded_response).await {
+            debug!("Unable to write response to client socket. Server remains alive.");
+            debug!("This is probably harmless.");
+            debug!("{:?}", e);
+            break; // Escape out of the thread
+        }
+        debug!("Response sent for request ID: {request_id}");
+
+        if let Some(topics) = topics {
+            debug!("Client subscribed to {topics:?}");
+            stream_updates(&mut socket, &topics).await;
+            break;
+        }
+    } // End of the request handling loop
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::handshake::BUS_PROTOCOL_VERSION;
+    use super::*;
+
+    fn ack_all(requests: &[BusRequest], responses: &mut Vec<BusResponse>) {
+        responses.extend(requests.iter().map(|_| BusResponse::Ack));
+    }
+
+    fn current_client() -> NegotiatedProtocol {
+        NegotiatedProtocol {
+            version: BUS_PROTOCOL_VERSION,
+            capabilities: vec![CAPABILITY_UNSUPPORTED_RESPONSE.to_string()],
+        }
+    }
+
+    #[test]
+    fn subscribe_must_be_sent_alone() {
+        let ping = WireRequest::Known(BusRequest::Ping);
+        assert!(subscription_reply(std::slice::from_ref(&ping)).is_none());
+
+        let subscribe = || {
+            WireRequest::Known(BusRequest::Subscribe {
+                topics: vec![BusTopic::UrgentIssues],
+            })
+        };
+        let (topics, reply) = subscription_reply(&[subscribe()]).expect("is a subscription");
+        assert_eq!(topics, Some(vec![BusTopic::UrgentIssues]));
+        assert_eq!(reply.responses, vec![BusResponse::Ack]);
+
+        let (topics, reply) = subscription_reply(&[ping, subscribe()]).expect("is a subscription");
+        assert_eq!(topics, None);
+        assert_eq!(reply.responses.len(), 2);
+        assert!(matches!(reply.responses[0], BusResponse::Fail(_)));
+    }
+
+    #[test]
+    fn unknown_requests_are_answered_as_unsupported() {
+        let requests = vec![
+            WireRequest::Known(BusRequest::Ping),
+            WireRequest::Unknown("FromTheFuture".to_string()),
+            WireRequest::Known(BusRequest::Ping),
+        ];
+        let (topics, reply) =
+            answer_session(requests, &current_client(), BusAccess::Admin, ack_all);
+        assert_eq!(topics, None);
+        assert_eq!(
+            reply.responses,
+            vec![
+                BusResponse::Ack,
+                BusResponse::Unsupported {
+                    request: "FromTheFuture".to_string()
+                },
+                BusResponse::Ack,
+            ]
+        );
+    }
+
+    #[test]
+    fn clients_without_the_capability_get_a_failure_instead() {
+        let requests = vec![WireRequest::Unknown("FromTheFuture".to_string())];
+        let legacy = NegotiatedProtocol {
+            version: 1,
+            capabilities: Vec::new(),
+        };
+        let (_, reply) = answer_session(requests, &legacy, BusAccess::Admin, ack_all);
+        assert!(
+            matches!(&reply.responses[..], [BusResponse::Fail(message)] if message.contains("FromTheFuture"))
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_request_round_trip_over_the_wire() {
+        use serde_cbor::Value;
+        use std::collections::BTreeMap;
+        use tokio::io::duplex;
+
+        let mut session = BTreeMap::new();
+        session.insert(
+            Value::Text("requests".to_string()),
+            Value::Array(vec![Value::Text("FromTheFuture".to_string())]),
+        );
+        let bytes = serde_cbor::to_vec(&Value::Map(session)).expect("encode");
+        let (mut client, mut server) = duplex(64 * 1024);
+        write_frame(&mut client, 9, &bytes).await.expect("write");
+
+        let (request_id, request_bytes) = read_frame(&mut server).await.expect("read");
+        let requests = decode_session_lenient(&request_bytes).expect("decode");
+        let (_, reply) = answer_session(requests, &current_client(), BusAccess::Admin, ack_all);
+        let encoded = encode_reply_cbor(&reply).expect("encode reply");
+        write_frame(&mut server, request_id, &encoded)
+            .await
+            .expect("write reply");
+
+        let (reply_id, reply_bytes) = read_frame(&mut client).await.expect("read reply");
+        assert_eq!(reply_id, 9);
+        let reply: BusReply = serde_cbor::from_slice(&reply_bytes).expect("decode reply");
+        assert_eq!(
+            reply.responses,
+            vec![BusResponse::Unsupported {
+                request: "FromTheFuture".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn read_only_connections_cannot_make_admin_requests() {
+        let requests = vec![
+            WireRequest::Known(BusRequest::GetCurrentThroughput),
+            WireRequest::Known(BusRequest::ReloadLibreQoS),
+        ];
+        let (_, reply) = answer_session(requests, &current_client(), BusAccess::ReadOnly, ack_all);
+        assert_eq!(reply.responses[0], BusResponse::Ack);
+        assert!(
+            matches!(&reply.responses[1], BusResponse::Fail(message) if message.contains("ReloadLibreQoS"))
+        );
+    }
+}
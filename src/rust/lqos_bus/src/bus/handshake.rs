@@ -0,0 +1,380 @@
+// SPDX-FileCopyrightText: 2025 LibreQoE support@libreqos.io
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-LibreQoS-Exception
+
+//! Connection handshake. Clients open with `HELLO_MAGIC` followed by a
+//! `BusHello` frame naming their protocol version and capabilities; `lqosd`
+//! answers with `WELCOME_MAGIC` and a `BusWelcome` frame, either accepting
+//! (and naming the version both sides will speak) or rejecting the client
+//! with a readable reason.
+//!
+//! Clients that predate versioning send the bare `LEGACY_MAGIC_NUMBER`.
+//! They are still served, as protocol version 1 with no capabilities.
+//!
+//! Remote clients also authenticate here, with a token in their hello or a
+//! TLS client certificate; the server decides what the connection may do.
+
+use super::BusClientError;
+use super::protocol::{read_frame, read_frame_limited, write_frame};
+use lqos_config::BusAccess;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::error;
+
+/// Sent by clients that predate protocol versioning.
+pub(crate) const LEGACY_MAGIC_NUMBER: [u8; 4] = [0x4C, 0x52, 0x45, 0x51]; // "LREQ"
+/// The server's answer to `LEGACY_MAGIC_NUMBER`.
+pub(crate) const LEGACY_MAGIC_RESPONSE: [u8; 4] = [0x4C, 0x52, 0x45, 0x50]; // "LREP"
+/// Opens a versioned handshake; a `BusHello` frame follows.
+pub(crate) const HELLO_MAGIC: [u8; 4] = [0x4C, 0x51, 0x42, 0x48]; // "LQBH"
+/// Answers a versioned handshake; a `BusWelcome` frame follows.
+pub(crate) const WELCOME_MAGIC: [u8; 4] = [0x4C, 0x51, 0x42, 0x57]; // "LQBW"
+/// Frame ID used for the hello and welcome frames.
+const HANDSHAKE_FRAME_ID: u64 = 0;
+/// Largest hello the server will read. The client hasn't authenticated
+/// yet, so it mustn't be able to make the server buffer a full frame.
+pub(crate) const MAX_HELLO_BYTES: usize = 4 * 1024;
+/// How long a client has to complete the handshake before it is dropped.
+pub(crate) const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The bus protocol version spoken by this build.
+pub const BUS_PROTOCOL_VERSION: u32 = 2;
+/// The oldest versioned protocol this build will talk to.
+pub const MIN_BUS_PROTOCOL_VERSION: u32 = 2;
+/// The version assigned to clients that skip the versioned handshake.
+pub const LEGACY_BUS_PROTOCOL_VERSION: u32 = 1;
+
+/// Capability: the server accepts `BusRequest::Subscribe`.
+pub const CAPABILITY_SUBSCRIBE: &str = "subscribe";
+/// Capability: the peer understands `BusResponse::Unsupported`. Clients
+/// without it are sent `BusResponse::Fail` for requests the server doesn't
+/// recognize.
+pub const CAPABILITY_UNSUPPORTED_RESPONSE: &str = "unsupported_response";
+
+/// Capabilities this build offers and understands.
+pub(crate) const CAPABILITIES: [&str; 2] = [CAPABILITY_SUBSCRIBE, CAPABILITY_UNSUPPORTED_RESPONSE];
+
+fn our_capabilities() -> Vec<String> {
+    CAPABILITIES.iter().map(|c| c.to_string()).collect()
+}
+
+/// The first frame a versioned client sends.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BusHello {
+    /// Newest protocol version the client speaks.
+    pub protocol_version: u32,
+    /// Capabilities the client understands. Names are strings so that
+    /// unknown ones can be ignored rather than breaking decoding.
+    pub capabilities: Vec<String>,
+    /// Bearer token for remote connections.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// The server's answer to a `BusHello`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BusWelcome {
+    /// The protocol version both sides will speak.
+    pub protocol_version: u32,
+    /// Capabilities the server offers.
+    pub capabilities: Vec<String>,
+    /// Set if the client was refused, explaining why. The server closes
+    /// the connection after sending it.
+    pub rejected: Option<String>,
+    /// True if the client was refused because it failed to authenticate.
+    #[serde(default)]
+    pub unauthorized: bool,
+}
+
+/// What one side learned about the other during the handshake.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NegotiatedProtocol {
+    /// The protocol version in use on the connection.
+    pub version: u32,
+    /// The peer's capabilities.
+    pub capabilities: Vec<String>,
+}
+
+impl NegotiatedProtocol {
+    fn legacy() -> Self {
+        Self {
+            version: LEGACY_BUS_PROTOCOL_VERSION,
+            capabilities: Vec::new(),
+        }
+    }
+
+    /// Does the peer advertise `capability`?
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+}
+
+/// Decides how the server answers a client's hello.
+pub(crate) fn welcome_for(hello: &BusHello) -> BusWelcome {
+    let rejected = (hello.protocol_version < MIN_BUS_PROTOCOL_VERSION).then(|| {
+        format!(
+            "Client speaks bus protocol version {}, lqosd requires {} to {}. Please upgrade the client.",
+            hello.protocol_version, MIN_BUS_PROTOCOL_VERSION, BUS_PROTOCOL_VERSION
+        )
+    });
+    BusWelcome {
+        protocol_version: hello.protocol_version.min(BUS_PROTOCOL_VERSION),
+        capabilities: our_capabilities(),
+        rejected,
+        unauthorized: false,
+    }
+}
+
+/// Checks the server's welcome from the client's side.
+pub(crate) fn accept_welcome(welcome: BusWelcome) -> Result<NegotiatedProtocol, BusClientError> {
+    if welcome.unauthorized {
+        return Err(BusClientError::Unauthorized);
+    }
+    if let Some(reason) = welcome.rejected {
+        return Err(BusClientError::IncompatibleProtocol(reason));
+    }
+    if welcome.protocol_version < MIN_BUS_PROTOCOL_VERSION
+        || welcome.protocol_version > BUS_PROTOCOL_VERSION
+    {
+        return Err(BusClientError::IncompatibleProtocol(format!(
+            "lqosd chose bus protocol version {}, this client speaks {} to {}",
+            welcome.protocol_version, MIN_BUS_PROTOCOL_VERSION, BUS_PROTOCOL_VERSION
+        )));
+    }
+    Ok(NegotiatedProtocol {
+        version: welcome.protocol_version,
+        capabilities: welcome.capabilities,
+    })
+}
+
+fn encode_cbor<T: Serialize>(value: &T) -> Result<Vec<u8>, BusClientError> {
+    serde_cbor::to_vec(value).map_err(|e| {
+        error!("Unable to serialize handshake to CBOR: {:?}", e);
+        BusClientError::EncodingError
+    })
+}
+
+fn decode_cbor<T: for<'de> Deserialize<'de>>(bytes: &[u8]) -> Result<T, BusClientError> {
+    serde_cbor::from_slice(bytes).map_err(|e| {
+        error!("Unable to deserialize handshake from CBOR: {:?}", e);
+        BusClientError::DecodingError
+    })
+}
+
+/// Performs the client side of the handshake, presenting `token` if given.
+pub(crate) async fn client_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    token: Option<&str>,
+) -> Result<NegotiatedProtocol, BusClientError> {
+    let hello = BusHello {
+        protocol_version: BUS_PROTOCOL_VERSION,
+        capabilities: our_capabilities(),
+        token: token.map(str::to_string),
+    };
+    stream
+        .write_all(&HELLO_MAGIC)
+        .await
+        .map_err(|_| BusClientError::StreamWriteError)?;
+    write_frame(stream, HANDSHAKE_FRAME_ID, &encode_cbor(&hello)?).await?;
+
+    let mut magic = [0u8; 4];
+    if stream.read_exact(&mut magic).await.is_err() {
+        // Servers that predate versioning hang up on a magic number they
+        // don't recognize.
+        return Err(BusClientError::IncompatibleProtocol(
+            "lqosd closed the connection during the handshake; it may be older than this client"
+                .to_string(),
+        ));
+    }
+    if magic != WELCOME_MAGIC {
+        error!("Received invalid handshake reply from the bus.");
+        return Err(BusClientError::StreamReadError);
+    }
+    let (_, bytes) = read_frame(stream).await?;
+    accept_welcome(decode_cbor(&bytes)?)
+}
+
+/// Performs the server side of the handshake. `authorize` maps the client's
+/// token (if any) to the access it is granted, or `None` to refuse it.
+/// Returns `None` if the client should be disconnected (a rejected client
+/// has already been told why).
+pub(crate) async fn server_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    authorize: impl FnOnce(Option<&str>) -> Option<BusAccess>,
+) -> Result<Option<(NegotiatedProtocol, BusAccess)>, BusClientError> {
+    let mut magic = [0u8; 4];
+    socket
+        .read_exact(&mut magic)
+        .await
+        .map_err(|_| BusClientError::StreamReadError)?;
+    match magic {
+        LEGACY_MAGIC_NUMBER => {
+            let Some(access) = authorize(None) else {
+                tracing::warn!("Refused unauthenticated legacy bus client");
+                return Ok(None);
+            };
+            socket
+                .write_all(&LEGACY_MAGIC_RESPONSE)
+                .await
+                .map_err(|_| BusClientError::StreamWriteError)?;
+            Ok(Some((NegotiatedProtocol::legacy(), access)))
+        }
+        HELLO_MAGIC => {
+            let (_, bytes) = read_frame_limited(socket, MAX_HELLO_BYTES).await?;
+            let hello: BusHello = decode_cbor(&bytes)?;
+            let mut welcome = welcome_for(&hello);
+            let access = authorize(hello.token.as_deref());
+            if access.is_none() && welcome.rejected.is_none() {
+                welcome.rejected = Some("Authentication failed".to_string());
+                welcome.unauthorized = true;
+            }
+            socket
+                .write_all(&WELCOME_MAGIC)
+                .await
+                .map_err(|_| BusClientError::StreamWriteError)?;
+            write_frame(socket, HANDSHAKE_FRAME_ID, &encode_cbor(&welcome)?).await?;
+            let (None, Some(access)) = (&welcome.rejected, access) else {
+                tracing::warn!(
+                    "Refused bus client: {}",
+                    welcome.rejected.unwrap_or_default()
+                );
+                return Ok(None);
+            };
+            let client = NegotiatedProtocol {
+                version: welcome.protocol_version,
+                capabilities: hello.capabilities,
+            };
+            Ok(Some((client, access)))
+        }
+        _ => Err(BusClientError::DecodingError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    #[tokio::test]
+    async fn versioned_clients_negotiate_capabilities() {
+        let (mut client, mut server) = duplex(64 * 1024);
+        let (client_side, server_side) = tokio::join!(
+            client_handshake(&mut client, None),
+            server_handshake(&mut server, |_| Some(BusAccess::Admin))
+        );
+
+        let client_side = client_side.expect("client handshake");
+        assert_eq!(client_side.version, BUS_PROTOCOL_VERSION);
+        assert!(client_side.supports(CAPABILITY_SUBSCRIBE));
+
+        let (server_side, access) = server_side.expect("server handshake").expect("accepted");
+        assert_eq!(access, BusAccess::Admin);
+        assert_eq!(server_side.version, BUS_PROTOCOL_VERSION);
+        assert!(server_side.supports(CAPABILITY_UNSUPPORTED_RESPONSE));
+        assert!(!server_side.supports("telepathy"));
+    }
+
+    #[tokio::test]
+    async fn legacy_clients_are_still_served() {
+        let (mut client, mut server) = duplex(1024);
+        client
+            .write_all(&LEGACY_MAGIC_NUMBER)
+            .await
+            .expect("write magic");
+        let (negotiated, _) = server_handshake(&mut server, |_| Some(BusAccess::Admin))
+            .await
+            .expect("server handshake")
+            .expect("accepted");
+        assert_eq!(negotiated, NegotiatedProtocol::legacy());
+
+        let mut reply = [0u8; 4];
+        client.read_exact(&mut reply).await.expect("read reply");
+        assert_eq!(reply, LEGACY_MAGIC_RESPONSE);
+    }
+
+    #[test]
+    fn old_clients_are_rejected_with_a_reason() {
+        let welcome = welcome_for(&BusHello {
+            protocol_version: MIN_BUS_PROTOCOL_VERSION - 1,
+            capabilities: Vec::new(),
+            token: None,
+        });
+        let reason = welcome.rejected.clone().expect("rejected");
+        assert!(reason.contains("upgrade"), "{reason}");
+        assert!(matches!(
+            accept_welcome(welcome),
+            Err(BusClientError::IncompatibleProtocol(_))
+        ));
+    }
+
+    #[test]
+    fn newer_clients_are_offered_our_version() {
+        let welcome = welcome_for(&BusHello {
+            protocol_version: BUS_PROTOCOL_VERSION + 5,
+            capabilities: vec!["from_the_future".to_string()],
+            token: None,
+        });
+        assert_eq!(welcome.rejected, None);
+        assert_eq!(welcome.protocol_version, BUS_PROTOCOL_VERSION);
+    }
+
+    #[tokio::test]
+    async fn pre_versioning_servers_give_a_clear_error() {
+        let (mut client, mut server) = duplex(1024);
+        let old_server = async move {
+            let mut magic = [0u8; 4];
+            server.read_exact(&mut magic).await.expect("read magic");
+            // An old server doesn't recognize the magic and hangs up.
+            drop(server);
+        };
+        let (result, _) = tokio::join!(client_handshake(&mut client, None), old_server);
+        assert!(matches!(
+            result,
+            Err(BusClientError::IncompatibleProtocol(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn tokens_decide_access() {
+        let authorize = |token: Option<&str>| match token {
+            Some("read-only-token") => Some(BusAccess::ReadOnly),
+            _ => None,
+        };
+
+        let (mut client, mut server) = duplex(64 * 1024);
+        let (client_side, server_side) = tokio::join!(
+            client_handshake(&mut client, Some("read-only-token")),
+            server_handshake(&mut server, authorize)
+        );
+        client_side.expect("accepted");
+        let (_, access) = server_side.expect("server handshake").expect("accepted");
+        assert_eq!(access, BusAccess::ReadOnly);
+
+        let (mut client, mut server) = duplex(64 * 1024);
+        let (client_side, server_side) = tokio::join!(
+            client_handshake(&mut client, Some("guess")),
+            server_handshake(&mut server, authorize)
+        );
+        assert!(matches!(client_side, Err(BusClientError::Unauthorized)));
+        assert!(server_side.expect("server handshake").is_none());
+    }
+
+    #[tokio::test]
+    async fn oversized_hellos_are_refused_before_authentication() {
+        let (mut client, mut server) = duplex(1024);
+        client.write_all(&HELLO_MAGIC).await.expect("write magic");
+        client.write_u64_le(HANDSHAKE_FRAME_ID).await.expect("id");
+        client
+            .write_u64_le(MAX_HELLO_BYTES as u64 + 1)
+            .await
+            .expect("length");
+        let mut authorized = false;
+        let result = server_handshake(&mut server, |_| {
+            authorized = true;
+            Some(BusAccess::Admin)
+        })
+        .await;
+        assert!(matches!(result, Err(BusClientError::DecodingError)));
+        assert!(!authorized);
+    }
+}
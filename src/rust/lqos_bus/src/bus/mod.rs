@@ -2,14 +2,22 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-LibreQoS-Exception
 
 mod client;
+mod connection;
+mod handshake;
 mod protocol;
 mod queue_data;
 mod reply;
 mod request;
 pub mod response;
 mod session;
+mod subscription;
+mod tls;
 mod unix_socket_server;
-pub use client::{LibreqosBusClient, bus_request};
+pub use client::{BusSubscription, LibreqosBusClient, RemoteBusTarget, bus_request};
+pub use handshake::{
+    BUS_PROTOCOL_VERSION, CAPABILITY_SUBSCRIBE, CAPABILITY_UNSUPPORTED_RESPONSE,
+    LEGACY_BUS_PROTOCOL_VERSION, MIN_BUS_PROTOCOL_VERSION,
+};
 pub use queue_data::*;
 pub use reply::BusReply;
 pub use request::{
@@ -23,7 +31,13 @@ pub use response::{
     TreeGuardRuntimeNodeOperationSnapshot, UrgentIssue,
 };
 pub use session::BusSession;
+pub use subscription::{
+    BakeryEvent, BusTopic, BusUpdate, CircuitActivityEntry, CircuitStatusChange,
+    LocalBusSubscription, StormGuardAction, TreeGuardDecision, has_bus_subscribers,
+    publish_bus_update, subscribe_bus_updates,
+};
 use thiserror::Error;
+pub use tls::{TlsBusServer, TlsBusServerError};
 pub use unix_socket_server::UnixSocketServer;
 
 /// The local socket path to which `lqosd` will bind itself,
@@ -58,4 +72,14 @@ pub enum BusClientError {
     /// The socket connection is no longer usable.
     #[error("Stream is no longer connected")]
     StreamNotConnected,
+    /// The client and `lqosd` can't agree on a protocol, or the server
+    /// lacks a capability the call needs.
+    #[error("Incompatible bus protocol: {0}")]
+    IncompatibleProtocol(String),
+    /// `lqosd` didn't accept the client's credentials.
+    #[error("The bus refused the client's credentials")]
+    Unauthorized,
+    /// A remote bus connection couldn't be set up.
+    #[error("Unable to connect to the remote bus: {0}")]
+    RemoteConnectionFailed(String),
 }
@@ -1,7 +1,8 @@
 // SPDX-FileCopyrightText: 2025 LibreQoE support@libreqos.io
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-LibreQoS-Exception
 
-use super::{BusClientError, BusReply, BusSession};
+use super::{BusClientError, BusReply, BusRequest, BusSession};
+use serde::Deserialize;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tracing::error;
 
@@ -16,11 +17,60 @@ pub(crate) fn encode_session_cbor(session: &BusSession) -> Result<Vec<u8>, BusCl
     })
 }
 
-pub(crate) fn decode_session_cbor(bytes: &[u8]) -> Result<BusSession, BusClientError> {
-    serde_cbor::from_slice(bytes).map_err(|e| {
+/// A request as received by the server.
+#[derive(Debug, PartialEq)]
+pub(crate) enum WireRequest {
+    /// A request this build understands.
+    Known(BusRequest),
+    /// A request this build can't decode, by variant name. Usually sent by
+    /// a client newer than `lqosd`.
+    Unknown(String),
+}
+
+/// A session whose requests are decoded one at a time.
+#[derive(Deserialize)]
+struct WireSession {
+    requests: Vec<serde_cbor::Value>,
+}
+
+/// Decodes a session, keeping requests this build doesn't recognize as
+/// `WireRequest::Unknown` rather than failing the whole session.
+pub(crate) fn decode_session_lenient(bytes: &[u8]) -> Result<Vec<WireRequest>, BusClientError> {
+    if let Ok(session) = serde_cbor::from_slice::<BusSession>(bytes) {
+        return Ok(session
+            .requests
+            .into_iter()
+            .map(WireRequest::Known)
+            .collect());
+    }
+    let session: WireSession = serde_cbor::from_slice(bytes).map_err(|e| {
         error!("Unable to deserialize session from CBOR: {:?}", e);
         BusClientError::DecodingError
-    })
+    })?;
+    Ok(session
+        .requests
+        .into_iter()
+        .map(|value| {
+            let name = request_name(&value);
+            match serde_cbor::value::from_value(value) {
+                Ok(request) => WireRequest::Known(request),
+                Err(_) => WireRequest::Unknown(name),
+            }
+        })
+        .collect())
+}
+
+/// The variant name of an encoded request: unit variants are encoded as a
+/// string, the rest as a single-entry map keyed by the name.
+fn request_name(value: &serde_cbor::Value) -> String {
+    match value {
+        serde_cbor::Value::Text(name) => name.clone(),
+        serde_cbor::Value::Map(map) => match map.keys().next() {
+            Some(serde_cbor::Value::Text(name)) if map.len() == 1 => name.clone(),
+            _ => "unrecognized".to_string(),
+        },
+        _ => "unrecognized".to_string(),
+    }
 }
 
 pub(crate) fn encode_reply_cbor(reply: &BusReply) -> Result<Vec<u8>, BusClientError> {
@@ -60,10 +110,6 @@ pub(crate) async fn write_frame<W: AsyncWrite + Unpin>(
         .await
         .map_err(|_| BusClientError::StreamWriteError)?;
 
-    if payload.is_empty() {
-        return Ok(());
-    }
-
     for chunk in payload.chunks(BUS_CHUNK_SIZE) {
         let chunk_len = u32::try_from(chunk.len()).map_err(|_| {
             error!("Chunk length exceeds u32 capacity.");
@@ -80,12 +126,26 @@ pub(crate) async fn write_frame<W: AsyncWrite + Unpin>(
             .await
             .map_err(|_| BusClientError::StreamWriteError)?;
     }
+    // TLS streams buffer records until flushed.
+    writer
+        .flush()
+        .await
+        .map_err(|_| BusClientError::StreamWriteError)?;
 
     Ok(())
 }
 
 pub(crate) async fn read_frame<R: AsyncRead + Unpin>(
     reader: &mut R,
+) -> Result<(u64, Vec<u8>), BusClientError> {
+    read_frame_limited(reader, MAX_FRAME_BYTES).await
+}
+
+/// Reads a frame, refusing one that announces more than `max_bytes` before
+/// any of its payload is buffered.
+pub(crate) async fn read_frame_limited<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_bytes: usize,
 ) -> Result<(u64, Vec<u8>), BusClientError> {
     let request_id = reader
         .read_u64_le()
@@ -104,11 +164,8 @@ pub(crate) async fn read_frame<R: AsyncRead + Unpin>(
         error!("Payload size exceeds usize capacity.");
         BusClientError::DecodingError
     })?;
-    if payload_len > MAX_FRAME_BYTES {
-        error!(
-            "Payload size {} exceeds MAX_FRAME_BYTES {}.",
-            payload_len, MAX_FRAME_BYTES
-        );
+    if payload_len > max_bytes {
+        error!("Payload size {payload_len} exceeds the frame limit {max_bytes}.");
         return Err(BusClientError::DecodingError);
     }
 
@@ -155,8 +212,8 @@ pub(crate) async fn read_frame<R: AsyncRead + Unpin>(
 #[cfg(test)]
 mod tests {
     use super::{
-        BUS_CHUNK_SIZE, MAX_FRAME_BYTES, decode_reply_cbor, decode_session_cbor, encode_reply_cbor,
-        encode_session_cbor, read_frame, write_frame,
+        BUS_CHUNK_SIZE, MAX_FRAME_BYTES, WireRequest, decode_reply_cbor, decode_session_lenient,
+        encode_reply_cbor, encode_session_cbor, read_frame, write_frame,
     };
     use crate::{BusReply, BusRequest, BusResponse, BusSession, bus::BusClientError};
     use tokio::io::{AsyncWriteExt, duplex};
@@ -167,8 +224,8 @@ mod tests {
             requests: vec![BusRequest::Ping],
         };
         let bytes = encode_session_cbor(&session).expect("encode_session_cbor");
-        let decoded = decode_session_cbor(&bytes).expect("decode_session_cbor");
-        assert_eq!(decoded.requests, session.requests);
+        let decoded = decode_session_lenient(&bytes).expect("decode_session_lenient");
+        assert_eq!(decoded, vec![WireRequest::Known(BusRequest::Ping)]);
     }
 
     #[test]
@@ -181,6 +238,36 @@ mod tests {
         assert_eq!(decoded.responses, reply.responses);
     }
 
+    #[test]
+    fn lenient_decode_keeps_unknown_requests() {
+        use serde_cbor::Value;
+        use std::collections::BTreeMap;
+
+        let mut future_request = BTreeMap::new();
+        future_request.insert(Value::Text("FromTheFuture".to_string()), Value::Integer(42));
+        let mut session = BTreeMap::new();
+        session.insert(
+            Value::Text("requests".to_string()),
+            Value::Array(vec![
+                Value::Text("Ping".to_string()),
+                Value::Map(future_request),
+                Value::Text("AlsoFromTheFuture".to_string()),
+            ]),
+        );
+        let bytes = serde_cbor::to_vec(&Value::Map(session)).expect("encode");
+
+        assert!(serde_cbor::from_slice::<BusSession>(&bytes).is_err());
+        let requests = decode_session_lenient(&bytes).expect("decode_session_lenient");
+        assert_eq!(
+            requests,
+            vec![
+                WireRequest::Known(BusRequest::Ping),
+                WireRequest::Unknown("FromTheFuture".to_string()),
+                WireRequest::Unknown("AlsoFromTheFuture".to_string()),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn frame_round_trip_small_payload() {
         let (mut client, mut server) = duplex(128 * 1024);
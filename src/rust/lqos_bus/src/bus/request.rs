@@ -3,7 +3,7 @@
 
 use crate::TcHandle;
 use allocative::Allocative;
-use lqos_config::Tunables;
+use lqos_config::{BusAccess, Tunables};
 use serde::{Deserialize, Serialize};
 
 /// Per-interface Bakery qdisc-budget report entry.
@@ -19,6 +19,12 @@ pub struct BakeryCapacityReportInterface {
     pub cake_qdiscs: usize,
     /// Planned `fq_codel` leaf qdiscs for the interface.
     pub fq_codel_qdiscs: usize,
+    /// Planned `fq_pie` leaf qdiscs for the interface.
+    #[serde(default)]
+    pub fq_pie_qdiscs: usize,
+    /// Planned `sfq` leaf qdiscs for the interface.
+    #[serde(default)]
+    pub sfq_qdiscs: usize,
     /// Estimated kernel memory cost for the interface's planned qdiscs.
     pub estimated_memory_bytes: u64,
 }
@@ -512,6 +518,9 @@ pub enum BusRequest {
     /// Retrieve list of protocols with recent flow data
     GetProtocolList,
 
+    /// Retrieve list of classified applications with recent flow data
+    GetApplicationList,
+
     /// Retrieve flow timeline entries for an ASN
     GetAsnFlowTimeline {
         /// ASN number to filter
@@ -530,6 +539,12 @@ pub enum BusRequest {
         protocol: String,
     },
 
+    /// Retrieve flow timeline entries for a classified application
+    GetApplicationFlowTimeline {
+        /// Application name to filter
+        application: String,
+    },
+
     /// Retrieve scheduler details (diagnostics)
     GetSchedulerDetails,
 
@@ -562,6 +577,125 @@ pub enum BusRequest {
 
     /// Retrieve current Insight license summary (licensed + optional max circuits).
     GetInsightLicenseSummary,
+
+    /// Switch the connection to push mode. Must be the only request in its
+    /// session. Returns an `Ack`, after which the server sends a
+    /// `BusUpdate` frame for each update on the listed topics until the
+    /// client disconnects. See `LibreqosBusClient::subscribe`.
+    Subscribe {
+        /// Topics to receive updates for.
+        topics: Vec<crate::BusTopic>,
+    },
+}
+
+impl BusRequest {
+    /// The access a connection needs to make this request. Anything that
+    /// changes shaping, configuration or daemon state, or exposes packet
+    /// contents, needs `BusAccess::Admin`.
+    pub fn required_access(&self) -> BusAccess {
+        match self {
+            BusRequest::MapIpToFlow { .. }
+            | BusRequest::ClearHotCache
+            | BusRequest::DelIpFlow { .. }
+            | BusRequest::ClearIpFlow
+            | BusRequest::ReloadLibreQoS
+            | BusRequest::UpdateLqosDTuning(..)
+            | BusRequest::UpdateLqosdConfig(..)
+            | BusRequest::InvalidateAuthCache
+            | BusRequest::GatherPacketData(..)
+            | BusRequest::GetPacketHeaderDump(..)
+            | BusRequest::GetPcapDump(..)
+            | BusRequest::BlackboardData { .. }
+            | BusRequest::BlackboardBlob { .. }
+            | BusRequest::BlackboardFinish
+            | BusRequest::BakeryStart
+            | BusRequest::BakeryCommit
+            | BusRequest::BakeryMqSetup { .. }
+            | BusRequest::BakeryAddSite { .. }
+            | BusRequest::BakeryAddCircuit { .. }
+            | BusRequest::BakeryReportPreflight { .. }
+            | BusRequest::TreeGuardSetNodeVirtual { .. }
+            | BusRequest::ApiReady
+            | BusRequest::ChatbotReady
+            | BusRequest::SchedulerReady
+            | BusRequest::SchedulerError(..)
+            | BusRequest::SchedulerOutput(..)
+            | BusRequest::LogInfo(..)
+            | BusRequest::BakeryChangeSiteSpeedLive { .. }
+            | BusRequest::SubmitUrgentIssue { .. }
+            | BusRequest::ClearUrgentIssue(..)
+            | BusRequest::ClearAllUrgentIssues => BusAccess::Admin,
+            #[cfg(feature = "equinix_tests")]
+            BusRequest::RequestLqosEquinixTest => BusAccess::Admin,
+            BusRequest::Ping
+            | BusRequest::GetCurrentThroughput
+            | BusRequest::GetTopNDownloaders { .. }
+            | BusRequest::GetTopNUploaders { .. }
+            | BusRequest::GetCircuitHeatmaps
+            | BusRequest::GetSiteHeatmaps
+            | BusRequest::GetAsnHeatmaps
+            | BusRequest::GetGlobalHeatmap
+            | BusRequest::GetExecutiveSummaryHeader
+            | BusRequest::GetWorstRtt { .. }
+            | BusRequest::GetWorstRetransmits { .. }
+            | BusRequest::GetBestRtt { .. }
+            | BusRequest::GetHostCounter
+            | BusRequest::ListIpFlow
+            | BusRequest::XdpPping
+            | BusRequest::RttHistogram
+            | BusRequest::HostCounts
+            | BusRequest::AllUnknownIps
+            | BusRequest::GetRawQueueData(..)
+            | BusRequest::WatchQueue(..)
+            | BusRequest::ValidateShapedDevicesCsv
+            | BusRequest::GetNetworkMap { .. }
+            | BusRequest::GetFullNetworkMap
+            | BusRequest::TopMapQueues(..)
+            | BusRequest::GetNodeNamesFromIds(..)
+            | BusRequest::GetAllCircuits
+            | BusRequest::GetCircuitById { .. }
+            | BusRequest::GetFunnel { .. }
+            | BusRequest::GetLqosStats
+            | BusRequest::DumpActiveFlows
+            | BusRequest::CountActiveFlows
+            | BusRequest::TopFlows { .. }
+            | BusRequest::FlowsByIp(..)
+            | BusRequest::CurrentEndpointsByCountry
+            | BusRequest::CurrentEndpointLatLon
+            | BusRequest::FlowDuration
+            | BusRequest::EtherProtocolSummary
+            | BusRequest::IpProtocolSummary
+            | BusRequest::GetStormguardStats
+            | BusRequest::GetStormguardDebug
+            | BusRequest::GetBakeryStats
+            | BusRequest::TreeGuardGetNodeVirtualStatus { .. }
+            | BusRequest::TreeGuardGetNodeVirtualBranchState { .. }
+            | BusRequest::CheckSchedulerStatus
+            | BusRequest::GetUrgentIssues
+            | BusRequest::GetDeviceCounts
+            | BusRequest::GetCircuitCount
+            | BusRequest::GetFlowMap
+            | BusRequest::GetAsnList
+            | BusRequest::GetCountryList
+            | BusRequest::GetProtocolList
+            | BusRequest::GetApplicationList
+            | BusRequest::GetAsnFlowTimeline { .. }
+            | BusRequest::GetCountryFlowTimeline { .. }
+            | BusRequest::GetProtocolFlowTimeline { .. }
+            | BusRequest::GetApplicationFlowTimeline { .. }
+            | BusRequest::GetSchedulerDetails
+            | BusRequest::GetQueueStatsTotal
+            | BusRequest::GetCircuitCapacity
+            | BusRequest::GetTreeCapacity
+            | BusRequest::GetRetransmitSummary
+            | BusRequest::GetTreeSummaryL2
+            | BusRequest::Search { .. }
+            | BusRequest::GetGlobalWarnings
+            | BusRequest::CheckInsight
+            | BusRequest::GetInsightLicenseSummary
+            | BusRequest::Subscribe { .. } => BusAccess::ReadOnly,
+        }
+    }
 }
 
 /// Defines the parts of the blackboard
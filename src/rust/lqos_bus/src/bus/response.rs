@@ -297,6 +297,19 @@ pub struct ProtocolListEntry {
     pub protocol: String,
 }
 
+/// Application list entry with recent flow counts, traffic and RTT
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Allocative)]
+pub struct ApplicationListEntry {
+    /// Flow count for this application
+    pub count: usize,
+    /// Application name
+    pub application: String,
+    /// Total bytes sent
+    pub bytes: DownUpOrder<u64>,
+    /// Median RTT in nanoseconds (down/up)
+    pub median_rtt_nanos: DownUpOrder<u64>,
+}
+
 /// Flow timeline entry for flow explorer
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Allocative)]
 pub struct FlowTimelineEntry {
@@ -320,6 +333,9 @@ pub struct FlowTimelineEntry {
     pub total_bytes: DownUpOrder<u64>,
     /// Protocol name
     pub protocol: String,
+    /// Classified application name, empty if unclassified
+    #[serde(default)]
+    pub application: String,
     /// Circuit ID
     pub circuit_id: String,
     /// Circuit name
@@ -448,6 +464,13 @@ pub enum BusResponse {
     /// and try later.
     NotReadyYet,
 
+    /// The server doesn't recognize the request, usually because the
+    /// client is newer than `lqosd`. Carries the request's variant name.
+    Unsupported {
+        /// The unrecognized request.
+        request: String,
+    },
+
     /// Current throughput for the overall system.
     CurrentThroughput {
         /// In bps
@@ -648,6 +671,9 @@ pub enum BusResponse {
     /// Protocol list (recent flows)
     ProtocolList(Vec<ProtocolListEntry>),
 
+    /// Application list (recent flows)
+    ApplicationList(Vec<ApplicationListEntry>),
+
     /// ASN flow timeline
     AsnFlowTimeline(Vec<FlowTimelineEntry>),
 
@@ -657,6 +683,9 @@ pub enum BusResponse {
     /// Protocol flow timeline
     ProtocolFlowTimeline(Vec<FlowTimelineEntry>),
 
+    /// Application flow timeline
+    ApplicationFlowTimeline(Vec<FlowTimelineEntry>),
+
     /// Scheduler details
     SchedulerDetails(SchedulerDetails),
 
@@ -0,0 +1,506 @@
+// SPDX-FileCopyrightText: 2025 LibreQoE support@libreqos.io
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-LibreQoS-Exception
+
+//! Push updates for clients that subscribe to bus topics.
+//!
+//! Publishers call `publish_bus_update` (cheap when nobody is listening).
+//! Every subscribed connection has its own receiver on a bounded broadcast
+//! channel, so a slow consumer never holds up the publisher or other
+//! clients: it skips the updates it missed and is told how many with
+//! `BusUpdate::Lagged`. A consumer that stops reading altogether is
+//! disconnected once a write has been stalled for `SUBSCRIBER_WRITE_TIMEOUT`.
+//! Code running inside the publishing process can subscribe with
+//! `subscribe_bus_updates` instead.
+
+use super::BusClientError;
+use super::protocol::{read_frame, write_frame};
+use crate::UrgentIssue;
+use allocative::Allocative;
+use lqos_utils::units::DownUpOrder;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+use tokio::sync::broadcast;
+use tracing::{debug, error, warn};
+
+/// Frame ID used for pushed updates, so they can't be mistaken for replies.
+pub(crate) const SUBSCRIPTION_FRAME_ID: u64 = u64::MAX;
+/// Updates buffered per subscriber before it starts missing them.
+const SUBSCRIPTION_BUFFER: usize = 1024;
+/// A subscriber whose socket stays unwritable this long is dropped.
+const SUBSCRIBER_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+static UPDATES: LazyLock<broadcast::Sender<Arc<BusUpdate>>> =
+    LazyLock::new(|| broadcast::channel(SUBSCRIPTION_BUFFER).0);
+static SUBSCRIBERS: [AtomicUsize; BusTopic::ALL.len()] =
+    [const { AtomicUsize::new(0) }; BusTopic::ALL.len()];
+
+/// Topics a bus client can subscribe to with `BusRequest::Subscribe`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Allocative)]
+pub enum BusTopic {
+    /// Whole-network throughput, once per second.
+    Throughput,
+    /// Urgent issues as they are raised.
+    UrgentIssues,
+    /// Bakery activity log entries.
+    Bakery,
+    /// TreeGuard decisions and the actions taken on them.
+    TreeGuard,
+    /// Per-circuit traffic, once per second.
+    CircuitActivity,
+    /// Changes made by StormGuard.
+    StormGuard,
+    /// Circuits going quiet and coming back.
+    CircuitStatus,
+}
+
+impl BusTopic {
+    /// Every topic.
+    pub const ALL: [BusTopic; 7] = [
+        BusTopic::Throughput,
+        BusTopic::UrgentIssues,
+        BusTopic::Bakery,
+        BusTopic::TreeGuard,
+        BusTopic::CircuitActivity,
+        BusTopic::StormGuard,
+        BusTopic::CircuitStatus,
+    ];
+
+    fn index(self) -> usize {
+        match self {
+            BusTopic::Throughput => 0,
+            BusTopic::UrgentIssues => 1,
+            BusTopic::Bakery => 2,
+            BusTopic::TreeGuard => 3,
+            BusTopic::CircuitActivity => 4,
+            BusTopic::StormGuard => 5,
+            BusTopic::CircuitStatus => 6,
+        }
+    }
+}
+
+/// An entry from the Bakery activity log.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Allocative)]
+pub struct BakeryEvent {
+    /// Unix timestamp in seconds.
+    pub ts: u64,
+    /// Stable short event code.
+    pub event: String,
+    /// `info`, `warning`, or `error`.
+    pub status: String,
+    /// Bakery site hash associated with the event, if any.
+    pub site_hash: Option<i64>,
+    /// Site name associated with the event, if any.
+    pub site_name: Option<String>,
+    /// Human-readable summary.
+    pub summary: String,
+}
+
+/// A TreeGuard decision, whether or not it was carried out.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Allocative)]
+pub struct TreeGuardDecision {
+    /// When the decision was made (RFC 3339).
+    pub time: String,
+    /// `node` or `circuit`.
+    pub entity_type: String,
+    /// Node name or circuit ID.
+    pub entity_id: String,
+    /// What TreeGuard did (or skipped).
+    pub action: String,
+    /// True if the change was written to the overrides file.
+    pub persisted: bool,
+    /// Why.
+    pub reason: String,
+}
+
+/// One circuit's traffic over the last second.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Allocative)]
+pub struct CircuitActivityEntry {
+    /// Circuit hash, as used by the shaper.
+    pub circuit_hash: i64,
+    /// Circuit ID from `ShapedDevices.csv`, if known.
+    pub circuit_id: Option<String>,
+    /// Throughput in bits per second.
+    pub bits_per_second: DownUpOrder<u64>,
+}
+
+/// A change StormGuard made (or, in dry-run mode, would have made) to a
+/// site's rate or a circuit's fallback queue.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Allocative)]
+pub struct StormGuardAction {
+    /// Unix timestamp in seconds.
+    pub ts: u64,
+    /// Site name.
+    pub site: String,
+    /// Site download rate after the change, in Mbps.
+    pub download_mbps: u64,
+    /// Site upload rate after the change, in Mbps.
+    pub upload_mbps: u64,
+    /// True if StormGuard is in dry-run mode and nothing was changed.
+    pub dry_run: bool,
+    /// What StormGuard saw and did.
+    pub summary: String,
+}
+
+/// A circuit that went quiet or came back.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Allocative)]
+pub struct CircuitStatusChange {
+    /// Circuit ID from `ShapedDevices.csv`.
+    pub circuit_id: String,
+    /// Circuit name from `ShapedDevices.csv`.
+    pub circuit_name: String,
+    /// Parent node from `ShapedDevices.csv`.
+    pub parent_node: String,
+    /// False if the circuit has stopped passing traffic.
+    pub online: bool,
+    /// Seconds since the circuit last passed traffic.
+    pub idle_seconds: u64,
+}
+
+/// An update pushed to subscribed clients.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Allocative)]
+pub enum BusUpdate {
+    /// Whole-network throughput for the last second.
+    Throughput {
+        /// In bps
+        bits_per_second: DownUpOrder<u64>,
+        /// In pps
+        packets_per_second: DownUpOrder<u64>,
+        /// PPS TCP only
+        tcp_packets_per_second: DownUpOrder<u64>,
+        /// PPS UDP only
+        udp_packets_per_second: DownUpOrder<u64>,
+        /// PPS ICMP only
+        icmp_packets_per_second: DownUpOrder<u64>,
+        /// How much of the traffic has been subject to the shaper?
+        shaped_bits_per_second: DownUpOrder<u64>,
+    },
+    /// A newly raised urgent issue.
+    UrgentIssue(UrgentIssue),
+    /// A new Bakery activity log entry.
+    BakeryEvent(BakeryEvent),
+    /// A new TreeGuard decision.
+    TreeGuardDecision(TreeGuardDecision),
+    /// Every circuit that passed traffic in the last second.
+    CircuitActivity(Vec<CircuitActivityEntry>),
+    /// A new StormGuard change.
+    StormGuardAction(StormGuardAction),
+    /// Circuits whose status changed since the last check.
+    CircuitStatus(Vec<CircuitStatusChange>),
+    /// The subscriber fell behind and this many updates were skipped.
+    Lagged {
+        /// Number of updates skipped.
+        missed: u64,
+    },
+}
+
+impl BusUpdate {
+    /// The topic this update belongs to. `Lagged` belongs to every topic.
+    pub fn topic(&self) -> Option<BusTopic> {
+        match self {
+            BusUpdate::Throughput { .. } => Some(BusTopic::Throughput),
+            BusUpdate::UrgentIssue(_) => Some(BusTopic::UrgentIssues),
+            BusUpdate::BakeryEvent(_) => Some(BusTopic::Bakery),
+            BusUpdate::TreeGuardDecision(_) => Some(BusTopic::TreeGuard),
+            BusUpdate::CircuitActivity(_) => Some(BusTopic::CircuitActivity),
+            BusUpdate::StormGuardAction(_) => Some(BusTopic::StormGuard),
+            BusUpdate::CircuitStatus(_) => Some(BusTopic::CircuitStatus),
+            BusUpdate::Lagged { .. } => None,
+        }
+    }
+}
+
+/// Is any client subscribed to `topic`? Lets publishers skip building
+/// updates nobody will receive.
+pub fn has_bus_subscribers(topic: BusTopic) -> bool {
+    SUBSCRIBERS[topic.index()].load(Ordering::Relaxed) > 0
+}
+
+/// Sends an update to every client subscribed to its topic. Never blocks.
+pub fn publish_bus_update(update: BusUpdate) {
+    let Some(topic) = update.topic() else {
+        return;
+    };
+    if has_bus_subscribers(topic) {
+        // Fails only if every receiver has just gone away.
+        let _ = UPDATES.send(Arc::new(update));
+    }
+}
+
+/// Counts a connection as subscribed to its topics while it lives.
+struct SubscriberGuard {
+    topics: Vec<BusTopic>,
+}
+
+impl SubscriberGuard {
+    fn new(topics: &[BusTopic]) -> Self {
+        let mut unique = topics.to_vec();
+        unique.sort_by_key(|topic| topic.index());
+        unique.dedup();
+        for topic in &unique {
+            SUBSCRIBERS[topic.index()].fetch_add(1, Ordering::Relaxed);
+        }
+        Self { topics: unique }
+    }
+}
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        for topic in &self.topics {
+            SUBSCRIBERS[topic.index()].fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Receives updates inside the publishing process, for forwarding them
+/// somewhere other than a bus connection. Counts as a subscriber to its
+/// topics for as long as it lives.
+pub struct LocalBusSubscription {
+    guard: SubscriberGuard,
+    receiver: broadcast::Receiver<Arc<BusUpdate>>,
+}
+
+impl LocalBusSubscription {
+    /// Waits for the next update on a subscribed topic. Returns `None` once
+    /// nothing can be published any more.
+    pub async fn recv(&mut self) -> Option<Arc<BusUpdate>> {
+        loop {
+            let update = self.receiver.recv().await;
+            if let Some(update) = self.accept(update)? {
+                return Some(update);
+            }
+        }
+    }
+
+    /// Like `recv`, for use outside an async runtime.
+    pub fn blocking_recv(&mut self) -> Option<Arc<BusUpdate>> {
+        loop {
+            let update = self.receiver.blocking_recv();
+            if let Some(update) = self.accept(update)? {
+                return Some(update);
+            }
+        }
+    }
+
+    /// `None` when the channel is closed, `Some(None)` for an update on a
+    /// topic this subscription didn't ask for.
+    fn accept(
+        &self,
+        update: Result<Arc<BusUpdate>, broadcast::error::RecvError>,
+    ) -> Option<Option<Arc<BusUpdate>>> {
+        match update {
+            Ok(update) => Some(
+                update
+                    .topic()
+                    .is_none_or(|topic| self.guard.topics.contains(&topic))
+                    .then_some(update),
+            ),
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                Some(Some(Arc::new(BusUpdate::Lagged { missed })))
+            }
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    }
+}
+
+/// Subscribes to `topics` from inside the process that publishes them.
+pub fn subscribe_bus_updates(topics: &[BusTopic]) -> LocalBusSubscription {
+    LocalBusSubscription {
+        guard: SubscriberGuard::new(topics),
+        receiver: UPDATES.subscribe(),
+    }
+}
+
+/// Pushes updates for `topics` to a subscribed client until it hangs up,
+/// falls too far behind to write to, or the publisher goes away.
+pub(crate) async fn stream_updates<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    topics: &[BusTopic],
+) {
+    let _guard = SubscriberGuard::new(topics);
+    let receiver = UPDATES.subscribe();
+    forward_updates(socket, topics, receiver).await;
+}
+
+async fn forward_updates<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    topics: &[BusTopic],
+    mut receiver: broadcast::Receiver<Arc<BusUpdate>>,
+) {
+    let (mut reader, mut writer) = tokio::io::split(socket);
+    let mut hangup = [0u8; 1];
+    loop {
+        let update = tokio::select! {
+            // Subscribers send nothing; any read completing means the
+            // client closed its end (or broke the protocol).
+            _ = reader.read(&mut hangup) => {
+                debug!("Bus subscriber disconnected");
+                return;
+            }
+            update = receiver.recv() => update,
+        };
+        let update = match update {
+            Ok(update) => update,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Bus subscriber is too slow, skipped {missed} updates");
+                Arc::new(BusUpdate::Lagged { missed })
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if update.topic().is_some_and(|topic| !topics.contains(&topic)) {
+            continue;
+        }
+        let Ok(bytes) = encode_update_cbor(&update) else {
+            continue;
+        };
+        let write = write_frame(&mut writer, SUBSCRIPTION_FRAME_ID, &bytes);
+        match tokio::time::timeout(SUBSCRIBER_WRITE_TIMEOUT, write).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                debug!("Unable to write to bus subscriber: {e:?}");
+                return;
+            }
+            Err(_) => {
+                warn!("Dropping bus subscriber that stopped reading updates");
+                return;
+            }
+        }
+    }
+}
+
+fn encode_update_cbor(update: &BusUpdate) -> Result<Vec<u8>, BusClientError> {
+    serde_cbor::to_vec(update).map_err(|e| {
+        error!("Unable to serialize bus update to CBOR: {:?}", e);
+        BusClientError::EncodingError
+    })
+}
+
+fn decode_update_cbor(bytes: &[u8]) -> Result<BusUpdate, BusClientError> {
+    serde_cbor::from_slice(bytes).map_err(|e| {
+        error!("Unable to deserialize bus update from CBOR: {:?}", e);
+        BusClientError::DecodingError
+    })
+}
+
+/// Reads the next pushed update from a subscribed connection.
+pub(crate) async fn read_update<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> Result<BusUpdate, BusClientError> {
+    let (frame_id, bytes) = read_frame(reader).await?;
+    if frame_id != SUBSCRIPTION_FRAME_ID {
+        error!("Received frame ID {frame_id} on a subscription.");
+        return Err(BusClientError::DecodingError);
+    }
+    decode_update_cbor(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncWriteExt, duplex};
+
+    fn bakery_event(summary: &str) -> BusUpdate {
+        BusUpdate::BakeryEvent(BakeryEvent {
+            ts: 1,
+            event: "full_reload_trigger".to_string(),
+            status: "warning".to_string(),
+            site_hash: None,
+            site_name: None,
+            summary: summary.to_string(),
+        })
+    }
+
+    fn throughput() -> BusUpdate {
+        BusUpdate::Throughput {
+            bits_per_second: DownUpOrder::new(8_000, 800),
+            packets_per_second: DownUpOrder::new(10, 1),
+            tcp_packets_per_second: DownUpOrder::new(10, 1),
+            udp_packets_per_second: DownUpOrder::zeroed(),
+            icmp_packets_per_second: DownUpOrder::zeroed(),
+            shaped_bits_per_second: DownUpOrder::new(8_000, 800),
+        }
+    }
+
+    #[test]
+    fn subscriber_counts_follow_connections() {
+        assert!(!has_bus_subscribers(BusTopic::CircuitActivity));
+        let guard = SubscriberGuard::new(&[BusTopic::CircuitActivity, BusTopic::CircuitActivity]);
+        assert!(has_bus_subscribers(BusTopic::CircuitActivity));
+        drop(guard);
+        assert!(!has_bus_subscribers(BusTopic::CircuitActivity));
+    }
+
+    #[tokio::test]
+    async fn local_subscriptions_receive_their_topics() {
+        let mut subscription = subscribe_bus_updates(&[BusTopic::StormGuard]);
+        assert!(has_bus_subscribers(BusTopic::StormGuard));
+        let action = BusUpdate::StormGuardAction(StormGuardAction {
+            ts: 1,
+            site: "Tower".to_string(),
+            download_mbps: 500,
+            upload_mbps: 100,
+            dry_run: false,
+            summary: "delay rising".to_string(),
+        });
+        // Published without a subscriber, so never sent.
+        publish_bus_update(BusUpdate::CircuitStatus(Vec::new()));
+        publish_bus_update(action.clone());
+        assert_eq!(subscription.recv().await.as_deref(), Some(&action));
+        drop(subscription);
+        assert!(!has_bus_subscribers(BusTopic::StormGuard));
+    }
+
+    #[tokio::test]
+    async fn only_subscribed_topics_are_forwarded() {
+        let (sender, receiver) = broadcast::channel(16);
+        let (mut client, mut server) = duplex(64 * 1024);
+        let forward = tokio::spawn(async move {
+            forward_updates(&mut server, &[BusTopic::Bakery], receiver).await;
+        });
+        sender.send(Arc::new(throughput())).expect("send");
+        sender.send(Arc::new(bakery_event("one"))).expect("send");
+
+        let update = read_update(&mut client).await.expect("update");
+        assert_eq!(update, bakery_event("one"));
+
+        drop(client);
+        forward
+            .await
+            .expect("forwarder stops when the client hangs up");
+    }
+
+    #[tokio::test]
+    async fn slow_subscribers_are_told_what_they_missed() {
+        let (sender, receiver) = broadcast::channel(4);
+        for i in 0..10 {
+            sender
+                .send(Arc::new(bakery_event(&i.to_string())))
+                .expect("send");
+        }
+        let (mut client, mut server) = duplex(64 * 1024);
+        tokio::spawn(async move {
+            forward_updates(&mut server, &[BusTopic::Bakery], receiver).await;
+        });
+
+        let first = read_update(&mut client).await.expect("update");
+        assert_eq!(first, BusUpdate::Lagged { missed: 6 });
+        let next = read_update(&mut client).await.expect("update");
+        assert_eq!(next, bakery_event("6"));
+        drop(sender);
+    }
+
+    #[tokio::test]
+    async fn replies_are_not_accepted_as_updates() {
+        let (mut client, mut server) = duplex(1024);
+        write_frame(&mut server, 3, b"not an update")
+            .await
+            .expect("write");
+        server.flush().await.expect("flush");
+        assert!(matches!(
+            read_update(&mut client).await,
+            Err(BusClientError::DecodingError)
+        ));
+    }
+}
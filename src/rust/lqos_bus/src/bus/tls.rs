@@ -0,0 +1,332 @@
+// SPDX-FileCopyrightText: 2025 LibreQoE support@libreqos.io
+// SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-LibreQoS-Exception
+
+//! The bus protocol over TCP, wrapped in TLS, for managing `lqosd` from
+//! another host. Clients authenticate with a token or a client certificate
+//! listed in `[remote_bus]`, and each credential is either read-only or
+//! admin.
+
+use crate::{BusRequest, BusResponse};
+use lqos_config::{BusAccess, RemoteBusConfig};
+use lqos_utils::rustls::ensure_rustls_crypto_provider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+
+use super::connection::serve_connection;
+use super::handshake::HANDSHAKE_TIMEOUT;
+
+/// Most remote clients served at once. Further connections are closed
+/// until one of these hangs up.
+const MAX_REMOTE_CONNECTIONS: usize = 64;
+
+/// Reads every certificate in a PEM file.
+pub(crate) fn load_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certificates = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Unable to read certificates from {path}: {e}"))?;
+    if certificates.is_empty() {
+        return Err(format!("No certificates found in {path}"));
+    }
+    Ok(certificates)
+}
+
+/// Reads the private key from a PEM file.
+pub(crate) fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>, String> {
+    PrivateKeyDer::from_pem_file(path)
+        .map_err(|e| format!("Unable to read private key from {path}: {e}"))
+}
+
+/// Builds a trust store from a PEM bundle.
+pub(crate) fn load_roots(path: &str) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    for certificate in load_certificates(path)? {
+        roots
+            .add(certificate)
+            .map_err(|e| format!("Invalid CA certificate in {path}: {e}"))?;
+    }
+    Ok(roots)
+}
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+/// Credentials from `[remote_bus]`, hashed for lookup.
+struct Credentials {
+    tokens: Vec<([u8; 32], String, BusAccess)>,
+    certificates: Vec<(Vec<u8>, String, BusAccess)>,
+}
+
+impl Credentials {
+    fn from_config(config: &RemoteBusConfig) -> Self {
+        let mut tokens = Vec::new();
+        let mut certificates = Vec::new();
+        for credential in &config.credentials {
+            let name = credential.name.clone();
+            if let Some(token) = &credential.token {
+                tokens.push((sha256(token.as_bytes()), name, credential.access));
+            } else if let Some(fingerprint) = credential.fingerprint() {
+                certificates.push((fingerprint, name, credential.access));
+            }
+        }
+        Self {
+            tokens,
+            certificates,
+        }
+    }
+
+    /// A client certificate takes precedence over a token.
+    fn authorize(&self, certificate: Option<&[u8]>, token: Option<&str>) -> Option<BusAccess> {
+        if let Some(certificate) = certificate {
+            let fingerprint = sha256(certificate);
+            if let Some((_, name, access)) = self
+                .certificates
+                .iter()
+                .find(|(known, _, _)| known[..] == fingerprint[..])
+            {
+                debug!("Remote bus client authenticated by certificate as {name}");
+                return Some(*access);
+            }
+        }
+        // Comparing digests keeps the comparison time independent of how
+        // much of the token matched.
+        let digest = sha256(token?.as_bytes());
+        let (_, name, access) = self.tokens.iter().find(|(known, _, _)| *known == digest)?;
+        debug!("Remote bus client authenticated by token as {name}");
+        Some(*access)
+    }
+}
+
+/// Listens for bus clients over TLS.
+pub struct TlsBusServer {
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    credentials: Arc<Credentials>,
+    connections: Arc<Semaphore>,
+}
+
+impl TlsBusServer {
+    /// Loads the certificates named in `config` and binds its listen address.
+    pub async fn bind(config: &RemoteBusConfig) -> Result<Self, TlsBusServerError> {
+        ensure_rustls_crypto_provider().map_err(|e| TlsBusServerError::Tls(e.to_string()))?;
+        let certificates =
+            load_certificates(&config.cert_file).map_err(TlsBusServerError::Certificate)?;
+        let key = load_private_key(&config.key_file).map_err(TlsBusServerError::Certificate)?;
+        let builder = ServerConfig::builder();
+        let builder = match &config.client_ca_file {
+            Some(ca_file) => {
+                let roots = load_roots(ca_file).map_err(TlsBusServerError::Certificate)?;
+                let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                    .allow_unauthenticated()
+                    .build()
+                    .map_err(|e| TlsBusServerError::Tls(e.to_string()))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let tls_config = builder
+            .with_single_cert(certificates, key)
+            .map_err(|e| TlsBusServerError::Tls(e.to_string()))?;
+        let listener = TcpListener::bind(&config.listen)
+            .await
+            .map_err(|e| TlsBusServerError::Bind(format!("{}: {e}", config.listen)))?;
+        Ok(Self {
+            listener,
+            acceptor: TlsAcceptor::from(Arc::new(tls_config)),
+            credentials: Arc::new(Credentials::from_config(config)),
+            connections: Arc::new(Semaphore::new(MAX_REMOTE_CONNECTIONS)),
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> Result<SocketAddr, TlsBusServerError> {
+        self.listener
+            .local_addr()
+            .map_err(|e| TlsBusServerError::Bind(e.to_string()))
+    }
+
+    /// Accepts clients forever, answering their requests with
+    /// `handle_bus_requests` as far as their credentials allow.
+    pub async fn listen(
+        self,
+        handle_bus_requests: fn(&[BusRequest], &mut Vec<BusResponse>),
+    ) -> Result<(), TlsBusServerError> {
+        info!("Remote bus listening on: {:?}", self.listener.local_addr());
+        loop {
+            let (stream, peer) = self
+                .listener
+                .accept()
+                .await
+                .map_err(|e| TlsBusServerError::Listen(e.to_string()))?;
+            let Ok(permit) = self.connections.clone().try_acquire_owned() else {
+                warn!(
+                    "Refused remote bus client {peer}: already serving {MAX_REMOTE_CONNECTIONS} clients"
+                );
+                continue;
+            };
+            let acceptor = self.acceptor.clone();
+            let credentials = self.credentials.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                let stream = match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        warn!("TLS handshake with remote bus client {peer} failed: {e}");
+                        return;
+                    }
+                    Err(_) => {
+                        warn!("TLS handshake with remote bus client {peer} timed out");
+                        return;
+                    }
+                };
+                let certificate = stream
+                    .get_ref()
+                    .1
+                    .peer_certificates()
+                    .and_then(|chain| chain.first())
+                    .map(|certificate| certificate.to_vec());
+                serve_connection(stream, handle_bus_requests, move |token| {
+                    let access = credentials.authorize(certificate.as_deref(), token);
+                    if access.is_none() {
+                        warn!("Remote bus client {peer} failed to authenticate");
+                    }
+                    access
+                })
+                .await;
+            });
+        }
+    }
+}
+
+/// Errors starting or running the TLS bus listener.
+#[derive(Error, Debug)]
+pub enum TlsBusServerError {
+    /// A certificate or key couldn't be loaded.
+    #[error("{0}")]
+    Certificate(String),
+    /// The TLS configuration was rejected.
+    #[error("TLS configuration error: {0}")]
+    Tls(String),
+    /// The listen address couldn't be bound.
+    #[error("Unable to bind {0}")]
+    Bind(String),
+    /// Accepting connections failed.
+    #[error("Unable to accept connections: {0}")]
+    Listen(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BusClientError, LibreqosBusClient, RemoteBusTarget};
+    use lqos_config::RemoteBusCredential;
+
+    const TOKEN: &str = "read-only-test-token";
+
+    fn testdata(file: &str) -> String {
+        format!("{}/src/bus/testdata/{file}", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    fn ack_all(requests: &[BusRequest], responses: &mut Vec<BusResponse>) {
+        responses.extend(requests.iter().map(|_| BusResponse::Ack));
+    }
+
+    async fn start_server() -> SocketAddr {
+        let client_certificate = load_certificates(&testdata("client.pem")).expect("client cert");
+        let fingerprint: String = sha256(&client_certificate[0])
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let config = RemoteBusConfig {
+            enabled: true,
+            listen: "127.0.0.1:0".to_string(),
+            cert_file: testdata("server.pem"),
+            key_file: testdata("server.key"),
+            client_ca_file: Some(testdata("ca.pem")),
+            credentials: vec![
+                RemoteBusCredential {
+                    name: "monitoring".to_string(),
+                    access: BusAccess::ReadOnly,
+                    token: Some(TOKEN.to_string()),
+                    certificate_sha256: None,
+                },
+                RemoteBusCredential {
+                    name: "automation".to_string(),
+                    access: BusAccess::Admin,
+                    token: None,
+                    certificate_sha256: Some(fingerprint),
+                },
+            ],
+        };
+        config.validate().expect("valid config");
+        let server = TlsBusServer::bind(&config).await.expect("bind");
+        let address = server.local_addr().expect("address");
+        tokio::spawn(server.listen(ack_all));
+        address
+    }
+
+    fn target(address: SocketAddr) -> RemoteBusTarget {
+        RemoteBusTarget {
+            address: address.to_string(),
+            server_name: "localhost".to_string(),
+            ca_file: testdata("ca.pem"),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn credentials_decide_what_remote_clients_may_do() {
+        let address = start_server().await;
+
+        let mut read_only = LibreqosBusClient::connect_remote(&RemoteBusTarget {
+            token: Some(TOKEN.to_string()),
+            ..target(address)
+        })
+        .await
+        .expect("token client connects");
+        let responses = read_only
+            .request(vec![BusRequest::Ping, BusRequest::ReloadLibreQoS])
+            .await
+            .expect("request");
+        assert_eq!(responses[0], BusResponse::Ack);
+        assert!(matches!(&responses[1], BusResponse::Fail(message) if message.contains("admin")));
+
+        let mut admin = LibreqosBusClient::connect_remote(&RemoteBusTarget {
+            client_cert_file: Some(testdata("client.pem")),
+            client_key_file: Some(testdata("client.key")),
+            ..target(address)
+        })
+        .await
+        .expect("certificate client connects");
+        let responses = admin
+            .request(vec![BusRequest::ReloadLibreQoS])
+            .await
+            .expect("request");
+        assert_eq!(responses, vec![BusResponse::Ack]);
+    }
+
+    #[tokio::test]
+    async fn unknown_credentials_are_refused() {
+        let address = start_server().await;
+        let result = LibreqosBusClient::connect_remote(&RemoteBusTarget {
+            token: Some("not-a-known-token".to_string()),
+            ..target(address)
+        })
+        .await;
+        assert!(matches!(result, Err(BusClientError::Unauthorized)));
+
+        let anonymous = LibreqosBusClient::connect_remote(&target(address)).await;
+        assert!(matches!(anonymous, Err(BusClientError::Unauthorized)));
+    }
+}
@@ -1,20 +1,15 @@
 // SPDX-FileCopyrightText: 2025 LibreQoE support@libreqos.io
 // SPDX-License-Identifier: AGPL-3.0-or-later WITH LicenseRef-LibreQoS-Exception
 
-use crate::{
-    BUS_SOCKET_PATH, BusReply, BusRequest, BusResponse,
-    bus::client::{MAGIC_NUMBER, MAGIC_RESPONSE},
-};
+use crate::{BUS_SOCKET_PATH, BusReply, BusRequest, BusResponse};
+use lqos_config::BusAccess;
 use std::{ffi::CString, fs::remove_file};
 use thiserror::Error;
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::UnixListener,
-};
-use tracing::{debug, error, info, warn};
+use tokio::net::UnixListener;
+use tracing::{error, info, warn};
 
 use super::BUS_SOCKET_DIRECTORY;
-use super::protocol::{decode_session_cbor, encode_reply_cbor, read_frame, write_frame};
+use super::connection::serve_connection;
 
 fn dropped_reply_response_count(reply: &BusReply) -> usize {
     reply.responses.len()
@@ -125,86 +120,18 @@ impl UnixSocketServer {
               },
               ret = listener.accept() => {
                 // We received a UNIX socket message
-                let Ok((mut socket, _)) = ret else {
+                let Ok((socket, _)) = ret else {
                     if ret.is_err() {
                       error!("Unable to listen for requests on bound {BUS_SOCKET_PATH}");
                       error!("{:?}", ret);
                     }
                     return Err(UnixSocketServerError::ListenFail);
                 };
-                tokio::spawn(async move {
-                    // Listen for the magic number
-                    let mut magic_buf = [0; 4];
-                    let bytes_read = socket.read_exact(&mut magic_buf).await;
-                    if bytes_read.is_err() {
-                        debug!("Unable to read magic number from client socket. Server remains alive.");
-                        debug!("This is probably harmless.");
-                        debug!("{:?}", bytes_read);
-                        return;
-                    }
-                    if magic_buf != MAGIC_NUMBER {
-                        warn!("Received invalid magic number from client socket.");
-                        return;
-                    }
-
-                    // Send the magic number back to the client
-                    if let Err(e) = socket.write_all(&MAGIC_RESPONSE).await {
-                        debug!("Unable to write magic number to client socket. Server remains alive.");
-                        debug!("This is probably harmless.");
-                        debug!("{:?}", e);
-                        return;
-                    }
-
-                    loop {
-                        let (request_id, request_bytes) = match read_frame(&mut socket).await {
-                            Ok(frame) => frame,
-                            Err(e) => {
-                                debug!("Unable to read request frame from client socket.");
-                                debug!("This is probably harmless.");
-                                debug!("{:?}", e);
-                                break;
-                            }
-                        };
-                        if request_bytes.is_empty() {
-                            warn!("Received empty request payload; closing client socket.");
-                            break;
-                        }
-                        debug!(
-                            "Received request ID: {request_id}, Size: {}",
-                            request_bytes.len()
-                        );
-
-                        // Decode the request
-                        let Ok(request) = decode_session_cbor(&request_bytes) else {
-                            warn!("Invalid data on local socket");
-                            break;
-                        };
-                        debug!("Received request: {:?}", request);
-
-                        // Handle the request and build the response
-                        let mut response = BusReply { responses: Vec::with_capacity(8) };
-                        handle_bus_requests(&request.requests, &mut response.responses);
-
-                        // Encode the response
-                        let Ok(encoded_response) = encode_reply_cbor(&response) else {
-                            warn!("Unable to encode response for request ID: {request_id}");
-                            break;
-                        };
-                        debug!("Sending response for request ID: {request_id}");
-
-                        // Send the response back to the client
-                        if let Err(e) =
-                            write_frame(&mut socket, request_id, &encoded_response).await
-                        {
-                            debug!("Unable to write response to client socket. Server remains alive.");
-                            debug!("This is probably harmless.");
-                            debug!("{:?}", e);
-                            break; // Escape out of the thread
-                        }
-                        debug!("Response sent for request ID: {request_id}");
-
-                    } // End of the request handling loop
-                });
+                tokio::spawn(serve_connection(
+                    socket,
+                    handle_bus_requests,
+                    |_| Some(BusAccess::Admin),
+                ));
               },
             );
         }
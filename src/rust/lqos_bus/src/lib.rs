@@ -13,7 +13,21 @@
 //! inside a `BusReply` object, containing one or more `BusResponse`
 //! detail objects. The session then terminates.
 //!
-//! Protocol versioning/negotiation is intentionally skipped.
+//! Alternatively a client may send a single `BusRequest::Subscribe`, after
+//! which `lqosd` pushes a `BusUpdate` frame for every update on the chosen
+//! topics (throughput, urgent issues, Bakery, TreeGuard, circuit activity)
+//! instead of being polled.
+//!
+//! Every connection opens with a handshake in which the client and `lqosd`
+//! agree on a protocol version and exchange capabilities, so mismatched
+//! builds fail with `BusClientError::IncompatibleProtocol` rather than a
+//! decoding error. Requests the server doesn't recognize are answered with
+//! `BusResponse::Unsupported`.
+//!
+//! `lqosd` can also serve the bus over TCP with TLS (`TlsBusServer`), so
+//! other hosts can use it via `LibreqosBusClient::connect_remote`. Remote
+//! clients authenticate with a token or client certificate, and read-only
+//! credentials can't make requests that change anything.
 
 #![deny(clippy::unwrap_used)]
 #![warn(missing_docs)]
@@ -25,18 +39,22 @@ pub use ip_stats::{
 };
 mod tc_handle;
 pub use bus::response::{
-    AsnHeatmapData, AsnListEntry, BakeryStatsSnapshot, CircuitCapacityRow, CircuitCount,
-    CircuitHeatmapData, CountryListEntry, DeviceCounts, ExecutiveSummaryHeader, FlowMapPoint,
-    FlowTimelineEntry, InsightLicenseSummary, NodeCapacity, ProtocolListEntry, QueueStatsTotal,
-    RetransmitSummary, SchedulerDetails, SearchResultEntry, SiteHeatmapData,
+    ApplicationListEntry, AsnHeatmapData, AsnListEntry, BakeryStatsSnapshot, CircuitCapacityRow,
+    CircuitCount, CircuitHeatmapData, CountryListEntry, DeviceCounts, ExecutiveSummaryHeader,
+    FlowMapPoint, FlowTimelineEntry, InsightLicenseSummary, NodeCapacity, ProtocolListEntry,
+    QueueStatsTotal, RetransmitSummary, SchedulerDetails, SearchResultEntry, SiteHeatmapData,
     StormguardDebugDirection, StormguardDebugEntry, TreeGuardRuntimeNodeBranchSnapshot,
     TreeGuardRuntimeNodeOperationSnapshot, UrgentIssue, WarningLevel,
 };
 pub use bus::{
-    BUS_SOCKET_PATH, BakeryCapacityReportInterface, BlackboardSystem, BusClientError, BusReply,
-    BusRequest, BusResponse, BusSession, CakeDiffTinTransit, CakeDiffTransit, CakeTransit,
-    LibreqosBusClient, QueueStoreTransit, TopFlowType, UnixSocketServer, UrgentSeverity,
-    UrgentSource, bus_request,
+    BUS_PROTOCOL_VERSION, BUS_SOCKET_PATH, BakeryCapacityReportInterface, BakeryEvent,
+    BlackboardSystem, BusClientError, BusReply, BusRequest, BusResponse, BusSession,
+    BusSubscription, BusTopic, BusUpdate, CAPABILITY_SUBSCRIBE, CAPABILITY_UNSUPPORTED_RESPONSE,
+    CakeDiffTinTransit, CakeDiffTransit, CakeTransit, CircuitActivityEntry, CircuitStatusChange,
+    LEGACY_BUS_PROTOCOL_VERSION, LibreqosBusClient, LocalBusSubscription, MIN_BUS_PROTOCOL_VERSION,
+    QueueStoreTransit, RemoteBusTarget, StormGuardAction, TlsBusServer, TlsBusServerError,
+    TopFlowType, TreeGuardDecision, UnixSocketServer, UrgentSeverity, UrgentSource, bus_request,
+    has_bus_subscribers, publish_bus_update, subscribe_bus_updates,
 };
 pub use tc_handle::TcHandle;
 
@@ -0,0 +1,569 @@
+//! The `audit_log` module keeps an append-only record of configuration and
+//! shaping changes: who made them, what they did and what changed. Entries are
+//! stored as JSON lines in `<lqos_directory>/audit.jsonl`, so the file can be
+//! exported or processed with ordinary tools.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{File, OpenOptions, rename},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{SyncSender, TrySendError, sync_channel},
+    },
+};
+use thiserror::Error;
+use tracing::warn;
+
+const AUDIT_FILE_NAME: &str = "audit.jsonl";
+const ROTATED_AUDIT_FILE_NAME: &str = "audit.jsonl.1";
+/// The live file is rotated once it grows past this size. One rotated file
+/// is kept.
+const MAX_AUDIT_FILE_BYTES: u64 = 64 * 1024 * 1024;
+/// Diffs larger than this are cut short and flagged as truncated.
+const MAX_CHANGES_PER_ENTRY: usize = 500;
+/// Values under keys containing any of these are never written to the log.
+const SENSITIVE_KEY_PARTS: [&str; 6] = [
+    "password",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "license_key",
+];
+/// Values at these paths are credentials despite their names: Slack and
+/// Teams webhook URLs embed the secret that authorizes posting.
+const SENSITIVE_PATHS: [&str; 1] = ["webhooks.destinations.url"];
+const REDACTED: &str = "[redacted]";
+/// Entries waiting for the background writer. Changes are rare; this only
+/// has to absorb a burst such as a large TreeGuard batch.
+const AUDIT_QUEUE_DEPTH: usize = 1024;
+
+static AUDIT_WRITER: Lazy<Option<AuditWriter>> = Lazy::new(|| {
+    AuditWriter::start(AUDIT_QUEUE_DEPTH, |entry| {
+        if let Err(e) = append_audit_entry(entry) {
+            warn!("Unable to write audit entry '{}': {e}", entry.action);
+        }
+    })
+    .inspect_err(|e| warn!("Unable to start the audit log writer: {e}"))
+    .ok()
+});
+
+/// Who made a change.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditActor {
+    /// A logged-in web UI user.
+    User {
+        /// The username.
+        name: String,
+    },
+    /// An API token.
+    Token {
+        /// The token id.
+        id: String,
+        /// The token's description.
+        name: String,
+    },
+    /// An unauthenticated visitor, when anonymous access is enabled.
+    Anonymous,
+    /// A command-line tool, run by the named local user.
+    Cli {
+        /// The tool name.
+        tool: String,
+        /// The local account that ran it.
+        user: String,
+    },
+    /// An automatic LibreQoS subsystem such as TreeGuard or StormGuard.
+    Subsystem {
+        /// The subsystem name.
+        name: String,
+    },
+}
+
+impl AuditActor {
+    /// Describes a command-line tool run by the current local user.
+    pub fn cli(tool: &str) -> Self {
+        let user = std::env::var("SUDO_USER")
+            .or_else(|_| std::env::var("USER"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        AuditActor::Cli {
+            tool: tool.to_string(),
+            user,
+        }
+    }
+
+    /// Describes an automatic subsystem.
+    pub fn subsystem(name: &str) -> Self {
+        AuditActor::Subsystem {
+            name: name.to_string(),
+        }
+    }
+
+    /// A short human-readable label, e.g. `user:admin`.
+    pub fn label(&self) -> String {
+        match self {
+            AuditActor::User { name } => format!("user:{name}"),
+            AuditActor::Token { id, name } => format!("token:{name} ({id})"),
+            AuditActor::Anonymous => "anonymous".to_string(),
+            AuditActor::Cli { tool, user } => format!("cli:{tool} ({user})"),
+            AuditActor::Subsystem { name } => format!("subsystem:{name}"),
+        }
+    }
+}
+
+/// One changed value. `before` is absent for additions and `after` for
+/// removals.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditChange {
+    /// Dotted path to the value, e.g. `queues.downlink_bandwidth_mbps`.
+    pub path: String,
+    /// The previous value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    /// The new value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+/// A single audit record.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the change was made, in UNIX seconds.
+    pub time: u64,
+    /// Who made the change.
+    pub actor: AuditActor,
+    /// What was done, e.g. `update_config` or `delete_shaped_device`.
+    pub action: String,
+    /// What it was done to, e.g. a device or site identifier.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Free-form context, such as the reason for an automatic action.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// The values that changed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<AuditChange>,
+    /// True when `changes` was cut short.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl AuditEntry {
+    /// Starts an entry stamped with the current time.
+    pub fn new(actor: AuditActor, action: &str) -> Self {
+        Self {
+            time: lqos_utils::unix_time::unix_now().unwrap_or(0),
+            actor,
+            action: action.to_string(),
+            target: None,
+            detail: None,
+            changes: Vec::new(),
+            truncated: false,
+        }
+    }
+
+    /// Sets what the change applied to.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Adds free-form context.
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Records the difference between two states. Either side may be
+    /// `None` for creations and deletions. Sensitive values are redacted.
+    pub fn changes<B: Serialize, A: Serialize>(
+        mut self,
+        before: Option<&B>,
+        after: Option<&A>,
+    ) -> Self {
+        let before = before.and_then(|b| serde_json::to_value(b).ok());
+        let after = after.and_then(|a| serde_json::to_value(a).ok());
+        let mut changes = Vec::new();
+        diff_values("", before.as_ref(), after.as_ref(), &mut changes);
+        if changes.len() > MAX_CHANGES_PER_ENTRY {
+            changes.truncate(MAX_CHANGES_PER_ENTRY);
+            self.truncated = true;
+        }
+        self.changes = changes;
+        self
+    }
+}
+
+/// Filters for [`read_audit_log`]. Empty fields match everything.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    /// Case-insensitive substring of the actor label.
+    #[serde(default)]
+    pub actor: Option<String>,
+    /// Case-insensitive substring of the action.
+    #[serde(default)]
+    pub action: Option<String>,
+    /// Only entries at or after this UNIX time.
+    #[serde(default)]
+    pub since: Option<u64>,
+    /// Entries to skip, newest first.
+    #[serde(default)]
+    pub offset: usize,
+    /// Maximum number of entries to return. Zero means no limit.
+    #[serde(default)]
+    pub limit: usize,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        let contains = |haystack: &str, needle: &Option<String>| {
+            needle
+                .as_deref()
+                .filter(|n| !n.is_empty())
+                .is_none_or(|n| haystack.to_lowercase().contains(&n.to_lowercase()))
+        };
+        self.since.is_none_or(|since| entry.time >= since)
+            && contains(&entry.actor.label(), &self.actor)
+            && contains(&entry.action, &self.action)
+    }
+}
+
+fn is_sensitive(path: &str) -> bool {
+    let path = path.to_lowercase();
+    SENSITIVE_KEY_PARTS.iter().any(|part| path.contains(part))
+        || SENSITIVE_PATHS.contains(&path.as_str())
+}
+
+/// Copies `value`, replacing anything under a sensitive key.
+fn redact(path: &str, value: &Value) -> Value {
+    if is_sensitive(path) {
+        return Value::String(REDACTED.to_string());
+    }
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, v)| (key.clone(), redact(&format!("{path}.{key}"), v)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(|v| redact(path, v)).collect()),
+        _ => value.clone(),
+    }
+}
+
+fn diff_values(
+    path: &str,
+    before: Option<&Value>,
+    after: Option<&Value>,
+    out: &mut Vec<AuditChange>,
+) {
+    if let (Some(Value::Object(b)), Some(Value::Object(a))) = (before, after) {
+        let mut keys: Vec<&String> = b.keys().chain(a.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let child = if path.is_empty() {
+                key.to_string()
+            } else {
+                format!("{path}.{key}")
+            };
+            diff_values(&child, b.get(key), a.get(key), out);
+        }
+        return;
+    }
+    if before == after {
+        return;
+    }
+    out.push(AuditChange {
+        path: path.to_string(),
+        before: before.map(|v| redact(path, v)),
+        after: after.map(|v| redact(path, v)),
+    });
+}
+
+/// Returns the path of the live audit log.
+pub fn audit_log_path() -> Result<PathBuf, AuditLogError> {
+    let config = crate::load_config().map_err(|_| AuditLogError::UnableToLoadEtcLqos)?;
+    Ok(Path::new(&config.lqos_directory).join(AUDIT_FILE_NAME))
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    path.with_file_name(ROTATED_AUDIT_FILE_NAME)
+}
+
+fn append_to(path: &Path, entry: &AuditEntry) -> Result<(), AuditLogError> {
+    if std::fs::metadata(path).is_ok_and(|m| m.len() >= MAX_AUDIT_FILE_BYTES) {
+        rename(path, rotated_path(path)).map_err(AuditLogError::Io)?;
+    }
+    let mut line = serde_json::to_string(entry).map_err(AuditLogError::Serialize)?;
+    line.push('\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(AuditLogError::Io)?;
+    // One write per entry keeps concurrent writers from interleaving lines.
+    file.write_all(line.as_bytes()).map_err(AuditLogError::Io)
+}
+
+/// Appends an entry to the audit log.
+pub fn append_audit_entry(entry: &AuditEntry) -> Result<(), AuditLogError> {
+    append_to(&audit_log_path()?, entry)
+}
+
+/// Queues an entry to be appended by a background thread, so that control
+/// loops and web requests never wait on disk. If the queue is full the entry
+/// is dropped, and the writer records how many were lost.
+pub fn queue_audit_entry(entry: AuditEntry) {
+    if let Some(writer) = AUDIT_WRITER.as_ref() {
+        writer.queue(entry);
+    }
+}
+
+/// A bounded queue feeding a thread that writes entries in order.
+struct AuditWriter {
+    sender: SyncSender<AuditEntry>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl AuditWriter {
+    fn start(depth: usize, write: impl Fn(&AuditEntry) + Send + 'static) -> std::io::Result<Self> {
+        let (sender, receiver) = sync_channel::<AuditEntry>(depth);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let missed = dropped.clone();
+        std::thread::Builder::new()
+            .name("Audit Log".to_string())
+            .spawn(move || {
+                while let Ok(entry) = receiver.recv() {
+                    write(&entry);
+                    let count = missed.swap(0, Ordering::Relaxed);
+                    if count > 0 {
+                        write(&dropped_entries_marker(count));
+                    }
+                }
+            })?;
+        Ok(Self { sender, dropped })
+    }
+
+    fn queue(&self, entry: AuditEntry) {
+        if let Err(TrySendError::Full(entry)) = self.sender.try_send(entry) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            warn!("Audit log queue is full; dropping '{}'", entry.action);
+        }
+    }
+}
+
+/// Marks a gap in the log left by entries dropped from a full queue.
+fn dropped_entries_marker(count: u64) -> AuditEntry {
+    AuditEntry::new(AuditActor::subsystem("audit_log"), "audit_entries_dropped").detail(format!(
+        "{count} audit entries were dropped because the writer fell behind"
+    ))
+}
+
+fn read_lines(path: &Path, mut each: impl FnMut(AuditEntry)) -> Result<(), AuditLogError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(AuditLogError::Io(e)),
+    };
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(AuditLogError::Io)?;
+        // A torn final line from a crash is skipped rather than failing the read
+        if let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) {
+            each(entry);
+        }
+    }
+    Ok(())
+}
+
+fn read_from(path: &Path, query: &AuditQuery) -> Result<Vec<AuditEntry>, AuditLogError> {
+    let mut entries = Vec::new();
+    for file in [rotated_path(path), path.to_path_buf()] {
+        read_lines(&file, |entry| {
+            if query.matches(&entry) {
+                entries.push(entry);
+            }
+        })?;
+    }
+    entries.reverse();
+    let limit = if query.limit == 0 {
+        usize::MAX
+    } else {
+        query.limit
+    };
+    Ok(entries.into_iter().skip(query.offset).take(limit).collect())
+}
+
+/// Reads matching entries, newest first.
+pub fn read_audit_log(query: &AuditQuery) -> Result<Vec<AuditEntry>, AuditLogError> {
+    read_from(&audit_log_path()?, query)
+}
+
+/// Errors that can occur while reading or writing the audit log.
+#[derive(Error, Debug)]
+pub enum AuditLogError {
+    /// Failed to load `/etc/lqos.conf` to locate the log.
+    #[error("Unable to load /etc/lqos.conf")]
+    UnableToLoadEtcLqos,
+    /// Failed to encode an entry.
+    #[error("Unable to serialize audit entry: {0}")]
+    Serialize(serde_json::Error),
+    /// Failed to read or write the log file.
+    #[error("Audit log I/O error: {0}")]
+    Io(std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn diff_reports_nested_changes_and_redacts_secrets() {
+        let before = json!({
+            "queues": {"downlink_bandwidth_mbps": 1000, "uplink_bandwidth_mbps": 1000},
+            "uisp_integration": {"token": "old-secret"},
+            "removed": true,
+        });
+        let after = json!({
+            "queues": {"downlink_bandwidth_mbps": 2000, "uplink_bandwidth_mbps": 1000},
+            "uisp_integration": {"token": "new-secret"},
+            "added": {"name": "x", "password": "hunter2"},
+        });
+        let entry = AuditEntry::new(AuditActor::subsystem("test"), "update")
+            .changes(Some(&before), Some(&after));
+
+        assert_eq!(
+            entry.changes,
+            vec![
+                AuditChange {
+                    path: "added".to_string(),
+                    before: None,
+                    after: Some(json!({"name": "x", "password": REDACTED})),
+                },
+                AuditChange {
+                    path: "queues.downlink_bandwidth_mbps".to_string(),
+                    before: Some(json!(1000)),
+                    after: Some(json!(2000)),
+                },
+                AuditChange {
+                    path: "removed".to_string(),
+                    before: Some(json!(true)),
+                    after: None,
+                },
+                AuditChange {
+                    path: "uisp_integration.token".to_string(),
+                    before: Some(json!(REDACTED)),
+                    after: Some(json!(REDACTED)),
+                },
+            ]
+        );
+        assert!(!entry.truncated);
+    }
+
+    #[test]
+    fn config_diffs_redact_licence_keys_and_webhook_urls() {
+        let before = crate::Config::default();
+        let mut after = before.clone();
+        after.long_term_stats.license_key = Some("licence-secret".to_string());
+        after.webhooks = toml::from_str(
+            r#"
+            [[destinations]]
+            name = "slack"
+            url = "https://hooks.slack.com/services/T/B/X"
+            format = "slack"
+            "#,
+        )
+        .expect("valid section");
+        let entry = AuditEntry::new(AuditActor::subsystem("test"), "update_config")
+            .changes(Some(&before), Some(&after));
+
+        let logged = serde_json::to_string(&entry).expect("serialize");
+        assert!(!logged.contains("licence-secret"), "{logged}");
+        assert!(!logged.contains("hooks.slack.com"), "{logged}");
+        assert!(logged.contains("\"name\":\"slack\""), "{logged}");
+    }
+
+    #[test]
+    fn appended_entries_read_back_newest_first() {
+        let dir = std::env::temp_dir().join(format!("lqos-audit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        let path = dir.join(AUDIT_FILE_NAME);
+        let _ = std::fs::remove_file(&path);
+
+        for (n, action) in ["add_user", "update_config", "add_user"].iter().enumerate() {
+            let mut entry = AuditEntry::new(
+                AuditActor::User {
+                    name: "admin".to_string(),
+                },
+                action,
+            );
+            entry.time = n as u64;
+            append_to(&path, &entry).expect("append entry");
+        }
+
+        let all = read_from(&path, &AuditQuery::default()).expect("read log");
+        assert_eq!(
+            all.iter().map(|e| e.time).collect::<Vec<_>>(),
+            vec![2, 1, 0]
+        );
+
+        let users = AuditQuery {
+            action: Some("USER".to_string()),
+            limit: 1,
+            ..Default::default()
+        };
+        let found = read_from(&path, &users).expect("read log");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].time, 2);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn full_queue_records_how_many_entries_were_dropped() {
+        use std::sync::{Mutex, mpsc::channel};
+        use std::time::{Duration, Instant};
+
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let (started_tx, started_rx) = channel::<()>();
+        let (release_tx, release_rx) = channel::<()>();
+        let (started_tx, release_rx) = (Mutex::new(started_tx), Mutex::new(release_rx));
+        let log = written.clone();
+        let writer = AuditWriter::start(1, move |entry: &AuditEntry| {
+            if entry.action == "first" {
+                let _ = started_tx.lock().expect("lock").send(());
+                let _ = release_rx.lock().expect("lock").recv();
+            }
+            log.lock().expect("lock").push(entry.clone());
+        })
+        .expect("start writer");
+
+        let entry = |action: &str| AuditEntry::new(AuditActor::subsystem("test"), action);
+        writer.queue(entry("first"));
+        started_rx.recv().expect("writer started");
+        // The writer is busy: one entry fits in the queue, two are dropped.
+        for action in ["second", "third", "fourth"] {
+            writer.queue(entry(action));
+        }
+        release_tx.send(()).expect("release writer");
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while written.lock().expect("lock").len() < 3 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let written = written.lock().expect("lock");
+        let actions: Vec<&str> = written.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, ["first", "audit_entries_dropped", "second"]);
+        assert!(
+            written[1]
+                .detail
+                .as_deref()
+                .is_some_and(|d| d.starts_with("2 "))
+        );
+    }
+}
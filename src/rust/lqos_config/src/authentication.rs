@@ -3,7 +3,8 @@
 
 use allocative::Allocative;
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier, password_hash::SaltString};
-use rand_core::OsRng;
+use lqos_utils::unix_time::unix_now;
+use rand_core::{OsRng, RngCore};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{
@@ -11,6 +12,7 @@ use std::{
     fs::{OpenOptions, read_to_string, remove_file, rename},
     io::Write,
     path::{Path, PathBuf},
+    str::FromStr,
 };
 use thiserror::Error;
 use tracing::{error, warn};
@@ -22,6 +24,8 @@ const INITIAL_AUTH_EPOCH: u64 = 1;
 const LEGACY_AUTH_FILE_NAME: &str = "webusers.toml";
 const CURRENT_AUTH_FILE_NAME: &str = "lqusers.toml";
 const LEGACY_PASSWORD_PEPPER: &str = "_LibreQosLikesPasswordsForDinner";
+const API_TOKEN_PREFIX: &str = "lqos_";
+const API_TOKEN_BYTES: usize = 32;
 
 fn default_auth_file_version() -> u32 {
     LEGACY_AUTH_FILE_VERSION
@@ -71,6 +75,115 @@ impl Display for UserRole {
     }
 }
 
+/// What an API token is allowed to do. Every token may read data; the other
+/// scopes each unlock one kind of change.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Allocative)]
+pub enum ApiTokenScope {
+    /// View data only.
+    ReadOnly,
+    /// Create, update and delete shaped devices.
+    ShapedDevicesWrite,
+    /// Set and clear operator overrides.
+    OverridesWrite,
+    /// Trigger a LibreQoS reload and clear urgent issues.
+    Reload,
+}
+
+impl ApiTokenScope {
+    /// Every scope, in display order.
+    pub const ALL: [ApiTokenScope; 4] = [
+        ApiTokenScope::ReadOnly,
+        ApiTokenScope::ShapedDevicesWrite,
+        ApiTokenScope::OverridesWrite,
+        ApiTokenScope::Reload,
+    ];
+}
+
+impl FromStr for ApiTokenScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(['_', ' '], "-").as_str() {
+            "read-only" | "readonly" => Ok(ApiTokenScope::ReadOnly),
+            "shaped-devices-write" | "shapeddeviceswrite" | "devices" => {
+                Ok(ApiTokenScope::ShapedDevicesWrite)
+            }
+            "overrides-write" | "overrideswrite" | "overrides" => Ok(ApiTokenScope::OverridesWrite),
+            "reload" => Ok(ApiTokenScope::Reload),
+            _ => Err(format!(
+                "Unknown scope '{s}'. Use read-only, shaped-devices-write, overrides-write or reload"
+            )),
+        }
+    }
+}
+
+impl Display for ApiTokenScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiTokenScope::ReadOnly => write!(f, "read-only"),
+            ApiTokenScope::ShapedDevicesWrite => write!(f, "shaped-devices-write"),
+            ApiTokenScope::OverridesWrite => write!(f, "overrides-write"),
+            ApiTokenScope::Reload => write!(f, "reload"),
+        }
+    }
+}
+
+/// A long-lived token for automation. Only a hash of the secret is stored;
+/// the secret itself is shown once, when the token is created.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Allocative)]
+pub struct ApiToken {
+    /// Identifier used to list and revoke the token.
+    pub id: String,
+    /// Free-form description, e.g. the script that uses the token.
+    pub name: String,
+    /// SHA-256 of the token secret.
+    pub token_hash: String,
+    /// What the token may do.
+    pub scopes: Vec<ApiTokenScope>,
+    /// Creation time, in UNIX seconds.
+    pub created: u64,
+    /// Expiry time in UNIX seconds. `None` never expires.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    /// When the token was last accepted, in UNIX seconds. Updated at most
+    /// once a minute.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<u64>,
+}
+
+impl ApiToken {
+    /// Does the token grant `scope`? Every token may read.
+    pub fn allows(&self, scope: ApiTokenScope) -> bool {
+        scope == ApiTokenScope::ReadOnly || self.scopes.contains(&scope)
+    }
+
+    /// Has the token expired at `now` (UNIX seconds)?
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Finds the token in `tokens` matching `secret`, rejecting expired
+    /// tokens. This does not record the use; see
+    /// [`WebUsers::record_api_token_use`].
+    pub fn verify<'a>(
+        tokens: &'a [ApiToken],
+        secret: &str,
+        now: u64,
+    ) -> Result<&'a ApiToken, AuthenticationError> {
+        if !WebUsers::is_api_token(secret) {
+            return Err(AuthenticationError::InvalidToken);
+        }
+        let hash = WebUsers::hash_api_token(secret);
+        let Some(token) = tokens.iter().find(|t| t.token_hash == hash) else {
+            return Err(AuthenticationError::InvalidToken);
+        };
+        if token.is_expired(now) {
+            return Err(AuthenticationError::TokenExpired);
+        }
+        Ok(token)
+    }
+}
+
 /// A user of the web UI.
 #[derive(Clone, Debug, Deserialize, Serialize, Allocative)]
 pub struct WebUser {
@@ -113,6 +226,8 @@ pub struct WebUsers {
     allow_unauthenticated_to_view: bool,
     #[serde(default)]
     users: Vec<WebUser>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    api_tokens: Vec<ApiToken>,
 }
 
 impl Default for WebUsers {
@@ -122,6 +237,7 @@ impl Default for WebUsers {
             auth_epoch: INITIAL_AUTH_EPOCH,
             allow_unauthenticated_to_view: false,
             users: Vec::new(),
+            api_tokens: Vec::new(),
         }
     }
 }
@@ -443,6 +559,100 @@ impl WebUsers {
     pub fn do_we_allow_anonymous(&self) -> bool {
         self.allow_unauthenticated_to_view
     }
+
+    fn hash_api_token(secret: &str) -> String {
+        let mut sha256 = Sha256::new();
+        sha256.update(secret.as_bytes());
+        format!("{:x}", sha256.finalize())
+    }
+
+    /// Does this string look like an API token rather than a session?
+    pub fn is_api_token(secret: &str) -> bool {
+        secret.starts_with(API_TOKEN_PREFIX)
+    }
+
+    fn new_api_token(
+        name: &str,
+        scopes: &[ApiTokenScope],
+        expires: Option<u64>,
+        now: u64,
+    ) -> (ApiToken, String) {
+        let mut bytes = [0u8; API_TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+        let secret = bytes.iter().fold(API_TOKEN_PREFIX.to_string(), |mut s, b| {
+            s.push_str(&format!("{b:02x}"));
+            s
+        });
+        let mut scopes = scopes.to_vec();
+        scopes.sort_by_key(|scope| ApiTokenScope::ALL.iter().position(|s| s == scope));
+        scopes.dedup();
+        let token = ApiToken {
+            id: Uuid::new_v4().simple().to_string()[..12].to_string(),
+            name: name.to_string(),
+            token_hash: Self::hash_api_token(&secret),
+            scopes,
+            created: now,
+            expires,
+            last_used: None,
+        };
+        (token, secret)
+    }
+
+    /// Creates a new API token and saves it. Returns the stored token and
+    /// the secret, which cannot be recovered later.
+    pub fn create_api_token(
+        &mut self,
+        name: &str,
+        scopes: &[ApiTokenScope],
+        expires: Option<u64>,
+    ) -> Result<(ApiToken, String), AuthenticationError> {
+        let now = unix_now().map_err(|_| AuthenticationError::ClockNotReady)?;
+        let (token, secret) = Self::new_api_token(name, scopes, expires, now);
+        self.api_tokens.push(token.clone());
+        self.save_to_disk()?;
+        Ok((token, secret))
+    }
+
+    /// Deletes an API token by id.
+    pub fn revoke_api_token(&mut self, id: &str) -> Result<(), AuthenticationError> {
+        let old_len = self.api_tokens.len();
+        self.api_tokens.retain(|t| t.id != id);
+        if old_len == self.api_tokens.len() {
+            return Err(AuthenticationError::TokenNotFound);
+        }
+        self.save_to_disk()?;
+        Ok(())
+    }
+
+    /// Return a list of API tokens. Secrets are never stored, so this is
+    /// safe to display.
+    pub fn get_api_tokens(&self) -> Vec<ApiToken> {
+        self.api_tokens.clone()
+    }
+
+    /// Updates a token's last-used time and saves the file.
+    pub fn record_api_token_use(&mut self, id: &str, now: u64) -> Result<(), AuthenticationError> {
+        let Some(token) = self.api_tokens.iter_mut().find(|t| t.id == id) else {
+            return Err(AuthenticationError::TokenNotFound);
+        };
+        token.last_used = Some(now);
+        self.save_to_disk()
+    }
+
+    /// Dump all API tokens to the console.
+    pub fn print_api_tokens(&self) {
+        let now = unix_now().unwrap_or(0);
+        self.api_tokens.iter().for_each(|t| {
+            let scopes = t
+                .scopes
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            let status = if t.is_expired(now) { "expired" } else { "" };
+            println!("{:<14} {:<30} {:<50} {status}", t.id, t.name, scopes);
+        });
+    }
 }
 
 /// Errors that can occur while managing web-UI authentication.
@@ -474,4 +684,65 @@ pub enum AuthenticationError {
     /// Username/password did not match.
     #[error("Invalid Login")]
     InvalidLogin,
+    /// No API token matches the presented secret.
+    #[error("Invalid API token")]
+    InvalidToken,
+    /// The API token is past its expiry time.
+    #[error("API token has expired")]
+    TokenExpired,
+    /// Attempted to revoke or reference a token that does not exist.
+    #[error("API token not found")]
+    TokenNotFound,
+    /// The system clock could not be read.
+    #[error("Unable to read the system clock")]
+    ClockNotReady,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_tokens_verify_by_secret_and_expire() {
+        let (token, secret) = WebUsers::new_api_token(
+            "provisioning",
+            &[
+                ApiTokenScope::Reload,
+                ApiTokenScope::ShapedDevicesWrite,
+                ApiTokenScope::Reload,
+            ],
+            Some(1_000),
+            10,
+        );
+        assert!(WebUsers::is_api_token(&secret));
+        assert_ne!(token.token_hash, secret);
+        assert_eq!(
+            token.scopes,
+            vec![ApiTokenScope::ShapedDevicesWrite, ApiTokenScope::Reload]
+        );
+        let tokens = vec![token.clone()];
+
+        let found = ApiToken::verify(&tokens, &secret, 500).expect("token should verify");
+        assert_eq!(found.id, token.id);
+        assert!(found.allows(ApiTokenScope::ReadOnly));
+        assert!(found.allows(ApiTokenScope::Reload));
+        assert!(!found.allows(ApiTokenScope::OverridesWrite));
+
+        assert!(matches!(
+            ApiToken::verify(&tokens, &secret, 1_000),
+            Err(AuthenticationError::TokenExpired)
+        ));
+        assert!(matches!(
+            ApiToken::verify(&tokens, "lqos_00", 500),
+            Err(AuthenticationError::InvalidToken)
+        ));
+    }
+
+    #[test]
+    fn scopes_parse_from_cli_names() {
+        for scope in ApiTokenScope::ALL {
+            assert_eq!(scope.to_string().parse::<ApiTokenScope>(), Ok(scope));
+        }
+        assert!("write-everything".parse::<ApiTokenScope>().is_err());
+    }
 }
@@ -327,6 +327,7 @@ fn migrate_influx(
             bucket: python_config.influx_dbbucket.clone(),
             org: python_config.influx_dborg.clone(),
             token: python_config.influx_dbtoken.clone(),
+            ..InfluxDbConfig::default()
         };
         new_config.influxdb = Some(cfg);
     }
@@ -19,9 +19,13 @@ mod python_migration;
 pub mod test_data;
 mod v15;
 pub use v15::{
-    BridgeConfig, LazyQueueMode, QueueMode, RttThresholds, SingleInterfaceConfig, StormguardConfig,
-    StormguardStrategy, TreeguardCircuitsConfig, TreeguardConfig, TreeguardCpuConfig,
-    TreeguardCpuMode, TreeguardLinksConfig, TreeguardQooConfig, Tunables,
+    AnomalyDetectionConfig, BridgeConfig, BusAccess, CakeAtmMode, CakeDiffserv, CakeFlowIsolation,
+    FlowConfig, FlowExportTarget, GeoIpConfig, InfluxDbConfig, LazyQueueMode, LocalHistoryConfig,
+    PrometheusConfig, QueueMode, QuotaDirection, QuotaPolicy, RemoteBusConfig, RemoteBusCredential,
+    RttThresholds, SingleInterfaceConfig, SqmProfile, StormguardConfig, StormguardStrategy,
+    TreeguardCircuitsConfig, TreeguardConfig, TreeguardCpuConfig, TreeguardCpuMode,
+    TreeguardLinksConfig, TreeguardQooConfig, Tunables, UsageQuotaConfig, WebhookDestination,
+    WebhookEventKind, WebhookFormat, WebhookSeverity, WebhooksConfig, is_valid_sqm_profile_name,
 };
 
 static CONFIG: Lazy<ArcSwap<Option<Arc<Config>>>> = Lazy::new(|| ArcSwap::from_pointee(None));
@@ -0,0 +1,113 @@
+//! Traffic anomaly (DDoS) detection thresholds.
+
+use allocative::Allocative;
+use serde::{Deserialize, Serialize};
+
+fn default_baseline_seconds() -> u32 {
+    600
+}
+
+fn default_deviation_factor() -> f64 {
+    8.0
+}
+
+fn default_min_packets_per_second() -> u64 {
+    20_000
+}
+
+fn default_min_mbps() -> u64 {
+    200
+}
+
+fn default_min_new_flows_per_second() -> u64 {
+    1_000
+}
+
+fn default_sustain_seconds() -> u32 {
+    3
+}
+
+fn default_include_sites() -> bool {
+    true
+}
+
+/// Settings for lqosd's per-circuit and per-site anomaly detector.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
+pub struct AnomalyDetectionConfig {
+    /// Whether lqosd watches for traffic anomalies.
+    #[serde(default)]
+    pub enabled: bool,
+    /// How much history the moving baselines represent, in seconds.
+    #[serde(default = "default_baseline_seconds")]
+    pub baseline_seconds: u32,
+    /// Traffic must exceed its baseline by this factor to be anomalous.
+    #[serde(default = "default_deviation_factor")]
+    pub deviation_factor: f64,
+    /// Packet rates below this are never flagged.
+    #[serde(default = "default_min_packets_per_second")]
+    pub min_packets_per_second: u64,
+    /// Bit rates below this (in Mbps) are never flagged.
+    #[serde(default = "default_min_mbps")]
+    pub min_mbps: u64,
+    /// New-flow rates below this are never flagged.
+    #[serde(default = "default_min_new_flows_per_second")]
+    pub min_new_flows_per_second: u64,
+    /// Seconds an anomaly has to persist before it is reported.
+    #[serde(default = "default_sustain_seconds")]
+    pub sustain_seconds: u32,
+    /// Also watch every site in `network.json`, not just circuits.
+    #[serde(default = "default_include_sites")]
+    pub include_sites: bool,
+}
+
+impl Default for AnomalyDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            baseline_seconds: default_baseline_seconds(),
+            deviation_factor: default_deviation_factor(),
+            min_packets_per_second: default_min_packets_per_second(),
+            min_mbps: default_min_mbps(),
+            min_new_flows_per_second: default_min_new_flows_per_second(),
+            sustain_seconds: default_sustain_seconds(),
+            include_sites: default_include_sites(),
+        }
+    }
+}
+
+impl AnomalyDetectionConfig {
+    /// Checks the baseline window, deviation factor and sustain time.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.baseline_seconds < 60 {
+            return Err("anomaly_detection.baseline_seconds must be at least 60".to_string());
+        }
+        if !self.deviation_factor.is_finite() || self.deviation_factor <= 1.0 {
+            return Err("anomaly_detection.deviation_factor must be greater than 1".to_string());
+        }
+        if self.sustain_seconds == 0 {
+            return Err("anomaly_detection.sustain_seconds must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_unusable_thresholds() {
+        AnomalyDetectionConfig::default()
+            .validate()
+            .expect("defaults are valid");
+        for bad in [
+            "baseline_seconds = 10",
+            "deviation_factor = 1.0",
+            "deviation_factor = -3",
+            "sustain_seconds = 0",
+        ] {
+            let cfg: AnomalyDetectionConfig = toml::from_str(bad).expect("parses");
+            assert!(cfg.validate().is_err(), "{bad}");
+        }
+    }
+}
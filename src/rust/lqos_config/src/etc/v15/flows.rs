@@ -5,14 +5,99 @@
 use allocative::Allocative;
 use serde::{Deserialize, Serialize};
 
+/// RFC 5612 example enterprise number. Collectors need to be told which PEN
+/// carries the LibreQoS information elements, so this is only a placeholder.
+fn default_ipfix_enterprise_number() -> u32 {
+    32473
+}
+
+fn default_sampling_rate() -> u32 {
+    1
+}
+
+fn default_export_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_template_refresh_seconds() -> u64 {
+    60
+}
+
+fn default_template_refresh_packets() -> u32 {
+    20
+}
+
+fn default_active_timeout_seconds() -> u64 {
+    60
+}
+
+/// One flow export destination.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
+pub struct FlowExportTarget {
+    /// Collector IP address or hostname.
+    pub ip: String,
+    /// Collector UDP port.
+    pub port: u16,
+    /// Export protocol: 5, 9 or 10 (IPFIX).
+    pub version: u8,
+    /// Export one in every `sampling_rate` flows. `1` exports every flow.
+    #[serde(default = "default_sampling_rate")]
+    pub sampling_rate: u32,
+    /// If not empty, only flows with a local or remote address inside one
+    /// of these subnets (CIDR notation) are exported.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_subnets: Vec<String>,
+    /// Flows with a local or remote address inside one of these subnets
+    /// are never exported. Takes precedence over `include_subnets`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_subnets: Vec<String>,
+}
+
+/// Flow tracking and export settings (the `[flows]` section).
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
 pub struct FlowConfig {
+    /// Inactive timeout: flows that are idle for this long are ended and
+    /// exported.
     pub flow_timeout_seconds: u64,
+    /// Enables flow export.
     pub netflow_enabled: bool,
+    /// Collector UDP port for the primary export target.
     pub netflow_port: Option<u16>,
+    /// Collector address for the primary export target.
     pub netflow_ip: Option<String>,
+    /// Export protocol: 5, 9 or 10 (IPFIX).
     pub netflow_version: Option<u8>,
+    /// RTT events whose remote address matches one of these subnets are
+    /// ignored by flow-based RTT tracking.
     pub do_not_track_subnets: Option<Vec<String>>,
+    /// Private Enterprise Number used for the LibreQoS-specific IPFIX
+    /// information elements (TCP RTT, retransmits, circuit ID and ASN).
+    #[serde(default = "default_ipfix_enterprise_number")]
+    pub ipfix_enterprise_number: u32,
+    /// Additional export destinations, each with its own protocol, sampling
+    /// and subnet filters. Exported alongside the `netflow_*` target.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub export_targets: Vec<FlowExportTarget>,
+    /// Longest time (milliseconds) a flow record waits in an exporter before
+    /// it is sent, even if the batch isn't full.
+    #[serde(default = "default_export_flush_interval_ms")]
+    pub export_flush_interval_ms: u64,
+    /// NetFlow v9 and IPFIX templates are re-sent at least this often
+    /// (seconds), so restarted collectors can decode the stream again.
+    #[serde(default = "default_template_refresh_seconds")]
+    pub template_refresh_seconds: u64,
+    /// Templates are also re-sent after this many export packets.
+    #[serde(default = "default_template_refresh_packets")]
+    pub template_refresh_packets: u32,
+    /// Active timeout: long-lived flows are exported every this many seconds
+    /// while they are still running, each record covering the traffic since
+    /// the previous one. `0` exports flows only when they end.
+    #[serde(default = "default_active_timeout_seconds")]
+    pub active_timeout_seconds: u64,
+    /// Operator application classification rules (TOML), consulted before
+    /// the rules shipped with lqosd. Reloaded when the file changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application_rules: Option<String>,
 }
 
 impl Default for FlowConfig {
@@ -24,6 +109,84 @@ impl Default for FlowConfig {
             netflow_ip: None,
             netflow_version: None,
             do_not_track_subnets: None,
+            ipfix_enterprise_number: default_ipfix_enterprise_number(),
+            export_targets: Vec::new(),
+            export_flush_interval_ms: default_export_flush_interval_ms(),
+            template_refresh_seconds: default_template_refresh_seconds(),
+            template_refresh_packets: default_template_refresh_packets(),
+            active_timeout_seconds: default_active_timeout_seconds(),
+            application_rules: None,
         }
     }
 }
+
+impl FlowConfig {
+    /// Every configured export destination: the legacy `netflow_*` target
+    /// (if fully specified) followed by `export_targets`.
+    pub fn all_export_targets(&self) -> Vec<FlowExportTarget> {
+        let mut targets = Vec::with_capacity(self.export_targets.len() + 1);
+        if let (Some(ip), Some(port), Some(version)) =
+            (&self.netflow_ip, self.netflow_port, self.netflow_version)
+        {
+            targets.push(FlowExportTarget {
+                ip: ip.clone(),
+                port,
+                version,
+                sampling_rate: 1,
+                include_subnets: Vec::new(),
+                exclude_subnets: Vec::new(),
+            });
+        }
+        targets.extend(self.export_targets.iter().cloned());
+        targets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_target_and_export_targets_are_combined() {
+        let raw = r#"
+            flow_timeout_seconds = 30
+            netflow_enabled = true
+            netflow_ip = "10.0.0.1"
+            netflow_port = 2055
+            netflow_version = 9
+
+            [[export_targets]]
+            ip = "10.0.0.2"
+            port = 4739
+            version = 10
+            sampling_rate = 10
+            include_subnets = ["100.64.0.0/10"]
+        "#;
+        let cfg: FlowConfig = toml::from_str(raw).expect("flows section should parse");
+        let targets = cfg.all_export_targets();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].ip, "10.0.0.1");
+        assert_eq!(targets[0].sampling_rate, 1);
+        assert_eq!(targets[1].version, 10);
+        assert_eq!(targets[1].sampling_rate, 10);
+        assert_eq!(
+            targets[1].include_subnets,
+            vec!["100.64.0.0/10".to_string()]
+        );
+        assert!(targets[1].exclude_subnets.is_empty());
+        assert_eq!(cfg.export_flush_interval_ms, 1000);
+        assert_eq!(cfg.template_refresh_seconds, 60);
+        assert_eq!(cfg.template_refresh_packets, 20);
+        assert_eq!(cfg.active_timeout_seconds, 60);
+        assert_eq!(cfg.application_rules, None);
+    }
+
+    #[test]
+    fn incomplete_legacy_target_is_ignored() {
+        let cfg = FlowConfig {
+            netflow_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        };
+        assert!(cfg.all_export_targets().is_empty());
+    }
+}
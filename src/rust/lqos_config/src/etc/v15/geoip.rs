@@ -0,0 +1,77 @@
+//! Where lqosd gets ASN and geolocation data for flow analysis.
+
+use allocative::Allocative;
+use serde::{Deserialize, Serialize};
+
+/// ASN and GeoIP database sources.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default, Allocative)]
+pub struct GeoIpConfig {
+    /// Local databases, consulted in order: MaxMind DB files (`.mmdb`, from
+    /// MaxMind, DB-IP or IPinfo) or IPinfo CSV files (`.csv`). They are
+    /// reloaded whenever they change on disk.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub databases: Vec<String>,
+    /// Download `geo2.bin` from insight.libreqos.com and consult it after the
+    /// local databases. Defaults to on only when no local databases are set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_fallback: Option<bool>,
+}
+
+impl GeoIpConfig {
+    /// Whether `geo2.bin` should be downloaded.
+    pub fn download_enabled(&self) -> bool {
+        self.download_fallback.unwrap_or(self.databases.is_empty())
+    }
+
+    /// Checks that every database path is absolute and of a known type.
+    pub fn validate(&self) -> Result<(), String> {
+        for path in &self.databases {
+            let lower = path.to_ascii_lowercase();
+            if !path.starts_with('/') {
+                return Err(format!("geoip database '{path}' must be an absolute path"));
+            }
+            if !(lower.ends_with(".mmdb") || lower.ends_with(".csv")) {
+                return Err(format!(
+                    "geoip database '{path}' must be a .mmdb or .csv file"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_databases_turn_off_the_download_unless_asked() {
+        assert!(GeoIpConfig::default().download_enabled());
+        let cfg: GeoIpConfig =
+            toml::from_str(r#"databases = ["/var/lib/libreqos/asn.mmdb"]"#).expect("parses");
+        cfg.validate().expect("valid");
+        assert!(!cfg.download_enabled());
+        let cfg = GeoIpConfig {
+            download_fallback: Some(true),
+            ..cfg
+        };
+        assert!(cfg.download_enabled());
+
+        let air_gapped = GeoIpConfig {
+            download_fallback: Some(false),
+            ..Default::default()
+        };
+        assert!(!air_gapped.download_enabled());
+
+        let relative = GeoIpConfig {
+            databases: vec!["asn.mmdb".to_string()],
+            ..Default::default()
+        };
+        assert!(relative.validate().is_err());
+        let unknown = GeoIpConfig {
+            databases: vec!["/tmp/asn.dat".to_string()],
+            ..Default::default()
+        };
+        assert!(unknown.validate().is_err());
+    }
+}
@@ -1,13 +1,56 @@
+//! InfluxDB v2 export configuration.
+
 use allocative::Allocative;
 use serde::{Deserialize, Serialize};
 
+fn default_interval_seconds() -> u64 {
+    10
+}
+
+fn default_max_lines_per_write() -> usize {
+    5000
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_spool_max_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+/// Settings for writing shaper statistics to InfluxDB v2.
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
 pub struct InfluxDbConfig {
+    /// Whether lqosd should export statistics to InfluxDB.
     pub enable_influxdb: bool,
+    /// Base URL of the InfluxDB server, e.g. `http://localhost:8086`.
     pub url: String,
+    /// Destination bucket.
     pub bucket: String,
+    /// Organization that owns the bucket.
     pub org: String,
+    /// API token with write access to the bucket.
     pub token: String,
+    /// How often (in seconds) lqosd samples per-circuit and per-site stats
+    /// and writes them to InfluxDB.
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+    /// Maximum number of line-protocol lines sent in a single write request.
+    #[serde(default = "default_max_lines_per_write")]
+    pub max_lines_per_write: usize,
+    /// Number of immediate retries (with exponential backoff) before a batch
+    /// is spooled to disk.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Directory used to spool batches that could not be delivered.
+    /// Defaults to `<lqos_directory>/influxdb_spool`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spool_path: Option<String>,
+    /// Upper bound on the size of the on-disk spool. The oldest batches are
+    /// discarded first when the bound is exceeded.
+    #[serde(default = "default_spool_max_bytes")]
+    pub spool_max_bytes: u64,
 }
 
 impl Default for InfluxDbConfig {
@@ -18,6 +61,34 @@ impl Default for InfluxDbConfig {
             bucket: "libreqos".to_string(),
             org: "Your ISP Name".to_string(),
             token: "".to_string(),
+            interval_seconds: default_interval_seconds(),
+            max_lines_per_write: default_max_lines_per_write(),
+            max_retries: default_max_retries(),
+            spool_path: None,
+            spool_max_bytes: default_spool_max_bytes(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_influxdb_section_gets_exporter_defaults() {
+        let raw = r#"
+            enable_influxdb = true
+            url = "http://localhost:8086"
+            bucket = "libreqos"
+            org = "isp"
+            token = "abc"
+        "#;
+        let cfg: InfluxDbConfig = toml::from_str(raw).expect("legacy section should parse");
+        assert!(cfg.enable_influxdb);
+        assert_eq!(cfg.interval_seconds, 10);
+        assert_eq!(cfg.max_lines_per_write, 5000);
+        assert_eq!(cfg.max_retries, 3);
+        assert!(cfg.spool_path.is_none());
+        assert_eq!(cfg.spool_max_bytes, 64 * 1024 * 1024);
+    }
+}
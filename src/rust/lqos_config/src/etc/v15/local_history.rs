@@ -0,0 +1,69 @@
+//! Embedded long-term statistics store configuration.
+
+use allocative::Allocative;
+use serde::{Deserialize, Serialize};
+
+fn default_second_retention_minutes() -> u64 {
+    60
+}
+
+fn default_minute_retention_days() -> u64 {
+    7
+}
+
+fn default_hour_retention_days() -> u64 {
+    365
+}
+
+/// Settings for the on-disk time-series store that keeps shaper, site and
+/// circuit history when no Insight licence is available.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
+pub struct LocalHistoryConfig {
+    /// Whether lqosd records local history. Off unless enabled, since it
+    /// writes to disk continuously.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directory holding the history files.
+    /// Defaults to `<lqos_directory>/history`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+    /// How long one-second samples are kept, in minutes.
+    #[serde(default = "default_second_retention_minutes")]
+    pub second_retention_minutes: u64,
+    /// How long one-minute summaries are kept, in days.
+    #[serde(default = "default_minute_retention_days")]
+    pub minute_retention_days: u64,
+    /// How long one-hour summaries are kept, in days.
+    #[serde(default = "default_hour_retention_days")]
+    pub hour_retention_days: u64,
+    /// Record per-circuit history as well as shaper and site totals. This is
+    /// by far the largest part of the store on big networks.
+    #[serde(default)]
+    pub record_circuits: bool,
+}
+
+impl Default for LocalHistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: None,
+            second_retention_minutes: default_second_retention_minutes(),
+            minute_retention_days: default_minute_retention_days(),
+            hour_retention_days: default_hour_retention_days(),
+            record_circuits: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_and_circuit_recording_are_opt_in() {
+        let cfg: LocalHistoryConfig =
+            toml::from_str(r#"directory = "/var/lib/libreqos/history""#).expect("parses");
+        assert!(!cfg.enabled);
+        assert!(!cfg.record_circuits);
+    }
+}
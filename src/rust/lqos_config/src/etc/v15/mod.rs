@@ -3,30 +3,51 @@
 mod top_config;
 pub use top_config::Config;
 pub use top_config::RttThresholds;
+mod anomaly_detection;
 mod bridge;
 mod flows;
+mod geoip;
 pub mod influxdb;
 mod integration_common;
 mod ip_ranges;
+mod local_history;
 mod long_term_stats;
 mod netzur_integration;
 mod powercode_integration;
+mod prometheus;
 mod queues;
+mod remote_bus;
 mod sonar_integration;
 mod splynx_integration;
 mod stormguard;
 mod treeguard;
 mod tuning;
 mod uisp_integration;
+mod usage_quotas;
 mod visp_integration;
+mod webhooks;
 mod wispgate;
 
+pub use anomaly_detection::AnomalyDetectionConfig;
 pub use bridge::*;
+pub use flows::{FlowConfig, FlowExportTarget};
+pub use geoip::GeoIpConfig;
+pub use influxdb::InfluxDbConfig;
+pub use local_history::LocalHistoryConfig;
 pub use long_term_stats::LongTermStats;
-pub use queues::{LazyQueueMode, QueueMode};
+pub use prometheus::PrometheusConfig;
+pub use queues::{
+    CakeAtmMode, CakeDiffserv, CakeFlowIsolation, LazyQueueMode, QueueMode, SqmProfile,
+    is_valid_sqm_profile_name,
+};
+pub use remote_bus::{BusAccess, RemoteBusConfig, RemoteBusCredential};
 pub use stormguard::{StormguardConfig, StormguardStrategy};
 pub use treeguard::{
     TreeguardCircuitsConfig, TreeguardConfig, TreeguardCpuConfig, TreeguardCpuMode,
     TreeguardLinksConfig, TreeguardQooConfig,
 };
 pub use tuning::Tunables;
+pub use usage_quotas::{QuotaDirection, QuotaPolicy, UsageQuotaConfig};
+pub use webhooks::{
+    WebhookDestination, WebhookEventKind, WebhookFormat, WebhookSeverity, WebhooksConfig,
+};
@@ -0,0 +1,44 @@
+//! Prometheus/OpenMetrics scrape endpoint configuration.
+
+use allocative::Allocative;
+use serde::{Deserialize, Serialize};
+
+fn default_max_site_series() -> usize {
+    500
+}
+
+fn default_max_circuit_series() -> usize {
+    1000
+}
+
+/// Settings for the `/metrics` endpoint served by the node manager.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
+pub struct PrometheusConfig {
+    /// Whether the node manager answers scrapes on `/metrics`.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Maximum number of sites (network.json nodes) exported with per-site
+    /// labels. The busiest sites are kept; `0` disables per-site series.
+    #[serde(default = "default_max_site_series")]
+    pub max_site_series: usize,
+    /// Maximum number of circuits exported with per-circuit labels. The
+    /// busiest circuits are kept; `0` disables per-circuit series.
+    #[serde(default = "default_max_circuit_series")]
+    pub max_circuit_series: usize,
+    /// Require an `Authorization: Bearer` API token on scrapes. Any token
+    /// works, since every token may read. Without this, anyone who can reach
+    /// the node manager can read the metrics, including circuit names.
+    #[serde(default)]
+    pub require_token: bool,
+}
+
+impl Default for PrometheusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_site_series: default_max_site_series(),
+            max_circuit_series: default_max_circuit_series(),
+            require_token: false,
+        }
+    }
+}
@@ -66,6 +66,9 @@ pub struct QueueConfig {
 
     /// Auto-change queues to fq_codel if they are greater than or equal to X Mbps. Defaults to 1000.
     pub fast_queues_fq_codel: Option<f64>,
+
+    /// Named CAKE parameter profiles, selected per circuit with a `cake:<name>` SQM override.
+    pub sqm_profiles: Vec<SqmProfile>,
 }
 
 impl Serialize for QueueConfig {
@@ -73,7 +76,7 @@ impl Serialize for QueueConfig {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("QueueConfig", 15)?;
+        let mut state = serializer.serialize_struct("QueueConfig", 16)?;
         state.serialize_field("default_sqm", &self.default_sqm)?;
         state.serialize_field("queue_mode", &self.queue_mode)?;
         // Preserve the legacy field during rewrites so older binaries that still
@@ -94,6 +97,7 @@ impl Serialize for QueueConfig {
         state.serialize_field("lazy_expire_seconds", &self.lazy_expire_seconds)?;
         state.serialize_field("lazy_threshold_bytes", &self.lazy_threshold_bytes)?;
         state.serialize_field("fast_queues_fq_codel", &self.fast_queues_fq_codel)?;
+        state.serialize_field("sqm_profiles", &self.sqm_profiles)?;
         state.end()
     }
 }
@@ -116,6 +120,7 @@ struct QueueConfigCompat {
     lazy_expire_seconds: Option<u64>,
     lazy_threshold_bytes: Option<u64>,
     fast_queues_fq_codel: Option<f64>,
+    sqm_profiles: Vec<SqmProfile>,
 }
 
 /// Lazy queue creation modes
@@ -147,6 +152,7 @@ impl Default for QueueConfig {
             lazy_expire_seconds: Some(600), // 10 minutes default
             lazy_threshold_bytes: None,
             fast_queues_fq_codel: None,
+            sqm_profiles: Vec::new(),
         }
     }
 }
@@ -156,6 +162,194 @@ impl QueueConfig {
     pub fn set_queue_mode(&mut self, queue_mode: QueueMode) {
         self.queue_mode = queue_mode;
     }
+
+    /// Finds a named SQM profile, ignoring case.
+    pub fn sqm_profile(&self, name: &str) -> Option<&SqmProfile> {
+        self.sqm_profiles
+            .iter()
+            .find(|profile| profile.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Checks that SQM profile names are usable in override tokens and unique.
+    pub fn validate_sqm_profiles(&self) -> Result<(), String> {
+        let mut seen = std::collections::HashSet::new();
+        for profile in &self.sqm_profiles {
+            if !is_valid_sqm_profile_name(&profile.name) {
+                return Err(format!(
+                    "sqm_profiles name '{}' must be non-empty and use only letters, digits, '-' or '_'",
+                    profile.name
+                ));
+            }
+            if !seen.insert(profile.name.to_ascii_lowercase()) {
+                return Err(format!(
+                    "sqm_profiles name '{}' is duplicated",
+                    profile.name
+                ));
+            }
+            if profile.rtt_ms == Some(0) {
+                return Err(format!(
+                    "sqm_profiles '{}' rtt_ms must be > 0",
+                    profile.name
+                ));
+            }
+            if let Some(overhead) = profile.overhead
+                && !(-64..=256).contains(&overhead)
+            {
+                return Err(format!(
+                    "sqm_profiles '{}' overhead must be between -64 and 256",
+                    profile.name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if `name` can be used as `cake:<name>` in an SQM override.
+pub fn is_valid_sqm_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// A named bundle of CAKE parameters. Circuits select one with a `cake:<name>`
+/// SQM override, e.g. ATM compensation for DSL or `ack-filter` for fibre.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default, Allocative)]
+#[serde(default)]
+pub struct SqmProfile {
+    /// Profile name, referenced as `cake:<name>`.
+    pub name: String,
+    /// Diffserv mode. `None` keeps CAKE's default (`diffserv3`).
+    pub diffserv: Option<CakeDiffserv>,
+    /// Flow isolation mode. `None` keeps CAKE's default (`triple-isolate`).
+    pub flow_isolation: Option<CakeFlowIsolation>,
+    /// Look up flows behind NAT using conntrack.
+    pub nat: bool,
+    /// Filter redundant TCP ACKs.
+    pub ack_filter: bool,
+    /// Per-packet framing overhead in bytes.
+    pub overhead: Option<i32>,
+    /// Minimum packet unit in bytes.
+    pub mpu: Option<u32>,
+    /// Link-layer cell framing compensation.
+    pub atm: Option<CakeAtmMode>,
+    /// RTT target in milliseconds. `None` applies the usual low-rate fixups.
+    pub rtt_ms: Option<u32>,
+}
+
+impl SqmProfile {
+    /// Expands the profile into `tc` CAKE arguments, excluding the leading `cake`.
+    pub fn cake_tokens(&self) -> Vec<String> {
+        let mut tokens = Vec::new();
+        if let Some(diffserv) = self.diffserv {
+            tokens.push(diffserv.as_tc_token().to_string());
+        }
+        if let Some(isolation) = self.flow_isolation {
+            tokens.push(isolation.as_tc_token().to_string());
+        }
+        if self.nat {
+            tokens.push("nat".to_string());
+        }
+        if self.ack_filter {
+            tokens.push("ack-filter".to_string());
+        }
+        if let Some(overhead) = self.overhead {
+            tokens.push("overhead".to_string());
+            tokens.push(overhead.to_string());
+        }
+        if let Some(mpu) = self.mpu {
+            tokens.push("mpu".to_string());
+            tokens.push(mpu.to_string());
+        }
+        if let Some(atm) = self.atm {
+            tokens.push(atm.as_tc_token().to_string());
+        }
+        if let Some(rtt_ms) = self.rtt_ms {
+            tokens.push("rtt".to_string());
+            tokens.push(format!("{rtt_ms}ms"));
+        }
+        tokens
+    }
+}
+
+/// CAKE diffserv modes.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Allocative)]
+#[serde(rename_all = "lowercase")]
+#[allow(missing_docs)]
+pub enum CakeDiffserv {
+    Besteffort,
+    Diffserv3,
+    Diffserv4,
+    Diffserv8,
+    Precedence,
+}
+
+impl CakeDiffserv {
+    /// The `tc` keyword for this mode.
+    pub const fn as_tc_token(self) -> &'static str {
+        match self {
+            Self::Besteffort => "besteffort",
+            Self::Diffserv3 => "diffserv3",
+            Self::Diffserv4 => "diffserv4",
+            Self::Diffserv8 => "diffserv8",
+            Self::Precedence => "precedence",
+        }
+    }
+}
+
+/// CAKE flow isolation modes.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Allocative)]
+#[serde(rename_all = "kebab-case")]
+#[allow(missing_docs)]
+pub enum CakeFlowIsolation {
+    Flowblind,
+    Srchost,
+    Dsthost,
+    Hosts,
+    Flows,
+    DualSrchost,
+    DualDsthost,
+    TripleIsolate,
+}
+
+impl CakeFlowIsolation {
+    /// The `tc` keyword for this mode.
+    pub const fn as_tc_token(self) -> &'static str {
+        match self {
+            Self::Flowblind => "flowblind",
+            Self::Srchost => "srchost",
+            Self::Dsthost => "dsthost",
+            Self::Hosts => "hosts",
+            Self::Flows => "flows",
+            Self::DualSrchost => "dual-srchost",
+            Self::DualDsthost => "dual-dsthost",
+            Self::TripleIsolate => "triple-isolate",
+        }
+    }
+}
+
+/// CAKE link-layer cell framing.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Allocative)]
+#[serde(rename_all = "lowercase")]
+pub enum CakeAtmMode {
+    /// No cell framing.
+    Noatm,
+    /// ATM cell framing, as used by ADSL.
+    Atm,
+    /// PTM 64b/65b framing, as used by VDSL2.
+    Ptm,
+}
+
+impl CakeAtmMode {
+    /// The `tc` keyword for this mode.
+    pub const fn as_tc_token(self) -> &'static str {
+        match self {
+            Self::Noatm => "noatm",
+            Self::Atm => "atm",
+            Self::Ptm => "ptm",
+        }
+    }
 }
 
 impl Default for QueueConfigCompat {
@@ -177,6 +371,7 @@ impl Default for QueueConfigCompat {
             lazy_expire_seconds: defaults.lazy_expire_seconds,
             lazy_threshold_bytes: defaults.lazy_threshold_bytes,
             fast_queues_fq_codel: defaults.fast_queues_fq_codel,
+            sqm_profiles: defaults.sqm_profiles,
         }
     }
 }
@@ -210,6 +405,7 @@ impl<'de> Deserialize<'de> for QueueConfig {
             lazy_expire_seconds: compat.lazy_expire_seconds,
             lazy_threshold_bytes: compat.lazy_threshold_bytes,
             fast_queues_fq_codel: compat.fast_queues_fq_codel,
+            sqm_profiles: compat.sqm_profiles,
         };
         cfg.set_queue_mode(queue_mode);
         Ok(cfg)
@@ -218,7 +414,7 @@ impl<'de> Deserialize<'de> for QueueConfig {
 
 #[cfg(test)]
 mod tests {
-    use super::{QueueConfig, QueueMode};
+    use super::{CakeAtmMode, CakeFlowIsolation, QueueConfig, QueueMode};
 
     #[test]
     fn deserialize_legacy_monitor_only_maps_to_observe() {
@@ -267,4 +463,74 @@ mod tests {
             "serialized config should preserve legacy monitor_only=false for compatibility"
         );
     }
+
+    #[test]
+    fn deserialize_sqm_profiles_and_expand_tokens() {
+        let parsed: QueueConfig = toml::from_str(
+            r#"
+default_sqm = "cake diffserv4"
+
+[[sqm_profiles]]
+name = "dsl"
+diffserv = "diffserv4"
+flow_isolation = "dual-srchost"
+nat = true
+overhead = 44
+mpu = 96
+atm = "atm"
+
+[[sqm_profiles]]
+name = "fibre"
+ack_filter = true
+rtt_ms = 50
+"#,
+        )
+        .expect("sqm profiles should deserialize");
+        parsed
+            .validate_sqm_profiles()
+            .expect("profiles should validate");
+
+        let dsl = parsed.sqm_profile("DSL").expect("dsl profile");
+        assert_eq!(dsl.flow_isolation, Some(CakeFlowIsolation::DualSrchost));
+        assert_eq!(dsl.atm, Some(CakeAtmMode::Atm));
+        assert_eq!(
+            dsl.cake_tokens(),
+            vec![
+                "diffserv4",
+                "dual-srchost",
+                "nat",
+                "overhead",
+                "44",
+                "mpu",
+                "96",
+                "atm"
+            ]
+        );
+        let fibre = parsed.sqm_profile("fibre").expect("fibre profile");
+        assert_eq!(fibre.cake_tokens(), vec!["ack-filter", "rtt", "50ms"]);
+    }
+
+    #[test]
+    fn validate_sqm_profiles_rejects_bad_and_duplicate_names() {
+        let mut config = QueueConfig {
+            sqm_profiles: vec![super::SqmProfile {
+                name: "dsl/fast".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(config.validate_sqm_profiles().is_err());
+
+        config.sqm_profiles = vec![
+            super::SqmProfile {
+                name: "dsl".to_string(),
+                ..Default::default()
+            },
+            super::SqmProfile {
+                name: "DSL".to_string(),
+                ..Default::default()
+            },
+        ];
+        assert!(config.validate_sqm_profiles().is_err());
+    }
 }
@@ -0,0 +1,211 @@
+//! Remote access to the lqosd bus over TLS.
+
+use allocative::Allocative;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+fn default_listen() -> String {
+    "0.0.0.0:9124".to_string()
+}
+
+/// What a bus connection is allowed to do.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default, Allocative)]
+#[serde(rename_all = "snake_case")]
+pub enum BusAccess {
+    /// Requests that only read state.
+    #[default]
+    ReadOnly,
+    /// Every request, including reloads, configuration and shaping changes.
+    Admin,
+}
+
+impl BusAccess {
+    /// Can a connection with this access make a request that needs `required`?
+    pub fn permits(self, required: BusAccess) -> bool {
+        self == BusAccess::Admin || required == BusAccess::ReadOnly
+    }
+}
+
+/// A client allowed to use the remote bus. Identified either by a bearer
+/// token sent in the handshake, or by the SHA-256 fingerprint of its TLS
+/// client certificate.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
+pub struct RemoteBusCredential {
+    /// Name used in logs.
+    pub name: String,
+    /// What this client may do.
+    #[serde(default)]
+    pub access: BusAccess,
+    /// Bearer token, at least 16 characters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// SHA-256 fingerprint of the client certificate, in hex (colons
+    /// optional). Requires `client_ca_file`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate_sha256: Option<String>,
+}
+
+impl RemoteBusCredential {
+    /// The certificate fingerprint as bytes, if set and well-formed.
+    pub fn fingerprint(&self) -> Option<Vec<u8>> {
+        let hex: String = self
+            .certificate_sha256
+            .as_ref()?
+            .chars()
+            .filter(|c| *c != ':')
+            .collect();
+        if hex.len() != 64 || !hex.is_ascii() {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect()
+    }
+}
+
+/// Settings for lqosd's TLS bus listener.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
+pub struct RemoteBusConfig {
+    /// Whether lqosd accepts bus connections over TCP.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Address and port to listen on.
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// Server certificate chain (PEM).
+    #[serde(default)]
+    pub cert_file: String,
+    /// Server private key (PEM).
+    #[serde(default)]
+    pub key_file: String,
+    /// CA bundle (PEM) used to verify client certificates. Enables mutual
+    /// TLS; clients without a certificate can still use a token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ca_file: Option<String>,
+    /// Clients allowed to connect.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub credentials: Vec<RemoteBusCredential>,
+}
+
+impl Default for RemoteBusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: default_listen(),
+            cert_file: String::new(),
+            key_file: String::new(),
+            client_ca_file: None,
+            credentials: Vec::new(),
+        }
+    }
+}
+
+impl RemoteBusConfig {
+    /// Checks the listener settings and credentials.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if self.listen.parse::<std::net::SocketAddr>().is_err() {
+            return Err(format!(
+                "remote_bus.listen '{}' is not an address and port",
+                self.listen
+            ));
+        }
+        if self.cert_file.is_empty() || self.key_file.is_empty() {
+            return Err("remote_bus needs cert_file and key_file".to_string());
+        }
+        let mut names = HashSet::new();
+        for credential in &self.credentials {
+            let name = &credential.name;
+            if name.trim().is_empty() {
+                return Err("remote_bus credentials need a name".to_string());
+            }
+            if !names.insert(name.as_str()) {
+                return Err(format!("remote_bus credential '{name}' is listed twice"));
+            }
+            match (&credential.token, &credential.certificate_sha256) {
+                (Some(token), None) => {
+                    if token.len() < 16 {
+                        return Err(format!(
+                            "remote_bus credential '{name}' needs a token of at least 16 characters"
+                        ));
+                    }
+                }
+                (None, Some(_)) => {
+                    if credential.fingerprint().is_none() {
+                        return Err(format!(
+                            "remote_bus credential '{name}' has an invalid certificate_sha256"
+                        ));
+                    }
+                    if self.client_ca_file.is_none() {
+                        return Err(format!(
+                            "remote_bus credential '{name}' uses a certificate, which requires client_ca_file"
+                        ));
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "remote_bus credential '{name}' needs exactly one of token or certificate_sha256"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FINGERPRINT: &str = "9F:86:D0:81:88:4C:7D:65:9A:2F:EA:A0:C5:5A:D0:15:A3:BF:4F:1B:2B:0B:82:2C:D1:5D:6C:15:B0:F0:0A:08";
+
+    #[test]
+    fn parses_credentials() {
+        let cfg: RemoteBusConfig = toml::from_str(&format!(
+            r#"
+            enabled = true
+            cert_file = "/etc/lqos/bus.crt"
+            key_file = "/etc/lqos/bus.key"
+            client_ca_file = "/etc/lqos/bus-clients.crt"
+
+            [[credentials]]
+            name = "grafana"
+            token = "0123456789abcdef"
+
+            [[credentials]]
+            name = "automation"
+            access = "admin"
+            certificate_sha256 = "{FINGERPRINT}"
+            "#
+        ))
+        .expect("valid section");
+        cfg.validate().expect("valid");
+        assert_eq!(cfg.listen, "0.0.0.0:9124");
+        assert_eq!(cfg.credentials[0].access, BusAccess::ReadOnly);
+        assert_eq!(cfg.credentials[1].access, BusAccess::Admin);
+        let fingerprint = cfg.credentials[1].fingerprint().expect("fingerprint");
+        assert_eq!(fingerprint.len(), 32);
+        assert_eq!(fingerprint[0], 0x9f);
+    }
+
+    #[test]
+    fn rejects_bad_credentials() {
+        let base = "enabled = true\ncert_file = \"a\"\nkey_file = \"b\"\n";
+        for bad in [
+            "[[credentials]]\nname = \"x\"\ntoken = \"short\"".to_string(),
+            "[[credentials]]\nname = \"x\"".to_string(),
+            format!("[[credentials]]\nname = \"x\"\ncertificate_sha256 = \"{FINGERPRINT}\""),
+            "client_ca_file = \"c\"\n[[credentials]]\nname = \"x\"\ncertificate_sha256 = \"abc\""
+                .to_string(),
+            "[[credentials]]\nname = \"x\"\ntoken = \"0123456789abcdef\"\n[[credentials]]\nname = \"x\"\ntoken = \"0123456789abcdeg\"".to_string(),
+        ] {
+            let cfg: RemoteBusConfig = toml::from_str(&format!("{base}{bad}")).expect("parses");
+            assert!(cfg.validate().is_err(), "{bad}");
+        }
+        let disabled: RemoteBusConfig = toml::from_str("").expect("parses");
+        disabled.validate().expect("disabled needs nothing");
+    }
+}
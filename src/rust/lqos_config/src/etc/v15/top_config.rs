@@ -158,6 +158,34 @@ pub struct Config {
     #[serde(default)]
     pub treeguard: treeguard::TreeguardConfig,
 
+    /// Prometheus/OpenMetrics scrape endpoint.
+    #[serde(default)]
+    pub prometheus: super::prometheus::PrometheusConfig,
+
+    /// On-disk history store used when Insight is not available.
+    #[serde(default)]
+    pub local_history: super::local_history::LocalHistoryConfig,
+
+    /// ASN and GeoIP database sources.
+    #[serde(default)]
+    pub geoip: super::geoip::GeoIpConfig,
+
+    /// Per-circuit usage accounting and data quotas.
+    #[serde(default)]
+    pub usage_quotas: super::usage_quotas::UsageQuotaConfig,
+
+    /// Traffic anomaly (DDoS) detection.
+    #[serde(default)]
+    pub anomaly_detection: super::anomaly_detection::AnomalyDetectionConfig,
+
+    /// Remote bus access over TLS.
+    #[serde(default)]
+    pub remote_bus: super::remote_bus::RemoteBusConfig,
+
+    /// Outbound webhook notifications.
+    #[serde(default)]
+    pub webhooks: super::webhooks::WebhooksConfig,
+
     /// Disable ICMP Ping Monitoring for Devices in the hosts view
     pub disable_icmp_ping: Option<bool>,
 
@@ -225,10 +253,16 @@ impl Config {
         if self.queues.default_sqm.trim().is_empty() {
             return Err("default_sqm cannot be empty. Please specify a qdisc type (e.g., 'cake diffserv4' or 'fq_codel')".to_string());
         }
+        self.queues.validate_sqm_profiles()?;
         if let Some(stormguard) = &self.stormguard {
             stormguard.validate()?;
         }
         self.treeguard.validate()?;
+        self.geoip.validate()?;
+        self.usage_quotas.validate()?;
+        self.anomaly_detection.validate()?;
+        self.remote_bus.validate()?;
+        self.webhooks.validate()?;
         Ok(())
     }
 
@@ -317,6 +351,13 @@ impl Default for Config {
             webserver_listen: None,
             stormguard: None,
             treeguard: treeguard::TreeguardConfig::default(),
+            prometheus: super::prometheus::PrometheusConfig::default(),
+            local_history: super::local_history::LocalHistoryConfig::default(),
+            geoip: super::geoip::GeoIpConfig::default(),
+            usage_quotas: super::usage_quotas::UsageQuotaConfig::default(),
+            anomaly_detection: super::anomaly_detection::AnomalyDetectionConfig::default(),
+            remote_bus: super::remote_bus::RemoteBusConfig::default(),
+            webhooks: super::webhooks::WebhooksConfig::default(),
             disable_icmp_ping: Some(false),
             exclude_efficiency_cores: true,
             enable_circuit_heatmaps: true,
@@ -0,0 +1,235 @@
+//! Per-circuit data usage accounting and quota policies.
+
+use allocative::Allocative;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+fn default_anchor_day() -> u8 {
+    1
+}
+
+fn default_history_periods() -> usize {
+    12
+}
+
+/// Which traffic counts towards a quota.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default, Allocative)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaDirection {
+    /// Download and upload combined.
+    #[default]
+    Total,
+    /// Download only.
+    Download,
+    /// Upload only.
+    Upload,
+}
+
+/// A data cap and the rates a circuit is throttled to once it is exceeded.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
+pub struct QuotaPolicy {
+    /// Unique name, shown in the UI.
+    pub name: String,
+    /// Circuits this policy applies to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub circuit_ids: Vec<String>,
+    /// Applies to every circuit whose parent node is listed here.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parent_nodes: Vec<String>,
+    /// Day of the month (1-28) this policy's billing periods start on.
+    /// Defaults to `billing_anchor_day`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub billing_anchor_day: Option<u8>,
+    /// Usage allowed per billing period, in gigabytes (10^9 bytes).
+    pub cap_gigabytes: f64,
+    /// Which traffic counts towards the cap.
+    #[serde(default)]
+    pub direction: QuotaDirection,
+    /// Download ceiling applied once the cap is exceeded, in Mbps.
+    pub fallback_download_mbps: f32,
+    /// Upload ceiling applied once the cap is exceeded, in Mbps.
+    pub fallback_upload_mbps: f32,
+}
+
+impl QuotaPolicy {
+    /// True if this policy covers the circuit. A policy that lists neither
+    /// circuits nor parent nodes covers every circuit.
+    pub fn matches(&self, circuit_id: &str, parent_node: &str) -> bool {
+        if self.circuit_ids.is_empty() && self.parent_nodes.is_empty() {
+            return true;
+        }
+        self.circuit_ids.iter().any(|id| id == circuit_id)
+            || self.parent_nodes.iter().any(|node| node == parent_node)
+    }
+
+    /// The cap in bytes.
+    pub fn cap_bytes(&self) -> u64 {
+        (self.cap_gigabytes * 1_000_000_000.0) as u64
+    }
+}
+
+/// Settings for the per-circuit usage ledger kept by lqosd.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
+pub struct UsageQuotaConfig {
+    /// Whether lqosd accumulates per-circuit usage and enforces quotas.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Day of the month (1-28) billing periods start on, at local midnight.
+    #[serde(default = "default_anchor_day")]
+    pub billing_anchor_day: u8,
+    /// Completed billing periods kept per circuit.
+    #[serde(default = "default_history_periods")]
+    pub history_periods: usize,
+    /// Quota policies. The first policy matching a circuit applies.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<QuotaPolicy>,
+}
+
+impl Default for UsageQuotaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            billing_anchor_day: default_anchor_day(),
+            history_periods: default_history_periods(),
+            policies: Vec::new(),
+        }
+    }
+}
+
+fn validate_anchor_day(day: u8, field: &str) -> Result<(), String> {
+    if (1..=28).contains(&day) {
+        Ok(())
+    } else {
+        Err(format!("{field} must be between 1 and 28"))
+    }
+}
+
+impl UsageQuotaConfig {
+    /// The first policy covering a circuit.
+    pub fn policy_for(&self, circuit_id: &str, parent_node: &str) -> Option<&QuotaPolicy> {
+        self.policies
+            .iter()
+            .find(|policy| policy.matches(circuit_id, parent_node))
+    }
+
+    /// The billing anchor day used for a circuit covered by `policy`.
+    pub fn anchor_day_for(&self, policy: Option<&QuotaPolicy>) -> u8 {
+        policy
+            .and_then(|policy| policy.billing_anchor_day)
+            .unwrap_or(self.billing_anchor_day)
+    }
+
+    /// Checks anchor days, caps, fallback rates and policy names.
+    pub fn validate(&self) -> Result<(), String> {
+        validate_anchor_day(self.billing_anchor_day, "usage_quotas.billing_anchor_day")?;
+        let mut names = HashSet::new();
+        for policy in &self.policies {
+            let name = policy.name.trim();
+            if name.is_empty() {
+                return Err("usage_quotas policies must have a name".to_string());
+            }
+            if !names.insert(name) {
+                return Err(format!("usage_quotas policy '{name}' is defined twice"));
+            }
+            if let Some(day) = policy.billing_anchor_day {
+                validate_anchor_day(
+                    day,
+                    &format!("usage_quotas policy '{name}' billing_anchor_day"),
+                )?;
+            }
+            if !policy.cap_gigabytes.is_finite() || policy.cap_gigabytes <= 0.0 {
+                return Err(format!(
+                    "usage_quotas policy '{name}' cap_gigabytes must be positive"
+                ));
+            }
+            if !(policy.fallback_download_mbps > 0.0 && policy.fallback_upload_mbps > 0.0) {
+                return Err(format!(
+                    "usage_quotas policy '{name}' fallback rates must be positive"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_policy_applies() {
+        let cfg: UsageQuotaConfig = toml::from_str(
+            r#"
+            enabled = true
+            billing_anchor_day = 15
+
+            [[policies]]
+            name = "vip"
+            circuit_ids = ["c1"]
+            billing_anchor_day = 3
+            cap_gigabytes = 500
+            fallback_download_mbps = 10
+            fallback_upload_mbps = 2
+
+            [[policies]]
+            name = "tower"
+            parent_nodes = ["Tower 1"]
+            cap_gigabytes = 100
+            direction = "download"
+            fallback_download_mbps = 5
+            fallback_upload_mbps = 1
+            "#,
+        )
+        .expect("policies should parse");
+        cfg.validate().expect("policies are valid");
+
+        let vip = cfg.policy_for("c1", "Tower 1").expect("vip applies");
+        assert_eq!(vip.name, "vip");
+        assert_eq!(cfg.anchor_day_for(Some(vip)), 3);
+        let tower = cfg.policy_for("c2", "Tower 1").expect("tower applies");
+        assert_eq!(tower.direction, QuotaDirection::Download);
+        assert_eq!(tower.cap_bytes(), 100_000_000_000);
+        assert_eq!(cfg.anchor_day_for(Some(tower)), 15);
+        assert!(cfg.policy_for("c3", "Tower 2").is_none());
+    }
+
+    #[test]
+    fn validate_rejects_bad_policies() {
+        let policy = QuotaPolicy {
+            name: "p".to_string(),
+            circuit_ids: Vec::new(),
+            parent_nodes: Vec::new(),
+            billing_anchor_day: None,
+            cap_gigabytes: 10.0,
+            direction: QuotaDirection::Total,
+            fallback_download_mbps: 1.0,
+            fallback_upload_mbps: 1.0,
+        };
+        let valid = UsageQuotaConfig {
+            policies: vec![policy.clone()],
+            ..Default::default()
+        };
+        assert!(valid.validate().is_ok());
+
+        let duplicate = UsageQuotaConfig {
+            policies: vec![policy.clone(), policy.clone()],
+            ..Default::default()
+        };
+        assert!(duplicate.validate().is_err());
+
+        let bad_anchor = UsageQuotaConfig {
+            billing_anchor_day: 31,
+            ..Default::default()
+        };
+        assert!(bad_anchor.validate().is_err());
+
+        let no_cap = UsageQuotaConfig {
+            policies: vec![QuotaPolicy {
+                cap_gigabytes: 0.0,
+                ..policy
+            }],
+            ..Default::default()
+        };
+        assert!(no_cap.validate().is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Outbound webhook notifications.
+
+use allocative::Allocative;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+fn default_circuit_down_seconds() -> u64 {
+    600
+}
+
+fn default_events() -> Vec<WebhookEventKind> {
+    WebhookEventKind::ALL.to_vec()
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_timeout_seconds() -> u64 {
+    10
+}
+
+/// The body format a destination expects.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Default, Allocative)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// LibreQoS's own JSON document.
+    #[default]
+    Json,
+    /// A Slack incoming webhook message.
+    Slack,
+    /// A Microsoft Teams workflow message carrying an Adaptive Card.
+    Teams,
+}
+
+/// How serious a notification is. Ordered from least to most severe.
+#[derive(
+    Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Default, Allocative,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookSeverity {
+    /// Something happened; no action needed.
+    #[default]
+    Info,
+    /// Worth a look.
+    Warning,
+    /// Needs attention.
+    Error,
+}
+
+/// The kinds of event that can trigger a webhook.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Allocative)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    /// Urgent issues raised by lqosd.
+    Urgent,
+    /// The Bakery entering or leaving the reload-required state.
+    Bakery,
+    /// Changes made by TreeGuard.
+    Treeguard,
+    /// Changes made by StormGuard.
+    Stormguard,
+    /// Circuits going quiet and coming back.
+    Circuit,
+}
+
+impl WebhookEventKind {
+    /// Every kind of event.
+    pub const ALL: [WebhookEventKind; 5] = [
+        WebhookEventKind::Urgent,
+        WebhookEventKind::Bakery,
+        WebhookEventKind::Treeguard,
+        WebhookEventKind::Stormguard,
+        WebhookEventKind::Circuit,
+    ];
+}
+
+/// An endpoint that receives notifications.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
+pub struct WebhookDestination {
+    /// Name used in logs.
+    pub name: String,
+    /// `http://` or `https://` URL to POST to.
+    pub url: String,
+    /// Body format.
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Notifications less severe than this are not sent.
+    #[serde(default)]
+    pub min_severity: WebhookSeverity,
+    /// Event kinds to send. Defaults to all of them.
+    #[serde(default = "default_events")]
+    pub events: Vec<WebhookEventKind>,
+    /// Shared secret. When set, each request carries an HMAC-SHA256
+    /// signature of its timestamp and body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Retries (with exponential backoff) before a notification is dropped.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Per-request timeout.
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+impl WebhookDestination {
+    /// Does this destination want a notification of `kind` at `severity`?
+    pub fn accepts(&self, kind: WebhookEventKind, severity: WebhookSeverity) -> bool {
+        severity >= self.min_severity && self.events.contains(&kind)
+    }
+}
+
+/// Settings for pushing notifications to webhooks.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Allocative)]
+pub struct WebhooksConfig {
+    /// Whether lqosd sends webhook notifications.
+    #[serde(default)]
+    pub enabled: bool,
+    /// A circuit that has passed no traffic for this long is reported as
+    /// down, and as up again once traffic resumes.
+    #[serde(default = "default_circuit_down_seconds")]
+    pub circuit_down_seconds: u64,
+    /// Where to send notifications.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub destinations: Vec<WebhookDestination>,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            circuit_down_seconds: default_circuit_down_seconds(),
+            destinations: Vec::new(),
+        }
+    }
+}
+
+impl WebhooksConfig {
+    /// Checks the destinations.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if self.circuit_down_seconds < 60 {
+            return Err("webhooks.circuit_down_seconds must be at least 60".to_string());
+        }
+        let mut names = HashSet::new();
+        for destination in &self.destinations {
+            let name = &destination.name;
+            if name.trim().is_empty() {
+                return Err("webhook destinations need a name".to_string());
+            }
+            if !names.insert(name.as_str()) {
+                return Err(format!("webhook destination '{name}' is listed twice"));
+            }
+            if !(destination.url.starts_with("http://") || destination.url.starts_with("https://"))
+            {
+                return Err(format!(
+                    "webhook destination '{name}' needs an http:// or https:// url"
+                ));
+            }
+            if destination.events.is_empty() {
+                return Err(format!(
+                    "webhook destination '{name}' doesn't list any events"
+                ));
+            }
+            if destination
+                .secret
+                .as_ref()
+                .is_some_and(|secret| secret.is_empty())
+            {
+                return Err(format!("webhook destination '{name}' has an empty secret"));
+            }
+            if !(1..=60).contains(&destination.timeout_seconds) {
+                return Err(format!(
+                    "webhook destination '{name}' needs a timeout_seconds between 1 and 60"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_destinations() {
+        let cfg: WebhooksConfig = toml::from_str(
+            r#"
+            enabled = true
+
+            [[destinations]]
+            name = "noc"
+            url = "https://hooks.example.com/libreqos"
+            secret = "shared-secret"
+
+            [[destinations]]
+            name = "slack"
+            url = "https://hooks.slack.com/services/T/B/X"
+            format = "slack"
+            min_severity = "warning"
+            events = ["urgent", "circuit"]
+            "#,
+        )
+        .expect("valid section");
+        cfg.validate().expect("valid");
+        assert_eq!(cfg.circuit_down_seconds, 600);
+        let noc = &cfg.destinations[0];
+        assert_eq!(noc.format, WebhookFormat::Json);
+        assert_eq!(noc.events, WebhookEventKind::ALL.to_vec());
+        assert_eq!(noc.max_retries, 3);
+        assert!(noc.accepts(WebhookEventKind::Stormguard, WebhookSeverity::Info));
+        let slack = &cfg.destinations[1];
+        assert_eq!(slack.format, WebhookFormat::Slack);
+        assert!(slack.accepts(WebhookEventKind::Circuit, WebhookSeverity::Error));
+        assert!(!slack.accepts(WebhookEventKind::Circuit, WebhookSeverity::Info));
+        assert!(!slack.accepts(WebhookEventKind::Bakery, WebhookSeverity::Error));
+    }
+
+    #[test]
+    fn rejects_bad_destinations() {
+        let base = "enabled = true\n";
+        for bad in [
+            "circuit_down_seconds = 10",
+            "[[destinations]]\nname = \"\"\nurl = \"https://a\"",
+            "[[destinations]]\nname = \"x\"\nurl = \"ftp://a\"",
+            "[[destinations]]\nname = \"x\"\nurl = \"https://a\"\nevents = []",
+            "[[destinations]]\nname = \"x\"\nurl = \"https://a\"\nsecret = \"\"",
+            "[[destinations]]\nname = \"x\"\nurl = \"https://a\"\ntimeout_seconds = 0",
+            "[[destinations]]\nname = \"x\"\nurl = \"https://a\"\n[[destinations]]\nname = \"x\"\nurl = \"https://b\"",
+        ] {
+            let cfg: WebhooksConfig = toml::from_str(&format!("{base}{bad}")).expect("parses");
+            assert!(cfg.validate().is_err(), "{bad}");
+        }
+        let disabled: WebhooksConfig = toml::from_str("").expect("parses");
+        disabled.validate().expect("disabled needs nothing");
+    }
+}
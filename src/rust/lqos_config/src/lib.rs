@@ -7,6 +7,7 @@
 
 #![deny(clippy::unwrap_used)]
 #![warn(missing_docs)]
+pub mod audit_log;
 pub mod authentication;
 mod circuit_ethernet_metadata;
 mod cpu_topology;
@@ -17,7 +18,11 @@ mod program_control;
 mod qoo_profiles;
 mod shaped_devices;
 
-pub use authentication::{AuthenticatedUser, UserRole, WebUser, WebUsers};
+pub use audit_log::{
+    AuditActor, AuditChange, AuditEntry, AuditQuery, append_audit_entry, queue_audit_entry,
+    read_audit_log,
+};
+pub use authentication::{ApiToken, ApiTokenScope, AuthenticatedUser, UserRole, WebUser, WebUsers};
 pub use circuit_ethernet_metadata::{
     CIRCUIT_ETHERNET_METADATA_FILENAME, CircuitEthernetMetadata, CircuitEthernetMetadataFile,
 };
@@ -25,11 +30,15 @@ pub use cpu_topology::{
     CpuListParseError, ShapingCpuDetection, ShapingCpuSource, detect_shaping_cpus,
 };
 pub use etc::{
-    BridgeConfig, Config, LazyQueueMode, QueueMode, RttThresholds, SingleInterfaceConfig,
-    StormguardConfig, StormguardStrategy, TreeguardCircuitsConfig, TreeguardConfig,
-    TreeguardCpuConfig, TreeguardCpuMode, TreeguardLinksConfig, TreeguardQooConfig, Tunables,
-    clear_cached_config, disable_xdp_bridge, enable_long_term_stats, load_config,
-    treeguard_cpu_mode_migration_notice, update_config,
+    AnomalyDetectionConfig, BridgeConfig, BusAccess, CakeAtmMode, CakeDiffserv, CakeFlowIsolation,
+    Config, FlowConfig, FlowExportTarget, GeoIpConfig, InfluxDbConfig, LazyQueueMode,
+    LocalHistoryConfig, PrometheusConfig, QueueMode, QuotaDirection, QuotaPolicy, RemoteBusConfig,
+    RemoteBusCredential, RttThresholds, SingleInterfaceConfig, SqmProfile, StormguardConfig,
+    StormguardStrategy, TreeguardCircuitsConfig, TreeguardConfig, TreeguardCpuConfig,
+    TreeguardCpuMode, TreeguardLinksConfig, TreeguardQooConfig, Tunables, UsageQuotaConfig,
+    WebhookDestination, WebhookEventKind, WebhookFormat, WebhookSeverity, WebhooksConfig,
+    clear_cached_config, disable_xdp_bridge, enable_long_term_stats, is_valid_sqm_profile_name,
+    load_config, treeguard_cpu_mode_migration_notice, update_config,
 };
 pub use network_json::{NetworkJson, NetworkJsonNode, NetworkJsonTransport};
 pub use planner::{
@@ -45,7 +54,7 @@ pub use qoo_profiles::{
     DEFAULT_QOO_PROFILE_ID, QooProfileInfo, QooProfilesError, active_qoo_profile,
     list_qoo_profiles, load_qoo_profiles_file,
 };
-pub use shaped_devices::{ConfigShapedDevices, ShapedDevice};
+pub use shaped_devices::{ConfigShapedDevices, ShapedDevice, is_valid_sqm_token};
 
 /// Used as a constant in determining buffer preallocation
 pub const SUPPORTED_CUSTOMERS: usize = 100_000;
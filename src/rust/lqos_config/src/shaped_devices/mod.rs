@@ -4,7 +4,7 @@ mod shaped_device;
 use csv::{QuoteStyle, ReaderBuilder, WriterBuilder};
 use lqos_utils::XdpIpAddress;
 use serializable::SerializableShapedDevice;
-pub use shaped_device::ShapedDevice;
+pub use shaped_device::{ShapedDevice, is_valid_sqm_token};
 use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
@@ -6,6 +6,19 @@ use serde::{Deserialize, Serialize};
 use std::net::{Ipv4Addr, Ipv6Addr};
 use tracing::error;
 
+/// Returns `true` if `token` is a single-direction SQM override: a queue
+/// kind, `none`, or `cake:<profile>` naming a configured SQM profile.
+///
+/// Profile existence is checked when queues are built, since the profile list
+/// lives in the main config rather than `ShapedDevices.csv`.
+pub fn is_valid_sqm_token(token: &str) -> bool {
+    match token.split_once(':') {
+        Some(("cake", profile)) => crate::is_valid_sqm_profile_name(profile),
+        Some(_) => false,
+        None => matches!(token, "cake" | "fq_codel" | "fq_pie" | "sfq" | "none"),
+    }
+}
+
 /// Represents a row in the `ShapedDevices.csv` file.
 #[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq, Allocative)]
 pub struct ShapedDevice {
@@ -57,10 +70,10 @@ pub struct ShapedDevice {
     /// Generic comments field, does nothing.
     pub comment: String,
 
-    /// Optional per-circuit SQM override token. Accepts "cake", "fq_codel",
-    /// "none", or directional "down_sqm/up_sqm" values like "cake/none" or
-    /// "/fq_codel". A single token applies to both directions; empty means
-    /// "use global default".
+    /// Optional per-circuit SQM override token. Accepts "cake", "cake:<profile>",
+    /// "fq_codel", "fq_pie", "sfq", "none", or directional "down_sqm/up_sqm"
+    /// values like "cake/none" or "/fq_codel". A single token applies to both
+    /// directions; empty means "use global default".
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sqm_override: Option<String>,
 
@@ -97,9 +110,9 @@ impl ShapedDevice {
     /// 11. Download Max Mbps
     /// 12. Upload Max Mbps
     /// 13. Comment
-    /// 14. sqm (optional; allowed values: "cake", "fq_codel", "none", or
-    ///     a directional override in the form "down_sqm/up_sqm". Either side
-    ///     may be empty to indicate no override for that direction, e.g.
+    /// 14. sqm (optional; allowed values: "cake", "cake:<profile>", "fq_codel",
+    ///     "fq_pie", "sfq", "none", or a directional override in the form "down_sqm/up_sqm".
+    ///     Either side may be empty to indicate no override for that direction, e.g.
     ///     "cake/" or "/fq_codel".)
     ///
     /// # Arguments
@@ -195,11 +208,10 @@ impl ShapedDevice {
                     let up = parts.next().unwrap_or("").trim();
 
                     // Validate each side if present
-                    let valid =
-                        |s: &str| -> bool { matches!(s, "" | "cake" | "fq_codel" | "none") };
+                    let valid = |s: &str| s.is_empty() || is_valid_sqm_token(s);
                     if !valid(down) || !valid(up) {
                         return Err(ShapedDevicesError::CsvEntryParseError(format!(
-                            "Invalid directional sqm override '{token}'. Allowed: 'cake', 'fq_codel', 'none', or 'down_sqm/up_sqm' (e.g. 'cake/fq_codel', '/none')"
+                            "Invalid directional sqm override '{token}'. Allowed: 'cake', 'cake:<profile>', 'fq_codel', 'fq_pie', 'sfq', 'none', or 'down_sqm/up_sqm' (e.g. 'cake/fq_codel', '/none')"
                         )));
                     }
 
@@ -207,14 +219,12 @@ impl ShapedDevice {
                     device.sqm_override = Some(format!("{down}/{up}"));
                 } else {
                     // Single token applies to both directions when used
-                    match token.as_str() {
-                        "cake" | "fq_codel" | "none" => device.sqm_override = Some(token),
-                        other => {
-                            return Err(ShapedDevicesError::CsvEntryParseError(format!(
-                                "Invalid sqm override '{other}'. Allowed values: 'cake', 'fq_codel', 'none', or 'down_sqm/up_sqm' (e.g. 'cake/fq_codel', '/none')"
-                            )));
-                        }
+                    if !is_valid_sqm_token(&token) {
+                        return Err(ShapedDevicesError::CsvEntryParseError(format!(
+                            "Invalid sqm override '{token}'. Allowed values: 'cake', 'cake:<profile>', 'fq_codel', 'fq_pie', 'sfq', 'none', or 'down_sqm/up_sqm' (e.g. 'cake/fq_codel', '/none')"
+                        )));
                     }
+                    device.sqm_override = Some(token);
                 }
             }
         }
@@ -426,4 +436,42 @@ mod tests {
         let result = ShapedDevice::from_csv(&record);
         assert!(result.is_err(), "Should reject invalid rate strings");
     }
+
+    #[test]
+    fn test_sqm_override_accepts_fq_pie_and_sfq() {
+        let record_with_sqm = |sqm: &str| {
+            StringRecord::from(vec![
+                "test5",
+                "Test Circuit 5",
+                "device5",
+                "Test Device 5",
+                "site5",
+                "00:00:00:00:00:05",
+                "192.168.1.5",
+                "",
+                "1.0",
+                "1.0",
+                "10.0",
+                "10.0",
+                "SQM override",
+                sqm,
+            ])
+        };
+
+        let device = ShapedDevice::from_csv(&record_with_sqm("FQ_PIE"))
+            .expect("Should accept fq_pie override");
+        assert_eq!(device.sqm_override.as_deref(), Some("fq_pie"));
+
+        let device = ShapedDevice::from_csv(&record_with_sqm("sfq/ fq_pie"))
+            .expect("Should accept directional sfq/fq_pie override");
+        assert_eq!(device.sqm_override.as_deref(), Some("sfq/fq_pie"));
+
+        assert!(ShapedDevice::from_csv(&record_with_sqm("pie")).is_err());
+
+        let device = ShapedDevice::from_csv(&record_with_sqm("cake:DSL/cake:fibre"))
+            .expect("Should accept profile overrides");
+        assert_eq!(device.sqm_override.as_deref(), Some("cake:dsl/cake:fibre"));
+        assert!(ShapedDevice::from_csv(&record_with_sqm("cake:")).is_err());
+        assert!(ShapedDevice::from_csv(&record_with_sqm("fq_codel:dsl")).is_err());
+    }
 }
@@ -0,0 +1,184 @@
+//! Writes Heimdall packet captures as annotated pcapng files.
+//!
+//! Packets are placed on the interface they arrived on: traffic sourced by a
+//! watched (customer) address arrives on the ISP-facing interface, the rest
+//! on the Internet-facing interface. Each packet carries a comment with its
+//! direction, DSCP/ECN marking and the CAKE tin it would be sorted into.
+
+use crate::{
+    pcapng::{EPB_FLAG_INBOUND, PcapNgWriter},
+    perf_interface::{HeimdallEvent, PACKET_OCTET_SIZE},
+};
+use lqos_bus::tos_parser;
+use lqos_utils::{XdpIpAddress, unix_time::time_since_boot};
+use std::{
+    fs::File,
+    io::BufWriter,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Writes `packets` to `filename`. `targets` are the watched addresses,
+/// used to work out each packet's direction. `comments` are attached to
+/// the capture as a whole.
+pub(crate) fn write_capture(
+    filename: &str,
+    mut packets: Vec<HeimdallEvent>,
+    targets: &[XdpIpAddress],
+    comments: &[String],
+) -> anyhow::Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let since_boot = Duration::from(time_since_boot()?);
+    let boot_epoch_nanos = now.saturating_sub(since_boot).as_nanos() as u64;
+
+    let (isp_interface, internet_interface, sqm) = match lqos_config::load_config() {
+        Ok(config) => (
+            config.isp_interface(),
+            config.internet_interface(),
+            config.queues.default_sqm.clone(),
+        ),
+        Err(_) => ("isp".to_string(), "internet".to_string(), String::new()),
+    };
+
+    let file = BufWriter::new(File::create(filename)?);
+    let mut writer = PcapNgWriter::new(file, comments)?;
+    let snap_len = PACKET_OCTET_SIZE as u32;
+    let isp = writer.add_interface(
+        &isp_interface,
+        "ISP-facing (customer side)",
+        snap_len,
+        &["Upload traffic from customers arrives here".to_string()],
+    )?;
+    let internet = writer.add_interface(
+        &internet_interface,
+        "Internet-facing",
+        snap_len,
+        &["Download traffic towards customers arrives here".to_string()],
+    )?;
+
+    packets.sort_by_key(|packet| packet.timestamp);
+    for packet in packets.iter() {
+        let upload = targets.contains(&packet.src);
+        let interface = if upload { isp } else { internet };
+        let captured = usize::min(packet.size as usize, PACKET_OCTET_SIZE);
+        writer.write_packet(
+            interface,
+            boot_epoch_nanos + packet.timestamp,
+            &packet.packet_data[..captured],
+            packet.size,
+            EPB_FLAG_INBOUND,
+            &packet_comment(upload, packet.tos, &sqm),
+        )?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn packet_comment(upload: bool, tos: u8, sqm: &str) -> String {
+    let (dscp, ecn) = tos_parser(tos);
+    let direction = if upload {
+        "Upload (from customer)"
+    } else {
+        "Download (to customer)"
+    };
+    let mut comment = format!(
+        "Direction: {direction}; DSCP: {} ({dscp}); ECN: {}",
+        dscp_name(dscp),
+        ecn_name(ecn)
+    );
+    if let Some(tin) = cake_tin(dscp, sqm) {
+        comment.push_str("; CAKE tin: ");
+        comment.push_str(tin);
+    }
+    comment
+}
+
+fn dscp_name(dscp: u8) -> String {
+    match dscp {
+        0 => "CS0".to_string(),
+        1 => "LE".to_string(),
+        44 => "VA".to_string(),
+        46 => "EF".to_string(),
+        d if d.is_multiple_of(8) => format!("CS{}", d / 8),
+        d if (10..=38).contains(&d) && d.is_multiple_of(2) => {
+            format!("AF{}{}", d / 8, (d % 8) / 2)
+        }
+        _ => "Unassigned".to_string(),
+    }
+}
+
+fn ecn_name(ecn: u8) -> &'static str {
+    match ecn {
+        0b01 => "ECT(1)",
+        0b10 => "ECT(0)",
+        0b11 => "CE",
+        _ => "Not-ECT",
+    }
+}
+
+/// CAKE's DSCP to tin tables (`sch_cake.c`), as tin names.
+fn cake_tin(dscp: u8, sqm: &str) -> Option<&'static str> {
+    const DIFFSERV4: [u8; 64] = [
+        0, 1, 0, 0, 2, 0, 2, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 2, 0, 2, 0, 2, 0, 2, 0, 2, 0,
+        2, 0, 3, 0, 2, 0, 2, 0, 2, 0, 3, 0, 0, 0, 3, 0, 3, 0, 3, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0,
+        0, 0, 0, 0,
+    ];
+    const DIFFSERV3: [u8; 64] = [
+        0, 1, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 2, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0,
+        0, 0, 0, 0,
+    ];
+    let index = usize::from(dscp & 0x3F);
+    if !sqm.contains("cake") {
+        return None;
+    }
+    if sqm.contains("besteffort") {
+        Some("Best Effort")
+    } else if sqm.contains("diffserv4") {
+        Some(["Best Effort", "Bulk", "Video", "Voice"][usize::from(DIFFSERV4[index])])
+    } else if sqm.contains("diffserv8") || sqm.contains("precedence") {
+        None
+    } else {
+        // diffserv3 is CAKE's default
+        Some(["Best Effort", "Bulk", "Voice"][usize::from(DIFFSERV3[index])])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_dscp_and_ecn_codepoints() {
+        assert_eq!(dscp_name(0), "CS0");
+        assert_eq!(dscp_name(8), "CS1");
+        assert_eq!(dscp_name(34), "AF41");
+        assert_eq!(dscp_name(14), "AF13");
+        assert_eq!(dscp_name(46), "EF");
+        assert_eq!(dscp_name(3), "Unassigned");
+        assert_eq!(ecn_name(0b10), "ECT(0)");
+        assert_eq!(ecn_name(0b11), "CE");
+    }
+
+    #[test]
+    fn maps_dscp_to_cake_tins() {
+        let sqm = "cake diffserv4";
+        assert_eq!(cake_tin(0, sqm), Some("Best Effort"));
+        assert_eq!(cake_tin(8, sqm), Some("Bulk"));
+        assert_eq!(cake_tin(34, sqm), Some("Video"));
+        assert_eq!(cake_tin(46, sqm), Some("Voice"));
+        assert_eq!(cake_tin(46, "cake"), Some("Voice"));
+        assert_eq!(cake_tin(34, "cake"), Some("Best Effort"));
+        assert_eq!(cake_tin(46, "cake besteffort"), Some("Best Effort"));
+        assert_eq!(cake_tin(46, "fq_codel"), None);
+    }
+
+    #[test]
+    fn comments_carry_direction_and_marking() {
+        // DSCP EF (46) with ECT(0)
+        let comment = packet_comment(true, (46 << 2) | 0b10, "cake diffserv4");
+        assert_eq!(
+            comment,
+            "Direction: Upload (from customer); DSCP: EF (46); ECN: ECT(0); CAKE tin: Voice"
+        );
+    }
+}
@@ -15,11 +15,18 @@ use timerfd::{SetTimeFlags, TimerFd, TimerState};
 use tracing::{debug, error, warn};
 mod timeline;
 pub use timeline::{hyperfocus_on_target, n_second_packet_dump, n_second_pcap};
-mod pcap;
+mod capture_file;
+mod pcapng;
+mod ring;
 mod watchlist;
 use anyhow::Result;
+pub use ring::{
+    RingCaptureInfo, RingCaptureTarget, freeze_ring_capture, ring_captures, start_ring_capture,
+    stop_ring_capture,
+};
 pub use watchlist::{heimdall_expire, heimdall_watch_ip, set_heimdall_mode};
 
+use crate::ring::maintain_ring_captures;
 use crate::timeline::expire_timeline;
 
 /// How long should Heimdall keep watching a flow after being requested
@@ -34,6 +41,17 @@ const TIMELINE_EXPIRE_SECS: u64 = 10;
 /// How long should an analysis session remain in memory?
 const SESSION_EXPIRE_SECONDS: u64 = 600;
 
+/// How many rolling captures may run at once? Each one keeps Heimdall in
+/// analysis mode and holds up to `RING_CAPTURE_MAX_PACKETS` in memory.
+const MAX_RING_CAPTURES: usize = 4;
+
+/// Upper bound on the packets retained by one rolling capture (roughly
+/// 200 bytes each).
+const RING_CAPTURE_MAX_PACKETS: usize = 250_000;
+
+/// Longest history a rolling capture may keep.
+const RING_CAPTURE_MAX_MINUTES: u64 = 60;
+
 /// Interface to running Heimdall (start this when lqosd starts)
 pub fn start_heimdall() -> Result<()> {
     if set_heimdall_mode(HeimdallMode::WatchOnly).is_err() {
@@ -65,6 +83,7 @@ pub fn start_heimdall() -> Result<()> {
             loop {
                 heimdall_expire();
                 expire_timeline();
+                maintain_ring_captures();
 
                 let missed_ticks = tfd.read();
                 if missed_ticks > 1 {
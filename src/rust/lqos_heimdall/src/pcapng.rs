@@ -0,0 +1,203 @@
+//! Minimal pcapng writer: one section header, interface descriptions and
+//! enhanced packet blocks. See the IETF pcapng draft
+//! (draft-ietf-opsawg-pcapng) for the block layouts.
+
+use std::io::{self, Write};
+
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+/// Ethernet
+const LINK_TYPE_ETHERNET: u16 = 1;
+
+const OPT_END_OF_OPTIONS: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const OPT_SHB_USER_APPLICATION: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_DESCRIPTION: u16 = 3;
+const OPT_IF_TSRESOL: u16 = 9;
+const OPT_EPB_FLAGS: u16 = 2;
+
+/// `epb_flags` direction bits: the packet was received on its interface.
+pub(crate) const EPB_FLAG_INBOUND: u32 = 0b01;
+
+pub(crate) struct PcapNgWriter<W: Write> {
+    out: W,
+    interfaces: u32,
+}
+
+impl<W: Write> PcapNgWriter<W> {
+    /// Starts a new capture file, attaching `comments` to the section header.
+    pub(crate) fn new(mut out: W, comments: &[String]) -> io::Result<Self> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_ne_bytes());
+        body.extend_from_slice(&1u16.to_ne_bytes()); // Major version
+        body.extend_from_slice(&0u16.to_ne_bytes()); // Minor version
+        body.extend_from_slice(&(-1i64).to_ne_bytes()); // Section length unknown
+        push_option(&mut body, OPT_SHB_USER_APPLICATION, b"LibreQoS Heimdall");
+        for comment in comments {
+            push_option(&mut body, OPT_COMMENT, comment.as_bytes());
+        }
+        end_options(&mut body);
+        write_block(&mut out, BLOCK_SECTION_HEADER, &body)?;
+        Ok(Self { out, interfaces: 0 })
+    }
+
+    /// Describes a capture interface, with nanosecond timestamp resolution.
+    /// Returns the interface id to use when writing packets seen on it.
+    pub(crate) fn add_interface(
+        &mut self,
+        name: &str,
+        description: &str,
+        snap_len: u32,
+        comments: &[String],
+    ) -> io::Result<u32> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&LINK_TYPE_ETHERNET.to_ne_bytes());
+        body.extend_from_slice(&0u16.to_ne_bytes()); // Reserved
+        body.extend_from_slice(&snap_len.to_ne_bytes());
+        push_option(&mut body, OPT_IF_NAME, name.as_bytes());
+        push_option(&mut body, OPT_IF_DESCRIPTION, description.as_bytes());
+        push_option(&mut body, OPT_IF_TSRESOL, &[9]); // 10^-9 seconds
+        for comment in comments {
+            push_option(&mut body, OPT_COMMENT, comment.as_bytes());
+        }
+        end_options(&mut body);
+        write_block(&mut self.out, BLOCK_INTERFACE_DESCRIPTION, &body)?;
+        let id = self.interfaces;
+        self.interfaces += 1;
+        Ok(id)
+    }
+
+    /// Writes one packet. `timestamp_nanos` is nanoseconds since the UNIX
+    /// epoch. `flags` is the `epb_flags` word, and `comment` is shown
+    /// alongside the packet in Wireshark.
+    pub(crate) fn write_packet(
+        &mut self,
+        interface_id: u32,
+        timestamp_nanos: u64,
+        data: &[u8],
+        original_len: u32,
+        flags: u32,
+        comment: &str,
+    ) -> io::Result<()> {
+        let mut body = Vec::with_capacity(20 + data.len() + comment.len() + 20);
+        body.extend_from_slice(&interface_id.to_ne_bytes());
+        body.extend_from_slice(&((timestamp_nanos >> 32) as u32).to_ne_bytes());
+        body.extend_from_slice(&(timestamp_nanos as u32).to_ne_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+        body.extend_from_slice(&original_len.to_ne_bytes());
+        body.extend_from_slice(data);
+        pad_to_32_bits(&mut body);
+        push_option(&mut body, OPT_EPB_FLAGS, &flags.to_ne_bytes());
+        if !comment.is_empty() {
+            push_option(&mut body, OPT_COMMENT, comment.as_bytes());
+        }
+        end_options(&mut body);
+        write_block(&mut self.out, BLOCK_ENHANCED_PACKET, &body)
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn write_block<W: Write>(out: &mut W, block_type: u32, body: &[u8]) -> io::Result<()> {
+    let total_len = (body.len() + 12) as u32;
+    out.write_all(&block_type.to_ne_bytes())?;
+    out.write_all(&total_len.to_ne_bytes())?;
+    out.write_all(body)?;
+    out.write_all(&total_len.to_ne_bytes())
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    let len = value.len().min(u16::MAX as usize - 3);
+    body.extend_from_slice(&code.to_ne_bytes());
+    body.extend_from_slice(&(len as u16).to_ne_bytes());
+    body.extend_from_slice(&value[..len]);
+    pad_to_32_bits(body);
+}
+
+fn end_options(body: &mut Vec<u8>) {
+    body.extend_from_slice(&OPT_END_OF_OPTIONS.to_ne_bytes());
+    body.extend_from_slice(&0u16.to_ne_bytes());
+}
+
+fn pad_to_32_bits(body: &mut Vec<u8>) {
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_ne_bytes(
+            bytes[offset..offset + 4]
+                .try_into()
+                .expect("four bytes available"),
+        )
+    }
+
+    /// Walks the blocks in a capture, returning (type, offset, length).
+    fn blocks(bytes: &[u8]) -> Vec<(u32, usize, usize)> {
+        let mut result = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let block_type = u32_at(bytes, offset);
+            let len = u32_at(bytes, offset + 4) as usize;
+            assert_eq!(len % 4, 0, "blocks are 32-bit aligned");
+            assert_eq!(u32_at(bytes, offset + len - 4) as usize, len);
+            result.push((block_type, offset, len));
+            offset += len;
+        }
+        result
+    }
+
+    #[test]
+    fn writes_well_formed_blocks() {
+        let mut writer = PcapNgWriter::new(Vec::new(), &["Circuit: Test (42)".to_string()])
+            .expect("write to memory");
+        let id = writer
+            .add_interface("eth1", "ISP-facing", 128, &[])
+            .expect("write to memory");
+        assert_eq!(id, 0);
+        writer
+            .write_packet(
+                id,
+                1_700_000_000_123_456_789,
+                &[0xAA; 61],
+                1500,
+                EPB_FLAG_INBOUND,
+                "Upload",
+            )
+            .expect("write to memory");
+        let bytes = writer.finish().expect("flush memory");
+
+        let blocks = blocks(&bytes);
+        let types: Vec<u32> = blocks.iter().map(|b| b.0).collect();
+        assert_eq!(
+            types,
+            vec![
+                BLOCK_SECTION_HEADER,
+                BLOCK_INTERFACE_DESCRIPTION,
+                BLOCK_ENHANCED_PACKET
+            ]
+        );
+        assert_eq!(u32_at(&bytes, 8), BYTE_ORDER_MAGIC);
+
+        let (_, epb, len) = blocks[2];
+        // Header (28) + 61 bytes of data padded to 64 + flags option (8) +
+        // comment option (4 + 8) + end of options (4) + trailing length.
+        assert_eq!(len, 28 + 64 + 8 + 12 + 4 + 4);
+        let timestamp =
+            (u64::from(u32_at(&bytes, epb + 12)) << 32) | u64::from(u32_at(&bytes, epb + 16));
+        assert_eq!(timestamp, 1_700_000_000_123_456_789);
+        assert_eq!(u32_at(&bytes, epb + 20), 61);
+        assert_eq!(u32_at(&bytes, epb + 24), 1500);
+        assert_eq!(u32_at(&bytes, epb + 28 + 64 + 4), EPB_FLAG_INBOUND);
+        assert_eq!(&bytes[epb + 28 + 64 + 12..epb + 28 + 64 + 18], b"Upload");
+    }
+}
@@ -0,0 +1,287 @@
+//! Continuous rolling captures. A ring capture keeps Heimdall watching a set
+//! of addresses (typically every device on a circuit) and retains the last
+//! few minutes of packets, so an intermittent problem can be "frozen" and
+//! downloaded after the customer reports it.
+//!
+//! While any ring capture is running Heimdall stays in analysis mode, which
+//! costs CPU on busy shapers.
+
+use crate::{
+    HeimdallMode, MAX_RING_CAPTURES, RING_CAPTURE_MAX_MINUTES, RING_CAPTURE_MAX_PACKETS,
+    capture_file::write_capture, heimdall_watch_ip, perf_interface::HeimdallEvent,
+    set_heimdall_mode, timeline::hyperfocus_active,
+};
+use dashmap::DashMap;
+use lqos_utils::{XdpIpAddress, unix_time::time_since_boot};
+use once_cell::sync::Lazy;
+use std::{
+    collections::VecDeque,
+    fs::remove_file,
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+/// What a ring capture should watch.
+#[derive(Clone, Debug, Default)]
+pub struct RingCaptureTarget {
+    /// Addresses to capture. A packet is kept if either its source or
+    /// destination is in this list.
+    pub ips: Vec<XdpIpAddress>,
+    /// Circuit the addresses belong to, if known.
+    pub circuit_id: Option<String>,
+    /// Circuit display name, if known.
+    pub circuit_name: Option<String>,
+}
+
+/// Summary of a running ring capture.
+#[derive(Clone, Debug)]
+pub struct RingCaptureInfo {
+    /// Ring capture id
+    pub id: usize,
+    /// Circuit the capture is attached to, if any.
+    pub circuit_id: Option<String>,
+    /// Circuit display name, if known.
+    pub circuit_name: Option<String>,
+    /// Watched addresses
+    pub ips: Vec<String>,
+    /// How much history is retained
+    pub window_seconds: u64,
+    /// Packets currently held in the ring
+    pub packets: usize,
+    /// When the capture started (UNIX seconds)
+    pub started_unix: u64,
+}
+
+struct RingCapture {
+    target: RingCaptureTarget,
+    window: Duration,
+    started_unix: u64,
+    packets: VecDeque<HeimdallEvent>,
+    dump_filename: Option<String>,
+}
+
+impl RingCapture {
+    fn matches(&self, event: &HeimdallEvent) -> bool {
+        self.target
+            .ips
+            .iter()
+            .any(|ip| *ip == event.src || *ip == event.dst)
+    }
+
+    fn push(&mut self, event: HeimdallEvent) {
+        if self.packets.len() >= RING_CAPTURE_MAX_PACKETS {
+            self.packets.pop_front();
+        }
+        self.packets.push_back(event);
+    }
+
+    fn expire(&mut self, now_nanos: u64) {
+        let oldest = now_nanos.saturating_sub(self.window.as_nanos() as u64);
+        while self
+            .packets
+            .front()
+            .is_some_and(|packet| packet.timestamp < oldest)
+        {
+            self.packets.pop_front();
+        }
+    }
+
+    fn info(&self, id: usize) -> RingCaptureInfo {
+        RingCaptureInfo {
+            id,
+            circuit_id: self.target.circuit_id.clone(),
+            circuit_name: self.target.circuit_name.clone(),
+            ips: self
+                .target
+                .ips
+                .iter()
+                .map(|ip| ip.as_ip().to_string())
+                .collect(),
+            window_seconds: self.window.as_secs(),
+            packets: self.packets.len(),
+            started_unix: self.started_unix,
+        }
+    }
+}
+
+impl Drop for RingCapture {
+    fn drop(&mut self) {
+        if let Some(df) = &self.dump_filename {
+            let path = Path::new(df);
+            if path.exists() {
+                let _ = remove_file(path);
+            }
+        }
+    }
+}
+
+static RING_CAPTURE_ID: AtomicUsize = AtomicUsize::new(0);
+static RING_CAPTURES: Lazy<DashMap<usize, RingCapture>> = Lazy::new(DashMap::new);
+/// Held while a capture is checked against `MAX_RING_CAPTURES` and added,
+/// so concurrent starts can't both pass the check.
+static RING_CAPTURE_START: Mutex<()> = Mutex::new(());
+
+/// Starts a rolling capture of `target`, keeping the last `window` of
+/// packets (capped at `RING_CAPTURE_MAX_MINUTES`).
+///
+/// ## Returns
+/// * The ring capture id, or an error if there is nothing to watch or too
+///   many rings are already running.
+pub fn start_ring_capture(target: RingCaptureTarget, window: Duration) -> anyhow::Result<usize> {
+    if target.ips.is_empty() {
+        anyhow::bail!("A ring capture needs at least one IP address");
+    }
+    let _starting = RING_CAPTURE_START
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if RING_CAPTURES.len() >= MAX_RING_CAPTURES {
+        anyhow::bail!("Only {MAX_RING_CAPTURES} ring captures may run at once");
+    }
+    let window = window.clamp(
+        Duration::from_secs(60),
+        Duration::from_secs(RING_CAPTURE_MAX_MINUTES * 60),
+    );
+    let started_unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    for ip in target.ips.iter() {
+        heimdall_watch_ip(*ip);
+    }
+    set_heimdall_mode(HeimdallMode::Analysis)?;
+
+    let id = RING_CAPTURE_ID.fetch_add(1, Ordering::Relaxed);
+    info!(
+        "Heimdall ring capture {id} started for {} address(es), {}s window",
+        target.ips.len(),
+        window.as_secs()
+    );
+    RING_CAPTURES.insert(
+        id,
+        RingCapture {
+            target,
+            window,
+            started_unix,
+            packets: VecDeque::new(),
+            dump_filename: None,
+        },
+    );
+    Ok(id)
+}
+
+/// Stops a ring capture and discards its packets.
+///
+/// ## Returns
+/// * `true` if the capture existed.
+pub fn stop_ring_capture(id: usize) -> bool {
+    let removed = RING_CAPTURES.remove(&id).is_some();
+    if removed {
+        info!("Heimdall ring capture {id} stopped");
+        if !ring_captures_active() && !hyperfocus_active() {
+            let _ = set_heimdall_mode(HeimdallMode::WatchOnly);
+        }
+    }
+    removed
+}
+
+/// Lists the running ring captures.
+pub fn ring_captures() -> Vec<RingCaptureInfo> {
+    let mut result: Vec<RingCaptureInfo> = RING_CAPTURES
+        .iter()
+        .map(|ring| ring.value().info(*ring.key()))
+        .collect();
+    result.sort_by_key(|ring| ring.id);
+    result
+}
+
+/// Are any ring captures running? Heimdall must stay in analysis mode while
+/// they are.
+pub(crate) fn ring_captures_active() -> bool {
+    !RING_CAPTURES.is_empty()
+}
+
+/// Called for every Heimdall event.
+pub(crate) fn store_on_rings(event: &HeimdallEvent) {
+    if RING_CAPTURES.is_empty() {
+        return;
+    }
+    for mut ring in RING_CAPTURES.iter_mut() {
+        if ring.matches(event) {
+            ring.push(event.clone());
+        }
+    }
+}
+
+/// Run once per second: trims old packets and keeps the watched addresses
+/// (and analysis mode) alive.
+pub(crate) fn maintain_ring_captures() {
+    if RING_CAPTURES.is_empty() {
+        return;
+    }
+    let Ok(now) = time_since_boot() else {
+        return;
+    };
+    let now_nanos = Duration::from(now).as_nanos() as u64;
+    for mut ring in RING_CAPTURES.iter_mut() {
+        ring.expire(now_nanos);
+        for ip in ring.target.ips.iter() {
+            heimdall_watch_ip(*ip);
+        }
+    }
+    if let Err(e) = set_heimdall_mode(HeimdallMode::Analysis) {
+        warn!("Unable to keep Heimdall in analysis mode for ring captures: {e:?}");
+    }
+}
+
+/// Freezes the current contents of a ring capture into a pcapng file. The
+/// ring keeps running; the file is replaced on the next freeze and removed
+/// when the capture stops.
+///
+/// ## Returns
+/// * Either `None` (unknown id or write failure) or the filename of the dump.
+pub fn freeze_ring_capture(id: usize) -> Option<String> {
+    let (packets, target, window) = {
+        let ring = RING_CAPTURES.get(&id)?;
+        (
+            ring.packets.iter().cloned().collect::<Vec<_>>(),
+            ring.target.clone(),
+            ring.window,
+        )
+    };
+
+    let filename = format!("/tmp/ring_cap_{id}.pcapng");
+    let comments = ring_comments(&target, window);
+    if let Err(e) = write_capture(&filename, packets, &target.ips, &comments) {
+        warn!("Unable to write ring capture {filename}: {e:?}");
+        return None;
+    }
+    if let Some(mut ring) = RING_CAPTURES.get_mut(&id) {
+        ring.dump_filename = Some(filename.clone());
+    }
+    Some(filename)
+}
+
+fn ring_comments(target: &RingCaptureTarget, window: Duration) -> Vec<String> {
+    let frozen = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut comments = vec![format!(
+        "LibreQoS rolling capture: last {} seconds, frozen at {frozen} (UNIX)",
+        window.as_secs(),
+    )];
+    match (&target.circuit_id, &target.circuit_name) {
+        (Some(id), Some(name)) => comments.push(format!("Circuit: {name} ({id})")),
+        (Some(id), None) => comments.push(format!("Circuit: {id}")),
+        _ => {}
+    }
+    let addresses: Vec<String> = target.ips.iter().map(|ip| ip.as_ip().to_string()).collect();
+    comments.push(format!("Addresses: {}", addresses.join(", ")));
+    comments
+}
@@ -1,7 +1,9 @@
 use crate::{
-    HeimdallMode, SESSION_EXPIRE_SECONDS, TIMELINE_EXPIRE_SECS, heimdall_watch_ip,
-    pcap::{PcapFileHeader, PcapPacketHeader},
-    perf_interface::{HeimdallEvent, PACKET_OCTET_SIZE},
+    HeimdallMode, SESSION_EXPIRE_SECONDS, TIMELINE_EXPIRE_SECS,
+    capture_file::write_capture,
+    heimdall_watch_ip,
+    perf_interface::HeimdallEvent,
+    ring::{ring_captures_active, store_on_rings},
     set_heimdall_mode,
 };
 use dashmap::{DashMap, DashSet};
@@ -9,14 +11,12 @@ use lqos_bus::{PacketHeader, tos_parser};
 use lqos_utils::{XdpIpAddress, unix_time::time_since_boot};
 use once_cell::sync::Lazy;
 use std::{
-    fs::{File, remove_file},
-    io::Write,
+    fs::remove_file,
     path::Path,
     sync::atomic::{AtomicBool, AtomicUsize},
     time::Duration,
 };
 use tracing::{info, warn};
-use zerocopy::IntoBytes;
 
 impl HeimdallEvent {
     fn as_header(&self) -> PacketHeader {
@@ -54,6 +54,7 @@ impl Timeline {
 static TIMELINE: Lazy<Timeline> = Lazy::new(Timeline::new);
 
 pub(crate) fn store_on_timeline(event: HeimdallEvent) {
+    store_on_rings(&event);
     TIMELINE.data.insert(event); // We're moving here deliberately
 }
 
@@ -71,6 +72,7 @@ pub(crate) fn expire_timeline() {
 }
 
 struct FocusSession {
+    target: XdpIpAddress,
     expire: u64,
     data: DashSet<HeimdallEvent>,
     dump_filename: Option<String>,
@@ -91,6 +93,11 @@ static HYPERFOCUSED: AtomicBool = AtomicBool::new(false);
 static FOCUS_SESSION_ID: AtomicUsize = AtomicUsize::new(0);
 static FOCUS_SESSIONS: Lazy<DashMap<usize, FocusSession>> = Lazy::new(DashMap::new);
 
+/// Is a one-shot capture session currently running?
+pub(crate) fn hyperfocus_active() -> bool {
+    HYPERFOCUSED.load(std::sync::atomic::Ordering::Relaxed)
+}
+
 /// Tell Heimdall to spend the next 10 seconds obsessing over an IP address,
 /// collecting full packet headers. This hurts your CPU, so use it sparingly.
 ///
@@ -127,7 +134,10 @@ pub fn hyperfocus_on_target(ip: XdpIpAddress) -> Option<(usize, usize)> {
                     heimdall_watch_ip(ip);
                     std::thread::sleep(Duration::from_secs(1));
                 }
-                let _ = set_heimdall_mode(HeimdallMode::WatchOnly);
+                // Rolling captures still need packet data.
+                if !ring_captures_active() {
+                    let _ = set_heimdall_mode(HeimdallMode::WatchOnly);
+                }
 
                 if let Ok(now) = time_since_boot() {
                     let since_boot = Duration::from(now);
@@ -135,6 +145,7 @@ pub fn hyperfocus_on_target(ip: XdpIpAddress) -> Option<(usize, usize)> {
                     FOCUS_SESSIONS.insert(
                         new_id,
                         FocusSession {
+                            target: ip,
                             expire: expire.as_nanos() as u64,
                             data: TIMELINE.data.clone(),
                             dump_filename: None,
@@ -167,7 +178,7 @@ pub fn n_second_packet_dump(session_id: usize) -> Option<Vec<PacketHeader>> {
 }
 
 /// Request a dump of the packet headers collected during a hyperfocus session,
-/// in LibPCAP format. This will return `None` if the session id is invalid or
+/// in pcapng format. This will return `None` if the session id is invalid or
 /// the session has expired, or the temporary filename used to store the dump
 /// if it is available.
 /// ## Returns
@@ -175,33 +186,17 @@ pub fn n_second_packet_dump(session_id: usize) -> Option<Vec<PacketHeader>> {
 /// ## Arguments
 /// * `session_id` - The session id of the hyperfocus session.
 pub fn n_second_pcap(session_id: usize) -> Option<String> {
-    if let Some(mut session) = FOCUS_SESSIONS.get_mut(&session_id) {
-        let filename = format!("/tmp/cap_sess_{session_id}");
-        session.dump_filename = Some(filename.clone());
-        let path = Path::new(&filename);
-        let mut out = File::create(path).expect("Unable to create {filename}");
-        out.write_all(PcapFileHeader::new().as_bytes())
-            .expect("Unable to write to {filename}");
-
-        for e in session.data.iter() {
-            let p = PcapPacketHeader::from_heimdall(&e);
-            if let Err(err) = out.write_all(p.as_bytes()) {
-                info!("Unable to write to {filename}: {:?}", err);
-                return None;
-            }
-            if e.size < PACKET_OCTET_SIZE as u32 {
-                if let Err(err) = out.write_all(&e.packet_data[0..e.size as usize]) {
-                    info!("Unable to write to {filename}: {:?}", err);
-                    return None;
-                }
-            } else if let Err(err) = out.write_all(&e.packet_data) {
-                info!("Unable to write to {filename}: {:?}", err);
-                return None;
-            }
-        }
-
-        Some(filename)
-    } else {
-        None
+    let mut session = FOCUS_SESSIONS.get_mut(&session_id)?;
+    let filename = format!("/tmp/cap_sess_{session_id}.pcapng");
+    session.dump_filename = Some(filename.clone());
+    let packets: Vec<HeimdallEvent> = session.data.iter().map(|e| e.clone()).collect();
+    let comments = vec![format!(
+        "LibreQoS packet capture of {}",
+        session.target.as_ip()
+    )];
+    if let Err(err) = write_capture(&filename, packets, &[session.target], &comments) {
+        info!("Unable to write to {filename}: {:?}", err);
+        return None;
     }
+    Some(filename)
 }
@@ -0,0 +1,144 @@
+//! Netlink attribute (`struct nlattr`) parsing.
+
+/// Attribute header: a `u16` length followed by a `u16` type.
+const NLA_HEADER_LEN: usize = 4;
+/// The top two bits of the type are the nested and byte-order flags.
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+/// Rounds a length up to netlink's 4-byte alignment.
+pub(crate) const fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// A single attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Attribute<'a> {
+    /// The attribute type, without the nested/byte-order flags.
+    pub kind: u16,
+    /// The attribute payload.
+    pub payload: &'a [u8],
+}
+
+impl<'a> Attribute<'a> {
+    /// Reads a native-endian `u32` at `offset` within the payload. Useful for
+    /// attributes that carry a C struct rather than a single value.
+    pub fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes = self.payload.get(offset..offset + 4)?;
+        Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+    }
+
+    /// Reads a native-endian `u64` at `offset` within the payload.
+    pub fn u64_at(&self, offset: usize) -> Option<u64> {
+        let bytes = self.payload.get(offset..offset + 8)?;
+        Some(u64::from_ne_bytes(bytes.try_into().ok()?))
+    }
+
+    /// Reads the payload as a `u32`.
+    pub fn u32(&self) -> Option<u32> {
+        self.u32_at(0)
+    }
+
+    /// Reads the payload as an `i32`.
+    pub fn i32(&self) -> Option<i32> {
+        self.u32().map(|v| v as i32)
+    }
+
+    /// Reads the payload as a `u64`.
+    pub fn u64(&self) -> Option<u64> {
+        self.u64_at(0)
+    }
+
+    /// Reads the payload as a NUL-terminated string.
+    pub fn string(&self) -> Option<&'a str> {
+        let end = self
+            .payload
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(self.payload.len());
+        std::str::from_utf8(&self.payload[..end]).ok()
+    }
+
+    /// Treats the payload as a run of nested attributes.
+    pub fn nested(&self) -> Attributes<'a> {
+        Attributes::new(self.payload)
+    }
+}
+
+/// Iterates over a run of attributes. Iteration stops at the first
+/// malformed header rather than reading past it.
+#[derive(Clone, Debug)]
+pub struct Attributes<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Attributes<'a> {
+    /// Wraps a buffer of attributes.
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Returns the first attribute of the given type.
+    pub fn get(&self, kind: u16) -> Option<Attribute<'a>> {
+        self.clone().find(|attr| attr.kind == kind)
+    }
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = Attribute<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.buf.get(..NLA_HEADER_LEN)?;
+        let len = u16::from_ne_bytes([header[0], header[1]]) as usize;
+        let kind = u16::from_ne_bytes([header[2], header[3]]) & NLA_TYPE_MASK;
+        if len < NLA_HEADER_LEN || len > self.buf.len() {
+            self.buf = &[];
+            return None;
+        }
+        let payload = &self.buf[NLA_HEADER_LEN..len];
+        self.buf = self.buf.get(align(len)..).unwrap_or_default();
+        Some(Attribute { kind, payload })
+    }
+}
+
+/// Appends an attribute, with padding, to a message being built.
+pub fn push_attribute(buf: &mut Vec<u8>, kind: u16, payload: &[u8]) {
+    let len = NLA_HEADER_LEN + payload.len();
+    buf.extend_from_slice(&(len as u16).to_ne_bytes());
+    buf.extend_from_slice(&kind.to_ne_bytes());
+    buf.extend_from_slice(payload);
+    buf.resize(align(buf.len()), 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iterates_aligned_attributes() {
+        let mut buf = Vec::new();
+        push_attribute(&mut buf, 1, b"cake\0");
+        push_attribute(&mut buf, 2, &42u32.to_ne_bytes());
+        let attrs: Vec<_> = Attributes::new(&buf).collect();
+        assert_eq!(attrs.len(), 2);
+        assert_eq!(attrs[0].string(), Some("cake"));
+        assert_eq!(attrs[1].u32(), Some(42));
+    }
+
+    #[test]
+    fn masks_nested_flag_and_reads_children() {
+        let mut inner = Vec::new();
+        push_attribute(&mut inner, 3, &7u64.to_ne_bytes());
+        let mut buf = Vec::new();
+        push_attribute(&mut buf, 4 | 0x8000, &inner);
+        let outer = Attributes::new(&buf).get(4).expect("nested attribute");
+        assert_eq!(outer.nested().get(3).and_then(|a| a.u64()), Some(7));
+    }
+
+    #[test]
+    fn stops_at_truncated_attribute() {
+        let mut buf = Vec::new();
+        push_attribute(&mut buf, 1, &1u32.to_ne_bytes());
+        buf.extend_from_slice(&[64, 0, 2, 0, 1, 2]);
+        assert_eq!(Attributes::new(&buf).count(), 1);
+    }
+}
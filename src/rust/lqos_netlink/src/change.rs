@@ -0,0 +1,169 @@
+//! Traffic control changes: adding, replacing, changing and deleting qdiscs
+//! and classes, one acknowledged request at a time.
+
+use crate::attributes::push_attribute;
+use crate::socket::{NLM_F_CREATE, NLM_F_EXCL, NLM_F_REPLACE, NetlinkError, RouteSocket};
+use crate::tc::{
+    RTM_DELQDISC, RTM_DELTCLASS, RTM_NEWQDISC, RTM_NEWTCLASS, TCA_KIND, TCA_OPTIONS,
+    interface_index, tcmsg,
+};
+use std::collections::HashMap;
+
+/// Marks an attribute as holding nested attributes.
+const NLA_F_NESTED: u16 = 0x8000;
+/// Deleting a large tree can keep the kernel busy well past a stats dump.
+const CHANGE_TIMEOUT_SECS: i64 = 60;
+
+/// What a change applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcObject {
+    /// A queueing discipline.
+    Qdisc,
+    /// A class within a classful qdisc.
+    Class,
+}
+
+/// How a change is applied, matching the `tc` verbs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcVerb {
+    /// Create; fails if the target already exists.
+    Add,
+    /// Create, or replace whatever is already there.
+    Replace,
+    /// Modify an existing target in place.
+    Change,
+    /// Remove the target.
+    Delete,
+}
+
+/// A single qdisc or class change, the equivalent of one `tc` command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TcChange {
+    /// Qdisc or class.
+    pub object: TcObject,
+    /// Add, replace, change or delete.
+    pub verb: TcVerb,
+    /// The interface name.
+    pub interface: String,
+    /// The qdisc handle or class id; zero lets the kernel choose, or
+    /// matches whatever is attached to `parent` when deleting.
+    pub handle: u32,
+    /// The parent handle; [`crate::TC_H_ROOT`] at the root.
+    pub parent: u32,
+    /// The qdisc or class kind, e.g. `cake` or `htb`. Not needed to delete.
+    pub kind: Option<String>,
+    /// Kind-specific options, already encoded as attributes.
+    pub options: Option<Vec<u8>>,
+}
+
+impl TcChange {
+    fn message_type(&self) -> u16 {
+        match (self.object, self.verb) {
+            (TcObject::Qdisc, TcVerb::Delete) => RTM_DELQDISC,
+            (TcObject::Qdisc, _) => RTM_NEWQDISC,
+            (TcObject::Class, TcVerb::Delete) => RTM_DELTCLASS,
+            (TcObject::Class, _) => RTM_NEWTCLASS,
+        }
+    }
+
+    fn flags(&self) -> u16 {
+        match self.verb {
+            TcVerb::Add => NLM_F_CREATE | NLM_F_EXCL,
+            TcVerb::Replace => NLM_F_CREATE | NLM_F_REPLACE,
+            TcVerb::Change | TcVerb::Delete => 0,
+        }
+    }
+
+    fn body(&self, ifindex: i32) -> Vec<u8> {
+        let mut body = tcmsg(ifindex, self.handle, self.parent);
+        if let Some(kind) = &self.kind {
+            let mut name = kind.as_bytes().to_vec();
+            name.push(0);
+            push_attribute(&mut body, TCA_KIND, &name);
+        }
+        if let Some(options) = &self.options {
+            push_attribute(&mut body, TCA_OPTIONS | NLA_F_NESTED, options);
+        }
+        body
+    }
+}
+
+/// A netlink socket for applying a series of changes. Keep one open for a
+/// whole batch rather than reopening per change.
+pub struct TcSession {
+    socket: RouteSocket,
+    interfaces: HashMap<String, i32>,
+}
+
+impl TcSession {
+    /// Opens a session.
+    pub fn open() -> Result<Self, NetlinkError> {
+        Ok(Self {
+            socket: RouteSocket::open(CHANGE_TIMEOUT_SECS)?,
+            interfaces: HashMap::new(),
+        })
+    }
+
+    /// Applies one change and waits for the kernel to accept or reject it.
+    pub fn apply(&mut self, change: &TcChange) -> Result<(), NetlinkError> {
+        let ifindex = match self.interfaces.get(&change.interface) {
+            Some(index) => *index,
+            None => {
+                let index = interface_index(&change.interface)?;
+                self.interfaces.insert(change.interface.clone(), index);
+                index
+            }
+        };
+        self.socket
+            .acknowledge(change.message_type(), change.flags(), &change.body(ifindex))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::Attributes;
+    use crate::tc::TcMessage;
+
+    #[test]
+    fn replace_qdisc_encodes_kind_and_nested_options() {
+        let mut options = Vec::new();
+        push_attribute(&mut options, 3, &1u32.to_ne_bytes());
+        let change = TcChange {
+            object: TcObject::Qdisc,
+            verb: TcVerb::Replace,
+            interface: "eth0".to_string(),
+            handle: 0x9000_0000,
+            parent: 0x0003_14af,
+            kind: Some("cake".to_string()),
+            options: Some(options),
+        };
+        assert_eq!(change.message_type(), RTM_NEWQDISC);
+        assert_eq!(change.flags(), NLM_F_CREATE | NLM_F_REPLACE);
+
+        let message = TcMessage::parse(&change.body(7)).expect("parse");
+        assert_eq!(message.ifindex, 7);
+        assert_eq!(message.parent, 0x0003_14af);
+        assert_eq!(message.kind, "cake");
+        let diffserv = Attributes::new(&message.options)
+            .get(3)
+            .and_then(|a| a.u32());
+        assert_eq!(diffserv, Some(1));
+    }
+
+    #[test]
+    fn delete_class_sends_no_attributes() {
+        let change = TcChange {
+            object: TcObject::Class,
+            verb: TcVerb::Delete,
+            interface: "eth0".to_string(),
+            handle: 0x0003_14af,
+            parent: 0x0003_0020,
+            kind: None,
+            options: None,
+        };
+        assert_eq!(change.message_type(), RTM_DELTCLASS);
+        assert_eq!(change.flags(), 0);
+        assert_eq!(change.body(7).len(), 20);
+    }
+}
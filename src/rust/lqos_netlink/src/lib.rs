@@ -0,0 +1,24 @@
+//! A small rtnetlink client for reading traffic control state straight
+//! from the kernel, instead of forking `tc` and parsing its JSON.
+//!
+//! Only what LibreQoS needs is implemented: qdisc and class dumps, looking
+//! up the qdisc attached to a class, and applying qdisc and class changes.
+//! Qdisc-specific options and statistics are raw attributes, encoded and
+//! decoded by the caller, since only the caller knows which structure it
+//! wants them in.
+
+#![deny(clippy::unwrap_used)]
+#![warn(missing_docs)]
+
+mod attributes;
+mod change;
+mod socket;
+mod tc;
+
+pub use attributes::{Attribute, Attributes, push_attribute};
+pub use change::{TcChange, TcObject, TcSession, TcVerb};
+pub use socket::NetlinkError;
+pub use tc::{
+    TC_H_ROOT, TcBasicStats, TcMessage, TcQueueStats, dump_classes, dump_qdiscs,
+    get_qdisc_by_parent,
+};
@@ -0,0 +1,226 @@
+//! A blocking `NETLINK_ROUTE` socket for request/response exchanges.
+
+use crate::attributes::{Attributes, align};
+use nix::errno::Errno;
+use nix::sys::socket::{
+    AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol, SockType, bind, recv, send,
+    setsockopt, socket, sockopt,
+};
+use nix::sys::time::TimeVal;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::sync::atomic::{AtomicU32, Ordering};
+use thiserror::Error;
+
+/// `struct nlmsghdr`: length, type, flags, sequence and port id.
+pub(crate) const NLMSG_HEADER_LEN: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_MULTI: u16 = 0x2;
+const NLM_F_ACK: u16 = 0x4;
+pub(crate) const NLM_F_REPLACE: u16 = 0x100;
+pub(crate) const NLM_F_EXCL: u16 = 0x200;
+pub(crate) const NLM_F_CREATE: u16 = 0x400;
+pub(crate) const NLM_F_DUMP: u16 = 0x300;
+/// Set on an error reply when the echoed request was truncated to its header.
+const NLM_F_CAPPED: u16 = 0x100;
+/// Set on an error reply carrying extended ACK attributes.
+const NLM_F_ACK_TLVS: u16 = 0x200;
+/// The human-readable reason in an extended ACK.
+const NLMSGERR_ATTR_MSG: u16 = 1;
+const SOL_NETLINK: nix::libc::c_int = 270;
+const NETLINK_EXT_ACK: nix::libc::c_int = 11;
+
+/// Dumps arrive as a series of datagrams, each up to a few pages.
+const RECV_BUFFER_BYTES: usize = 256 * 1024;
+/// A wedged kernel reply should not hang the caller forever.
+pub(crate) const RECV_TIMEOUT_SECS: i64 = 5;
+
+static SEQUENCE: AtomicU32 = AtomicU32::new(1);
+
+/// Errors from talking to the kernel over netlink.
+#[derive(Error, Debug)]
+pub enum NetlinkError {
+    /// The netlink socket could not be opened or configured.
+    #[error("Unable to open netlink socket: {0}")]
+    Socket(Errno),
+    /// Sending or receiving failed.
+    #[error("Netlink I/O failed: {0}")]
+    Io(Errno),
+    /// The kernel answered with an error, and possibly a reason.
+    #[error("Kernel rejected netlink request: {errno}{}", reason_suffix(.reason))]
+    Kernel {
+        /// The error code.
+        errno: Errno,
+        /// The extended ACK message, when the kernel supplied one.
+        reason: Option<String>,
+    },
+    /// A reply could not be decoded.
+    #[error("Malformed netlink message")]
+    Malformed,
+    /// The named interface does not exist.
+    #[error("Unknown interface: {0}")]
+    UnknownInterface(String),
+}
+
+impl NetlinkError {
+    /// The kernel error code, if the kernel rejected the request.
+    pub fn errno(&self) -> Option<Errno> {
+        match self {
+            Self::Kernel { errno, .. } => Some(*errno),
+            _ => None,
+        }
+    }
+}
+
+fn reason_suffix(reason: &Option<String>) -> String {
+    reason
+        .as_ref()
+        .map(|reason| format!(" ({reason})"))
+        .unwrap_or_default()
+}
+
+pub(crate) struct RouteSocket {
+    fd: OwnedFd,
+}
+
+impl RouteSocket {
+    pub(crate) fn open(timeout_secs: i64) -> Result<Self, NetlinkError> {
+        let fd = socket(
+            AddressFamily::Netlink,
+            SockType::Raw,
+            SockFlag::SOCK_CLOEXEC,
+            SockProtocol::NetlinkRoute,
+        )
+        .map_err(NetlinkError::Socket)?;
+        bind(fd.as_raw_fd(), &NetlinkAddr::new(0, 0)).map_err(NetlinkError::Socket)?;
+        setsockopt(&fd, sockopt::ReceiveTimeout, &TimeVal::new(timeout_secs, 0))
+            .map_err(NetlinkError::Socket)?;
+        // Ask for the kernel's explanation along with error codes. Older
+        // kernels refuse the option; errors then arrive without a reason.
+        let enable: nix::libc::c_int = 1;
+        let _ = unsafe {
+            nix::libc::setsockopt(
+                fd.as_raw_fd(),
+                SOL_NETLINK,
+                NETLINK_EXT_ACK,
+                (&enable as *const nix::libc::c_int).cast(),
+                std::mem::size_of::<nix::libc::c_int>() as nix::libc::socklen_t,
+            )
+        };
+        Ok(Self { fd })
+    }
+
+    /// Sends one request and hands the payload of every reply of
+    /// `reply_type` to `each`, following multipart dumps through to
+    /// `NLMSG_DONE`.
+    pub(crate) fn request(
+        &self,
+        message_type: u16,
+        flags: u16,
+        body: &[u8],
+        reply_type: u16,
+        mut each: impl FnMut(&[u8]) -> Result<(), NetlinkError>,
+    ) -> Result<(), NetlinkError> {
+        let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        let mut message = Vec::with_capacity(NLMSG_HEADER_LEN + body.len());
+        message.extend_from_slice(&((NLMSG_HEADER_LEN + body.len()) as u32).to_ne_bytes());
+        message.extend_from_slice(&message_type.to_ne_bytes());
+        message.extend_from_slice(&(flags | NLM_F_REQUEST).to_ne_bytes());
+        message.extend_from_slice(&sequence.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(body);
+        send(self.fd.as_raw_fd(), &message, MsgFlags::empty()).map_err(NetlinkError::Io)?;
+
+        let mut buf = vec![0u8; RECV_BUFFER_BYTES];
+        loop {
+            let len =
+                recv(self.fd.as_raw_fd(), &mut buf, MsgFlags::empty()).map_err(NetlinkError::Io)?;
+            let mut remaining = &buf[..len];
+            while remaining.len() >= NLMSG_HEADER_LEN {
+                let header = Header::parse(remaining)?;
+                let payload = &remaining[NLMSG_HEADER_LEN..header.len];
+                remaining = remaining.get(align(header.len)..).unwrap_or_default();
+                if header.sequence != sequence {
+                    // Left over from an earlier, abandoned request
+                    continue;
+                }
+                match header.message_type {
+                    NLMSG_DONE => return Ok(()),
+                    NLMSG_ERROR => {
+                        let code = payload
+                            .get(..4)
+                            .and_then(|b| b.try_into().ok())
+                            .map(i32::from_ne_bytes)
+                            .ok_or(NetlinkError::Malformed)?;
+                        return match code {
+                            0 => Ok(()),
+                            code => Err(NetlinkError::Kernel {
+                                errno: Errno::from_raw(-code),
+                                reason: extended_ack_reason(header.flags, payload),
+                            }),
+                        };
+                    }
+                    t if t == reply_type => each(payload)?,
+                    _ => {}
+                }
+                if header.flags & NLM_F_MULTI == 0 {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Sends a change request and waits for the kernel to acknowledge it.
+    pub(crate) fn acknowledge(
+        &self,
+        message_type: u16,
+        flags: u16,
+        body: &[u8],
+    ) -> Result<(), NetlinkError> {
+        self.request(message_type, flags | NLM_F_ACK, body, 0, |_| Ok(()))
+    }
+}
+
+/// Pulls the reason out of an error reply: the error code, then the echoed
+/// request (just its header if capped), then the extended ACK attributes.
+fn extended_ack_reason(flags: u16, payload: &[u8]) -> Option<String> {
+    if flags & NLM_F_ACK_TLVS == 0 {
+        return None;
+    }
+    let echoed_len = if flags & NLM_F_CAPPED != 0 {
+        NLMSG_HEADER_LEN
+    } else {
+        let len = payload.get(4..8)?.try_into().ok().map(u32::from_ne_bytes)?;
+        align(len as usize)
+    };
+    let reason = Attributes::new(payload.get(4 + echoed_len..)?)
+        .get(NLMSGERR_ATTR_MSG)?
+        .string()?
+        .trim()
+        .to_string();
+    (!reason.is_empty()).then_some(reason)
+}
+
+struct Header {
+    len: usize,
+    message_type: u16,
+    flags: u16,
+    sequence: u32,
+}
+
+impl Header {
+    fn parse(buf: &[u8]) -> Result<Self, NetlinkError> {
+        let header = buf.get(..NLMSG_HEADER_LEN).ok_or(NetlinkError::Malformed)?;
+        let len = u32::from_ne_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        if len < NLMSG_HEADER_LEN || len > buf.len() {
+            return Err(NetlinkError::Malformed);
+        }
+        Ok(Self {
+            len,
+            message_type: u16::from_ne_bytes([header[4], header[5]]),
+            flags: u16::from_ne_bytes([header[6], header[7]]),
+            sequence: u32::from_ne_bytes([header[8], header[9], header[10], header[11]]),
+        })
+    }
+}
@@ -0,0 +1,292 @@
+//! Traffic control messages: `RTM_GETQDISC` and `RTM_GETTCLASS`.
+
+use crate::attributes::{Attribute, Attributes};
+use crate::socket::{NLM_F_DUMP, NetlinkError, RECV_TIMEOUT_SECS, RouteSocket};
+use nix::errno::Errno;
+use nix::net::if_::if_nametoindex;
+
+pub(crate) const RTM_NEWQDISC: u16 = 36;
+pub(crate) const RTM_DELQDISC: u16 = 37;
+const RTM_GETQDISC: u16 = 38;
+pub(crate) const RTM_NEWTCLASS: u16 = 40;
+pub(crate) const RTM_DELTCLASS: u16 = 41;
+const RTM_GETTCLASS: u16 = 42;
+
+/// `struct tcmsg`: family, padding, ifindex, handle, parent and info.
+const TCMSG_LEN: usize = 20;
+
+/// The parent of a root qdisc or class.
+pub const TC_H_ROOT: u32 = 0xffff_ffff;
+
+pub(crate) const TCA_KIND: u16 = 1;
+pub(crate) const TCA_OPTIONS: u16 = 2;
+const TCA_XSTATS: u16 = 4;
+const TCA_STATS2: u16 = 7;
+
+const TCA_STATS_BASIC: u16 = 1;
+const TCA_STATS_QUEUE: u16 = 3;
+const TCA_STATS_APP: u16 = 4;
+const TCA_STATS_PKT64: u16 = 8;
+
+/// Byte and packet counters (`struct gnet_stats_basic`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TcBasicStats {
+    /// Bytes sent.
+    pub bytes: u64,
+    /// Packets sent. Read from the 64-bit counter where the kernel has one.
+    pub packets: u64,
+}
+
+/// Queue counters (`struct gnet_stats_queue`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TcQueueStats {
+    /// Packets currently queued.
+    pub qlen: u32,
+    /// Bytes currently queued.
+    pub backlog: u32,
+    /// Packets dropped.
+    pub drops: u32,
+    /// Packets requeued.
+    pub requeues: u32,
+    /// Times the queue was over its limit.
+    pub overlimits: u32,
+}
+
+/// A qdisc or class, as reported by the kernel.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TcMessage {
+    /// Interface index.
+    pub ifindex: i32,
+    /// The qdisc handle or class id.
+    pub handle: u32,
+    /// The parent handle; [`TC_H_ROOT`] at the root.
+    pub parent: u32,
+    /// For classes, the handle of the attached leaf qdisc (zero if none).
+    pub info: u32,
+    /// The qdisc or class kind, e.g. `cake` or `htb`.
+    pub kind: String,
+    /// Byte and packet counters.
+    pub basic: TcBasicStats,
+    /// Queue counters.
+    pub queue: TcQueueStats,
+    /// `TCA_OPTIONS`: kind-specific nested attributes.
+    pub options: Vec<u8>,
+    /// Kind-specific statistics (`TCA_STATS_APP`, or the legacy
+    /// `TCA_XSTATS`). Nested attributes for CAKE, a C struct for most others.
+    pub xstats: Vec<u8>,
+}
+
+impl TcMessage {
+    /// True for a root qdisc or class.
+    pub fn is_root(&self) -> bool {
+        self.parent == TC_H_ROOT
+    }
+
+    /// Iterates over the kind-specific options.
+    pub fn options(&self) -> Attributes<'_> {
+        Attributes::new(&self.options)
+    }
+
+    /// The kind-specific statistics as a single attribute, so struct fields
+    /// can be read with [`Attribute::u32_at`].
+    pub fn xstats(&self) -> Attribute<'_> {
+        Attribute {
+            kind: TCA_STATS_APP,
+            payload: &self.xstats,
+        }
+    }
+
+    /// Decodes a `tcmsg` and its attributes.
+    pub fn parse(payload: &[u8]) -> Result<Self, NetlinkError> {
+        let header = Attribute {
+            kind: 0,
+            payload: payload.get(..TCMSG_LEN).ok_or(NetlinkError::Malformed)?,
+        };
+        let mut message = TcMessage {
+            ifindex: header.u32_at(4).ok_or(NetlinkError::Malformed)? as i32,
+            handle: header.u32_at(8).ok_or(NetlinkError::Malformed)?,
+            parent: header.u32_at(12).ok_or(NetlinkError::Malformed)?,
+            info: header.u32_at(16).ok_or(NetlinkError::Malformed)?,
+            ..Default::default()
+        };
+        let mut legacy_xstats = None;
+        for attr in Attributes::new(&payload[TCMSG_LEN..]) {
+            match attr.kind {
+                TCA_KIND => message.kind = attr.string().unwrap_or_default().to_string(),
+                TCA_OPTIONS => message.options = attr.payload.to_vec(),
+                TCA_XSTATS => legacy_xstats = Some(attr.payload),
+                TCA_STATS2 => message.parse_stats(attr),
+                _ => {}
+            }
+        }
+        if message.xstats.is_empty()
+            && let Some(xstats) = legacy_xstats
+        {
+            message.xstats = xstats.to_vec();
+        }
+        Ok(message)
+    }
+
+    fn parse_stats(&mut self, stats: Attribute) {
+        let mut packets64 = None;
+        for attr in stats.nested() {
+            match attr.kind {
+                TCA_STATS_BASIC => {
+                    self.basic.bytes = attr.u64_at(0).unwrap_or_default();
+                    self.basic.packets = attr.u32_at(8).unwrap_or_default() as u64;
+                }
+                TCA_STATS_PKT64 => packets64 = attr.u64(),
+                TCA_STATS_QUEUE => {
+                    self.queue = TcQueueStats {
+                        qlen: attr.u32_at(0).unwrap_or_default(),
+                        backlog: attr.u32_at(4).unwrap_or_default(),
+                        drops: attr.u32_at(8).unwrap_or_default(),
+                        requeues: attr.u32_at(12).unwrap_or_default(),
+                        overlimits: attr.u32_at(16).unwrap_or_default(),
+                    }
+                }
+                TCA_STATS_APP => self.xstats = attr.payload.to_vec(),
+                _ => {}
+            }
+        }
+        if let Some(packets) = packets64 {
+            self.basic.packets = packets;
+        }
+    }
+}
+
+pub(crate) fn interface_index(interface: &str) -> Result<i32, NetlinkError> {
+    if_nametoindex(interface)
+        .map(|index| index as i32)
+        .map_err(|_| NetlinkError::UnknownInterface(interface.to_string()))
+}
+
+pub(crate) fn tcmsg(ifindex: i32, handle: u32, parent: u32) -> Vec<u8> {
+    let mut body = vec![0u8; 4];
+    body.extend_from_slice(&ifindex.to_ne_bytes());
+    body.extend_from_slice(&handle.to_ne_bytes());
+    body.extend_from_slice(&parent.to_ne_bytes());
+    body.extend_from_slice(&0u32.to_ne_bytes());
+    body
+}
+
+fn dump(
+    interface: &str,
+    request_type: u16,
+    reply_type: u16,
+) -> Result<Vec<TcMessage>, NetlinkError> {
+    let ifindex = interface_index(interface)?;
+    let socket = RouteSocket::open(RECV_TIMEOUT_SECS)?;
+    let mut messages = Vec::new();
+    socket.request(
+        request_type,
+        NLM_F_DUMP,
+        &tcmsg(ifindex, 0, 0),
+        reply_type,
+        |payload| {
+            let message = TcMessage::parse(payload)?;
+            // Older kernels dump qdiscs for every interface
+            if message.ifindex == ifindex {
+                messages.push(message);
+            }
+            Ok(())
+        },
+    )?;
+    Ok(messages)
+}
+
+/// Lists every visible qdisc on an interface, with statistics. Equivalent
+/// to `tc -s qdisc show dev <interface>`.
+pub fn dump_qdiscs(interface: &str) -> Result<Vec<TcMessage>, NetlinkError> {
+    dump(interface, RTM_GETQDISC, RTM_NEWQDISC)
+}
+
+/// Lists every class on an interface, with statistics. Equivalent to
+/// `tc -s class show dev <interface>`.
+pub fn dump_classes(interface: &str) -> Result<Vec<TcMessage>, NetlinkError> {
+    dump(interface, RTM_GETTCLASS, RTM_NEWTCLASS)
+}
+
+/// Fetches the qdisc attached to class `parent`, or `None` if there is no
+/// such class. Cheaper than a dump when watching a single circuit.
+pub fn get_qdisc_by_parent(
+    interface: &str,
+    parent: u32,
+) -> Result<Option<TcMessage>, NetlinkError> {
+    let ifindex = interface_index(interface)?;
+    let socket = RouteSocket::open(RECV_TIMEOUT_SECS)?;
+    let mut found = None;
+    let result = socket.request(
+        RTM_GETQDISC,
+        0,
+        &tcmsg(ifindex, 0, parent),
+        RTM_NEWQDISC,
+        |payload| {
+            found = Some(TcMessage::parse(payload)?);
+            Ok(())
+        },
+    );
+    match result {
+        Ok(()) => Ok(found),
+        Err(e) if e.errno() == Some(Errno::ENOENT) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::push_attribute;
+
+    fn tc_payload(parent: u32, attrs: &[u8]) -> Vec<u8> {
+        let mut payload = tcmsg(3, 0x8001_0000, parent);
+        payload.extend_from_slice(attrs);
+        payload
+    }
+
+    #[test]
+    fn parses_header_kind_and_stats() {
+        let mut basic = 1234u64.to_ne_bytes().to_vec();
+        basic.extend_from_slice(&10u32.to_ne_bytes());
+        basic.extend_from_slice(&[0; 4]);
+        let mut queue = Vec::new();
+        for value in [1u32, 2, 3, 4, 5] {
+            queue.extend_from_slice(&value.to_ne_bytes());
+        }
+        let mut stats = Vec::new();
+        push_attribute(&mut stats, TCA_STATS_BASIC, &basic);
+        push_attribute(&mut stats, TCA_STATS_QUEUE, &queue);
+        push_attribute(&mut stats, TCA_STATS_PKT64, &(1u64 << 33).to_ne_bytes());
+        push_attribute(&mut stats, TCA_STATS_APP, &[9, 9, 9, 9]);
+        let mut attrs = Vec::new();
+        push_attribute(&mut attrs, TCA_KIND, b"fq_codel\0");
+        push_attribute(&mut attrs, TCA_STATS2, &stats);
+
+        let message = TcMessage::parse(&tc_payload(0x0003_0002, &attrs)).expect("parse");
+        assert_eq!(message.ifindex, 3);
+        assert_eq!(message.handle, 0x8001_0000);
+        assert_eq!(message.parent, 0x0003_0002);
+        assert!(!message.is_root());
+        assert_eq!(message.kind, "fq_codel");
+        assert_eq!(message.basic.bytes, 1234);
+        assert_eq!(message.basic.packets, 1 << 33);
+        assert_eq!(message.queue.backlog, 2);
+        assert_eq!(message.queue.overlimits, 5);
+        assert_eq!(message.xstats, vec![9, 9, 9, 9]);
+    }
+
+    #[test]
+    fn falls_back_to_legacy_xstats() {
+        let mut attrs = Vec::new();
+        push_attribute(&mut attrs, TCA_KIND, b"mq\0");
+        push_attribute(&mut attrs, TCA_XSTATS, &[1, 2, 3, 4]);
+        let message = TcMessage::parse(&tc_payload(TC_H_ROOT, &attrs)).expect("parse");
+        assert!(message.is_root());
+        assert_eq!(message.xstats, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn rejects_short_header() {
+        assert!(TcMessage::parse(&[0; 8]).is_err());
+    }
+}
@@ -2,7 +2,9 @@
 #![warn(missing_docs)]
 
 mod overrides_file;
+mod schedules;
 pub use overrides_file::{
-    CircuitAdjustment, NetworkAdjustment, OverrideFile, OverrideLayer, OverrideStore,
-    UispOverrides, UispRouteOverride,
+    CircuitAdjustment, ExpiredAdjustments, NetworkAdjustment, OverrideFile, OverrideLayer,
+    OverrideStore, UispOverrides, UispRouteOverride,
 };
+pub use schedules::{BandwidthSchedule, ScheduleTarget, ScheduleTimeZone};
@@ -3,8 +3,10 @@ use std::net::{Ipv4Addr, Ipv6Addr};
 use anyhow::{Result, anyhow};
 use clap::{Args, Parser, Subcommand};
 
-use lqos_config::ShapedDevice;
-use lqos_overrides::{CircuitAdjustment, NetworkAdjustment, OverrideFile};
+use lqos_config::{AuditActor, AuditEntry, ShapedDevice, append_audit_entry, is_valid_sqm_token};
+use lqos_overrides::{
+    BandwidthSchedule, CircuitAdjustment, NetworkAdjustment, OverrideFile, ScheduleTarget,
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "lqos_overrides")]
@@ -36,6 +38,11 @@ enum Commands {
         #[command(subcommand)]
         command: UispCommand,
     },
+    /// Manage time-of-day bandwidth schedules for circuits and sites
+    Schedules {
+        #[command(subcommand)]
+        command: SchedulesCommand,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -80,6 +87,18 @@ enum AdjustmentsCommand {
         #[arg(long)]
         parent_node: String,
     },
+    /// Set or replace a device SQM override, e.g. `cake:dsl` to select a named SQM profile
+    AddDeviceSqm {
+        #[arg(long)]
+        device_id: String,
+        /// SQM override token ("cake", "cake:<profile>", "fq_codel", "fq_pie", "sfq", "none",
+        /// or "down_sqm/up_sqm").
+        #[arg(long)]
+        sqm_override: String,
+        /// Remove the override automatically after this long, e.g. "2h" or "1d12h"
+        #[arg(long, value_parser = parse_lifetime)]
+        expires_in: Option<u64>,
+    },
     /// Remove an adjustment by index (see list)
     DeleteIndex {
         #[arg(long)]
@@ -148,6 +167,19 @@ enum UispCommand {
     RouteList,
 }
 
+#[derive(Subcommand, Debug)]
+enum SchedulesCommand {
+    /// Add or replace a schedule by ID
+    Set(SetScheduleArgs),
+    /// Remove a schedule by ID
+    Remove {
+        #[arg(long)]
+        id: String,
+    },
+    /// List schedules
+    List,
+}
+
 #[derive(Args, Debug)]
 struct AddArgs {
     #[arg(long)]
@@ -178,7 +210,8 @@ struct AddArgs {
     upload_max_mbps: f32,
     #[arg(long, default_value = "")]
     comment: String,
-    /// Optional per-circuit SQM override token ("cake", "fq_codel", "none", or "down_sqm/up_sqm").
+    /// Optional per-circuit SQM override token ("cake", "cake:<profile>", "fq_codel", "fq_pie",
+    /// "sfq", "none", or "down_sqm/up_sqm").
     /// A single token applies to both directions; empty means use defaults.
     #[arg(long, default_value = "")]
     sqm_override: String,
@@ -256,21 +289,21 @@ fn normalize_sqm_override(raw: &str) -> Result<Option<String>> {
         let mut parts = token.splitn(2, '/');
         let down = parts.next().unwrap_or("").trim();
         let up = parts.next().unwrap_or("").trim();
-        let valid = |s: &str| matches!(s, "" | "cake" | "fq_codel" | "none");
+        let valid = |s: &str| s.is_empty() || is_valid_sqm_token(s);
         if !valid(down) || !valid(up) {
             return Err(anyhow!(
-                "invalid directional sqm override '{token}'. Allowed: 'cake', 'fq_codel', 'none', or 'down_sqm/up_sqm' (e.g. 'cake/fq_codel', '/none')"
+                "invalid directional sqm override '{token}'. Allowed: 'cake', 'cake:<profile>', 'fq_codel', 'fq_pie', 'sfq', 'none', or 'down_sqm/up_sqm' (e.g. 'cake/fq_codel', '/none')"
             ));
         }
         return Ok(Some(format!("{down}/{up}")));
     }
 
-    match token.as_str() {
-        "cake" | "fq_codel" | "none" => Ok(Some(token)),
-        _ => Err(anyhow!(
-            "invalid sqm override '{token}'. Allowed values: 'cake', 'fq_codel', 'none', or 'down_sqm/up_sqm' (e.g. 'cake/fq_codel', '/none')"
-        )),
+    if is_valid_sqm_token(&token) {
+        return Ok(Some(token));
     }
+    Err(anyhow!(
+        "invalid sqm override '{token}'. Allowed values: 'cake', 'cake:<profile>', 'fq_codel', 'fq_pie', 'sfq', 'none', or 'down_sqm/up_sqm' (e.g. 'cake/fq_codel', '/none')"
+    ))
 }
 
 #[derive(Args, Debug, Default)]
@@ -285,6 +318,9 @@ struct AddCircuitSpeedArgs {
     min_upload_bandwidth: Option<f32>,
     #[arg(long)]
     max_upload_bandwidth: Option<f32>,
+    /// Remove the adjustment automatically after this long, e.g. "2h" or "1d12h"
+    #[arg(long, value_parser = parse_lifetime)]
+    expires_in: Option<u64>,
 }
 
 #[derive(Args, Debug, Default)]
@@ -311,6 +347,153 @@ struct AddSiteSpeedArgs {
     download_bandwidth_mbps: Option<f32>,
     #[arg(long)]
     upload_bandwidth_mbps: Option<f32>,
+    /// Remove the adjustment automatically after this long, e.g. "2h" or "1d12h"
+    #[arg(long, value_parser = parse_lifetime)]
+    expires_in: Option<u64>,
+}
+
+/// Parses a lifetime such as "90m", "2h" or "1d12h" into seconds.
+fn parse_lifetime(raw: &str) -> Result<u64> {
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in raw.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(anyhow!("invalid lifetime '{raw}': unknown unit '{c}'")),
+        };
+        let value: u64 = digits
+            .parse()
+            .map_err(|_| anyhow!("invalid lifetime '{raw}': expected a number before '{c}'"))?;
+        total = total.saturating_add(value.saturating_mul(unit));
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        return Err(anyhow!(
+            "invalid lifetime '{raw}': add a unit (s, m, h, d or w) after {digits}"
+        ));
+    }
+    if total == 0 {
+        return Err(anyhow!(
+            "invalid lifetime '{raw}': must be longer than zero"
+        ));
+    }
+    Ok(total)
+}
+
+fn now_unix() -> u64 {
+    chrono::Utc::now().timestamp().max(0) as u64
+}
+
+fn expiry_from_lifetime(expires_in: Option<u64>) -> Option<u64> {
+    expires_in.map(|seconds| now_unix().saturating_add(seconds))
+}
+
+/// Formats how long a temporary adjustment has left, e.g. "1d 2h 5m".
+fn remaining_lifetime(expires_at: u64, now: u64) -> String {
+    if expires_at <= now {
+        return "expired".to_string();
+    }
+    let remaining = expires_at - now;
+    let (days, hours, minutes) = (
+        remaining / 86_400,
+        (remaining % 86_400) / 3_600,
+        (remaining % 3_600) / 60,
+    );
+    let mut parts = Vec::new();
+    if days > 0 {
+        parts.push(format!("{days}d"));
+    }
+    if hours > 0 {
+        parts.push(format!("{hours}h"));
+    }
+    if minutes > 0 || parts.is_empty() {
+        parts.push(format!("{}m", minutes.max(1)));
+    }
+    parts.join(" ")
+}
+
+/// Serializes adjustments for `list`, adding an `expires_in` field to temporary entries.
+fn list_with_lifetimes<T: serde::Serialize>(
+    items: &[T],
+    expires_at: impl Fn(&T) -> Option<u64>,
+) -> Result<String> {
+    let now = now_unix();
+    let values = items
+        .iter()
+        .map(|item| {
+            let mut value = serde_json::to_value(item)?;
+            if let (Some(expires_at), Some(object)) = (expires_at(item), value.as_object_mut()) {
+                object.insert(
+                    "expires_in".to_string(),
+                    remaining_lifetime(expires_at, now).into(),
+                );
+            }
+            Ok(value)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(serde_json::to_string_pretty(&values)?)
+}
+
+#[derive(Args, Debug, Default)]
+struct SetScheduleArgs {
+    #[arg(long)]
+    id: String,
+    /// Circuit to adjust (exactly one of --circuit-id or --site-name)
+    #[arg(
+        long,
+        conflicts_with = "site_name",
+        required_unless_present = "site_name"
+    )]
+    circuit_id: Option<String>,
+    /// Site to adjust
+    #[arg(long)]
+    site_name: Option<String>,
+    /// Window clauses, e.g. "mon-fri 22:00-06:00; sat,sun 00:00-24:00"
+    #[arg(long)]
+    window: String,
+    /// "local", "UTC", or an IANA zone name such as "America/Chicago"
+    #[arg(long, default_value = "local")]
+    timezone: String,
+    #[arg(long)]
+    download_bandwidth_mbps: Option<f32>,
+    #[arg(long)]
+    upload_bandwidth_mbps: Option<f32>,
+    /// Store the schedule without applying it
+    #[arg(long)]
+    disabled: bool,
+}
+
+impl SetScheduleArgs {
+    fn into_schedule(self) -> Result<BandwidthSchedule> {
+        let target = match (self.circuit_id, self.site_name) {
+            (Some(circuit_id), None) => ScheduleTarget::Circuit { circuit_id },
+            (None, Some(site_name)) => ScheduleTarget::Site { site_name },
+            _ => {
+                return Err(anyhow!(
+                    "specify exactly one of --circuit-id or --site-name"
+                ));
+            }
+        };
+        let schedule = BandwidthSchedule {
+            id: self.id.trim().to_string(),
+            enabled: !self.disabled,
+            target,
+            window: self.window,
+            timezone: self.timezone,
+            download_bandwidth_mbps: self.download_bandwidth_mbps,
+            upload_bandwidth_mbps: self.upload_bandwidth_mbps,
+        };
+        schedule.validate()?;
+        Ok(schedule)
+    }
 }
 
 fn main() -> Result<()> {
@@ -318,6 +501,7 @@ fn main() -> Result<()> {
 
     // Load overrides file at start
     let mut overrides = OverrideFile::load()?;
+    let before = serde_json::to_value(&overrides)?;
 
     match cli.command {
         Commands::PersistentDevices { command: cmd } => match cmd {
@@ -363,6 +547,7 @@ fn main() -> Result<()> {
                     max_download_bandwidth: args.max_download_bandwidth,
                     min_upload_bandwidth: args.min_upload_bandwidth,
                     max_upload_bandwidth: args.max_upload_bandwidth,
+                    expires_at: expiry_from_lifetime(args.expires_in),
                 };
                 overrides.add_circuit_adjustment(adj);
                 overrides.save()?;
@@ -404,6 +589,24 @@ fn main() -> Result<()> {
                 overrides.save()?;
                 println!("Added reparent-circuit adjustment; overrides saved.");
             }
+            AdjustmentsCommand::AddDeviceSqm {
+                device_id,
+                sqm_override,
+                expires_in,
+            } => {
+                let sqm_override = normalize_sqm_override(&sqm_override)?
+                    .ok_or_else(|| anyhow!("sqm override must not be empty"))?;
+                if overrides.set_device_sqm_override_until_return_changed(
+                    device_id,
+                    Some(sqm_override),
+                    expiry_from_lifetime(expires_in),
+                ) {
+                    overrides.save()?;
+                    println!("Set device SQM adjustment; overrides saved.");
+                } else {
+                    println!("Device SQM adjustment unchanged.");
+                }
+            }
             AdjustmentsCommand::DeleteIndex { index } => {
                 let ok = overrides.remove_circuit_adjustment_by_index(index);
                 if ok {
@@ -415,7 +618,10 @@ fn main() -> Result<()> {
             }
             AdjustmentsCommand::List => {
                 let list = overrides.circuit_adjustments();
-                println!("{}", serde_json::to_string_pretty(&list)?);
+                println!(
+                    "{}",
+                    list_with_lifetimes(list, CircuitAdjustment::expires_at)?
+                );
             }
         },
         Commands::NetworkAdjustments { command: cmd } => match cmd {
@@ -425,6 +631,7 @@ fn main() -> Result<()> {
                     site_name: args.site_name,
                     download_bandwidth_mbps: args.download_bandwidth_mbps,
                     upload_bandwidth_mbps: args.upload_bandwidth_mbps,
+                    expires_at: expiry_from_lifetime(args.expires_in),
                 };
                 overrides.add_network_adjustment(adj);
                 overrides.save()?;
@@ -460,7 +667,10 @@ fn main() -> Result<()> {
             }
             NetworkAdjustmentsCommand::List => {
                 let list = overrides.network_adjustments();
-                println!("{}", serde_json::to_string_pretty(&list)?);
+                println!(
+                    "{}",
+                    list_with_lifetimes(list, NetworkAdjustment::expires_at)?
+                );
             }
         },
         Commands::Uisp { command: cmd } => match cmd {
@@ -518,11 +728,59 @@ fn main() -> Result<()> {
                 }
             }
         },
+        Commands::Schedules { command: cmd } => match cmd {
+            SchedulesCommand::Set(args) => {
+                let schedule = args.into_schedule()?;
+                if overrides.set_bandwidth_schedule_return_changed(schedule) {
+                    overrides.save()?;
+                    println!("Set bandwidth schedule; overrides saved.");
+                } else {
+                    println!("Bandwidth schedule unchanged.");
+                }
+            }
+            SchedulesCommand::Remove { id } => {
+                let removed = overrides.remove_bandwidth_schedule_count(&id);
+                if removed > 0 {
+                    overrides.save()?;
+                    println!("Removed bandwidth schedule '{id}'; overrides saved.");
+                } else {
+                    println!("No bandwidth schedule found with id '{id}'.");
+                }
+            }
+            SchedulesCommand::List => {
+                let list = overrides.bandwidth_schedules();
+                println!("{}", serde_json::to_string_pretty(&list)?);
+            }
+        },
     }
 
+    audit_changes(&before, &overrides);
     Ok(())
 }
 
+/// Records any change to the overrides file in the LibreQoS audit log. The
+/// edit has already been saved, so failures here are only reported.
+fn audit_changes(before: &serde_json::Value, overrides: &OverrideFile) {
+    let after = match serde_json::to_value(overrides) {
+        Ok(after) => after,
+        Err(e) => {
+            eprintln!("Unable to audit overrides change: {e}");
+            return;
+        }
+    };
+    if *before == after {
+        return;
+    }
+    let command: Vec<String> = std::env::args().skip(1).collect();
+    let entry = AuditEntry::new(AuditActor::cli("lqos_overrides"), "update_overrides")
+        .target("lqos_overrides.json")
+        .detail(command.join(" "))
+        .changes(Some(before), Some(&after));
+    if let Err(e) = append_audit_entry(&entry) {
+        eprintln!("Unable to write audit log entry: {e}");
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -577,4 +835,31 @@ mod tests {
             other => panic!("unexpected top-level command: {other:?}"),
         }
     }
+
+    #[test]
+    fn normalize_sqm_override_accepts_profiles() {
+        assert_eq!(
+            normalize_sqm_override(" CAKE:DSL / fq_codel ").expect("valid override"),
+            Some("cake:dsl/fq_codel".to_string())
+        );
+        assert_eq!(
+            normalize_sqm_override("cake:fibre").expect("valid override"),
+            Some("cake:fibre".to_string())
+        );
+        assert!(normalize_sqm_override("cake:bad name").is_err());
+        assert!(normalize_sqm_override("fq_codel:dsl").is_err());
+    }
+
+    #[test]
+    fn lifetimes_parse_and_format() {
+        assert_eq!(parse_lifetime("90m").expect("valid lifetime"), 5_400);
+        assert_eq!(parse_lifetime("1d12h").expect("valid lifetime"), 129_600);
+        assert!(parse_lifetime("2").is_err());
+        assert!(parse_lifetime("0h").is_err());
+        assert!(parse_lifetime("3y").is_err());
+
+        assert_eq!(remaining_lifetime(1_000 + 93_900, 1_000), "1d 2h 5m");
+        assert_eq!(remaining_lifetime(1_030, 1_000), "1m");
+        assert_eq!(remaining_lifetime(1_000, 1_000), "expired");
+    }
 }
@@ -8,6 +8,7 @@ use lqos_config::ShapedDevice;
 use serde::{Deserialize, Serialize};
 
 use crate::overrides_file::file_lock::FileLock;
+use crate::schedules::BandwidthSchedule;
 
 mod file_lock;
 
@@ -46,6 +47,10 @@ pub enum CircuitAdjustment {
         min_upload_bandwidth: Option<f32>,
         /// Replacement maximum upload bandwidth in Mbps.
         max_upload_bandwidth: Option<f32>,
+        /// Unix time (seconds) after which the adjustment is pruned. Temporary adjustments are
+        /// applied by lqosd at runtime instead of being written into `ShapedDevices.csv`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires_at: Option<u64>,
     },
     /// Replaces some or all bandwidth values for a specific device.
     DeviceAdjustSpeed {
@@ -66,6 +71,10 @@ pub enum CircuitAdjustment {
         device_id: String,
         /// Replacement SQM override token. `None` or empty removes the override.
         sqm_override: Option<String>,
+        /// Unix time (seconds) after which the adjustment is pruned. Temporary adjustments are
+        /// applied by lqosd at runtime instead of being written into `ShapedDevices.csv`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires_at: Option<u64>,
     },
     /// Removes a circuit from generated output by circuit ID.
     RemoveCircuit {
@@ -101,6 +110,10 @@ pub enum NetworkAdjustment {
         download_bandwidth_mbps: Option<f32>,
         /// Replacement upload bandwidth in Mbps.
         upload_bandwidth_mbps: Option<f32>,
+        /// Unix time (seconds) after which the adjustment is pruned. Temporary adjustments are
+        /// applied by lqosd at runtime instead of being written into `network.json`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires_at: Option<u64>,
     },
     /// Marks a named node as virtual or non-virtual.
     SetNodeVirtual {
@@ -112,6 +125,55 @@ pub enum NetworkAdjustment {
     },
 }
 
+impl CircuitAdjustment {
+    /// Unix time (seconds) after which this adjustment lapses, if it is temporary.
+    pub fn expires_at(&self) -> Option<u64> {
+        match self {
+            Self::CircuitAdjustSpeed { expires_at, .. }
+            | Self::DeviceAdjustSqm { expires_at, .. } => *expires_at,
+            _ => None,
+        }
+    }
+
+    /// True if this adjustment is temporary and has lapsed at `now` (unix seconds).
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at()
+            .is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+impl NetworkAdjustment {
+    /// Unix time (seconds) after which this adjustment lapses, if it is temporary.
+    pub fn expires_at(&self) -> Option<u64> {
+        match self {
+            Self::AdjustSiteSpeed { expires_at, .. } => *expires_at,
+            Self::SetNodeVirtual { .. } => None,
+        }
+    }
+
+    /// True if this adjustment is temporary and has lapsed at `now` (unix seconds).
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at()
+            .is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Temporary adjustments removed by [`OverrideFile::prune_expired_adjustments`].
+#[derive(Clone, Debug, Default)]
+pub struct ExpiredAdjustments {
+    /// Expired circuit/device adjustments.
+    pub circuit_adjustments: Vec<CircuitAdjustment>,
+    /// Expired network adjustments.
+    pub network_adjustments: Vec<NetworkAdjustment>,
+}
+
+impl ExpiredAdjustments {
+    /// True if nothing was pruned.
+    pub fn is_empty(&self) -> bool {
+        self.circuit_adjustments.is_empty() && self.network_adjustments.is_empty()
+    }
+}
+
 /// Consolidated UISP-specific overrides stored in an override file.
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct UispOverrides {
@@ -154,6 +216,9 @@ pub struct OverrideFile {
     /// UISP integration consolidated overrides
     #[serde(default)]
     uisp: Option<UispOverrides>,
+    /// Time-of-day rate schedules that lqosd applies live to circuits and sites.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    bandwidth_schedules: Vec<BandwidthSchedule>,
 }
 
 fn overrides_path(config: &lqos_config::Config, layer: OverrideLayer) -> PathBuf {
@@ -284,6 +349,7 @@ fn legacy_sqm_adjustments_from_devices(devices: &[ShapedDevice]) -> Vec<CircuitA
             Some(CircuitAdjustment::DeviceAdjustSqm {
                 device_id: device.device_id.clone(),
                 sqm_override: Some(sqm_override.to_string()),
+                expires_at: None,
             })
         })
         .collect()
@@ -380,22 +446,11 @@ fn merge_network_adjustments_owned(
     let mut stormguard_site_seen: HashSet<String> = HashSet::new();
     for adj in stormguard_adjustments {
         if let NetworkAdjustment::AdjustSiteSpeed {
-            node_id,
-            site_name,
-            download_bandwidth_mbps,
-            upload_bandwidth_mbps,
+            node_id, site_name, ..
         } = adj
         {
             let key = site_speed_key(node_id.as_deref(), site_name);
-            stormguard_site_speeds.insert(
-                key.clone(),
-                NetworkAdjustment::AdjustSiteSpeed {
-                    node_id: node_id.clone(),
-                    site_name: site_name.clone(),
-                    download_bandwidth_mbps: *download_bandwidth_mbps,
-                    upload_bandwidth_mbps: *upload_bandwidth_mbps,
-                },
-            );
+            stormguard_site_speeds.insert(key.clone(), adj.clone());
             if !stormguard_site_seen.contains(&key) {
                 stormguard_site_order.push(key.clone());
                 stormguard_site_seen.insert(key);
@@ -425,10 +480,7 @@ fn merge_network_adjustments_owned(
                 });
             }
             NetworkAdjustment::AdjustSiteSpeed {
-                node_id,
-                site_name,
-                download_bandwidth_mbps,
-                upload_bandwidth_mbps,
+                node_id, site_name, ..
             } => {
                 let key = site_speed_key(node_id.as_deref(), site_name);
                 if operator_site_speed_seen.contains(&key) {
@@ -436,12 +488,7 @@ fn merge_network_adjustments_owned(
                 }
                 operator_site_speed_seen.insert(key);
                 operator_site_name_seen.insert(site_name.clone());
-                out.push(NetworkAdjustment::AdjustSiteSpeed {
-                    node_id: node_id.clone(),
-                    site_name: site_name.clone(),
-                    download_bandwidth_mbps: *download_bandwidth_mbps,
-                    upload_bandwidth_mbps: *upload_bandwidth_mbps,
-                });
+                out.push(adj.clone());
             }
         }
     }
@@ -450,24 +497,15 @@ fn merge_network_adjustments_owned(
         if operator_site_speed_seen.contains(&key) {
             continue;
         }
-        let Some(NetworkAdjustment::AdjustSiteSpeed {
-            node_id,
-            site_name,
-            download_bandwidth_mbps,
-            upload_bandwidth_mbps,
-        }) = stormguard_site_speeds.get(&key)
+        let Some(adj @ NetworkAdjustment::AdjustSiteSpeed { site_name, .. }) =
+            stormguard_site_speeds.get(&key)
         else {
             continue;
         };
         if operator_site_name_seen.contains(site_name) {
             continue;
         }
-        out.push(NetworkAdjustment::AdjustSiteSpeed {
-            node_id: node_id.clone(),
-            site_name: site_name.clone(),
-            download_bandwidth_mbps: *download_bandwidth_mbps,
-            upload_bandwidth_mbps: *upload_bandwidth_mbps,
-        });
+        out.push(adj.clone());
     }
 
     out
@@ -540,6 +578,36 @@ impl OverrideFile {
         &self.network_adjustments
     }
 
+    /// Removes every temporary adjustment that has lapsed at `now` (unix seconds) and returns
+    /// what was removed.
+    pub fn prune_expired_adjustments(&mut self, now: u64) -> ExpiredAdjustments {
+        let (expired_circuit, circuit): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.circuit_adjustments)
+                .into_iter()
+                .partition(|adj| adj.is_expired(now));
+        let (expired_network, network): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.network_adjustments)
+                .into_iter()
+                .partition(|adj| adj.is_expired(now));
+        self.circuit_adjustments = circuit;
+        self.network_adjustments = network;
+        ExpiredAdjustments {
+            circuit_adjustments: expired_circuit,
+            network_adjustments: expired_network,
+        }
+    }
+
+    /// Drops temporary adjustments, leaving only those that should be written into
+    /// `ShapedDevices.csv` and `network.json`. Temporary ones are applied at runtime so
+    /// that the files keep the rates to revert to.
+    pub fn without_temporary_adjustments(mut self) -> Self {
+        self.circuit_adjustments
+            .retain(|adj| adj.expires_at().is_none());
+        self.network_adjustments
+            .retain(|adj| adj.expires_at().is_none());
+        self
+    }
+
     /// Borrow UISP overrides if present
     pub fn uisp(&self) -> Option<&UispOverrides> {
         self.uisp.as_ref()
@@ -630,6 +698,17 @@ impl OverrideFile {
         &mut self,
         device_id: String,
         sqm_override: Option<String>,
+    ) -> bool {
+        self.set_device_sqm_override_until_return_changed(device_id, sqm_override, None)
+    }
+
+    /// Add or replace an SQM override token for `device_id` that is pruned after `expires_at`
+    /// (unix seconds), or never when `None`. Returns true if changed.
+    pub fn set_device_sqm_override_until_return_changed(
+        &mut self,
+        device_id: String,
+        sqm_override: Option<String>,
+        expires_at: Option<u64>,
     ) -> bool {
         let normalized = sqm_override.and_then(|sqm| {
             let trimmed = sqm.trim();
@@ -646,7 +725,10 @@ impl OverrideFile {
                 CircuitAdjustment::DeviceAdjustSqm {
                     device_id: current,
                     sqm_override: existing_sqm,
-                } if current == &device_id && existing_sqm == &normalized
+                    expires_at: existing_expiry,
+                } if current == &device_id
+                    && existing_sqm == &normalized
+                    && *existing_expiry == expires_at
             )
         }) {
             return false;
@@ -664,6 +746,7 @@ impl OverrideFile {
             .push(CircuitAdjustment::DeviceAdjustSqm {
                 device_id,
                 sqm_override: normalized,
+                expires_at,
             });
         true
     }
@@ -691,6 +774,35 @@ impl OverrideFile {
         false
     }
 
+    /// Borrow the list of bandwidth schedules without modifying the file.
+    pub fn bandwidth_schedules(&self) -> &[BandwidthSchedule] {
+        &self.bandwidth_schedules
+    }
+
+    /// Add or replace a bandwidth schedule by `id`. Returns true if changed.
+    pub fn set_bandwidth_schedule_return_changed(&mut self, schedule: BandwidthSchedule) -> bool {
+        if let Some(existing) = self
+            .bandwidth_schedules
+            .iter_mut()
+            .find(|s| s.id == schedule.id)
+        {
+            if *existing == schedule {
+                return false;
+            }
+            *existing = schedule;
+            return true;
+        }
+        self.bandwidth_schedules.push(schedule);
+        true
+    }
+
+    /// Remove the bandwidth schedule with `id`. Returns number removed.
+    pub fn remove_bandwidth_schedule_count(&mut self, id: &str) -> usize {
+        let before = self.bandwidth_schedules.len();
+        self.bandwidth_schedules.retain(|s| s.id != id);
+        before.saturating_sub(self.bandwidth_schedules.len())
+    }
+
     /// Add a network adjustment entry.
     pub fn add_network_adjustment(&mut self, adj: NetworkAdjustment) {
         self.network_adjustments.push(adj);
@@ -745,6 +857,7 @@ impl OverrideFile {
             site_name: site_name.clone(),
             download_bandwidth_mbps,
             upload_bandwidth_mbps,
+            expires_at: None,
         };
         if self.find_site_bandwidth_override(node_id.as_deref(), &site_name) == Some(&desired) {
             return false;
@@ -937,7 +1050,9 @@ impl OverrideStore {
 
         let operator_path = overrides_path(&config, OverrideLayer::Operator);
         ensure_exists_default(&operator_path)?;
-        let operator = load_from_path(&operator_path)?;
+        let mut operator: OverrideFile = load_from_path(&operator_path)?;
+        // lqosd prunes lapsed adjustments periodically; never apply one that it has not reached yet.
+        operator.prune_expired_adjustments(chrono::Utc::now().timestamp().max(0) as u64);
 
         if !apply_stormguard && !apply_treeguard {
             drop(lock);
@@ -1060,6 +1175,7 @@ mod tests {
                 CircuitAdjustment::DeviceAdjustSqm {
                     device_id,
                     sqm_override,
+                    ..
                 } => sqm_override.as_deref().map(|sqm| (device_id.as_str(), sqm)),
                 _ => None,
             })
@@ -1095,6 +1211,7 @@ mod tests {
             site_name: "Site1".to_string(),
             download_bandwidth_mbps: Some(100.0),
             upload_bandwidth_mbps: Some(50.0),
+            expires_at: None,
         });
 
         let mut stormguard = OverrideFile::default();
@@ -1108,6 +1225,7 @@ mod tests {
             site_name: "Site3".to_string(),
             download_bandwidth_mbps: Some(200.0),
             upload_bandwidth_mbps: Some(100.0),
+            expires_at: None,
         });
 
         let merged = merge_owned_sections(operator, stormguard, treeguard);
@@ -1192,4 +1310,85 @@ mod tests {
 
         assert!(!of.set_circuit_rtt_excluded_return_changed("C1", false));
     }
+
+    #[test]
+    fn bandwidth_schedules_upsert_by_id_and_round_trip() {
+        let json = r#"{"bandwidth_schedules":[{"id":"maint","target":{"type":"site","site_name":"Tower 4"},"window":"tue 02:00-04:00","download_bandwidth_mbps":100.0,"upload_bandwidth_mbps":null}]}"#;
+        let mut of: OverrideFile = serde_json::from_str(json).expect("schedules deserialize");
+        let maint = of.bandwidth_schedules()[0].clone();
+        assert!(maint.enabled);
+        assert_eq!(maint.timezone, "local");
+
+        assert!(!of.set_bandwidth_schedule_return_changed(maint.clone()));
+        let mut replaced = maint.clone();
+        replaced.download_bandwidth_mbps = Some(50.0);
+        assert!(of.set_bandwidth_schedule_return_changed(replaced));
+        assert_eq!(of.bandwidth_schedules().len(), 1);
+        assert_eq!(
+            of.bandwidth_schedules()[0].download_bandwidth_mbps,
+            Some(50.0)
+        );
+
+        let round_trip: OverrideFile =
+            serde_json::from_str(&serde_json::to_string(&of).expect("schedules serialize"))
+                .expect("round trip");
+        assert_eq!(round_trip.bandwidth_schedules(), of.bandwidth_schedules());
+
+        assert_eq!(of.remove_bandwidth_schedule_count("maint"), 1);
+        assert_eq!(of.remove_bandwidth_schedule_count("maint"), 0);
+    }
+
+    #[test]
+    fn expired_adjustments_are_pruned_and_never_materialized() {
+        let json = r#"{
+            "circuit_adjustments": [
+                {"type":"circuit_adjust_speed","circuit_id":"c1","min_download_bandwidth":null,"max_download_bandwidth":500.0,"min_upload_bandwidth":null,"max_upload_bandwidth":null,"expires_at":1000},
+                {"type":"circuit_adjust_speed","circuit_id":"c2","min_download_bandwidth":null,"max_download_bandwidth":50.0,"min_upload_bandwidth":null,"max_upload_bandwidth":null},
+                {"type":"device_adjust_sqm","device_id":"d1","sqm_override":"fq_codel","expires_at":3000}
+            ],
+            "network_adjustments": [
+                {"type":"adjust_site_speed","site_name":"Tower","download_bandwidth_mbps":900.0,"upload_bandwidth_mbps":null,"expires_at":2000}
+            ]
+        }"#;
+        let mut of: OverrideFile = serde_json::from_str(json).expect("adjustments deserialize");
+        assert_eq!(of.circuit_adjustments()[0].expires_at(), Some(1000));
+        assert_eq!(of.circuit_adjustments()[1].expires_at(), None);
+
+        let expired = of.prune_expired_adjustments(2000);
+        assert_eq!(expired.circuit_adjustments.len(), 1);
+        assert_eq!(expired.network_adjustments.len(), 1);
+        assert_eq!(of.circuit_adjustments().len(), 2);
+        assert!(of.network_adjustments().is_empty());
+        assert!(of.prune_expired_adjustments(2000).is_empty());
+
+        // Permanent entries serialize exactly as before.
+        let raw = serde_json::to_string(&of).expect("adjustments serialize");
+        assert_eq!(raw.matches("expires_at").count(), 1);
+
+        let materialized = of.without_temporary_adjustments();
+        assert!(matches!(
+            materialized.circuit_adjustments(),
+            [CircuitAdjustment::CircuitAdjustSpeed { circuit_id, .. }] if circuit_id == "c2"
+        ));
+    }
+
+    #[test]
+    fn permanent_sqm_override_replaces_temporary_one() {
+        let mut of = OverrideFile::default();
+        assert!(of.set_device_sqm_override_until_return_changed(
+            "dev1".to_string(),
+            Some("cake".to_string()),
+            Some(1000),
+        ));
+        assert!(!of.set_device_sqm_override_until_return_changed(
+            "dev1".to_string(),
+            Some("cake".to_string()),
+            Some(1000),
+        ));
+        assert!(
+            of.set_device_sqm_override_return_changed("dev1".to_string(), Some("cake".to_string()))
+        );
+        assert_eq!(of.circuit_adjustments().len(), 1);
+        assert_eq!(of.circuit_adjustments()[0].expires_at(), None);
+    }
 }
@@ -0,0 +1,375 @@
+//! Time-of-day and day-of-week bandwidth schedules.
+//!
+//! A schedule replaces a circuit's or site's ceiling rates while one of its
+//! windows is open. Windows use a small cron-like syntax:
+//!
+//! ```text
+//! mon-fri 22:00-06:00; sat,sun 00:00-24:00
+//! ```
+//!
+//! Each clause is a day list (`*`, names, ranges and commas) followed by a
+//! `start-end` time range. A range whose end is not after its start runs
+//! overnight and belongs to the day on which it starts. Schedules are stored
+//! in the operator overrides file; `lqosd` applies and reverts them live.
+//!
+//! Windows are read in the shaper's local time or a named IANA zone such as
+//! `America/Chicago`, so they follow daylight saving either way.
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Datelike, Local, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// What a bandwidth schedule applies to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduleTarget {
+    /// A circuit, by circuit ID.
+    Circuit {
+        /// Circuit identifier from `ShapedDevices.csv`.
+        circuit_id: String,
+    },
+    /// A site (network.json node), by name.
+    Site {
+        /// Site name from `network.json`.
+        site_name: String,
+    },
+}
+
+impl ScheduleTarget {
+    /// The circuit ID or site name this target refers to.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Circuit { circuit_id } => circuit_id,
+            Self::Site { site_name } => site_name,
+        }
+    }
+}
+
+/// A named, cron-like window during which replacement rates apply.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BandwidthSchedule {
+    /// Unique name for this schedule, e.g. `night-boost`.
+    pub id: String,
+    /// Disabled schedules are kept but never applied.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// The circuit or site whose rates are replaced.
+    pub target: ScheduleTarget,
+    /// One or more `;`-separated window clauses, e.g. `mon-fri 22:00-06:00`.
+    pub window: String,
+    /// `local` (the shaper's time zone), `UTC`, or an IANA zone name such as
+    /// `America/Chicago`.
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// Replacement download ceiling in Mbps while active. `None` leaves it unchanged.
+    pub download_bandwidth_mbps: Option<f32>,
+    /// Replacement upload ceiling in Mbps while active. `None` leaves it unchanged.
+    pub upload_bandwidth_mbps: Option<f32>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_timezone() -> String {
+    "local".to_string()
+}
+
+impl BandwidthSchedule {
+    /// Checks the ID, target, rates, window and time zone.
+    pub fn validate(&self) -> Result<()> {
+        if self.id.trim().is_empty() {
+            return Err(anyhow!("schedule id must not be empty"));
+        }
+        if self.target.name().trim().is_empty() {
+            return Err(anyhow!("schedule '{}' has an empty target", self.id));
+        }
+        if self.download_bandwidth_mbps.is_none() && self.upload_bandwidth_mbps.is_none() {
+            return Err(anyhow!(
+                "schedule '{}' must set a download or upload rate",
+                self.id
+            ));
+        }
+        for rate in [self.download_bandwidth_mbps, self.upload_bandwidth_mbps]
+            .into_iter()
+            .flatten()
+        {
+            if !rate.is_finite() || rate < 1.0 {
+                return Err(anyhow!(
+                    "schedule '{}' rates must be at least 1 Mbps",
+                    self.id
+                ));
+            }
+        }
+        parse_windows(&self.window)?;
+        ScheduleTimeZone::parse(&self.timezone)?;
+        Ok(())
+    }
+
+    /// Returns true if the schedule is enabled and one of its windows is open now.
+    pub fn is_active_now(&self) -> Result<bool> {
+        self.is_active_at(Utc::now())
+    }
+
+    /// Returns true if the schedule is enabled and one of its windows is open at `now`.
+    pub fn is_active_at(&self, now: DateTime<Utc>) -> Result<bool> {
+        if !self.enabled {
+            return Ok(false);
+        }
+        let windows = parse_windows(&self.window)?;
+        let (weekday, minute) = ScheduleTimeZone::parse(&self.timezone)?.local_time(now);
+        Ok(windows.iter().any(|w| w.contains(weekday, minute)))
+    }
+}
+
+/// The time zone a schedule's windows are evaluated in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScheduleTimeZone {
+    /// The shaper's own time zone, including daylight saving changes.
+    Local,
+    /// An IANA zone, including its daylight saving changes.
+    Named(Tz),
+}
+
+impl ScheduleTimeZone {
+    /// Parses `local`, `UTC`, or an IANA zone name such as `America/Chicago`.
+    /// Bare UTC offsets are refused: they don't follow daylight saving, so
+    /// their windows would be an hour out for half the year.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let trimmed = raw.trim();
+        if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        if trimmed.eq_ignore_ascii_case("utc") || trimmed.eq_ignore_ascii_case("gmt") {
+            return Ok(Self::Named(Tz::UTC));
+        }
+        if trimmed.starts_with(['+', '-']) {
+            return Err(anyhow!(
+                "invalid time zone '{trimmed}'. UTC offsets don't follow daylight saving; use an IANA zone name such as 'America/Chicago'"
+            ));
+        }
+        trimmed.parse::<Tz>().map(Self::Named).map_err(|_| {
+            anyhow!(
+                "invalid time zone '{trimmed}'. Use 'local', 'UTC', or an IANA zone name such as 'America/Chicago'"
+            )
+        })
+    }
+
+    /// Weekday (0 = Monday) and minute-of-day of `now` in this time zone.
+    fn local_time(&self, now: DateTime<Utc>) -> (u8, u16) {
+        fn split<Zone: chrono::TimeZone>(time: DateTime<Zone>) -> (u8, u16) {
+            (
+                time.weekday().num_days_from_monday() as u8,
+                (time.hour() * 60 + time.minute()) as u16,
+            )
+        }
+        match self {
+            Self::Local => split(now.with_timezone(&Local)),
+            Self::Named(zone) => split(now.with_timezone(zone)),
+        }
+    }
+}
+
+/// One parsed window clause.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ScheduleWindow {
+    /// Bit 0 is Monday.
+    days: u8,
+    start_minute: u16,
+    end_minute: u16,
+}
+
+impl ScheduleWindow {
+    fn contains(&self, weekday: u8, minute: u16) -> bool {
+        let day_bit = |day: u8| self.days & (1 << day) != 0;
+        if self.start_minute < self.end_minute {
+            return day_bit(weekday) && minute >= self.start_minute && minute < self.end_minute;
+        }
+        // Overnight: the tail end belongs to the previous day's window.
+        let yesterday = (weekday + 6) % 7;
+        (day_bit(weekday) && minute >= self.start_minute)
+            || (day_bit(yesterday) && minute < self.end_minute)
+    }
+}
+
+fn parse_windows(raw: &str) -> Result<Vec<ScheduleWindow>> {
+    let windows = raw
+        .split(';')
+        .map(str::trim)
+        .filter(|clause| !clause.is_empty())
+        .map(parse_window)
+        .collect::<Result<Vec<_>>>()?;
+    if windows.is_empty() {
+        return Err(anyhow!("schedule window must not be empty"));
+    }
+    Ok(windows)
+}
+
+fn parse_window(clause: &str) -> Result<ScheduleWindow> {
+    let mut parts = clause.split_whitespace();
+    let (Some(days), Some(times), None) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(anyhow!(
+            "invalid window '{clause}'. Expected '<days> <HH:MM>-<HH:MM>', e.g. 'mon-fri 22:00-06:00'"
+        ));
+    };
+    let (start, end) = times
+        .split_once('-')
+        .ok_or_else(|| anyhow!("invalid time range '{times}' in window '{clause}'"))?;
+    let start_minute = parse_time(start)?;
+    let end_minute = parse_time(end)?;
+    if start_minute == MINUTES_PER_DAY {
+        return Err(anyhow!("window '{clause}' cannot start at 24:00"));
+    }
+    Ok(ScheduleWindow {
+        days: parse_days(days)?,
+        start_minute,
+        end_minute,
+    })
+}
+
+fn parse_days(raw: &str) -> Result<u8> {
+    let lower = raw.to_ascii_lowercase();
+    if lower == "*" || lower == "daily" {
+        return Ok(0x7f);
+    }
+    let mut days = 0u8;
+    for item in lower.split(',') {
+        let (first, last) = match item.split_once('-') {
+            Some((first, last)) => (parse_day(first)?, parse_day(last)?),
+            None => (parse_day(item)?, parse_day(item)?),
+        };
+        // Ranges may wrap around the week, e.g. `sat-mon`.
+        let mut day = first;
+        loop {
+            days |= 1 << day;
+            if day == last {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+    }
+    Ok(days)
+}
+
+fn parse_day(raw: &str) -> Result<u8> {
+    let prefix = raw.get(..3).unwrap_or(raw);
+    DAY_NAMES
+        .iter()
+        .position(|name| *name == prefix)
+        .map(|day| day as u8)
+        .ok_or_else(|| anyhow!("invalid day '{raw}'. Use mon, tue, wed, thu, fri, sat or sun"))
+}
+
+fn parse_time(raw: &str) -> Result<u16> {
+    let invalid = || anyhow!("invalid time '{raw}'. Use HH:MM (00:00 to 24:00)");
+    let (hours, minutes) = raw.split_once(':').ok_or_else(invalid)?;
+    let hours: u16 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u16 = minutes.parse().map_err(|_| invalid())?;
+    // Hours are checked first so the multiplication can't overflow.
+    if hours > 24 || minutes > 59 || hours * 60 + minutes > MINUTES_PER_DAY {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn schedule(window: &str, timezone: &str) -> BandwidthSchedule {
+        BandwidthSchedule {
+            id: "night-boost".to_string(),
+            enabled: true,
+            target: ScheduleTarget::Circuit {
+                circuit_id: "c1".to_string(),
+            },
+            window: window.to_string(),
+            timezone: timezone.to_string(),
+            download_bandwidth_mbps: Some(200.0),
+            upload_bandwidth_mbps: None,
+        }
+    }
+
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        // October 2026: the 12th is a Monday.
+        Utc.with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+            .single()
+            .expect("valid timestamp")
+    }
+
+    #[test]
+    fn overnight_windows_belong_to_the_start_day() {
+        let night = schedule("mon-fri 22:00-06:00", "UTC");
+        assert!(night.is_active_at(utc(12, 23, 0)).expect("valid")); // Mon 23:00
+        assert!(night.is_active_at(utc(13, 5, 59)).expect("valid")); // Tue 05:59
+        assert!(!night.is_active_at(utc(13, 6, 0)).expect("valid")); // Tue 06:00
+        assert!(night.is_active_at(utc(17, 3, 0)).expect("valid")); // Sat 03:00, Fri's window
+        assert!(!night.is_active_at(utc(18, 3, 0)).expect("valid")); // Sun 03:00
+        assert!(!night.is_active_at(utc(12, 3, 0)).expect("valid")); // Mon 03:00, Sun off
+    }
+
+    #[test]
+    fn clauses_day_lists_and_zones() {
+        let weekend = schedule("sat,sun 00:00-24:00; wed 12:00-13:00", "Europe/Berlin");
+        assert!(weekend.is_active_at(utc(16, 23, 0)).expect("valid")); // Sat 01:00 at +2
+        assert!(!weekend.is_active_at(utc(16, 21, 0)).expect("valid")); // Fri 23:00 at +2
+        assert!(weekend.is_active_at(utc(14, 10, 30)).expect("valid")); // Wed 12:30 at +2
+
+        let wrapping = schedule("sat-mon 09:00-10:00", "America/Sao_Paulo");
+        assert!(wrapping.is_active_at(utc(12, 12, 15)).expect("valid")); // Mon 09:15 at -3
+        assert!(!wrapping.is_active_at(utc(13, 12, 15)).expect("valid")); // Tue
+
+        let mut disabled = schedule("* 00:00-24:00", "local");
+        assert!(disabled.is_active_at(utc(12, 12, 0)).expect("valid"));
+        disabled.enabled = false;
+        assert!(!disabled.is_active_at(utc(12, 12, 0)).expect("valid"));
+    }
+
+    #[test]
+    fn named_zones_follow_daylight_saving() {
+        let evening = schedule("* 22:00-23:00", "America/Chicago");
+        let at = |month, day, hour| {
+            Utc.with_ymd_and_hms(2026, month, day, hour, 30, 0)
+                .single()
+                .expect("valid timestamp")
+        };
+        assert!(evening.is_active_at(at(7, 16, 3)).expect("valid")); // 22:30 CDT
+        assert!(!evening.is_active_at(at(1, 16, 3)).expect("valid")); // 21:30 CST
+        assert!(evening.is_active_at(at(1, 16, 4)).expect("valid")); // 22:30 CST
+    }
+
+    #[test]
+    fn validate_rejects_malformed_schedules() {
+        assert!(schedule("mon-fri 22:00-06:00", "local").validate().is_ok());
+        assert!(
+            schedule("weekdays 22:00-06:00", "local")
+                .validate()
+                .is_err()
+        );
+        assert!(schedule("mon 25:00-06:00", "local").validate().is_err());
+        assert!(schedule("mon 24:00-06:00", "local").validate().is_err());
+        assert!(schedule("mon 1093:00-06:00", "local").validate().is_err());
+        assert!(schedule("mon 22:00", "local").validate().is_err());
+        assert!(schedule(" ; ", "local").validate().is_err());
+        assert!(
+            schedule("mon 22:00-06:00", "Europe/Paris")
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            schedule("mon 22:00-06:00", "Mars/Olympus")
+                .validate()
+                .is_err()
+        );
+        assert!(schedule("mon 22:00-06:00", "+05:30").validate().is_err());
+
+        let mut no_rates = schedule("mon 22:00-06:00", "local");
+        no_rates.download_bandwidth_mbps = None;
+        assert!(no_rates.validate().is_err());
+    }
+}
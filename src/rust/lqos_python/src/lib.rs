@@ -1501,9 +1501,13 @@ fn overrides_circuit_adjustments(py: Python<'_>) -> PyResult<Vec<PyObject>> {
                 max_download_bandwidth,
                 min_upload_bandwidth,
                 max_upload_bandwidth,
+                expires_at,
             } => {
                 d.set_item("type", "circuit_adjust_speed")?;
                 d.set_item("circuit_id", circuit_id.clone())?;
+                if let Some(v) = expires_at {
+                    d.set_item("expires_at", *v)?;
+                }
                 if let Some(v) = min_download_bandwidth {
                     d.set_item("min_download_bandwidth", *v)?;
                 }
@@ -1542,9 +1546,13 @@ fn overrides_circuit_adjustments(py: Python<'_>) -> PyResult<Vec<PyObject>> {
             lqos_overrides::CircuitAdjustment::DeviceAdjustSqm {
                 device_id,
                 sqm_override,
+                expires_at,
             } => {
                 d.set_item("type", "device_adjust_sqm")?;
                 d.set_item("device_id", device_id.clone())?;
+                if let Some(v) = expires_at {
+                    d.set_item("expires_at", *v)?;
+                }
                 if let Some(value) = sqm_override {
                     d.set_item("sqm_override", value.clone())?;
                 }
@@ -1600,9 +1608,13 @@ fn overrides_circuit_adjustments_effective(py: Python<'_>) -> PyResult<Vec<PyObj
                 max_download_bandwidth,
                 min_upload_bandwidth,
                 max_upload_bandwidth,
+                expires_at,
             } => {
                 d.set_item("type", "circuit_adjust_speed")?;
                 d.set_item("circuit_id", circuit_id.clone())?;
+                if let Some(v) = expires_at {
+                    d.set_item("expires_at", *v)?;
+                }
                 if let Some(v) = min_download_bandwidth {
                     d.set_item("min_download_bandwidth", *v)?;
                 }
@@ -1641,9 +1653,13 @@ fn overrides_circuit_adjustments_effective(py: Python<'_>) -> PyResult<Vec<PyObj
             lqos_overrides::CircuitAdjustment::DeviceAdjustSqm {
                 device_id,
                 sqm_override,
+                expires_at,
             } => {
                 d.set_item("type", "device_adjust_sqm")?;
                 d.set_item("device_id", device_id.clone())?;
+                if let Some(v) = expires_at {
+                    d.set_item("expires_at", *v)?;
+                }
                 if let Some(value) = sqm_override {
                     d.set_item("sqm_override", value.clone())?;
                 }
@@ -1674,12 +1690,12 @@ fn overrides_circuit_adjustments_effective(py: Python<'_>) -> PyResult<Vec<PyObj
 
 /// Returns the list of circuit adjustments that should be materialized into `ShapedDevices.csv`.
 ///
-/// This includes only operator-owned circuit adjustments so adaptive runtime layers do not
-/// overwrite the source-of-truth CSV.
+/// This includes only permanent operator-owned circuit adjustments so adaptive runtime layers
+/// and temporary adjustments do not overwrite the source-of-truth CSV.
 #[pyfunction]
 fn overrides_circuit_adjustments_materialized(py: Python<'_>) -> PyResult<Vec<PyObject>> {
     let overrides = match lqos_overrides::OverrideStore::load_effective(false, false) {
-        Ok(o) => o,
+        Ok(o) => o.without_temporary_adjustments(),
         Err(e) => return Err(PyOSError::new_err(e.to_string())),
     };
 
@@ -1693,9 +1709,13 @@ fn overrides_circuit_adjustments_materialized(py: Python<'_>) -> PyResult<Vec<Py
                 max_download_bandwidth,
                 min_upload_bandwidth,
                 max_upload_bandwidth,
+                expires_at,
             } => {
                 d.set_item("type", "circuit_adjust_speed")?;
                 d.set_item("circuit_id", circuit_id.clone())?;
+                if let Some(v) = expires_at {
+                    d.set_item("expires_at", *v)?;
+                }
                 if let Some(v) = min_download_bandwidth {
                     d.set_item("min_download_bandwidth", *v)?;
                 }
@@ -1734,9 +1754,13 @@ fn overrides_circuit_adjustments_materialized(py: Python<'_>) -> PyResult<Vec<Py
             lqos_overrides::CircuitAdjustment::DeviceAdjustSqm {
                 device_id,
                 sqm_override,
+                expires_at,
             } => {
                 d.set_item("type", "device_adjust_sqm")?;
                 d.set_item("device_id", device_id.clone())?;
+                if let Some(v) = expires_at {
+                    d.set_item("expires_at", *v)?;
+                }
                 if let Some(value) = sqm_override {
                     d.set_item("sqm_override", value.clone())?;
                 }
@@ -1798,7 +1822,8 @@ fn overrides_network_adjustments_effective(py: Python<'_>) -> PyResult<Vec<PyObj
 
 /// Returns the list of network adjustments that should be materialized into `network.json`.
 ///
-/// This includes only operator-owned network adjustments.
+/// This includes only permanent operator-owned network adjustments; lqosd applies temporary
+/// site speed adjustments live.
 ///
 /// TreeGuard virtual-node decisions and StormGuard adaptive site-speed decisions are intentionally
 /// excluded so runtime automation does not overwrite the operator-authored topology/source-of-truth
@@ -1806,7 +1831,7 @@ fn overrides_network_adjustments_effective(py: Python<'_>) -> PyResult<Vec<PyObj
 #[pyfunction]
 fn overrides_network_adjustments_materialized(py: Python<'_>) -> PyResult<Vec<PyObject>> {
     let overrides = match lqos_overrides::OverrideStore::load_effective(false, false) {
-        Ok(o) => o,
+        Ok(o) => o.without_temporary_adjustments(),
         Err(e) => return Err(PyOSError::new_err(e.to_string())),
     };
 
@@ -1826,8 +1851,12 @@ fn network_adjustments_to_py(
                 site_name,
                 download_bandwidth_mbps,
                 upload_bandwidth_mbps,
+                expires_at,
             } => {
                 d.set_item("type", "adjust_site_speed")?;
+                if let Some(v) = expires_at {
+                    d.set_item("expires_at", *v)?;
+                }
                 if let Some(node_id) = node_id {
                     d.set_item("node_id", node_id.clone())?;
                 }
@@ -2624,6 +2653,7 @@ fn shaping_runtime_sqm_fingerprint() -> Result<String> {
             lqos_overrides::CircuitAdjustment::DeviceAdjustSqm {
                 device_id,
                 sqm_override,
+                ..
             } => {
                 let token = sqm_override
                     .as_deref()
@@ -2924,6 +2954,8 @@ impl Bakery {
                 infra_qdiscs: detail.infra_qdiscs,
                 cake_qdiscs: detail.cake_qdiscs,
                 fq_codel_qdiscs: detail.fq_codel_qdiscs,
+                fq_pie_qdiscs: detail.fq_pie_qdiscs,
+                sfq_qdiscs: detail.sfq_qdiscs,
                 estimated_memory_bytes: detail.estimated_memory_bytes,
             })
             .collect::<Vec<_>>();
@@ -2954,12 +2986,14 @@ impl Bakery {
                         .get(&entry.name)
                         .expect("detail should exist for interface");
                     format!(
-                        "{} estimated {} qdiscs (infra {}, cake {}, fq_codel {})",
+                        "{} estimated {} qdiscs (infra {}, cake {}, fq_codel {}, fq_pie {}, sfq {})",
                         entry.name,
                         entry.planned_qdiscs,
                         detail.infra_qdiscs,
                         detail.cake_qdiscs,
-                        detail.fq_codel_qdiscs
+                        detail.fq_codel_qdiscs,
+                        detail.fq_pie_qdiscs,
+                        detail.sfq_qdiscs
                     )
                 })
                 .collect::<Vec<_>>()
@@ -2999,6 +3033,8 @@ impl Bakery {
             detail_dict.set_item("infra_qdiscs", detail.infra_qdiscs)?;
             detail_dict.set_item("cake_qdiscs", detail.cake_qdiscs)?;
             detail_dict.set_item("fq_codel_qdiscs", detail.fq_codel_qdiscs)?;
+            detail_dict.set_item("fq_pie_qdiscs", detail.fq_pie_qdiscs)?;
+            detail_dict.set_item("sfq_qdiscs", detail.sfq_qdiscs)?;
             detail_dict.set_item("estimated_memory_bytes", detail.estimated_memory_bytes)?;
             interface_details.set_item(interface, detail_dict)?;
         }
@@ -9,6 +9,8 @@ pub enum QueueDiff {
     //    Mq,
     //    Htb,
     FqCodel(FqCodelDiff),
+    FqPie(FqPieDiff),
+    Sfq(SfqDiff),
     Cake(CakeDiff),
     //    ClsAct,
 }
@@ -28,6 +30,20 @@ pub(crate) fn make_queue_diff(
                 Err(QueueDiffError::NotImplemented)
             }
         },
+        QueueType::FqPie(..) => match current {
+            QueueType::FqPie(..) => Ok(fq_pie_diff(previous, current)?),
+            _ => {
+                error!("Queue diffs are not implemented for FqPie to {:?}", current);
+                Err(QueueDiffError::NotImplemented)
+            }
+        },
+        QueueType::Sfq(..) => match current {
+            QueueType::Sfq(..) => Ok(sfq_diff(previous, current)?),
+            _ => {
+                error!("Queue diffs are not implemented for Sfq to {:?}", current);
+                Err(QueueDiffError::NotImplemented)
+            }
+        },
         QueueType::Cake(..) => match current {
             QueueType::Cake(..) => Ok(cake_diff(previous, current)?),
             _ => {
@@ -116,3 +132,52 @@ fn fq_codel_diff(previous: &QueueType, current: &QueueType) -> Result<QueueDiff,
     }
     Err(QueueDiffError::NotImplemented)
 }
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FqPieDiff {
+    pub bytes: u64,
+    pub packets: u32,
+    pub backlog: u32,
+    pub drops: u32,
+    pub marks: u32,
+}
+
+fn fq_pie_diff(previous: &QueueType, current: &QueueType) -> Result<QueueDiff, QueueDiffError> {
+    if let QueueType::FqPie(prev) = previous
+        && let QueueType::FqPie(new) = current
+    {
+        // Delta counters; backlog is instantaneous
+        let diff = FqPieDiff {
+            bytes: new.bytes.saturating_sub(prev.bytes),
+            packets: new.packets.saturating_sub(prev.packets),
+            backlog: new.backlog,
+            drops: new.drops.saturating_sub(prev.drops),
+            marks: new.ecn_mark.saturating_sub(prev.ecn_mark),
+        };
+        return Ok(QueueDiff::FqPie(diff));
+    }
+    Err(QueueDiffError::NotImplemented)
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SfqDiff {
+    pub bytes: u64,
+    pub packets: u32,
+    pub backlog: u32,
+    pub drops: u32,
+}
+
+fn sfq_diff(previous: &QueueType, current: &QueueType) -> Result<QueueDiff, QueueDiffError> {
+    if let QueueType::Sfq(prev) = previous
+        && let QueueType::Sfq(new) = current
+    {
+        let diff = SfqDiff {
+            bytes: new.bytes.saturating_sub(prev.bytes),
+            packets: new.packets.saturating_sub(prev.packets),
+            backlog: new.backlog,
+            drops: new.drops.saturating_sub(prev.drops),
+        };
+        return Ok(QueueDiff::Sfq(diff));
+    }
+    Err(QueueDiffError::NotImplemented)
+}
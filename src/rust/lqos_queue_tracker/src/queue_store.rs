@@ -63,11 +63,15 @@ impl Into<QueueStoreTransit> for QueueStore {
         let kind_down = match &self.current_download {
             QueueType::Cake(_) => "cake",
             QueueType::FqCodel(_) => "fq_codel",
+            QueueType::FqPie(_) => "fq_pie",
+            QueueType::Sfq(_) => "sfq",
             _ => "none",
         };
         let kind_up = match &self.current_upload {
             QueueType::Cake(_) => "cake",
             QueueType::FqCodel(_) => "fq_codel",
+            QueueType::FqPie(_) => "fq_pie",
+            QueueType::Sfq(_) => "sfq",
             _ => "none",
         };
         QueueStoreTransit {
@@ -120,11 +124,47 @@ impl Into<CakeDiffTransit> for QueueDiff {
                     tins,
                 }
             }
+            QueueDiff::FqPie(c) => single_tin_transit(
+                c.bytes,
+                c.packets,
+                CakeDiffTinTransit {
+                    sent_bytes: c.bytes,
+                    backlog_bytes: c.backlog,
+                    drops: c.drops,
+                    marks: c.marks,
+                    base_delay_us: 0,
+                },
+            ),
+            QueueDiff::Sfq(c) => single_tin_transit(
+                c.bytes,
+                c.packets,
+                CakeDiffTinTransit {
+                    sent_bytes: c.bytes,
+                    backlog_bytes: c.backlog,
+                    drops: c.drops,
+                    marks: 0,
+                    base_delay_us: 0,
+                },
+            ),
             _ => CakeDiffTransit::default(),
         }
     }
 }
 
+/// Wraps a classless qdisc's counters as a Cake-like transit, padded to the
+/// four tins the UI expects (as for fq_codel above).
+fn single_tin_transit(bytes: u64, packets: u32, tin: CakeDiffTinTransit) -> CakeDiffTransit {
+    let qlen = tin.backlog_bytes;
+    let mut tins = vec![tin];
+    tins.resize_with(4, CakeDiffTinTransit::default);
+    CakeDiffTransit {
+        bytes,
+        packets,
+        qlen,
+        tins,
+    }
+}
+
 #[allow(clippy::from_over_into)]
 impl Into<CakeDiffTinTransit> for CakeDiffTin {
     fn into(self) -> CakeDiffTinTransit {
@@ -163,6 +203,10 @@ impl Into<CakeTransit> for QueueType {
                 //tins: c.tins.iter().cloned().map(|t| t.into()).collect(),
                 //drops: c.drops,
             }
+        } else if let QueueType::FqPie(fq_pie) = self {
+            CakeTransit {
+                memory_used: fq_pie.memory_used,
+            }
         } else {
             CakeTransit::default()
         }
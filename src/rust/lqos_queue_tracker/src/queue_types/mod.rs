@@ -1,7 +1,10 @@
 pub(crate) mod tc_cake;
 mod tc_fq_codel;
+mod tc_fq_pie;
 mod tc_htb;
 mod tc_mq;
+mod tc_sfq;
+use lqos_netlink::TcMessage;
 use serde::Serialize;
 use serde_json::Value;
 use thiserror::Error;
@@ -12,6 +15,8 @@ pub enum QueueType {
     Mq(tc_mq::TcMultiQueue),
     Htb(tc_htb::TcHtb),
     FqCodel(tc_fq_codel::TcFqCodel),
+    FqPie(tc_fq_pie::TcFqPie),
+    Sfq(tc_sfq::TcSfq),
     Cake(tc_cake::TcCake),
     ClsAct,
 }
@@ -25,6 +30,8 @@ impl QueueType {
             "mq" => Ok(QueueType::Mq(tc_mq::TcMultiQueue::from_json(map)?)),
             "htb" => Ok(QueueType::Htb(tc_htb::TcHtb::from_json(map)?)),
             "fq_codel" => Ok(QueueType::FqCodel(tc_fq_codel::TcFqCodel::from_json(map)?)),
+            "fq_pie" => Ok(QueueType::FqPie(tc_fq_pie::TcFqPie::from_json(map)?)),
+            "sfq" => Ok(QueueType::Sfq(tc_sfq::TcSfq::from_json(map)?)),
             "cake" => Ok(QueueType::Cake(tc_cake::TcCake::from_json(map)?)),
             "clsact" => Ok(QueueType::ClsAct),
             _ => {
@@ -35,6 +42,31 @@ impl QueueType {
             }
         }
     }
+
+    fn from_netlink(message: &TcMessage) -> Result<QueueType, QDiscError> {
+        match message.kind.as_str() {
+            "mq" => Ok(QueueType::Mq(tc_mq::TcMultiQueue::from_netlink(message))),
+            "htb" => Ok(QueueType::Htb(tc_htb::TcHtb::from_netlink(message))),
+            "fq_codel" => Ok(QueueType::FqCodel(tc_fq_codel::TcFqCodel::from_netlink(
+                message,
+            ))),
+            "fq_pie" => Ok(QueueType::FqPie(tc_fq_pie::TcFqPie::from_netlink(message))),
+            "sfq" => Ok(QueueType::Sfq(tc_sfq::TcSfq::from_netlink(message))),
+            "cake" => Ok(QueueType::Cake(tc_cake::TcCake::from_netlink(message))),
+            "clsact" => Ok(QueueType::ClsAct),
+            kind => {
+                debug!("I don't know how to decode qdisc type {kind}");
+                Err(QDiscError::UnknownQdisc(format!(
+                    "Unknown queue kind: {kind}"
+                )))
+            }
+        }
+    }
+}
+
+/// Decodes qdiscs read over netlink, mirroring [`deserialize_tc_tree`].
+pub fn decode_netlink_qdiscs(messages: &[TcMessage]) -> Result<Vec<QueueType>, QDiscError> {
+    messages.iter().map(QueueType::from_netlink).collect()
 }
 
 /// Separated into a separate function for cleaner benchmark code
@@ -75,6 +107,10 @@ pub enum QDiscError {
     HtbOpts,
     #[error("Unable to parse fq_codel options")]
     CodelOpts,
+    #[error("Unable to parse fq_pie options")]
+    FqPieOpts,
+    #[error("Unable to parse sfq options")]
+    SfqOpts,
 }
 
 /// Used to extract TC handles without unwrapping.
@@ -97,3 +133,142 @@ macro_rules! parse_tc_handle {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{QueueType, decode_netlink_qdiscs, deserialize_tc_tree};
+    use lqos_netlink::{TcMessage, push_attribute};
+
+    fn cake_message() -> TcMessage {
+        let mut options = Vec::new();
+        push_attribute(&mut options, 3, &1u32.to_ne_bytes()); // diffserv4
+        push_attribute(&mut options, 5, &7u32.to_ne_bytes()); // triple-isolate
+        push_attribute(&mut options, 7, &100_000u32.to_ne_bytes());
+        push_attribute(&mut options, 12, &[]);
+        push_attribute(&mut options, 16, &0u32.to_ne_bytes());
+
+        let mut tin = Vec::new();
+        push_attribute(&mut tin, 3, &4096u64.to_ne_bytes());
+        push_attribute(&mut tin, 4, &3u32.to_ne_bytes());
+        push_attribute(&mut tin, 8, &5u32.to_ne_bytes());
+        let mut tins = Vec::new();
+        push_attribute(&mut tins, 2, &tin);
+        push_attribute(&mut tins, 1, &[]);
+        let mut xstats = Vec::new();
+        push_attribute(&mut xstats, 4, &2048u32.to_ne_bytes());
+        push_attribute(&mut xstats, 10, &tins);
+
+        let mut message = TcMessage {
+            handle: 0x9000_0000,
+            parent: 0x0003_14af,
+            kind: "cake".to_string(),
+            options,
+            xstats,
+            ..Default::default()
+        };
+        message.basic.bytes = 1_000_000;
+        message.queue.drops = 11;
+        message
+    }
+
+    #[test]
+    fn decodes_cake_from_netlink() {
+        let queues = decode_netlink_qdiscs(&[cake_message()]).expect("cake should decode");
+        let QueueType::Cake(cake) = &queues[0] else {
+            panic!("expected a cake qdisc");
+        };
+        assert_eq!(cake.parent.to_string(), "3:14af");
+        assert_eq!(cake.bytes, 1_000_000);
+        assert_eq!(cake.drops, 11);
+        assert_eq!(cake.memory_used, 2048);
+        assert_eq!(cake.options.rtt, 100_000);
+        assert!(cake.options.raw);
+        assert_eq!(
+            serde_json::to_value(&cake.options.diffserv).expect("serialize"),
+            "diffserv4"
+        );
+        // Tins are ordered by index, not by arrival
+        assert_eq!(cake.tins.len(), 2);
+        assert_eq!(cake.tins[1].sent_bytes, 4096);
+        assert_eq!(cake.tins[1].drops, 3);
+        assert_eq!(cake.tins[1].ecn_marks, 5);
+    }
+
+    #[test]
+    fn decodes_fq_pie_and_sfq_from_netlink() {
+        let mut options = Vec::new();
+        push_attribute(&mut options, 3, &15_000u32.to_ne_bytes()); // target
+        push_attribute(&mut options, 10, &1u32.to_ne_bytes()); // ecn
+        let xstats: Vec<u8> = [120u32, 4, 0, 0, 9, 2, 0, 1, 8192]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+        let fq_pie = TcMessage {
+            parent: 0x0003_14af,
+            kind: "fq_pie".to_string(),
+            options,
+            xstats,
+            ..Default::default()
+        };
+
+        let qopt: Vec<u8> = [1514u32, 0, 127, 1024, 128, 127, 0, 127]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+        let sfq = TcMessage {
+            parent: 0x0003_14b0,
+            kind: "sfq".to_string(),
+            options: qopt,
+            ..Default::default()
+        };
+
+        let queues = decode_netlink_qdiscs(&[fq_pie, sfq]).expect("should decode");
+        let QueueType::FqPie(fq_pie) = &queues[0] else {
+            panic!("expected an fq_pie qdisc");
+        };
+        assert_eq!(fq_pie.options.target, 15_000);
+        assert!(fq_pie.options.ecn);
+        assert_eq!(fq_pie.pkts_in, 120);
+        assert_eq!(fq_pie.dropped, 4);
+        assert_eq!(fq_pie.ecn_mark, 9);
+        assert_eq!(fq_pie.memory_used, 8192);
+
+        let QueueType::Sfq(sfq) = &queues[1] else {
+            panic!("expected an sfq qdisc");
+        };
+        assert_eq!(sfq.options.quantum, 1514);
+        assert_eq!(sfq.options.divisor, 1024);
+        assert_eq!(sfq.options.depth, 127);
+        assert_eq!(sfq.options.limit, 127);
+    }
+
+    #[test]
+    fn parses_fq_pie_and_sfq_from_tc_json() {
+        let json = r#"[
+            {"kind":"fq_pie","handle":"0:","parent":"3:14af","options":{"limit":10240,"flows":1024,"target":15000,"ecn":true},
+             "bytes":560,"packets":8,"drops":1,"pkts_in":8,"dropped":1,"ecn_mark":2,"memory_used":4096},
+            {"kind":"sfq","handle":"0:","parent":"3:14b0","options":{"limit":127,"quantum":1514,"depth":127,"divisor":1024},
+             "bytes":100,"packets":1,"drops":0}
+        ]"#;
+        let queues = deserialize_tc_tree(json).expect("should parse");
+        let QueueType::FqPie(fq_pie) = &queues[0] else {
+            panic!("expected an fq_pie qdisc");
+        };
+        assert_eq!(fq_pie.ecn_mark, 2);
+        assert_eq!(fq_pie.options.flows, 1024);
+        let QueueType::Sfq(sfq) = &queues[1] else {
+            panic!("expected an sfq qdisc");
+        };
+        assert_eq!(sfq.bytes, 100);
+        assert_eq!(sfq.options.divisor, 1024);
+    }
+
+    #[test]
+    fn rejects_unknown_netlink_kind() {
+        let message = TcMessage {
+            kind: "sfb".to_string(),
+            ..Default::default()
+        };
+        assert!(decode_netlink_qdiscs(&[message]).is_err());
+    }
+}
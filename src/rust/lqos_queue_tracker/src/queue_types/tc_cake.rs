@@ -1,6 +1,7 @@
 use super::QDiscError;
 use crate::parse_tc_handle;
 use lqos_bus::TcHandle;
+use lqos_netlink::{Attribute, TcMessage};
 use lqos_utils::{dashy_table_enum, string_table_enum};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -22,6 +23,79 @@ dashy_table_enum!(
 );
 string_table_enum!(BandWidth, unlimited); // in the present implementation with htb, always unlimited
 
+// Netlink option attributes (`TCA_CAKE_*`)
+const TCA_CAKE_DIFFSERV_MODE: u16 = 3;
+const TCA_CAKE_FLOW_MODE: u16 = 5;
+const TCA_CAKE_OVERHEAD: u16 = 6;
+const TCA_CAKE_RTT: u16 = 7;
+const TCA_CAKE_NAT: u16 = 11;
+const TCA_CAKE_RAW: u16 = 12;
+const TCA_CAKE_WASH: u16 = 13;
+const TCA_CAKE_INGRESS: u16 = 15;
+const TCA_CAKE_ACK_FILTER: u16 = 16;
+const TCA_CAKE_SPLIT_GSO: u16 = 17;
+const TCA_CAKE_FWMARK: u16 = 18;
+
+// Netlink statistics attributes (`TCA_CAKE_STATS_*`)
+const TCA_CAKE_STATS_CAPACITY_ESTIMATE64: u16 = 2;
+const TCA_CAKE_STATS_MEMORY_LIMIT: u16 = 3;
+const TCA_CAKE_STATS_MEMORY_USED: u16 = 4;
+const TCA_CAKE_STATS_AVG_NETOFF: u16 = 5;
+const TCA_CAKE_STATS_MIN_NETLEN: u16 = 6;
+const TCA_CAKE_STATS_MAX_NETLEN: u16 = 7;
+const TCA_CAKE_STATS_MIN_ADJLEN: u16 = 8;
+const TCA_CAKE_STATS_MAX_ADJLEN: u16 = 9;
+const TCA_CAKE_STATS_TIN_STATS: u16 = 10;
+
+// Netlink per-tin statistics attributes (`TCA_CAKE_TIN_STATS_*`)
+const TCA_CAKE_TIN_STATS_SENT_PACKETS: u16 = 2;
+const TCA_CAKE_TIN_STATS_SENT_BYTES64: u16 = 3;
+const TCA_CAKE_TIN_STATS_DROPPED_PACKETS: u16 = 4;
+const TCA_CAKE_TIN_STATS_ACKS_DROPPED_PACKETS: u16 = 6;
+const TCA_CAKE_TIN_STATS_ECN_MARKED_PACKETS: u16 = 8;
+const TCA_CAKE_TIN_STATS_BACKLOG_BYTES: u16 = 11;
+const TCA_CAKE_TIN_STATS_THRESHOLD_RATE64: u16 = 12;
+const TCA_CAKE_TIN_STATS_TARGET_US: u16 = 13;
+const TCA_CAKE_TIN_STATS_INTERVAL_US: u16 = 14;
+const TCA_CAKE_TIN_STATS_WAY_INDIRECT_HITS: u16 = 15;
+const TCA_CAKE_TIN_STATS_WAY_MISSES: u16 = 16;
+const TCA_CAKE_TIN_STATS_WAY_COLLISIONS: u16 = 17;
+const TCA_CAKE_TIN_STATS_PEAK_DELAY_US: u16 = 18;
+const TCA_CAKE_TIN_STATS_AVG_DELAY_US: u16 = 19;
+const TCA_CAKE_TIN_STATS_BASE_DELAY_US: u16 = 20;
+const TCA_CAKE_TIN_STATS_SPARSE_FLOWS: u16 = 21;
+const TCA_CAKE_TIN_STATS_BULK_FLOWS: u16 = 22;
+const TCA_CAKE_TIN_STATS_UNRESPONSIVE_FLOWS: u16 = 23;
+const TCA_CAKE_TIN_STATS_MAX_SKBLEN: u16 = 24;
+const TCA_CAKE_TIN_STATS_FLOW_QUANTUM: u16 = 25;
+
+/// Kernel enum values, named as `tc` prints them so both readers agree.
+const DIFFSERV_NAMES: [&str; 5] = [
+    "diffserv3",
+    "diffserv4",
+    "diffserv8",
+    "besteffort",
+    "precedence",
+];
+const FLOW_MODE_NAMES: [&str; 8] = [
+    "flowblind",
+    "srchost",
+    "dsthost",
+    "hosts",
+    "flows",
+    "dual-srchost",
+    "dual-dsthost",
+    "triple-isolate",
+];
+const ACK_FILTER_NAMES: [&str; 3] = ["disabled", "ack-filter", "ack-filter-aggressive"];
+
+fn kernel_enum_name(names: &[&'static str], attr: Attribute) -> &'static str {
+    attr.u32()
+        .and_then(|value| names.get(value as usize))
+        .copied()
+        .unwrap_or_default()
+}
+
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct TcCake {
     pub(crate) handle: TcHandle,
@@ -133,7 +207,81 @@ impl TcCake {
     }
 }
 
+impl TcCake {
+    pub(crate) fn from_netlink(message: &TcMessage) -> Self {
+        let mut result = Self {
+            handle: TcHandle::from_u32(message.handle),
+            parent: TcHandle::from_u32(message.parent),
+            options: TcCakeOptions::from_netlink(message),
+            bytes: message.basic.bytes,
+            packets: message.basic.packets as u32,
+            overlimits: message.queue.overlimits,
+            requeues: message.queue.requeues,
+            backlog: message.queue.backlog,
+            qlen: message.queue.qlen,
+            drops: message.queue.drops,
+            ..Default::default()
+        };
+        for attr in message.xstats().nested() {
+            let value = attr.u32().unwrap_or(0);
+            match attr.kind {
+                TCA_CAKE_STATS_CAPACITY_ESTIMATE64 => {
+                    result.capacity_estimate = attr.u64().unwrap_or(0) as u32
+                }
+                TCA_CAKE_STATS_MEMORY_LIMIT => result.memory_limit = value,
+                TCA_CAKE_STATS_MEMORY_USED => result.memory_used = value,
+                TCA_CAKE_STATS_AVG_NETOFF => result.avg_hdr_offset = value as u16,
+                TCA_CAKE_STATS_MIN_NETLEN => result.min_network_size = value as u16,
+                TCA_CAKE_STATS_MAX_NETLEN => result.max_network_size = value as u16,
+                TCA_CAKE_STATS_MIN_ADJLEN => result.min_adj_size = value as u16,
+                TCA_CAKE_STATS_MAX_ADJLEN => result.max_adj_size = value as u16,
+                TCA_CAKE_STATS_TIN_STATS => {
+                    // One nested attribute per tin, numbered from 1
+                    let mut tins: Vec<_> = attr.nested().collect();
+                    tins.sort_by_key(|tin| tin.kind);
+                    result.tins = tins.iter().map(TcCakeTin::from_netlink).collect();
+                }
+                _ => {}
+            }
+        }
+        result
+    }
+}
+
 impl TcCakeOptions {
+    fn from_netlink(message: &TcMessage) -> Self {
+        let mut result = Self::default();
+        for attr in message.options() {
+            let enabled = attr.u32().unwrap_or(0) != 0;
+            match attr.kind {
+                TCA_CAKE_DIFFSERV_MODE => {
+                    result.diffserv = DiffServ::from_str(kernel_enum_name(&DIFFSERV_NAMES, attr))
+                }
+                TCA_CAKE_FLOW_MODE => {
+                    result.flowmode = FlowMode::from_str(kernel_enum_name(&FLOW_MODE_NAMES, attr))
+                }
+                TCA_CAKE_ACK_FILTER => {
+                    result.ack_filter =
+                        AckFilter::from_str(kernel_enum_name(&ACK_FILTER_NAMES, attr))
+                }
+                TCA_CAKE_OVERHEAD => result.overhead = attr.i32().unwrap_or(0) as u16,
+                TCA_CAKE_RTT => result.rtt = attr.u32().unwrap_or(0) as u64,
+                TCA_CAKE_NAT => result.nat = enabled,
+                // A flag: present when no overhead compensation is configured
+                TCA_CAKE_RAW => result.raw = true,
+                TCA_CAKE_WASH => result.wash = enabled,
+                TCA_CAKE_INGRESS => result.ingress = enabled,
+                TCA_CAKE_SPLIT_GSO => result.split_gso = enabled,
+                TCA_CAKE_FWMARK => result.fwmark = TcHandle::from_u32(attr.u32().unwrap_or(0)),
+                _ => {}
+            }
+        }
+        // tc reports "unlimited" when no base rate is set, which is always
+        // the case under HTB
+        result.bandwidth = BandWidth::unlimited;
+        result
+    }
+
     fn from_json(value: &Value) -> Result<Self, QDiscError> {
         match value {
             Value::Object(map) => {
@@ -175,6 +323,39 @@ impl TcCakeOptions {
 }
 
 impl TcCakeTin {
+    fn from_netlink(tin: &Attribute) -> Self {
+        let mut result = Self::default();
+        for attr in tin.nested() {
+            let value = attr.u32().unwrap_or(0);
+            match attr.kind {
+                TCA_CAKE_TIN_STATS_SENT_PACKETS => result.sent_packets = value,
+                TCA_CAKE_TIN_STATS_SENT_BYTES64 => result.sent_bytes = attr.u64().unwrap_or(0),
+                TCA_CAKE_TIN_STATS_DROPPED_PACKETS => result.drops = value,
+                TCA_CAKE_TIN_STATS_ACKS_DROPPED_PACKETS => result.ack_drops = value,
+                TCA_CAKE_TIN_STATS_ECN_MARKED_PACKETS => result.ecn_marks = value,
+                TCA_CAKE_TIN_STATS_BACKLOG_BYTES => result.backlog_bytes = value,
+                TCA_CAKE_TIN_STATS_THRESHOLD_RATE64 => {
+                    result.threshold_rate = attr.u64().unwrap_or(0)
+                }
+                TCA_CAKE_TIN_STATS_TARGET_US => result.target_us = value,
+                TCA_CAKE_TIN_STATS_INTERVAL_US => result.interval_us = value,
+                TCA_CAKE_TIN_STATS_WAY_INDIRECT_HITS => result.way_indirect_hits = value as u16,
+                TCA_CAKE_TIN_STATS_WAY_MISSES => result.way_misses = value as u16,
+                TCA_CAKE_TIN_STATS_WAY_COLLISIONS => result.way_collisions = value as u16,
+                TCA_CAKE_TIN_STATS_PEAK_DELAY_US => result.peak_delay_us = value,
+                TCA_CAKE_TIN_STATS_AVG_DELAY_US => result.avg_delay_us = value,
+                TCA_CAKE_TIN_STATS_BASE_DELAY_US => result.base_delay_us = value,
+                TCA_CAKE_TIN_STATS_SPARSE_FLOWS => result.sparse_flows = value as u16,
+                TCA_CAKE_TIN_STATS_BULK_FLOWS => result.bulk_flows = value as u16,
+                TCA_CAKE_TIN_STATS_UNRESPONSIVE_FLOWS => result.unresponsive_flows = value as u16,
+                TCA_CAKE_TIN_STATS_MAX_SKBLEN => result.max_pkt_len = value as u16,
+                TCA_CAKE_TIN_STATS_FLOW_QUANTUM => result.flow_quantum = value as u16,
+                _ => {}
+            }
+        }
+        result
+    }
+
     fn from_json(value: &Value) -> Result<Self, QDiscError> {
         match value {
             Value::Object(map) => {
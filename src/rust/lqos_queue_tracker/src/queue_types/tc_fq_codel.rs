@@ -8,10 +8,23 @@
 use super::QDiscError;
 use crate::parse_tc_handle;
 use lqos_bus::TcHandle;
+use lqos_netlink::TcMessage;
 use serde::Serialize;
 use serde_json::Value;
 use tracing::info;
 
+// Netlink attributes (`TCA_FQ_CODEL_*`)
+const TCA_FQ_CODEL_TARGET: u16 = 1;
+const TCA_FQ_CODEL_LIMIT: u16 = 2;
+const TCA_FQ_CODEL_INTERVAL: u16 = 3;
+const TCA_FQ_CODEL_ECN: u16 = 4;
+const TCA_FQ_CODEL_FLOWS: u16 = 5;
+const TCA_FQ_CODEL_QUANTUM: u16 = 6;
+const TCA_FQ_CODEL_DROP_BATCH_SIZE: u16 = 8;
+const TCA_FQ_CODEL_MEMORY_LIMIT: u16 = 9;
+/// `tc_fq_codel_xstats.type` for qdisc (rather than class) statistics.
+const TCA_FQ_CODEL_XSTATS_QDISC: u32 = 0;
+
 #[derive(Default, Clone, Debug, Serialize)]
 pub struct TcFqCodel {
     pub(crate) handle: TcHandle,
@@ -79,6 +92,46 @@ impl TcFqCodel {
         }
         Ok(result)
     }
+
+    pub(crate) fn from_netlink(message: &TcMessage) -> Self {
+        let mut result = Self {
+            handle: TcHandle::from_u32(message.handle),
+            parent: TcHandle::from_u32(message.parent),
+            bytes: message.basic.bytes,
+            packets: message.basic.packets as u32,
+            drops: message.queue.drops,
+            overlimits: message.queue.overlimits,
+            requeues: message.queue.requeues,
+            backlog: message.queue.backlog,
+            qlen: message.queue.qlen,
+            ..Default::default()
+        };
+        for attr in message.options() {
+            let value = attr.u32().unwrap_or(0);
+            match attr.kind {
+                TCA_FQ_CODEL_TARGET => result.options.target = value as u64,
+                TCA_FQ_CODEL_LIMIT => result.options.limit = value,
+                TCA_FQ_CODEL_INTERVAL => result.options.interval = value as u64,
+                TCA_FQ_CODEL_ECN => result.options.ecn = value != 0,
+                TCA_FQ_CODEL_FLOWS => result.options.flows = value as u16,
+                TCA_FQ_CODEL_QUANTUM => result.options.quantum = value as u16,
+                TCA_FQ_CODEL_DROP_BATCH_SIZE => result.options.drop_batch = value as u16,
+                TCA_FQ_CODEL_MEMORY_LIMIT => result.options.memory_limit = value,
+                _ => {}
+            }
+        }
+        // struct tc_fq_codel_xstats: type, then tc_fq_codel_qd_stats
+        let xstats = message.xstats();
+        if xstats.u32_at(0) == Some(TCA_FQ_CODEL_XSTATS_QDISC) {
+            result.maxpacket = xstats.u32_at(4).unwrap_or(0) as u16;
+            result.drop_overlimit = xstats.u32_at(8).unwrap_or(0);
+            result.ecn_mark = xstats.u32_at(12).unwrap_or(0);
+            result.new_flow_count = xstats.u32_at(16).unwrap_or(0);
+            result.new_flows_len = xstats.u32_at(20).unwrap_or(0) as u16;
+            result.old_flows_len = xstats.u32_at(24).unwrap_or(0) as u16;
+        }
+        result
+    }
 }
 
 impl TcFqCodelOptions {
@@ -0,0 +1,185 @@
+/*
+{"kind":"fq_pie","handle":"0:","parent":"7fff:a",
+    "options":{"limit":10240,"flows":1024,"target":15000,"tupdate":15000,"alpha":2,"beta":20,"quantum":1514,"memory_limit":33554432,"ecn_prob":10,"ecn":false,"bytemode":false,"dq_rate_estimator":false},
+    "bytes":560,"packets":8,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0,"pkts_in":8,"overlimit":0,"overmemory":0,"dropped":0,
+    "ecn_mark":0,"new_flow_count":1,"new_flows_len":0,"old_flows_len":0,"memory_used":0},
+*/
+
+use super::QDiscError;
+use crate::parse_tc_handle;
+use lqos_bus::TcHandle;
+use lqos_netlink::TcMessage;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::info;
+
+// Netlink attributes (`TCA_FQ_PIE_*`)
+const TCA_FQ_PIE_LIMIT: u16 = 1;
+const TCA_FQ_PIE_FLOWS: u16 = 2;
+const TCA_FQ_PIE_TARGET: u16 = 3;
+const TCA_FQ_PIE_TUPDATE: u16 = 4;
+const TCA_FQ_PIE_ALPHA: u16 = 5;
+const TCA_FQ_PIE_BETA: u16 = 6;
+const TCA_FQ_PIE_QUANTUM: u16 = 7;
+const TCA_FQ_PIE_MEMORY_LIMIT: u16 = 8;
+const TCA_FQ_PIE_ECN_PROB: u16 = 9;
+const TCA_FQ_PIE_ECN: u16 = 10;
+const TCA_FQ_PIE_BYTEMODE: u16 = 11;
+const TCA_FQ_PIE_DQ_RATE_ESTIMATOR: u16 = 12;
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct TcFqPie {
+    pub(crate) handle: TcHandle,
+    pub(crate) parent: TcHandle,
+    pub(crate) options: TcFqPieOptions,
+    pub(crate) bytes: u64,
+    pub(crate) packets: u32,
+    pub(crate) drops: u32,
+    pub(crate) overlimits: u32,
+    pub(crate) requeues: u32,
+    pub(crate) backlog: u32,
+    pub(crate) qlen: u32,
+    pub(crate) pkts_in: u32,
+    pub(crate) overlimit: u32,
+    pub(crate) overmemory: u32,
+    pub(crate) dropped: u32,
+    pub(crate) ecn_mark: u32,
+    pub(crate) new_flow_count: u32,
+    pub(crate) new_flows_len: u32,
+    pub(crate) old_flows_len: u32,
+    pub(crate) memory_used: u32,
+}
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub(crate) struct TcFqPieOptions {
+    pub(crate) limit: u32,
+    pub(crate) flows: u32,
+    pub(crate) target: u32,  // microseconds
+    pub(crate) tupdate: u32, // microseconds
+    pub(crate) alpha: u32,
+    pub(crate) beta: u32,
+    pub(crate) quantum: u32,
+    pub(crate) memory_limit: u32,
+    pub(crate) ecn_prob: u32,
+    pub(crate) ecn: bool,
+    pub(crate) bytemode: bool,
+    pub(crate) dq_rate_estimator: bool,
+}
+
+impl TcFqPie {
+    pub(crate) fn from_json(
+        map: &serde_json::Map<std::string::String, Value>,
+    ) -> Result<Self, QDiscError> {
+        let mut result = Self::default();
+        for (key, value) in map.iter() {
+            match key.as_str() {
+                "handle" => {
+                    parse_tc_handle!(result.handle, value);
+                }
+                "parent" => {
+                    parse_tc_handle!(result.parent, value);
+                }
+                "bytes" => result.bytes = value.as_u64().unwrap_or(0),
+                "packets" => result.packets = value.as_u64().unwrap_or(0) as u32,
+                "drops" => result.drops = value.as_u64().unwrap_or(0) as u32,
+                "overlimits" => result.overlimits = value.as_u64().unwrap_or(0) as u32,
+                "requeues" => result.requeues = value.as_u64().unwrap_or(0) as u32,
+                "backlog" => result.backlog = value.as_u64().unwrap_or(0) as u32,
+                "qlen" => result.qlen = value.as_u64().unwrap_or(0) as u32,
+                "pkts_in" => result.pkts_in = value.as_u64().unwrap_or(0) as u32,
+                "overlimit" => result.overlimit = value.as_u64().unwrap_or(0) as u32,
+                "overmemory" => result.overmemory = value.as_u64().unwrap_or(0) as u32,
+                "dropped" => result.dropped = value.as_u64().unwrap_or(0) as u32,
+                "ecn_mark" => result.ecn_mark = value.as_u64().unwrap_or(0) as u32,
+                "new_flow_count" => result.new_flow_count = value.as_u64().unwrap_or(0) as u32,
+                "new_flows_len" => result.new_flows_len = value.as_u64().unwrap_or(0) as u32,
+                "old_flows_len" => result.old_flows_len = value.as_u64().unwrap_or(0) as u32,
+                "memory_used" => result.memory_used = value.as_u64().unwrap_or(0) as u32,
+                "options" => result.options = TcFqPieOptions::from_json(value)?,
+                "kind" => {}
+                _ => {
+                    info!("Unknown entry in tc-fq_pie json decoder: {key}");
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    pub(crate) fn from_netlink(message: &TcMessage) -> Self {
+        let mut result = Self {
+            handle: TcHandle::from_u32(message.handle),
+            parent: TcHandle::from_u32(message.parent),
+            bytes: message.basic.bytes,
+            packets: message.basic.packets as u32,
+            drops: message.queue.drops,
+            overlimits: message.queue.overlimits,
+            requeues: message.queue.requeues,
+            backlog: message.queue.backlog,
+            qlen: message.queue.qlen,
+            ..Default::default()
+        };
+        for attr in message.options() {
+            let value = attr.u32().unwrap_or(0);
+            match attr.kind {
+                TCA_FQ_PIE_LIMIT => result.options.limit = value,
+                TCA_FQ_PIE_FLOWS => result.options.flows = value,
+                TCA_FQ_PIE_TARGET => result.options.target = value,
+                TCA_FQ_PIE_TUPDATE => result.options.tupdate = value,
+                TCA_FQ_PIE_ALPHA => result.options.alpha = value,
+                TCA_FQ_PIE_BETA => result.options.beta = value,
+                TCA_FQ_PIE_QUANTUM => result.options.quantum = value,
+                TCA_FQ_PIE_MEMORY_LIMIT => result.options.memory_limit = value,
+                TCA_FQ_PIE_ECN_PROB => result.options.ecn_prob = value,
+                TCA_FQ_PIE_ECN => result.options.ecn = value != 0,
+                TCA_FQ_PIE_BYTEMODE => result.options.bytemode = value != 0,
+                TCA_FQ_PIE_DQ_RATE_ESTIMATOR => result.options.dq_rate_estimator = value != 0,
+                _ => {}
+            }
+        }
+        // struct tc_fq_pie_xstats
+        let xstats = message.xstats();
+        result.pkts_in = xstats.u32_at(0).unwrap_or(0);
+        result.dropped = xstats.u32_at(4).unwrap_or(0);
+        result.overlimit = xstats.u32_at(8).unwrap_or(0);
+        result.overmemory = xstats.u32_at(12).unwrap_or(0);
+        result.ecn_mark = xstats.u32_at(16).unwrap_or(0);
+        result.new_flow_count = xstats.u32_at(20).unwrap_or(0);
+        result.new_flows_len = xstats.u32_at(24).unwrap_or(0);
+        result.old_flows_len = xstats.u32_at(28).unwrap_or(0);
+        result.memory_used = xstats.u32_at(32).unwrap_or(0);
+        result
+    }
+}
+
+impl TcFqPieOptions {
+    fn from_json(value: &Value) -> Result<Self, QDiscError> {
+        match value {
+            Value::Object(map) => {
+                let mut result = Self::default();
+                for (key, value) in map.iter() {
+                    match key.as_str() {
+                        "limit" => result.limit = value.as_u64().unwrap_or(0) as u32,
+                        "flows" => result.flows = value.as_u64().unwrap_or(0) as u32,
+                        "target" => result.target = value.as_u64().unwrap_or(0) as u32,
+                        "tupdate" => result.tupdate = value.as_u64().unwrap_or(0) as u32,
+                        "alpha" => result.alpha = value.as_u64().unwrap_or(0) as u32,
+                        "beta" => result.beta = value.as_u64().unwrap_or(0) as u32,
+                        "quantum" => result.quantum = value.as_u64().unwrap_or(0) as u32,
+                        "memory_limit" => result.memory_limit = value.as_u64().unwrap_or(0) as u32,
+                        "ecn_prob" => result.ecn_prob = value.as_u64().unwrap_or(0) as u32,
+                        "ecn" => result.ecn = value.as_bool().unwrap_or(false),
+                        "bytemode" => result.bytemode = value.as_bool().unwrap_or(false),
+                        "dq_rate_estimator" => {
+                            result.dq_rate_estimator = value.as_bool().unwrap_or(false)
+                        }
+                        _ => {
+                            info!("Unknown entry in tc-fq_pie-options json decoder: {key}");
+                        }
+                    }
+                }
+                Ok(result)
+            }
+            _ => Err(QDiscError::FqPieOpts),
+        }
+    }
+}
@@ -6,10 +6,15 @@
 use super::QDiscError;
 use crate::parse_tc_handle;
 use lqos_bus::TcHandle;
+use lqos_netlink::TcMessage;
 use serde::Serialize;
 use serde_json::Value;
 use tracing::info;
 
+// Netlink attributes (`TCA_HTB_*`)
+const TCA_HTB_INIT: u16 = 2;
+const TCA_HTB_DIRECT_QLEN: u16 = 5;
+
 #[derive(Default, Clone, Debug, Serialize)]
 pub struct TcHtb {
     handle: TcHandle,
@@ -61,6 +66,34 @@ impl TcHtb {
         }
         Ok(result)
     }
+
+    pub(crate) fn from_netlink(message: &TcMessage) -> Self {
+        let mut options = TcHtbOptions::default();
+        for attr in message.options() {
+            match attr.kind {
+                // struct tc_htb_glob: version, rate2quantum, defcls, debug, direct_pkts
+                TCA_HTB_INIT => {
+                    options.r2q = attr.u32_at(4).unwrap_or(0);
+                    options.default = TcHandle::from_u32(attr.u32_at(8).unwrap_or(0));
+                    options.direct_packets_stat = attr.u32_at(16).unwrap_or(0);
+                }
+                TCA_HTB_DIRECT_QLEN => options.direct_qlen = attr.u32().unwrap_or(0),
+                _ => {}
+            }
+        }
+        Self {
+            handle: TcHandle::from_u32(message.handle),
+            parent: TcHandle::from_u32(message.parent),
+            bytes: message.basic.bytes,
+            packets: message.basic.packets as u32,
+            drops: message.queue.drops,
+            overlimits: message.queue.overlimits,
+            requeues: message.queue.requeues,
+            backlog: message.queue.backlog,
+            qlen: message.queue.qlen,
+            options,
+        }
+    }
 }
 
 impl TcHtbOptions {
@@ -5,6 +5,7 @@
 use super::QDiscError;
 use crate::parse_tc_handle;
 use lqos_bus::TcHandle;
+use lqos_netlink::TcMessage;
 use serde::Serialize;
 use serde_json::Value;
 use tracing::info;
@@ -49,4 +50,18 @@ impl TcMultiQueue {
         }
         Ok(result)
     }
+
+    pub(crate) fn from_netlink(message: &TcMessage) -> Self {
+        Self {
+            handle: TcHandle::from_u32(message.handle),
+            root: message.is_root(),
+            bytes: message.basic.bytes,
+            packets: message.basic.packets as u32,
+            drops: message.queue.drops,
+            overlimits: message.queue.overlimits,
+            requeues: message.queue.requeues,
+            backlog: message.queue.backlog,
+            qlen: message.queue.qlen,
+        }
+    }
 }
@@ -0,0 +1,120 @@
+/*
+{"kind":"sfq","handle":"0:","parent":"7fff:a",
+    "options":{"limit":127,"quantum":1514,"depth":127,"divisor":1024},
+    "bytes":560,"packets":8,"drops":0,"overlimits":0,"requeues":0,"backlog":0,"qlen":0},
+*/
+
+use super::QDiscError;
+use crate::parse_tc_handle;
+use lqos_bus::TcHandle;
+use lqos_netlink::{Attribute, TcMessage};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::info;
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct TcSfq {
+    pub(crate) handle: TcHandle,
+    pub(crate) parent: TcHandle,
+    pub(crate) options: TcSfqOptions,
+    pub(crate) bytes: u64,
+    pub(crate) packets: u32,
+    pub(crate) drops: u32,
+    pub(crate) overlimits: u32,
+    pub(crate) requeues: u32,
+    pub(crate) backlog: u32,
+    pub(crate) qlen: u32,
+}
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub(crate) struct TcSfqOptions {
+    pub(crate) limit: u32,
+    pub(crate) quantum: u32,
+    pub(crate) depth: u32,
+    pub(crate) divisor: u32,
+    pub(crate) perturb: u32, // seconds
+}
+
+impl TcSfq {
+    pub(crate) fn from_json(
+        map: &serde_json::Map<std::string::String, Value>,
+    ) -> Result<Self, QDiscError> {
+        let mut result = Self::default();
+        for (key, value) in map.iter() {
+            match key.as_str() {
+                "handle" => {
+                    parse_tc_handle!(result.handle, value);
+                }
+                "parent" => {
+                    parse_tc_handle!(result.parent, value);
+                }
+                "bytes" => result.bytes = value.as_u64().unwrap_or(0),
+                "packets" => result.packets = value.as_u64().unwrap_or(0) as u32,
+                "drops" => result.drops = value.as_u64().unwrap_or(0) as u32,
+                "overlimits" => result.overlimits = value.as_u64().unwrap_or(0) as u32,
+                "requeues" => result.requeues = value.as_u64().unwrap_or(0) as u32,
+                "backlog" => result.backlog = value.as_u64().unwrap_or(0) as u32,
+                "qlen" => result.qlen = value.as_u64().unwrap_or(0) as u32,
+                "options" => result.options = TcSfqOptions::from_json(value)?,
+                "kind" => {}
+                _ => {
+                    info!("Unknown entry in tc-sfq json decoder: {key}");
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    pub(crate) fn from_netlink(message: &TcMessage) -> Self {
+        // sfq dumps a bare `struct tc_sfq_qopt_v1` rather than attributes:
+        // quantum, perturb_period, limit, divisor, flows, then depth.
+        let qopt = Attribute {
+            kind: 0,
+            payload: &message.options,
+        };
+        Self {
+            handle: TcHandle::from_u32(message.handle),
+            parent: TcHandle::from_u32(message.parent),
+            options: TcSfqOptions {
+                quantum: qopt.u32_at(0).unwrap_or(0),
+                perturb: qopt.u32_at(4).unwrap_or(0),
+                divisor: qopt.u32_at(12).unwrap_or(0),
+                depth: qopt.u32_at(20).unwrap_or(0),
+                // v1 carries the effective limit after the RED block
+                limit: qopt.u32_at(28).or_else(|| qopt.u32_at(8)).unwrap_or(0),
+            },
+            bytes: message.basic.bytes,
+            packets: message.basic.packets as u32,
+            drops: message.queue.drops,
+            overlimits: message.queue.overlimits,
+            requeues: message.queue.requeues,
+            backlog: message.queue.backlog,
+            qlen: message.queue.qlen,
+        }
+    }
+}
+
+impl TcSfqOptions {
+    fn from_json(value: &Value) -> Result<Self, QDiscError> {
+        match value {
+            Value::Object(map) => {
+                let mut result = Self::default();
+                for (key, value) in map.iter() {
+                    match key.as_str() {
+                        "limit" => result.limit = value.as_u64().unwrap_or(0) as u32,
+                        "quantum" => result.quantum = value.as_u64().unwrap_or(0) as u32,
+                        "depth" => result.depth = value.as_u64().unwrap_or(0) as u32,
+                        "divisor" => result.divisor = value.as_u64().unwrap_or(0) as u32,
+                        "perturb" => result.perturb = value.as_u64().unwrap_or(0) as u32,
+                        "flows" | "headdrop" => {}
+                        _ => {
+                            info!("Unknown entry in tc-sfq-options json decoder: {key}");
+                        }
+                    }
+                }
+                Ok(result)
+            }
+            _ => Err(QDiscError::SfqOpts),
+        }
+    }
+}
@@ -1,12 +1,38 @@
-use crate::{deserialize_tc_tree, queue_types::QueueType};
+use crate::deserialize_tc_tree;
+use crate::queue_types::{QueueType, decode_netlink_qdiscs};
 use lqos_bus::TcHandle;
+use lqos_netlink::NetlinkError;
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
 use thiserror::Error;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 const TC: &str = "/sbin/tc";
 
+/// Set once netlink has failed, so the fallback is only announced once.
+static NETLINK_WARNED: AtomicBool = AtomicBool::new(false);
+
+fn warn_netlink_fallback(error: &NetlinkError) {
+    if !NETLINK_WARNED.swap(true, Ordering::Relaxed) {
+        warn!("Unable to read queues over netlink ({error}); falling back to tc");
+    }
+}
+
 pub fn read_all_queues_from_interface(interface: &str) -> Result<Vec<QueueType>, QueueReaderError> {
+    match lqos_netlink::dump_qdiscs(interface) {
+        Ok(messages) => decode_netlink_qdiscs(&messages).map_err(|e| {
+            debug!("Failed to decode netlink qdisc dump.");
+            debug!("{:?}", e);
+            QueueReaderError::Deserialization
+        }),
+        Err(e) => {
+            warn_netlink_fallback(&e);
+            read_all_queues_with_tc(interface)
+        }
+    }
+}
+
+fn read_all_queues_with_tc(interface: &str) -> Result<Vec<QueueType>, QueueReaderError> {
     let command_output = Command::new(TC)
         .args(["-s", "-j", "qdisc", "show", "dev", interface])
         .output()
@@ -33,6 +59,23 @@ pub fn read_all_queues_from_interface(interface: &str) -> Result<Vec<QueueType>,
 pub fn read_named_queue_from_interface(
     interface: &str,
     tc_handle: TcHandle,
+) -> Result<Vec<QueueType>, QueueReaderError> {
+    match lqos_netlink::get_qdisc_by_parent(interface, tc_handle.as_u32()) {
+        Ok(message) => decode_netlink_qdiscs(message.as_slice()).map_err(|e| {
+            error!("Failed to decode netlink qdisc for {tc_handle}.");
+            error!("{:?}", e);
+            QueueReaderError::Deserialization
+        }),
+        Err(e) => {
+            warn_netlink_fallback(&e);
+            read_named_queue_with_tc(interface, tc_handle)
+        }
+    }
+}
+
+fn read_named_queue_with_tc(
+    interface: &str,
+    tc_handle: TcHandle,
 ) -> Result<Vec<QueueType>, QueueReaderError> {
     let command_output = Command::new(TC)
         .args([
@@ -153,6 +153,7 @@ fn current_site_override(
                 site_name: current,
                 download_bandwidth_mbps,
                 upload_bandwidth_mbps,
+                ..
             } if current == site_name => Some((*download_bandwidth_mbps, *upload_bandwidth_mbps)),
             _ => None,
         })
@@ -194,6 +195,7 @@ fn has_sqm_override_for_device_ids(overrides: &OverrideFile, device_ids: &HashSe
             lqos_overrides::CircuitAdjustment::DeviceAdjustSqm {
                 device_id,
                 sqm_override,
+                ..
             } if device_ids.contains(device_id.as_str())
                 && sqm_override
                     .as_deref()
@@ -352,6 +354,7 @@ fn group_circuit_fallbacks(
         let lqos_overrides::CircuitAdjustment::DeviceAdjustSqm {
             device_id,
             sqm_override,
+            ..
         } = adj
         else {
             continue;
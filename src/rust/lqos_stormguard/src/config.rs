@@ -145,6 +145,7 @@ fn load_stormguard_site_overrides() -> HashMap<String, (Option<f32>, Option<f32>
                 site_name,
                 download_bandwidth_mbps,
                 upload_bandwidth_mbps,
+                ..
             } => Some((
                 site_name.clone(),
                 (*download_bandwidth_mbps, *upload_bandwidth_mbps),
@@ -21,9 +21,13 @@ use crate::site_state::stormguard_state::StormguardState;
 use crate::{MOVING_AVERAGE_BUFFER_SIZE, READING_ACCUMULATOR_SIZE};
 use crossbeam_channel::Sender;
 use lqos_bakery::BakeryCommands;
-use lqos_bus::{StormguardDebugDirection, StormguardDebugEntry, TcHandle};
-use lqos_config::NetworkJsonTransport;
+use lqos_bus::{
+    BusTopic, BusUpdate, StormGuardAction, StormguardDebugDirection, StormguardDebugEntry,
+    TcHandle, has_bus_subscribers, publish_bus_update,
+};
+use lqos_config::{AuditActor, AuditEntry, NetworkJsonTransport, queue_audit_entry};
 use lqos_queue_tracker::QUEUE_STRUCTURE;
+use lqos_utils::unix_time::unix_now;
 use std::collections::{HashMap, HashSet};
 use std::time::{Duration, Instant};
 use tracing::{debug, info, warn};
@@ -551,17 +555,27 @@ impl SiteStateTracker {
                     cooldown_secs,
                     recommendation.action,
                 );
-                let _ = log_sender.send(LogCommand::SpeedChange {
-                    site: recommendation.site.clone(),
-                    download: site.queue_download_mbps,
-                    upload: site.queue_upload_mbps,
-                    state: format!("{summary}; dry_run_target={new_rate}"),
-                });
+                Self::report_action(
+                    &log_sender,
+                    &recommendation.site,
+                    site,
+                    true,
+                    format!("{summary}; dry_run_target={new_rate}"),
+                );
                 continue;
             }
 
             // Apply to the site
             Self::set_site_rate(site, recommendation.direction, new_rate);
+            Self::audit(
+                AuditEntry::new(AuditActor::subsystem("stormguard"), "set_site_rate")
+                    .target(format!(
+                        "{}:{}",
+                        recommendation.site, recommendation.direction
+                    ))
+                    .detail(summary.clone())
+                    .changes(Some(&(current_rate as u64)), Some(&new_rate)),
+            );
 
             // Actually make the change
             Self::apply_dependents(
@@ -591,12 +605,7 @@ impl SiteStateTracker {
             );
 
             // Report
-            let _ = log_sender.send(LogCommand::SpeedChange {
-                site: recommendation.site.clone(),
-                download: site.queue_download_mbps,
-                upload: site.queue_upload_mbps,
-                state: summary,
-            });
+            Self::report_action(&log_sender, &recommendation.site, site, false, summary);
         }
 
         if !pending_site_updates.is_empty() {
@@ -677,6 +686,21 @@ impl SiteStateTracker {
             }
         };
 
+        let audit_action = match &outcome {
+            CircuitFallbackOutcome::Applied { .. } => Some("apply_circuit_fallback"),
+            CircuitFallbackOutcome::Cleared { .. } => Some("clear_circuit_fallback"),
+            _ => None,
+        };
+        let dry_run = matches!(outcome, CircuitFallbackOutcome::DryRun { .. });
+        let acted = audit_action.is_some() || dry_run;
+        if let Some(action) = audit_action {
+            Self::audit(
+                AuditEntry::new(AuditActor::subsystem("stormguard"), action)
+                    .target(circuit_id)
+                    .detail(format!("{summary}; sqm={}", config.circuit_fallback_sqm)),
+            );
+        }
+
         let (outcome_text, enters_cooldown) = match outcome {
             CircuitFallbackOutcome::Applied { persisted } => {
                 active_circuit_fallbacks.insert(circuit_id.to_string());
@@ -727,12 +751,17 @@ impl SiteStateTracker {
                 recommendation.action,
             );
         }
-        let _ = log_sender.send(LogCommand::SpeedChange {
-            site: recommendation.site.clone(),
-            download: site.queue_download_mbps,
-            upload: site.queue_upload_mbps,
-            state: format!("{summary}; {outcome_text}"),
-        });
+        let state = format!("{summary}; {outcome_text}");
+        if acted {
+            Self::report_action(log_sender, &recommendation.site, site, dry_run, state);
+        } else {
+            let _ = log_sender.send(LogCommand::SpeedChange {
+                site: recommendation.site.clone(),
+                download: site.queue_download_mbps,
+                upload: site.queue_upload_mbps,
+                state,
+            });
+        }
     }
 
     fn site_rate(site: &SiteState, direction: RecommendationDirection) -> u64 {
@@ -842,6 +871,37 @@ impl SiteStateTracker {
         }
     }
 
+    /// Writes a change to the StormGuard log and tells bus subscribers.
+    fn report_action(
+        log_sender: &std::sync::mpsc::Sender<LogCommand>,
+        site_name: &str,
+        site: &SiteState,
+        dry_run: bool,
+        state: String,
+    ) {
+        if has_bus_subscribers(BusTopic::StormGuard) {
+            publish_bus_update(BusUpdate::StormGuardAction(StormGuardAction {
+                ts: unix_now().unwrap_or_default(),
+                site: site_name.to_string(),
+                download_mbps: site.queue_download_mbps,
+                upload_mbps: site.queue_upload_mbps,
+                dry_run,
+                summary: state.clone(),
+            }));
+        }
+        let _ = log_sender.send(LogCommand::SpeedChange {
+            site: site_name.to_string(),
+            download: site.queue_download_mbps,
+            upload: site.queue_upload_mbps,
+            state,
+        });
+    }
+
+    /// Queued for the shared writer, so the control loop never waits on disk.
+    fn audit(entry: AuditEntry) {
+        queue_audit_entry(entry);
+    }
+
     fn circuit_outcome_enters_cooldown(enters_cooldown: &bool) -> bool {
         *enters_cooldown
     }
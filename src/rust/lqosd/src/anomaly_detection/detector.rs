@@ -0,0 +1,513 @@
+//! Baselines and the anomaly test, independent of where the counters come
+//! from so they can be driven with synthetic traffic.
+
+use fxhash::FxHashMap;
+use lqos_config::AnomalyDetectionConfig;
+use lqos_utils::units::DownUpOrder;
+use std::hash::Hash;
+
+/// Ticks a subject has to be observed before it can be flagged.
+const WARMUP_TICKS: u32 = 60;
+/// Subjects idle for this many ticks are forgotten.
+const FORGET_AFTER_IDLE_TICKS: u32 = 3600;
+/// While a subject is anomalous its baseline learns this much slower, so an
+/// attack isn't absorbed into "normal" within a few minutes.
+const ANOMALOUS_LEARNING_DIVISOR: f64 = 10.0;
+/// A protocol mix typical of an attack is flagged at this fraction of the
+/// deviation factor.
+const MIX_FACTOR_DIVISOR: f64 = 2.0;
+
+/// One tick's traffic for a circuit or site. Download is traffic towards
+/// the customer, upload is traffic from it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TrafficCounters {
+    pub bytes: DownUpOrder<u64>,
+    pub packets: DownUpOrder<u64>,
+    pub tcp_packets: DownUpOrder<u64>,
+    pub udp_packets: DownUpOrder<u64>,
+    pub icmp_packets: DownUpOrder<u64>,
+    /// Flows first seen this tick.
+    pub new_flows: u64,
+    /// TCP flows first seen this tick.
+    pub new_tcp_flows: u64,
+}
+
+impl TrafficCounters {
+    pub fn add(&mut self, other: &TrafficCounters) {
+        self.bytes.checked_add(other.bytes);
+        self.packets.checked_add(other.packets);
+        self.tcp_packets.checked_add(other.tcp_packets);
+        self.udp_packets.checked_add(other.udp_packets);
+        self.icmp_packets.checked_add(other.icmp_packets);
+        self.new_flows = self.new_flows.saturating_add(other.new_flows);
+        self.new_tcp_flows = self.new_tcp_flows.saturating_add(other.new_tcp_flows);
+    }
+
+    fn direction(&self, direction: AnomalyDirection) -> DirectionCounters {
+        let pick = |counter: &DownUpOrder<u64>| match direction {
+            AnomalyDirection::Inbound => counter.down,
+            AnomalyDirection::Outbound => counter.up,
+        };
+        DirectionCounters {
+            bits: pick(&self.bytes).saturating_mul(8),
+            packets: pick(&self.packets),
+            tcp: pick(&self.tcp_packets),
+            udp: pick(&self.udp_packets),
+            icmp: pick(&self.icmp_packets),
+        }
+    }
+}
+
+struct DirectionCounters {
+    bits: u64,
+    packets: u64,
+    tcp: u64,
+    udp: u64,
+    icmp: u64,
+}
+
+impl DirectionCounters {
+    fn share(&self, count: u64) -> f64 {
+        if self.packets == 0 {
+            0.0
+        } else {
+            count as f64 / self.packets as f64
+        }
+    }
+
+    fn mean_packet_bytes(&self) -> f64 {
+        if self.packets == 0 {
+            0.0
+        } else {
+            self.bits as f64 / 8.0 / self.packets as f64
+        }
+    }
+}
+
+/// Which way the anomalous traffic flows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnomalyDirection {
+    /// Towards the customer: they are the target.
+    Inbound,
+    /// From the customer: they are the source.
+    Outbound,
+}
+
+impl AnomalyDirection {
+    const BOTH: [AnomalyDirection; 2] = [AnomalyDirection::Inbound, AnomalyDirection::Outbound];
+
+    fn index(self) -> usize {
+        match self {
+            Self::Inbound => 0,
+            Self::Outbound => 1,
+        }
+    }
+}
+
+/// What the anomalous traffic looks like.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttackKind {
+    /// Mostly UDP packets.
+    UdpFlood,
+    /// Mostly ICMP packets.
+    IcmpFlood,
+    /// Bursts of new TCP flows made of small packets.
+    SynFlood,
+    /// Too much traffic with no telltale protocol mix.
+    Volumetric,
+}
+
+impl AttackKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::UdpFlood => "UDP flood",
+            Self::IcmpFlood => "ICMP flood",
+            Self::SynFlood => "SYN flood",
+            Self::Volumetric => "volumetric flood",
+        }
+    }
+}
+
+/// A newly confirmed anomaly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anomaly<K> {
+    pub subject: K,
+    pub direction: AnomalyDirection,
+    pub kind: AttackKind,
+    pub packets_per_second: u64,
+    pub baseline_packets_per_second: u64,
+    pub bits_per_second: u64,
+    pub baseline_bits_per_second: u64,
+    pub new_flows_per_second: u64,
+    pub baseline_new_flows_per_second: u64,
+}
+
+/// Changes reported by one `observe` pass.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnomalyEvent<K> {
+    Started(Anomaly<K>),
+    /// The subject's traffic is back within its baseline.
+    Ended(K),
+}
+
+/// Detection thresholds, taken from `[anomaly_detection]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DetectorSettings {
+    pub baseline_seconds: u32,
+    pub deviation_factor: f64,
+    pub min_packets_per_second: u64,
+    pub min_bits_per_second: u64,
+    pub min_new_flows_per_second: u64,
+    pub sustain_ticks: u32,
+}
+
+impl From<&AnomalyDetectionConfig> for DetectorSettings {
+    fn from(config: &AnomalyDetectionConfig) -> Self {
+        Self {
+            baseline_seconds: config.baseline_seconds,
+            deviation_factor: config.deviation_factor,
+            min_packets_per_second: config.min_packets_per_second,
+            min_bits_per_second: config.min_mbps.saturating_mul(1_000_000),
+            min_new_flows_per_second: config.min_new_flows_per_second,
+            sustain_ticks: config.sustain_seconds,
+        }
+    }
+}
+
+/// Exponentially weighted moving averages for one direction.
+#[derive(Clone, Copy, Debug, Default)]
+struct DirectionBaseline {
+    packets: f64,
+    bits: f64,
+    udp_share: f64,
+    icmp_share: f64,
+}
+
+#[derive(Debug, Default)]
+struct Baseline {
+    directions: [DirectionBaseline; 2],
+    new_flows: f64,
+    samples: u32,
+    idle_ticks: u32,
+    /// Consecutive anomalous ticks.
+    anomalous_ticks: u32,
+    reported: bool,
+}
+
+/// Tracks baselines for a set of subjects (circuits or sites) and flags
+/// the ones whose traffic jumps well above them.
+pub struct Detector<K> {
+    settings: DetectorSettings,
+    baselines: FxHashMap<K, Baseline>,
+}
+
+impl<K: Eq + Hash + Clone> Detector<K> {
+    pub fn new(settings: DetectorSettings) -> Self {
+        Self {
+            settings,
+            baselines: FxHashMap::default(),
+        }
+    }
+
+    /// Applies new thresholds, keeping the learned baselines.
+    pub fn set_settings(&mut self, settings: DetectorSettings) {
+        self.settings = settings;
+    }
+
+    /// Feeds one tick of traffic. Subjects missing from `samples` are
+    /// treated as idle for the tick.
+    pub fn observe(&mut self, samples: &FxHashMap<K, TrafficCounters>) -> Vec<AnomalyEvent<K>> {
+        let idle = TrafficCounters::default();
+        let mut events = Vec::new();
+        for subject in samples.keys() {
+            if !self.baselines.contains_key(subject) {
+                self.baselines.insert(subject.clone(), Baseline::default());
+            }
+        }
+        let settings = &self.settings;
+        self.baselines.retain(|subject, baseline| {
+            let counters = samples.get(subject);
+            if counters.is_none() {
+                baseline.idle_ticks += 1;
+            } else {
+                baseline.idle_ticks = 0;
+            }
+            let event = Self::evaluate(settings, subject, baseline, counters.unwrap_or(&idle));
+            if let Some(event) = event {
+                events.push(event);
+            }
+            baseline.reported || baseline.idle_ticks < FORGET_AFTER_IDLE_TICKS
+        });
+        events
+    }
+
+    fn evaluate(
+        settings: &DetectorSettings,
+        subject: &K,
+        baseline: &mut Baseline,
+        counters: &TrafficCounters,
+    ) -> Option<AnomalyEvent<K>> {
+        let warmed_up = baseline.samples >= WARMUP_TICKS;
+        let factor = settings.deviation_factor;
+        let new_flows = counters.new_flows;
+        let new_flows_anomalous = new_flows >= settings.min_new_flows_per_second
+            && new_flows as f64 > baseline.new_flows * factor;
+
+        let mut worst: Option<Anomaly<K>> = None;
+        for direction in AnomalyDirection::BOTH {
+            let current = counters.direction(direction);
+            let learned = baseline.directions[direction.index()];
+            let packets_high = current.packets >= settings.min_packets_per_second;
+            let bits_high = current.bits >= settings.min_bits_per_second;
+            let packets_over = current.packets as f64 > learned.packets * factor;
+            let bits_over = current.bits as f64 > learned.bits * factor;
+            let kind = classify(
+                &current,
+                new_flows_anomalous,
+                new_flows,
+                counters.new_tcp_flows,
+            );
+            let mix_shifted = match kind {
+                AttackKind::UdpFlood => learned.udp_share < 0.5,
+                AttackKind::IcmpFlood => learned.icmp_share < 0.2,
+                AttackKind::SynFlood => true,
+                AttackKind::Volumetric => false,
+            };
+            let mix_over = mix_shifted
+                && packets_high
+                && current.packets as f64 > learned.packets * factor / MIX_FACTOR_DIVISOR;
+            let flows_over = new_flows_anomalous && current.packets > 0;
+            let anomalous = (packets_high && packets_over)
+                || (bits_high && bits_over)
+                || mix_over
+                || flows_over;
+            if !anomalous {
+                continue;
+            }
+            let candidate = Anomaly {
+                subject: subject.clone(),
+                direction,
+                kind,
+                packets_per_second: current.packets,
+                baseline_packets_per_second: learned.packets as u64,
+                bits_per_second: current.bits,
+                baseline_bits_per_second: learned.bits as u64,
+                new_flows_per_second: new_flows,
+                baseline_new_flows_per_second: baseline.new_flows as u64,
+            };
+            if worst
+                .as_ref()
+                .is_none_or(|w| candidate.packets_per_second > w.packets_per_second)
+            {
+                worst = Some(candidate);
+            }
+        }
+
+        let mut event = None;
+        match (warmed_up, worst) {
+            (true, Some(anomaly)) => {
+                baseline.anomalous_ticks = baseline.anomalous_ticks.saturating_add(1);
+                if !baseline.reported && baseline.anomalous_ticks >= settings.sustain_ticks {
+                    baseline.reported = true;
+                    event = Some(AnomalyEvent::Started(anomaly));
+                }
+            }
+            _ => {
+                baseline.anomalous_ticks = 0;
+                if baseline.reported {
+                    baseline.reported = false;
+                    event = Some(AnomalyEvent::Ended(subject.clone()));
+                }
+            }
+        }
+
+        // Learn, quickly at first, then over the configured window.
+        let mut alpha = (1.0 / settings.baseline_seconds.max(1) as f64)
+            .max(1.0 / (baseline.samples as f64 + 1.0));
+        if baseline.anomalous_ticks > 0 {
+            alpha /= ANOMALOUS_LEARNING_DIVISOR;
+        }
+        for direction in AnomalyDirection::BOTH {
+            let current = counters.direction(direction);
+            let learned = &mut baseline.directions[direction.index()];
+            learned.packets += alpha * (current.packets as f64 - learned.packets);
+            learned.bits += alpha * (current.bits as f64 - learned.bits);
+            if current.packets > 0 {
+                learned.udp_share += alpha * (current.share(current.udp) - learned.udp_share);
+                learned.icmp_share += alpha * (current.share(current.icmp) - learned.icmp_share);
+            }
+        }
+        baseline.new_flows += alpha * (new_flows as f64 - baseline.new_flows);
+        baseline.samples = baseline.samples.saturating_add(1);
+        event
+    }
+}
+
+/// Names the attack a direction's protocol mix looks like.
+fn classify(
+    current: &DirectionCounters,
+    new_flows_anomalous: bool,
+    new_flows: u64,
+    new_tcp_flows: u64,
+) -> AttackKind {
+    if current.share(current.icmp) >= 0.5 {
+        AttackKind::IcmpFlood
+    } else if current.share(current.udp) >= 0.8 {
+        AttackKind::UdpFlood
+    } else if current.share(current.tcp) >= 0.8
+        && current.mean_packet_bytes() < 128.0
+        && new_flows_anomalous
+        && new_tcp_flows.saturating_mul(2) >= new_flows
+    {
+        AttackKind::SynFlood
+    } else {
+        AttackKind::Volumetric
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> DetectorSettings {
+        DetectorSettings::from(&AnomalyDetectionConfig::default())
+    }
+
+    /// Mostly TCP, 1,000-byte packets, a handful of new flows.
+    fn normal(pps: u64) -> TrafficCounters {
+        let down = pps;
+        let up = pps / 2;
+        TrafficCounters {
+            bytes: DownUpOrder::new(down * 1_000, up * 100),
+            packets: DownUpOrder::new(down, up),
+            tcp_packets: DownUpOrder::new(down * 9 / 10, up * 9 / 10),
+            udp_packets: DownUpOrder::new(down / 10, up / 10),
+            icmp_packets: DownUpOrder::zeroed(),
+            new_flows: 20,
+            new_tcp_flows: 15,
+        }
+    }
+
+    fn run(
+        detector: &mut Detector<&'static str>,
+        subject: &'static str,
+        counters: TrafficCounters,
+        ticks: u32,
+    ) -> Vec<AnomalyEvent<&'static str>> {
+        let samples: FxHashMap<_, _> = [(subject, counters)].into_iter().collect();
+        (0..ticks)
+            .flat_map(|_| detector.observe(&samples))
+            .collect()
+    }
+
+    fn started(events: &[AnomalyEvent<&'static str>]) -> Vec<Anomaly<&'static str>> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                AnomalyEvent::Started(anomaly) => Some(anomaly.clone()),
+                AnomalyEvent::Ended(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn steady_traffic_is_not_flagged() {
+        let mut detector = Detector::new(settings());
+        assert!(run(&mut detector, "c1", normal(10_000), 600).is_empty());
+    }
+
+    #[test]
+    fn inbound_udp_flood_is_flagged_after_it_persists() {
+        let mut detector = Detector::new(settings());
+        run(&mut detector, "c1", normal(2_000), 300);
+        let mut flood = normal(2_000);
+        flood.packets.down = 400_000;
+        flood.udp_packets.down = 395_000;
+        flood.tcp_packets.down = 5_000;
+        flood.bytes.down = 400_000 * 500;
+
+        assert!(
+            run(&mut detector, "c1", flood, 2).is_empty(),
+            "not sustained yet"
+        );
+        let events = run(&mut detector, "c1", flood, 10);
+        let anomalies = started(&events);
+        assert_eq!(anomalies.len(), 1, "reported once");
+        let anomaly = &anomalies[0];
+        assert_eq!(anomaly.subject, "c1");
+        assert_eq!(anomaly.direction, AnomalyDirection::Inbound);
+        assert_eq!(anomaly.kind, AttackKind::UdpFlood);
+        assert_eq!(anomaly.packets_per_second, 400_000);
+        assert!(anomaly.baseline_packets_per_second < 3_000);
+
+        let events = run(&mut detector, "c1", normal(2_000), 1);
+        assert_eq!(events, vec![AnomalyEvent::Ended("c1")]);
+    }
+
+    #[test]
+    fn outbound_syn_flood_is_flagged() {
+        let mut detector = Detector::new(settings());
+        run(&mut detector, "c1", normal(2_000), 300);
+        let mut flood = normal(2_000);
+        flood.packets.up = 60_000;
+        flood.tcp_packets.up = 59_000;
+        flood.udp_packets.up = 1_000;
+        flood.bytes.up = 60_000 * 60;
+        flood.new_flows = 55_000;
+        flood.new_tcp_flows = 54_000;
+
+        let anomalies = started(&run(&mut detector, "c1", flood, 3));
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].direction, AnomalyDirection::Outbound);
+        assert_eq!(anomalies[0].kind, AttackKind::SynFlood);
+    }
+
+    #[test]
+    fn icmp_mix_shift_is_flagged_below_the_deviation_factor() {
+        let mut detector = Detector::new(settings());
+        run(&mut detector, "site", normal(10_000), 300);
+        // 5x the usual packet rate: below the 8x factor, but almost all ICMP.
+        let mut flood = normal(10_000);
+        flood.packets.down = 50_000;
+        flood.icmp_packets.down = 45_000;
+        flood.tcp_packets.down = 4_000;
+        flood.udp_packets.down = 1_000;
+
+        let anomalies = started(&run(&mut detector, "site", flood, 3));
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].kind, AttackKind::IcmpFlood);
+    }
+
+    #[test]
+    fn small_circuits_stay_below_the_floors() {
+        let mut detector = Detector::new(settings());
+        run(&mut detector, "c1", normal(10), 300);
+        // 100x the baseline, but only 1,000 pps and 8 Mbps.
+        let mut burst = normal(10);
+        burst.packets.down = 1_000;
+        burst.bytes.down = 1_000_000;
+        assert!(run(&mut detector, "c1", burst, 20).is_empty());
+    }
+
+    #[test]
+    fn nothing_is_flagged_during_warmup() {
+        let mut detector = Detector::new(settings());
+        run(&mut detector, "c1", normal(10), 10);
+        let mut flood = normal(10);
+        flood.packets.down = 500_000;
+        flood.udp_packets.down = 500_000;
+        assert!(run(&mut detector, "c1", flood, 10).is_empty());
+    }
+
+    #[test]
+    fn idle_subjects_are_forgotten() {
+        let mut detector = Detector::new(settings());
+        run(&mut detector, "c1", normal(10), 1);
+        assert_eq!(detector.baselines.len(), 1);
+        let empty = FxHashMap::default();
+        for _ in 0..FORGET_AFTER_IDLE_TICKS {
+            detector.observe(&empty);
+        }
+        assert_eq!(detector.baselines.len(), 0);
+    }
+}
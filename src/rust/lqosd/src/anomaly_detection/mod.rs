@@ -0,0 +1,425 @@
+//! Traffic anomaly (DDoS) detection.
+//!
+//! When `[anomaly_detection] enabled` is set, the throughput tracker hands
+//! every active host's counters for the tick to `observe`. They are summed
+//! per circuit and per site and compared against moving baselines of packet,
+//! bit and new-flow rates. A sustained jump, or a protocol mix typical of an
+//! attack, raises an urgent issue naming the circuit or site, the kind of
+//! flood, and the busiest IPs and ports seen by the flow tracker.
+
+mod detector;
+
+use crate::shaped_devices_tracker::SHAPED_DEVICES;
+use crate::throughput_tracker::flow_data::ALL_FLOWS;
+use crate::urgent;
+pub(crate) use detector::TrafficCounters;
+use detector::{Anomaly, AnomalyDirection, AnomalyEvent, AttackKind, Detector, DetectorSettings};
+use fxhash::{FxHashMap, FxHashSet};
+use lqos_bus::{UrgentSeverity, UrgentSource};
+use lqos_config::NetworkJson;
+use lqos_utils::XdpIpAddress;
+use lqos_utils::packet_scale::{scale_bits, scale_packets};
+use lqos_utils::unix_time::time_since_boot;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::net::IpAddr;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const URGENT_CODE: &str = "TRAFFIC_ANOMALY";
+/// IPs and ports listed per anomaly.
+const TOP_OFFENDERS: usize = 5;
+/// Only flows seen this recently are considered part of an anomaly.
+const RECENT_FLOW_SECONDS: u64 = 5;
+
+/// One host's traffic for a tick.
+pub(crate) struct HostTraffic {
+    pub(crate) ip: XdpIpAddress,
+    pub(crate) circuit_hash: Option<i64>,
+    pub(crate) network_json_parents: Vec<usize>,
+    pub(crate) counters: TrafficCounters,
+}
+
+struct Detectors {
+    circuits: Detector<i64>,
+    /// Keyed by node name, so baselines survive `network.json` reloads.
+    sites: Detector<String>,
+}
+
+static DETECTORS: Lazy<Mutex<Option<Detectors>>> = Lazy::new(|| Mutex::new(None));
+
+/// Is anomaly detection turned on?
+pub(crate) fn is_enabled() -> bool {
+    lqos_config::load_config().is_ok_and(|config| config.anomaly_detection.enabled)
+}
+
+/// Called once per throughput tick with every active host's traffic.
+pub(crate) fn observe(hosts: Vec<HostTraffic>, net_json: &NetworkJson) {
+    let Ok(config) = lqos_config::load_config() else {
+        return;
+    };
+    let settings = DetectorSettings::from(&config.anomaly_detection);
+    let include_sites = config.anomaly_detection.include_sites;
+    let nodes = net_json.get_nodes_when_ready();
+
+    let mut circuits: FxHashMap<i64, TrafficCounters> = FxHashMap::default();
+    let mut sites_by_index: FxHashMap<usize, TrafficCounters> = FxHashMap::default();
+    for host in &hosts {
+        if let Some(circuit_hash) = host.circuit_hash {
+            circuits
+                .entry(circuit_hash)
+                .or_default()
+                .add(&host.counters);
+        }
+        if include_sites {
+            // Index 0 is the root of the tree: the whole network.
+            for parent in host.network_json_parents.iter().filter(|i| **i != 0) {
+                sites_by_index
+                    .entry(*parent)
+                    .or_default()
+                    .add(&host.counters);
+            }
+        }
+    }
+    let sites: FxHashMap<String, TrafficCounters> = sites_by_index
+        .into_iter()
+        .filter_map(|(index, counters)| Some((nodes.get(index)?.name.clone(), counters)))
+        .collect();
+
+    let (circuit_events, site_events) = {
+        let mut lock = DETECTORS.lock();
+        let detectors = lock.get_or_insert_with(|| Detectors {
+            circuits: Detector::new(settings.clone()),
+            sites: Detector::new(settings.clone()),
+        });
+        detectors.circuits.set_settings(settings.clone());
+        detectors.sites.set_settings(settings);
+        let circuit_events = detectors.circuits.observe(&circuits);
+        let site_events = if include_sites {
+            detectors.sites.observe(&sites)
+        } else {
+            Vec::new()
+        };
+        (circuit_events, site_events)
+    };
+    if circuit_events.is_empty() && site_events.is_empty() {
+        return;
+    }
+
+    let shaped = SHAPED_DEVICES.load();
+    let circuit_label = |circuit_hash: i64| {
+        shaped
+            .devices
+            .iter()
+            .find(|device| device.circuit_hash == circuit_hash)
+            .map(|device| format!("Circuit {} ({})", device.circuit_name, device.circuit_id))
+            .unwrap_or_else(|| format!("Circuit #{circuit_hash}"))
+    };
+    for event in circuit_events {
+        match event {
+            AnomalyEvent::Started(anomaly) => {
+                let local_ips: FxHashSet<XdpIpAddress> = hosts
+                    .iter()
+                    .filter(|host| host.circuit_hash == Some(anomaly.subject))
+                    .map(|host| host.ip)
+                    .collect();
+                let label = circuit_label(anomaly.subject);
+                report(
+                    &label,
+                    &anomaly,
+                    &local_ips,
+                    format!("anomaly_circuit_{}", anomaly.subject),
+                );
+            }
+            AnomalyEvent::Ended(circuit_hash) => {
+                info!(
+                    "{} traffic is back within its baseline",
+                    circuit_label(circuit_hash)
+                );
+            }
+        }
+    }
+    for event in site_events {
+        match event {
+            AnomalyEvent::Started(anomaly) => {
+                let local_ips: FxHashSet<XdpIpAddress> = hosts
+                    .iter()
+                    .filter(|host| {
+                        host.network_json_parents
+                            .iter()
+                            .any(|i| nodes.get(*i).is_some_and(|n| n.name == anomaly.subject))
+                    })
+                    .map(|host| host.ip)
+                    .collect();
+                let label = format!("Site {}", anomaly.subject);
+                report(
+                    &label,
+                    &anomaly,
+                    &local_ips,
+                    format!("anomaly_site_{}", anomaly.subject),
+                );
+            }
+            AnomalyEvent::Ended(site) => {
+                info!("Site {site} traffic is back within its baseline");
+            }
+        }
+    }
+}
+
+/// Raises the urgent issue for a newly confirmed anomaly.
+fn report<K>(
+    label: &str,
+    anomaly: &Anomaly<K>,
+    local_ips: &FxHashSet<XdpIpAddress>,
+    dedupe_key: String,
+) {
+    let message = describe(label, anomaly);
+    let flows = recent_flows(local_ips, anomaly.direction);
+    let context =
+        Offenders::from_flows(&flows, anomaly.kind, anomaly.direction).describe(anomaly.direction);
+    warn!("{message} {context}");
+    urgent::submit(
+        UrgentSource::System,
+        UrgentSeverity::Error,
+        URGENT_CODE.to_string(),
+        message,
+        Some(context),
+        Some(dedupe_key),
+    );
+}
+
+fn describe<K>(label: &str, anomaly: &Anomaly<K>) -> String {
+    let (role, direction) = match anomaly.direction {
+        AnomalyDirection::Inbound => ("the target of", "inbound"),
+        AnomalyDirection::Outbound => ("the source of", "outbound"),
+    };
+    let mut message = format!(
+        "{label} is {role} a probable {}: {} / {} {direction}, baseline {} / {}",
+        anomaly.kind.describe(),
+        scale_packets(anomaly.packets_per_second),
+        scale_bits(anomaly.bits_per_second),
+        scale_packets(anomaly.baseline_packets_per_second),
+        scale_bits(anomaly.baseline_bits_per_second),
+    );
+    if anomaly.new_flows_per_second > anomaly.baseline_new_flows_per_second.saturating_mul(2) {
+        message.push_str(&format!(
+            ", {} new flows/s (baseline {})",
+            anomaly.new_flows_per_second, anomaly.baseline_new_flows_per_second
+        ));
+    }
+    message.push('.');
+    message
+}
+
+/// A flow belonging to an anomalous circuit or site, seen from the
+/// customer's side.
+struct FlowSample {
+    local_ip: IpAddr,
+    remote_ip: IpAddr,
+    protocol: u8,
+    local_port: u16,
+    remote_port: u16,
+    /// Rate in the anomaly's direction.
+    bits_per_second: u64,
+}
+
+/// Recently active flows for a set of hosts.
+fn recent_flows(
+    local_ips: &FxHashSet<XdpIpAddress>,
+    direction: AnomalyDirection,
+) -> Vec<FlowSample> {
+    let Ok(now) = time_since_boot() else {
+        return Vec::new();
+    };
+    let recent = Duration::from(now)
+        .saturating_sub(Duration::from_secs(RECENT_FLOW_SECONDS))
+        .as_nanos() as u64;
+    let all_flows = ALL_FLOWS.lock();
+    all_flows
+        .flow_data
+        .iter()
+        .filter(|(key, (data, _))| data.last_seen >= recent && local_ips.contains(&key.local_ip))
+        .map(|(key, (data, _))| FlowSample {
+            local_ip: key.local_ip.as_ip(),
+            remote_ip: key.remote_ip.as_ip(),
+            protocol: key.ip_protocol,
+            local_port: key.dst_port,
+            remote_port: key.src_port,
+            bits_per_second: match direction {
+                AnomalyDirection::Inbound => data.rate_estimate_bps.down as u64,
+                AnomalyDirection::Outbound => data.rate_estimate_bps.up as u64,
+            },
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Talker {
+    flows: u64,
+    bits_per_second: u64,
+}
+
+/// The busiest remote IPs, ports and local hosts in an anomaly.
+#[derive(Debug, Default, PartialEq)]
+struct Offenders {
+    remote_ips: Vec<(IpAddr, Talker)>,
+    /// "udp/53", "tcp/443" or "icmp". The local port for inbound floods
+    /// (what is being hit), the remote port for outbound ones.
+    ports: Vec<(String, Talker)>,
+    local_ips: Vec<(IpAddr, Talker)>,
+}
+
+impl Offenders {
+    fn from_flows(flows: &[FlowSample], kind: AttackKind, direction: AnomalyDirection) -> Self {
+        let mut remote_ips: FxHashMap<IpAddr, Talker> = FxHashMap::default();
+        let mut ports: FxHashMap<String, Talker> = FxHashMap::default();
+        let mut local_ips: FxHashMap<IpAddr, Talker> = FxHashMap::default();
+        for flow in flows {
+            let port = match direction {
+                AnomalyDirection::Inbound => flow.local_port,
+                AnomalyDirection::Outbound => flow.remote_port,
+            };
+            let port = match flow.protocol {
+                6 => format!("tcp/{port}"),
+                17 => format!("udp/{port}"),
+                1 | 58 => "icmp".to_string(),
+                other => format!("proto {other}"),
+            };
+            for talker in [
+                remote_ips.entry(flow.remote_ip).or_default(),
+                ports.entry(port).or_default(),
+                local_ips.entry(flow.local_ip).or_default(),
+            ] {
+                talker.flows += 1;
+                talker.bits_per_second =
+                    talker.bits_per_second.saturating_add(flow.bits_per_second);
+            }
+        }
+        Self {
+            remote_ips: top(remote_ips, kind),
+            ports: top(ports, kind),
+            local_ips: top(local_ips, kind),
+        }
+    }
+
+    fn describe(&self, direction: AnomalyDirection) -> String {
+        let (remote, local) = match direction {
+            AnomalyDirection::Inbound => ("Sources", "Targeted hosts"),
+            AnomalyDirection::Outbound => ("Destinations", "Sending hosts"),
+        };
+        if self.remote_ips.is_empty() {
+            return "No matching flows were tracked.".to_string();
+        }
+        format!(
+            "{remote}: {}. Ports: {}. {local}: {}.",
+            list(&self.remote_ips),
+            list(&self.ports),
+            list(&self.local_ips)
+        )
+    }
+}
+
+/// The busiest entries: by flow count for SYN floods, where each flow is
+/// tiny, otherwise by rate.
+fn top<T>(talkers: FxHashMap<T, Talker>, kind: AttackKind) -> Vec<(T, Talker)> {
+    let mut talkers: Vec<_> = talkers.into_iter().collect();
+    if kind == AttackKind::SynFlood {
+        talkers.sort_by(|a, b| {
+            (b.1.flows, b.1.bits_per_second).cmp(&(a.1.flows, a.1.bits_per_second))
+        });
+    } else {
+        talkers.sort_by(|a, b| {
+            (b.1.bits_per_second, b.1.flows).cmp(&(a.1.bits_per_second, a.1.flows))
+        });
+    }
+    talkers.truncate(TOP_OFFENDERS);
+    talkers
+}
+
+fn list<T: std::fmt::Display>(talkers: &[(T, Talker)]) -> String {
+    talkers
+        .iter()
+        .map(|(name, talker)| {
+            format!(
+                "{name} ({} flows, {})",
+                talker.flows,
+                scale_bits(talker.bits_per_second)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(remote: &str, protocol: u8, local_port: u16, bits_per_second: u64) -> FlowSample {
+        FlowSample {
+            local_ip: "100.64.0.10".parse().expect("ip"),
+            remote_ip: remote.parse().expect("ip"),
+            protocol,
+            local_port,
+            remote_port: 40_000,
+            bits_per_second,
+        }
+    }
+
+    #[test]
+    fn offenders_are_ranked_by_rate() {
+        let flows = vec![
+            flow("198.51.100.1", 17, 53, 1_000),
+            flow("198.51.100.2", 17, 53, 900_000_000),
+            flow("198.51.100.2", 17, 123, 100_000_000),
+            flow("198.51.100.3", 1, 0, 5_000),
+        ];
+        let offenders =
+            Offenders::from_flows(&flows, AttackKind::UdpFlood, AnomalyDirection::Inbound);
+        assert_eq!(
+            offenders.remote_ips[0],
+            (
+                "198.51.100.2".parse().expect("ip"),
+                Talker {
+                    flows: 2,
+                    bits_per_second: 1_000_000_000
+                }
+            )
+        );
+        assert_eq!(offenders.ports[0].0, "udp/53");
+        assert_eq!(offenders.ports[1].0, "udp/123");
+        assert_eq!(offenders.local_ips.len(), 1);
+        let context = offenders.describe(AnomalyDirection::Inbound);
+        assert!(context.starts_with("Sources: 198.51.100.2 (2 flows, 1.00 gbit/s)"));
+    }
+
+    #[test]
+    fn syn_flood_offenders_are_ranked_by_flow_count() {
+        let mut flows: Vec<_> = (0..10).map(|_| flow("203.0.113.7", 6, 80, 100)).collect();
+        flows.push(flow("203.0.113.8", 6, 443, 50_000_000));
+        let offenders =
+            Offenders::from_flows(&flows, AttackKind::SynFlood, AnomalyDirection::Inbound);
+        assert_eq!(offenders.remote_ips[0].0.to_string(), "203.0.113.7");
+        assert_eq!(offenders.ports[0].0, "tcp/80");
+    }
+
+    #[test]
+    fn message_names_the_subject_and_attack() {
+        let anomaly = Anomaly {
+            subject: 1_i64,
+            direction: AnomalyDirection::Outbound,
+            kind: AttackKind::SynFlood,
+            packets_per_second: 60_000,
+            baseline_packets_per_second: 1_000,
+            bits_per_second: 28_800_000,
+            baseline_bits_per_second: 800_000,
+            new_flows_per_second: 55_000,
+            baseline_new_flows_per_second: 20,
+        };
+        assert_eq!(
+            describe("Circuit Alice (1234)", &anomaly),
+            "Circuit Alice (1234) is the source of a probable SYN flood: 60.00 kpps / \
+             28.80 mbit/s outbound, baseline 1.00 kpps / 800.00 kbit/s, \
+             55000 new flows/s (baseline 20)."
+        );
+    }
+}
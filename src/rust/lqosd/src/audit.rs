@@ -0,0 +1,10 @@
+//! Records configuration and shaping changes in the audit log
+//! (`lqos_config::audit_log`). Entries are written by the shared background
+//! writer, so that web requests and the TreeGuard loop never wait on disk.
+
+use lqos_config::{AuditEntry, queue_audit_entry};
+
+/// Queues an entry for the audit log.
+pub fn record(entry: AuditEntry) {
+    queue_audit_entry(entry);
+}
@@ -0,0 +1,659 @@
+//! Applies time-of-day bandwidth schedules (`lqos_overrides::BandwidthSchedule`).
+//!
+//! LibreQoS.py always sends the baseline rates for every site and circuit. As
+//! those pass through the bus we remember them and substitute the rates of any
+//! schedule that is currently open, so a reload in the middle of a window keeps
+//! the scheduled rates. A background tick then watches for windows opening and
+//! closing and moves the affected classes with `ChangeSiteSpeedLive` /
+//! `ChangeCircuitSpeedLive`, without a full reload.
+//!
+//! Temporary (expiring) speed adjustments from the operator overrides file are
+//! overlaid the same way and take precedence over schedules; see
+//! `override_expiry` for how they lapse. Data quota throttles (see
+//! `usage_quotas`) are applied last and only ever lower the ceilings.
+//!
+//! Whether each schedule is active, and since when, is kept in
+//! `bandwidth_schedules_state.json` so that restarts neither lose the UI
+//! history nor log duplicate transitions.
+
+use crate::audit;
+use lqos_bakery::BakeryCommands;
+use lqos_config::{AuditActor, AuditEntry};
+use lqos_overrides::{
+    BandwidthSchedule, CircuitAdjustment, NetworkAdjustment, OverrideFile, ScheduleTarget,
+};
+use lqos_utils::hash_to_i64;
+use lqos_utils::unix_time::unix_now;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often window transitions are checked.
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+const STATE_FILE: &str = "bandwidth_schedules_state.json";
+
+static SCHEDULER: Lazy<Mutex<SchedulerState>> = Lazy::new(|| Mutex::new(SchedulerState::default()));
+
+/// Min/max rates in Mbps for one site or circuit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScheduledRates {
+    pub download_min: f32,
+    pub upload_min: f32,
+    pub download_max: f32,
+    pub upload_max: f32,
+}
+
+/// Current state of one schedule, for the UI.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BandwidthScheduleStatus {
+    pub id: String,
+    pub active: bool,
+    /// When the schedule last became active or inactive (unix seconds).
+    pub since_unix: Option<u64>,
+    /// False until LibreQoS.py has sent the target site or circuit.
+    pub target_loaded: bool,
+    /// Why the schedule cannot be evaluated, if it is invalid.
+    pub error: Option<String>,
+}
+
+/// A temporary speed adjustment; `None` keeps the underlying rate.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct TemporaryRates {
+    download_min: Option<f32>,
+    upload_min: Option<f32>,
+    download_max: Option<f32>,
+    upload_max: Option<f32>,
+}
+
+impl TemporaryRates {
+    fn apply(&self, rates: ScheduledRates) -> ScheduledRates {
+        ScheduledRates {
+            download_min: self.download_min.unwrap_or(rates.download_min),
+            upload_min: self.upload_min.unwrap_or(rates.upload_min),
+            download_max: self.download_max.unwrap_or(rates.download_max),
+            upload_max: self.upload_max.unwrap_or(rates.upload_max),
+        }
+    }
+}
+
+/// Ceilings in Mbps a circuit is held to after exceeding its data quota.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuotaThrottle {
+    pub download_max: f32,
+    pub upload_max: f32,
+}
+
+impl QuotaThrottle {
+    fn apply(&self, rates: ScheduledRates) -> ScheduledRates {
+        let download_max = rates.download_max.min(self.download_max);
+        let upload_max = rates.upload_max.min(self.upload_max);
+        ScheduledRates {
+            download_min: rates.download_min.min(download_max),
+            upload_min: rates.upload_min.min(upload_max),
+            download_max,
+            upload_max,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum TargetKey {
+    Site(i64),
+    Circuit(i64),
+}
+
+impl From<&ScheduleTarget> for TargetKey {
+    fn from(target: &ScheduleTarget) -> Self {
+        match target {
+            ScheduleTarget::Site { site_name } => Self::Site(hash_to_i64(site_name)),
+            ScheduleTarget::Circuit { circuit_id } => Self::Circuit(hash_to_i64(circuit_id)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct ScheduleRecord {
+    active: bool,
+    since_unix: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PersistedState {
+    schedules: HashMap<String, ScheduleRecord>,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    schedules: Vec<BandwidthSchedule>,
+    /// Unexpired temporary speed adjustments, by target.
+    temporary: HashMap<TargetKey, TemporaryRates>,
+    /// Circuits over their data quota.
+    quota_throttles: HashMap<TargetKey, QuotaThrottle>,
+    /// Rates as sent by LibreQoS.py, before any schedule.
+    baselines: HashMap<TargetKey, ScheduledRates>,
+    /// Rates most recently handed to the Bakery.
+    applied: HashMap<TargetKey, ScheduledRates>,
+    persisted: PersistedState,
+    errors: HashMap<String, String>,
+}
+
+impl SchedulerState {
+    /// Rates for `key` with the first open schedule (in file order) applied,
+    /// then any temporary adjustment on top, then any quota throttle.
+    fn effective_rates(&self, key: TargetKey, baseline: ScheduledRates) -> ScheduledRates {
+        let scheduled = self
+            .schedules
+            .iter()
+            .filter(|s| TargetKey::from(&s.target) == key && self.is_active(&s.id))
+            .map(|s| scheduled_rates(baseline, s))
+            .next()
+            .unwrap_or(baseline);
+        let adjusted = match self.temporary.get(&key) {
+            Some(temporary) => temporary.apply(scheduled),
+            None => scheduled,
+        };
+        match self.quota_throttles.get(&key) {
+            Some(throttle) => throttle.apply(adjusted),
+            None => adjusted,
+        }
+    }
+
+    fn is_active(&self, id: &str) -> bool {
+        self.persisted
+            .schedules
+            .get(id)
+            .is_some_and(|record| record.active)
+    }
+
+    /// Re-evaluates every window. Returns true if any schedule changed state.
+    fn evaluate(&mut self, now: u64) -> bool {
+        let mut changed = false;
+        self.errors.clear();
+        for schedule in &self.schedules {
+            let active = match schedule.is_active_now() {
+                Ok(active) => active,
+                Err(e) => {
+                    self.errors.insert(schedule.id.clone(), e.to_string());
+                    false
+                }
+            };
+            let record = self
+                .persisted
+                .schedules
+                .entry(schedule.id.clone())
+                .or_default();
+            if record.active == active && record.since_unix != 0 {
+                continue;
+            }
+            if record.since_unix != 0 || active {
+                let action = if active {
+                    "bandwidth_schedule_started"
+                } else {
+                    "bandwidth_schedule_ended"
+                };
+                info!("Bandwidth schedule '{}': {action}", schedule.id);
+                audit::record(
+                    AuditEntry::new(AuditActor::subsystem("bandwidth_schedules"), action)
+                        .target(schedule.id.clone())
+                        .detail(schedule.target.name().to_string()),
+                );
+            }
+            record.active = active;
+            record.since_unix = now;
+            changed = true;
+        }
+        let before = self.persisted.schedules.len();
+        let ids: Vec<&str> = self.schedules.iter().map(|s| s.id.as_str()).collect();
+        self.persisted
+            .schedules
+            .retain(|id, _| ids.contains(&id.as_str()));
+        changed || before != self.persisted.schedules.len()
+    }
+
+    /// Commands that bring every known target to its effective rates.
+    fn pending_changes(&mut self) -> Vec<BakeryCommands> {
+        let mut commands = Vec::new();
+        let targets: Vec<(TargetKey, ScheduledRates)> =
+            self.baselines.iter().map(|(k, v)| (*k, *v)).collect();
+        for (key, baseline) in targets {
+            let effective = self.effective_rates(key, baseline);
+            if self.applied.get(&key) == Some(&effective) {
+                continue;
+            }
+            self.applied.insert(key, effective);
+            commands.push(live_speed_command(key, effective));
+        }
+        commands
+    }
+}
+
+/// Replaces the ceilings with the schedule's. Minimums above a lowered
+/// ceiling are brought down to it, as HTB requires min <= max.
+fn scheduled_rates(baseline: ScheduledRates, schedule: &BandwidthSchedule) -> ScheduledRates {
+    let download_max = schedule
+        .download_bandwidth_mbps
+        .unwrap_or(baseline.download_max);
+    let upload_max = schedule
+        .upload_bandwidth_mbps
+        .unwrap_or(baseline.upload_max);
+    ScheduledRates {
+        download_min: baseline.download_min.min(download_max),
+        upload_min: baseline.upload_min.min(upload_max),
+        download_max,
+        upload_max,
+    }
+}
+
+fn live_speed_command(key: TargetKey, rates: ScheduledRates) -> BakeryCommands {
+    match key {
+        TargetKey::Site(site_hash) => BakeryCommands::ChangeSiteSpeedLive {
+            site_hash,
+            download_bandwidth_min: rates.download_min,
+            upload_bandwidth_min: rates.upload_min,
+            download_bandwidth_max: rates.download_max,
+            upload_bandwidth_max: rates.upload_max,
+        },
+        TargetKey::Circuit(circuit_hash) => BakeryCommands::ChangeCircuitSpeedLive {
+            circuit_hash,
+            download_bandwidth_min: rates.download_min,
+            upload_bandwidth_min: rates.upload_min,
+            download_bandwidth_max: rates.download_max,
+            upload_bandwidth_max: rates.upload_max,
+        },
+    }
+}
+
+fn state_path() -> Option<PathBuf> {
+    let config = lqos_config::load_config().ok()?;
+    Some(PathBuf::from(&config.lqos_directory).join(STATE_FILE))
+}
+
+fn load_persisted() -> PersistedState {
+    let Some(path) = state_path() else {
+        return PersistedState::default();
+    };
+    let Ok(raw) = std::fs::read_to_string(&path) else {
+        return PersistedState::default();
+    };
+    serde_json::from_str(&raw).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable {}: {e}", path.display());
+        PersistedState::default()
+    })
+}
+
+fn save_persisted(state: &PersistedState) {
+    let Some(path) = state_path() else {
+        return;
+    };
+    match serde_json::to_string_pretty(state) {
+        Ok(raw) => {
+            if let Err(e) = std::fs::write(&path, raw) {
+                warn!("Unable to write {}: {e}", path.display());
+            }
+        }
+        Err(e) => warn!("Unable to serialize bandwidth schedule state: {e}"),
+    }
+}
+
+/// Temporary speed adjustments that have not lapsed at `now`.
+fn temporary_rates(overrides: &OverrideFile, now: u64) -> HashMap<TargetKey, TemporaryRates> {
+    let circuits = overrides
+        .circuit_adjustments()
+        .iter()
+        .filter(|adj| adj.expires_at().is_some() && !adj.is_expired(now))
+        .filter_map(|adj| match adj {
+            CircuitAdjustment::CircuitAdjustSpeed {
+                circuit_id,
+                min_download_bandwidth,
+                max_download_bandwidth,
+                min_upload_bandwidth,
+                max_upload_bandwidth,
+                ..
+            } => Some((
+                TargetKey::Circuit(hash_to_i64(circuit_id)),
+                TemporaryRates {
+                    download_min: *min_download_bandwidth,
+                    upload_min: *min_upload_bandwidth,
+                    download_max: *max_download_bandwidth,
+                    upload_max: *max_upload_bandwidth,
+                },
+            )),
+            _ => None,
+        });
+    let sites = overrides
+        .network_adjustments()
+        .iter()
+        .filter(|adj| adj.expires_at().is_some() && !adj.is_expired(now))
+        .filter_map(|adj| match adj {
+            NetworkAdjustment::AdjustSiteSpeed {
+                site_name,
+                download_bandwidth_mbps,
+                upload_bandwidth_mbps,
+                ..
+            } => Some((
+                TargetKey::Site(hash_to_i64(site_name)),
+                TemporaryRates {
+                    download_max: *download_bandwidth_mbps,
+                    upload_max: *upload_bandwidth_mbps,
+                    ..Default::default()
+                },
+            )),
+            _ => None,
+        });
+    circuits.chain(sites).collect()
+}
+
+fn load_overrides(state: &mut SchedulerState, now: u64) {
+    match OverrideFile::load() {
+        Ok(overrides) => {
+            state.schedules = overrides.bandwidth_schedules().to_vec();
+            state.temporary = temporary_rates(&overrides, now);
+        }
+        Err(e) => warn!("Unable to load bandwidth schedules: {e:?}"),
+    }
+}
+
+/// Re-evaluates all schedules and sends any resulting rate changes to the
+/// Bakery. Fails if a change could not be handed to the Bakery.
+fn tick(reload: bool) -> Result<(), String> {
+    let commands = {
+        let mut state = SCHEDULER.lock();
+        let now = unix_now().unwrap_or(0);
+        if reload {
+            load_overrides(&mut state, now);
+        }
+        if state.evaluate(now) {
+            save_persisted(&state.persisted);
+        }
+        state.pending_changes()
+    };
+    if commands.is_empty() {
+        return Ok(());
+    }
+    let Some(sender) = lqos_bakery::BAKERY_SENDER.get() else {
+        return Ok(());
+    };
+    for command in commands {
+        if let Err(e) = sender.send(command) {
+            warn!("Unable to send scheduled rate change to the Bakery: {e}");
+            return Err(format!("Unable to send rate change to the Bakery: {e}"));
+        }
+    }
+    Ok(())
+}
+
+/// Loads schedules and their saved state, then starts the schedule thread.
+pub fn start_bandwidth_scheduler() -> anyhow::Result<()> {
+    {
+        let mut state = SCHEDULER.lock();
+        let now = unix_now().unwrap_or(0);
+        state.persisted = load_persisted();
+        load_overrides(&mut state, now);
+        if state.evaluate(now) {
+            save_persisted(&state.persisted);
+        }
+    }
+    std::thread::Builder::new()
+        .name("Bandwidth Schedules".to_string())
+        .spawn(|| {
+            loop {
+                std::thread::sleep(TICK_INTERVAL);
+                crate::override_expiry::prune_expired_overrides();
+                let _ = tick(true);
+            }
+        })?;
+    Ok(())
+}
+
+/// Reloads schedules after they were edited and applies the result at once.
+pub fn schedules_changed() {
+    let _ = tick(true);
+}
+
+/// Reloads schedules and temporary adjustments after the overrides file
+/// changed, moving affected classes live.
+pub fn overrides_changed() -> Result<(), String> {
+    tick(true)
+}
+
+/// Replaces the set of quota-throttled circuits (keyed by circuit hash) and
+/// moves any circuit whose ceilings changed live.
+pub fn set_quota_throttles(throttles: HashMap<i64, QuotaThrottle>) -> Result<(), String> {
+    {
+        let mut state = SCHEDULER.lock();
+        state.quota_throttles = throttles
+            .into_iter()
+            .map(|(circuit_hash, throttle)| (TargetKey::Circuit(circuit_hash), throttle))
+            .collect();
+    }
+    tick(false)
+}
+
+/// Records the baseline rates LibreQoS.py sent for a site and returns the
+/// rates to shape with right now.
+pub fn site_rates(site_hash: i64, baseline: ScheduledRates) -> ScheduledRates {
+    overlay(TargetKey::Site(site_hash), baseline)
+}
+
+/// Records the baseline rates LibreQoS.py sent for a circuit and returns the
+/// rates to shape with right now.
+pub fn circuit_rates(circuit_hash: i64, baseline: ScheduledRates) -> ScheduledRates {
+    overlay(TargetKey::Circuit(circuit_hash), baseline)
+}
+
+fn overlay(key: TargetKey, baseline: ScheduledRates) -> ScheduledRates {
+    let mut state = SCHEDULER.lock();
+    state.baselines.insert(key, baseline);
+    let effective = state.effective_rates(key, baseline);
+    state.applied.insert(key, effective);
+    effective
+}
+
+/// Current state of every configured schedule.
+pub fn schedule_status() -> Vec<BandwidthScheduleStatus> {
+    let state = SCHEDULER.lock();
+    state
+        .schedules
+        .iter()
+        .map(|schedule| {
+            let record = state.persisted.schedules.get(&schedule.id);
+            BandwidthScheduleStatus {
+                id: schedule.id.clone(),
+                active: record.is_some_and(|r| r.active),
+                since_unix: record.map(|r| r.since_unix),
+                target_loaded: state
+                    .baselines
+                    .contains_key(&TargetKey::from(&schedule.target)),
+                error: state.errors.get(&schedule.id).cloned(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn baseline() -> ScheduledRates {
+        ScheduledRates {
+            download_min: 10.0,
+            upload_min: 5.0,
+            download_max: 100.0,
+            upload_max: 20.0,
+        }
+    }
+
+    fn always(id: &str, circuit_id: &str, down: f32) -> BandwidthSchedule {
+        BandwidthSchedule {
+            id: id.to_string(),
+            enabled: true,
+            target: ScheduleTarget::Circuit {
+                circuit_id: circuit_id.to_string(),
+            },
+            window: "* 00:00-24:00".to_string(),
+            timezone: "UTC".to_string(),
+            download_bandwidth_mbps: Some(down),
+            upload_bandwidth_mbps: None,
+        }
+    }
+
+    #[test]
+    fn open_schedules_replace_ceilings_and_revert_when_removed() {
+        let key = TargetKey::Circuit(hash_to_i64("c1"));
+        let mut state = SchedulerState {
+            schedules: vec![always("boost", "c1", 300.0), always("other", "c1", 50.0)],
+            ..Default::default()
+        };
+        assert!(state.evaluate(1_000));
+        state.baselines.insert(key, baseline());
+
+        // The first open schedule in file order wins; minimums are untouched.
+        let commands = state.pending_changes();
+        assert!(matches!(
+            commands.as_slice(),
+            [BakeryCommands::ChangeCircuitSpeedLive {
+                download_bandwidth_min,
+                download_bandwidth_max,
+                upload_bandwidth_max,
+                ..
+            }] if *download_bandwidth_min == 10.0
+                && *download_bandwidth_max == 300.0
+                && *upload_bandwidth_max == 20.0
+        ));
+        assert!(state.pending_changes().is_empty());
+
+        // Stable state is not re-recorded.
+        assert!(!state.evaluate(2_000));
+        assert_eq!(state.persisted.schedules["boost"].since_unix, 1_000);
+
+        state.schedules[0].enabled = false;
+        state.schedules.truncate(1);
+        assert!(state.evaluate(3_000));
+        assert!(!state.persisted.schedules.contains_key("other"));
+        let commands = state.pending_changes();
+        assert!(matches!(
+            commands.as_slice(),
+            [BakeryCommands::ChangeCircuitSpeedLive {
+                download_bandwidth_max,
+                ..
+            }] if *download_bandwidth_max == 100.0
+        ));
+    }
+
+    #[test]
+    fn schedules_below_the_minimum_lower_the_minimum_too() {
+        let mut night = always("night", "c1", 4.0);
+        night.upload_bandwidth_mbps = Some(2.0);
+        let rates = scheduled_rates(baseline(), &night);
+        assert_eq!(
+            rates,
+            ScheduledRates {
+                download_min: 4.0,
+                upload_min: 2.0,
+                download_max: 4.0,
+                upload_max: 2.0,
+            }
+        );
+
+        // Raised ceilings leave the minimums alone.
+        let rates = scheduled_rates(baseline(), &always("boost", "c1", 300.0));
+        assert_eq!(rates.download_min, 10.0);
+        assert_eq!(rates.upload_min, 5.0);
+    }
+
+    #[test]
+    fn invalid_schedules_are_reported_and_inactive() {
+        let mut broken = always("broken", "c1", 300.0);
+        broken.timezone = "Mars/Olympus".to_string();
+        let mut state = SchedulerState {
+            schedules: vec![broken],
+            ..Default::default()
+        };
+        state.evaluate(1_000);
+        assert!(!state.is_active("broken"));
+        assert!(state.errors.contains_key("broken"));
+    }
+
+    #[test]
+    fn temporary_adjustments_win_over_schedules_until_they_lapse() {
+        let overrides: OverrideFile = serde_json::from_str(
+            r#"{
+                "circuit_adjustments": [
+                    {"type":"circuit_adjust_speed","circuit_id":"c1","min_download_bandwidth":null,"max_download_bandwidth":500.0,"min_upload_bandwidth":null,"max_upload_bandwidth":null,"expires_at":2000},
+                    {"type":"circuit_adjust_speed","circuit_id":"c2","min_download_bandwidth":null,"max_download_bandwidth":50.0,"min_upload_bandwidth":null,"max_upload_bandwidth":null}
+                ],
+                "network_adjustments": [
+                    {"type":"adjust_site_speed","site_name":"Tower","download_bandwidth_mbps":null,"upload_bandwidth_mbps":80.0,"expires_at":2000}
+                ]
+            }"#,
+        )
+        .expect("overrides deserialize");
+        let key = TargetKey::Circuit(hash_to_i64("c1"));
+        let mut state = SchedulerState {
+            schedules: vec![always("boost", "c1", 300.0)],
+            temporary: temporary_rates(&overrides, 1_000),
+            ..Default::default()
+        };
+        // Permanent adjustments are already in the rates LibreQoS.py sends.
+        assert_eq!(state.temporary.len(), 2);
+        assert_eq!(
+            state.temporary[&TargetKey::Site(hash_to_i64("Tower"))].apply(baseline()),
+            ScheduledRates {
+                upload_max: 80.0,
+                ..baseline()
+            }
+        );
+
+        state.evaluate(1_000);
+        state.baselines.insert(key, baseline());
+        assert!(matches!(
+            state.pending_changes().as_slice(),
+            [BakeryCommands::ChangeCircuitSpeedLive {
+                download_bandwidth_max,
+                ..
+            }] if *download_bandwidth_max == 500.0
+        ));
+
+        state.temporary = temporary_rates(&overrides, 2_000);
+        assert!(state.temporary.is_empty());
+        assert!(matches!(
+            state.pending_changes().as_slice(),
+            [BakeryCommands::ChangeCircuitSpeedLive {
+                download_bandwidth_max,
+                ..
+            }] if *download_bandwidth_max == 300.0
+        ));
+    }
+
+    #[test]
+    fn quota_throttles_only_lower_ceilings() {
+        let key = TargetKey::Circuit(hash_to_i64("c1"));
+        let mut state = SchedulerState::default();
+        state.baselines.insert(key, baseline());
+        state.quota_throttles.insert(
+            key,
+            QuotaThrottle {
+                download_max: 5.0,
+                upload_max: 1_000.0,
+            },
+        );
+        let throttled = state.effective_rates(key, baseline());
+        assert_eq!(throttled.download_max, 5.0);
+        assert!(throttled.download_min <= 5.0);
+        assert_eq!(throttled.upload_max, baseline().upload_max);
+        assert_eq!(state.pending_changes().len(), 1);
+
+        state.quota_throttles.clear();
+        assert!(matches!(
+            state.pending_changes().as_slice(),
+            [BakeryCommands::ChangeCircuitSpeedLive {
+                download_bandwidth_max,
+                ..
+            }] if *download_bandwidth_max == baseline().download_max
+        ));
+    }
+}
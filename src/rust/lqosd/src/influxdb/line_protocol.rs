@@ -0,0 +1,152 @@
+//! Minimal InfluxDB line-protocol encoder.
+//!
+//! See <https://docs.influxdata.com/influxdb/v2/reference/syntax/line-protocol/>
+//! for the escaping rules implemented here.
+
+use std::fmt::Write;
+
+/// A single field value in a line-protocol point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FieldValue {
+    Integer(i64),
+    Float(f64),
+}
+
+/// One line-protocol point: measurement, tag set, field set and a timestamp
+/// in whole seconds.
+#[derive(Debug, Clone)]
+pub(crate) struct Point {
+    measurement: &'static str,
+    tags: Vec<(&'static str, String)>,
+    fields: Vec<(&'static str, FieldValue)>,
+    timestamp: u64,
+}
+
+impl Point {
+    pub(crate) fn new(measurement: &'static str, timestamp: u64) -> Self {
+        Self {
+            measurement,
+            tags: Vec::new(),
+            fields: Vec::new(),
+            timestamp,
+        }
+    }
+
+    /// Adds a tag. Empty values are skipped, since line protocol does not
+    /// allow them.
+    pub(crate) fn tag(&mut self, key: &'static str, value: &str) -> &mut Self {
+        if !value.is_empty() {
+            self.tags.push((key, value.to_string()));
+        }
+        self
+    }
+
+    pub(crate) fn int(&mut self, key: &'static str, value: i64) -> &mut Self {
+        self.fields.push((key, FieldValue::Integer(value)));
+        self
+    }
+
+    /// Adds a float field. Non-finite values are skipped.
+    pub(crate) fn float(&mut self, key: &'static str, value: f64) -> &mut Self {
+        if value.is_finite() {
+            self.fields.push((key, FieldValue::Float(value)));
+        }
+        self
+    }
+
+    pub(crate) fn has_fields(&self) -> bool {
+        !self.fields.is_empty()
+    }
+
+    /// Appends this point (and a trailing newline) to `out`. Points without
+    /// fields are not valid line protocol and are silently skipped.
+    pub(crate) fn write_line(&self, out: &mut String) {
+        if self.fields.is_empty() {
+            return;
+        }
+        escape_into(out, self.measurement, &[',', ' ']);
+        // Tags should be sorted by key for best write performance.
+        let mut tags: Vec<&(&'static str, String)> = self.tags.iter().collect();
+        tags.sort_by_key(|(k, _)| *k);
+        for (key, value) in tags {
+            out.push(',');
+            escape_into(out, key, &[',', '=', ' ']);
+            out.push('=');
+            escape_into(out, value, &[',', '=', ' ']);
+        }
+        for (i, (key, value)) in self.fields.iter().enumerate() {
+            out.push(if i == 0 { ' ' } else { ',' });
+            escape_into(out, key, &[',', '=', ' ']);
+            out.push('=');
+            match value {
+                FieldValue::Integer(v) => {
+                    let _ = write!(out, "{v}i");
+                }
+                FieldValue::Float(v) => {
+                    let _ = write!(out, "{v}");
+                }
+            }
+        }
+        let _ = writeln!(out, " {}", self.timestamp);
+    }
+}
+
+fn escape_into(out: &mut String, raw: &str, special: &[char]) {
+    for c in raw.chars() {
+        match c {
+            // Newlines can't be escaped in line protocol; replace them.
+            '\n' | '\r' => out.push(' '),
+            '\\' => out.push_str("\\\\"),
+            c if special.contains(&c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_integer_and_float_fields() {
+        let mut point = Point::new("circuit", 1_700_000_000);
+        point
+            .tag("circuit_id", "c1")
+            .int("bytes_down", 1234)
+            .float("rtt_ms", 12.5);
+        let mut out = String::new();
+        point.write_line(&mut out);
+        assert_eq!(
+            out,
+            "circuit,circuit_id=c1 bytes_down=1234i,rtt_ms=12.5 1700000000\n"
+        );
+    }
+
+    #[test]
+    fn escapes_tag_values_and_sorts_tags() {
+        let mut point = Point::new("site", 1);
+        point
+            .tag("site_name", "Tower 1, North=A")
+            .tag("parent", "Root")
+            .int("bytes_down", 1);
+        let mut out = String::new();
+        point.write_line(&mut out);
+        assert_eq!(
+            out,
+            "site,parent=Root,site_name=Tower\\ 1\\,\\ North\\=A bytes_down=1i 1\n"
+        );
+    }
+
+    #[test]
+    fn skips_empty_tags_non_finite_floats_and_fieldless_points() {
+        let mut point = Point::new("circuit", 5);
+        point.tag("circuit_name", "").float("rtt_ms", f64::NAN);
+        assert!(!point.has_fields());
+        let mut out = String::new();
+        point.write_line(&mut out);
+        assert!(out.is_empty());
+    }
+}
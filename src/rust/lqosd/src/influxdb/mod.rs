@@ -0,0 +1,577 @@
+//! Native InfluxDB v2 exporter.
+//!
+//! Every `interval_seconds` the throughput monitor hands the exporter the
+//! same per-circuit and per-site batches that are collected for Insight. The
+//! exporter converts them to line protocol and writes them to InfluxDB from
+//! its own thread, retrying with exponential backoff. Batches that still
+//! can't be delivered are kept in a bounded on-disk spool and replayed once
+//! the server is reachable again.
+
+mod line_protocol;
+mod spool;
+mod writer;
+
+use crate::shaped_devices_tracker::{NETWORK_JSON, SHAPED_DEVICES};
+use crate::throughput_tracker::{TickBatches, gather_tick_batches};
+use crossbeam_channel::Sender;
+use fxhash::FxHashMap;
+use line_protocol::Point;
+use lqos_config::{Config, InfluxDbConfig};
+use lqos_utils::hash_to_i64;
+use lqos_utils::unix_time::unix_now;
+use spool::Spool;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use writer::{InfluxWriter, WriteError};
+
+#[cfg(test)]
+pub(crate) use writer::test_server;
+
+/// Samples waiting for the exporter. Kept small: if InfluxDB is slow we
+/// would rather drop a sample than build up memory.
+const SAMPLE_QUEUE_DEPTH: usize = 4;
+/// Cap on the exponential backoff between delivery attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Spooled batches replayed per sample, so a large backlog can't stall
+/// fresh data.
+const MAX_REPLAY_PER_SAMPLE: usize = 10;
+
+static INFLUX_SENDER: OnceLock<Sender<InfluxSample>> = OnceLock::new();
+static LAST_SAMPLE: AtomicU64 = AtomicU64::new(0);
+
+struct InfluxSample {
+    timestamp: u64,
+    batches: TickBatches,
+}
+
+/// Starts the InfluxDB exporter thread. The thread is idle unless
+/// `[influxdb] enable_influxdb` is set; configuration is re-read for every
+/// sample so changes apply without a restart.
+pub fn start_influxdb_exporter() -> anyhow::Result<()> {
+    let (tx, rx) = crossbeam_channel::bounded::<InfluxSample>(SAMPLE_QUEUE_DEPTH);
+    std::thread::Builder::new()
+        .name("InfluxDB Exporter".to_string())
+        .spawn(move || {
+            let mut exporter = Exporter::default();
+            while let Ok(sample) = rx.recv() {
+                exporter.handle_sample(sample);
+            }
+            warn!("InfluxDB exporter thread exiting");
+        })?;
+    let _ = INFLUX_SENDER.set(tx);
+    Ok(())
+}
+
+/// Called once per throughput tick. Gathers and enqueues a sample when the
+/// exporter is enabled and `interval_seconds` have passed since the last one.
+pub(crate) fn maybe_submit_sample(config: &Config, scale: f64) {
+    let Some(influx) = config.influxdb.as_ref() else {
+        return;
+    };
+    if !influx.enable_influxdb {
+        return;
+    }
+    let Some(sender) = INFLUX_SENDER.get() else {
+        return;
+    };
+    let Ok(now) = unix_now() else {
+        return;
+    };
+    let last = LAST_SAMPLE.load(Ordering::Relaxed);
+    if now.saturating_sub(last) < influx.interval_seconds.max(1) {
+        return;
+    }
+    LAST_SAMPLE.store(now, Ordering::Relaxed);
+
+    let sample = InfluxSample {
+        timestamp: now,
+        batches: gather_tick_batches(now, scale),
+    };
+    if sender.try_send(sample).is_err() {
+        debug!("InfluxDB exporter is busy; dropping sample");
+    }
+}
+
+#[derive(Default)]
+struct Exporter {
+    active: Option<(InfluxDbConfig, InfluxWriter, Spool)>,
+    backoff: Backoff,
+}
+
+impl Exporter {
+    fn handle_sample(&mut self, sample: InfluxSample) {
+        let Ok(config) = lqos_config::load_config() else {
+            return;
+        };
+        let Some(influx) = config.influxdb.clone() else {
+            return;
+        };
+        if !influx.enable_influxdb {
+            self.active = None;
+            return;
+        }
+        if self.active.as_ref().map(|(c, _, _)| c) != Some(&influx) {
+            match Self::connect(&influx, &config.lqos_directory) {
+                Ok((writer, spool)) => {
+                    info!("InfluxDB exporter writing to {}", influx.url);
+                    self.active = Some((influx, writer, spool));
+                    self.backoff = Backoff::default();
+                }
+                Err(e) => {
+                    warn!("Unable to configure InfluxDB exporter: {e:?}");
+                    self.active = None;
+                    return;
+                }
+            }
+        }
+        let Some((influx, writer, spool)) = self.active.as_mut() else {
+            return;
+        };
+
+        let names = NameLookup::from_tracker();
+        let points = build_points(&sample, &names);
+        for body in encode_batches(&points, influx.max_lines_per_write.max(1)) {
+            let delivered = self.backoff.ready(Instant::now())
+                && deliver(writer, &body, influx.max_retries, &mut self.backoff);
+            if !delivered {
+                match spool.push(&body) {
+                    Ok(0) => {}
+                    Ok(evicted) => {
+                        warn!("InfluxDB spool full; discarded {evicted} oldest batch(es)")
+                    }
+                    Err(e) => warn!("Unable to spool InfluxDB batch: {e:?}"),
+                }
+                if let Ok(bytes) = spool.total_bytes() {
+                    debug!("InfluxDB spool holds {bytes} bytes");
+                }
+            }
+        }
+        if self.backoff.ready(Instant::now()) {
+            replay_spool(writer, spool, &mut self.backoff);
+        }
+    }
+
+    fn connect(
+        influx: &InfluxDbConfig,
+        lqos_directory: &str,
+    ) -> anyhow::Result<(InfluxWriter, Spool)> {
+        let writer = InfluxWriter::new(influx)?;
+        let spool_path = match &influx.spool_path {
+            Some(path) => Path::new(path).to_path_buf(),
+            None => Path::new(lqos_directory).join("influxdb_spool"),
+        };
+        let spool = Spool::new(&spool_path, influx.spool_max_bytes)?;
+        Ok((writer, spool))
+    }
+}
+
+/// Tries to write a batch, retrying up to `max_retries` times with
+/// exponential backoff. Returns `true` when the batch no longer needs to be
+/// kept (delivered, or rejected as invalid by the server).
+fn deliver(writer: &InfluxWriter, body: &str, max_retries: u32, backoff: &mut Backoff) -> bool {
+    let mut delay = Duration::from_secs(1);
+    for attempt in 0..=max_retries {
+        match writer.write(body) {
+            Ok(()) => {
+                backoff.reset();
+                return true;
+            }
+            Err(WriteError::Rejected(e)) => {
+                warn!("Dropping InfluxDB batch: {e}");
+                return true;
+            }
+            Err(WriteError::Retryable(e)) => {
+                debug!("InfluxDB write attempt {} failed: {e}", attempt + 1);
+                if attempt < max_retries {
+                    std::thread::sleep(delay);
+                    delay = (delay * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+    backoff.record_failure(Instant::now());
+    warn!(
+        "InfluxDB unreachable; spooling data and retrying in {:?}",
+        backoff.current_delay()
+    );
+    false
+}
+
+fn replay_spool(writer: &InfluxWriter, spool: &Spool, backoff: &mut Backoff) {
+    for _ in 0..MAX_REPLAY_PER_SAMPLE {
+        let (path, body) = match spool.oldest() {
+            Ok(Some(entry)) => entry,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Unable to read InfluxDB spool: {e:?}");
+                return;
+            }
+        };
+        match writer.write(&body) {
+            Ok(()) => {}
+            Err(WriteError::Rejected(e)) => warn!("Dropping spooled InfluxDB batch: {e}"),
+            Err(WriteError::Retryable(e)) => {
+                debug!("Replaying InfluxDB spool failed: {e}");
+                backoff.record_failure(Instant::now());
+                return;
+            }
+        }
+        if let Err(e) = spool.remove(&path) {
+            warn!("Unable to remove replayed InfluxDB batch {path:?}: {e:?}");
+            return;
+        }
+    }
+}
+
+/// Exponential backoff between delivery attempts after a failure.
+#[derive(Default)]
+struct Backoff {
+    failures: u32,
+    next_attempt: Option<Instant>,
+}
+
+impl Backoff {
+    fn ready(&self, now: Instant) -> bool {
+        self.next_attempt.is_none_or(|next| now >= next)
+    }
+
+    fn current_delay(&self) -> Duration {
+        if self.failures == 0 {
+            return Duration::ZERO;
+        }
+        let exponent = (self.failures - 1).min(16);
+        (Duration::from_secs(5) * 2u32.pow(exponent)).min(MAX_BACKOFF)
+    }
+
+    fn record_failure(&mut self, now: Instant) {
+        self.failures = self.failures.saturating_add(1);
+        self.next_attempt = Some(now + self.current_delay());
+    }
+
+    fn reset(&mut self) {
+        self.failures = 0;
+        self.next_attempt = None;
+    }
+}
+
+/// Human-readable identifiers for the hashes carried by the stats batches.
+#[derive(Default)]
+struct NameLookup {
+    /// circuit hash -> (circuit id, circuit name, parent node)
+    circuits: FxHashMap<i64, (String, String, String)>,
+    /// site hash -> (site name, node type)
+    sites: FxHashMap<i64, (String, String)>,
+}
+
+impl NameLookup {
+    fn from_tracker() -> Self {
+        let mut names = NameLookup::default();
+        for device in SHAPED_DEVICES.load().devices.iter() {
+            names
+                .circuits
+                .entry(device.circuit_hash)
+                .or_insert_with(|| {
+                    (
+                        device.circuit_id.clone(),
+                        device.circuit_name.clone(),
+                        device.parent_node.clone(),
+                    )
+                });
+        }
+        let net_json = NETWORK_JSON.read();
+        for node in net_json.get_nodes_when_ready().iter() {
+            names.sites.insert(
+                hash_to_i64(&node.name),
+                (
+                    node.name.clone(),
+                    node.node_type.clone().unwrap_or_default(),
+                ),
+            );
+        }
+        names
+    }
+}
+
+/// Merges the per-metric batches into one point per circuit and per site.
+/// Entries whose hash no longer resolves to a circuit or site are skipped.
+fn build_points(sample: &InfluxSample, names: &NameLookup) -> Vec<Point> {
+    let ts = sample.timestamp;
+    let batches = &sample.batches;
+    let mut circuits: FxHashMap<i64, Point> = FxHashMap::default();
+    let mut sites: FxHashMap<i64, Point> = FxHashMap::default();
+
+    for c in batches.circuit_throughput.iter() {
+        if let Some(point) = circuit_point(&mut circuits, names, c.circuit_hash, ts) {
+            point
+                .int("bytes_down", c.download_bytes as i64)
+                .int("bytes_up", c.upload_bytes as i64)
+                .int("packets_down", c.packets_down as i64)
+                .int("packets_up", c.packets_up as i64);
+        }
+    }
+    for c in batches.circuit_rtt.iter() {
+        if let Some(point) = circuit_point(&mut circuits, names, c.circuit_hash, ts) {
+            point.float("rtt_ms", c.median_rtt as f64);
+        }
+    }
+    for c in batches.circuit_retransmits.iter() {
+        if let Some(point) = circuit_point(&mut circuits, names, c.circuit_hash, ts) {
+            point
+                .int("tcp_retransmits_down", c.tcp_retransmits_down as i64)
+                .int("tcp_retransmits_up", c.tcp_retransmits_up as i64);
+        }
+    }
+    for c in batches.circuit_cake_drops.iter() {
+        if let Some(point) = circuit_point(&mut circuits, names, c.circuit_hash, ts) {
+            point
+                .int("cake_drops_down", c.cake_drops_down as i64)
+                .int("cake_drops_up", c.cake_drops_up as i64);
+        }
+    }
+    for c in batches.circuit_cake_marks.iter() {
+        if let Some(point) = circuit_point(&mut circuits, names, c.circuit_hash, ts) {
+            point
+                .int("cake_marks_down", c.cake_marks_down as i64)
+                .int("cake_marks_up", c.cake_marks_up as i64);
+        }
+    }
+
+    for s in batches.site_throughput.iter() {
+        if let Some(point) = site_point(&mut sites, names, s.site_hash, ts) {
+            point
+                .int("bytes_down", s.download_bytes as i64)
+                .int("bytes_up", s.upload_bytes as i64)
+                .int("packets_down", s.packets_down as i64)
+                .int("packets_up", s.packets_up as i64);
+        }
+    }
+    for s in batches.site_rtt.iter() {
+        if let Some(point) = site_point(&mut sites, names, s.site_hash, ts) {
+            point.float("rtt_ms", s.median_rtt as f64);
+        }
+    }
+    for s in batches.site_retransmits.iter() {
+        if let Some(point) = site_point(&mut sites, names, s.site_hash, ts) {
+            point
+                .int("tcp_retransmits_down", s.tcp_retransmits_down as i64)
+                .int("tcp_retransmits_up", s.tcp_retransmits_up as i64);
+        }
+    }
+    for s in batches.site_cake_drops.iter() {
+        if let Some(point) = site_point(&mut sites, names, s.site_hash, ts) {
+            point
+                .int("cake_drops_down", s.cake_drops_down as i64)
+                .int("cake_drops_up", s.cake_drops_up as i64);
+        }
+    }
+    for s in batches.site_cake_marks.iter() {
+        if let Some(point) = site_point(&mut sites, names, s.site_hash, ts) {
+            point
+                .int("cake_marks_down", s.cake_marks_down as i64)
+                .int("cake_marks_up", s.cake_marks_up as i64);
+        }
+    }
+
+    circuits
+        .into_values()
+        .chain(sites.into_values())
+        .filter(Point::has_fields)
+        .collect()
+}
+
+fn circuit_point<'a>(
+    points: &'a mut FxHashMap<i64, Point>,
+    names: &NameLookup,
+    hash: i64,
+    ts: u64,
+) -> Option<&'a mut Point> {
+    let (id, name, parent) = names.circuits.get(&hash)?;
+    Some(points.entry(hash).or_insert_with(|| {
+        let mut point = Point::new("circuit", ts);
+        point
+            .tag("circuit_id", id)
+            .tag("circuit_name", name)
+            .tag("parent_node", parent);
+        point
+    }))
+}
+
+fn site_point<'a>(
+    points: &'a mut FxHashMap<i64, Point>,
+    names: &NameLookup,
+    hash: i64,
+    ts: u64,
+) -> Option<&'a mut Point> {
+    let (name, node_type) = names.sites.get(&hash)?;
+    Some(points.entry(hash).or_insert_with(|| {
+        let mut point = Point::new("site", ts);
+        point.tag("site_name", name).tag("node_type", node_type);
+        point
+    }))
+}
+
+/// Encodes points into request bodies of at most `max_lines` lines each.
+fn encode_batches(points: &[Point], max_lines: usize) -> Vec<String> {
+    points
+        .chunks(max_lines)
+        .map(|chunk| {
+            let mut body = String::new();
+            for point in chunk {
+                point.write_line(&mut body);
+            }
+            body
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lts2_sys::shared_types::{CircuitRtt, CircuitThroughput, SiteCakeDrops};
+
+    fn sample() -> InfluxSample {
+        InfluxSample {
+            timestamp: 100,
+            batches: TickBatches {
+                circuit_throughput: vec![
+                    CircuitThroughput {
+                        timestamp: 100,
+                        circuit_hash: 1,
+                        download_bytes: 1000,
+                        upload_bytes: 200,
+                        packets_down: 10,
+                        packets_up: 2,
+                        tcp_packets_down: 0,
+                        tcp_packets_up: 0,
+                        udp_packets_down: 0,
+                        udp_packets_up: 0,
+                        icmp_packets_down: 0,
+                        icmp_packets_up: 0,
+                    },
+                    CircuitThroughput {
+                        timestamp: 100,
+                        circuit_hash: 99,
+                        download_bytes: 1,
+                        upload_bytes: 1,
+                        packets_down: 1,
+                        packets_up: 1,
+                        tcp_packets_down: 0,
+                        tcp_packets_up: 0,
+                        udp_packets_down: 0,
+                        udp_packets_up: 0,
+                        icmp_packets_down: 0,
+                        icmp_packets_up: 0,
+                    },
+                ],
+                circuit_rtt: vec![CircuitRtt {
+                    timestamp: 100,
+                    circuit_hash: 1,
+                    median_rtt: 25.5,
+                }],
+                site_cake_drops: vec![SiteCakeDrops {
+                    timestamp: 100,
+                    site_hash: 7,
+                    cake_drops_down: 3,
+                    cake_drops_up: 4,
+                }],
+                ..Default::default()
+            },
+        }
+    }
+
+    fn names() -> NameLookup {
+        let mut names = NameLookup::default();
+        names.circuits.insert(
+            1,
+            (
+                "c1".to_string(),
+                "Alice Smith".to_string(),
+                "AP 1".to_string(),
+            ),
+        );
+        names
+            .sites
+            .insert(7, ("Tower".to_string(), "site".to_string()));
+        names
+    }
+
+    #[test]
+    fn merges_metrics_into_one_point_per_entity() {
+        let points = build_points(&sample(), &names());
+        let body = encode_batches(&points, 100).concat();
+        let mut lines: Vec<&str> = body.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "circuit,circuit_id=c1,circuit_name=Alice\\ Smith,parent_node=AP\\ 1 bytes_down=1000i,bytes_up=200i,packets_down=10i,packets_up=2i,rtt_ms=25.5 100",
+                "site,node_type=site,site_name=Tower cake_drops_down=3i,cake_drops_up=4i 100",
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_bodies_by_line_limit() {
+        let points: Vec<Point> = (0..5)
+            .map(|i| {
+                let mut p = Point::new("circuit", i);
+                p.int("bytes_down", 1);
+                p
+            })
+            .collect();
+        let bodies = encode_batches(&points, 2);
+        assert_eq!(bodies.len(), 3);
+        assert_eq!(bodies[2].lines().count(), 1);
+    }
+
+    #[test]
+    fn backoff_grows_and_resets() {
+        let now = Instant::now();
+        let mut backoff = Backoff::default();
+        assert!(backoff.ready(now));
+        backoff.record_failure(now);
+        assert_eq!(backoff.current_delay(), Duration::from_secs(5));
+        assert!(!backoff.ready(now));
+        assert!(backoff.ready(now + Duration::from_secs(5)));
+        backoff.record_failure(now);
+        backoff.record_failure(now);
+        assert_eq!(backoff.current_delay(), Duration::from_secs(20));
+        for _ in 0..20 {
+            backoff.record_failure(now);
+        }
+        assert_eq!(backoff.current_delay(), MAX_BACKOFF);
+        backoff.reset();
+        assert!(backoff.ready(now));
+    }
+
+    #[test]
+    fn delivery_spools_after_retries_are_exhausted() {
+        let _ = lqos_utils::rustls::ensure_rustls_crypto_provider();
+        let (url, received) = writer::test_server::spawn(vec![500, 500, 204]);
+        let config = InfluxDbConfig {
+            enable_influxdb: true,
+            url,
+            ..Default::default()
+        };
+        let writer = InfluxWriter::new(&config).expect("writer");
+        let mut backoff = Backoff::default();
+
+        // One retry: both attempts hit a 500, so the batch must be spooled.
+        assert!(!deliver(&writer, "a b=1i 1\n", 1, &mut backoff));
+        assert!(!backoff.ready(Instant::now()));
+
+        let dir = std::env::temp_dir().join(format!("lqos_influx_{}", uuid::Uuid::new_v4()));
+        let mut spool = Spool::new(&dir, 1024).expect("spool");
+        spool.push("a b=1i 1\n").expect("push");
+
+        // Once the server recovers, the spool is replayed and emptied.
+        backoff.reset();
+        replay_spool(&writer, &spool, &mut backoff);
+        assert!(spool.oldest().expect("read").is_none());
+        assert_eq!(received.lock().expect("lock").len(), 3);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
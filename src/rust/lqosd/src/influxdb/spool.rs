@@ -0,0 +1,141 @@
+//! Bounded on-disk spool for line-protocol batches that could not be
+//! delivered. Each batch is stored as its own file; file names sort in
+//! arrival order so the oldest batch is always replayed (or evicted) first.
+
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+const SPOOL_EXTENSION: &str = "lp";
+
+pub(crate) struct Spool {
+    dir: PathBuf,
+    max_bytes: u64,
+    sequence: u64,
+}
+
+impl Spool {
+    pub(crate) fn new(dir: &Path, max_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            sequence: 0,
+        })
+    }
+
+    /// Stores a batch, then evicts the oldest batches until the spool fits
+    /// within its size bound. Returns the number of evicted batches.
+    pub(crate) fn push(&mut self, body: &str) -> Result<usize> {
+        if body.len() as u64 > self.max_bytes {
+            anyhow::bail!(
+                "Batch of {} bytes exceeds the spool limit of {} bytes",
+                body.len(),
+                self.max_bytes
+            );
+        }
+        let now_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        self.sequence = self.sequence.wrapping_add(1);
+        let name = format!(
+            "{now_ms:020}-{:010}.{SPOOL_EXTENSION}",
+            self.sequence % 10_000_000_000
+        );
+        fs::write(self.dir.join(name), body)?;
+        self.enforce_limit()
+    }
+
+    /// Returns the oldest spooled batch, if any.
+    pub(crate) fn oldest(&self) -> Result<Option<(PathBuf, String)>> {
+        let Some((path, _)) = self.entries()?.into_iter().next() else {
+            return Ok(None);
+        };
+        let body = fs::read_to_string(&path)?;
+        Ok(Some((path, body)))
+    }
+
+    pub(crate) fn remove(&self, path: &Path) -> Result<()> {
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    pub(crate) fn total_bytes(&self) -> Result<u64> {
+        Ok(self.entries()?.iter().map(|(_, size)| size).sum())
+    }
+
+    fn enforce_limit(&self) -> Result<usize> {
+        let entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|(_, size)| size).sum();
+        let mut evicted = 0;
+        for (path, size) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Unable to evict spooled InfluxDB batch {path:?}: {e:?}");
+                continue;
+            }
+            total = total.saturating_sub(size);
+            evicted += 1;
+        }
+        Ok(evicted)
+    }
+
+    /// Lists spooled batches, oldest first.
+    fn entries(&self) -> Result<Vec<(PathBuf, u64)>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SPOOL_EXTENSION) {
+                continue;
+            }
+            entries.push((path, entry.metadata()?.len()));
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("lqos_influx_spool_{}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn replays_oldest_batch_first() {
+        let dir = test_dir();
+        let mut spool = Spool::new(&dir, 1024).expect("spool");
+        spool.push("first\n").expect("push");
+        spool.push("second\n").expect("push");
+
+        let (path, body) = spool.oldest().expect("read").expect("batch");
+        assert_eq!(body, "first\n");
+        spool.remove(&path).expect("remove");
+        let (_, body) = spool.oldest().expect("read").expect("batch");
+        assert_eq!(body, "second\n");
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn evicts_oldest_batches_beyond_limit() {
+        let dir = test_dir();
+        let mut spool = Spool::new(&dir, 20).expect("spool");
+        assert_eq!(spool.push("aaaaaaaaa\n").expect("push"), 0);
+        assert_eq!(spool.push("bbbbbbbbb\n").expect("push"), 0);
+        assert_eq!(spool.push("ccccccccc\n").expect("push"), 1);
+        assert_eq!(spool.total_bytes().expect("size"), 20);
+        let (_, body) = spool.oldest().expect("read").expect("batch");
+        assert_eq!(body, "bbbbbbbbb\n");
+
+        assert!(spool.push(&"x".repeat(21)).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,191 @@
+//! HTTP client for the InfluxDB v2 `/api/v2/write` endpoint.
+
+use lqos_config::InfluxDbConfig;
+use reqwest::StatusCode;
+use std::time::Duration;
+use thiserror::Error;
+
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub(crate) enum WriteError {
+    /// The batch might succeed later (network failure, 5xx, 429).
+    #[error("InfluxDB write failed (retryable): {0}")]
+    Retryable(String),
+    /// InfluxDB rejected the batch itself; retrying will not help.
+    #[error("InfluxDB rejected the write: {0}")]
+    Rejected(String),
+}
+
+pub(crate) struct InfluxWriter {
+    client: reqwest::blocking::Client,
+    write_url: reqwest::Url,
+    token: String,
+}
+
+impl InfluxWriter {
+    pub(crate) fn new(config: &InfluxDbConfig) -> anyhow::Result<Self> {
+        let base = config.url.trim_end_matches('/');
+        let write_url = reqwest::Url::parse_with_params(
+            &format!("{base}/api/v2/write"),
+            &[
+                ("org", config.org.as_str()),
+                ("bucket", config.bucket.as_str()),
+                ("precision", "s"),
+            ],
+        )?;
+        let client = reqwest::blocking::Client::builder()
+            .timeout(WRITE_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            client,
+            write_url,
+            token: config.token.clone(),
+        })
+    }
+
+    /// Sends one line-protocol body.
+    pub(crate) fn write(&self, body: &str) -> Result<(), WriteError> {
+        let mut request = self
+            .client
+            .post(self.write_url.clone())
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(body.to_string());
+        if !self.token.is_empty() {
+            request = request.header("Authorization", format!("Token {}", self.token));
+        }
+        let response = request
+            .send()
+            .map_err(|e| WriteError::Retryable(e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let detail = format!("{status}: {}", response.text().unwrap_or_default());
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            Err(WriteError::Retryable(detail))
+        } else {
+            Err(WriteError::Rejected(detail))
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_server {
+    //! A tiny HTTP stand-in for InfluxDB (and webhook receivers), answering
+    //! each connection with the next status code from a script and recording
+    //! what it received.
+
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct ReceivedRequest {
+        pub(crate) request_line: String,
+        pub(crate) authorization: Option<String>,
+        pub(crate) headers: Vec<(String, String)>,
+        pub(crate) body: String,
+    }
+
+    pub(crate) fn spawn(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<ReceivedRequest>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind test listener");
+        let url = format!("http://{}", listener.local_addr().expect("local addr"));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        std::thread::spawn(move || {
+            for status in statuses {
+                let Ok((stream, _)) = listener.accept() else {
+                    return;
+                };
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                let _ = reader.read_line(&mut request_line);
+                let mut content_length = 0usize;
+                let mut authorization = None;
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        let name = name.to_ascii_lowercase();
+                        let value = value.trim().to_string();
+                        match name.as_str() {
+                            "content-length" => content_length = value.parse().unwrap_or(0),
+                            "authorization" => authorization = Some(value.clone()),
+                            _ => {}
+                        }
+                        headers.push((name, value));
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                let _ = reader.read_exact(&mut body);
+                if let Ok(mut log) = log.lock() {
+                    log.push(ReceivedRequest {
+                        request_line: request_line.trim().to_string(),
+                        authorization,
+                        headers,
+                        body: String::from_utf8_lossy(&body).to_string(),
+                    });
+                }
+                let mut stream = reader.into_inner();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status} Test\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                );
+            }
+        });
+        (url, received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_for(url: &str) -> InfluxDbConfig {
+        InfluxDbConfig {
+            enable_influxdb: true,
+            url: url.to_string(),
+            bucket: "libreqos".to_string(),
+            org: "My ISP".to_string(),
+            token: "secret".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn posts_line_protocol_with_token() {
+        let _ = lqos_utils::rustls::ensure_rustls_crypto_provider();
+        let (url, received) = test_server::spawn(vec![204]);
+        let writer = InfluxWriter::new(&config_for(&url)).expect("writer");
+        writer.write("circuit bytes_down=1i 1\n").expect("write");
+
+        let received = received.lock().expect("lock");
+        assert_eq!(received.len(), 1);
+        assert!(
+            received[0]
+                .request_line
+                .starts_with("POST /api/v2/write?org=My+ISP&bucket=libreqos&precision=s ")
+        );
+        assert_eq!(received[0].authorization.as_deref(), Some("Token secret"));
+        assert_eq!(received[0].body, "circuit bytes_down=1i 1\n");
+    }
+
+    #[test]
+    fn classifies_server_and_client_errors() {
+        let _ = lqos_utils::rustls::ensure_rustls_crypto_provider();
+        let (url, _) = test_server::spawn(vec![503, 400]);
+        let writer = InfluxWriter::new(&config_for(&url)).expect("writer");
+        assert!(matches!(
+            writer.write("a b=1i 1\n"),
+            Err(WriteError::Retryable(_))
+        ));
+        assert!(matches!(
+            writer.write("a b=1i 1\n"),
+            Err(WriteError::Rejected(_))
+        ));
+    }
+}
@@ -0,0 +1,367 @@
+//! Embedded long-term statistics.
+//!
+//! Without an Insight licence the only history lqosd has is a few minutes of
+//! in-memory ring buffers. This module records shaper, site and circuit
+//! throughput, RTT, retransmits and CAKE statistics to disk once a second,
+//! downsampling into one-minute and one-hour tiers, so the long-term charts
+//! keep working on unlicensed shapers. Retention for each tier is set in the
+//! `[local_history]` section of `lqos.conf`.
+
+mod sample;
+mod store;
+
+use crate::throughput_tracker::flow_data::ALL_FLOWS;
+use crate::throughput_tracker::{
+    THROUGHPUT_TRACKER, TickBatches, gather_tick_batches, min_max_median_rtt,
+    min_max_median_tcp_retransmits,
+};
+use crossbeam_channel::Sender;
+use fxhash::FxHashMap;
+use lqos_config::{Config, LocalHistoryConfig};
+use lqos_queue_tracker::TOTAL_QUEUE_STATS;
+use lqos_utils::unix_time::unix_now;
+pub(crate) use sample::{Aggregate, Metric, Sample, SeriesKey};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+pub(crate) use store::Frame;
+use store::{HistoryStore, SlotRollup, circuit_partitions, series_partitions, tiers};
+use tracing::{debug, info, warn};
+
+/// Samples waiting to be written. Disk writes are quick; if they aren't, a
+/// dropped second is better than a growing queue.
+const SAMPLE_QUEUE_DEPTH: usize = 4;
+/// Seconds between retention sweeps.
+const EXPIRE_INTERVAL_SECONDS: u64 = 60;
+/// Queries over every circuit read at most this many slots, picking a
+/// coarser tier if necessary.
+const MAX_CIRCUIT_SCAN_SLOTS: u64 = 240;
+
+static HISTORY_SENDER: OnceLock<Sender<HistorySample>> = OnceLock::new();
+static LAST_SAMPLE: AtomicU64 = AtomicU64::new(0);
+
+struct HistorySample {
+    timestamp: u64,
+    shaper: Sample,
+    batches: TickBatches,
+    record_circuits: bool,
+}
+
+/// Starts the history writer thread. It is idle unless
+/// `[local_history] enabled` is set.
+pub fn start_local_history() -> anyhow::Result<()> {
+    let (tx, rx) = crossbeam_channel::bounded::<HistorySample>(SAMPLE_QUEUE_DEPTH);
+    std::thread::Builder::new()
+        .name("Local History".to_string())
+        .spawn(move || {
+            let mut writer = Writer::default();
+            while let Ok(sample) = rx.recv() {
+                writer.handle_sample(sample);
+            }
+            warn!("Local history thread exiting");
+        })?;
+    let _ = HISTORY_SENDER.set(tx);
+    Ok(())
+}
+
+/// Is local history being recorded?
+pub(crate) fn history_enabled() -> bool {
+    lqos_config::load_config().is_ok_and(|config| config.local_history.enabled)
+}
+
+/// Called once per throughput tick. Gathers and enqueues one sample per
+/// second when local history is enabled.
+pub(crate) fn maybe_submit_sample(config: &Config, scale: f64) {
+    if !config.local_history.enabled {
+        return;
+    }
+    let Some(sender) = HISTORY_SENDER.get() else {
+        return;
+    };
+    let Ok(now) = unix_now() else {
+        return;
+    };
+    if LAST_SAMPLE.swap(now, Ordering::Relaxed) == now {
+        return;
+    }
+
+    // Don't record obviously bad data
+    let bits_per_second = THROUGHPUT_TRACKER.bits_per_second();
+    if bits_per_second.down > config.queues.downlink_bandwidth_mbps * 1_000_000
+        || bits_per_second.up > config.queues.uplink_bandwidth_mbps * 1_000_000
+    {
+        debug!("Spike detected - not recording local history");
+        return;
+    }
+
+    let sample = HistorySample {
+        timestamp: now,
+        shaper: shaper_sample(scale),
+        batches: gather_tick_batches(now, scale),
+        record_circuits: config.local_history.record_circuits,
+    };
+    if sender.try_send(sample).is_err() {
+        debug!("Local history writer is busy; dropping sample");
+    }
+}
+
+fn shaper_sample(scale: f64) -> Sample {
+    let scaled = |value: u64| (value as f64 * scale) as f32;
+    let tracker = &THROUGHPUT_TRACKER;
+    let mut sample = Sample::default();
+    sample.set(
+        Metric::BytesDown,
+        scaled(tracker.bytes_per_second.get_down()),
+    );
+    sample.set(Metric::BytesUp, scaled(tracker.bytes_per_second.get_up()));
+    sample.set(
+        Metric::ShapedBytesDown,
+        scaled(tracker.shaped_bytes_per_second.get_down()),
+    );
+    sample.set(
+        Metric::ShapedBytesUp,
+        scaled(tracker.shaped_bytes_per_second.get_up()),
+    );
+    sample.set(
+        Metric::PacketsDown,
+        scaled(tracker.packets_per_second.get_down()),
+    );
+    sample.set(
+        Metric::PacketsUp,
+        scaled(tracker.packets_per_second.get_up()),
+    );
+    sample.set(
+        Metric::TcpPacketsDown,
+        scaled(tracker.tcp_packets_per_second.get_down()),
+    );
+    sample.set(
+        Metric::TcpPacketsUp,
+        scaled(tracker.tcp_packets_per_second.get_up()),
+    );
+    sample.set(
+        Metric::UdpPacketsDown,
+        scaled(tracker.udp_packets_per_second.get_down()),
+    );
+    sample.set(
+        Metric::UdpPacketsUp,
+        scaled(tracker.udp_packets_per_second.get_up()),
+    );
+    sample.set(
+        Metric::IcmpPacketsDown,
+        scaled(tracker.icmp_packets_per_second.get_down()),
+    );
+    sample.set(
+        Metric::IcmpPacketsUp,
+        scaled(tracker.icmp_packets_per_second.get_up()),
+    );
+    if let Some(rtt) = min_max_median_rtt() {
+        sample.set(Metric::RttMs, rtt.median);
+    }
+    let retransmits = min_max_median_tcp_retransmits();
+    sample.set(Metric::RetransmitsDown, retransmits.down as f32);
+    sample.set(Metric::RetransmitsUp, retransmits.up as f32);
+    sample.set(
+        Metric::CakeMarksDown,
+        TOTAL_QUEUE_STATS.marks.get_down() as f32,
+    );
+    sample.set(Metric::CakeMarksUp, TOTAL_QUEUE_STATS.marks.get_up() as f32);
+    sample.set(
+        Metric::CakeDropsDown,
+        TOTAL_QUEUE_STATS.drops.get_down() as f32,
+    );
+    sample.set(Metric::CakeDropsUp, TOTAL_QUEUE_STATS.drops.get_up() as f32);
+    sample.set(Metric::Flows, ALL_FLOWS.lock().flow_data.len() as f32);
+    sample
+}
+
+#[derive(Default)]
+struct Writer {
+    active: Option<(LocalHistoryConfig, HistoryStore)>,
+    last_expire: u64,
+}
+
+impl Writer {
+    fn handle_sample(&mut self, sample: HistorySample) {
+        let Ok(config) = lqos_config::load_config() else {
+            return;
+        };
+        if !config.local_history.enabled {
+            self.active = None;
+            return;
+        }
+        if self.active.as_ref().map(|(c, _)| c) != Some(&config.local_history) {
+            info!("Recording local history");
+            let store = HistoryStore::new(tiers(&config.local_history, &config.lqos_directory));
+            self.active = Some((config.local_history.clone(), store));
+        }
+        let Some((_, store)) = self.active.as_mut() else {
+            return;
+        };
+
+        if let Err(e) = store.record(build_frame(sample)) {
+            warn!("Unable to write local history: {e:?}");
+        }
+        let Ok(now) = unix_now() else {
+            return;
+        };
+        if now.saturating_sub(self.last_expire) >= EXPIRE_INTERVAL_SECONDS {
+            self.last_expire = now;
+            match store.expire(now as i64) {
+                Ok(0) => {}
+                Ok(removed) => debug!("Removed {removed} expired local history segment(s)"),
+                Err(e) => warn!("Unable to expire local history: {e:?}"),
+            }
+        }
+    }
+}
+
+/// Merges the shaper totals and the per-metric batches into one sample per
+/// series.
+fn build_frame(sample: HistorySample) -> Frame {
+    let batches = &sample.batches;
+    let mut series: FxHashMap<SeriesKey, Sample> = FxHashMap::default();
+    let mut set = |key: SeriesKey, metric: Metric, value: f32| {
+        series.entry(key).or_default().set(metric, value);
+    };
+
+    for s in batches.site_throughput.iter() {
+        let key = SeriesKey::Site(s.site_hash);
+        set(key, Metric::BytesDown, s.download_bytes as f32);
+        set(key, Metric::BytesUp, s.upload_bytes as f32);
+        set(key, Metric::PacketsDown, s.packets_down as f32);
+        set(key, Metric::PacketsUp, s.packets_up as f32);
+        set(key, Metric::TcpPacketsDown, s.packets_tcp_down as f32);
+        set(key, Metric::TcpPacketsUp, s.packets_tcp_up as f32);
+    }
+    for s in batches.site_rtt.iter() {
+        set(SeriesKey::Site(s.site_hash), Metric::RttMs, s.median_rtt);
+    }
+    for s in batches.site_retransmits.iter() {
+        let key = SeriesKey::Site(s.site_hash);
+        set(key, Metric::RetransmitsDown, s.tcp_retransmits_down as f32);
+        set(key, Metric::RetransmitsUp, s.tcp_retransmits_up as f32);
+    }
+    for s in batches.site_cake_marks.iter() {
+        let key = SeriesKey::Site(s.site_hash);
+        set(key, Metric::CakeMarksDown, s.cake_marks_down as f32);
+        set(key, Metric::CakeMarksUp, s.cake_marks_up as f32);
+    }
+    for s in batches.site_cake_drops.iter() {
+        let key = SeriesKey::Site(s.site_hash);
+        set(key, Metric::CakeDropsDown, s.cake_drops_down as f32);
+        set(key, Metric::CakeDropsUp, s.cake_drops_up as f32);
+    }
+
+    if sample.record_circuits {
+        for c in batches.circuit_throughput.iter() {
+            let key = SeriesKey::Circuit(c.circuit_hash);
+            set(key, Metric::BytesDown, c.download_bytes as f32);
+            set(key, Metric::BytesUp, c.upload_bytes as f32);
+            set(key, Metric::PacketsDown, c.packets_down as f32);
+            set(key, Metric::PacketsUp, c.packets_up as f32);
+            set(key, Metric::TcpPacketsDown, c.tcp_packets_down as f32);
+            set(key, Metric::TcpPacketsUp, c.tcp_packets_up as f32);
+        }
+        for c in batches.circuit_rtt.iter() {
+            set(
+                SeriesKey::Circuit(c.circuit_hash),
+                Metric::RttMs,
+                c.median_rtt,
+            );
+        }
+        for c in batches.circuit_retransmits.iter() {
+            let key = SeriesKey::Circuit(c.circuit_hash);
+            set(key, Metric::RetransmitsDown, c.tcp_retransmits_down as f32);
+            set(key, Metric::RetransmitsUp, c.tcp_retransmits_up as f32);
+        }
+        for c in batches.circuit_cake_marks.iter() {
+            let key = SeriesKey::Circuit(c.circuit_hash);
+            set(key, Metric::CakeMarksDown, c.cake_marks_down as f32);
+            set(key, Metric::CakeMarksUp, c.cake_marks_up as f32);
+        }
+        for c in batches.circuit_cake_drops.iter() {
+            let key = SeriesKey::Circuit(c.circuit_hash);
+            set(key, Metric::CakeDropsDown, c.cake_drops_down as f32);
+            set(key, Metric::CakeDropsUp, c.cake_drops_up as f32);
+        }
+    }
+
+    let mut series: Vec<(SeriesKey, Sample)> = series.into_iter().collect();
+    series.push((SeriesKey::Shaper, sample.shaper));
+    Frame {
+        time: sample.timestamp as i64,
+        series,
+    }
+}
+
+/// What a history query reads.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum HistorySelection {
+    /// A single series
+    Series(SeriesKey),
+    /// Every circuit
+    Circuits,
+}
+
+/// Frames answering a history query, oldest first.
+pub(crate) struct HistoryQuery {
+    /// Seconds covered by each frame
+    pub(crate) width: u64,
+    pub(crate) frames: Vec<Frame>,
+}
+
+/// Reads the last `seconds` of history from the finest tier that covers
+/// them, combining slots so that no more than `max_points` frames are
+/// returned. This reads from disk, so call it from a blocking context.
+///
+/// ## Returns
+/// * `None` if local history is disabled.
+pub(crate) fn query_history(
+    selection: HistorySelection,
+    seconds: u64,
+    max_points: usize,
+) -> Option<HistoryQuery> {
+    let config = lqos_config::load_config().ok()?;
+    if !config.local_history.enabled {
+        return None;
+    }
+    let now = unix_now().ok()? as i64;
+    let tiers = tiers(&config.local_history, &config.lqos_directory);
+    let max_slots = match selection {
+        HistorySelection::Series(_) => u64::MAX,
+        HistorySelection::Circuits => MAX_CIRCUIT_SCAN_SLOTS,
+    };
+    let tier = tiers
+        .iter()
+        .find(|tier| tier.retention >= seconds && seconds / tier.resolution <= max_slots)
+        .or(tiers.last())?;
+
+    let mut frames = match selection {
+        HistorySelection::Series(key) => {
+            let mut frames = tier.read(&series_partitions(&key), now - seconds as i64, now);
+            for frame in frames.iter_mut() {
+                frame.series.retain(|(k, _)| *k == key);
+            }
+            frames.retain(|frame| !frame.series.is_empty());
+            frames
+        }
+        HistorySelection::Circuits => tier.read(&circuit_partitions(), now - seconds as i64, now),
+    };
+
+    let slots = seconds.div_ceil(tier.resolution);
+    let factor = slots.div_ceil(max_points.max(1) as u64).max(1);
+    let width = tier.resolution * factor;
+    if factor > 1 {
+        frames = combine_frames(frames, width);
+    }
+    Some(HistoryQuery { width, frames })
+}
+
+fn combine_frames(frames: Vec<Frame>, width: u64) -> Vec<Frame> {
+    let mut rollup = SlotRollup::default();
+    let mut combined: Vec<Frame> = frames
+        .iter()
+        .filter_map(|frame| rollup.push(width, frame))
+        .collect();
+    combined.extend(rollup.take());
+    combined
+}
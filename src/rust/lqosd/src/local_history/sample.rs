@@ -0,0 +1,190 @@
+//! Values stored in the history: which series a value belongs to, which
+//! metric it is, and how values are summarised when downsampling.
+
+use serde::{Deserialize, Serialize};
+
+/// The entity a series describes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) enum SeriesKey {
+    /// Shaper-wide totals
+    Shaper,
+    /// A network.json node, by `hash_to_i64(name)`
+    Site(i64),
+    /// A circuit, by circuit hash
+    Circuit(i64),
+}
+
+/// Everything the store records. Rates are per second.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) enum Metric {
+    BytesDown,
+    BytesUp,
+    ShapedBytesDown,
+    ShapedBytesUp,
+    PacketsDown,
+    PacketsUp,
+    TcpPacketsDown,
+    TcpPacketsUp,
+    UdpPacketsDown,
+    UdpPacketsUp,
+    IcmpPacketsDown,
+    IcmpPacketsUp,
+    RttMs,
+    RetransmitsDown,
+    RetransmitsUp,
+    CakeMarksDown,
+    CakeMarksUp,
+    CakeDropsDown,
+    CakeDropsUp,
+    Flows,
+}
+
+impl Metric {
+    /// Counters are left out of a sample when they are zero, so a missing
+    /// value means zero. A missing RTT means there was nothing to measure.
+    fn zero_when_missing(self) -> bool {
+        self != Metric::RttMs
+    }
+}
+
+/// Summary of the values seen in one time slot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Aggregate {
+    pub(crate) min: f32,
+    pub(crate) max: f32,
+    pub(crate) median: f32,
+}
+
+impl Aggregate {
+    fn single(value: f32) -> Self {
+        Self {
+            min: value,
+            max: value,
+            median: value,
+        }
+    }
+}
+
+/// One series' values for one time slot. Only observed metrics are present.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Sample {
+    values: Vec<(Metric, Aggregate)>,
+}
+
+impl Sample {
+    /// Records a raw (one second) reading.
+    pub(crate) fn set(&mut self, metric: Metric, value: f32) {
+        self.set_aggregate(metric, Aggregate::single(value));
+    }
+
+    fn set_aggregate(&mut self, metric: Metric, aggregate: Aggregate) {
+        if let Some(slot) = self.values.iter_mut().find(|(m, _)| *m == metric) {
+            slot.1 = aggregate;
+        } else {
+            self.values.push((metric, aggregate));
+        }
+    }
+
+    pub(crate) fn get(&self, metric: Metric) -> Option<Aggregate> {
+        self.values
+            .iter()
+            .find(|(m, _)| *m == metric)
+            .map(|(_, a)| *a)
+    }
+
+    /// Like `get`, but reports a missing counter as zero.
+    pub(crate) fn get_or_zero(&self, metric: Metric) -> Aggregate {
+        self.get(metric).unwrap_or_default()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+struct RollupMetric {
+    metric: Metric,
+    min: f32,
+    max: f32,
+    medians: Vec<f32>,
+}
+
+/// Combines the samples of several slots into one coarser sample: minimum
+/// of minimums, maximum of maximums and median of medians.
+#[derive(Default)]
+pub(crate) struct Rollup {
+    metrics: Vec<RollupMetric>,
+}
+
+impl Rollup {
+    pub(crate) fn add(&mut self, sample: &Sample) {
+        for (metric, value) in sample.values.iter() {
+            if let Some(entry) = self.metrics.iter_mut().find(|m| m.metric == *metric) {
+                entry.min = entry.min.min(value.min);
+                entry.max = entry.max.max(value.max);
+                entry.medians.push(value.median);
+            } else {
+                self.metrics.push(RollupMetric {
+                    metric: *metric,
+                    min: value.min,
+                    max: value.max,
+                    medians: vec![value.median],
+                });
+            }
+        }
+    }
+
+    /// Produces the combined sample. `slots` is the number of slots the
+    /// rollup covers; counters missing from some of them count as zero.
+    pub(crate) fn finish(self, slots: usize) -> Sample {
+        let mut sample = Sample::default();
+        for mut entry in self.metrics.into_iter() {
+            if entry.metric.zero_when_missing() && entry.medians.len() < slots {
+                entry.min = entry.min.min(0.0);
+                entry.medians.resize(slots, 0.0);
+            }
+            entry.medians.sort_by(f32::total_cmp);
+            let median = entry.medians[entry.medians.len() / 2];
+            sample.set_aggregate(
+                entry.metric,
+                Aggregate {
+                    min: entry.min,
+                    max: entry.max,
+                    median,
+                },
+            );
+        }
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rollup_treats_missing_counters_as_zero() {
+        let mut rollup = Rollup::default();
+        for bytes in [100.0, 300.0, 200.0] {
+            let mut sample = Sample::default();
+            sample.set(Metric::BytesDown, bytes);
+            sample.set(Metric::RttMs, bytes / 10.0);
+            rollup.add(&sample);
+        }
+        // Two idle seconds: no counters and no RTT
+        let combined = rollup.finish(5);
+
+        let bytes = combined.get(Metric::BytesDown).expect("bytes recorded");
+        assert_eq!(bytes.min, 0.0);
+        assert_eq!(bytes.max, 300.0);
+        assert_eq!(bytes.median, 100.0);
+
+        let rtt = combined.get(Metric::RttMs).expect("rtt recorded");
+        assert_eq!(rtt.min, 10.0);
+        assert_eq!(rtt.max, 30.0);
+        assert_eq!(rtt.median, 20.0);
+
+        assert_eq!(combined.get(Metric::Flows), None);
+        assert_eq!(combined.get_or_zero(Metric::Flows), Aggregate::default());
+    }
+}
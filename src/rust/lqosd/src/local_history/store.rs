@@ -0,0 +1,472 @@
+//! On-disk layout and downsampling.
+//!
+//! Each tier has its own directory. Series are spread over partitions
+//! (`shaper`, `sites-NN`, `circuits-NN`) so that reading one site or circuit
+//! doesn't mean decoding the whole network, and each partition holds one
+//! file per segment of time: `<dir>/<tier>/<partition>/<segment start>.seg`.
+//! A segment file is a sequence of frames, each a timestamp, a length and a
+//! deflated bincode body. Files are only ever appended to, and expired
+//! segments are deleted whole. A frame torn by a crash or a full disk is cut
+//! off before the next append, so the frames after it stay readable.
+
+use super::sample::{Rollup, Sample, SeriesKey};
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use fxhash::FxHashMap;
+use lqos_config::LocalHistoryConfig;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{OpenOptions, create_dir_all, read_dir, remove_file};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+const SITE_PARTITIONS: i64 = 16;
+const CIRCUIT_PARTITIONS: i64 = 64;
+const FRAME_HEADER_BYTES: usize = 12;
+
+/// Every series recorded for one time slot.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Frame {
+    /// Start of the slot (UNIX seconds)
+    pub(crate) time: i64,
+    pub(crate) series: Vec<(SeriesKey, Sample)>,
+}
+
+fn partition(key: &SeriesKey) -> String {
+    match key {
+        SeriesKey::Shaper => "shaper".to_string(),
+        SeriesKey::Site(hash) => format!("sites-{:02}", hash.rem_euclid(SITE_PARTITIONS)),
+        SeriesKey::Circuit(hash) => format!("circuits-{:02}", hash.rem_euclid(CIRCUIT_PARTITIONS)),
+    }
+}
+
+pub(crate) fn series_partitions(key: &SeriesKey) -> Vec<String> {
+    vec![partition(key)]
+}
+
+pub(crate) fn circuit_partitions() -> Vec<String> {
+    (0..CIRCUIT_PARTITIONS)
+        .map(|n| format!("circuits-{n:02}"))
+        .collect()
+}
+
+/// One resolution of the store.
+pub(crate) struct Tier {
+    /// Seconds covered by each slot
+    pub(crate) resolution: u64,
+    /// Seconds covered by each segment file
+    segment_span: u64,
+    /// Seconds of history kept
+    pub(crate) retention: u64,
+    root: PathBuf,
+    /// The segment each partition last had its tail checked in, since
+    /// startup
+    checked: Mutex<FxHashMap<String, i64>>,
+}
+
+/// The tiers described by the configuration, finest first.
+pub(crate) fn tiers(config: &LocalHistoryConfig, lqos_directory: &str) -> Vec<Tier> {
+    let root = match &config.directory {
+        Some(directory) => PathBuf::from(directory),
+        None => Path::new(lqos_directory).join("history"),
+    };
+    vec![
+        Tier::new(
+            &root,
+            "seconds",
+            1,
+            600,
+            config.second_retention_minutes * 60,
+        ),
+        Tier::new(
+            &root,
+            "minutes",
+            60,
+            6 * 3600,
+            config.minute_retention_days * 86_400,
+        ),
+        Tier::new(
+            &root,
+            "hours",
+            3600,
+            7 * 86_400,
+            config.hour_retention_days * 86_400,
+        ),
+    ]
+}
+
+impl Tier {
+    fn new(
+        root: &Path,
+        name: &'static str,
+        resolution: u64,
+        segment_span: u64,
+        retention: u64,
+    ) -> Self {
+        Self {
+            resolution,
+            segment_span,
+            retention,
+            root: root.join(name),
+            checked: Mutex::new(FxHashMap::default()),
+        }
+    }
+
+    fn segment_start(&self, time: i64) -> i64 {
+        time - time.rem_euclid(self.segment_span as i64)
+    }
+
+    /// Writes a frame, split across the partitions its series belong to.
+    pub(crate) fn append(&self, frame: &Frame) -> anyhow::Result<()> {
+        let mut by_partition: FxHashMap<String, Vec<(SeriesKey, Sample)>> = FxHashMap::default();
+        for (key, sample) in frame.series.iter() {
+            by_partition
+                .entry(partition(key))
+                .or_default()
+                .push((*key, sample.clone()));
+        }
+        let segment = self.segment_start(frame.time);
+        for (partition, series) in by_partition.into_iter() {
+            let directory = self.root.join(&partition);
+            create_dir_all(&directory)?;
+            let body = encode_frame(&Frame {
+                time: frame.time,
+                series,
+            })?;
+            let path = directory.join(format!("{segment}.seg"));
+            if self.checked.lock().get(&partition) != Some(&segment) {
+                trim_torn_frame(&path)?;
+                self.checked.lock().insert(partition, segment);
+            }
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let length = file.metadata()?.len();
+            let mut bytes = Vec::with_capacity(FRAME_HEADER_BYTES + body.len());
+            bytes.extend_from_slice(&frame.time.to_le_bytes());
+            bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&body);
+            if let Err(e) = file.write_all(&bytes) {
+                // Don't leave part of a frame for the next one to land behind
+                let _ = file.set_len(length);
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the frames in `from..=to` from the given partitions, merging
+    /// frames with the same timestamp. A frame cut short by a crash ends
+    /// the read of its file.
+    pub(crate) fn read(&self, partitions: &[String], from: i64, to: i64) -> Vec<Frame> {
+        let mut merged: BTreeMap<i64, Vec<(SeriesKey, Sample)>> = BTreeMap::new();
+        for partition in partitions {
+            for (start, path) in self.segments(partition) {
+                if start > to || start + (self.segment_span as i64) <= from {
+                    continue;
+                }
+                let Ok(bytes) = std::fs::read(&path) else {
+                    continue;
+                };
+                for (time, body) in FrameIter::new(&bytes) {
+                    if time < from || time > to {
+                        continue;
+                    }
+                    if let Ok(frame) = decode_frame(body) {
+                        merged.entry(time).or_default().extend(frame.series);
+                    }
+                }
+            }
+        }
+        merged
+            .into_iter()
+            .map(|(time, series)| Frame { time, series })
+            .collect()
+    }
+
+    /// Deletes segments that are entirely older than the retention period.
+    pub(crate) fn expire(&self, now: i64) -> anyhow::Result<usize> {
+        let oldest = now - self.retention as i64;
+        let mut removed = 0;
+        if !self.root.exists() {
+            return Ok(0);
+        }
+        for entry in read_dir(&self.root)? {
+            let Some(partition) = entry?.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            for (start, path) in self.segments(&partition) {
+                if start + (self.segment_span as i64) <= oldest {
+                    remove_file(path)?;
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    fn segments(&self, partition: &str) -> Vec<(i64, PathBuf)> {
+        let Ok(entries) = read_dir(self.root.join(partition)) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != "seg" {
+                    return None;
+                }
+                let start = path.file_stem()?.to_str()?.parse::<i64>().ok()?;
+                Some((start, path))
+            })
+            .collect()
+    }
+}
+
+/// Cuts a segment back to its last whole frame.
+fn trim_torn_frame(path: &Path) -> anyhow::Result<()> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut frames = FrameIter::new(&bytes);
+    for _ in frames.by_ref() {}
+    if frames.bytes.is_empty() {
+        return Ok(());
+    }
+    let whole = bytes.len() - frames.bytes.len();
+    warn!(
+        "Trimming {} bytes of a torn frame from {}",
+        frames.bytes.len(),
+        path.display()
+    );
+    OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_len(whole as u64)?;
+    Ok(())
+}
+
+fn encode_frame(frame: &Frame) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(&bincode::serialize(frame)?)?;
+    Ok(encoder.finish()?)
+}
+
+fn decode_frame(body: &[u8]) -> anyhow::Result<Frame> {
+    let mut raw = Vec::new();
+    DeflateDecoder::new(body).read_to_end(&mut raw)?;
+    Ok(bincode::deserialize(&raw)?)
+}
+
+/// Walks the frames in a segment file without decoding them.
+struct FrameIter<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> FrameIter<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+}
+
+impl<'a> Iterator for FrameIter<'a> {
+    type Item = (i64, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (header, rest) = self.bytes.split_at_checked(FRAME_HEADER_BYTES)?;
+        let time = i64::from_le_bytes(header[..8].try_into().ok()?);
+        let len = u32::from_le_bytes(header[8..].try_into().ok()?) as usize;
+        let (body, rest) = rest.split_at_checked(len)?;
+        self.bytes = rest;
+        Some((time, body))
+    }
+}
+
+/// Accumulates consecutive frames into slots of `resolution` seconds.
+#[derive(Default)]
+pub(crate) struct SlotRollup {
+    slot: i64,
+    frames: usize,
+    series: FxHashMap<SeriesKey, Rollup>,
+}
+
+impl SlotRollup {
+    /// Adds a frame. Returns the previous slot, summarised, once a frame
+    /// from a later slot arrives.
+    pub(crate) fn push(&mut self, resolution: u64, frame: &Frame) -> Option<Frame> {
+        let slot = frame.time - frame.time.rem_euclid(resolution as i64);
+        let finished = if self.frames > 0 && slot != self.slot {
+            self.take()
+        } else {
+            None
+        };
+        self.slot = slot;
+        self.frames += 1;
+        for (key, sample) in frame.series.iter() {
+            self.series.entry(*key).or_default().add(sample);
+        }
+        finished
+    }
+
+    /// Summarises whatever has been collected so far.
+    pub(crate) fn take(&mut self) -> Option<Frame> {
+        if self.frames == 0 {
+            return None;
+        }
+        let frames = std::mem::take(&mut self.frames);
+        let series = std::mem::take(&mut self.series)
+            .into_iter()
+            .map(|(key, rollup)| (key, rollup.finish(frames)))
+            .filter(|(_, sample)| !sample.is_empty())
+            .collect();
+        Some(Frame {
+            time: self.slot,
+            series,
+        })
+    }
+}
+
+/// Writes incoming one-second frames to every tier, downsampling as it goes.
+pub(crate) struct HistoryStore {
+    tiers: Vec<Tier>,
+    /// `rollups[n]` builds slots for `tiers[n + 1]`
+    rollups: Vec<SlotRollup>,
+}
+
+impl HistoryStore {
+    pub(crate) fn new(tiers: Vec<Tier>) -> Self {
+        let rollups = (1..tiers.len()).map(|_| SlotRollup::default()).collect();
+        Self { tiers, rollups }
+    }
+
+    pub(crate) fn record(&mut self, frame: Frame) -> anyhow::Result<()> {
+        self.tiers[0].append(&frame)?;
+        let mut pending = Some(frame);
+        for (rollup, tier) in self.rollups.iter_mut().zip(self.tiers.iter().skip(1)) {
+            let Some(frame) = pending.take() else {
+                break;
+            };
+            pending = rollup.push(tier.resolution, &frame);
+            if let Some(finished) = &pending {
+                tier.append(finished)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn expire(&self, now: i64) -> anyhow::Result<usize> {
+        let mut removed = 0;
+        for tier in self.tiers.iter() {
+            removed += tier.expire(now)?;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_history::sample::Metric;
+
+    fn test_config(name: &str) -> (LocalHistoryConfig, PathBuf) {
+        let directory =
+            std::env::temp_dir().join(format!("lqos_history_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let config = LocalHistoryConfig {
+            directory: Some(directory.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        (config, directory)
+    }
+
+    fn frame(time: i64, bytes: f32) -> Frame {
+        let mut shaper = Sample::default();
+        shaper.set(Metric::BytesDown, bytes);
+        let mut circuit = Sample::default();
+        circuit.set(Metric::BytesDown, bytes / 2.0);
+        Frame {
+            time,
+            series: vec![
+                (SeriesKey::Shaper, shaper),
+                (SeriesKey::Circuit(42), circuit),
+            ],
+        }
+    }
+
+    #[test]
+    fn records_and_downsamples_across_tiers() {
+        let (config, directory) = test_config("downsample");
+        let mut store = HistoryStore::new(tiers(&config, "/unused"));
+        let start = 1_700_000_000 - 1_700_000_000 % 3600;
+        for second in 0..125 {
+            store
+                .record(frame(start + second, second as f32))
+                .expect("write history");
+        }
+
+        let tiers = &store.tiers;
+        let shaper = series_partitions(&SeriesKey::Shaper);
+        let seconds = tiers[0].read(&shaper, start, start + 200);
+        assert_eq!(seconds.len(), 125);
+        assert_eq!(seconds[10].series.len(), 1, "circuits live elsewhere");
+
+        // Two complete minutes; the third is still being collected
+        let minutes = tiers[1].read(&shaper, start, start + 200);
+        assert_eq!(minutes.len(), 2);
+        let first = minutes[0].series[0]
+            .1
+            .get(Metric::BytesDown)
+            .expect("bytes");
+        assert_eq!((first.min, first.max, first.median), (0.0, 59.0, 30.0));
+
+        let circuit = series_partitions(&SeriesKey::Circuit(42));
+        let minutes = tiers[1].read(&circuit, start, start + 200);
+        assert_eq!(minutes[1].series[0].0, SeriesKey::Circuit(42));
+        assert_eq!(minutes[1].time, start + 60);
+
+        let _ = std::fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn trims_truncated_frames_and_expires_old_segments() {
+        let (config, directory) = test_config("expire");
+        let store = HistoryStore::new(tiers(&config, "/unused"));
+        let tier = &store.tiers[0];
+        let start = 1_700_000_000 - 1_700_000_000 % 600;
+        tier.append(&frame(start, 1.0)).expect("write history");
+        tier.append(&frame(start + 1, 2.0)).expect("write history");
+
+        // Simulate a crash part way through a write
+        let shaper = series_partitions(&SeriesKey::Shaper);
+        let path = directory
+            .join("seconds")
+            .join(&shaper[0])
+            .join(format!("{start}.seg"));
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .expect("segment exists");
+        file.write_all(&(start + 2).to_le_bytes())
+            .expect("write partial frame");
+        assert_eq!(tier.read(&shaper, start, start + 10).len(), 2);
+
+        // After a restart, new frames land after the last whole one
+        let store = HistoryStore::new(tiers(&config, "/unused"));
+        let tier = &store.tiers[0];
+        tier.append(&frame(start + 3, 3.0)).expect("write history");
+        let frames = tier.read(&shaper, start, start + 10);
+        assert_eq!(
+            frames.iter().map(|f| f.time).collect::<Vec<_>>(),
+            vec![start, start + 1, start + 3]
+        );
+
+        assert_eq!(store.expire(start + 600).expect("expire"), 0);
+        assert_eq!(store.expire(start + 600 + 3600).expect("expire"), 2);
+        assert!(tier.read(&shaper, start, start + 10).is_empty());
+
+        let _ = std::fs::remove_dir_all(directory);
+    }
+}
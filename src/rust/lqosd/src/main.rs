@@ -3,13 +3,19 @@
 
 #![deny(clippy::unwrap_used)]
 
+mod anomaly_detection;
+mod audit;
+mod bandwidth_schedules;
 mod blackboard;
 mod file_lock;
+mod influxdb;
 mod ip_mapping;
+mod local_history;
 #[cfg(feature = "equinix_tests")]
 mod lqos_daht_test;
 pub mod lts2_sys;
 mod node_manager;
+mod override_expiry;
 mod preflight_checks;
 mod program_control;
 mod remote_commands;
@@ -24,8 +30,10 @@ mod tool_status;
 mod treeguard;
 mod tuning;
 mod urgent;
+mod usage_quotas;
 mod validation;
 mod version_checks;
+mod webhooks;
 
 #[cfg(feature = "flamegraphs")]
 use std::io::Write;
@@ -39,8 +47,8 @@ use crate::{
 };
 use anyhow::Result;
 use lqos_bus::{
-    BusRequest, BusResponse, InsightLicenseSummary, TreeGuardRuntimeNodeBranchSnapshot,
-    TreeGuardRuntimeNodeOperationSnapshot, UnixSocketServer,
+    BusRequest, BusResponse, InsightLicenseSummary, TlsBusServer,
+    TreeGuardRuntimeNodeBranchSnapshot, TreeGuardRuntimeNodeOperationSnapshot, UnixSocketServer,
 };
 use lqos_heimdall::{n_second_packet_dump, perf_interface::heimdall_handle_events, start_heimdall};
 use lqos_queue_tracker::{
@@ -234,6 +242,24 @@ fn main() -> Result<()> {
         info!("Insight client started successfully");
     }
     blackboard::start_blackboard();
+    if let Err(e) = bandwidth_schedules::start_bandwidth_scheduler() {
+        warn!("Failed to start bandwidth schedules: {e:?}");
+    }
+    if let Err(e) = usage_quotas::start_usage_quotas() {
+        warn!("Failed to start usage quotas: {e:?}");
+    }
+    if let Err(e) = influxdb::start_influxdb_exporter() {
+        warn!("Failed to start InfluxDB exporter: {e:?}");
+    }
+    if let Err(e) = local_history::start_local_history() {
+        warn!("Failed to start local history: {e:?}");
+    }
+    if let Err(e) = webhooks::start_webhooks() {
+        warn!("Failed to start webhooks: {e:?}");
+    }
+    if let Err(e) = shaped_devices_tracker::start_circuit_status_watcher() {
+        warn!("Failed to start circuit status watcher: {e:?}");
+    }
     start_remote_commands();
     let flow_tx = setup_netflow_tracker()?;
     let _ = throughput_tracker::flow_data::setup_flow_analysis();
@@ -365,6 +391,21 @@ fn main() -> Result<()> {
                     warn!("Webserver disabled by configuration");
                 }
 
+                // Remote bus over TLS, if enabled
+                if config.remote_bus.enabled {
+                    let remote_bus = config.remote_bus.clone();
+                    tokio::spawn(async move {
+                        match TlsBusServer::bind(&remote_bus).await {
+                            Ok(server) => {
+                                if let Err(e) = server.listen(handle_bus_requests).await {
+                                    error!("Remote bus stopped: {e}");
+                                }
+                            }
+                            Err(e) => error!("Unable to start the remote bus: {e}"),
+                        }
+                    });
+                }
+
                 // Main bus listen loop
                 if let Err(e) = server.listen(handle_bus_requests, bus_rx).await {
                     error!("Bus stopped: {e:?}");
@@ -657,15 +698,24 @@ fn handle_bus_requests(requests: &[BusRequest], responses: &mut Vec<BusResponse>
             } => {
                 if let Some(sender) = lqos_bakery::BAKERY_SENDER.get() {
                     let sender = sender.clone();
+                    let rates = bandwidth_schedules::site_rates(
+                        *site_hash,
+                        bandwidth_schedules::ScheduledRates {
+                            download_min: *download_bandwidth_min,
+                            upload_min: *upload_bandwidth_min,
+                            download_max: *download_bandwidth_max,
+                            upload_max: *upload_bandwidth_max,
+                        },
+                    );
                     let _ = sender.send(lqos_bakery::BakeryCommands::AddSite {
                         site_hash: *site_hash,
                         parent_class_id: *parent_class_id,
                         up_parent_class_id: *up_parent_class_id,
                         class_minor: *class_minor,
-                        download_bandwidth_min: *download_bandwidth_min,
-                        upload_bandwidth_min: *upload_bandwidth_min,
-                        download_bandwidth_max: *download_bandwidth_max,
-                        upload_bandwidth_max: *upload_bandwidth_max,
+                        download_bandwidth_min: rates.download_min,
+                        upload_bandwidth_min: rates.upload_min,
+                        download_bandwidth_max: rates.download_max,
+                        upload_bandwidth_max: rates.upload_max,
                     });
                     BusResponse::Ack
                 } else {
@@ -700,6 +750,15 @@ fn handle_bus_requests(requests: &[BusRequest], responses: &mut Vec<BusResponse>
                     }
                 if let Some(sender) = lqos_bakery::BAKERY_SENDER.get() {
                     let sender = sender.clone();
+                    let rates = bandwidth_schedules::circuit_rates(
+                        *circuit_hash,
+                        bandwidth_schedules::ScheduledRates {
+                            download_min: *download_bandwidth_min,
+                            upload_min: *upload_bandwidth_min,
+                            download_max: *download_bandwidth_max,
+                            upload_max: *upload_bandwidth_max,
+                        },
+                    );
                     let _ = sender.send(lqos_bakery::BakeryCommands::AddCircuit {
                         circuit_hash: *circuit_hash,
                         circuit_name: circuit_name.clone(),
@@ -707,10 +766,10 @@ fn handle_bus_requests(requests: &[BusRequest], responses: &mut Vec<BusResponse>
                         parent_class_id: *parent_class_id,
                         up_parent_class_id: *up_parent_class_id,
                         class_minor: *class_minor,
-                        download_bandwidth_min: *download_bandwidth_min,
-                        upload_bandwidth_min: *upload_bandwidth_min,
-                        download_bandwidth_max: *download_bandwidth_max,
-                        upload_bandwidth_max: *upload_bandwidth_max,
+                        download_bandwidth_min: rates.download_min,
+                        upload_bandwidth_min: rates.upload_min,
+                        download_bandwidth_max: rates.download_max,
+                        upload_bandwidth_max: rates.upload_max,
                         class_major: *class_major,
                         up_class_major: *up_class_major,
                         down_qdisc_handle: None,
@@ -769,6 +828,8 @@ fn handle_bus_requests(requests: &[BusRequest], responses: &mut Vec<BusResponse>
                                 infra_qdiscs: entry.infra_qdiscs,
                                 cake_qdiscs: entry.cake_qdiscs,
                                 fq_codel_qdiscs: entry.fq_codel_qdiscs,
+                                fq_pie_qdiscs: entry.fq_pie_qdiscs,
+                                sfq_qdiscs: entry.sfq_qdiscs,
                                 estimated_memory_bytes: entry.estimated_memory_bytes,
                             })
                             .collect(),
@@ -971,6 +1032,18 @@ fn handle_bus_requests(requests: &[BusRequest], responses: &mut Vec<BusResponse>
                     .collect();
                 BusResponse::ProtocolList(entries)
             }
+            BusRequest::GetApplicationList => {
+                let entries = node_manager::application_list_data()
+                    .into_iter()
+                    .map(|entry| lqos_bus::ApplicationListEntry {
+                        count: entry.count,
+                        application: entry.application,
+                        bytes: entry.bytes,
+                        median_rtt_nanos: entry.median_rtt_nanos,
+                    })
+                    .collect();
+                BusResponse::ApplicationList(entries)
+            }
             BusRequest::GetAsnFlowTimeline { asn } => {
                 let data = node_manager::flow_timeline_data(*asn)
                     .into_iter()
@@ -992,6 +1065,13 @@ fn handle_bus_requests(requests: &[BusRequest], responses: &mut Vec<BusResponse>
                     .collect();
                 BusResponse::ProtocolFlowTimeline(data)
             }
+            BusRequest::GetApplicationFlowTimeline { application } => {
+                let data = node_manager::application_timeline_data(application)
+                    .into_iter()
+                    .map(flow_timeline_to_bus)
+                    .collect();
+                BusResponse::ApplicationFlowTimeline(data)
+            }
             BusRequest::GetSchedulerDetails => {
                 let details = node_manager::scheduler_details_data();
                 BusResponse::SchedulerDetails(lqos_bus::SchedulerDetails {
@@ -1043,6 +1123,11 @@ fn handle_bus_requests(requests: &[BusRequest], responses: &mut Vec<BusResponse>
                     max_circuits,
                 })
             }
+            // The socket server answers these itself; they only get here
+            // through the in-process channel, which can't stream.
+            BusRequest::Subscribe { .. } => BusResponse::Fail(
+                "Subscriptions are only available on the bus socket".to_string(),
+            ),
         });
     }
 }
@@ -1067,6 +1152,7 @@ fn flow_timeline_to_bus(entry: node_manager::FlowTimeline) -> lqos_bus::FlowTime
         retransmit_times_up: entry.retransmit_times_up,
         total_bytes: entry.total_bytes,
         protocol: entry.protocol,
+        application: entry.application,
         circuit_id: entry.circuit_id,
         circuit_name: entry.circuit_name,
         remote_ip: entry.remote_ip,
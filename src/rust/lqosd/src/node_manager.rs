@@ -1,5 +1,7 @@
+mod api_v1;
 mod auth;
 pub(crate) mod local_api;
+mod metrics;
 mod run;
 mod shaper_queries_actor;
 mod static_pages;
@@ -12,8 +14,9 @@ pub use local_api::circuit_count::circuit_count_data;
 pub use local_api::device_counts::device_count;
 pub(crate) use local_api::executive_cache::invalidate_executive_cache_snapshot;
 pub use local_api::flow_explorer::{
-    FlowTimeline, asn_list_data, country_list_data, country_timeline_data, flow_timeline_data,
-    protocol_list_data, protocol_timeline_data,
+    FlowTimeline, application_list_data, application_timeline_data, asn_list_data,
+    country_list_data, country_timeline_data, flow_timeline_data, protocol_list_data,
+    protocol_timeline_data,
 };
 pub use local_api::flow_map::flow_map_data;
 pub use local_api::scheduler::scheduler_details_data;
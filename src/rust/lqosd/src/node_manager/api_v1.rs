@@ -0,0 +1,541 @@
+//! Versioned JSON API for integrating LibreQoS with external systems
+//! (OSS/BSS, provisioning, monitoring). Unlike the websocket, which is
+//! shaped around the UI, these endpoints are documented: the OpenAPI
+//! document at `/api/v1/openapi.json` is built from the same Rust types the
+//! handlers exchange.
+//!
+//! Reads authenticate with an API token (`Authorization: Bearer ...`) or a
+//! logged-in session and are open to any login. Changes need an API token
+//! with the matching scope, and are written to the audit log.
+
+mod openapi;
+mod schemas;
+
+use crate::audit;
+use crate::bandwidth_schedules;
+use crate::node_manager::auth::{LoginResult, api_auth_layer};
+use crate::node_manager::local_api::circuit_live::{
+    CircuitLiveMetrics, CircuitMetricsQuery, circuit_live_metrics,
+};
+use crate::node_manager::local_api::urgent::{
+    UrgentList, urgent_clear_all_data, urgent_clear_id, urgent_list_data,
+};
+use crate::node_manager::local_api::{
+    circuit, config, network_tree, reload_libreqos, shaped_device_api,
+};
+use axum::extract::{Path, Query};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Extension, Json, Router};
+use lqos_config::{ApiTokenScope, AuditActor, AuditEntry, NetworkJsonTransport, ShapedDevice};
+use lqos_overrides::{
+    CircuitAdjustment, NetworkAdjustment, OverrideFile, OverrideLayer, OverrideStore,
+};
+use openapi::Operation;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::warn;
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// The body of every failed request.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    /// What went wrong.
+    error: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, error: impl Into<String>) -> Self {
+        Self {
+            status,
+            error: error.into(),
+        }
+    }
+
+    fn forbidden() -> Self {
+        Self::new(StatusCode::FORBIDDEN, "Permission denied")
+    }
+
+    fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "Not found")
+    }
+
+    /// Maps the error strings returned by the shaped device editors.
+    fn from_message(message: String) -> Self {
+        match message.as_str() {
+            "Unauthorized" => Self::forbidden(),
+            "Not found" => Self::not_found(),
+            _ => Self::new(StatusCode::BAD_REQUEST, message),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+/// A circuit and the shaped devices that belong to it.
+#[derive(Clone, Debug, Serialize)]
+pub struct Circuit {
+    pub circuit_id: String,
+    pub circuit_name: String,
+    pub parent_node: String,
+    pub devices: Vec<ShapedDevice>,
+}
+
+impl Circuit {
+    /// Groups devices into circuits, in the order circuits first appear.
+    fn group(devices: Vec<ShapedDevice>) -> Vec<Circuit> {
+        let mut circuits: Vec<Circuit> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for device in devices {
+            match index.get(&device.circuit_id) {
+                Some(&i) => circuits[i].devices.push(device),
+                None => {
+                    index.insert(device.circuit_id.clone(), circuits.len());
+                    circuits.push(Circuit {
+                        circuit_id: device.circuit_id.clone(),
+                        circuit_name: device.circuit_name.clone(),
+                        parent_node: device.parent_node.clone(),
+                        devices: vec![device],
+                    });
+                }
+            }
+        }
+        circuits
+    }
+}
+
+/// A node of the network tree and its position in the tree's node list,
+/// which is what `parents` and `immediate_parent` refer to.
+#[derive(Clone, Debug, Serialize)]
+pub struct NetworkTreeNode {
+    pub index: usize,
+    pub node: NetworkJsonTransport,
+}
+
+/// The operator overrides layer (`lqos_overrides.json`). Entries are
+/// removed by their position in these lists.
+#[derive(Clone, Debug, Serialize)]
+pub struct Overrides {
+    pub circuit_adjustments: Vec<CircuitAdjustment>,
+    pub network_adjustments: Vec<NetworkAdjustment>,
+}
+
+impl From<&OverrideFile> for Overrides {
+    fn from(overrides: &OverrideFile) -> Self {
+        Self {
+            circuit_adjustments: overrides.circuit_adjustments().to_vec(),
+            network_adjustments: overrides.network_adjustments().to_vec(),
+        }
+    }
+}
+
+/// A human-readable result.
+#[derive(Clone, Debug, Serialize)]
+pub struct MessageResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+struct CircuitStatsQuery {
+    ids: String,
+}
+
+/// The `/api/v1` router.
+pub fn api_v1() -> Router {
+    Router::new()
+        .route("/devices", get(list_devices).post(create_device))
+        .route(
+            "/devices/:id",
+            get(get_device).put(update_device).delete(delete_device),
+        )
+        .route("/circuits", get(list_circuits))
+        .route("/circuits/:id", get(get_circuit).delete(delete_circuit))
+        .route("/circuits/:id/live", get(get_circuit_live))
+        .route("/stats/circuits", get(circuit_stats))
+        .route("/network/tree", get(get_network_tree))
+        .route("/overrides", get(get_overrides))
+        .route("/overrides/circuit", post(add_circuit_override))
+        .route("/overrides/circuit/:index", delete(remove_circuit_override))
+        .route("/overrides/network", post(add_network_override))
+        .route("/overrides/network/:index", delete(remove_network_override))
+        .route("/urgent", get(list_urgent).delete(clear_all_urgent))
+        .route("/urgent/:id", delete(clear_urgent))
+        .route("/reload", post(reload))
+        .route_layer(axum::middleware::from_fn(api_auth_layer))
+        .route("/openapi.json", get(openapi_document))
+}
+
+/// Every endpoint, for the OpenAPI document.
+fn operations() -> Vec<Operation> {
+    vec![
+        Operation::new("get", "/devices", "List shaped devices").returns::<Vec<ShapedDevice>>(200),
+        Operation::new("post", "/devices", "Add a shaped device")
+            .requires("ShapedDevicesWrite")
+            .body::<ShapedDevice>()
+            .returns::<ShapedDevice>(201),
+        Operation::new("get", "/devices/:id", "Get a shaped device").returns::<ShapedDevice>(200),
+        Operation::new("put", "/devices/:id", "Replace a shaped device")
+            .requires("ShapedDevicesWrite")
+            .body::<ShapedDevice>()
+            .returns::<ShapedDevice>(200),
+        Operation::new("delete", "/devices/:id", "Remove a shaped device")
+            .requires("ShapedDevicesWrite"),
+        Operation::new("get", "/circuits", "List circuits with their devices")
+            .returns::<Vec<Circuit>>(200),
+        Operation::new("get", "/circuits/:id", "Get a circuit with its devices")
+            .returns::<Circuit>(200),
+        Operation::new(
+            "delete",
+            "/circuits/:id",
+            "Remove a circuit and all its devices",
+        )
+        .requires("ShapedDevicesWrite"),
+        Operation::new("get", "/circuits/:id/live", "Live traffic for a circuit")
+            .returns::<CircuitLiveMetrics>(200),
+        Operation::new(
+            "get",
+            "/stats/circuits",
+            "Live traffic for several circuits",
+        )
+        .query("ids", "Comma-separated circuit IDs, at most 250")
+        .returns::<Vec<CircuitLiveMetrics>>(200),
+        Operation::new("get", "/network/tree", "The network tree with live traffic")
+            .returns::<Vec<NetworkTreeNode>>(200),
+        Operation::new("get", "/overrides", "List operator overrides").returns::<Overrides>(200),
+        Operation::new(
+            "post",
+            "/overrides/circuit",
+            "Add a circuit or device override",
+        )
+        .requires("OverridesWrite")
+        .body::<CircuitAdjustment>()
+        .returns::<Overrides>(201),
+        Operation::new(
+            "delete",
+            "/overrides/circuit/:index",
+            "Remove a circuit or device override",
+        )
+        .requires("OverridesWrite")
+        .returns::<Overrides>(200),
+        Operation::new("post", "/overrides/network", "Add a network override")
+            .requires("OverridesWrite")
+            .body::<NetworkAdjustment>()
+            .returns::<Overrides>(201),
+        Operation::new(
+            "delete",
+            "/overrides/network/:index",
+            "Remove a network override",
+        )
+        .requires("OverridesWrite")
+        .returns::<Overrides>(200),
+        Operation::new("get", "/urgent", "List urgent issues").returns::<UrgentList>(200),
+        Operation::new("delete", "/urgent", "Clear all urgent issues").requires("Reload"),
+        Operation::new("delete", "/urgent/:id", "Clear an urgent issue").requires("Reload"),
+        Operation::new("post", "/reload", "Reload LibreQoS")
+            .requires("Reload")
+            .returns::<MessageResponse>(200),
+        Operation::new("get", "/openapi.json", "This document").public(),
+    ]
+}
+
+async fn openapi_document() -> Json<Value> {
+    Json(openapi::document::<ApiError>(
+        "LibreQoS API",
+        "1",
+        "/api/v1",
+        &operations(),
+    ))
+}
+
+async fn list_devices() -> Json<Vec<ShapedDevice>> {
+    Json(shaped_device_api::all_shaped_devices_data())
+}
+
+async fn get_device(Path(device_id): Path<String>) -> ApiResult<Json<ShapedDevice>> {
+    shaped_device_api::shaped_device_data(&device_id)
+        .map(Json)
+        .ok_or_else(ApiError::not_found)
+}
+
+async fn create_device(
+    Extension(login): Extension<LoginResult>,
+    Extension(actor): Extension<AuditActor>,
+    Json(device): Json<ShapedDevice>,
+) -> ApiResult<(StatusCode, Json<ShapedDevice>)> {
+    config::create_shaped_device_data(login, &actor, device)
+        .map(|device| (StatusCode::CREATED, Json(device)))
+        .map_err(ApiError::from_message)
+}
+
+async fn update_device(
+    Extension(login): Extension<LoginResult>,
+    Extension(actor): Extension<AuditActor>,
+    Path(device_id): Path<String>,
+    Json(device): Json<ShapedDevice>,
+) -> ApiResult<Json<ShapedDevice>> {
+    config::update_shaped_device_data(login, &actor, device_id, device)
+        .map(Json)
+        .map_err(ApiError::from_message)
+}
+
+async fn delete_device(
+    Extension(login): Extension<LoginResult>,
+    Extension(actor): Extension<AuditActor>,
+    Path(device_id): Path<String>,
+) -> ApiResult<StatusCode> {
+    config::delete_shaped_device_data(login, &actor, device_id)
+        .map(|()| StatusCode::NO_CONTENT)
+        .map_err(ApiError::from_message)
+}
+
+async fn list_circuits() -> Json<Vec<Circuit>> {
+    Json(Circuit::group(shaped_device_api::all_shaped_devices_data()))
+}
+
+async fn get_circuit(Path(circuit_id): Path<String>) -> ApiResult<Json<Circuit>> {
+    let data = circuit::circuit_by_id_data(&circuit_id).ok_or_else(ApiError::not_found)?;
+    Circuit::group(data.devices)
+        .pop()
+        .map(Json)
+        .ok_or_else(ApiError::not_found)
+}
+
+async fn delete_circuit(
+    Extension(login): Extension<LoginResult>,
+    Extension(actor): Extension<AuditActor>,
+    Path(circuit_id): Path<String>,
+) -> ApiResult<StatusCode> {
+    config::delete_circuit_data(login, &actor, circuit_id)
+        .map(|_| StatusCode::NO_CONTENT)
+        .map_err(ApiError::from_message)
+}
+
+async fn get_circuit_live(Path(circuit_id): Path<String>) -> ApiResult<Json<CircuitLiveMetrics>> {
+    circuit_live_metrics(&CircuitMetricsQuery {
+        circuit_ids: vec![circuit_id],
+    })
+    .pop()
+    .map(Json)
+    .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "No live data for this circuit"))
+}
+
+async fn circuit_stats(
+    Query(query): Query<CircuitStatsQuery>,
+) -> ApiResult<Json<Vec<CircuitLiveMetrics>>> {
+    let circuit_ids: Vec<String> = query
+        .ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect();
+    if circuit_ids.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "ids must list at least one circuit",
+        ));
+    }
+    Ok(Json(circuit_live_metrics(&CircuitMetricsQuery {
+        circuit_ids,
+    })))
+}
+
+async fn get_network_tree() -> Json<Vec<NetworkTreeNode>> {
+    Json(
+        network_tree::network_tree_data()
+            .into_iter()
+            .map(|(index, node)| NetworkTreeNode { index, node })
+            .collect(),
+    )
+}
+
+async fn get_overrides() -> ApiResult<Json<Overrides>> {
+    let overrides = OverrideStore::load_layer(OverrideLayer::Operator).map_err(|e| {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Unable to load overrides: {e}"),
+        )
+    })?;
+    Ok(Json(Overrides::from(&overrides)))
+}
+
+/// Applies `edit` to the operator overrides layer, saves and audits it.
+/// `edit` returns the entry before and after the change, or `None` when
+/// there was nothing to change.
+fn edit_operator_overrides<T: Serialize>(
+    login: LoginResult,
+    actor: &AuditActor,
+    action: &str,
+    edit: impl FnOnce(&mut OverrideFile) -> Option<(Option<T>, Option<T>)>,
+) -> ApiResult<Overrides> {
+    if !login.allows(ApiTokenScope::OverridesWrite) {
+        return Err(ApiError::forbidden());
+    }
+    let mut overrides = OverrideStore::load_layer(OverrideLayer::Operator).map_err(|e| {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Unable to load overrides: {e}"),
+        )
+    })?;
+    let (before, after) = edit(&mut overrides).ok_or_else(ApiError::not_found)?;
+    OverrideStore::save_layer(OverrideLayer::Operator, &overrides).map_err(|e| {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Unable to save overrides: {e}"),
+        )
+    })?;
+    audit::record(
+        AuditEntry::new(actor.clone(), action)
+            .target("lqos_overrides.json")
+            .changes(before.as_ref(), after.as_ref()),
+    );
+    // Temporary adjustments take effect now; the rest at the next reload.
+    if let Err(e) = bandwidth_schedules::overrides_changed() {
+        warn!("Unable to apply changed overrides live: {e}");
+    }
+    Ok(Overrides::from(&overrides))
+}
+
+async fn add_circuit_override(
+    Extension(login): Extension<LoginResult>,
+    Extension(actor): Extension<AuditActor>,
+    Json(adjustment): Json<CircuitAdjustment>,
+) -> ApiResult<(StatusCode, Json<Overrides>)> {
+    edit_operator_overrides(login, &actor, "add_circuit_adjustment", |overrides| {
+        overrides.add_circuit_adjustment(adjustment.clone());
+        Some((None, Some(adjustment)))
+    })
+    .map(|overrides| (StatusCode::CREATED, Json(overrides)))
+}
+
+async fn remove_circuit_override(
+    Extension(login): Extension<LoginResult>,
+    Extension(actor): Extension<AuditActor>,
+    Path(index): Path<usize>,
+) -> ApiResult<Json<Overrides>> {
+    edit_operator_overrides(login, &actor, "remove_circuit_adjustment", |overrides| {
+        let removed = overrides.circuit_adjustments().get(index).cloned()?;
+        overrides.remove_circuit_adjustment_by_index(index);
+        Some((Some(removed), None))
+    })
+    .map(Json)
+}
+
+async fn add_network_override(
+    Extension(login): Extension<LoginResult>,
+    Extension(actor): Extension<AuditActor>,
+    Json(adjustment): Json<NetworkAdjustment>,
+) -> ApiResult<(StatusCode, Json<Overrides>)> {
+    edit_operator_overrides(login, &actor, "add_network_adjustment", |overrides| {
+        overrides.add_network_adjustment(adjustment.clone());
+        Some((None, Some(adjustment)))
+    })
+    .map(|overrides| (StatusCode::CREATED, Json(overrides)))
+}
+
+async fn remove_network_override(
+    Extension(login): Extension<LoginResult>,
+    Extension(actor): Extension<AuditActor>,
+    Path(index): Path<usize>,
+) -> ApiResult<Json<Overrides>> {
+    edit_operator_overrides(login, &actor, "remove_network_adjustment", |overrides| {
+        let removed = overrides.network_adjustments().get(index).cloned()?;
+        overrides.remove_network_adjustment_by_index(index);
+        Some((Some(removed), None))
+    })
+    .map(Json)
+}
+
+async fn list_urgent() -> Json<UrgentList> {
+    Json(urgent_list_data())
+}
+
+async fn clear_urgent(
+    Extension(login): Extension<LoginResult>,
+    Extension(actor): Extension<AuditActor>,
+    Path(id): Path<u64>,
+) -> ApiResult<StatusCode> {
+    if !login.allows(ApiTokenScope::Reload) {
+        return Err(ApiError::forbidden());
+    }
+    if !urgent_clear_id(id) {
+        return Err(ApiError::not_found());
+    }
+    audit::record(AuditEntry::new(actor, "clear_urgent_issue").target(id.to_string()));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn clear_all_urgent(
+    Extension(login): Extension<LoginResult>,
+    Extension(actor): Extension<AuditActor>,
+) -> ApiResult<StatusCode> {
+    if !login.allows(ApiTokenScope::Reload) {
+        return Err(ApiError::forbidden());
+    }
+    urgent_clear_all_data();
+    audit::record(AuditEntry::new(actor, "clear_all_urgent_issues"));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn reload(
+    Extension(login): Extension<LoginResult>,
+    Extension(actor): Extension<AuditActor>,
+) -> ApiResult<Json<MessageResponse>> {
+    if !login.allows(ApiTokenScope::Reload) {
+        return Err(ApiError::forbidden());
+    }
+    reload_libreqos::reload_libreqos(&actor)
+        .await
+        .map(|message| Json(MessageResponse { message }))
+        .map_err(|message| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(circuit_id: &str, device_id: &str) -> ShapedDevice {
+        ShapedDevice {
+            circuit_id: circuit_id.to_string(),
+            circuit_name: format!("Circuit {circuit_id}"),
+            device_id: device_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn devices_group_into_circuits_in_order() {
+        let circuits = Circuit::group(vec![device("2", "a"), device("1", "b"), device("2", "c")]);
+        assert_eq!(circuits.len(), 2);
+        assert_eq!(circuits[0].circuit_id, "2");
+        assert_eq!(circuits[0].devices.len(), 2);
+        assert_eq!(circuits[1].circuit_name, "Circuit 1");
+    }
+
+    #[test]
+    fn every_operation_is_documented_with_resolvable_references() {
+        let document = openapi::document::<ApiError>("LibreQoS API", "1", "/api/v1", &operations());
+        assert_eq!(document["paths"].as_object().unwrap().len(), 16);
+        let text = document.to_string();
+        for reference in text.split("\"#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(
+                document["components"]["schemas"][name].is_object(),
+                "{name} is referenced but not defined"
+            );
+        }
+        assert!(
+            document["paths"]["/devices/{id}"]["put"]["requestBody"].is_object(),
+            "PUT /devices/{{id}} takes a device"
+        );
+    }
+}
@@ -0,0 +1,505 @@
+//! Builds the OpenAPI 3.1 document for `/api/v1` from the Rust types the
+//! endpoints exchange.
+//!
+//! Each type describes its JSON form through [`ApiSchema`]. Structs and
+//! tagged enums use [`object_schema!`] and [`tagged_enum_schema!`], which
+//! destructure the type without `..`: adding, removing or retyping a field
+//! stops the build until the schema is updated to match.
+
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Named schemas, emitted under `components/schemas`.
+pub(crate) type Components = BTreeMap<String, Value>;
+
+/// A type with a JSON schema.
+pub(crate) trait ApiSchema {
+    /// Named types are described once under `components/schemas` and
+    /// referenced from everywhere else.
+    const NAME: Option<&'static str> = None;
+    /// Whether an object field of this type may be missing or null.
+    const OPTIONAL: bool = false;
+    /// The JSON schema for this type, registering any named types it uses.
+    fn schema(components: &mut Components) -> Value;
+}
+
+/// Returns the schema for `T`, or a reference to it for named types.
+pub(crate) fn schema_for<T: ApiSchema>(components: &mut Components) -> Value {
+    let Some(name) = T::NAME else {
+        return T::schema(components);
+    };
+    if !components.contains_key(name) {
+        // Reserve the name first so that recursive types terminate.
+        components.insert(name.to_string(), Value::Null);
+        let schema = T::schema(components);
+        components.insert(name.to_string(), schema);
+    }
+    json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+/// One object property: its JSON name, schema and whether it's optional.
+pub(crate) type Property = (&'static str, Value, bool);
+
+/// An object schema with the given properties. Optional ones aren't
+/// listed as required.
+pub(crate) fn object(properties: Vec<Property>) -> Value {
+    let required: Vec<&str> = properties
+        .iter()
+        .filter(|(_, _, optional)| !optional)
+        .map(|(name, _, _)| *name)
+        .collect();
+    let properties: Map<String, Value> = properties
+        .into_iter()
+        .map(|(name, schema, _)| (name.to_string(), schema))
+        .collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// One variant of an internally tagged enum, as an object with a constant
+/// tag property.
+pub(crate) fn tagged_variant(tag: &'static str, variant: &str, mut fields: Vec<Property>) -> Value {
+    fields.insert(
+        0,
+        (tag, json!({ "type": "string", "const": variant }), false),
+    );
+    object(fields)
+}
+
+/// The JSON name of a field: its identifier, unless serde renames it.
+macro_rules! field_name {
+    ($field:ident) => {
+        stringify!($field)
+    };
+    ($field:ident, $rename:literal) => {
+        $rename
+    };
+}
+pub(crate) use field_name;
+
+/// Implements [`ApiSchema`] for a struct as a named object schema. Every
+/// field must be listed, with `as "name"` for serde renames; fields marked
+/// `#[serde(skip)]` go in the trailing `skip` list.
+macro_rules! object_schema {
+    (
+        $ty:ident {
+            $($field:ident $(as $rename:literal)?: $fty:ty),* $(,)?
+        }
+        $(skip { $($skipped:ident),* $(,)? })?
+    ) => {
+        impl $crate::node_manager::api_v1::openapi::ApiSchema for $ty {
+            const NAME: Option<&'static str> = Some(stringify!($ty));
+
+            fn schema(
+                components: &mut $crate::node_manager::api_v1::openapi::Components,
+            ) -> serde_json::Value {
+                use $crate::node_manager::api_v1::openapi::{ApiSchema, schema_for};
+                let _ = |value: &$ty| {
+                    let $ty { $($field,)* $($($skipped: _,)*)? } = value;
+                    $(let _: &$fty = $field;)*
+                };
+                $crate::node_manager::api_v1::openapi::object(vec![$((
+                    $crate::node_manager::api_v1::openapi::field_name!($field $(, $rename)?),
+                    schema_for::<$fty>(components),
+                    <$fty as ApiSchema>::OPTIONAL,
+                )),*])
+            }
+        }
+    };
+}
+pub(crate) use object_schema;
+
+/// Implements [`ApiSchema`] for an internally tagged enum whose variants
+/// all have named fields. Every variant and field must be listed, each
+/// variant with its serialized tag value.
+macro_rules! tagged_enum_schema {
+    (
+        $ty:ident tag $tag:literal {
+            $($variant:ident as $value:literal {
+                $($field:ident $(as $rename:literal)?: $fty:ty),* $(,)?
+            }),* $(,)?
+        }
+    ) => {
+        impl $crate::node_manager::api_v1::openapi::ApiSchema for $ty {
+            const NAME: Option<&'static str> = Some(stringify!($ty));
+
+            fn schema(
+                components: &mut $crate::node_manager::api_v1::openapi::Components,
+            ) -> serde_json::Value {
+                use $crate::node_manager::api_v1::openapi::{ApiSchema, schema_for};
+                let _ = |value: &$ty| match value {
+                    $($ty::$variant { $($field),* } => {
+                        $(let _: &$fty = $field;)*
+                    })*
+                };
+                serde_json::json!({
+                    "oneOf": [$(
+                        $crate::node_manager::api_v1::openapi::tagged_variant(
+                            $tag,
+                            $value,
+                            vec![$((
+                                $crate::node_manager::api_v1::openapi::field_name!($field $(, $rename)?),
+                                schema_for::<$fty>(components),
+                                <$fty as ApiSchema>::OPTIONAL,
+                            )),*],
+                        )
+                    ),*],
+                    "discriminator": { "propertyName": $tag },
+                })
+            }
+        }
+    };
+}
+pub(crate) use tagged_enum_schema;
+
+macro_rules! primitive_schema {
+    ($($ty:ty => $schema:tt),* $(,)?) => {
+        $(impl ApiSchema for $ty {
+            fn schema(_: &mut Components) -> Value {
+                json!($schema)
+            }
+        })*
+    };
+}
+
+primitive_schema! {
+    String => { "type": "string" },
+    bool => { "type": "boolean" },
+    u32 => { "type": "integer", "format": "int32", "minimum": 0 },
+    u64 => { "type": "integer", "format": "int64", "minimum": 0 },
+    usize => { "type": "integer", "format": "int64", "minimum": 0 },
+    i64 => { "type": "integer", "format": "int64" },
+    f32 => { "type": "number", "format": "float" },
+    f64 => { "type": "number", "format": "double" },
+    Ipv4Addr => { "type": "string", "format": "ipv4" },
+    Ipv6Addr => { "type": "string", "format": "ipv6" },
+}
+
+impl<T: ApiSchema> ApiSchema for Option<T> {
+    const OPTIONAL: bool = true;
+
+    fn schema(components: &mut Components) -> Value {
+        json!({ "anyOf": [schema_for::<T>(components), { "type": "null" }] })
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for Vec<T> {
+    fn schema(components: &mut Components) -> Value {
+        json!({ "type": "array", "items": schema_for::<T>(components) })
+    }
+}
+
+impl<A: ApiSchema, B: ApiSchema> ApiSchema for (A, B) {
+    fn schema(components: &mut Components) -> Value {
+        json!({
+            "type": "array",
+            "prefixItems": [schema_for::<A>(components), schema_for::<B>(components)],
+            "minItems": 2,
+            "maxItems": 2,
+        })
+    }
+}
+
+type SchemaFn = fn(&mut Components) -> Value;
+
+/// One endpoint in the document.
+pub(crate) struct Operation {
+    method: &'static str,
+    path: &'static str,
+    summary: &'static str,
+    scope: Option<&'static str>,
+    query: Vec<(&'static str, &'static str)>,
+    body: Option<SchemaFn>,
+    status: u16,
+    response: Option<SchemaFn>,
+    public: bool,
+}
+
+impl Operation {
+    /// An endpoint at an axum-style path (`/devices/:id`), answering
+    /// `204 No Content` until [`Operation::returns`] says otherwise.
+    pub(crate) fn new(method: &'static str, path: &'static str, summary: &'static str) -> Self {
+        Self {
+            method,
+            path,
+            summary,
+            scope: None,
+            query: Vec::new(),
+            body: None,
+            status: 204,
+            response: None,
+            public: false,
+        }
+    }
+
+    /// The API token scope needed, beyond read-only.
+    pub(crate) fn requires(mut self, scope: &'static str) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    /// Adds a string query parameter.
+    pub(crate) fn query(mut self, name: &'static str, description: &'static str) -> Self {
+        self.query.push((name, description));
+        self
+    }
+
+    /// The JSON request body.
+    pub(crate) fn body<T: ApiSchema>(mut self) -> Self {
+        self.body = Some(schema_for::<T>);
+        self
+    }
+
+    /// The status and JSON body of a successful response.
+    pub(crate) fn returns<T: ApiSchema>(mut self, status: u16) -> Self {
+        self.status = status;
+        self.response = Some(schema_for::<T>);
+        self
+    }
+
+    /// Marks the endpoint as needing no authentication.
+    pub(crate) fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    /// The path in OpenAPI form, and its parameter names.
+    fn openapi_path(&self) -> (String, Vec<&'static str>) {
+        let mut params = Vec::new();
+        let path = self
+            .path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(param) => {
+                    params.push(param);
+                    format!("{{{param}}}")
+                }
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        (path, params)
+    }
+
+    fn describe(&self, components: &mut Components, error: &Value) -> Value {
+        let (_, params) = self.openapi_path();
+        let mut parameters: Vec<Value> = params
+            .iter()
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string" },
+                })
+            })
+            .collect();
+        parameters.extend(self.query.iter().map(|(name, description)| {
+            json!({
+                "name": name,
+                "in": "query",
+                "description": description,
+                "schema": { "type": "string" },
+            })
+        }));
+
+        let success = match self.response {
+            Some(schema) => json!({
+                "description": "Success",
+                "content": { "application/json": { "schema": schema(components) } },
+            }),
+            None => json!({ "description": "Success" }),
+        };
+        let error_response = json!({
+            "description": "Error",
+            "content": { "application/json": { "schema": error } },
+        });
+        let mut responses = Map::new();
+        responses.insert(self.status.to_string(), success);
+        responses.insert("default".to_string(), error_response);
+
+        let mut operation = json!({
+            "summary": self.summary,
+            "parameters": parameters,
+            "responses": responses,
+        });
+        if let Some(scope) = self.scope {
+            operation["description"] = json!(format!("Requires the `{scope}` API token scope."));
+        }
+        if let Some(body) = self.body {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": body(components) } },
+            });
+        }
+        if self.public {
+            operation["security"] = json!([]);
+        }
+        operation
+    }
+}
+
+/// Builds the OpenAPI document for `operations`, served under `server`.
+/// Failed requests answer with `E`.
+pub(crate) fn document<E: ApiSchema>(
+    title: &str,
+    version: &str,
+    server: &str,
+    operations: &[Operation],
+) -> Value {
+    let mut components = Components::new();
+    let error = schema_for::<E>(&mut components);
+    let mut paths: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+    for operation in operations {
+        let (path, _) = operation.openapi_path();
+        let described = operation.describe(&mut components, &error);
+        paths
+            .entry(path)
+            .or_default()
+            .insert(operation.method.to_string(), described);
+    }
+    json!({
+        "openapi": "3.1.0",
+        "info": { "title": title, "version": version },
+        "servers": [{ "url": server }],
+        "security": [{ "bearerAuth": [] }],
+        "paths": paths,
+        "components": {
+            "schemas": components,
+            "securitySchemes": {
+                "bearerAuth": { "type": "http", "scheme": "bearer" },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Sample {
+        name: String,
+        #[serde(rename = "type")]
+        kind: Option<String>,
+        ranges: Vec<(Ipv4Addr, u32)>,
+        #[serde(skip)]
+        #[allow(dead_code)]
+        hash: i64,
+    }
+
+    object_schema!(Sample {
+        name: String,
+        kind as "type": Option<String>,
+        ranges: Vec<(Ipv4Addr, u32)>,
+    } skip { hash });
+
+    #[derive(Serialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum Change {
+        Rename { from: String, to: String },
+        Remove { name: String },
+    }
+
+    tagged_enum_schema!(Change tag "type" {
+        Rename as "rename" { from: String, to: String },
+        Remove as "remove" { name: String },
+    });
+
+    #[derive(Serialize)]
+    struct Failure {
+        error: String,
+    }
+
+    object_schema!(Failure { error: String });
+
+    #[test]
+    fn structs_describe_their_serialized_fields() {
+        let mut components = Components::new();
+        let reference = schema_for::<Sample>(&mut components);
+        assert_eq!(reference["$ref"], "#/components/schemas/Sample");
+        let schema = &components["Sample"];
+        let sample = serde_json::to_value(Sample {
+            name: "a".to_string(),
+            kind: None,
+            ranges: vec![(Ipv4Addr::LOCALHOST, 8)],
+            hash: 0,
+        })
+        .unwrap();
+        let serialized: Vec<&String> = sample.as_object().unwrap().keys().collect();
+        let described: Vec<&String> = schema["properties"].as_object().unwrap().keys().collect();
+        let mut serialized_sorted = serialized.clone();
+        serialized_sorted.sort();
+        assert_eq!(serialized_sorted, described);
+        assert_eq!(schema["required"], json!(["name", "ranges"]));
+        assert_eq!(
+            schema["properties"]["ranges"]["items"]["prefixItems"][0]["format"],
+            "ipv4"
+        );
+    }
+
+    #[test]
+    fn tagged_enums_are_one_of_their_variants() {
+        let mut components = Components::new();
+        schema_for::<Change>(&mut components);
+        let variants = components["Change"]["oneOf"].as_array().unwrap();
+        let tag = serde_json::to_value(Change::Remove {
+            name: "x".to_string(),
+        })
+        .unwrap()["type"]
+            .clone();
+        assert!(
+            variants
+                .iter()
+                .any(|variant| variant["properties"]["type"]["const"] == tag)
+        );
+        let _ = Change::Rename {
+            from: String::new(),
+            to: String::new(),
+        };
+    }
+
+    #[test]
+    fn documents_paths_parameters_and_components() {
+        let document = document::<Failure>(
+            "Test",
+            "1",
+            "/api",
+            &[
+                Operation::new("get", "/samples/:id", "Get a sample").returns::<Sample>(200),
+                Operation::new("post", "/changes", "Apply a change")
+                    .requires("overrides_write")
+                    .body::<Change>(),
+            ],
+        );
+        let get = &document["paths"]["/samples/{id}"]["get"];
+        assert_eq!(get["parameters"][0]["name"], "id");
+        assert_eq!(
+            get["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/Sample"
+        );
+        assert_eq!(
+            get["responses"]["default"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/Failure"
+        );
+        let post = &document["paths"]["/changes"]["post"];
+        assert!(post["responses"]["204"].is_object());
+        assert!(
+            post["description"]
+                .as_str()
+                .unwrap()
+                .contains("overrides_write")
+        );
+        for name in ["Sample", "Change", "Failure"] {
+            assert!(
+                document["components"]["schemas"][name].is_object(),
+                "{name}"
+            );
+        }
+    }
+}
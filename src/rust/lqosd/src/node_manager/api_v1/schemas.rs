@@ -0,0 +1,242 @@
+//! JSON schemas for the types exchanged by `/api/v1`.
+
+use super::openapi::{
+    ApiSchema, Components, object, object_schema, schema_for, tagged_enum_schema,
+};
+use super::{ApiError, Circuit, MessageResponse, NetworkTreeNode, Overrides};
+use crate::node_manager::local_api::circuit_live::CircuitLiveMetrics;
+use crate::node_manager::local_api::urgent::{UrgentItem, UrgentList};
+use lqos_config::{NetworkJsonTransport, ShapedDevice};
+use lqos_overrides::{CircuitAdjustment, NetworkAdjustment};
+use lqos_utils::units::{DownUpOrder, RetransmitCount, TcpPacketCount, TcpRetransmitSample};
+use serde_json::Value;
+
+object_schema!(ShapedDevice {
+    circuit_id: String,
+    circuit_name: String,
+    device_id: String,
+    device_name: String,
+    parent_node: String,
+    mac: String,
+    ipv4: Vec<(std::net::Ipv4Addr, u32)>,
+    ipv6: Vec<(std::net::Ipv6Addr, u32)>,
+    download_min_mbps: f32,
+    upload_min_mbps: f32,
+    download_max_mbps: f32,
+    upload_max_mbps: f32,
+    comment: String,
+    sqm_override: Option<String>,
+} skip { circuit_hash, device_hash, parent_hash });
+
+tagged_enum_schema!(CircuitAdjustment tag "type" {
+    CircuitAdjustSpeed as "circuit_adjust_speed" {
+        circuit_id: String,
+        min_download_bandwidth: Option<f32>,
+        max_download_bandwidth: Option<f32>,
+        min_upload_bandwidth: Option<f32>,
+        max_upload_bandwidth: Option<f32>,
+        expires_at: Option<u64>,
+    },
+    DeviceAdjustSpeed as "device_adjust_speed" {
+        device_id: String,
+        min_download_bandwidth: Option<f32>,
+        max_download_bandwidth: Option<f32>,
+        min_upload_bandwidth: Option<f32>,
+        max_upload_bandwidth: Option<f32>,
+    },
+    DeviceAdjustSqm as "device_adjust_sqm" {
+        device_id: String,
+        sqm_override: Option<String>,
+        expires_at: Option<u64>,
+    },
+    RemoveCircuit as "remove_circuit" { circuit_id: String },
+    RemoveDevice as "remove_device" { device_id: String },
+    ReparentCircuit as "reparent_circuit" { circuit_id: String, parent_node: String },
+});
+
+tagged_enum_schema!(NetworkAdjustment tag "type" {
+    AdjustSiteSpeed as "adjust_site_speed" {
+        node_id: Option<String>,
+        site_name: String,
+        download_bandwidth_mbps: Option<f32>,
+        upload_bandwidth_mbps: Option<f32>,
+        expires_at: Option<u64>,
+    },
+    SetNodeVirtual as "set_node_virtual" {
+        node_name: String,
+        virtual_node as "virtual": bool,
+    },
+});
+
+object_schema!(NetworkJsonTransport {
+    name: String,
+    id: Option<String>,
+    is_virtual as "virtual": bool,
+    runtime_virtualized: bool,
+    max_throughput: (f64, f64),
+    configured_max_throughput: (f64, f64),
+    effective_max_throughput: Option<(f64, f64)>,
+    current_throughput: (u64, u64),
+    current_packets: (u64, u64),
+    current_tcp_packets: (u64, u64),
+    current_udp_packets: (u64, u64),
+    current_icmp_packets: (u64, u64),
+    current_retransmits: (u64, u64),
+    current_tcp_retransmit_packets: (u64, u64),
+    current_marks: (u64, u64),
+    current_drops: (u64, u64),
+    rtts: Vec<f32>,
+    qoo: (Option<f32>, Option<f32>),
+    parents: Vec<usize>,
+    immediate_parent: Option<usize>,
+    node_type as "type": Option<String>,
+    latitude: Option<f32>,
+    longitude: Option<f32>,
+    subtree_site_count: u32,
+    subtree_circuit_count: u32,
+    subtree_device_count: u32,
+});
+
+object_schema!(CircuitLiveMetrics {
+    circuit_id: String,
+    bytes_per_second: DownUpOrder<u64>,
+    rtt_current_p50_nanos: DownUpOrder<Option<u64>>,
+    qoo: DownUpOrder<Option<f32>>,
+    tcp_retransmit_sample: DownUpOrder<TcpRetransmitSample>,
+    last_seen_nanos: u64,
+});
+
+object_schema!(TcpRetransmitSample {
+    retransmits: RetransmitCount,
+    packets: TcpPacketCount,
+});
+
+impl ApiSchema for RetransmitCount {
+    fn schema(components: &mut Components) -> Value {
+        schema_for::<u64>(components)
+    }
+}
+
+impl ApiSchema for TcpPacketCount {
+    fn schema(components: &mut Components) -> Value {
+        schema_for::<u64>(components)
+    }
+}
+
+impl<T: ApiSchema> ApiSchema for DownUpOrder<T> {
+    fn schema(components: &mut Components) -> Value {
+        let _ = |value: &DownUpOrder<T>| {
+            let DownUpOrder { down, up } = value;
+            let _: (&T, &T) = (down, up);
+        };
+        object(vec![
+            ("down", schema_for::<T>(components), T::OPTIONAL),
+            ("up", schema_for::<T>(components), T::OPTIONAL),
+        ])
+    }
+}
+
+object_schema!(UrgentItem {
+    id: u64,
+    ts: u64,
+    source: String,
+    severity: String,
+    code: String,
+    message: String,
+    context: Option<String>,
+});
+
+object_schema!(UrgentList {
+    items: Vec<UrgentItem>,
+});
+
+object_schema!(Circuit {
+    circuit_id: String,
+    circuit_name: String,
+    parent_node: String,
+    devices: Vec<ShapedDevice>,
+});
+
+object_schema!(NetworkTreeNode {
+    index: usize,
+    node: NetworkJsonTransport,
+});
+
+object_schema!(Overrides {
+    circuit_adjustments: Vec<CircuitAdjustment>,
+    network_adjustments: Vec<NetworkAdjustment>,
+});
+
+object_schema!(MessageResponse { message: String });
+
+object_schema!(ApiError { error: String } skip { status });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Checks a named schema against what serde actually produces.
+    fn assert_describes<T: ApiSchema + serde::Serialize>(value: &T) {
+        let mut components = Components::new();
+        schema_for::<T>(&mut components);
+        let schema = &components[T::NAME.expect("named")];
+        let serialized = serde_json::to_value(value).expect("serializes");
+        let serialized: BTreeSet<&String> =
+            serialized.as_object().expect("object").keys().collect();
+        let described: BTreeSet<&String> = schema["properties"]
+            .as_object()
+            .expect("properties")
+            .keys()
+            .collect();
+        assert!(
+            serialized.is_subset(&described),
+            "{serialized:?} vs {described:?}"
+        );
+        for required in schema["required"].as_array().expect("required") {
+            assert!(
+                serialized.iter().any(|key| *key == required),
+                "{required} is required but not serialized"
+            );
+        }
+    }
+
+    #[test]
+    fn shaped_device_schema_matches_serde() {
+        assert_describes(&ShapedDevice {
+            sqm_override: Some("cake".to_string()),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn adjustment_tags_match_serde() {
+        let mut components = Components::new();
+        schema_for::<CircuitAdjustment>(&mut components);
+        schema_for::<NetworkAdjustment>(&mut components);
+        let tags = |name: &str| -> Vec<Value> {
+            components[name]["oneOf"]
+                .as_array()
+                .expect("variants")
+                .iter()
+                .map(|variant| variant["properties"]["type"]["const"].clone())
+                .collect()
+        };
+        let circuit = serde_json::to_value(CircuitAdjustment::ReparentCircuit {
+            circuit_id: "c".to_string(),
+            parent_node: "p".to_string(),
+        })
+        .expect("serializes");
+        assert!(tags("CircuitAdjustment").contains(&circuit["type"]));
+        let network = serde_json::to_value(NetworkAdjustment::SetNodeVirtual {
+            node_name: "n".to_string(),
+            virtual_node: true,
+        })
+        .expect("serializes");
+        assert!(tags("NetworkAdjustment").contains(&network["type"]));
+        let variant = components["NetworkAdjustment"]["oneOf"][1]["properties"]
+            .as_object()
+            .expect("properties");
+        assert!(variant.contains_key("virtual"));
+    }
+}
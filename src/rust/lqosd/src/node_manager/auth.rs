@@ -1,14 +1,16 @@
 //! Provides authentication for the Node Manager.
 
 use axum::Json;
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, StatusCode, header};
 use axum::response::{Html, IntoResponse, Redirect, Response};
 use axum_extra::extract::CookieJar;
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use base64::Engine;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use hmac::{Hmac, Mac};
-use lqos_config::{AuthenticatedUser, UserRole, WebUsers, load_config};
+use lqos_config::{
+    ApiToken, ApiTokenScope, AuditActor, AuthenticatedUser, UserRole, WebUsers, load_config,
+};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use rand::RngCore;
@@ -26,6 +28,8 @@ const COOKIE_NAME: &str = "User-Token";
 const SESSION_TOKEN_VERSION: &str = "v1";
 const SESSION_DURATION_SECS: u64 = 60 * 60 * 24 * 30;
 const SESSION_KEY_FILE_NAME: &str = "lqusers.session.key";
+/// API token last-used times are written back no more often than this.
+const TOKEN_LAST_USED_RESOLUTION_SECS: u64 = 60;
 
 type HmacSha256 = Hmac<Sha256>;
 
@@ -48,6 +52,7 @@ struct AuthSnapshot {
     bootstrap_state: AuthBootstrapState,
     auth_epoch: u64,
     allow_anonymous: bool,
+    api_tokens: Vec<ApiToken>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -161,6 +166,7 @@ fn auth_snapshot() -> AuthSnapshot {
                 bootstrap_state: AuthBootstrapState::CorruptUsersFile,
                 auth_epoch: 0,
                 allow_anonymous: false,
+                api_tokens: Vec::new(),
             };
         }
     };
@@ -177,6 +183,7 @@ fn auth_snapshot() -> AuthSnapshot {
             bootstrap_state: AuthBootstrapState::MissingUsersFile,
             auth_epoch: 0,
             allow_anonymous: false,
+            api_tokens: Vec::new(),
         },
         Some(_) => match WebUsers::load_or_create() {
             Ok(users) => AuthSnapshot {
@@ -187,6 +194,7 @@ fn auth_snapshot() -> AuthSnapshot {
                 },
                 auth_epoch: users.auth_epoch(),
                 allow_anonymous: users.do_we_allow_anonymous(),
+                api_tokens: users.get_api_tokens(),
             },
             Err(e) => {
                 warn!("Unable to load auth state: {e}");
@@ -194,6 +202,7 @@ fn auth_snapshot() -> AuthSnapshot {
                     bootstrap_state: AuthBootstrapState::CorruptUsersFile,
                     auth_epoch: 0,
                     allow_anonymous: false,
+                    api_tokens: Vec::new(),
                 }
             }
         },
@@ -410,13 +419,50 @@ pub async fn get_username(jar: &CookieJar) -> String {
     }
 }
 
+/// The scopes of an API token, packed so that `LoginResult` stays `Copy`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TokenScopes(u8);
+
+impl TokenScopes {
+    fn bit(scope: ApiTokenScope) -> u8 {
+        let index = ApiTokenScope::ALL
+            .iter()
+            .position(|s| *s == scope)
+            .unwrap_or_default();
+        1 << index
+    }
+
+    fn from_token(token: &ApiToken) -> Self {
+        Self(token.scopes.iter().fold(0, |bits, s| bits | Self::bit(*s)))
+    }
+
+    fn contains(self, scope: ApiTokenScope) -> bool {
+        scope == ApiTokenScope::ReadOnly || self.0 & Self::bit(scope) != 0
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum LoginResult {
     Admin,
     ReadOnly,
+    /// Authenticated with an API token. Never treated as an administrator.
+    Token(TokenScopes),
     Denied,
 }
 
+impl LoginResult {
+    /// Is this login permitted to perform actions covered by `scope`?
+    /// Administrators may do anything and any login may read.
+    pub fn allows(self, scope: ApiTokenScope) -> bool {
+        match self {
+            LoginResult::Admin => true,
+            LoginResult::ReadOnly => scope == ApiTokenScope::ReadOnly,
+            LoginResult::Token(scopes) => scopes.contains(scope),
+            LoginResult::Denied => false,
+        }
+    }
+}
+
 fn login_result_for_session(user: Option<SessionUser>, allow_anonymous: bool) -> LoginResult {
     match user {
         Some(SessionUser {
@@ -432,60 +478,214 @@ fn login_result_for_session(user: Option<SessionUser>, allow_anonymous: bool) ->
     }
 }
 
-/// Checks an incoming request for a `User-Token` cookie. If found,
-/// it validates the request against the signed session and current auth epoch.
-/// Missing or empty auth state redirects to first-run; invalid sessions redirect
-/// to login unless anonymous read-only is enabled.
-pub async fn auth_layer(
-    jar: CookieJar,
-    mut req: axum::extract::Request,
-    next: axum::middleware::Next,
-) -> Response {
+/// Returns the secret from an `Authorization: Bearer` header, if present.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.trim().split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then_some(token.trim())
+        .filter(|token| !token.is_empty())
+}
+
+/// Validates an API token secret against the cached auth state, recording
+/// when it was last used.
+fn login_from_api_token(secret: &str, snapshot: &AuthSnapshot) -> (LoginResult, AuditActor) {
+    let now = now_unix_secs();
+    let token = match ApiToken::verify(&snapshot.api_tokens, secret, now) {
+        Ok(token) => token,
+        Err(e) => {
+            warn!("API token rejected: {e}");
+            return (LoginResult::Denied, AuditActor::Anonymous);
+        }
+    };
+
+    let stale = token
+        .last_used
+        .is_none_or(|t| now.saturating_sub(t) >= TOKEN_LAST_USED_RESOLUTION_SECS);
+    if stale {
+        // Saving changes the auth file, which refreshes the cached snapshot.
+        if let Err(e) = WebUsers::load_or_create()
+            .and_then(|mut users| users.record_api_token_use(&token.id, now))
+        {
+            warn!("Unable to record API token use: {e}");
+        }
+    }
+
+    (
+        LoginResult::Token(TokenScopes::from_token(token)),
+        AuditActor::Token {
+            id: token.id.clone(),
+            name: token.name.clone(),
+        },
+    )
+}
+
+/// Why a request couldn't be authenticated.
+enum AuthFailure {
+    /// No users have been created yet.
+    NotSetUp,
+    /// The users file can't be read.
+    CorruptUsersFile,
+    /// A bearer token was presented but isn't valid.
+    InvalidToken,
+    /// The session cookie couldn't be checked.
+    Session(StatusCode),
+    /// Neither a valid session nor a token was presented.
+    NotLoggedIn,
+}
+
+/// Works out who is making a request, from a bearer token or the session
+/// cookie.
+fn authenticate(
+    jar: &CookieJar,
+    headers: &HeaderMap,
+) -> Result<(LoginResult, AuditActor), AuthFailure> {
     let snapshot = auth_snapshot();
     match snapshot.bootstrap_state {
         AuthBootstrapState::MissingUsersFile | AuthBootstrapState::NoUsersConfigured => {
-            return Redirect::temporary("/first-run.html").into_response();
-        }
-        AuthBootstrapState::CorruptUsersFile => {
-            return Redirect::temporary("/login.html").into_response();
+            return Err(AuthFailure::NotSetUp);
         }
+        AuthBootstrapState::CorruptUsersFile => return Err(AuthFailure::CorruptUsersFile),
         AuthBootstrapState::Ready => {}
     }
 
-    let login_result = match session_from_cookie(&jar, &snapshot) {
-        Ok(user) => login_result_for_session(user, snapshot.allow_anonymous),
-        Err(status) => return (status, "Unable to validate session").into_response(),
-    };
+    if let Some(secret) = bearer_token(headers) {
+        let (login_result, actor) = login_from_api_token(secret, &snapshot);
+        if login_result == LoginResult::Denied {
+            return Err(AuthFailure::InvalidToken);
+        }
+        return Ok((login_result, actor));
+    }
 
-    match login_result {
-        LoginResult::Admin | LoginResult::ReadOnly => {
+    let user = session_from_cookie(jar, &snapshot).map_err(AuthFailure::Session)?;
+    let actor = user
+        .as_ref()
+        .map_or(AuditActor::Anonymous, |user| AuditActor::User {
+            name: user.username.clone(),
+        });
+    match login_result_for_session(user, snapshot.allow_anonymous) {
+        LoginResult::Denied => Err(AuthFailure::NotLoggedIn),
+        login_result => {
             record_first_login_timestamp_if_needed();
+            Ok((login_result, actor))
+        }
+    }
+}
+
+/// Checks an incoming request for an `Authorization: Bearer` API token or a
+/// `User-Token` cookie. Tokens are checked against the stored API tokens;
+/// cookies against the signed session and current auth epoch.
+/// Missing or empty auth state redirects to first-run; invalid sessions redirect
+/// to login unless anonymous read-only is enabled. Invalid tokens are refused
+/// outright, since a script cannot follow a login redirect.
+pub async fn auth_layer(
+    jar: CookieJar,
+    mut req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    match authenticate(&jar, req.headers()) {
+        Ok((login_result, actor)) => {
             req.extensions_mut().insert(login_result);
+            req.extensions_mut().insert(actor);
             next.run(req).await
         }
-        LoginResult::Denied => Redirect::temporary("/login.html").into_response(),
+        Err(AuthFailure::NotSetUp) => Redirect::temporary("/first-run.html").into_response(),
+        Err(AuthFailure::CorruptUsersFile | AuthFailure::NotLoggedIn) => {
+            Redirect::temporary("/login.html").into_response()
+        }
+        Err(AuthFailure::InvalidToken) => {
+            (StatusCode::UNAUTHORIZED, "Invalid API token").into_response()
+        }
+        Err(AuthFailure::Session(status)) => (status, "Unable to validate session").into_response(),
     }
 }
 
-pub async fn login_from_token(token: &str) -> LoginResult {
+/// Like [`auth_layer`], but for machine clients: failures are a JSON error
+/// with a status code instead of a redirect to the login page. Changes must
+/// carry an API token: browsers attach the session cookie to cross-site
+/// requests, so accepting it here would allow request forgery.
+pub async fn api_auth_layer(
+    jar: CookieJar,
+    mut req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let authenticated = if req.method().is_safe() || bearer_token(req.headers()).is_some() {
+        authenticate(&jar, req.headers())
+    } else {
+        Err(AuthFailure::NotLoggedIn)
+    };
+    let (status, message) = match authenticated {
+        Ok((login_result, actor)) => {
+            req.extensions_mut().insert(login_result);
+            req.extensions_mut().insert(actor);
+            return next.run(req).await;
+        }
+        Err(AuthFailure::NotSetUp) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "No users have been configured",
+        ),
+        Err(AuthFailure::CorruptUsersFile) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Unable to read the users file",
+        ),
+        Err(AuthFailure::InvalidToken) => (StatusCode::UNAUTHORIZED, "Invalid API token"),
+        Err(AuthFailure::Session(status)) => (status, "Unable to validate session"),
+        Err(AuthFailure::NotLoggedIn) if req.method().is_safe() => {
+            (StatusCode::UNAUTHORIZED, "Authentication required")
+        }
+        Err(AuthFailure::NotLoggedIn) => (StatusCode::UNAUTHORIZED, "Changes require an API token"),
+    };
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+/// Validates the `Authorization: Bearer` API token on a request that
+/// doesn't go through [`auth_layer`]. Session cookies are not accepted.
+pub fn login_from_bearer(headers: &HeaderMap) -> LoginResult {
     let snapshot = auth_snapshot();
     if snapshot.bootstrap_state != AuthBootstrapState::Ready {
         return LoginResult::Denied;
     }
+    bearer_token(headers).map_or(LoginResult::Denied, |secret| {
+        login_from_api_token(secret, &snapshot).0
+    })
+}
+
+/// Validates the token presented in the websocket handshake, which may be
+/// either a signed session or an API token. Also returns who is logged in,
+/// for the audit log.
+pub async fn login_from_token(token: &str) -> (LoginResult, AuditActor) {
+    let snapshot = auth_snapshot();
+    if snapshot.bootstrap_state != AuthBootstrapState::Ready {
+        return (LoginResult::Denied, AuditActor::Anonymous);
+    }
+    if WebUsers::is_api_token(token) {
+        return login_from_api_token(token, &snapshot);
+    }
 
     let key = match session_key() {
         Ok(key) => key,
         Err(e) => {
             warn!("Unable to load session key for websocket auth: {e}");
-            return LoginResult::Denied;
+            return (LoginResult::Denied, AuditActor::Anonymous);
         }
     };
 
-    let login_result = match verify_signed_session(&key, token, &snapshot) {
-        Ok(user) => login_result_for_session(user, snapshot.allow_anonymous),
+    let (login_result, actor) = match verify_signed_session(&key, token, &snapshot) {
+        Ok(user) => {
+            let actor = user
+                .as_ref()
+                .map_or(AuditActor::Anonymous, |user| AuditActor::User {
+                    name: user.username.clone(),
+                });
+            (
+                login_result_for_session(user, snapshot.allow_anonymous),
+                actor,
+            )
+        }
         Err(e) => {
             warn!("Unable to verify websocket session token: {e}");
-            LoginResult::Denied
+            (LoginResult::Denied, AuditActor::Anonymous)
         }
     };
 
@@ -493,7 +693,7 @@ pub async fn login_from_token(token: &str) -> LoginResult {
         record_first_login_timestamp_if_needed();
     }
 
-    login_result
+    (login_result, actor)
 }
 
 /// Invalidate the cached auth snapshot after user-management changes.
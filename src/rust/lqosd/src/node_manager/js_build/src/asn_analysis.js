@@ -37,6 +37,7 @@ const state = {
     asnList: [],
     countryList: [],
     protocolList: [],
+    applicationList: [],
     listCountsByAsn: new Map(),
     evidenceScope: { type: "asn", value: null, label: "" },
     evidenceSort: "bytes",
@@ -695,6 +696,7 @@ function requestLists() {
     requestAsnList();
     requestCountryList();
     requestProtocolList();
+    requestApplicationList();
 }
 
 function requestAsnList() {
@@ -788,6 +790,30 @@ function requestProtocolList() {
     wsClient.send({ ProtocolList: {} });
 }
 
+function requestApplicationList() {
+    listenOnce("ApplicationList", (msg) => {
+        state.applicationList = (msg?.data || []).slice().sort((a, b) => toNumber(b?.count, 0) - toNumber(a?.count, 0));
+        renderDropdown({
+            targetId: "asnAnalysisApplicationControl",
+            buttonText: "Application",
+            items: state.applicationList,
+            emptyText: "No recent classified flows",
+            itemRenderer: (row) => {
+                const li = document.createElement("li");
+                li.className = "dropdown-item";
+                const bytes = toNumber(row?.bytes?.down, 0) + toNumber(row?.bytes?.up, 0);
+                li.innerText = `${row.application} (${row.count}, ${scaleNumber(bytes, 0)}B)`;
+                li.onclick = () => {
+                    state.evidenceScope = { type: "application", value: row.application, label: row.application };
+                    loadEvidenceForScope(state.evidenceScope);
+                };
+                return li;
+            },
+        });
+    });
+    wsClient.send({ ApplicationList: {} });
+}
+
 function loadEvidenceForScope(scope) {
     const token = ++state.activeEvidenceRequestToken;
     state.evidencePage = 0;
@@ -818,6 +844,16 @@ function loadEvidenceForScope(scope) {
         return;
     }
 
+    if (scope.type === "application") {
+        listenOnce("ApplicationFlowTimeline", (msg) => {
+            if (token !== state.activeEvidenceRequestToken) return;
+            state.evidenceRows = (msg?.data || []).slice();
+            renderFlowEvidence();
+        });
+        wsClient.send({ ApplicationFlowTimeline: { application: scope.value } });
+        return;
+    }
+
     listenOnce("AsnFlowTimeline", (msg) => {
         if (token !== state.activeEvidenceRequestToken) return;
         state.evidenceRows = (msg?.data || []).slice();
@@ -833,6 +869,9 @@ function evidenceScopeLabel() {
     if (state.evidenceScope.type === "protocol") {
         return `Showing recent completed flows for protocol ${state.evidenceScope.label}.`;
     }
+    if (state.evidenceScope.type === "application") {
+        return `Showing recent completed flows for application ${state.evidenceScope.label}.`;
+    }
     const selectedLabel = state.selectedAsn ? asnDisplayName(state.selectedAsn) : "the selected ASN";
     return `Showing recent completed flows for ${selectedLabel}.`;
 }
@@ -910,7 +949,7 @@ function renderFlowRows(allRows) {
             </td>
             <td>
                 <div class="asn-analysis-flow-meta">
-                    <strong>${escapeHtml(row.protocol || "—")}</strong>
+                    <strong>${escapeHtml(row.application ? `${row.application} (${row.protocol})` : (row.protocol || "—"))}</strong>
                     <span>Duration ${escapeHtml(durationLabel)}</span>
                 </div>
             </td>
@@ -1746,7 +1746,10 @@ function renderTrafficTab() {
             row.classList.add("small");
             row.style.opacity = toNumber(rowData.opacity, 1);
 
-            row.appendChild(truncatedTrafficCell(rowData.protocol_name, "lqos-circuit-traffic-protocol-cell"));
+            const protocolLabel = rowData.application_name
+                ? `${rowData.application_name} (${rowData.protocol_name})`
+                : rowData.protocol_name;
+            row.appendChild(truncatedTrafficCell(protocolLabel, "lqos-circuit-traffic-protocol-cell"));
             row.appendChild(simpleRowHtml(formatThroughput(rowData.down_bps, plan.down)));
             row.appendChild(simpleRowHtml(formatThroughput(rowData.up_bps, plan.up)));
             row.appendChild(simpleRow(scaleNumber(rowData.bytes_sent_down)));
@@ -2273,7 +2276,7 @@ function wireupAnalysis(circuits) {
                         btn.classList.add("btn-success");
                         btn.onclick = () => {
                             let url = "/local-api/pcapDump/" + sessionId;
-                            download(url, "capture.pcap");
+                            download(url, "capture.pcapng");
                             //console.log(url);
 
                             // Restore the buttons
@@ -2291,12 +2294,71 @@ function wireupAnalysis(circuits) {
         entry.appendChild(item);
         listUl.appendChild(entry);
     });
+    appendRingCaptureItems(listUl);
     list.appendChild(listUl);
     let parent = document.getElementById("captureButton");
     clearDiv(parent);
     parent.appendChild(list);
 }
 
+const RING_CAPTURE_MINUTES = 15;
+
+function ringCaptureItem(html, onclick) {
+    let entry = document.createElement("li");
+    let item = document.createElement("a");
+    item.classList.add("dropdown-item");
+    item.innerHTML = html;
+    item.onclick = onclick;
+    entry.appendChild(item);
+    return entry;
+}
+
+function refreshCaptureMenu() {
+    requestCircuitById((payload) => {
+        wireupAnalysis(payload.devices || []);
+    });
+}
+
+// Rolling captures keep the last few minutes of packets for the whole
+// circuit, so an intermittent problem can be frozen and downloaded later.
+function appendRingCaptureItems(listUl) {
+    listenOnce("RingCaptures", (msg) => {
+        const rings = (msg?.data || []).filter((ring) => ring.circuit_id === circuit_id);
+        let divider = document.createElement("li");
+        divider.innerHTML = "<hr class='dropdown-divider'>";
+        listUl.appendChild(divider);
+        if (rings.length === 0) {
+            listUl.appendChild(ringCaptureItem(
+                "<i class='fa fa-circle-dot'></i> Start rolling capture (last " + RING_CAPTURE_MINUTES + " minutes)",
+                () => {
+                    listenOnce("RingCaptureStarted", (reply) => {
+                        if (!reply?.data?.Ok) {
+                            alert("Unable to start rolling capture: " + (reply?.data?.Fail?.reason || "unknown error"));
+                        }
+                        refreshCaptureMenu();
+                    });
+                    wsClient.send({ StartRingCapture: { circuit: circuit_id, ip: null, minutes: RING_CAPTURE_MINUTES } });
+                },
+            ));
+            return;
+        }
+        rings.forEach((ring) => {
+            listUl.appendChild(ringCaptureItem(
+                "<i class='fa fa-download'></i> Freeze and download rolling capture (" + ring.packets + " packets)",
+                () => download("/local-api/ringCaptureDump/" + ring.id, "rolling_capture.pcapng"),
+            ));
+            listUl.appendChild(ringCaptureItem(
+                "<i class='fa fa-stop'></i> Stop rolling capture",
+                () => {
+                    listenOnce("RingCaptureStopped", () => refreshCaptureMenu());
+                    wsClient.send({ StopRingCapture: { id: ring.id } });
+                },
+            ));
+        });
+    });
+    wsClient.send({ ListRingCaptures: {} });
+}
+
 function download(dataurl, filename) {
     const link = document.createElement("a");
     link.href = dataurl;
@@ -457,6 +457,83 @@ export function deleteUser(payload, onComplete, onError) {
     );
 }
 
+export function getApiTokens(onComplete, onError) {
+    sendWsRequest(
+        "GetApiTokens",
+        { GetApiTokens: {} },
+        (msg) => {
+            if (onComplete) onComplete(msg.data || []);
+        },
+        onError,
+    );
+}
+
+export function createApiToken(payload, onComplete, onError) {
+    sendWsRequest(
+        "CreateApiTokenResult",
+        { CreateApiToken: payload },
+        (msg) => {
+            if (onComplete) onComplete(msg);
+        },
+        onError,
+    );
+}
+
+export function revokeApiToken(payload, onComplete, onError) {
+    sendWsRequest(
+        "RevokeApiTokenResult",
+        { RevokeApiToken: payload },
+        (msg) => {
+            if (onComplete) onComplete(msg);
+        },
+        onError,
+    );
+}
+
+export function getAuditLog(query, onComplete, onError) {
+    sendWsRequest(
+        "AuditLog",
+        { GetAuditLog: { query } },
+        (msg) => {
+            if (onComplete) onComplete(msg.data || []);
+        },
+        onError,
+    );
+}
+
+export function getBandwidthSchedules(onComplete, onError) {
+    sendWsRequest(
+        "BandwidthSchedules",
+        { GetBandwidthSchedules: {} },
+        (msg) => {
+            if (onComplete) onComplete(msg.data);
+        },
+        onError,
+    );
+}
+
+export function saveBandwidthSchedule(schedule, onComplete, onError) {
+    sendWsRequest(
+        "BandwidthScheduleResult",
+        { SaveBandwidthSchedule: { schedule } },
+        (msg) => {
+            if (onComplete) onComplete(msg);
+        },
+        onError,
+    );
+}
+
+export function deleteBandwidthSchedule(id, onComplete, onError) {
+    sendWsRequest(
+        "BandwidthScheduleResult",
+        { DeleteBandwidthSchedule: { id } },
+        (msg) => {
+            if (onComplete) onComplete(msg);
+        },
+        onError,
+    );
+}
+
 export function validNodeList(network_json) {
     let nodes = [];
 
@@ -495,7 +572,9 @@ export function renderConfigMenu(currentPage) {
         { href: "config_wispgate.html", icon: "fa-link", text: "WispGate", id: "wispgate" },
         { href: "config_network.html", icon: "fa-map", text: "Network Layout", id: "network" },
         { href: "config_devices.html", icon: "fa-table", text: "Shaped Devices", id: "devices" },
-        { href: "config_users.html", icon: "fa-users", text: "LibreQoS Users", id: "users" }
+        { href: "config_users.html", icon: "fa-users", text: "LibreQoS Users", id: "users" },
+        { href: "config_schedules.html", icon: "fa-clock", text: "Bandwidth Schedules", id: "schedules" },
+        { href: "config_audit.html", icon: "fa-history", text: "Audit Log", id: "audit" }
     ];
 
     const menuHtml = `
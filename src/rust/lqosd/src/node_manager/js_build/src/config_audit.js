@@ -0,0 +1,119 @@
+import { getAuditLog, renderConfigMenu } from "./config/config_helper";
+
+const PAGE_SIZE = 100;
+
+let offset = 0;
+
+function actorLabel(actor) {
+    switch (actor.kind) {
+        case "user": return `user:${actor.name}`;
+        case "token": return `token:${actor.name}`;
+        case "cli": return `cli:${actor.tool} (${actor.user})`;
+        case "subsystem": return `subsystem:${actor.name}`;
+        default: return actor.kind;
+    }
+}
+
+function formatValue(value) {
+    if (value === undefined) return "-";
+    return typeof value === "string" ? value : JSON.stringify(value);
+}
+
+function currentQuery() {
+    return {
+        actor: $("#audit-actor").val().trim() || null,
+        action: $("#audit-action").val().trim() || null,
+        offset: offset,
+        limit: PAGE_SIZE,
+    };
+}
+
+function updateExportLink(query) {
+    const params = new URLSearchParams();
+    if (query.actor) params.set("actor", query.actor);
+    if (query.action) params.set("action", query.action);
+    const suffix = params.toString();
+    $("#audit-export").attr("href", "/local-api/auditLog.jsonl" + (suffix ? "?" + suffix : ""));
+}
+
+function changesCell(entry) {
+    const cell = $("<td>");
+    if (entry.changes && entry.changes.length > 0) {
+        const list = $('<ul class="list-unstyled small mb-0">');
+        entry.changes.forEach((change) => {
+            list.append(
+                $("<li>").append(
+                    $("<code>").text(change.path || "(value)"),
+                    document.createTextNode(`: ${formatValue(change.before)} → ${formatValue(change.after)}`),
+                ),
+            );
+        });
+        if (entry.truncated) {
+            list.append('<li class="text-muted">(truncated)</li>');
+        }
+        cell.append(list);
+    }
+    if (entry.detail) {
+        cell.append($('<div class="small text-muted">').text(entry.detail));
+    }
+    return cell;
+}
+
+function renderEntries(entries, append) {
+    const list = $("#audit-list");
+    let tbody = list.find("tbody");
+    if (!append || tbody.length === 0) {
+        list.empty();
+        if (entries.length === 0) {
+            list.html('<div class="alert alert-info">No audit entries found</div>');
+            $("#audit-more").hide();
+            return;
+        }
+        const tableWrap = $('<div class="table-responsive lqos-table-wrap">');
+        const table = $('<table class="lqos-table lqos-table-compact mb-0">')
+            .append("<thead><tr><th>Time</th><th>Actor</th><th>Action</th><th>Target</th><th>Changes</th></tr></thead>");
+        tbody = $("<tbody>");
+        table.append(tbody);
+        tableWrap.append(table);
+        list.append(tableWrap);
+    }
+
+    entries.forEach((entry) => {
+        tbody.append(
+            $("<tr>")
+                .append($("<td>").text(new Date(entry.time * 1000).toLocaleString()))
+                .append($("<td>").text(actorLabel(entry.actor)))
+                .append($("<td>").text(entry.action))
+                .append($("<td>").text(entry.target || ""))
+                .append(changesCell(entry)),
+        );
+    });
+    $("#audit-more").toggle(entries.length === PAGE_SIZE);
+}
+
+function loadAuditLog(append) {
+    if (!append) offset = 0;
+    const query = currentQuery();
+    updateExportLink(query);
+    getAuditLog(
+        query,
+        (entries) => {
+            offset += entries.length;
+            renderEntries(entries, append);
+        },
+        () => {
+            $("#audit-list").html('<div class="alert alert-danger">Failed to load the audit log</div>');
+        },
+    );
+}
+
+$(document).ready(() => {
+    renderConfigMenu("audit");
+    loadAuditLog(false);
+
+    $("#audit-filter-form").on("submit", (e) => {
+        e.preventDefault();
+        loadAuditLog(false);
+    });
+    $("#audit-more").on("click", () => loadAuditLog(true));
+});
@@ -537,8 +537,18 @@ function populateModal(device) {
     parentSelect.prop("disabled", nodes.length === 0);
 
     const sqm = parseSqmOverride(device.sqm_override || "");
-    $("#sdModalSqmDown").val(sqm.down);
-    $("#sdModalSqmUp").val(sqm.up);
+    setSqmSelect("#sdModalSqmDown", sqm.down);
+    setSqmSelect("#sdModalSqmUp", sqm.up);
+}
+
+function setSqmSelect(selector, token) {
+    // Named profiles (cake:<profile>) live in config, so add them on demand
+    const select = $(selector);
+    select.find("option[data-profile]").remove();
+    if (token.startsWith("cake:")) {
+        select.append($("<option>").val(token).text(token).attr("data-profile", "1"));
+    }
+    select.val(token);
 }
 
 function collectModalDevice() {
@@ -659,9 +669,10 @@ function validateModalDevice(device) {
     }
 
     const sqm = device.sqm_override ? parseSqmOverride(device.sqm_override) : { down: "", up: "" };
-    const validSqm = (token) => token === "" || token === "cake" || token === "fq_codel" || token === "none";
+    const validSqm = (token) => ["", "cake", "fq_codel", "fq_pie", "sfq", "none"].includes(token)
+        || /^cake:[a-z0-9_-]+$/.test(token);
     if (!validSqm(sqm.down) || !validSqm(sqm.up)) {
-        errors.push("SQM overrides must be blank, cake, fq_codel, or none.");
+        errors.push("SQM overrides must be blank, cake, cake:<profile>, fq_codel, fq_pie, sfq, or none.");
     }
 
     return { valid: errors.length === 0, errors };
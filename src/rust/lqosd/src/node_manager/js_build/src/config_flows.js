@@ -147,6 +147,12 @@ function validateConfig() {
         return false;
     }
 
+    const activeTimeout = parseInt(document.getElementById("activeTimeout").value);
+    if (isNaN(activeTimeout) || activeTimeout < 0) {
+        alert("Active Timeout must be a number of seconds (0 to disable)");
+        return false;
+    }
+
     // Validate optional fields if provided
     const netflowPort = document.getElementById("netflowPort").value;
     if (netflowPort && (isNaN(netflowPort) || netflowPort < 1 || netflowPort > 65535)) {
@@ -178,9 +184,12 @@ function updateConfig() {
         return;
     }
 
-    // Update only the flows section
+    // Update only the flows section, keeping settings this page doesn't edit
     window.config.flows = {
+        ...(window.config.flows || {}),
         flow_timeout_seconds: parseInt(document.getElementById("flowTimeout").value),
+        active_timeout_seconds: parseInt(document.getElementById("activeTimeout").value),
+        application_rules: document.getElementById("applicationRules").value.trim() || null,
         netflow_enabled: document.getElementById("enableNetflow").checked,
         netflow_port: document.getElementById("netflowPort").value ? 
             parseInt(document.getElementById("netflowPort").value) : null,
@@ -258,6 +267,8 @@ loadConfig(() => {
         
         // Required fields
         document.getElementById("flowTimeout").value = flows.flow_timeout_seconds ?? 30;
+        document.getElementById("activeTimeout").value = flows.active_timeout_seconds ?? 60;
+        document.getElementById("applicationRules").value = flows.application_rules ?? "";
         document.getElementById("enableNetflow").checked = flows.netflow_enabled ?? false;
 
         // Optional fields
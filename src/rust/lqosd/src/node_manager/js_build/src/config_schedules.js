@@ -0,0 +1,139 @@
+import {
+    deleteBandwidthSchedule,
+    getBandwidthSchedules,
+    renderConfigMenu,
+    saveBandwidthSchedule,
+} from "./config/config_helper";
+
+let schedules = [];
+
+function targetLabel(target) {
+    return target.type === "site" ? `Site: ${target.site_name}` : `Circuit: ${target.circuit_id}`;
+}
+
+function rateLabel(rate) {
+    return rate === null || rate === undefined ? "-" : `${rate} Mbps`;
+}
+
+function statusCell(status) {
+    const cell = $("<td>");
+    if (!status) return cell;
+    if (status.error) {
+        return cell.append($('<span class="badge bg-danger">').text("Invalid")).attr("title", status.error);
+    }
+    cell.append(
+        status.active
+            ? $('<span class="badge bg-success">').text("Active")
+            : $('<span class="badge bg-secondary">').text("Idle"),
+    );
+    if (status.since_unix) {
+        cell.append($('<div class="small text-muted">').text("since " + new Date(status.since_unix * 1000).toLocaleString()));
+    }
+    if (!status.target_loaded) {
+        cell.append($('<div class="small text-warning">').text("Target not in the shaping tree"));
+    }
+    return cell;
+}
+
+function fillForm(schedule) {
+    $("#schedule-id").val(schedule.id);
+    $("#schedule-target-type").val(schedule.target.type);
+    $("#schedule-target").val(schedule.target.type === "site" ? schedule.target.site_name : schedule.target.circuit_id);
+    $("#schedule-window").val(schedule.window);
+    $("#schedule-timezone").val(schedule.timezone);
+    $("#schedule-down").val(schedule.download_bandwidth_mbps ?? "");
+    $("#schedule-up").val(schedule.upload_bandwidth_mbps ?? "");
+    $("#schedule-enabled").prop("checked", schedule.enabled);
+}
+
+function render(data) {
+    schedules = data.schedules;
+    $("#schedule-editor").toggle(data.writable);
+    const list = $("#schedule-list");
+    list.empty();
+    if (schedules.length === 0) {
+        list.html('<div class="alert alert-info">No bandwidth schedules configured</div>');
+        return;
+    }
+    const statusById = new Map(data.status.map((s) => [s.id, s]));
+    const table = $('<table class="lqos-table lqos-table-compact mb-0">')
+        .append("<thead><tr><th>Name</th><th>Target</th><th>Window</th><th>Time Zone</th><th>Download</th><th>Upload</th><th>Status</th><th></th></tr></thead>");
+    const tbody = $("<tbody>");
+    schedules.forEach((schedule, index) => {
+        const actions = $("<td>");
+        if (data.writable) {
+            actions.append(
+                $('<button type="button" class="btn btn-sm btn-outline-primary me-1">')
+                    .html('<i class="fa fa-pencil"></i>')
+                    .on("click", () => fillForm(schedules[index])),
+                $('<button type="button" class="btn btn-sm btn-outline-danger">')
+                    .html('<i class="fa fa-trash"></i>')
+                    .on("click", () => removeSchedule(schedule.id)),
+            );
+        }
+        tbody.append(
+            $("<tr>")
+                .append($("<td>").text(schedule.id + (schedule.enabled ? "" : " (disabled)")))
+                .append($("<td>").text(targetLabel(schedule.target)))
+                .append($("<td>").append($("<code>").text(schedule.window)))
+                .append($("<td>").text(schedule.timezone))
+                .append($("<td>").text(rateLabel(schedule.download_bandwidth_mbps)))
+                .append($("<td>").text(rateLabel(schedule.upload_bandwidth_mbps)))
+                .append(statusCell(statusById.get(schedule.id)))
+                .append(actions),
+        );
+    });
+    table.append(tbody);
+    list.append($('<div class="table-responsive lqos-table-wrap">').append(table));
+}
+
+function showMessage(ok, message) {
+    $("#schedule-form-message")
+        .removeClass("text-success text-danger")
+        .addClass(ok ? "text-success" : "text-danger")
+        .text(ok ? "Saved" : message);
+}
+
+function optionalRate(selector) {
+    const raw = $(selector).val().trim();
+    return raw === "" ? null : parseFloat(raw);
+}
+
+function formSchedule() {
+    const type = $("#schedule-target-type").val();
+    const name = $("#schedule-target").val().trim();
+    return {
+        id: $("#schedule-id").val().trim(),
+        enabled: $("#schedule-enabled").is(":checked"),
+        target: type === "site" ? { type, site_name: name } : { type, circuit_id: name },
+        window: $("#schedule-window").val().trim(),
+        timezone: $("#schedule-timezone").val().trim() || "local",
+        download_bandwidth_mbps: optionalRate("#schedule-down"),
+        upload_bandwidth_mbps: optionalRate("#schedule-up"),
+    };
+}
+
+function handleResult(result) {
+    showMessage(result.ok, result.message);
+    if (result.ok && result.data) render(result.data);
+}
+
+function removeSchedule(id) {
+    if (!confirm(`Delete schedule "${id}"? Any rates it applied revert immediately.`)) return;
+    deleteBandwidthSchedule(id, handleResult, () => showMessage(false, "Delete failed"));
+}
+
+function load() {
+    getBandwidthSchedules(render, () => {
+        $("#schedule-list").html('<div class="alert alert-danger">Failed to load bandwidth schedules</div>');
+    });
+}
+
+$(document).ready(() => {
+    renderConfigMenu("schedules");
+    load();
+    $("#schedule-form").on("submit", (e) => {
+        e.preventDefault();
+        saveBandwidthSchedule(formSchedule(), handleResult, () => showMessage(false, "Save failed"));
+    });
+});
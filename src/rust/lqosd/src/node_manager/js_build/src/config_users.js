@@ -1,17 +1,28 @@
 import {
     addUser,
+    createApiToken,
     deleteUser,
+    getApiTokens,
     getUsers,
     renderConfigMenu,
+    revokeApiToken,
     updateUser,
 } from "./config/config_helper";
 
+const SCOPE_LABELS = {
+    ReadOnly: "Read-only",
+    ShapedDevicesWrite: "Shaped devices (write)",
+    OverridesWrite: "Overrides (write)",
+    Reload: "Reload",
+};
+
 $(document).ready(() => {
     // Render the configuration menu
     renderConfigMenu('users');
     
     loadUsers();
-    
+    loadApiTokens();
+
     // Handle add user form submission
     $('#add-user-form').on('submit', function(e) {
         e.preventDefault();
@@ -46,6 +57,47 @@ $(document).ready(() => {
         );
     });
 
+    // Handle add token form submission
+    $('#add-token-form').on('submit', function(e) {
+        e.preventDefault();
+        const name = $('#token-name').val().trim();
+        const scopes = $('.token-scope:checked').map(function() {
+            return $(this).val();
+        }).get();
+        const expiresDays = parseInt($('#token-expires').val(), 10);
+
+        if (!name) {
+            alert('Token name cannot be empty');
+            return;
+        }
+        if (scopes.length === 0) {
+            alert('Select at least one scope');
+            return;
+        }
+
+        createApiToken(
+            {
+                name: name,
+                scopes: scopes,
+                expires_days: Number.isFinite(expiresDays) && expiresDays > 0 ? expiresDays : null,
+            },
+            (msg) => {
+                if (msg && msg.ok) {
+                    $('#token-name').val('');
+                    $('#token-expires').val('');
+                    $('#token-secret').text(msg.secret);
+                    $('#token-secret-box').removeClass('d-none');
+                    loadApiTokens();
+                } else {
+                    alert(msg && msg.message ? msg.message : 'Failed to create token');
+                }
+            },
+            () => {
+                alert('Failed to create token');
+            },
+        );
+    });
+
     // Handle edit user form submission
     $('#save-user-changes').on('click', function() {
         const username = $('#edit-username').val();
@@ -150,3 +202,72 @@ function loadUsers() {
         $('#users-list').html('<div class="alert alert-danger">Failed to load users</div>');
     });
 }
+
+function formatTimestamp(ts) {
+    if (ts === undefined || ts === null) {
+        return 'Never';
+    }
+    return new Date(ts * 1000).toLocaleString();
+}
+
+function loadApiTokens() {
+    getApiTokens((tokens) => {
+        const tokenList = $('#tokens-list');
+        tokenList.empty();
+
+        if (tokens.length === 0) {
+            tokenList.html('<div class="alert alert-info">No API tokens</div>');
+            return;
+        }
+
+        const now = Date.now() / 1000;
+        const tableWrap = $('<div class="table-responsive lqos-table-wrap">');
+        const table = $('<table class="lqos-table lqos-table-compact mb-0">')
+            .append('<thead><tr><th>Name</th><th>Scopes</th><th>Expires</th><th>Last Used</th><th>Actions</th></tr></thead>');
+        const tbody = $('<tbody>');
+
+        tokens.forEach(token => {
+            const scopes = token.scopes.map(s => SCOPE_LABELS[s] || s).join(', ');
+            const expired = token.expires !== undefined && token.expires !== null && token.expires <= now;
+            const expires = token.expires === undefined || token.expires === null
+                ? 'Never'
+                : formatTimestamp(token.expires) + (expired ? ' (expired)' : '');
+            const row = $('<tr>')
+                .append($('<td>').text(token.name))
+                .append($('<td>').text(scopes))
+                .append($('<td>').text(expires))
+                .append($('<td>').text(formatTimestamp(token.last_used)))
+                .append(`<td>
+                    <button class="btn btn-sm btn-danger revoke-token" data-id="${token.id}">
+                        <i class="fa fa-trash"></i> Revoke
+                    </button>
+                </td>`);
+            tbody.append(row);
+        });
+
+        table.append(tbody);
+        tableWrap.append(table);
+        tokenList.append(tableWrap);
+
+        $('.revoke-token').on('click', function() {
+            if (confirm('Revoke this token? Scripts using it will stop working.')) {
+                const id = $(this).data('id');
+                revokeApiToken(
+                    { id: String(id) },
+                    (msg) => {
+                        if (msg && msg.ok) {
+                            loadApiTokens();
+                        } else {
+                            alert(msg && msg.message ? msg.message : 'Failed to revoke token');
+                        }
+                    },
+                    () => {
+                        alert('Failed to revoke token');
+                    },
+                );
+            }
+        });
+    }, () => {
+        $('#tokens-list').html('<div class="alert alert-danger">Failed to load API tokens</div>');
+    });
+}
@@ -49,7 +49,7 @@ export class FlowsSankey extends DashboardGraph {
         renderableSankeyFlows(flows).forEach((flow) => {
             flowCount++;
             let localDevice = flow.device_name;
-            let proto = flow.protocol_name;
+            let proto = flow.application_name || flow.protocol_name;
             let asn = "ASN: " + flow.asn_id;
             if (flow.asn_name !== "") asn += " " + flow.asn_name;
             let remoteDevice = flow.remote_ip;
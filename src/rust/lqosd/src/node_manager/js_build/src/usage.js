@@ -0,0 +1,134 @@
+import {clearDiv, simpleRow, theading} from "./helpers/builders";
+import {scaleNumber} from "./lq_js_common/helpers/scaling";
+import {get_ws_client} from "./pubsub/ws";
+
+const wsClient = get_ws_client();
+const listenOnce = (eventName, handler) => {
+   const wrapped = (msg) => {
+      wsClient.off(eventName, wrapped);
+      handler(msg);
+   };
+   wsClient.on(eventName, wrapped);
+};
+
+function downloadCsv() {
+   const monthInput = document.getElementById("csvMonth");
+   const month = monthInput && monthInput.value ? monthInput.value : null;
+   listenOnce("UsageLedgerCsv", (msg) => {
+      const csv = msg && msg.csv ? msg.csv : "";
+      if (!csv) {
+         console.warn("Empty usage CSV payload");
+         return;
+      }
+      const blob = new Blob([csv], { type: "text/csv;charset=utf-8" });
+      const url = URL.createObjectURL(blob);
+      const link = document.createElement("a");
+      link.href = url;
+      link.download = month ? `usage-${month}.csv` : "usage.csv";
+      document.body.appendChild(link);
+      link.click();
+      link.remove();
+      URL.revokeObjectURL(url);
+   });
+   wsClient.send({ UsageLedgerCsv: { month } });
+}
+
+function usageCell(row) {
+   const td = document.createElement("td");
+   if (row.percent_used === null || row.percent_used === undefined) {
+      td.textContent = "-";
+      return td;
+   }
+   const percent = row.percent_used;
+   td.textContent = `${percent.toFixed(1)}% of ${scaleNumber(row.cap_bytes)}B`;
+   if (percent >= 100) {
+      td.classList.add("text-danger");
+   } else if (percent >= 80) {
+      td.classList.add("text-warning");
+   }
+   return td;
+}
+
+function usageRow(row) {
+   const tr = document.createElement("tr");
+   const circuitCell = document.createElement("td");
+   const link = document.createElement("a");
+   link.href = `circuit.html?id=${encodeURIComponent(row.circuit_id)}`;
+   link.classList.add("redactable");
+   link.textContent = row.circuit_name || row.circuit_id;
+   circuitCell.appendChild(link);
+   tr.appendChild(circuitCell);
+   tr.appendChild(simpleRow(row.parent_node || "-", true));
+   tr.appendChild(simpleRow(`${row.period_start} to ${row.period_end}`));
+   tr.appendChild(simpleRow(scaleNumber(row.download_bytes) + "B"));
+   tr.appendChild(simpleRow(scaleNumber(row.upload_bytes) + "B"));
+   tr.appendChild(simpleRow(row.policy || "-"));
+   tr.appendChild(usageCell(row));
+   const throttled = document.createElement("td");
+   if (row.throttled) {
+      throttled.innerHTML = '<span class="badge bg-danger">Throttled</span>';
+      if (row.throttled_since) {
+         throttled.title = "Since " + new Date(row.throttled_since * 1000).toLocaleString();
+      }
+   } else {
+      throttled.textContent = "-";
+   }
+   tr.appendChild(throttled);
+   return tr;
+}
+
+function loadUsage() {
+   listenOnce("UsageLedger", (msg) => {
+      const data = msg && msg.data ? msg.data : { enabled: false, rows: [] };
+      const target = document.getElementById("usage");
+      clearDiv(target);
+
+      if (!data.enabled) {
+         const p = document.createElement("p");
+         p.classList.add("text-muted");
+         p.textContent = "Usage accounting is disabled. Set enabled = true in the [usage_quotas] section of lqos.conf to start recording.";
+         target.appendChild(p);
+      }
+      if (!data.rows || data.rows.length === 0) {
+         if (data.enabled) {
+            const p = document.createElement("p");
+            p.classList.add("text-muted");
+            p.textContent = "No usage has been recorded yet.";
+            target.appendChild(p);
+         }
+         return;
+      }
+
+      const rows = [...data.rows].sort((a, b) =>
+         (b.download_bytes + b.upload_bytes) - (a.download_bytes + a.upload_bytes));
+      const table = document.createElement("table");
+      table.classList.add("lqos-table", "lqos-table-compact");
+      const thead = document.createElement("thead");
+      thead.appendChild(theading("Circuit"));
+      thead.appendChild(theading("Parent"));
+      thead.appendChild(theading("Billing Period"));
+      thead.appendChild(theading("Download"));
+      thead.appendChild(theading("Upload"));
+      thead.appendChild(theading("Policy"));
+      thead.appendChild(theading("Quota Used"));
+      thead.appendChild(theading("Status"));
+      table.appendChild(thead);
+      const tbody = document.createElement("tbody");
+      rows.forEach((row) => tbody.appendChild(usageRow(row)));
+      table.appendChild(tbody);
+      const tableWrap = document.createElement("div");
+      tableWrap.classList.add("lqos-table-wrap");
+      tableWrap.appendChild(table);
+      target.appendChild(tableWrap);
+   });
+   wsClient.send({ GetUsageLedger: {} });
+}
+
+const button = document.getElementById("btnCsv");
+if (button) {
+   button.onclick = () => {
+      downloadCsv();
+   };
+}
+
+loadUsage();
@@ -1,3 +1,5 @@
+pub(crate) mod audit_log;
+pub(crate) mod bandwidth_schedules;
 pub(crate) mod circuit;
 pub(crate) mod circuit_activity;
 pub(crate) mod circuit_count;
@@ -25,6 +27,7 @@ pub(crate) mod shaped_devices_page;
 pub(crate) mod tree_attached_circuits;
 pub(crate) mod unknown_ips;
 pub(crate) mod urgent;
+pub(crate) mod usage_ledger;
 pub(crate) mod warnings;
 
 use crate::node_manager::auth::auth_layer;
@@ -35,7 +38,13 @@ use tower_http::cors::CorsLayer;
 
 pub fn local_api(shaper_query: tokio::sync::mpsc::Sender<ShaperQueryCommand>) -> Router {
     Router::new()
+        .route("/auditLog.jsonl", get(audit_log::audit_log_export))
+        .route("/usageLedger.csv", get(usage_ledger::usage_ledger_export))
         .route("/pcapDump/:id", get(packet_analysis::pcap_dump))
+        .route(
+            "/ringCaptureDump/:id",
+            get(packet_analysis::ring_capture_dump),
+        )
         .layer(Extension(shaper_query))
         .layer(CorsLayer::very_permissive())
         .route_layer(axum::middleware::from_fn(auth_layer))
@@ -0,0 +1,68 @@
+//! Audit log queries and JSON lines export for administrators.
+
+use crate::node_manager::auth::LoginResult;
+use axum::Extension;
+use axum::extract::Query;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use lqos_config::{AuditEntry, AuditQuery, read_audit_log};
+use tokio::task::spawn_blocking;
+use tracing::warn;
+
+/// Entries returned to the UI when the query does not set a limit.
+const DEFAULT_PAGE_SIZE: usize = 200;
+
+async fn query_audit_log(query: AuditQuery) -> Result<Vec<AuditEntry>, StatusCode> {
+    spawn_blocking(move || read_audit_log(&query))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| {
+            warn!("Unable to read audit log: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+pub async fn audit_log_data(
+    login: LoginResult,
+    mut query: AuditQuery,
+) -> Result<Vec<AuditEntry>, StatusCode> {
+    if login != LoginResult::Admin {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if query.limit == 0 {
+        query.limit = DEFAULT_PAGE_SIZE;
+    }
+    query_audit_log(query).await
+}
+
+/// Downloads every matching entry as JSON lines, oldest first.
+pub async fn audit_log_export(
+    Extension(login): Extension<LoginResult>,
+    Query(query): Query<AuditQuery>,
+) -> Response {
+    if login != LoginResult::Admin {
+        return (StatusCode::FORBIDDEN, "Unauthorized").into_response();
+    }
+    let entries = match query_audit_log(query).await {
+        Ok(entries) => entries,
+        Err(status) => return (status, "Unable to read audit log").into_response(),
+    };
+    let mut body = String::new();
+    for entry in entries.iter().rev() {
+        if let Ok(line) = serde_json::to_string(entry) {
+            body.push_str(&line);
+            body.push('\n');
+        }
+    }
+    (
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"audit.jsonl\"",
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
@@ -0,0 +1,92 @@
+use crate::audit;
+use crate::bandwidth_schedules::{BandwidthScheduleStatus, schedule_status, schedules_changed};
+use crate::node_manager::auth::LoginResult;
+use axum::http::StatusCode;
+use lqos_config::{ApiTokenScope, AuditActor, AuditEntry};
+use lqos_overrides::{BandwidthSchedule, OverrideLayer, OverrideStore};
+use serde::{Deserialize, Serialize};
+
+/// Schedules from the operator overrides file together with their live state.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BandwidthSchedulesData {
+    /// Whether the current session may edit schedules.
+    pub writable: bool,
+    /// Configured schedules, in evaluation order.
+    pub schedules: Vec<BandwidthSchedule>,
+    /// Live state of each schedule.
+    pub status: Vec<BandwidthScheduleStatus>,
+}
+
+/// Lists bandwidth schedules and whether each one is currently applied.
+pub fn get_bandwidth_schedules_data(
+    login: LoginResult,
+) -> Result<BandwidthSchedulesData, StatusCode> {
+    let overrides = OverrideStore::load_layer(OverrideLayer::Operator)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(BandwidthSchedulesData {
+        writable: login.allows(ApiTokenScope::OverridesWrite),
+        schedules: overrides.bandwidth_schedules().to_vec(),
+        status: schedule_status(),
+    })
+}
+
+/// Adds or replaces a schedule by ID and applies it immediately.
+pub fn save_bandwidth_schedule_data(
+    login: LoginResult,
+    actor: &AuditActor,
+    schedule: BandwidthSchedule,
+) -> Result<BandwidthSchedulesData, String> {
+    if !login.allows(ApiTokenScope::OverridesWrite) {
+        return Err("Unauthorized".to_string());
+    }
+    schedule.validate().map_err(|e| e.to_string())?;
+
+    let mut overrides = OverrideStore::load_layer(OverrideLayer::Operator)
+        .map_err(|e| format!("Unable to load overrides: {e}"))?;
+    let before = overrides
+        .bandwidth_schedules()
+        .iter()
+        .find(|s| s.id == schedule.id)
+        .cloned();
+    let id = schedule.id.clone();
+    if overrides.set_bandwidth_schedule_return_changed(schedule.clone()) {
+        OverrideStore::save_layer(OverrideLayer::Operator, &overrides)
+            .map_err(|e| format!("Unable to save overrides: {e}"))?;
+        audit::record(
+            AuditEntry::new(actor.clone(), "set_bandwidth_schedule")
+                .target(id)
+                .changes(before.as_ref(), Some(&schedule)),
+        );
+        schedules_changed();
+    }
+    get_bandwidth_schedules_data(login).map_err(|_| "Unable to load schedules".to_string())
+}
+
+/// Removes a schedule by ID; an active schedule's rates revert immediately.
+pub fn delete_bandwidth_schedule_data(
+    login: LoginResult,
+    actor: &AuditActor,
+    id: String,
+) -> Result<BandwidthSchedulesData, String> {
+    if !login.allows(ApiTokenScope::OverridesWrite) {
+        return Err("Unauthorized".to_string());
+    }
+    let mut overrides = OverrideStore::load_layer(OverrideLayer::Operator)
+        .map_err(|e| format!("Unable to load overrides: {e}"))?;
+    let before = overrides
+        .bandwidth_schedules()
+        .iter()
+        .find(|s| s.id == id)
+        .cloned();
+    if overrides.remove_bandwidth_schedule_count(&id) > 0 {
+        OverrideStore::save_layer(OverrideLayer::Operator, &overrides)
+            .map_err(|e| format!("Unable to save overrides: {e}"))?;
+        audit::record(
+            AuditEntry::new(actor.clone(), "delete_bandwidth_schedule")
+                .target(id)
+                .changes(before.as_ref(), None::<&BandwidthSchedule>),
+        );
+        schedules_changed();
+    }
+    get_bandwidth_schedules_data(login).map_err(|_| "Unable to load schedules".to_string())
+}
@@ -45,6 +45,8 @@ pub struct CircuitTrafficFlowsQuery {
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct CircuitTrafficFlowRow {
     pub protocol_name: String,
+    /// Classified application, empty if unclassified.
+    pub application_name: String,
     pub down_bps: u32,
     pub up_bps: u32,
     pub bytes_sent_down: u64,
@@ -111,6 +113,8 @@ pub struct CircuitFlowSankeyRow {
     pub asn_id: u32,
     pub asn_name: String,
     pub protocol_name: String,
+    /// Classified application, empty if unclassified.
+    pub application_name: String,
     pub remote_ip: String,
     pub down_bps: u32,
     pub up_bps: u32,
@@ -124,6 +128,7 @@ struct CircuitFlowSnapshotRow {
     asn_name: String,
     asn_country: String,
     protocol_name: String,
+    application_name: String,
     remote_ip: String,
     down_bps: u32,
     up_bps: u32,
@@ -274,6 +279,7 @@ fn flow_snapshot_rows(circuit_id: &str) -> Vec<CircuitFlowSnapshotRow> {
                 asn_name: geo.name,
                 asn_country: geo.country,
                 protocol_name: analysis.protocol_analysis.to_string(),
+                application_name: analysis.application.name(),
                 remote_ip: key.remote_ip.to_string(),
                 down_bps: display_rate.down,
                 up_bps: display_rate.up,
@@ -323,6 +329,7 @@ fn sort_traffic_rows(rows: &mut [CircuitFlowSnapshotRow], sort_column: &str, sor
     rows.sort_by(|a, b| {
         let primary = match sort_column {
             "protocol" => compare_strings(&a.protocol_name, &b.protocol_name, asc),
+            "application" => compare_strings(&a.application_name, &b.application_name, asc),
             "bytes" => compare_u64(a.bytes_sent_down + a.bytes_sent_up, b.bytes_sent_down + b.bytes_sent_up, asc),
             "packets" => compare_u64(a.packets_sent_down + a.packets_sent_up, b.packets_sent_down + b.packets_sent_up, asc),
             "retransmits" => compare_f64(a.retransmit_down_pct + a.retransmit_up_pct, b.retransmit_down_pct + b.retransmit_up_pct, asc),
@@ -372,6 +379,7 @@ pub fn circuit_traffic_flows_page(query: &CircuitTrafficFlowsQuery) -> CircuitTr
         .take(page_size)
         .map(|row| CircuitTrafficFlowRow {
             protocol_name: row.protocol_name,
+            application_name: row.application_name,
             down_bps: row.down_bps,
             up_bps: row.up_bps,
             bytes_sent_down: row.bytes_sent_down,
@@ -509,6 +517,7 @@ pub fn circuit_flow_sankey_rows(circuit_id: &str) -> Vec<CircuitFlowSankeyRow> {
             asn_id: row.asn_id,
             asn_name: row.asn_name,
             protocol_name: row.protocol_name,
+            application_name: row.application_name,
             remote_ip: row.remote_ip,
             down_bps: row.down_bps,
             up_bps: row.up_bps,
@@ -1,9 +1,14 @@
+use crate::audit;
 use crate::node_manager::auth::LoginResult;
 use crate::shaped_devices_tracker::SHAPED_DEVICES;
 use axum::http::StatusCode;
 use default_net::get_interfaces;
 use lqos_bus::{BusRequest, bus_request};
-use lqos_config::{Config, ConfigShapedDevices, ShapedDevice, UserRole, WebUser, WebUsers};
+use lqos_config::authentication::AuthenticationError;
+use lqos_config::{
+    ApiToken, ApiTokenScope, AuditActor, AuditEntry, Config, ConfigShapedDevices, ShapedDevice,
+    UserRole, WebUser, WebUsers,
+};
 use lqos_utils::hash_to_i64;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -325,6 +330,23 @@ fn validate_shaped_devices(devices: &[ShapedDevice]) -> Result<(), String> {
     Ok(())
 }
 
+/// Reads the current `network.json`, for audit diffs.
+fn current_network_json() -> Option<Value> {
+    let config = lqos_config::load_config().ok()?;
+    let path = std::path::Path::new(&config.lqos_directory).join("network.json");
+    let raw = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&raw).ok()
+}
+
+/// Keys devices by device ID so audit diffs name the devices that changed
+/// rather than list positions.
+fn devices_by_id(devices: &[ShapedDevice]) -> BTreeMap<&str, &ShapedDevice> {
+    devices
+        .iter()
+        .map(|device| (device.device_id.as_str(), device))
+        .collect()
+}
+
 fn persist_shaped_devices(mut devices: Vec<ShapedDevice>) -> Result<(), String> {
     for device in &mut devices {
         normalize_shaped_device(device);
@@ -352,14 +374,19 @@ fn persist_shaped_devices(mut devices: Vec<ShapedDevice>) -> Result<(), String>
 
 pub async fn update_lqosd_config_data(
     login: LoginResult,
+    actor: &AuditActor,
     config: Config,
 ) -> Result<(), StatusCode> {
     if login != LoginResult::Admin {
         return Err(StatusCode::FORBIDDEN);
     }
+    let before = lqos_config::load_config().ok();
+    let entry =
+        AuditEntry::new(actor.clone(), "update_config").changes(before.as_deref(), Some(&config));
     bus_request(vec![BusRequest::UpdateLqosdConfig(Box::new(config))])
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    audit::record(entry);
     Ok(())
 }
 
@@ -370,6 +397,7 @@ pub async fn update_lqosd_config_data(
 /// managed topology editing is locked, or when validation/persistence fails.
 pub fn update_network_and_devices_data(
     login: LoginResult,
+    actor: &AuditActor,
     network_json: Value,
     shaped_devices: Vec<ShapedDevice>,
 ) -> Result<(), String> {
@@ -377,8 +405,21 @@ pub fn update_network_and_devices_data(
         return Err("Unauthorized".to_string());
     }
     ensure_topology_editor_unlocked()?;
+    let network_before = current_network_json();
+    let devices_before = SHAPED_DEVICES.load();
+    let network_entry = AuditEntry::new(actor.clone(), "update_network_json")
+        .target("network.json")
+        .changes(network_before.as_ref(), Some(&network_json));
+    let devices_entry = AuditEntry::new(actor.clone(), "update_shaped_devices")
+        .target("ShapedDevices.csv")
+        .changes(
+            Some(&devices_by_id(&devices_before.devices)),
+            Some(&devices_by_id(&shaped_devices)),
+        );
     persist_network_json(&network_json)?;
+    audit::record(network_entry);
     persist_shaped_devices(shaped_devices)?;
+    audit::record(devices_entry);
 
     Ok(())
 }
@@ -390,6 +431,7 @@ pub fn update_network_and_devices_data(
 /// managed topology editing is locked, or when persistence fails.
 pub fn update_network_json_only_data(
     login: LoginResult,
+    actor: &AuditActor,
     network_json: Value,
 ) -> Result<(), String> {
     if login != LoginResult::Admin {
@@ -397,18 +439,24 @@ pub fn update_network_json_only_data(
     }
     ensure_topology_editor_unlocked()?;
 
+    let before = current_network_json();
     persist_network_json(&network_json)?;
+    audit::record(
+        AuditEntry::new(actor.clone(), "update_network_json")
+            .target("network.json")
+            .changes(before.as_ref(), Some(&network_json)),
+    );
 
     Ok(())
 }
 
 /// Returns one shaped device row by device identifier for administrative
-/// callers.
+/// callers and API tokens allowed to write shaped devices.
 pub fn get_shaped_device_data(
     login: LoginResult,
     device_id: String,
 ) -> Result<Option<ShapedDevice>, StatusCode> {
-    if login != LoginResult::Admin {
+    if !login.allows(ApiTokenScope::ShapedDevicesWrite) {
         return Err(StatusCode::FORBIDDEN);
     }
     let wanted = device_id.trim();
@@ -420,15 +468,17 @@ pub fn get_shaped_device_data(
         .cloned())
 }
 
-/// Creates one shaped device row for administrative callers.
+/// Creates one shaped device row for administrative callers and API tokens
+/// allowed to write shaped devices.
 ///
 /// Returns an error string when the caller is unauthorized, when integration-
 /// managed topology editing is locked, or when validation/persistence fails.
 pub fn create_shaped_device_data(
     login: LoginResult,
+    actor: &AuditActor,
     device: ShapedDevice,
 ) -> Result<ShapedDevice, String> {
-    if login != LoginResult::Admin {
+    if !login.allows(ApiTokenScope::ShapedDevicesWrite) {
         return Err("Unauthorized".to_string());
     }
     ensure_topology_editor_unlocked()?;
@@ -438,20 +488,27 @@ pub fn create_shaped_device_data(
     let created = get_shaped_device_data(login, device.device_id.clone())
         .map_err(|_| "Unable to reload shaped device".to_string())?
         .ok_or_else(|| "Unable to reload shaped device".to_string())?;
+    audit::record(
+        AuditEntry::new(actor.clone(), "create_shaped_device")
+            .target(created.device_id.clone())
+            .changes(None::<&ShapedDevice>, Some(&created)),
+    );
     Ok(created)
 }
 
-/// Updates one shaped device row for administrative callers.
+/// Updates one shaped device row for administrative callers and API tokens
+/// allowed to write shaped devices.
 ///
 /// Returns an error string when the caller is unauthorized, when integration-
 /// managed topology editing is locked, when the row is not found, or when
 /// validation/persistence fails.
 pub fn update_shaped_device_data(
     login: LoginResult,
+    actor: &AuditActor,
     original_device_id: String,
     device: ShapedDevice,
 ) -> Result<ShapedDevice, String> {
-    if login != LoginResult::Admin {
+    if !login.allows(ApiTokenScope::ShapedDevicesWrite) {
         return Err("Unauthorized".to_string());
     }
     ensure_topology_editor_unlocked()?;
@@ -460,33 +517,83 @@ pub fn update_shaped_device_data(
     let Some(index) = devices.iter().position(|row| row.device_id == wanted) else {
         return Err("Not found".to_string());
     };
-    devices[index] = device.clone();
+    let before = std::mem::replace(&mut devices[index], device.clone());
     persist_shaped_devices(devices)?;
     let updated = get_shaped_device_data(login, device.device_id.clone())
         .map_err(|_| "Unable to reload shaped device".to_string())?
         .ok_or_else(|| "Unable to reload shaped device".to_string())?;
+    audit::record(
+        AuditEntry::new(actor.clone(), "update_shaped_device")
+            .target(wanted)
+            .changes(Some(&before), Some(&updated)),
+    );
     Ok(updated)
 }
 
-/// Deletes one shaped device row for administrative callers.
+/// Deletes one shaped device row for administrative callers and API tokens
+/// allowed to write shaped devices.
 ///
 /// Returns an error string when the caller is unauthorized, when integration-
 /// managed topology editing is locked, when the row is not found, or when
 /// persistence fails.
-pub fn delete_shaped_device_data(login: LoginResult, device_id: String) -> Result<(), String> {
-    if login != LoginResult::Admin {
+pub fn delete_shaped_device_data(
+    login: LoginResult,
+    actor: &AuditActor,
+    device_id: String,
+) -> Result<(), String> {
+    if !login.allows(ApiTokenScope::ShapedDevicesWrite) {
         return Err("Unauthorized".to_string());
     }
     ensure_topology_editor_unlocked()?;
     let wanted = device_id.trim();
     let mut devices = SHAPED_DEVICES.load().devices.clone();
-    let before = devices.len();
+    let Some(index) = devices.iter().position(|device| device.device_id == wanted) else {
+        return Err("Not found".to_string());
+    };
+    let removed = devices.remove(index);
+    // Device IDs are unique once validated, but drop any stray duplicates too
     devices.retain(|device| device.device_id != wanted);
-    if devices.len() == before {
+    persist_shaped_devices(devices)?;
+    audit::record(
+        AuditEntry::new(actor.clone(), "delete_shaped_device")
+            .target(wanted)
+            .changes(Some(&removed), None::<&ShapedDevice>),
+    );
+    Ok(())
+}
+
+/// Deletes every shaped device row belonging to a circuit, for administrative
+/// callers and API tokens allowed to write shaped devices.
+///
+/// Returns the removed rows, or an error string when the caller is
+/// unauthorized, when integration-managed topology editing is locked, when the
+/// circuit is not found, or when persistence fails.
+pub fn delete_circuit_data(
+    login: LoginResult,
+    actor: &AuditActor,
+    circuit_id: String,
+) -> Result<Vec<ShapedDevice>, String> {
+    if !login.allows(ApiTokenScope::ShapedDevicesWrite) {
+        return Err("Unauthorized".to_string());
+    }
+    ensure_topology_editor_unlocked()?;
+    let wanted = circuit_id.trim();
+    let (removed, devices): (Vec<_>, Vec<_>) = SHAPED_DEVICES
+        .load()
+        .devices
+        .iter()
+        .cloned()
+        .partition(|device| device.circuit_id == wanted);
+    if removed.is_empty() {
         return Err("Not found".to_string());
     }
     persist_shaped_devices(devices)?;
-    Ok(())
+    audit::record(
+        AuditEntry::new(actor.clone(), "delete_circuit")
+            .target(wanted)
+            .changes(Some(&removed), None::<&Vec<ShapedDevice>>),
+    );
+    Ok(removed)
 }
 
 pub fn get_users_data(login: LoginResult) -> Result<Vec<WebUser>, StatusCode> {
@@ -497,7 +604,20 @@ pub fn get_users_data(login: LoginResult) -> Result<Vec<WebUser>, StatusCode> {
     Ok(users.get_users())
 }
 
-pub fn add_user_data(login: LoginResult, data: UserRequest) -> Result<String, StatusCode> {
+/// The parts of a user worth auditing. Password hashes are never logged.
+fn audited_user(users: &WebUsers, username: &str) -> Option<Value> {
+    users
+        .get_users()
+        .into_iter()
+        .find(|u| u.username == username)
+        .map(|u| serde_json::json!({ "username": u.username, "role": u.role }))
+}
+
+pub fn add_user_data(
+    login: LoginResult,
+    actor: &AuditActor,
+    data: UserRequest,
+) -> Result<String, StatusCode> {
     if login != LoginResult::Admin {
         return Err(StatusCode::FORBIDDEN);
     }
@@ -509,13 +629,24 @@ pub fn add_user_data(login: LoginResult, data: UserRequest) -> Result<String, St
         _ => return Err(StatusCode::BAD_REQUEST),
     };
     let mut users = WebUsers::load_or_create().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let username = data.username.trim();
+    let before = audited_user(&users, username);
     users
-        .add_or_update_user(data.username.trim(), password, data.role.into())
+        .add_or_update_user(username, password, data.role.into())
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    audit::record(
+        AuditEntry::new(actor.clone(), "add_user")
+            .target(username)
+            .changes(before.as_ref(), audited_user(&users, username).as_ref()),
+    );
     Ok(format!("User '{}' added", data.username))
 }
 
-pub fn update_user_data(login: LoginResult, data: UserRequest) -> Result<String, StatusCode> {
+pub fn update_user_data(
+    login: LoginResult,
+    actor: &AuditActor,
+    data: UserRequest,
+) -> Result<String, StatusCode> {
     if login != LoginResult::Admin {
         return Err(StatusCode::FORBIDDEN);
     }
@@ -537,13 +668,28 @@ pub fn update_user_data(login: LoginResult, data: UserRequest) -> Result<String,
     }
 
     let password = data.password.as_deref().filter(|p| !p.is_empty());
+    let before = audited_user(&users, &data.username);
     users
         .update_user_with_optional_password(&data.username, password, data.role.into())
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut entry = AuditEntry::new(actor.clone(), "update_user")
+        .target(data.username.clone())
+        .changes(
+            before.as_ref(),
+            audited_user(&users, &data.username).as_ref(),
+        );
+    if password.is_some() {
+        entry = entry.detail("Password changed");
+    }
+    audit::record(entry);
     Ok("User updated".to_string())
 }
 
-pub fn delete_user_data(login: LoginResult, username: String) -> Result<String, StatusCode> {
+pub fn delete_user_data(
+    login: LoginResult,
+    actor: &AuditActor,
+    username: String,
+) -> Result<String, StatusCode> {
     if login != LoginResult::Admin {
         return Err(StatusCode::FORBIDDEN);
     }
@@ -563,12 +709,80 @@ pub fn delete_user_data(login: LoginResult, username: String) -> Result<String,
         }
     }
 
+    let before = audited_user(&users, &username);
     users
         .remove_user(&username)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    audit::record(
+        AuditEntry::new(actor.clone(), "delete_user")
+            .target(username)
+            .changes(before.as_ref(), None::<&Value>),
+    );
     Ok("User deleted".to_string())
 }
 
+pub fn get_api_tokens_data(login: LoginResult) -> Result<Vec<ApiToken>, StatusCode> {
+    if login != LoginResult::Admin {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let users = WebUsers::load_or_create().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(users.get_api_tokens())
+}
+
+/// Creates an API token, returning its one-time secret.
+pub fn create_api_token_data(
+    login: LoginResult,
+    actor: &AuditActor,
+    data: ApiTokenRequest,
+) -> Result<String, StatusCode> {
+    if login != LoginResult::Admin {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if data.name.trim().is_empty() || data.scopes.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let expires = match data.expires_days {
+        Some(days) => Some(
+            lqos_utils::unix_time::unix_now()
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .saturating_add(days.saturating_mul(86_400)),
+        ),
+        None => None,
+    };
+    let mut users = WebUsers::load_or_create().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (token, secret) = users
+        .create_api_token(data.name.trim(), &data.scopes, expires)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    audit::record(
+        AuditEntry::new(actor.clone(), "create_api_token")
+            .target(token.id.clone())
+            .changes(None::<&ApiToken>, Some(&token)),
+    );
+    Ok(secret)
+}
+
+pub fn revoke_api_token_data(
+    login: LoginResult,
+    actor: &AuditActor,
+    id: String,
+) -> Result<String, StatusCode> {
+    if login != LoginResult::Admin {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let mut users = WebUsers::load_or_create().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let before = users.get_api_tokens().into_iter().find(|t| t.id == id);
+    users.revoke_api_token(&id).map_err(|e| match e {
+        AuthenticationError::TokenNotFound => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+    audit::record(
+        AuditEntry::new(actor.clone(), "revoke_api_token")
+            .target(id)
+            .changes(before.as_ref(), None::<&ApiToken>),
+    );
+    Ok("Token revoked".to_string())
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct UserRequest {
     pub username: String,
@@ -576,6 +790,13 @@ pub struct UserRequest {
     pub role: String,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct ApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    pub expires_days: Option<u64>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::validate_network_json;
@@ -1,6 +1,6 @@
 use crate::throughput_tracker::flow_data::{
-    AsnCountryListEntry, AsnListEntry, AsnProtocolListEntry, FlowAnalysis, FlowbeeLocalData,
-    RECENT_FLOWS, RttData,
+    ApplicationListEntry, AsnCountryListEntry, AsnListEntry, AsnProtocolListEntry, FlowAnalysis,
+    FlowbeeLocalData, RECENT_FLOWS, RttData,
 };
 use crate::{
     shaped_devices_tracker::{SHAPED_DEVICE_HASH_CACHE, SHAPED_DEVICES},
@@ -24,6 +24,10 @@ pub fn protocol_list_data() -> Vec<AsnProtocolListEntry> {
     RECENT_FLOWS.protocol_list()
 }
 
+pub fn application_list_data() -> Vec<ApplicationListEntry> {
+    RECENT_FLOWS.application_list()
+}
+
 #[derive(Debug, Serialize)]
 pub struct FlowTimeline {
     pub start: u64,
@@ -36,6 +40,7 @@ pub struct FlowTimeline {
     pub retransmit_times_up: Vec<u64>,
     pub total_bytes: DownUpOrder<u64>,
     pub protocol: String,
+    pub application: String,
     pub circuit_id: String,
     pub circuit_name: String,
     pub remote_ip: String,
@@ -118,6 +123,7 @@ fn all_flows_to_transport(
                 retransmit_times_up,
                 total_bytes: flow.1.bytes_sent,
                 protocol: flow.2.protocol_analysis.to_string(),
+                application: flow.2.application.name(),
                 circuit_id,
                 circuit_name,
                 remote_ip: flow.0.remote_ip.as_ip().to_string(),
@@ -150,3 +156,15 @@ pub fn protocol_timeline_data(protocol_name: &str) -> Vec<FlowTimeline> {
 
     all_flows_to_transport(boot_time, all_flows_for_asn)
 }
+
+pub fn application_timeline_data(application: &str) -> Vec<FlowTimeline> {
+    let time_since_boot = time_since_boot().expect("failed to retrieve time since boot");
+    let since_boot = Duration::from(time_since_boot);
+    let boot_time = unix_now()
+        .expect("failed to retrieve current unix time")
+        .saturating_sub(since_boot.as_secs());
+
+    let all_flows_for_application = RECENT_FLOWS.all_flows_for_application(application);
+
+    all_flows_to_transport(boot_time, all_flows_for_application)
+}
@@ -1,4 +1,5 @@
 mod last_24_hours;
+mod local_history;
 mod shaper_status;
 
 use crate::lts2_sys::lts2_client::{LicenseStatus, set_license_status};
@@ -7,6 +8,7 @@ use crate::node_manager::auth::LoginResult;
 use crate::node_manager::local_api::circuit_count;
 use axum::http::StatusCode;
 pub use last_24_hours::*;
+pub use local_history::{LocalHistoryPoint, circuit_history_data, site_history_data};
 use lqos_bus::{BusRequest, bus_request};
 use lqos_config::load_config;
 use serde::{Deserialize, Serialize};
@@ -5,13 +5,13 @@ use tracing::warn;
 
 #[derive(Serialize, Deserialize, Copy, Clone, Debug)]
 pub struct ThroughputData {
-    time: i64, // Unix timestamp
-    max_down: i64,
-    max_up: i64,
-    min_down: i64,
-    min_up: i64,
-    median_down: i64,
-    median_up: i64,
+    pub time: i64, // Unix timestamp
+    pub max_down: i64,
+    pub max_up: i64,
+    pub min_down: i64,
+    pub min_up: i64,
+    pub median_down: i64,
+    pub median_up: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -45,19 +45,19 @@ pub struct FullPacketData {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CakeData {
-    time: i64, // Unix timestamp
-    max_marks_down: i64,
-    max_marks_up: i64,
-    min_marks_down: i64,
-    min_marks_up: i64,
-    median_marks_down: i64,
-    median_marks_up: i64,
-    max_drops_down: i64,
-    max_drops_up: i64,
-    min_drops_down: i64,
-    min_drops_up: i64,
-    median_drops_down: i64,
-    median_drops_up: i64,
+    pub time: i64, // Unix timestamp
+    pub max_marks_down: i64,
+    pub max_marks_up: i64,
+    pub min_marks_down: i64,
+    pub min_marks_up: i64,
+    pub median_marks_down: i64,
+    pub median_marks_up: i64,
+    pub max_drops_down: i64,
+    pub max_drops_up: i64,
+    pub min_drops_down: i64,
+    pub min_drops_up: i64,
+    pub median_drops_down: i64,
+    pub median_drops_up: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -69,9 +69,9 @@ pub struct PercentShapedWeb {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FlowCountViewWeb {
-    time: i64,
-    shaper_id: i64,
-    flow_count: f64,
+    pub time: i64,
+    pub shaper_id: i64,
+    pub flow_count: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -140,7 +140,11 @@ pub async fn throughput_period_data(
     shaper_query: tokio::sync::mpsc::Sender<ShaperQueryCommand>,
     seconds: i32,
 ) -> Result<Vec<ThroughputData>, StatusCode> {
-    super::insight_gate().await?;
+    if let Err(status) = super::insight_gate().await {
+        return super::local_history::throughput(seconds)
+            .await
+            .ok_or(status);
+    }
     let (tx, rx) = tokio::sync::oneshot::channel();
     shaper_query
         .send(ShaperQueryCommand::ShaperThroughput { seconds, reply: tx })
@@ -157,7 +161,9 @@ pub async fn packets_period_data(
     shaper_query: tokio::sync::mpsc::Sender<ShaperQueryCommand>,
     seconds: i32,
 ) -> Result<Vec<FullPacketData>, StatusCode> {
-    super::insight_gate().await?;
+    if let Err(status) = super::insight_gate().await {
+        return super::local_history::packets(seconds).await.ok_or(status);
+    }
     let (tx, rx) = tokio::sync::oneshot::channel();
     shaper_query
         .send(ShaperQueryCommand::ShaperPackets { seconds, reply: tx })
@@ -177,7 +183,11 @@ pub async fn percent_shaped_period_data(
     shaper_query: tokio::sync::mpsc::Sender<ShaperQueryCommand>,
     seconds: i32,
 ) -> Result<Vec<PercentShapedWeb>, StatusCode> {
-    super::insight_gate().await?;
+    if let Err(status) = super::insight_gate().await {
+        return super::local_history::percent_shaped(seconds)
+            .await
+            .ok_or(status);
+    }
     let (tx, rx) = tokio::sync::oneshot::channel();
     shaper_query
         .send(ShaperQueryCommand::ShaperPercent { seconds, reply: tx })
@@ -197,7 +207,9 @@ pub async fn percent_flows_period_data(
     shaper_query: tokio::sync::mpsc::Sender<ShaperQueryCommand>,
     seconds: i32,
 ) -> Result<Vec<FlowCountViewWeb>, StatusCode> {
-    super::insight_gate().await?;
+    if let Err(status) = super::insight_gate().await {
+        return super::local_history::flows(seconds).await.ok_or(status);
+    }
     let (tx, rx) = tokio::sync::oneshot::channel();
     shaper_query
         .send(ShaperQueryCommand::ShaperFlows { seconds, reply: tx })
@@ -217,7 +229,11 @@ pub async fn rtt_histo_period_data(
     shaper_query: tokio::sync::mpsc::Sender<ShaperQueryCommand>,
     seconds: i32,
 ) -> Result<Vec<ShaperRttHistogramEntry>, StatusCode> {
-    super::insight_gate().await?;
+    if let Err(status) = super::insight_gate().await {
+        return super::local_history::rtt_histogram(seconds)
+            .await
+            .ok_or(status);
+    }
     tracing::error!("rtt_histo_period");
     let (tx, rx) = tokio::sync::oneshot::channel();
     shaper_query
@@ -238,7 +254,11 @@ pub async fn top10_downloaders_period_data(
     shaper_query: tokio::sync::mpsc::Sender<ShaperQueryCommand>,
     seconds: i32,
 ) -> Result<Vec<Top10Circuit>, StatusCode> {
-    super::insight_gate().await?;
+    if let Err(status) = super::insight_gate().await {
+        return super::local_history::top_downloaders(seconds)
+            .await
+            .ok_or(status);
+    }
     tracing::error!("rtt_histo_period");
     let (tx, rx) = tokio::sync::oneshot::channel();
     shaper_query
@@ -259,7 +279,9 @@ pub async fn worst10_rtt_period_data(
     shaper_query: tokio::sync::mpsc::Sender<ShaperQueryCommand>,
     seconds: i32,
 ) -> Result<Vec<Worst10RttCircuit>, StatusCode> {
-    super::insight_gate().await?;
+    if let Err(status) = super::insight_gate().await {
+        return super::local_history::worst_rtt(seconds).await.ok_or(status);
+    }
     tracing::error!("rtt_histo_period");
     let (tx, rx) = tokio::sync::oneshot::channel();
     shaper_query
@@ -280,7 +302,11 @@ pub async fn worst10_rxmit_period_data(
     shaper_query: tokio::sync::mpsc::Sender<ShaperQueryCommand>,
     seconds: i32,
 ) -> Result<Vec<Worst10RxmitCircuit>, StatusCode> {
-    super::insight_gate().await?;
+    if let Err(status) = super::insight_gate().await {
+        return super::local_history::worst_retransmits(seconds)
+            .await
+            .ok_or(status);
+    }
     tracing::error!("rtt_histo_period");
     let (tx, rx) = tokio::sync::oneshot::channel();
     shaper_query
@@ -321,7 +347,9 @@ pub async fn top10_flows_period_data(
 pub async fn recent_medians_data(
     shaper_query: tokio::sync::mpsc::Sender<ShaperQueryCommand>,
 ) -> Result<Vec<RecentMedians>, StatusCode> {
-    super::insight_gate().await?;
+    if let Err(status) = super::insight_gate().await {
+        return super::local_history::recent_medians().await.ok_or(status);
+    }
     tracing::debug!("rtt_histo_period");
     let (tx, rx) = tokio::sync::oneshot::channel();
     shaper_query
@@ -342,7 +370,9 @@ pub async fn cake_period_data(
     shaper_query: tokio::sync::mpsc::Sender<ShaperQueryCommand>,
     seconds: i32,
 ) -> Result<Vec<CakeData>, StatusCode> {
-    super::insight_gate().await?;
+    if let Err(status) = super::insight_gate().await {
+        return super::local_history::cake(seconds).await.ok_or(status);
+    }
     let (tx, rx) = tokio::sync::oneshot::channel();
     shaper_query
         .send(ShaperQueryCommand::CakeTotals { seconds, reply: tx })
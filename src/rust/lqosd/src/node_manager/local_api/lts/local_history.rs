@@ -0,0 +1,475 @@
+//! Answers the LTS history requests from the on-disk local history when
+//! Insight isn't available.
+
+use super::{
+    CakeData, FlowCountViewWeb, FullPacketData, PercentShapedWeb, RecentMedians,
+    ShaperRttHistogramEntry, ThroughputData, Top10Circuit, Worst10RttCircuit, Worst10RxmitCircuit,
+};
+use crate::local_history::{
+    Aggregate, HistoryQuery, HistorySelection, Metric, Sample, SeriesKey, query_history,
+};
+use crate::shaped_devices_tracker::SHAPED_DEVICES;
+use axum::http::StatusCode;
+use fxhash::FxHashMap;
+use lqos_config::load_config;
+use lqos_utils::hash_to_i64;
+use serde::Serialize;
+use std::cmp::Ordering;
+
+/// Most points returned for a chart.
+const MAX_POINTS: usize = 1000;
+/// For summaries of the whole period: slots are never combined.
+const ALL_POINTS: usize = usize::MAX;
+const RTT_HISTOGRAM_BUCKETS: usize = 50;
+const RTT_HISTOGRAM_BUCKET_MS: f32 = 10.0;
+const TOP_CIRCUITS: usize = 10;
+
+#[derive(Debug, Serialize, Clone, Copy)]
+pub struct MinMaxMedian {
+    pub min: f64,
+    pub max: f64,
+    pub median: f64,
+}
+
+impl From<Aggregate> for MinMaxMedian {
+    fn from(value: Aggregate) -> Self {
+        Self {
+            min: value.min as f64,
+            max: value.max as f64,
+            median: value.median as f64,
+        }
+    }
+}
+
+/// One slot of site or circuit history. Rates are per second.
+#[derive(Debug, Serialize, Clone)]
+pub struct LocalHistoryPoint {
+    pub time: i64, // Unix timestamp
+    pub bytes_down: MinMaxMedian,
+    pub bytes_up: MinMaxMedian,
+    pub rtt_ms: Option<MinMaxMedian>,
+    pub retransmits_down: MinMaxMedian,
+    pub retransmits_up: MinMaxMedian,
+    pub cake_marks_down: MinMaxMedian,
+    pub cake_marks_up: MinMaxMedian,
+    pub cake_drops_down: MinMaxMedian,
+    pub cake_drops_up: MinMaxMedian,
+}
+
+async fn query(
+    selection: HistorySelection,
+    seconds: i32,
+    max_points: usize,
+) -> Option<HistoryQuery> {
+    let seconds = u64::try_from(seconds).ok()?.max(1);
+    tokio::task::spawn_blocking(move || query_history(selection, seconds, max_points))
+        .await
+        .ok()?
+}
+
+async fn shaper_points(seconds: i32) -> Option<Vec<(i64, Sample)>> {
+    let history = query(
+        HistorySelection::Series(SeriesKey::Shaper),
+        seconds,
+        MAX_POINTS,
+    )
+    .await?;
+    Some(
+        history
+            .frames
+            .into_iter()
+            .filter_map(|frame| {
+                let time = frame.time;
+                frame
+                    .series
+                    .into_iter()
+                    .next()
+                    .map(|(_, sample)| (time, sample))
+            })
+            .collect(),
+    )
+}
+
+fn metric(sample: &Sample, metric: Metric) -> Aggregate {
+    sample.get_or_zero(metric)
+}
+
+pub(super) async fn throughput(seconds: i32) -> Option<Vec<ThroughputData>> {
+    let points = shaper_points(seconds).await?;
+    Some(
+        points
+            .iter()
+            .map(|(time, s)| {
+                let (down, up) = (metric(s, Metric::BytesDown), metric(s, Metric::BytesUp));
+                ThroughputData {
+                    time: *time,
+                    max_down: down.max as i64,
+                    max_up: up.max as i64,
+                    min_down: down.min as i64,
+                    min_up: up.min as i64,
+                    median_down: down.median as i64,
+                    median_up: up.median as i64,
+                }
+            })
+            .collect(),
+    )
+}
+
+pub(super) async fn packets(seconds: i32) -> Option<Vec<FullPacketData>> {
+    let points = shaper_points(seconds).await?;
+    Some(
+        points
+            .iter()
+            .map(|(time, s)| {
+                let (down, up) = (metric(s, Metric::PacketsDown), metric(s, Metric::PacketsUp));
+                let (tcp_down, tcp_up) = (
+                    metric(s, Metric::TcpPacketsDown),
+                    metric(s, Metric::TcpPacketsUp),
+                );
+                let (udp_down, udp_up) = (
+                    metric(s, Metric::UdpPacketsDown),
+                    metric(s, Metric::UdpPacketsUp),
+                );
+                let (icmp_down, icmp_up) = (
+                    metric(s, Metric::IcmpPacketsDown),
+                    metric(s, Metric::IcmpPacketsUp),
+                );
+                FullPacketData {
+                    time: *time,
+                    max_down: down.max as i64,
+                    max_up: up.max as i64,
+                    max_tcp_down: tcp_down.max as i64,
+                    max_tcp_up: tcp_up.max as i64,
+                    max_udp_down: udp_down.max as i64,
+                    max_udp_up: udp_up.max as i64,
+                    max_icmp_down: icmp_down.max as i64,
+                    max_icmp_up: icmp_up.max as i64,
+                    min_down: down.min as i64,
+                    min_up: up.min as i64,
+                    min_tcp_down: tcp_down.min as i64,
+                    min_tcp_up: tcp_up.min as i64,
+                    min_udp_down: udp_down.min as i64,
+                    min_udp_up: udp_up.min as i64,
+                    min_icmp_down: icmp_down.min as i64,
+                    min_icmp_up: icmp_up.min as i64,
+                    median_down: down.median as i64,
+                    median_up: up.median as i64,
+                    median_tcp_down: tcp_down.median as i64,
+                    median_tcp_up: tcp_up.median as i64,
+                    median_udp_down: udp_down.median as i64,
+                    median_udp_up: udp_up.median as i64,
+                    median_icmp_down: icmp_down.median as i64,
+                    median_icmp_up: icmp_up.median as i64,
+                }
+            })
+            .collect(),
+    )
+}
+
+pub(super) async fn percent_shaped(seconds: i32) -> Option<Vec<PercentShapedWeb>> {
+    let points = shaper_points(seconds).await?;
+    Some(
+        points
+            .iter()
+            .map(|(time, s)| {
+                let total = metric(s, Metric::BytesDown).median + metric(s, Metric::BytesUp).median;
+                let shaped = metric(s, Metric::ShapedBytesDown).median
+                    + metric(s, Metric::ShapedBytesUp).median;
+                let percent_shaped = if total > 0.0 {
+                    (shaped / total * 100.0).min(100.0) as f64
+                } else {
+                    0.0
+                };
+                PercentShapedWeb {
+                    time: *time,
+                    shaper_id: 0,
+                    percent_shaped,
+                }
+            })
+            .collect(),
+    )
+}
+
+pub(super) async fn flows(seconds: i32) -> Option<Vec<FlowCountViewWeb>> {
+    let points = shaper_points(seconds).await?;
+    Some(
+        points
+            .iter()
+            .map(|(time, s)| FlowCountViewWeb {
+                time: *time,
+                shaper_id: 0,
+                flow_count: metric(s, Metric::Flows).median as f64,
+            })
+            .collect(),
+    )
+}
+
+pub(super) async fn cake(seconds: i32) -> Option<Vec<CakeData>> {
+    let points = shaper_points(seconds).await?;
+    Some(
+        points
+            .iter()
+            .map(|(time, s)| {
+                let (marks_down, marks_up) = (
+                    metric(s, Metric::CakeMarksDown),
+                    metric(s, Metric::CakeMarksUp),
+                );
+                let (drops_down, drops_up) = (
+                    metric(s, Metric::CakeDropsDown),
+                    metric(s, Metric::CakeDropsUp),
+                );
+                CakeData {
+                    time: *time,
+                    max_marks_down: marks_down.max as i64,
+                    max_marks_up: marks_up.max as i64,
+                    min_marks_down: marks_down.min as i64,
+                    min_marks_up: marks_up.min as i64,
+                    median_marks_down: marks_down.median as i64,
+                    median_marks_up: marks_up.median as i64,
+                    max_drops_down: drops_down.max as i64,
+                    max_drops_up: drops_up.max as i64,
+                    min_drops_down: drops_down.min as i64,
+                    min_drops_up: drops_up.min as i64,
+                    median_drops_down: drops_down.median as i64,
+                    median_drops_up: drops_up.median as i64,
+                }
+            })
+            .collect(),
+    )
+}
+
+pub(super) async fn recent_medians() -> Option<Vec<RecentMedians>> {
+    let median_of = |history: &HistoryQuery| -> (i64, i64) {
+        let mut down = Vec::new();
+        let mut up = Vec::new();
+        for frame in history.frames.iter() {
+            for (_, sample) in frame.series.iter() {
+                down.push(metric(sample, Metric::BytesDown).median);
+                up.push(metric(sample, Metric::BytesUp).median);
+            }
+        }
+        (median(&mut down) as i64, median(&mut up) as i64)
+    };
+    let shaper = HistorySelection::Series(SeriesKey::Shaper);
+    let yesterday = query(shaper, 86_400, ALL_POINTS).await?;
+    let last_week = query(shaper, 7 * 86_400, ALL_POINTS).await?;
+    Some(vec![RecentMedians {
+        yesterday: median_of(&yesterday),
+        last_week: median_of(&last_week),
+    }])
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f32::total_cmp);
+    values[values.len() / 2]
+}
+
+pub(super) async fn rtt_histogram(seconds: i32) -> Option<Vec<ShaperRttHistogramEntry>> {
+    let history = query(HistorySelection::Circuits, seconds, ALL_POINTS).await?;
+    let mut buckets = vec![0; RTT_HISTOGRAM_BUCKETS];
+    for frame in history.frames.iter() {
+        for (_, sample) in frame.series.iter() {
+            if let Some(rtt) = sample.get(Metric::RttMs) {
+                let bucket = (rtt.median / RTT_HISTOGRAM_BUCKET_MS) as usize;
+                buckets[bucket.min(RTT_HISTOGRAM_BUCKETS - 1)] += 1;
+            }
+        }
+    }
+    Some(
+        buckets
+            .into_iter()
+            .map(|value| ShaperRttHistogramEntry { value })
+            .collect(),
+    )
+}
+
+/// A circuit's behaviour over the whole queried period.
+struct CircuitSummary {
+    circuit_id: String,
+    circuit_name: String,
+    /// Mean of the download medians, in Mbps
+    mbps_down: f64,
+    rtt: Option<f64>,
+    /// Fraction of downstream TCP packets that were retransmitted
+    rxmit: Option<f64>,
+}
+
+async fn circuit_summaries(seconds: i32) -> Option<Vec<CircuitSummary>> {
+    #[derive(Default)]
+    struct Totals {
+        bytes_down: f64,
+        rtt: Vec<f32>,
+        retransmits: f64,
+        tcp_packets: f64,
+    }
+
+    let history = query(HistorySelection::Circuits, seconds, ALL_POINTS).await?;
+    let mut totals: FxHashMap<i64, Totals> = FxHashMap::default();
+    for frame in history.frames.iter() {
+        for (key, sample) in frame.series.iter() {
+            let SeriesKey::Circuit(hash) = key else {
+                continue;
+            };
+            let entry = totals.entry(*hash).or_default();
+            entry.bytes_down += metric(sample, Metric::BytesDown).median as f64;
+            entry.retransmits += metric(sample, Metric::RetransmitsDown).median as f64;
+            entry.tcp_packets += metric(sample, Metric::TcpPacketsDown).median as f64;
+            if let Some(rtt) = sample.get(Metric::RttMs) {
+                entry.rtt.push(rtt.median);
+            }
+        }
+    }
+
+    // Slots without a frame were idle, so average over the whole window
+    let slots = (seconds.max(1) as u64).div_ceil(history.width.max(1)) as f64;
+    let shaped = SHAPED_DEVICES.load();
+    let mut names: FxHashMap<i64, (&str, &str)> = FxHashMap::default();
+    for device in shaped.devices.iter() {
+        names
+            .entry(device.circuit_hash)
+            .or_insert((&device.circuit_id, &device.circuit_name));
+    }
+    Some(
+        totals
+            .into_iter()
+            .filter_map(|(hash, mut totals)| {
+                let (circuit_id, circuit_name) = names.get(&hash)?;
+                Some(CircuitSummary {
+                    circuit_id: circuit_id.to_string(),
+                    circuit_name: circuit_name.to_string(),
+                    mbps_down: totals.bytes_down / slots * 8.0 / 1_000_000.0,
+                    rtt: (!totals.rtt.is_empty()).then(|| median(&mut totals.rtt) as f64),
+                    rxmit: (totals.tcp_packets > 0.0)
+                        .then(|| (totals.retransmits / totals.tcp_packets).min(1.0)),
+                })
+            })
+            .collect(),
+    )
+}
+
+fn worst_first(
+    mut circuits: Vec<CircuitSummary>,
+    key: fn(&CircuitSummary) -> Option<f64>,
+) -> Vec<CircuitSummary> {
+    circuits.retain(|c| key(c).is_some());
+    circuits.sort_by(|a, b| key(b).partial_cmp(&key(a)).unwrap_or(Ordering::Equal));
+    circuits.truncate(TOP_CIRCUITS);
+    circuits
+}
+
+fn shaper_name() -> String {
+    load_config()
+        .map(|config| config.node_name.clone())
+        .unwrap_or_default()
+}
+
+pub(super) async fn top_downloaders(seconds: i32) -> Option<Vec<Top10Circuit>> {
+    let circuits = worst_first(circuit_summaries(seconds).await?, |c| Some(c.mbps_down));
+    let shaper_name = shaper_name();
+    Some(
+        circuits
+            .into_iter()
+            .map(|c| Top10Circuit {
+                shaper_id: 0,
+                shaper_name: shaper_name.clone(),
+                circuit_hash: c.circuit_id,
+                circuit_name: c.circuit_name,
+                bytes_down: c.mbps_down,
+                rtt: c.rtt,
+                rxmit: c.rxmit,
+            })
+            .collect(),
+    )
+}
+
+pub(super) async fn worst_rtt(seconds: i32) -> Option<Vec<Worst10RttCircuit>> {
+    let circuits = worst_first(circuit_summaries(seconds).await?, |c| c.rtt);
+    let shaper_name = shaper_name();
+    Some(
+        circuits
+            .into_iter()
+            .map(|c| Worst10RttCircuit {
+                shaper_id: 0,
+                shaper_name: shaper_name.clone(),
+                circuit_hash: c.circuit_id,
+                circuit_name: c.circuit_name,
+                bytes_down: c.mbps_down,
+                rtt: c.rtt,
+                rxmit: c.rxmit,
+            })
+            .collect(),
+    )
+}
+
+pub(super) async fn worst_retransmits(seconds: i32) -> Option<Vec<Worst10RxmitCircuit>> {
+    let circuits = worst_first(circuit_summaries(seconds).await?, |c| c.rxmit);
+    let shaper_name = shaper_name();
+    Some(
+        circuits
+            .into_iter()
+            .map(|c| Worst10RxmitCircuit {
+                shaper_id: 0,
+                shaper_name: shaper_name.clone(),
+                circuit_hash: c.circuit_id,
+                circuit_name: c.circuit_name,
+                bytes_down: c.mbps_down,
+                rtt: c.rtt,
+                rxmit: c.rxmit,
+            })
+            .collect(),
+    )
+}
+
+async fn series_history(
+    key: SeriesKey,
+    seconds: i32,
+) -> Result<Vec<LocalHistoryPoint>, StatusCode> {
+    let history = query(HistorySelection::Series(key), seconds, MAX_POINTS)
+        .await
+        .ok_or(StatusCode::FORBIDDEN)?;
+    Ok(history
+        .frames
+        .iter()
+        .flat_map(|frame| {
+            frame.series.iter().map(|(_, s)| LocalHistoryPoint {
+                time: frame.time,
+                bytes_down: metric(s, Metric::BytesDown).into(),
+                bytes_up: metric(s, Metric::BytesUp).into(),
+                rtt_ms: s.get(Metric::RttMs).map(MinMaxMedian::from),
+                retransmits_down: metric(s, Metric::RetransmitsDown).into(),
+                retransmits_up: metric(s, Metric::RetransmitsUp).into(),
+                cake_marks_down: metric(s, Metric::CakeMarksDown).into(),
+                cake_marks_up: metric(s, Metric::CakeMarksUp).into(),
+                cake_drops_down: metric(s, Metric::CakeDropsDown).into(),
+                cake_drops_up: metric(s, Metric::CakeDropsUp).into(),
+            })
+        })
+        .collect())
+}
+
+/// History for one network.json node, from local history only.
+pub async fn site_history_data(
+    site: &str,
+    seconds: i32,
+) -> Result<Vec<LocalHistoryPoint>, StatusCode> {
+    series_history(SeriesKey::Site(hash_to_i64(site)), seconds).await
+}
+
+/// History for one circuit, from local history only.
+pub async fn circuit_history_data(
+    circuit_id: &str,
+    seconds: i32,
+) -> Result<Vec<LocalHistoryPoint>, StatusCode> {
+    let safe_id = circuit_id.to_lowercase().trim().to_string();
+    let circuit_hash = SHAPED_DEVICES
+        .load()
+        .devices
+        .iter()
+        .find(|d| d.circuit_id.to_lowercase().trim() == safe_id)
+        .map(|d| d.circuit_hash)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    series_history(SeriesKey::Circuit(circuit_hash), seconds).await
+}
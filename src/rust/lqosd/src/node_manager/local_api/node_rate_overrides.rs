@@ -1,6 +1,7 @@
+use crate::audit;
 use crate::node_manager::auth::LoginResult;
 use axum::http::StatusCode;
-use lqos_config::load_config;
+use lqos_config::{ApiTokenScope, AuditActor, AuditEntry, load_config};
 use lqos_overrides::{NetworkAdjustment, OverrideLayer, OverrideStore};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
@@ -64,9 +65,10 @@ pub fn get_node_rate_override_data(
 /// Save or replace the operator-owned rate override for a tree node.
 pub fn set_node_rate_override_data(
     login: LoginResult,
+    actor: &AuditActor,
     update: NodeRateOverrideUpdate,
 ) -> Result<NodeRateOverrideData, StatusCode> {
-    if login != LoginResult::Admin {
+    if !login.allows(ApiTokenScope::OverridesWrite) {
         return Err(StatusCode::FORBIDDEN);
     }
     validate_update_payload(&update)?;
@@ -86,15 +88,25 @@ pub fn set_node_rate_override_data(
 
     let mut overrides = OverrideStore::load_layer(OverrideLayer::Operator)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let before = overrides
+        .find_site_bandwidth_override(Some(&update.node_id), &update.node_name)
+        .cloned();
     let changed = overrides.set_site_bandwidth_override(
-        Some(update.node_id),
-        update.node_name,
+        Some(update.node_id.clone()),
+        update.node_name.clone(),
         update.download_bandwidth_mbps,
         update.upload_bandwidth_mbps,
     );
     if changed {
         OverrideStore::save_layer(OverrideLayer::Operator, &overrides)
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let after =
+            overrides.find_site_bandwidth_override(Some(&update.node_id), &update.node_name);
+        audit::record(
+            AuditEntry::new(actor.clone(), "set_node_rate_override")
+                .target(update.node_name)
+                .changes(before.as_ref(), after),
+        );
     }
 
     build_node_rate_override_data(login, query)
@@ -103,9 +115,10 @@ pub fn set_node_rate_override_data(
 /// Remove the operator-owned rate override for a tree node.
 pub fn clear_node_rate_override_data(
     login: LoginResult,
+    actor: &AuditActor,
     query: NodeRateOverrideQuery,
 ) -> Result<NodeRateOverrideData, StatusCode> {
-    if login != LoginResult::Admin {
+    if !login.allows(ApiTokenScope::OverridesWrite) {
         return Err(StatusCode::FORBIDDEN);
     }
     if let Some(reason) = edit_disabled_reason(login, &query) {
@@ -123,10 +136,18 @@ pub fn clear_node_rate_override_data(
 
     let mut overrides = OverrideStore::load_layer(OverrideLayer::Operator)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let before = overrides
+        .find_site_bandwidth_override(Some(node_id), &query.node_name)
+        .cloned();
     let removed = overrides.remove_site_bandwidth_override_count(Some(node_id), &query.node_name);
     if removed > 0 {
         OverrideStore::save_layer(OverrideLayer::Operator, &overrides)
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        audit::record(
+            AuditEntry::new(actor.clone(), "clear_node_rate_override")
+                .target(query.node_name.clone())
+                .changes(before.as_ref(), None::<&NetworkAdjustment>),
+        );
     }
 
     build_node_rate_override_data(login, query)
@@ -157,7 +178,7 @@ fn build_node_rate_override_data(
 
     let disabled_reason = edit_disabled_reason(login, &query);
     Ok(NodeRateOverrideData {
-        writable: login == LoginResult::Admin,
+        writable: login.allows(ApiTokenScope::OverridesWrite),
         can_edit: disabled_reason.is_none(),
         disabled_reason,
         has_override,
@@ -169,7 +190,7 @@ fn build_node_rate_override_data(
 }
 
 fn edit_disabled_reason(login: LoginResult, query: &NodeRateOverrideQuery) -> Option<String> {
-    if login != LoginResult::Admin {
+    if !login.allows(ApiTokenScope::OverridesWrite) {
         return Some("Only administrators can edit node rate overrides.".to_string());
     }
     let trimmed_name = query.node_name.trim();
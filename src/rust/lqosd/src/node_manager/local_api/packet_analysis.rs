@@ -1,10 +1,13 @@
+use crate::shaped_devices_tracker::SHAPED_DEVICES;
 use axum::body::Body;
 use axum::extract::Path;
-use axum::http::{HeaderMap, Request};
+use axum::http::{HeaderMap, Request, StatusCode};
 use axum::response::IntoResponse;
-use lqos_heimdall::n_second_pcap;
+use lqos_heimdall::{RingCaptureTarget, n_second_pcap};
+use lqos_utils::XdpIpAddress;
 use serde::Serialize;
 use std::net::IpAddr;
+use std::time::Duration;
 use tower_http::services::ServeFile;
 
 #[derive(Debug, Serialize, Clone)]
@@ -26,11 +29,123 @@ pub fn request_analysis_data(ip: &str) -> RequestAnalysisResult {
 }
 
 pub async fn pcap_dump(Path(id): Path<usize>, headers: HeaderMap) -> impl IntoResponse {
-    let filename = n_second_pcap(id).expect("Could not determine pcap filename");
+    let Some(filename) = n_second_pcap(id) else {
+        return (StatusCode::NOT_FOUND, "Unknown capture session").into_response();
+    };
     let mut req = Request::new(Body::empty());
     *req.headers_mut() = headers;
-    ServeFile::new(filename)
-        .try_call(req)
-        .await
-        .expect("ServeFile call failed")
+    match ServeFile::new(filename).try_call(req).await {
+        Ok(response) => response.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub enum RingCaptureStartResult {
+    Fail { reason: String },
+    Ok { id: usize },
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RingCaptureSummary {
+    pub id: usize,
+    pub circuit_id: Option<String>,
+    pub circuit_name: Option<String>,
+    pub ips: Vec<String>,
+    pub window_seconds: u64,
+    pub packets: usize,
+    pub started_unix: u64,
+}
+
+/// Starts a rolling capture of either a single IP address or every address
+/// on a circuit. If both are given, the IP is captured and labelled with the
+/// circuit's metadata.
+pub fn start_ring_capture_data(
+    circuit: Option<&str>,
+    ip: Option<&str>,
+    minutes: u64,
+) -> RingCaptureStartResult {
+    let mut target = RingCaptureTarget::default();
+    if let Some(circuit) = circuit {
+        let safe_id = circuit.to_lowercase().trim().to_string();
+        let shaped = SHAPED_DEVICES.load();
+        for device in shaped
+            .devices
+            .iter()
+            .filter(|d| d.circuit_id.to_lowercase().trim() == safe_id)
+        {
+            target.circuit_id = Some(device.circuit_id.clone());
+            target.circuit_name = Some(device.circuit_name.clone());
+            if ip.is_none() {
+                target.ips.extend(
+                    device
+                        .ipv4
+                        .iter()
+                        .map(|(ip, _)| XdpIpAddress::from(IpAddr::V4(*ip))),
+                );
+                target.ips.extend(
+                    device
+                        .ipv6
+                        .iter()
+                        .map(|(ip, _)| XdpIpAddress::from(IpAddr::V6(*ip))),
+                );
+            }
+        }
+        if target.circuit_id.is_none() {
+            return RingCaptureStartResult::Fail {
+                reason: format!("Unknown circuit: {circuit}"),
+            };
+        }
+    }
+    if let Some(ip) = ip {
+        match ip.parse::<IpAddr>() {
+            Ok(ip) => target.ips.push(ip.into()),
+            Err(_) => {
+                return RingCaptureStartResult::Fail {
+                    reason: format!("Invalid IP address: {ip}"),
+                };
+            }
+        }
+    }
+
+    // Heimdall clamps the window; don't let an absurd request overflow first.
+    let window = Duration::from_secs(minutes.saturating_mul(60));
+    match lqos_heimdall::start_ring_capture(target, window) {
+        Ok(id) => RingCaptureStartResult::Ok { id },
+        Err(e) => RingCaptureStartResult::Fail {
+            reason: e.to_string(),
+        },
+    }
+}
+
+pub fn stop_ring_capture_data(id: usize) -> bool {
+    lqos_heimdall::stop_ring_capture(id)
+}
+
+pub fn ring_captures_data() -> Vec<RingCaptureSummary> {
+    lqos_heimdall::ring_captures()
+        .into_iter()
+        .map(|ring| RingCaptureSummary {
+            id: ring.id,
+            circuit_id: ring.circuit_id,
+            circuit_name: ring.circuit_name,
+            ips: ring.ips,
+            window_seconds: ring.window_seconds,
+            packets: ring.packets,
+            started_unix: ring.started_unix,
+        })
+        .collect()
+}
+
+/// Freezes a rolling capture and downloads it as pcapng.
+pub async fn ring_capture_dump(Path(id): Path<usize>, headers: HeaderMap) -> impl IntoResponse {
+    let Some(filename) = lqos_heimdall::freeze_ring_capture(id) else {
+        return (StatusCode::NOT_FOUND, "Unknown ring capture").into_response();
+    };
+    let mut req = Request::new(Body::empty());
+    *req.headers_mut() = headers;
+    match ServeFile::new(filename).try_call(req).await {
+        Ok(response) => response.into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
 }
@@ -1,16 +1,31 @@
+use crate::audit;
 use crate::node_manager::auth::LoginResult;
+use lqos_config::{ApiTokenScope, AuditActor, AuditEntry};
 use tokio::task::spawn_blocking;
 use tracing::info;
 
-pub async fn reload_libreqos_with_login(login: LoginResult) -> String {
-    info!("Reloading LibreQoS");
-    if let LoginResult::Admin = login {
-        let Ok(result) = spawn_blocking(lqos_config::load_libreqos).await else {
-            return "Failed to spawn blocking thread".to_string();
-        };
-        //println!("{:?}", result);
-        result.unwrap_or_else(|_| "Unable to reload LibreQoS".to_string())
+pub async fn reload_libreqos_with_login(login: LoginResult, actor: &AuditActor) -> String {
+    if login.allows(ApiTokenScope::Reload) {
+        reload_libreqos(actor)
+            .await
+            .unwrap_or_else(|message| message)
     } else {
-        "You must be an admin to reload LibreQoS".to_string()
+        "You must be an admin or use a reload-scoped API token to reload LibreQoS".to_string()
+    }
+}
+
+/// Reloads LibreQoS on behalf of `actor`. Callers check that they may.
+pub async fn reload_libreqos(actor: &AuditActor) -> Result<String, String> {
+    info!("Reloading LibreQoS");
+    let Ok(result) = spawn_blocking(lqos_config::load_libreqos).await else {
+        return Err("Failed to spawn blocking thread".to_string());
+    };
+    //println!("{:?}", result);
+    match result {
+        Ok(message) => {
+            audit::record(AuditEntry::new(actor.clone(), "reload_libreqos"));
+            Ok(message)
+        }
+        Err(_) => Err("Unable to reload LibreQoS".to_string()),
     }
 }
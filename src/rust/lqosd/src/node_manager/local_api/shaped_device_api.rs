@@ -4,3 +4,13 @@ use lqos_config::ShapedDevice;
 pub fn all_shaped_devices_data() -> Vec<ShapedDevice> {
     SHAPED_DEVICES.load().devices.clone()
 }
+
+pub fn shaped_device_data(device_id: &str) -> Option<ShapedDevice> {
+    let wanted = device_id.trim();
+    SHAPED_DEVICES
+        .load()
+        .devices
+        .iter()
+        .find(|device| device.device_id == wanted)
+        .cloned()
+}
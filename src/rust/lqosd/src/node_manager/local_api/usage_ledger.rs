@@ -0,0 +1,65 @@
+//! Per-circuit data usage for the UI, and CSV export of billing periods.
+
+use crate::usage_quotas::{UsageLedgerRow, usage_csv, usage_rows};
+use axum::extract::Query;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use lqos_config::QuotaPolicy;
+use serde::{Deserialize, Serialize};
+
+/// Current billing period usage for every circuit.
+#[derive(Clone, Debug, Serialize)]
+pub struct UsageLedgerData {
+    /// Whether usage is being accounted at all.
+    pub enabled: bool,
+    /// Configured quota policies, in evaluation order.
+    pub policies: Vec<QuotaPolicy>,
+    pub rows: Vec<UsageLedgerRow>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UsageExportQuery {
+    /// `YYYY-MM`; only periods starting in this month are exported.
+    pub month: Option<String>,
+}
+
+pub fn get_usage_ledger_data() -> UsageLedgerData {
+    let quotas = lqos_config::load_config()
+        .map(|config| config.usage_quotas.clone())
+        .unwrap_or_default();
+    UsageLedgerData {
+        enabled: quotas.enabled,
+        policies: quotas.policies,
+        rows: usage_rows(false),
+    }
+}
+
+/// Accepts `YYYY-MM` only; anything else exports every period.
+fn month_filter(month: Option<&str>) -> Option<&str> {
+    month.filter(|month| {
+        month.len() == 7
+            && month
+                .char_indices()
+                .all(|(i, c)| if i == 4 { c == '-' } else { c.is_ascii_digit() })
+    })
+}
+
+pub fn usage_ledger_csv_data(month: Option<&str>) -> String {
+    usage_csv(month_filter(month))
+}
+
+/// Downloads every recorded billing period as CSV.
+pub async fn usage_ledger_export(Query(query): Query<UsageExportQuery>) -> Response {
+    let filename = match month_filter(query.month.as_deref()) {
+        Some(month) => format!("attachment; filename=\"usage-{month}.csv\""),
+        None => "attachment; filename=\"usage.csv\"".to_string(),
+    };
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv".to_string()),
+            (header::CONTENT_DISPOSITION, filename),
+        ],
+        usage_ledger_csv_data(query.month.as_deref()),
+    )
+        .into_response()
+}
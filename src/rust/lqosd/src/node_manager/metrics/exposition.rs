@@ -0,0 +1,203 @@
+//! Text encoder for the Prometheus exposition format (0.0.4) and
+//! OpenMetrics 1.0.
+//!
+//! The two formats differ only in a few places that matter here: OpenMetrics
+//! names counter families without the `_total` suffix (samples keep it) and
+//! requires a terminating `# EOF` line.
+
+use std::fmt::Write;
+
+pub(crate) const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+pub(crate) const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Prometheus,
+    OpenMetrics,
+}
+
+impl Format {
+    /// Picks the format requested by a scraper's `Accept` header.
+    pub(crate) fn from_accept(accept: Option<&str>) -> Self {
+        match accept {
+            Some(accept) if accept.contains("application/openmetrics-text") => Self::OpenMetrics,
+            _ => Self::Prometheus,
+        }
+    }
+
+    pub(crate) fn content_type(self) -> &'static str {
+        match self {
+            Self::Prometheus => PROMETHEUS_CONTENT_TYPE,
+            Self::OpenMetrics => OPENMETRICS_CONTENT_TYPE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+pub(crate) struct MetricsWriter {
+    format: Format,
+    out: String,
+    current_kind: MetricKind,
+    current_name: String,
+}
+
+impl MetricsWriter {
+    pub(crate) fn new(format: Format) -> Self {
+        Self {
+            format,
+            out: String::new(),
+            current_kind: MetricKind::Gauge,
+            current_name: String::new(),
+        }
+    }
+
+    /// Starts a metric family. Counter names are given without the `_total`
+    /// suffix; it is added where each format expects it.
+    pub(crate) fn family(&mut self, name: &str, kind: MetricKind, help: &str) {
+        let exposed_name = match (kind, self.format) {
+            (MetricKind::Counter, Format::Prometheus) => format!("{name}_total"),
+            _ => name.to_string(),
+        };
+        let kind_name = match kind {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        };
+        let _ = writeln!(self.out, "# HELP {exposed_name} {}", escape_help(help));
+        let _ = writeln!(self.out, "# TYPE {exposed_name} {kind_name}");
+        self.current_kind = kind;
+        self.current_name = name.to_string();
+    }
+
+    /// Writes one sample for the family most recently started.
+    pub(crate) fn sample(&mut self, labels: &[(&str, &str)], value: f64) {
+        self.out.push_str(&self.current_name);
+        if self.current_kind == MetricKind::Counter {
+            self.out.push_str("_total");
+        }
+        if !labels.is_empty() {
+            self.out.push('{');
+            for (i, (key, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.out.push(',');
+                }
+                self.out.push_str(key);
+                self.out.push_str("=\"");
+                escape_label_into(&mut self.out, value);
+                self.out.push('"');
+            }
+            self.out.push('}');
+        }
+        self.out.push(' ');
+        write_value(&mut self.out, value);
+        self.out.push('\n');
+    }
+
+    /// Convenience for a family with a single unlabelled sample.
+    pub(crate) fn single(&mut self, name: &str, kind: MetricKind, help: &str, value: f64) {
+        self.family(name, kind, help);
+        self.sample(&[], value);
+    }
+
+    pub(crate) fn finish(mut self) -> String {
+        if self.format == Format::OpenMetrics {
+            self.out.push_str("# EOF\n");
+        }
+        self.out
+    }
+}
+
+fn write_value(out: &mut String, value: f64) {
+    if value.is_nan() {
+        out.push_str("NaN");
+    } else if value == f64::INFINITY {
+        out.push_str("+Inf");
+    } else if value == f64::NEG_INFINITY {
+        out.push_str("-Inf");
+    } else {
+        let _ = write!(out, "{value}");
+    }
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label_into(out: &mut String, raw: &str) {
+    for c in raw.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_follow_each_format() {
+        let mut prom = MetricsWriter::new(Format::Prometheus);
+        prom.single(
+            "lqos_bus_requests",
+            MetricKind::Counter,
+            "Bus requests.",
+            5.0,
+        );
+        assert_eq!(
+            prom.finish(),
+            "# HELP lqos_bus_requests_total Bus requests.\n\
+             # TYPE lqos_bus_requests_total counter\n\
+             lqos_bus_requests_total 5\n"
+        );
+
+        let mut om = MetricsWriter::new(Format::OpenMetrics);
+        om.single(
+            "lqos_bus_requests",
+            MetricKind::Counter,
+            "Bus requests.",
+            5.0,
+        );
+        assert_eq!(
+            om.finish(),
+            "# HELP lqos_bus_requests Bus requests.\n\
+             # TYPE lqos_bus_requests counter\n\
+             lqos_bus_requests_total 5\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        let mut writer = MetricsWriter::new(Format::Prometheus);
+        writer.family("lqos_site_rtt_seconds", MetricKind::Gauge, "RTT.");
+        writer.sample(&[("site", "Tower \"A\"\\1\nB")], 0.025);
+        writer.sample(&[("site", "Idle")], f64::NAN);
+        let out = writer.finish();
+        assert!(out.contains("lqos_site_rtt_seconds{site=\"Tower \\\"A\\\"\\\\1\\nB\"} 0.025\n"));
+        assert!(out.contains("lqos_site_rtt_seconds{site=\"Idle\"} NaN\n"));
+    }
+
+    #[test]
+    fn negotiates_format_from_accept_header() {
+        assert_eq!(Format::from_accept(None), Format::Prometheus);
+        assert_eq!(
+            Format::from_accept(Some("text/plain;version=0.0.4;q=0.5,*/*;q=0.1")),
+            Format::Prometheus
+        );
+        assert_eq!(
+            Format::from_accept(Some(
+                "application/openmetrics-text;version=1.0.0,text/plain;q=0.5"
+            )),
+            Format::OpenMetrics
+        );
+    }
+}
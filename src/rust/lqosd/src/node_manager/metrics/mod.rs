@@ -0,0 +1,545 @@
+//! Prometheus/OpenMetrics scrape endpoint (`/metrics`).
+//!
+//! Exposes shaper-wide counters, per-site and per-circuit gauges, Bakery
+//! status and TreeGuard/StormGuard state. Per-site and per-circuit series are
+//! capped by `[prometheus]` in `/etc/lqos.conf`: only the busiest entities are
+//! exported, and the number left out is reported so large networks don't
+//! silently lose data.
+
+mod exposition;
+
+use crate::node_manager::auth::login_from_bearer;
+use crate::shaped_devices_tracker::NETWORK_JSON;
+use crate::shaped_devices_tracker::circuit_live::fresh_circuit_live_snapshot;
+use crate::stats::{BUS_REQUESTS, FLOWS_TRACKED, HIGH_WATERMARK, TIME_TO_POLL_HOSTS};
+use crate::throughput_tracker::THROUGHPUT_TRACKER;
+use crate::treeguard::actor::cached_status_snapshot;
+use axum::http::header::{ACCEPT, CONTENT_TYPE, WWW_AUTHENTICATE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use exposition::{Format, MetricKind, MetricsWriter};
+use lqos_bakery::BakeryMode;
+use lqos_config::{ApiTokenScope, PrometheusConfig, load_config};
+use lqos_utils::rtt::{FlowbeeEffectiveDirection, RttBucket};
+use lqos_utils::units::DownUpOrder;
+use std::sync::atomic::Ordering;
+
+const DIRECTIONS: [&str; 2] = ["down", "up"];
+
+/// Answers a Prometheus scrape. Returns 404 unless `[prometheus] enabled`,
+/// and 401 without a valid API token when `[prometheus] require_token`.
+pub(crate) async fn metrics_page(headers: HeaderMap) -> Response {
+    let Ok(config) = load_config() else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    if !config.prometheus.enabled {
+        return (StatusCode::NOT_FOUND, "Prometheus metrics are disabled").into_response();
+    }
+    if config.prometheus.require_token
+        && !login_from_bearer(&headers).allows(ApiTokenScope::ReadOnly)
+    {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(WWW_AUTHENTICATE, "Bearer")],
+            "An API token is required",
+        )
+            .into_response();
+    }
+    let format = Format::from_accept(headers.get(ACCEPT).and_then(|v| v.to_str().ok()));
+    let body = render(format, &config.prometheus);
+    ([(CONTENT_TYPE, format.content_type())], body).into_response()
+}
+
+fn render(format: Format, limits: &PrometheusConfig) -> String {
+    let mut writer = MetricsWriter::new(format);
+    write_shaper_metrics(&mut writer);
+    let (sites, omitted_sites) = busiest(site_rows(), limits.max_site_series, |s| {
+        s.bytes_per_second.sum()
+    });
+    let (circuits, omitted_circuits) = busiest(circuit_rows(), limits.max_circuit_series, |c| {
+        c.bytes_per_second.sum()
+    });
+    write_site_metrics(&mut writer, &sites);
+    write_circuit_metrics(&mut writer, &circuits);
+    writer.family(
+        "lqos_metrics_omitted_entities",
+        MetricKind::Gauge,
+        "Entities left out of this scrape by the configured series limits.",
+    );
+    writer.sample(&[("kind", "site")], omitted_sites as f64);
+    writer.sample(&[("kind", "circuit")], omitted_circuits as f64);
+    write_bakery_metrics(&mut writer);
+    write_treeguard_metrics(&mut writer);
+    write_stormguard_metrics(&mut writer);
+    writer.finish()
+}
+
+/// Keeps the `limit` entries with the highest weight, returning them and the
+/// number of entries dropped.
+fn busiest<T>(mut items: Vec<T>, limit: usize, weight: impl Fn(&T) -> u64) -> (Vec<T>, usize) {
+    if items.len() <= limit {
+        return (items, 0);
+    }
+    items.sort_by_key(|item| std::cmp::Reverse(weight(item)));
+    let omitted = items.len() - limit;
+    items.truncate(limit);
+    (items, omitted)
+}
+
+fn write_down_up(writer: &mut MetricsWriter, labels: &[(&str, &str)], values: DownUpOrder<f64>) {
+    let mut with_direction = labels.to_vec();
+    with_direction.push(("direction", DIRECTIONS[0]));
+    writer.sample(&with_direction, values.down);
+    if let Some(last) = with_direction.last_mut() {
+        last.1 = DIRECTIONS[1];
+    }
+    writer.sample(&with_direction, values.up);
+}
+
+fn write_shaper_metrics(writer: &mut MetricsWriter) {
+    writer.single(
+        "lqos_bus_requests",
+        MetricKind::Counter,
+        "Requests handled by the lqosd bus.",
+        BUS_REQUESTS.load(Ordering::Relaxed) as f64,
+    );
+    writer.single(
+        "lqos_flows_tracked",
+        MetricKind::Gauge,
+        "Flows currently tracked by the flow tracker.",
+        FLOWS_TRACKED.load(Ordering::Relaxed) as f64,
+    );
+    writer.single(
+        "lqos_host_poll_duration_seconds",
+        MetricKind::Gauge,
+        "Time taken by the last throughput polling cycle.",
+        TIME_TO_POLL_HOSTS.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+    );
+
+    let bits = THROUGHPUT_TRACKER.bits_per_second();
+    let shaped_bits = THROUGHPUT_TRACKER.shaped_bits_per_second();
+    let packets = THROUGHPUT_TRACKER.packets_per_second();
+    let high_watermark = HIGH_WATERMARK.as_down_up();
+    writer.family(
+        "lqos_throughput_bits_per_second",
+        MetricKind::Gauge,
+        "Current throughput across all traffic.",
+    );
+    write_down_up(writer, &[], to_f64(bits));
+    writer.family(
+        "lqos_shaped_throughput_bits_per_second",
+        MetricKind::Gauge,
+        "Current throughput belonging to shaped devices.",
+    );
+    write_down_up(writer, &[], to_f64(shaped_bits));
+    writer.family(
+        "lqos_packets_per_second",
+        MetricKind::Gauge,
+        "Current packet rate across all traffic.",
+    );
+    write_down_up(writer, &[], to_f64(packets));
+    writer.family(
+        "lqos_throughput_high_watermark_bits_per_second",
+        MetricKind::Gauge,
+        "Highest throughput observed since lqosd started.",
+    );
+    write_down_up(writer, &[], to_f64(high_watermark));
+}
+
+fn to_f64(values: DownUpOrder<u64>) -> DownUpOrder<f64> {
+    DownUpOrder::new(values.down as f64, values.up as f64)
+}
+
+struct SiteRow {
+    name: String,
+    node_type: String,
+    max_throughput_mbps: (f64, f64),
+    bytes_per_second: DownUpOrder<u64>,
+    tcp_retransmits: DownUpOrder<u64>,
+    drops: DownUpOrder<u64>,
+    marks: DownUpOrder<u64>,
+    rtt_p50_seconds: DownUpOrder<f64>,
+}
+
+/// Copies the per-site figures out of `network.json`, skipping the synthetic
+/// root node (its totals are exported as the shaper-wide series).
+fn site_rows() -> Vec<SiteRow> {
+    let net_json = NETWORK_JSON.read();
+    net_json
+        .get_nodes_when_ready()
+        .iter()
+        .skip(1)
+        .map(|node| {
+            let rtt = |direction| {
+                node.rtt_buffer
+                    .percentile(RttBucket::Current, direction, 50)
+                    .map(|rtt| rtt.as_seconds())
+                    .unwrap_or(f64::NAN)
+            };
+            SiteRow {
+                name: node.name.clone(),
+                node_type: node.node_type.clone().unwrap_or_default(),
+                max_throughput_mbps: node.max_throughput,
+                bytes_per_second: node.current_throughput,
+                tcp_retransmits: node.current_tcp_retransmits,
+                drops: node.current_drops,
+                marks: node.current_marks,
+                rtt_p50_seconds: DownUpOrder::new(
+                    rtt(FlowbeeEffectiveDirection::Download),
+                    rtt(FlowbeeEffectiveDirection::Upload),
+                ),
+            }
+        })
+        .collect()
+}
+
+fn write_site_metrics(writer: &mut MetricsWriter, sites: &[SiteRow]) {
+    type SiteValue = fn(&SiteRow) -> DownUpOrder<f64>;
+    let families: [(&str, &str, SiteValue); 6] = [
+        (
+            "lqos_site_throughput_bytes_per_second",
+            "Current throughput through a site.",
+            |s| to_f64(s.bytes_per_second),
+        ),
+        (
+            "lqos_site_max_throughput_bits_per_second",
+            "Configured capacity of a site.",
+            |s| {
+                DownUpOrder::new(
+                    s.max_throughput_mbps.0 * 1_000_000.0,
+                    s.max_throughput_mbps.1 * 1_000_000.0,
+                )
+            },
+        ),
+        (
+            "lqos_site_rtt_p50_seconds",
+            "Median round-trip time observed at a site.",
+            |s| s.rtt_p50_seconds,
+        ),
+        (
+            "lqos_site_tcp_retransmits",
+            "TCP retransmits observed at a site during the last cycle.",
+            |s| to_f64(s.tcp_retransmits),
+        ),
+        (
+            "lqos_site_cake_drops",
+            "CAKE drops at a site during the last cycle.",
+            |s| to_f64(s.drops),
+        ),
+        (
+            "lqos_site_cake_marks",
+            "CAKE ECN marks at a site during the last cycle.",
+            |s| to_f64(s.marks),
+        ),
+    ];
+    for (name, help, value) in families {
+        writer.family(name, MetricKind::Gauge, help);
+        for site in sites {
+            write_down_up(
+                writer,
+                &[("site", &site.name), ("type", &site.node_type)],
+                value(site),
+            );
+        }
+    }
+}
+
+struct CircuitRow {
+    circuit_id: String,
+    circuit_name: String,
+    parent_node: String,
+    plan_mbps: DownUpOrder<f32>,
+    bytes_per_second: DownUpOrder<u64>,
+    rtt_p50_seconds: DownUpOrder<f64>,
+    tcp_retransmits: DownUpOrder<u64>,
+    tcp_retransmit_ratio: DownUpOrder<f64>,
+}
+
+fn circuit_rows() -> Vec<CircuitRow> {
+    let snapshot = fresh_circuit_live_snapshot();
+    let nanos_to_seconds = |nanos: Option<u64>| {
+        nanos
+            .map(|n| n as f64 / 1_000_000_000.0)
+            .unwrap_or(f64::NAN)
+    };
+    snapshot
+        .by_circuit_id
+        .values()
+        .map(|circuit| {
+            let retransmits = circuit.tcp_retransmit_sample;
+            CircuitRow {
+                circuit_id: circuit.circuit_id.clone(),
+                circuit_name: circuit.circuit_name.clone(),
+                parent_node: circuit.parent_node.clone(),
+                plan_mbps: circuit.plan_mbps,
+                bytes_per_second: circuit.bytes_per_second,
+                rtt_p50_seconds: DownUpOrder::new(
+                    nanos_to_seconds(circuit.rtt_current_p50_nanos.down),
+                    nanos_to_seconds(circuit.rtt_current_p50_nanos.up),
+                ),
+                tcp_retransmits: DownUpOrder::new(
+                    retransmits.down.retransmits.get(),
+                    retransmits.up.retransmits.get(),
+                ),
+                tcp_retransmit_ratio: DownUpOrder::new(
+                    retransmits.down.fraction().map_or(f64::NAN, |f| f.get()),
+                    retransmits.up.fraction().map_or(f64::NAN, |f| f.get()),
+                ),
+            }
+        })
+        .collect()
+}
+
+fn write_circuit_metrics(writer: &mut MetricsWriter, circuits: &[CircuitRow]) {
+    type CircuitValue = fn(&CircuitRow) -> DownUpOrder<f64>;
+    let families: [(&str, &str, CircuitValue); 5] = [
+        (
+            "lqos_circuit_throughput_bytes_per_second",
+            "Current throughput for a circuit.",
+            |c| to_f64(c.bytes_per_second),
+        ),
+        (
+            "lqos_circuit_plan_bits_per_second",
+            "Maximum plan rate for a circuit.",
+            |c| {
+                DownUpOrder::new(
+                    f64::from(c.plan_mbps.down) * 1_000_000.0,
+                    f64::from(c.plan_mbps.up) * 1_000_000.0,
+                )
+            },
+        ),
+        (
+            "lqos_circuit_rtt_p50_seconds",
+            "Median round-trip time for a circuit.",
+            |c| c.rtt_p50_seconds,
+        ),
+        (
+            "lqos_circuit_tcp_retransmits",
+            "TCP retransmits for a circuit during the last sample window.",
+            |c| to_f64(c.tcp_retransmits),
+        ),
+        (
+            "lqos_circuit_tcp_retransmit_ratio",
+            "Fraction of TCP packets retransmitted for a circuit.",
+            |c| c.tcp_retransmit_ratio,
+        ),
+    ];
+    for (name, help, value) in families {
+        writer.family(name, MetricKind::Gauge, help);
+        for circuit in circuits {
+            write_down_up(
+                writer,
+                &[
+                    ("circuit_id", &circuit.circuit_id),
+                    ("circuit_name", &circuit.circuit_name),
+                    ("parent_node", &circuit.parent_node),
+                ],
+                value(circuit),
+            );
+        }
+    }
+}
+
+fn write_bakery_metrics(writer: &mut MetricsWriter) {
+    let status = lqos_bakery::bakery_status_snapshot();
+    writer.single(
+        "lqos_bakery_active_circuits",
+        MetricKind::Gauge,
+        "Circuits currently managed by Bakery.",
+        status.active_circuits as f64,
+    );
+    writer.family(
+        "lqos_bakery_mode",
+        MetricKind::Gauge,
+        "Current Bakery mode (1 for the active mode).",
+    );
+    for (mode, label) in [
+        (BakeryMode::Idle, "idle"),
+        (BakeryMode::ApplyingFullReload, "applying_full_reload"),
+        (BakeryMode::ApplyingLiveChange, "applying_live_change"),
+    ] {
+        let active = if status.mode == mode { 1.0 } else { 0.0 };
+        writer.sample(&[("mode", label)], active);
+    }
+    if let Some(unix) = status.last_success_unix {
+        writer.single(
+            "lqos_bakery_last_success_timestamp_seconds",
+            MetricKind::Gauge,
+            "Unix time of the last successful Bakery apply.",
+            unix as f64,
+        );
+    }
+    if let Some(unix) = status.last_failure_unix {
+        writer.single(
+            "lqos_bakery_last_failure_timestamp_seconds",
+            MetricKind::Gauge,
+            "Unix time of the last failed Bakery apply.",
+            unix as f64,
+        );
+    }
+    writer.single(
+        "lqos_bakery_last_build_duration_seconds",
+        MetricKind::Gauge,
+        "Time spent building the last Bakery apply.",
+        status.last_build_duration_ms as f64 / 1000.0,
+    );
+    writer.single(
+        "lqos_bakery_last_apply_duration_seconds",
+        MetricKind::Gauge,
+        "Time spent running the last Bakery apply through tc.",
+        status.last_apply_duration_ms as f64 / 1000.0,
+    );
+    writer.single(
+        "lqos_bakery_last_tc_commands",
+        MetricKind::Gauge,
+        "Number of tc commands in the last Bakery apply.",
+        status.last_total_tc_commands as f64,
+    );
+    let ops = &status.runtime_operations;
+    writer.family(
+        "lqos_bakery_runtime_operations",
+        MetricKind::Gauge,
+        "Bakery runtime node operations by state.",
+    );
+    for (state, count) in [
+        ("submitted", ops.submitted_count),
+        ("deferred", ops.deferred_count),
+        ("applying", ops.applying_count),
+        ("awaiting_cleanup", ops.awaiting_cleanup_count),
+        ("failed", ops.failed_count),
+        ("dirty", ops.dirty_count),
+    ] {
+        writer.sample(&[("state", state)], count as f64);
+    }
+    writer.family(
+        "lqos_bakery_live_qdiscs",
+        MetricKind::Gauge,
+        "Live qdisc handles observed per interface.",
+    );
+    for interface in &status.live_capacity_interfaces {
+        writer.sample(
+            &[("interface", &interface.name)],
+            interface.live_qdiscs as f64,
+        );
+    }
+    writer.single(
+        "lqos_bakery_qdisc_safe_budget",
+        MetricKind::Gauge,
+        "Safe per-interface qdisc budget.",
+        status.live_capacity_safe_budget as f64,
+    );
+}
+
+fn write_treeguard_metrics(writer: &mut MetricsWriter) {
+    let Some(status) = cached_status_snapshot() else {
+        return;
+    };
+    let flag = |b: bool| if b { 1.0 } else { 0.0 };
+    writer.single(
+        "lqos_treeguard_enabled",
+        MetricKind::Gauge,
+        "Whether TreeGuard is enabled.",
+        flag(status.enabled),
+    );
+    writer.single(
+        "lqos_treeguard_dry_run",
+        MetricKind::Gauge,
+        "Whether TreeGuard is in dry-run mode.",
+        flag(status.dry_run),
+    );
+    writer.single(
+        "lqos_treeguard_paused",
+        MetricKind::Gauge,
+        "Whether TreeGuard is paused for a Bakery reload.",
+        flag(status.paused_for_bakery_reload),
+    );
+    writer.family(
+        "lqos_treeguard_nodes",
+        MetricKind::Gauge,
+        "Nodes seen by TreeGuard, by state.",
+    );
+    writer.sample(&[("state", "total")], status.total_nodes as f64);
+    writer.sample(&[("state", "managed")], status.managed_nodes as f64);
+    writer.sample(&[("state", "virtualized")], status.virtualized_nodes as f64);
+    writer.family(
+        "lqos_treeguard_circuits",
+        MetricKind::Gauge,
+        "Circuits seen by TreeGuard, by state.",
+    );
+    writer.sample(&[("state", "total")], status.total_circuits as f64);
+    writer.sample(&[("state", "managed")], status.managed_circuits as f64);
+    writer.sample(&[("state", "cake")], status.cake_circuits as f64);
+    writer.sample(&[("state", "fq_codel")], status.fq_codel_circuits as f64);
+    writer.sample(&[("state", "mixed_sqm")], status.mixed_sqm_circuits as f64);
+    writer.single(
+        "lqos_treeguard_warnings",
+        MetricKind::Gauge,
+        "Warnings currently reported by TreeGuard.",
+        status.warnings.len() as f64,
+    );
+}
+
+fn write_stormguard_metrics(writer: &mut MetricsWriter) {
+    let stats = lqos_stormguard::STORMGUARD_STATS.lock().clone();
+    writer.family(
+        "lqos_stormguard_rate_bits_per_second",
+        MetricKind::Gauge,
+        "Current queue rate chosen by StormGuard for a site.",
+    );
+    for (site, down_mbps, up_mbps) in &stats {
+        write_down_up(
+            writer,
+            &[("site", site)],
+            DownUpOrder::new(
+                *down_mbps as f64 * 1_000_000.0,
+                *up_mbps as f64 * 1_000_000.0,
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busiest_keeps_highest_weights_and_counts_the_rest() {
+        let (kept, omitted) = busiest(vec![5u64, 50, 1, 20], 2, |v| *v);
+        assert_eq!(kept, vec![50, 20]);
+        assert_eq!(omitted, 2);
+
+        let (kept, omitted) = busiest(vec![5u64, 50], 10, |v| *v);
+        assert_eq!(kept, vec![5, 50]);
+        assert_eq!(omitted, 0);
+
+        let (kept, omitted) = busiest(vec![5u64, 50], 0, |v| *v);
+        assert!(kept.is_empty());
+        assert_eq!(omitted, 2);
+    }
+
+    #[test]
+    fn site_series_carry_labels_and_directions() {
+        let sites = vec![SiteRow {
+            name: "Tower 1".to_string(),
+            node_type: "site".to_string(),
+            max_throughput_mbps: (100.0, 20.0),
+            bytes_per_second: DownUpOrder::new(1000, 200),
+            tcp_retransmits: DownUpOrder::new(3, 1),
+            drops: DownUpOrder::zeroed(),
+            marks: DownUpOrder::zeroed(),
+            rtt_p50_seconds: DownUpOrder::new(0.012, f64::NAN),
+        }];
+        let mut writer = MetricsWriter::new(Format::Prometheus);
+        write_site_metrics(&mut writer, &sites);
+        let out = writer.finish();
+        assert!(out.contains(
+            "lqos_site_throughput_bytes_per_second{site=\"Tower 1\",type=\"site\",direction=\"down\"} 1000\n"
+        ));
+        assert!(out.contains(
+            "lqos_site_max_throughput_bits_per_second{site=\"Tower 1\",type=\"site\",direction=\"up\"} 20000000\n"
+        ));
+        assert!(out.contains(
+            "lqos_site_rtt_p50_seconds{site=\"Tower 1\",type=\"site\",direction=\"up\"} NaN\n"
+        ));
+    }
+}
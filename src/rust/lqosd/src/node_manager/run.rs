@@ -1,5 +1,7 @@
 use crate::lts2_sys::control_channel::ControlChannelCommand;
+use crate::node_manager::api_v1::api_v1;
 use crate::node_manager::local_api::local_api;
+use crate::node_manager::metrics::metrics_page;
 use crate::node_manager::shaper_queries_actor::shaper_queries_actor;
 use crate::node_manager::{
     auth,
@@ -57,6 +59,7 @@ pub async fn spawn_webserver(
         .route("/doLogin", post(auth::try_login))
         .route("/firstLogin", post(auth::first_user))
         .route("/health", get(health_check))
+        .route("/metrics", get(metrics_page))
         // Backwards compatible aliases for historical misspellings.
         .route_service(
             "/config_spylnx.js",
@@ -78,6 +81,7 @@ pub async fn spawn_webserver(
         .nest("/vendor", vendor_route()?) // Serve /vendor as purely static
         .nest("/", static_routes()?)
         .nest("/local-api", local_api(shaper_tx))
+        .nest("/api/v1", api_v1())
         .fallback_service(ServeDir::new(static_path))
         .layer(CorsLayer::very_permissive());
 
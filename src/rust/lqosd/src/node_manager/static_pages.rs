@@ -34,6 +34,7 @@ pub(super) fn static_routes() -> Result<Router> {
         "tree.html",
         "help.html",
         "unknown_ips.html",
+        "usage.html",
         "configuration.html",
         "circuit.html",
         "ethernet_caps.html",
@@ -75,6 +76,8 @@ pub(super) fn static_routes() -> Result<Router> {
         "config_network.html",
         "config_devices.html",
         "config_users.html",
+        "config_audit.html",
+        "config_schedules.html",
         "config_wispgate.html",
         "config_stormguard.html",
         "config_treeguard.html",
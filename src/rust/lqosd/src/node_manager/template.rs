@@ -104,7 +104,7 @@ pub async fn apply_templates(
 
     let res = next.run(req).await;
     //let mut lts_script = "<script>window.hasLts = false;</script>";
-    let mut script_has_lts = false;
+    let script_has_lts;
     let mut script_has_insight = false;
     let new_version = crate::version_checks::new_version_available();
 
@@ -120,7 +120,10 @@ pub async fn apply_templates(
             LtsStatus::AlwaysFree | LtsStatus::FreeTrial | LtsStatus::SelfHosted | LtsStatus::Full
         );
         match lts_status {
-            LtsStatus::Invalid | LtsStatus::NotChecked => {}
+            LtsStatus::Invalid | LtsStatus::NotChecked => {
+                // Long-term charts are still available from local history
+                script_has_lts = crate::local_history::history_enabled();
+            }
             _ => {
                 // Link to it
                 trial_link = INSIGHT_LINK_ACTIVE.to_string();
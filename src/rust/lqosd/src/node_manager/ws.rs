@@ -8,12 +8,13 @@
 use std::collections::{BTreeMap, HashSet};
 use std::sync::Arc;
 
-use crate::node_manager::auth::{LoginResult, login_from_token};
+use crate::node_manager::auth::{LoginResult, bearer_token, login_from_token};
 use crate::node_manager::local_api::{
-    circuit, circuit_count, config, cpu_affinity, dashboard_themes, device_counts, directories,
-    ethernet_caps, executive, flow_explorer, flow_map, lts, network_tree, network_tree_lite,
-    node_rate_overrides, packet_analysis, reload_libreqos, scheduler, search, shaped_device_api,
-    shaped_devices_page, unknown_ips, urgent, warnings,
+    audit_log, bandwidth_schedules, circuit, circuit_count, config, cpu_affinity, dashboard_themes,
+    device_counts, directories, ethernet_caps, executive, flow_explorer, flow_map, lts,
+    network_tree, network_tree_lite, node_rate_overrides, packet_analysis, reload_libreqos,
+    scheduler, search, shaped_device_api, shaped_devices_page, unknown_ips, urgent, usage_ledger,
+    warnings,
 };
 use crate::node_manager::shaper_queries_actor::ShaperQueryCommand;
 use crate::node_manager::ws::messages::{
@@ -41,6 +42,7 @@ use axum::{
 };
 use futures_util::{SinkExt, StreamExt};
 use lqos_bus::BusRequest;
+use lqos_config::{AuditActor, AuditEntry};
 use serde_cbor::Value as CborValue;
 use tokio::sync::mpsc::Sender;
 use tracing::{info, warn};
@@ -104,6 +106,7 @@ async fn ws_handler(
         .get(header::ACCEPT_LANGUAGE)
         .and_then(|h| h.to_str().ok())
         .map(|s| s.to_string());
+    let bearer = bearer_token(&headers).map(|s| s.to_string());
     ws.on_upgrade(move |socket| async move {
         handle_socket(
             socket,
@@ -112,6 +115,7 @@ async fn ws_handler(
             control_tx,
             shaper_query,
             browser_language,
+            bearer,
         )
         .await;
     })
@@ -124,6 +128,7 @@ async fn handle_socket(
     control_tx: tokio::sync::mpsc::Sender<crate::lts2_sys::control_channel::ControlChannelCommand>,
     shaper_query: Sender<ShaperQueryCommand>,
     browser_language: Option<String>,
+    bearer: Option<String>,
 ) {
     info!("Websocket connected");
 
@@ -142,6 +147,7 @@ async fn handle_socket(
     let mut subscribed_channels = HashSet::new();
     let mut handshake_complete = false;
     let mut login = LoginResult::Denied;
+    let mut actor = AuditActor::Anonymous;
     let handshake_timeout =
         tokio::time::sleep(std::time::Duration::from_secs(HANDSHAKE_TIMEOUT_SECS));
     tokio::pin!(handshake_timeout);
@@ -179,6 +185,8 @@ async fn handle_socket(
                             &mut WsRequestState {
                                 private_state: &mut private_state,
                                 login: &mut login,
+                                actor: &mut actor,
+                                bearer: bearer.as_deref(),
                                 shaper_query: shaper_query.clone(),
                             },
                         )
@@ -216,6 +224,10 @@ fn is_benign_recv_error(err: &axum::Error, handshake_complete: bool) -> bool {
 struct WsRequestState<'a> {
     private_state: &'a mut single_user_channels::PrivateState,
     login: &'a mut LoginResult,
+    /// Who changes made over this socket are attributed to in the audit log
+    actor: &'a mut AuditActor,
+    /// API token from the upgrade request's `Authorization` header
+    bearer: Option<&'a str>,
     shaper_query: Sender<ShaperQueryCommand>,
 }
 
@@ -269,13 +281,18 @@ async fn receive_channel_message(
                 warn!("Websocket handshake ack mismatch");
                 return true;
             }
-            let token = reply.token.trim();
-            let login_result = login_from_token(token).await;
+            // Scripts may authenticate the upgrade request instead of the hello
+            let token = match reply.token.trim() {
+                "" => request_state.bearer.unwrap_or_default(),
+                token => token,
+            };
+            let (login_result, actor) = login_from_token(token).await;
             if login_result == LoginResult::Denied {
                 warn!("Websocket handshake token rejected");
                 return true;
             }
             *request_state.login = login_result;
+            *request_state.actor = actor;
             *handshake_complete = true;
             info!("Websocket handshake completed");
             return false;
@@ -498,6 +515,13 @@ async fn receive_channel_message(
                         Ok(_) => (true, "Ok".to_string()),
                         Err(e) => (false, format!("{e:?}")),
                     };
+                if ok {
+                    crate::audit::record(
+                        AuditEntry::new(request_state.actor.clone(), "set_circuit_rtt_excluded")
+                            .target(circuit_id.clone())
+                            .changes(Some(&!excluded), Some(&excluded)),
+                    );
+                }
                 let response = WsResponse::SetCircuitRttExcludedResult {
                     ok,
                     message,
@@ -517,6 +541,39 @@ async fn receive_channel_message(
                 return true;
             }
         }
+        WsRequest::StartRingCapture {
+            circuit,
+            ip,
+            minutes,
+        } => {
+            let data = if *request_state.login != LoginResult::Admin {
+                packet_analysis::RingCaptureStartResult::Fail {
+                    reason: "Unauthorized".to_string(),
+                }
+            } else {
+                packet_analysis::start_ring_capture_data(circuit.as_deref(), ip.as_deref(), minutes)
+            };
+            let response = WsResponse::RingCaptureStarted { data };
+            if send_ws_response(&tx, response).await {
+                return true;
+            }
+        }
+        WsRequest::StopRingCapture { id } => {
+            let ok = *request_state.login == LoginResult::Admin
+                && packet_analysis::stop_ring_capture_data(id);
+            let response = WsResponse::RingCaptureStopped { id, ok };
+            if send_ws_response(&tx, response).await {
+                return true;
+            }
+        }
+        WsRequest::ListRingCaptures => {
+            let response = WsResponse::RingCaptures {
+                data: packet_analysis::ring_captures_data(),
+            };
+            if send_ws_response(&tx, response).await {
+                return true;
+            }
+        }
         WsRequest::CpuAffinitySummary => {
             let response = WsResponse::CpuAffinitySummary {
                 data: cpu_affinity::cpu_affinity_summary_data(),
@@ -613,6 +670,14 @@ async fn receive_channel_message(
                 return true;
             }
         }
+        WsRequest::ApplicationList => {
+            let response = WsResponse::ApplicationList {
+                data: flow_explorer::application_list_data(),
+            };
+            if send_ws_response(&tx, response).await {
+                return true;
+            }
+        }
         WsRequest::AsnFlowTimeline { asn } => {
             let response = WsResponse::AsnFlowTimeline {
                 asn,
@@ -640,6 +705,15 @@ async fn receive_channel_message(
                 return true;
             }
         }
+        WsRequest::ApplicationFlowTimeline { application } => {
+            let response = WsResponse::ApplicationFlowTimeline {
+                application: application.clone(),
+                data: flow_explorer::application_timeline_data(&application),
+            };
+            if send_ws_response(&tx, response).await {
+                return true;
+            }
+        }
         WsRequest::GlobalWarnings => {
             let data = warnings::global_warnings_data().await;
             let response = WsResponse::GlobalWarnings { data };
@@ -998,7 +1072,11 @@ async fn receive_channel_message(
             }
         }
         WsRequest::ReloadLibreQoS => {
-            let message = reload_libreqos::reload_libreqos_with_login(*request_state.login).await;
+            let message = reload_libreqos::reload_libreqos_with_login(
+                *request_state.login,
+                request_state.actor,
+            )
+            .await;
             let response = WsResponse::ReloadResult { message };
             if send_ws_response(&tx, response).await {
                 return true;
@@ -1400,6 +1478,58 @@ async fn receive_channel_message(
                 }
             }
         }
+        WsRequest::LtsSiteHistory { site, seconds } => {
+            match lts::site_history_data(&site, seconds).await {
+                Ok(data) => {
+                    let response = WsResponse::LtsSiteHistory {
+                        site,
+                        seconds,
+                        data,
+                    };
+                    if send_ws_response(&tx, response).await {
+                        return true;
+                    }
+                }
+                Err(_) => {
+                    let response = WsResponse::Error {
+                        message: "Local history not enabled".to_string(),
+                    };
+                    if send_ws_response(&tx, response).await {
+                        return true;
+                    }
+                }
+            }
+        }
+        WsRequest::LtsCircuitHistory { circuit, seconds } => {
+            match lts::circuit_history_data(&circuit, seconds).await {
+                Ok(data) => {
+                    let response = WsResponse::LtsCircuitHistory {
+                        circuit,
+                        seconds,
+                        data,
+                    };
+                    if send_ws_response(&tx, response).await {
+                        return true;
+                    }
+                }
+                Err(StatusCode::NOT_FOUND) => {
+                    let response = WsResponse::Error {
+                        message: format!("Unknown circuit: {circuit}"),
+                    };
+                    if send_ws_response(&tx, response).await {
+                        return true;
+                    }
+                }
+                Err(_) => {
+                    let response = WsResponse::Error {
+                        message: "Local history not enabled".to_string(),
+                    };
+                    if send_ws_response(&tx, response).await {
+                        return true;
+                    }
+                }
+            }
+        }
         WsRequest::AdminCheck => {
             let response = WsResponse::AdminCheck {
                 ok: config::admin_check_data(*request_state.login),
@@ -1473,7 +1603,9 @@ async fn receive_channel_message(
             }
         }
         WsRequest::UpdateConfig { config: cfg } => {
-            let result = config::update_lqosd_config_data(*request_state.login, cfg).await;
+            let result =
+                config::update_lqosd_config_data(*request_state.login, request_state.actor, cfg)
+                    .await;
             let (ok, message) = match result {
                 Ok(()) => (true, "Ok".to_string()),
                 Err(StatusCode::FORBIDDEN) => (false, "Unauthorized".to_string()),
@@ -1485,7 +1617,11 @@ async fn receive_channel_message(
             }
         }
         WsRequest::UpdateNetworkJsonOnly { network_json } => {
-            let result = config::update_network_json_only_data(*request_state.login, network_json);
+            let result = config::update_network_json_only_data(
+                *request_state.login,
+                request_state.actor,
+                network_json,
+            );
             let (ok, message) = match result {
                 Ok(()) => (true, "Ok".to_string()),
                 Err(message) => (false, message),
@@ -1501,6 +1637,7 @@ async fn receive_channel_message(
         } => {
             let result = config::update_network_and_devices_data(
                 *request_state.login,
+                request_state.actor,
                 network_json,
                 shaped_devices,
             );
@@ -1548,8 +1685,11 @@ async fn receive_channel_message(
             }
         }
         WsRequest::SetNodeRateOverride { update } => {
-            let result =
-                node_rate_overrides::set_node_rate_override_data(*request_state.login, update);
+            let result = node_rate_overrides::set_node_rate_override_data(
+                *request_state.login,
+                request_state.actor,
+                update,
+            );
             match result {
                 Ok(data) => {
                     let response = WsResponse::SetNodeRateOverrideResult {
@@ -1614,8 +1754,11 @@ async fn receive_channel_message(
             }
         }
         WsRequest::ClearNodeRateOverride { query } => {
-            let result =
-                node_rate_overrides::clear_node_rate_override_data(*request_state.login, query);
+            let result = node_rate_overrides::clear_node_rate_override_data(
+                *request_state.login,
+                request_state.actor,
+                query,
+            );
             match result {
                 Ok(data) => {
                     let response = WsResponse::ClearNodeRateOverrideResult {
@@ -1732,7 +1875,11 @@ async fn receive_channel_message(
             }
         }
         WsRequest::CreateShapedDevice { device } => {
-            match config::create_shaped_device_data(*request_state.login, device) {
+            match config::create_shaped_device_data(
+                *request_state.login,
+                request_state.actor,
+                device,
+            ) {
                 Ok(device) => {
                     let response = WsResponse::CreateShapedDeviceResult {
                         ok: true,
@@ -1760,6 +1907,7 @@ async fn receive_channel_message(
             device,
         } => match config::update_shaped_device_data(
             *request_state.login,
+            request_state.actor,
             original_device_id,
             device,
         ) {
@@ -1786,7 +1934,11 @@ async fn receive_channel_message(
         },
         WsRequest::DeleteShapedDevice { device_id } => {
             let device_id_clone = device_id.clone();
-            match config::delete_shaped_device_data(*request_state.login, device_id) {
+            match config::delete_shaped_device_data(
+                *request_state.login,
+                request_state.actor,
+                device_id,
+            ) {
                 Ok(()) => {
                     let response = WsResponse::DeleteShapedDeviceResult {
                         ok: true,
@@ -1864,6 +2016,7 @@ async fn receive_channel_message(
         } => {
             let result = config::add_user_data(
                 *request_state.login,
+                request_state.actor,
                 config::UserRequest {
                     username,
                     password,
@@ -1891,6 +2044,7 @@ async fn receive_channel_message(
         } => {
             let result = config::update_user_data(
                 *request_state.login,
+                request_state.actor,
                 config::UserRequest {
                     username,
                     password,
@@ -1912,7 +2066,8 @@ async fn receive_channel_message(
             }
         }
         WsRequest::DeleteUser { username } => {
-            let result = config::delete_user_data(*request_state.login, username);
+            let result =
+                config::delete_user_data(*request_state.login, request_state.actor, username);
             let (ok, message) = match result {
                 Ok(message) => (true, message),
                 Err(StatusCode::FORBIDDEN) => (false, "Unauthorized".to_string()),
@@ -1927,6 +2082,183 @@ async fn receive_channel_message(
                 return true;
             }
         }
+        WsRequest::GetAuditLog { query } => {
+            match audit_log::audit_log_data(*request_state.login, query).await {
+                Ok(data) => {
+                    let response = WsResponse::AuditLog { data };
+                    if send_ws_response(&tx, response).await {
+                        return true;
+                    }
+                }
+                Err(StatusCode::FORBIDDEN) => {
+                    let response = WsResponse::Error {
+                        message: "Unauthorized".to_string(),
+                    };
+                    if send_ws_response(&tx, response).await {
+                        return true;
+                    }
+                }
+                Err(_) => {
+                    let response = WsResponse::Error {
+                        message: "Unable to read audit log".to_string(),
+                    };
+                    if send_ws_response(&tx, response).await {
+                        return true;
+                    }
+                }
+            }
+        }
+        WsRequest::GetBandwidthSchedules => {
+            match bandwidth_schedules::get_bandwidth_schedules_data(*request_state.login) {
+                Ok(data) => {
+                    let response = WsResponse::BandwidthSchedules { data };
+                    if send_ws_response(&tx, response).await {
+                        return true;
+                    }
+                }
+                Err(_) => {
+                    let response = WsResponse::Error {
+                        message: "Unable to load bandwidth schedules".to_string(),
+                    };
+                    if send_ws_response(&tx, response).await {
+                        return true;
+                    }
+                }
+            }
+        }
+        WsRequest::SaveBandwidthSchedule { schedule } => {
+            let result = bandwidth_schedules::save_bandwidth_schedule_data(
+                *request_state.login,
+                request_state.actor,
+                schedule,
+            );
+            let response = match result {
+                Ok(data) => WsResponse::BandwidthScheduleResult {
+                    ok: true,
+                    message: "Ok".to_string(),
+                    data: Some(data),
+                },
+                Err(message) => WsResponse::BandwidthScheduleResult {
+                    ok: false,
+                    message,
+                    data: None,
+                },
+            };
+            if send_ws_response(&tx, response).await {
+                return true;
+            }
+        }
+        WsRequest::DeleteBandwidthSchedule { id } => {
+            let result = bandwidth_schedules::delete_bandwidth_schedule_data(
+                *request_state.login,
+                request_state.actor,
+                id,
+            );
+            let response = match result {
+                Ok(data) => WsResponse::BandwidthScheduleResult {
+                    ok: true,
+                    message: "Ok".to_string(),
+                    data: Some(data),
+                },
+                Err(message) => WsResponse::BandwidthScheduleResult {
+                    ok: false,
+                    message,
+                    data: None,
+                },
+            };
+            if send_ws_response(&tx, response).await {
+                return true;
+            }
+        }
+        WsRequest::GetUsageLedger => {
+            let response = WsResponse::UsageLedger {
+                data: usage_ledger::get_usage_ledger_data(),
+            };
+            if send_ws_response(&tx, response).await {
+                return true;
+            }
+        }
+        WsRequest::UsageLedgerCsv { month } => {
+            let response = WsResponse::UsageLedgerCsv {
+                csv: usage_ledger::usage_ledger_csv_data(month.as_deref()),
+            };
+            if send_ws_response(&tx, response).await {
+                return true;
+            }
+        }
+        WsRequest::GetApiTokens => match config::get_api_tokens_data(*request_state.login) {
+            Ok(data) => {
+                let response = WsResponse::GetApiTokens { data };
+                if send_ws_response(&tx, response).await {
+                    return true;
+                }
+            }
+            Err(StatusCode::FORBIDDEN) => {
+                let response = WsResponse::Error {
+                    message: "Unauthorized".to_string(),
+                };
+                if send_ws_response(&tx, response).await {
+                    return true;
+                }
+            }
+            Err(_) => {
+                let response = WsResponse::Error {
+                    message: "Unable to load API tokens".to_string(),
+                };
+                if send_ws_response(&tx, response).await {
+                    return true;
+                }
+            }
+        },
+        WsRequest::CreateApiToken {
+            name,
+            scopes,
+            expires_days,
+        } => {
+            let result = config::create_api_token_data(
+                *request_state.login,
+                request_state.actor,
+                config::ApiTokenRequest {
+                    name,
+                    scopes,
+                    expires_days,
+                },
+            );
+            let (ok, message, secret) = match result {
+                Ok(secret) => (true, "Token created".to_string(), Some(secret)),
+                Err(StatusCode::FORBIDDEN) => (false, "Unauthorized".to_string(), None),
+                Err(StatusCode::BAD_REQUEST) => (false, "Invalid token data".to_string(), None),
+                Err(_) => (false, "Error".to_string(), None),
+            };
+            if ok {
+                crate::node_manager::auth::refresh_cached_users().await;
+            }
+            let response = WsResponse::CreateApiTokenResult {
+                ok,
+                message,
+                secret,
+            };
+            if send_ws_response(&tx, response).await {
+                return true;
+            }
+        }
+        WsRequest::RevokeApiToken { id } => {
+            let result =
+                config::revoke_api_token_data(*request_state.login, request_state.actor, id);
+            let (ok, message) = match result {
+                Ok(message) => (true, message),
+                Err(StatusCode::FORBIDDEN) => (false, "Unauthorized".to_string()),
+                Err(StatusCode::BAD_REQUEST) => (false, "Unknown token".to_string()),
+                Err(_) => (false, "Error".to_string()),
+            };
+            if ok {
+                crate::node_manager::auth::refresh_cached_users().await;
+            }
+            let response = WsResponse::RevokeApiTokenResult { ok, message };
+            if send_ws_response(&tx, response).await {
+                return true;
+            }
+        }
         WsRequest::HelloReply(_) => {}
     }
     false
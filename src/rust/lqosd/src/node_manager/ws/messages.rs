@@ -1,5 +1,6 @@
 use crate::lts2_sys::control_channel::{SupportTicket, SupportTicketSummary};
 use crate::node_manager::WarningLevel;
+use crate::node_manager::local_api::bandwidth_schedules::BandwidthSchedulesData;
 use crate::node_manager::local_api::circuit::CircuitByIdData;
 use crate::node_manager::local_api::circuit_activity::{
     CircuitFlowSankeyRow, CircuitSummaryData, CircuitTopAsnsData, CircuitTopAsnsQuery,
@@ -19,7 +20,9 @@ use crate::node_manager::local_api::network_tree_lite::NetworkTreeLiteNode;
 use crate::node_manager::local_api::node_rate_overrides::{
     NodeRateOverrideData, NodeRateOverrideQuery, NodeRateOverrideUpdate,
 };
-use crate::node_manager::local_api::packet_analysis::RequestAnalysisResult;
+use crate::node_manager::local_api::packet_analysis::{
+    RequestAnalysisResult, RingCaptureStartResult, RingCaptureSummary,
+};
 use crate::node_manager::local_api::scheduler::{SchedulerDetails, SchedulerStatus};
 use crate::node_manager::local_api::search::SearchResult;
 use crate::node_manager::local_api::shaped_devices_page::{
@@ -30,6 +33,7 @@ use crate::node_manager::local_api::tree_attached_circuits::{
 };
 use crate::node_manager::local_api::unknown_ips::{ClearUnknownIpsResponse, UnknownIp};
 use crate::node_manager::local_api::urgent::{UrgentList, UrgentStatus};
+use crate::node_manager::local_api::usage_ledger::UsageLedgerData;
 use crate::node_manager::local_api::{
     circuit_count::CircuitCount,
     circuit_live::{CircuitLiveMetrics, CircuitMetricsQuery},
@@ -39,8 +43,8 @@ use crate::node_manager::local_api::{
     },
     flow_explorer::FlowTimeline,
     lts::{
-        AsnFlowSizeWeb, CakeData, FlowCountViewWeb, FullPacketData, LtsTrialConfig,
-        PercentShapedWeb, RecentMedians, ShaperRttHistogramEntry, ShaperStatus,
+        AsnFlowSizeWeb, CakeData, FlowCountViewWeb, FullPacketData, LocalHistoryPoint,
+        LtsTrialConfig, PercentShapedWeb, RecentMedians, ShaperRttHistogramEntry, ShaperStatus,
         ThroughputData as LtsThroughputData, Top10Circuit, Worst10RttCircuit, Worst10RxmitCircuit,
     },
 };
@@ -48,11 +52,15 @@ use crate::node_manager::ws::published_channels::PublishedChannels;
 use crate::node_manager::ws::ticker::ipstats_conversion::IpStatsWithPlan;
 use crate::throughput_tracker::TcpRetransmitTotal;
 use crate::throughput_tracker::flow_data::{
-    AsnCountryListEntry, AsnListEntry, AsnProtocolListEntry,
+    ApplicationListEntry, AsnCountryListEntry, AsnListEntry, AsnProtocolListEntry,
 };
 use lqos_bus::{Circuit, FlowbeeSummaryData, QueueStoreTransit, StormguardDebugEntry};
 use lqos_config::QooProfileInfo;
-use lqos_config::{Config, NetworkJsonTransport, ShapedDevice, WebUser};
+use lqos_config::{
+    ApiToken, ApiTokenScope, AuditEntry, AuditQuery, Config, NetworkJsonTransport, ShapedDevice,
+    WebUser,
+};
+use lqos_overrides::BandwidthSchedule;
 use lqos_utils::units::DownUpOrder;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -168,6 +176,14 @@ pub enum WsRequest {
         seconds: i32,
     },
     LtsRecentMedian,
+    LtsSiteHistory {
+        site: String,
+        seconds: i32,
+    },
+    LtsCircuitHistory {
+        circuit: String,
+        seconds: i32,
+    },
     AdminCheck,
     GetConfig,
     QooProfiles,
@@ -228,6 +244,30 @@ pub enum WsRequest {
     DeleteUser {
         username: String,
     },
+    GetApiTokens,
+    CreateApiToken {
+        name: String,
+        scopes: Vec<ApiTokenScope>,
+        expires_days: Option<u64>,
+    },
+    RevokeApiToken {
+        id: String,
+    },
+    GetAuditLog {
+        query: AuditQuery,
+    },
+    GetBandwidthSchedules,
+    SaveBandwidthSchedule {
+        schedule: BandwidthSchedule,
+    },
+    DeleteBandwidthSchedule {
+        id: String,
+    },
+    GetUsageLedger,
+    UsageLedgerCsv {
+        #[serde(default)]
+        month: Option<String>,
+    },
     CircuitById {
         id: String,
     },
@@ -250,6 +290,15 @@ pub enum WsRequest {
     RequestAnalysis {
         ip: String,
     },
+    StartRingCapture {
+        circuit: Option<String>,
+        ip: Option<String>,
+        minutes: u64,
+    },
+    StopRingCapture {
+        id: usize,
+    },
+    ListRingCaptures,
     CpuAffinitySummary,
     CpuAffinityRuntimeSnapshot,
     CpuAffinityCircuits {
@@ -271,6 +320,7 @@ pub enum WsRequest {
     AsnList,
     CountryList,
     ProtocolList,
+    ApplicationList,
     AsnFlowTimeline {
         asn: u32,
     },
@@ -280,6 +330,9 @@ pub enum WsRequest {
     ProtocolFlowTimeline {
         protocol: String,
     },
+    ApplicationFlowTimeline {
+        application: String,
+    },
     UrgentStatus,
     UrgentList,
     UrgentClear {
@@ -348,6 +401,8 @@ pub struct BakeryCapacityInterfaceData {
     pub infra_qdiscs: usize,
     pub cake_qdiscs: usize,
     pub fq_codel_qdiscs: usize,
+    pub fq_pie_qdiscs: usize,
+    pub sfq_qdiscs: usize,
     pub estimated_memory_bytes: u64,
 }
 
@@ -688,6 +743,36 @@ pub enum WsResponse {
         ok: bool,
         message: String,
     },
+    GetApiTokens {
+        data: Vec<ApiToken>,
+    },
+    CreateApiTokenResult {
+        ok: bool,
+        message: String,
+        /// Shown once; only a hash is kept
+        secret: Option<String>,
+    },
+    RevokeApiTokenResult {
+        ok: bool,
+        message: String,
+    },
+    AuditLog {
+        data: Vec<AuditEntry>,
+    },
+    BandwidthSchedules {
+        data: BandwidthSchedulesData,
+    },
+    BandwidthScheduleResult {
+        ok: bool,
+        message: String,
+        data: Option<BandwidthSchedulesData>,
+    },
+    UsageLedger {
+        data: UsageLedgerData,
+    },
+    UsageLedgerCsv {
+        csv: String,
+    },
     LtsTrialConfigResult {
         data: LtsTrialConfig,
     },
@@ -747,6 +832,16 @@ pub enum WsResponse {
     LtsRecentMedian {
         data: Vec<RecentMedians>,
     },
+    LtsSiteHistory {
+        site: String,
+        seconds: i32,
+        data: Vec<LocalHistoryPoint>,
+    },
+    LtsCircuitHistory {
+        circuit: String,
+        seconds: i32,
+        data: Vec<LocalHistoryPoint>,
+    },
     DevicesAll {
         data: Vec<ShapedDevice>,
     },
@@ -773,6 +868,16 @@ pub enum WsResponse {
     RequestAnalysisResult {
         data: RequestAnalysisResult,
     },
+    RingCaptureStarted {
+        data: RingCaptureStartResult,
+    },
+    RingCaptureStopped {
+        id: usize,
+        ok: bool,
+    },
+    RingCaptures {
+        data: Vec<RingCaptureSummary>,
+    },
     CpuAffinitySummary {
         data: Vec<CpuAffinitySummaryEntry>,
     },
@@ -800,6 +905,9 @@ pub enum WsResponse {
     ProtocolList {
         data: Vec<AsnProtocolListEntry>,
     },
+    ApplicationList {
+        data: Vec<ApplicationListEntry>,
+    },
     AsnFlowTimeline {
         asn: u32,
         data: Vec<FlowTimeline>,
@@ -812,6 +920,10 @@ pub enum WsResponse {
         protocol: String,
         data: Vec<FlowTimeline>,
     },
+    ApplicationFlowTimeline {
+        application: String,
+        data: Vec<FlowTimeline>,
+    },
     SearchResults {
         term: String,
         results: Vec<SearchResult>,
@@ -72,6 +72,8 @@ fn map_preflight(snapshot: BakeryPreflightSnapshot) -> BakeryPreflightData {
                 infra_qdiscs: entry.infra_qdiscs,
                 cake_qdiscs: entry.cake_qdiscs,
                 fq_codel_qdiscs: entry.fq_codel_qdiscs,
+                fq_pie_qdiscs: entry.fq_pie_qdiscs,
+                sfq_qdiscs: entry.sfq_qdiscs,
                 estimated_memory_bytes: entry.estimated_memory_bytes,
             })
             .collect(),
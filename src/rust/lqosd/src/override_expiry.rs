@@ -0,0 +1,177 @@
+//! Prunes temporary (expiring) operator overrides and reverts them.
+//!
+//! Temporary speed adjustments are never written into `ShapedDevices.csv` or
+//! `network.json`; lqosd overlays them on the rates LibreQoS.py sends (see
+//! `bandwidth_schedules`). Reverting one is therefore just dropping it from
+//! the overlay and moving the class back live. Temporary SQM overrides are
+//! applied by LibreQoS.py itself, so when one lapses lqosd asks for a reload.
+//!
+//! Anything that stops a lapsed override from being reverted is raised as an
+//! urgent issue, since the customer would otherwise keep the temporary rate.
+
+use crate::{audit, bandwidth_schedules, urgent};
+use lqos_bus::{UrgentSeverity, UrgentSource};
+use lqos_config::{AuditActor, AuditEntry};
+use lqos_overrides::{
+    CircuitAdjustment, ExpiredAdjustments, NetworkAdjustment, OverrideLayer, OverrideStore,
+};
+use lqos_utils::unix_time::unix_now;
+use tracing::{info, warn};
+
+const URGENT_CODE: &str = "OVERRIDE_EXPIRY_FAILED";
+
+/// Removes lapsed adjustments from the operator overrides file and reverts
+/// their effect. Called from the bandwidth schedule thread.
+pub fn prune_expired_overrides() {
+    let now = unix_now().unwrap_or(0);
+    let mut overrides = match OverrideStore::load_layer(OverrideLayer::Operator) {
+        Ok(overrides) => overrides,
+        Err(e) => {
+            warn!("Unable to load overrides to prune expired entries: {e:?}");
+            return;
+        }
+    };
+    let expired = overrides.prune_expired_adjustments(now);
+    if expired.is_empty() {
+        return;
+    }
+    if let Err(e) = OverrideStore::save_layer(OverrideLayer::Operator, &overrides) {
+        raise(
+            "prune",
+            format!("Unable to remove expired overrides from lqos_overrides.json: {e}"),
+            describe(&expired),
+        );
+        return;
+    }
+    record_expired(&expired);
+
+    if let Err(e) = bandwidth_schedules::overrides_changed() {
+        raise(
+            "rates",
+            format!("Unable to revert expired speed overrides live: {e}"),
+            describe(&expired),
+        );
+    }
+    if needs_reload(&expired) {
+        let context = describe(&expired);
+        let spawned = std::thread::Builder::new()
+            .name("Override Expiry Reload".to_string())
+            .spawn(move || {
+                if let Err(e) = lqos_config::load_libreqos() {
+                    raise(
+                        "reload",
+                        format!("Unable to reload LibreQoS after SQM overrides expired: {e:?}"),
+                        context,
+                    );
+                }
+            });
+        if let Err(e) = spawned {
+            raise(
+                "reload",
+                format!("Unable to start a reload after SQM overrides expired: {e}"),
+                describe(&expired),
+            );
+        }
+    }
+}
+
+/// SQM overrides are applied by LibreQoS.py, so only a reload reverts them.
+fn needs_reload(expired: &ExpiredAdjustments) -> bool {
+    expired
+        .circuit_adjustments
+        .iter()
+        .any(|adj| !matches!(adj, CircuitAdjustment::CircuitAdjustSpeed { .. }))
+}
+
+fn target_name(adj: &CircuitAdjustment) -> &str {
+    match adj {
+        CircuitAdjustment::CircuitAdjustSpeed { circuit_id, .. }
+        | CircuitAdjustment::RemoveCircuit { circuit_id }
+        | CircuitAdjustment::ReparentCircuit { circuit_id, .. } => circuit_id,
+        CircuitAdjustment::DeviceAdjustSpeed { device_id, .. }
+        | CircuitAdjustment::DeviceAdjustSqm { device_id, .. }
+        | CircuitAdjustment::RemoveDevice { device_id } => device_id,
+    }
+}
+
+fn network_target_name(adj: &NetworkAdjustment) -> &str {
+    match adj {
+        NetworkAdjustment::AdjustSiteSpeed { site_name, .. } => site_name,
+        NetworkAdjustment::SetNodeVirtual { node_name, .. } => node_name,
+    }
+}
+
+fn describe(expired: &ExpiredAdjustments) -> String {
+    let targets: Vec<&str> = expired
+        .circuit_adjustments
+        .iter()
+        .map(target_name)
+        .chain(expired.network_adjustments.iter().map(network_target_name))
+        .collect();
+    format!("Expired overrides for: {}", targets.join(", "))
+}
+
+fn record_expired(expired: &ExpiredAdjustments) {
+    let actor = AuditActor::subsystem("override_expiry");
+    for adj in &expired.circuit_adjustments {
+        info!("Temporary override for '{}' expired", target_name(adj));
+        audit::record(
+            AuditEntry::new(actor.clone(), "override_expired")
+                .target(target_name(adj).to_string())
+                .changes(Some(adj), None::<&CircuitAdjustment>),
+        );
+    }
+    for adj in &expired.network_adjustments {
+        info!(
+            "Temporary override for '{}' expired",
+            network_target_name(adj)
+        );
+        audit::record(
+            AuditEntry::new(actor.clone(), "override_expired")
+                .target(network_target_name(adj).to_string())
+                .changes(Some(adj), None::<&NetworkAdjustment>),
+        );
+    }
+}
+
+fn raise(stage: &str, message: String, context: String) {
+    warn!("{message}");
+    urgent::submit(
+        UrgentSource::System,
+        UrgentSeverity::Error,
+        URGENT_CODE.to_string(),
+        message,
+        Some(context),
+        Some(format!("override_expiry_{stage}")),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_sqm_expiry_needs_a_reload() {
+        let speed = CircuitAdjustment::CircuitAdjustSpeed {
+            circuit_id: "c1".to_string(),
+            min_download_bandwidth: None,
+            max_download_bandwidth: Some(500.0),
+            min_upload_bandwidth: None,
+            max_upload_bandwidth: None,
+            expires_at: Some(1),
+        };
+        let sqm = CircuitAdjustment::DeviceAdjustSqm {
+            device_id: "d1".to_string(),
+            sqm_override: Some("fq_codel".to_string()),
+            expires_at: Some(1),
+        };
+        let mut expired = ExpiredAdjustments {
+            circuit_adjustments: vec![speed],
+            network_adjustments: Vec::new(),
+        };
+        assert!(!needs_reload(&expired));
+        expired.circuit_adjustments.push(sqm);
+        assert!(needs_reload(&expired));
+        assert_eq!(describe(&expired), "Expired overrides for: c1, d1");
+    }
+}
@@ -0,0 +1,197 @@
+//! Reports circuits that stop passing traffic, and again when they resume.
+//!
+//! The throughput tracker forgets hosts a few minutes after their last
+//! packet, so the watcher keeps its own record of when each circuit was last
+//! heard from. It only runs while something subscribes to
+//! `BusTopic::CircuitStatus`, and only reports circuits it has seen pass
+//! traffic since then: a circuit that was already quiet is never "down".
+
+use super::SHAPED_DEVICES;
+use super::circuit_live::fresh_circuit_live_snapshot;
+use fxhash::{FxHashMap, FxHashSet};
+use lqos_bus::{BusTopic, BusUpdate, CircuitStatusChange, has_bus_subscribers, publish_bus_update};
+use std::time::{Duration, Instant};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Starts the watcher thread.
+pub fn start_circuit_status_watcher() -> anyhow::Result<()> {
+    std::thread::Builder::new()
+        .name("Circuit Status".to_string())
+        .spawn(|| {
+            let mut tracker = CircuitStatusTracker::default();
+            loop {
+                std::thread::sleep(CHECK_INTERVAL);
+                if !has_bus_subscribers(BusTopic::CircuitStatus) {
+                    tracker = CircuitStatusTracker::default();
+                    continue;
+                }
+                let Ok(config) = lqos_config::load_config() else {
+                    continue;
+                };
+                let down_after = Duration::from_secs(config.webhooks.circuit_down_seconds);
+                let changes = tracker.check(Instant::now(), down_after);
+                if !changes.is_empty() {
+                    publish_bus_update(BusUpdate::CircuitStatus(changes));
+                }
+            }
+        })?;
+    Ok(())
+}
+
+struct TrackedCircuit {
+    circuit_name: String,
+    parent_node: String,
+    last_heard: Instant,
+    online: bool,
+}
+
+/// A circuit's most recent traffic, as seen by the throughput tracker.
+struct Heard<'a> {
+    circuit_id: &'a str,
+    circuit_name: &'a str,
+    parent_node: &'a str,
+    idle: Duration,
+}
+
+#[derive(Default)]
+struct CircuitStatusTracker {
+    circuits: FxHashMap<String, TrackedCircuit>,
+}
+
+impl CircuitStatusTracker {
+    fn check(&mut self, now: Instant, down_after: Duration) -> Vec<CircuitStatusChange> {
+        let snapshot = fresh_circuit_live_snapshot();
+        let heard = snapshot.by_circuit_id.values().map(|circuit| Heard {
+            circuit_id: &circuit.circuit_id,
+            circuit_name: &circuit.circuit_name,
+            parent_node: &circuit.parent_node,
+            idle: Duration::from_nanos(circuit.last_seen_nanos),
+        });
+        let shaped = SHAPED_DEVICES.load();
+        let known: FxHashSet<&str> = shaped
+            .devices
+            .iter()
+            .map(|device| device.circuit_id.as_str())
+            .collect();
+        self.update(now, heard, &known, down_after)
+    }
+
+    /// Records `heard` and returns the circuits whose status changed.
+    /// Circuits no longer in `known` (removed from `ShapedDevices.csv`)
+    /// are forgotten without a report.
+    fn update<'a>(
+        &mut self,
+        now: Instant,
+        heard: impl Iterator<Item = Heard<'a>>,
+        known: &FxHashSet<&str>,
+        down_after: Duration,
+    ) -> Vec<CircuitStatusChange> {
+        for circuit in heard {
+            // `u64::MAX` nanoseconds means the tracker has no timestamp.
+            let Some(last_heard) = now.checked_sub(circuit.idle) else {
+                continue;
+            };
+            let tracked = self
+                .circuits
+                .entry(circuit.circuit_id.to_string())
+                .or_insert_with(|| TrackedCircuit {
+                    circuit_name: String::new(),
+                    parent_node: String::new(),
+                    last_heard,
+                    online: circuit.idle < down_after,
+                });
+            tracked.circuit_name = circuit.circuit_name.to_string();
+            tracked.parent_node = circuit.parent_node.to_string();
+            tracked.last_heard = tracked.last_heard.max(last_heard);
+        }
+        self.circuits
+            .retain(|circuit_id, _| known.contains(circuit_id.as_str()));
+
+        let mut changes = Vec::new();
+        for (circuit_id, tracked) in self.circuits.iter_mut() {
+            let idle = now.saturating_duration_since(tracked.last_heard);
+            let online = idle < down_after;
+            if online != tracked.online {
+                tracked.online = online;
+                changes.push(CircuitStatusChange {
+                    circuit_id: circuit_id.clone(),
+                    circuit_name: tracked.circuit_name.clone(),
+                    parent_node: tracked.parent_node.clone(),
+                    online,
+                    idle_seconds: idle.as_secs(),
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.circuit_id.cmp(&b.circuit_id));
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOWN_AFTER: Duration = Duration::from_secs(600);
+
+    fn heard(circuit_id: &str, idle_secs: u64) -> Heard<'_> {
+        Heard {
+            circuit_id,
+            circuit_name: "Customer",
+            parent_node: "Tower",
+            idle: Duration::from_secs(idle_secs),
+        }
+    }
+
+    #[test]
+    fn circuits_go_down_when_quiet_and_up_when_heard() {
+        let known: FxHashSet<&str> = ["a", "b"].into_iter().collect();
+        let start = Instant::now();
+        let mut tracker = CircuitStatusTracker::default();
+        let changes = tracker.update(
+            start,
+            [heard("a", 0), heard("b", 0)].into_iter(),
+            &known,
+            DOWN_AFTER,
+        );
+        assert!(changes.is_empty());
+
+        // "a" falls out of the throughput tracker; "b" keeps talking.
+        let later = start + Duration::from_secs(700);
+        let changes = tracker.update(later, [heard("b", 1)].into_iter(), &known, DOWN_AFTER);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].circuit_id, "a");
+        assert!(!changes[0].online);
+        assert_eq!(changes[0].idle_seconds, 700);
+
+        // Reported once, not on every check.
+        let changes = tracker.update(later, std::iter::empty(), &known, DOWN_AFTER);
+        assert!(changes.is_empty());
+
+        let back = later + Duration::from_secs(30);
+        let changes = tracker.update(back, [heard("a", 2)].into_iter(), &known, DOWN_AFTER);
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].online);
+    }
+
+    #[test]
+    fn removed_and_never_heard_circuits_are_not_reported() {
+        let start = Instant::now();
+        let mut tracker = CircuitStatusTracker::default();
+        let known: FxHashSet<&str> = ["a"].into_iter().collect();
+        tracker.update(start, [heard("a", 0)].into_iter(), &known, DOWN_AFTER);
+
+        let later = start + Duration::from_secs(700);
+        let never_heard = Heard {
+            idle: Duration::from_nanos(u64::MAX),
+            ..heard("c", 0)
+        };
+        let changes = tracker.update(
+            later,
+            [never_heard].into_iter(),
+            &FxHashSet::default(),
+            DOWN_AFTER,
+        );
+        assert!(changes.is_empty());
+    }
+}
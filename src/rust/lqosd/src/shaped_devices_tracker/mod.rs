@@ -23,9 +23,11 @@ const SHAPED_DEVICES_RELOAD_RETRY_DELAY_MS: u64 = 500;
 const SHAPED_DEVICES_RELOAD_ATTEMPTS: usize = 2;
 
 pub mod circuit_live;
+mod circuit_status;
 mod netjson;
 use crate::throughput_tracker::THROUGHPUT_TRACKER;
 pub use circuit_live::CircuitLiveSnapshot;
+pub use circuit_status::start_circuit_status_watcher;
 pub use netjson::*;
 
 pub static SHAPED_DEVICES: Lazy<ArcSwap<ConfigShapedDevices>> =
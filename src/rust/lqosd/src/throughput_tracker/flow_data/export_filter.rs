@@ -0,0 +1,137 @@
+//! Per-destination flow export filtering: subnet include/exclude lists and
+//! 1-in-N flow sampling.
+//!
+//! Sampling is keyed on a hash of the flow rather than a running counter, so
+//! every record for a flow (interim and final) gets the same decision.
+
+use ip_network::IpNetwork;
+use ip_network_table::IpNetworkTable;
+use lqos_config::FlowExportTarget;
+use std::net::IpAddr;
+use tracing::error;
+
+pub(crate) struct ExportFilter {
+    include: Option<IpNetworkTable<bool>>,
+    exclude: IpNetworkTable<bool>,
+    sampling_rate: u64,
+}
+
+impl ExportFilter {
+    pub(crate) fn from_target(target: &FlowExportTarget) -> Self {
+        let include = if target.include_subnets.is_empty() {
+            None
+        } else {
+            Some(subnet_table(&target.include_subnets))
+        };
+        Self {
+            include,
+            exclude: subnet_table(&target.exclude_subnets),
+            sampling_rate: u64::from(target.sampling_rate.max(1)),
+        }
+    }
+
+    /// Decides whether a flow between `local` and `remote` is exported.
+    /// Sampling is applied after the subnet filters, so a 1-in-N rate is
+    /// 1-in-N of the flows that matched.
+    pub(crate) fn accepts(&self, local: IpAddr, remote: IpAddr, flow_hash: u64) -> bool {
+        let matches = |table: &IpNetworkTable<bool>| {
+            table.longest_match(local).is_some() || table.longest_match(remote).is_some()
+        };
+        if matches(&self.exclude) {
+            return false;
+        }
+        if let Some(include) = &self.include
+            && !matches(include)
+        {
+            return false;
+        }
+        flow_hash.is_multiple_of(self.sampling_rate)
+    }
+}
+
+fn subnet_table(subnets: &[String]) -> IpNetworkTable<bool> {
+    let mut table = IpNetworkTable::new();
+    for subnet in subnets {
+        match parse_subnet(subnet) {
+            Some(network) => {
+                table.insert(network, true);
+            }
+            None => error!("Invalid subnet in flow export target: {subnet}"),
+        }
+    }
+    table
+}
+
+/// Parses CIDR notation, allowing host-only entries (`/32` or `/128`).
+pub(crate) fn parse_subnet(subnet: &str) -> Option<IpNetwork> {
+    let subnet = subnet.trim();
+    let (ip, mask) = match subnet.split_once('/') {
+        Some((ip, mask)) => (ip.trim(), Some(mask.trim())),
+        None => (subnet, None),
+    };
+    let ip: IpAddr = ip.parse().ok()?;
+    let mask = match mask {
+        Some(mask) => mask.parse::<u8>().ok()?,
+        None if ip.is_ipv4() => 32,
+        None => 128,
+    };
+    IpNetwork::new_truncate(ip, mask).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(include: &[&str], exclude: &[&str], sampling_rate: u32) -> FlowExportTarget {
+        FlowExportTarget {
+            ip: "127.0.0.1".to_string(),
+            port: 2055,
+            version: 9,
+            sampling_rate,
+            include_subnets: include.iter().map(|s| s.to_string()).collect(),
+            exclude_subnets: exclude.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().expect("valid test address")
+    }
+
+    #[test]
+    fn include_and_exclude_lists_match_either_address() {
+        let filter = ExportFilter::from_target(&target(
+            &["100.64.0.0/10", "2001:db8::/32"],
+            &["100.64.1.0/24"],
+            1,
+        ));
+        assert!(filter.accepts(ip("100.64.0.5"), ip("8.8.8.8"), 7));
+        assert!(filter.accepts(ip("192.168.1.1"), ip("100.64.9.9"), 7));
+        assert!(filter.accepts(ip("2001:db8::1"), ip("2606:4700::1111"), 7));
+        assert!(!filter.accepts(ip("100.64.1.7"), ip("8.8.8.8"), 7));
+        assert!(!filter.accepts(ip("192.168.1.1"), ip("8.8.8.8"), 7));
+    }
+
+    #[test]
+    fn samples_one_in_n_matching_flows() {
+        let filter = ExportFilter::from_target(&target(&[], &["10.0.0.1"], 3));
+        let accepted = (0..9)
+            .filter(|hash| filter.accepts(ip("100.64.0.1"), ip("8.8.8.8"), *hash))
+            .count();
+        assert_eq!(accepted, 3);
+        // The same flow always gets the same decision.
+        assert!(filter.accepts(ip("100.64.0.1"), ip("8.8.8.8"), 6));
+        assert!(filter.accepts(ip("100.64.0.1"), ip("8.8.8.8"), 6));
+        assert!(!filter.accepts(ip("10.0.0.1"), ip("8.8.8.8"), 6));
+    }
+
+    #[test]
+    fn parses_host_entries_and_rejects_garbage() {
+        assert!(parse_subnet("10.0.0.1").is_some());
+        assert!(parse_subnet(" 10.0.0.0 / 8 ").is_some());
+        assert!(parse_subnet("2001:db8::1").is_some());
+        assert!(parse_subnet("10.0.0.0/33").is_none());
+        assert!(parse_subnet("not-a-subnet").is_none());
+        let filter = ExportFilter::from_target(&target(&[], &[], 1));
+        assert!(filter.accepts(ip("1.1.1.1"), ip("2.2.2.2"), 5));
+    }
+}
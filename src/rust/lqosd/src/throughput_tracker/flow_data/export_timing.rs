@@ -0,0 +1,267 @@
+//! Export cadence shared by the flow exporters: time-based batch flushing,
+//! template retransmission (RFC 3954 section 9 / RFC 7011 section 8.4) and
+//! active-timeout interim records for long-lived flows.
+
+use super::{FlowAnalysis, FlowbeeLocalData};
+use fxhash::FxHashMap;
+use lqos_config::FlowConfig;
+use lqos_sys::flowbee_data::FlowbeeKey;
+use lqos_utils::units::DownUpOrder;
+use std::time::{Duration, Instant};
+
+/// Per-exporter timing, taken from the `[flows]` configuration.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ExportTiming {
+    /// Longest time a record may wait before a partial batch is sent.
+    pub(crate) flush_interval: Duration,
+    pub(crate) template_refresh_interval: Duration,
+    pub(crate) template_refresh_packets: u32,
+}
+
+impl ExportTiming {
+    pub(crate) fn from_config(config: &FlowConfig) -> Self {
+        Self {
+            flush_interval: Duration::from_millis(config.export_flush_interval_ms.max(10)),
+            template_refresh_interval: Duration::from_secs(config.template_refresh_seconds),
+            template_refresh_packets: config.template_refresh_packets,
+        }
+    }
+
+    pub(crate) fn template_refresh(&self) -> TemplateRefresh {
+        TemplateRefresh {
+            interval: self.template_refresh_interval,
+            packets: self.template_refresh_packets,
+            packets_since: 0,
+            last_sent: None,
+        }
+    }
+}
+
+/// Tracks when templates must be re-sent: after `packets` export packets or
+/// `interval`, whichever comes first. A zero value disables that trigger.
+pub(crate) struct TemplateRefresh {
+    interval: Duration,
+    packets: u32,
+    packets_since: u32,
+    last_sent: Option<Instant>,
+}
+
+impl TemplateRefresh {
+    pub(crate) fn due(&self, now: Instant) -> bool {
+        let Some(last_sent) = self.last_sent else {
+            return true;
+        };
+        (self.packets > 0 && self.packets_since >= self.packets)
+            || (!self.interval.is_zero() && now.duration_since(last_sent) >= self.interval)
+    }
+
+    pub(crate) fn templates_sent(&mut self, now: Instant) {
+        self.last_sent = Some(now);
+        self.packets_since = 0;
+    }
+
+    pub(crate) fn packet_sent(&mut self) {
+        self.packets_since = self.packets_since.saturating_add(1);
+    }
+}
+
+/// Why a record is being exported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ExportTrigger {
+    /// Interim record for a flow that is still running.
+    ActiveTimeout,
+    /// Final record for a flow that ended or went idle.
+    Finished,
+}
+
+/// What the exporters receive: the flow, its analysis and why it was sent.
+pub(crate) type ExportedFlow = (FlowbeeKey, (FlowbeeLocalData, FlowAnalysis), ExportTrigger);
+
+/// True when a flow started at `start_time` crossed an active-timeout
+/// boundary between two observations (all values in nanoseconds since boot).
+pub(crate) fn crossed_active_timeout(
+    start_time: u64,
+    previous_last_seen: u64,
+    last_seen: u64,
+    timeout_nanos: u64,
+) -> bool {
+    if timeout_nanos == 0 || last_seen <= previous_last_seen {
+        return false;
+    }
+    let periods = |t: u64| t.saturating_sub(start_time) / timeout_nanos;
+    periods(last_seen) > periods(previous_last_seen)
+}
+
+/// Counters already exported for a flow through interim records.
+struct ExportedTotals {
+    bytes_sent: DownUpOrder<u64>,
+    packets_sent: DownUpOrder<u64>,
+    tcp_retransmits: DownUpOrder<u16>,
+    last_seen: u64,
+    updated: Instant,
+}
+
+/// Turns cumulative flow counters into per-record deltas, so that collectors
+/// summing interim and final records count every byte exactly once.
+#[derive(Default)]
+pub(crate) struct FlowDeltas {
+    exported: FxHashMap<FlowbeeKey, ExportedTotals>,
+}
+
+impl FlowDeltas {
+    /// Interim record for a flow still in progress. Covers the traffic since
+    /// the previous record for the same flow.
+    pub(crate) fn active(&mut self, key: FlowbeeKey, data: &FlowbeeLocalData) -> FlowbeeLocalData {
+        let record = self.delta(&key, data);
+        self.exported.insert(
+            key,
+            ExportedTotals {
+                bytes_sent: data.bytes_sent,
+                packets_sent: data.packets_sent,
+                tcp_retransmits: data.tcp_retransmits,
+                last_seen: data.last_seen,
+                updated: Instant::now(),
+            },
+        );
+        record
+    }
+
+    /// Final record for a flow that has ended.
+    pub(crate) fn finished(
+        &mut self,
+        key: FlowbeeKey,
+        data: &FlowbeeLocalData,
+    ) -> FlowbeeLocalData {
+        let record = self.delta(&key, data);
+        self.exported.remove(&key);
+        record
+    }
+
+    /// Forgets flows that stopped producing interim records without a final
+    /// one (e.g. dropped at the flow limit).
+    pub(crate) fn prune(&mut self, max_age: Duration) {
+        self.exported
+            .retain(|_, totals| totals.updated.elapsed() < max_age);
+    }
+
+    fn delta(&self, key: &FlowbeeKey, data: &FlowbeeLocalData) -> FlowbeeLocalData {
+        let mut record = data.clone();
+        if let Some(previous) = self.exported.get(key) {
+            record.start_time = previous.last_seen.min(data.last_seen);
+            record.bytes_sent = data.bytes_sent.checked_sub_or_zero(previous.bytes_sent);
+            record.packets_sent = data.packets_sent.checked_sub_or_zero(previous.packets_sent);
+            record.tcp_retransmits = data
+                .tcp_retransmits
+                .checked_sub_or_zero(previous.tcp_retransmits);
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(bytes: u64, packets: u64, last_seen: u64) -> FlowbeeLocalData {
+        FlowbeeLocalData {
+            start_time: 1_000,
+            last_seen,
+            bytes_sent: DownUpOrder::new(bytes, bytes / 2),
+            packets_sent: DownUpOrder::new(packets, packets / 2),
+            rate_estimate_bps: DownUpOrder::zeroed(),
+            display_rate_bps: None,
+            tcp_retransmits: DownUpOrder::new(0, 0),
+            end_status: 0,
+            tos: 0,
+            tc_handle: 0,
+            cpu: 0,
+            circuit_hash: None,
+            device_hash: None,
+            tcp_info: None,
+        }
+    }
+
+    fn key() -> FlowbeeKey {
+        let mut key = FlowbeeKey::default();
+        key.src_port = 443;
+        key.dst_port = 50000;
+        key.ip_protocol = 6;
+        key
+    }
+
+    #[test]
+    fn templates_refresh_by_packet_count_or_time() {
+        let timing = ExportTiming {
+            flush_interval: Duration::from_secs(1),
+            template_refresh_interval: Duration::from_secs(60),
+            template_refresh_packets: 3,
+        };
+        let mut refresh = timing.template_refresh();
+        let start = Instant::now();
+        assert!(refresh.due(start));
+
+        refresh.templates_sent(start);
+        refresh.packet_sent();
+        refresh.packet_sent();
+        assert!(!refresh.due(start));
+        refresh.packet_sent();
+        assert!(refresh.due(start));
+
+        refresh.templates_sent(start);
+        assert!(!refresh.due(start + Duration::from_secs(59)));
+        assert!(refresh.due(start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn active_timeout_fires_once_per_period() {
+        let second = 1_000_000_000;
+        assert!(!crossed_active_timeout(
+            0,
+            10 * second,
+            59 * second,
+            60 * second
+        ));
+        assert!(crossed_active_timeout(
+            0,
+            59 * second,
+            61 * second,
+            60 * second
+        ));
+        assert!(!crossed_active_timeout(
+            0,
+            61 * second,
+            62 * second,
+            60 * second
+        ));
+        assert!(crossed_active_timeout(
+            0,
+            62 * second,
+            125 * second,
+            60 * second
+        ));
+        assert!(!crossed_active_timeout(0, 59 * second, 61 * second, 0));
+    }
+
+    #[test]
+    fn interim_and_final_records_carry_deltas() {
+        let mut deltas = FlowDeltas::default();
+
+        let first = deltas.active(key(), &flow(1_000, 10, 5_000));
+        assert_eq!(first.bytes_sent.down, 1_000);
+        assert_eq!(first.start_time, 1_000);
+
+        let second = deltas.active(key(), &flow(1_600, 16, 9_000));
+        assert_eq!(second.bytes_sent.down, 600);
+        assert_eq!(second.packets_sent.down, 6);
+        assert_eq!(second.start_time, 5_000);
+
+        let last = deltas.finished(key(), &flow(2_000, 20, 12_000));
+        assert_eq!(last.bytes_sent.down, 400);
+        assert_eq!(last.bytes_sent.up, 200);
+        assert_eq!(last.start_time, 9_000);
+
+        // Once finished, the flow is forgotten.
+        let fresh = deltas.finished(key(), &flow(2_000, 20, 12_000));
+        assert_eq!(fresh.bytes_sent.down, 2_000);
+    }
+}
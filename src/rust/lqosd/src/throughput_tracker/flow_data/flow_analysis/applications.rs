@@ -0,0 +1,365 @@
+//! Rule-driven application classification. Flows are matched against the
+//! operator's rules (`[flows] application_rules`) and then the rules shipped
+//! in `applications.toml`, combining remote ASN, remote prefix, remote port
+//! and IP protocol. The first matching rule names the flow's application.
+
+use super::super::export_filter::parse_subnet;
+use allocative_derive::Allocative;
+use arc_swap::ArcSwap;
+use ip_network_table::IpNetworkTable;
+use lqos_bus::{UrgentSeverity, UrgentSource};
+use lqos_utils::file_watcher::FileWatcher;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+const DEFAULT_RULES: &str = include_str!("applications.toml");
+
+/// Application names, indexed by `ApplicationId`. Only ever grows, so ids
+/// held by existing flows stay valid when the rules are reloaded.
+static APPLICATION_NAMES: Lazy<RwLock<Vec<String>>> =
+    Lazy::new(|| RwLock::new(vec![String::new()]));
+
+static CLASSIFIER: Lazy<ArcSwap<ApplicationClassifier>> = Lazy::new(|| {
+    ArcSwap::from_pointee(
+        ApplicationClassifier::from_toml(DEFAULT_RULES).unwrap_or_else(|e| {
+            warn!("Built-in application rules are invalid: {e}");
+            ApplicationClassifier::default()
+        }),
+    )
+});
+
+/// The application a flow was classified as. `0` is unclassified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Allocative)]
+pub struct ApplicationId(pub u16);
+
+impl ApplicationId {
+    fn intern(name: &str) -> Self {
+        if let Some(index) = APPLICATION_NAMES.read().iter().position(|n| n == name) {
+            return Self(index as u16);
+        }
+        let mut names = APPLICATION_NAMES.write();
+        if let Some(index) = names.iter().position(|n| n == name) {
+            return Self(index as u16);
+        }
+        if names.len() > u16::MAX as usize {
+            return Self(0);
+        }
+        names.push(name.to_string());
+        Self((names.len() - 1) as u16)
+    }
+
+    /// The application name, or an empty string if unclassified.
+    pub fn name(&self) -> String {
+        APPLICATION_NAMES
+            .read()
+            .get(self.0 as usize)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    application: Vec<RuleDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDefinition {
+    name: String,
+    #[serde(default)]
+    asns: Vec<u32>,
+    #[serde(default)]
+    prefixes: Vec<String>,
+    #[serde(default)]
+    ports: Vec<PortDefinition>,
+    #[serde(default)]
+    protocols: Vec<String>,
+}
+
+/// `443` or `"27000-27100"`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PortDefinition {
+    Port(u16),
+    Range(String),
+}
+
+impl PortDefinition {
+    fn to_range(&self) -> Option<RangeInclusive<u16>> {
+        match self {
+            Self::Port(port) => Some(*port..=*port),
+            Self::Range(range) => {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+                let start = start.trim().parse().ok()?;
+                let end = end.trim().parse().ok()?;
+                (start <= end).then_some(start..=end)
+            }
+        }
+    }
+}
+
+struct Rule {
+    application: ApplicationId,
+    asns: Vec<u32>,
+    prefixes: Option<IpNetworkTable<bool>>,
+    ports: Vec<RangeInclusive<u16>>,
+    protocols: Vec<u8>,
+}
+
+impl Rule {
+    fn from_definition(definition: RuleDefinition) -> anyhow::Result<Self> {
+        let name = definition.name.trim();
+        if name.is_empty() {
+            anyhow::bail!("application rule without a name");
+        }
+        if definition.asns.is_empty()
+            && definition.prefixes.is_empty()
+            && definition.ports.is_empty()
+        {
+            anyhow::bail!("application rule '{name}' needs asns, prefixes or ports");
+        }
+        let prefixes = if definition.prefixes.is_empty() {
+            None
+        } else {
+            let mut table = IpNetworkTable::new();
+            for prefix in &definition.prefixes {
+                let Some(network) = parse_subnet(prefix) else {
+                    anyhow::bail!("application rule '{name}' has invalid prefix '{prefix}'");
+                };
+                table.insert(network, true);
+            }
+            Some(table)
+        };
+        let ports = definition
+            .ports
+            .iter()
+            .map(|port| {
+                port.to_range().ok_or_else(|| {
+                    anyhow::anyhow!("application rule '{name}' has an invalid port range")
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut protocols = Vec::new();
+        for protocol in &definition.protocols {
+            match protocol.to_ascii_lowercase().as_str() {
+                "tcp" => protocols.push(6),
+                "udp" => protocols.push(17),
+                "icmp" => protocols.extend([1, 58]),
+                other => anyhow::bail!("application rule '{name}' has unknown protocol '{other}'"),
+            }
+        }
+        Ok(Self {
+            application: ApplicationId::intern(name),
+            asns: definition.asns,
+            prefixes,
+            ports,
+            protocols,
+        })
+    }
+
+    fn matches(&self, remote_ip: IpAddr, asn: u32, protocol: u8, remote_port: u16) -> bool {
+        let has_remote = !self.asns.is_empty() || self.prefixes.is_some();
+        let remote_matches = self.asns.contains(&asn)
+            || self
+                .prefixes
+                .as_ref()
+                .is_some_and(|table| table.longest_match(remote_ip).is_some());
+        if has_remote && !remote_matches {
+            return false;
+        }
+        if !self.ports.is_empty() && !self.ports.iter().any(|r| r.contains(&remote_port)) {
+            return false;
+        }
+        self.protocols.is_empty() || self.protocols.contains(&protocol)
+    }
+}
+
+/// An ordered list of application rules.
+#[derive(Default)]
+pub struct ApplicationClassifier {
+    rules: Vec<Rule>,
+}
+
+impl ApplicationClassifier {
+    /// Parses a rules file.
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        let file: RulesFile = toml::from_str(text)?;
+        let rules = file
+            .application
+            .into_iter()
+            .map(Rule::from_definition)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    /// The first matching rule's application. `remote_port` is the port on
+    /// the remote (service) side of the flow.
+    pub fn classify(
+        &self,
+        remote_ip: IpAddr,
+        asn: u32,
+        protocol: u8,
+        remote_port: u16,
+    ) -> ApplicationId {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(remote_ip, asn, protocol, remote_port))
+            .map(|rule| rule.application)
+            .unwrap_or_default()
+    }
+}
+
+/// Classifies a flow with the currently loaded rules.
+pub fn classify_application(
+    remote_ip: IpAddr,
+    asn: u32,
+    protocol: u8,
+    remote_port: u16,
+) -> ApplicationId {
+    CLASSIFIER
+        .load()
+        .classify(remote_ip, asn, protocol, remote_port)
+}
+
+/// (Re)builds the classifier from the operator's rules followed by the
+/// built-in ones. Invalid operator rules are reported and skipped.
+fn reload_application_rules() {
+    let Ok(mut classifier) = ApplicationClassifier::from_toml(DEFAULT_RULES) else {
+        return;
+    };
+    let path = lqos_config::load_config()
+        .ok()
+        .and_then(|config| config.flows.as_ref()?.application_rules.clone());
+    if let Some(path) = path {
+        let operator = std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| ApplicationClassifier::from_toml(&text));
+        match operator {
+            Ok(operator) => {
+                info!(
+                    "Loaded {} application rules from {path}",
+                    operator.rules.len()
+                );
+                let mut rules = operator.rules;
+                rules.append(&mut classifier.rules);
+                classifier.rules = rules;
+            }
+            Err(e) => {
+                let message = format!("Unable to load application rules {path}: {e}");
+                warn!("{message}");
+                crate::urgent::submit(
+                    UrgentSource::System,
+                    UrgentSeverity::Warning,
+                    "APPLICATION_RULES_FAILED".to_string(),
+                    message,
+                    None,
+                    Some("application_rules".to_string()),
+                );
+            }
+        }
+    }
+    CLASSIFIER.store(Arc::new(classifier));
+}
+
+/// Loads the application rules and reloads them whenever the operator's
+/// rules file changes.
+pub fn start_application_rules() {
+    reload_application_rules();
+    let Some(path) = lqos_config::load_config()
+        .ok()
+        .and_then(|config| config.flows.as_ref()?.application_rules.clone())
+    else {
+        return;
+    };
+    let spawned = std::thread::Builder::new()
+        .name("App Rules Watcher".to_string())
+        .spawn(move || {
+            let mut watcher = FileWatcher::new(&path, PathBuf::from(&path));
+            watcher.set_file_created_callback(reload_application_rules);
+            watcher.set_file_changed_callback(reload_application_rules);
+            loop {
+                let result = watcher.watch();
+                info!("Application rules watcher returned: {result:?}");
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Unable to watch application rules for changes: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_rules_parse() {
+        let classifier = ApplicationClassifier::from_toml(DEFAULT_RULES).expect("valid rules");
+        let ip: IpAddr = "198.51.100.1".parse().expect("ip");
+        assert_eq!(classifier.classify(ip, 2906, 6, 443).name(), "Netflix");
+        assert_eq!(
+            classifier.classify(ip, 8075, 17, 3479).name(),
+            "Microsoft Teams"
+        );
+        assert_eq!(classifier.classify(ip, 8075, 6, 443).name(), "Microsoft");
+        assert_eq!(classifier.classify(ip, 0, 17, 27015).name(), "Steam");
+        assert_eq!(classifier.classify(ip, 0, 6, 27015), ApplicationId(0));
+    }
+
+    #[test]
+    fn first_matching_rule_wins_and_all_fields_must_match() {
+        let classifier = ApplicationClassifier::from_toml(
+            r#"
+            [[application]]
+            name = "Game Servers"
+            prefixes = ["203.0.113.0/24"]
+            ports = [3000, "4000-4010"]
+            protocols = ["udp"]
+
+            [[application]]
+            name = "Example Hosting"
+            asns = [64500]
+            prefixes = ["203.0.113.0/24"]
+            "#,
+        )
+        .expect("valid rules");
+        let inside: IpAddr = "203.0.113.9".parse().expect("ip");
+        let outside: IpAddr = "192.0.2.9".parse().expect("ip");
+        assert_eq!(
+            classifier.classify(inside, 0, 17, 4005).name(),
+            "Game Servers"
+        );
+        assert_eq!(
+            classifier.classify(inside, 0, 6, 4005).name(),
+            "Example Hosting"
+        );
+        assert_eq!(
+            classifier.classify(outside, 64500, 17, 4005).name(),
+            "Example Hosting"
+        );
+        assert_eq!(
+            classifier.classify(outside, 64501, 17, 4005),
+            ApplicationId(0)
+        );
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        for rules in [
+            "[[application]]\nname = \"Nothing\"",
+            "[[application]]\nname = \"X\"\nports = [\"20-10\"]",
+            "[[application]]\nname = \"X\"\nprefixes = [\"not-a-prefix\"]",
+            "[[application]]\nname = \"X\"\nasns = [1]\nprotocols = [\"sctp\"]",
+            "[[application]]\nname = \"X\"\nasn = [1]",
+        ] {
+            assert!(ApplicationClassifier::from_toml(rules).is_err(), "{rules}");
+        }
+    }
+}
@@ -7,11 +7,11 @@ use std::{io::Read, net::IpAddr, path::Path};
 use tracing::{debug, info};
 
 #[derive(Deserialize, Clone, Debug)]
-struct AsnEncoded {
-    network: IpAddr,
-    prefix: u8,
+pub(super) struct AsnEncoded {
+    pub(super) network: IpAddr,
+    pub(super) prefix: u8,
     pub asn: u32,
-    organization: String,
+    pub(super) organization: String,
 }
 
 #[allow(dead_code)]
@@ -29,8 +29,7 @@ pub struct GeoIpLocation {
 impl GeoIpLocation {
     pub fn city_and_country(&self) -> String {
         format!("{}, {}", self.city, self.country)
-            .trim_end_matches(',')
-            .trim()
+            .trim_matches(|c: char| c == ',' || c.is_whitespace())
             .to_string()
     }
 }
@@ -105,13 +104,17 @@ impl GeoTable {
         let mut buffer = Vec::new();
         flate2::read::GzDecoder::new(file).read_to_end(&mut buffer)?;
         let geobin: Geobin = bincode::deserialize(&buffer)?;
+        Ok(Self::from_records(geobin.asn, geobin.geo))
+    }
 
+    /// Builds the lookup tries from decoded records.
+    pub(super) fn from_records(asn: Vec<AsnEncoded>, geo: Vec<GeoIpLocation>) -> Self {
         // Build the ASN trie and ASN lookup map
         let mut asn_lookup = FxHashMap::default();
 
         debug!("Building ASN trie");
         let mut asn_trie = ip_network_table::IpNetworkTable::<AsnEncoded>::new();
-        for entry in geobin.asn {
+        for entry in asn {
             asn_lookup.insert(entry.asn, entry.organization.clone());
             let (ip, prefix) = match entry.network {
                 IpAddr::V4(ip) => (ip.to_ipv6_mapped(), entry.prefix + 96),
@@ -125,7 +128,7 @@ impl GeoTable {
         // Build the GeoIP trie
         debug!("Building GeoIP trie");
         let mut geo_trie = ip_network_table::IpNetworkTable::<GeoIpLocation>::new();
-        for entry in geobin.geo {
+        for entry in geo {
             let (ip, prefix) = match entry.network {
                 IpAddr::V4(ip) => (ip.to_ipv6_mapped(), entry.prefix + 96),
                 IpAddr::V6(ip) => (ip, entry.prefix),
@@ -141,78 +144,66 @@ impl GeoTable {
             geo_trie.len().1
         );
 
-        Ok(Self {
+        Self {
             asn_trie,
             geo_trie,
             asn_lookup,
-        })
-    }
-
-    pub fn find_asn(&self, ip: IpAddr) -> Option<u32> {
-        debug!("Looking up ASN for IP: {:?}", ip);
-        let ip = match ip {
-            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
-            IpAddr::V6(ip) => ip,
-        };
-        if let Some(matched) = self.asn_trie.longest_match(ip) {
-            debug!("Matched ASN: {:?}", matched.1.asn);
-            Some(matched.1.asn)
-        } else {
-            debug!("No ASN found");
-            None
         }
     }
+}
 
-    pub fn find_owners_by_ip(&self, ip: IpAddr) -> AsnNameCountryFlag {
-        debug!("Looking up ASN for IP: {:?}", ip);
-        let ip = match ip {
-            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
-            IpAddr::V6(ip) => ip,
-        };
-        let mut owners = "Unknown".to_string();
-        let mut country = "Unknown".to_string();
-        let mut flag = "Unknown".to_string();
-
-        if let Some(matched) = self.asn_trie.longest_match(ip) {
-            debug!("Matched ASN: {:?}", matched.1.asn);
-            owners = matched.1.organization.clone();
-        }
-        if let Some(matched) = self.geo_trie.longest_match(ip) {
-            debug!("Matched Geo: {:?}", matched.1.city_and_country());
-            country = matched.1.city_and_country();
-            flag = matched.1.country_iso_code.clone();
-        }
-
-        AsnNameCountryFlag {
-            name: owners,
-            country,
-            flag,
-        }
+fn to_v6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
     }
+}
 
-    pub fn find_lat_lon_by_ip(&self, ip: IpAddr) -> (f64, f64) {
+impl GeoLookup for GeoTable {
+    fn owner(&self, ip: IpAddr) -> Option<(u32, String)> {
         debug!("Looking up ASN for IP: {:?}", ip);
-        let ip = match ip {
-            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
-            IpAddr::V6(ip) => ip,
-        };
-
-        if let Some(matched) = self.geo_trie.longest_match(ip) {
-            debug!("Matched Geo: {:?}", matched.1.city_and_country());
-            return (matched.1.latitude, matched.1.longitude);
-        }
+        let matched = self.asn_trie.longest_match(to_v6(ip))?;
+        debug!("Matched ASN: {:?}", matched.1.asn);
+        Some((matched.1.asn, matched.1.organization.clone()))
+    }
 
-        (0.0, 0.0)
+    fn location(&self, ip: IpAddr) -> Option<GeoLocation> {
+        let matched = self.geo_trie.longest_match(to_v6(ip))?;
+        debug!("Matched Geo: {:?}", matched.1.city_and_country());
+        Some(GeoLocation {
+            description: matched.1.city_and_country(),
+            country_iso_code: matched.1.country_iso_code.clone(),
+            latitude: matched.1.latitude,
+            longitude: matched.1.longitude,
+        })
     }
 
-    pub fn find_name_by_id(&self, id: u32) -> String {
-        self.asn_lookup
-            .get(&id)
-            .cloned()
-            .unwrap_or_else(|| "Unknown".to_string())
+    fn asn_name(&self, asn: u32) -> Option<String> {
+        self.asn_lookup.get(&asn).cloned()
     }
 }
 
+/// Where an IP address is, as far as a database knows.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GeoLocation {
+    /// "City, Country", or just the country.
+    pub description: String,
+    pub country_iso_code: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Lookups every ASN/geo source provides. `None` means the source has no
+/// answer, so the next source can be tried.
+pub trait GeoLookup {
+    /// ASN number and organization owning `ip`.
+    fn owner(&self, ip: IpAddr) -> Option<(u32, String)>;
+    /// Location of `ip`.
+    fn location(&self, ip: IpAddr) -> Option<GeoLocation>;
+    /// Organization name for an ASN seen by this source.
+    fn asn_name(&self, asn: u32) -> Option<String>;
+}
+
 #[derive(Default)]
 pub struct AsnNameCountryFlag {
     pub name: String,
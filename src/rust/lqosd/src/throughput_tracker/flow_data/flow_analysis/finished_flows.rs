@@ -1,6 +1,6 @@
 use super::{
-    FlowAnalysis, FlowbeeEffectiveDirection, get_asn_lat_lon, get_asn_name_and_country,
-    get_asn_name_by_id,
+    ApplicationId, FlowAnalysis, FlowbeeEffectiveDirection, get_asn_lat_lon,
+    get_asn_name_and_country, get_asn_name_by_id,
 };
 use crate::shaped_devices_tracker::SHAPED_DEVICES;
 use crate::throughput_tracker::flow_data::FlowbeeLocalData;
@@ -55,6 +55,16 @@ pub struct AsnProtocolListEntry {
     pub protocol: String,
 }
 
+/// Recent traffic for one classified application.
+#[derive(Debug, Serialize)]
+pub struct ApplicationListEntry {
+    pub count: usize,
+    pub application: String,
+    pub bytes: DownUpOrder<u64>,
+    /// Median RTT (nanoseconds) across the application's flows.
+    pub median_rtt_nanos: DownUpOrder<u64>,
+}
+
 impl TimeBuffer {
     fn new() -> Self {
         Self {
@@ -324,6 +334,20 @@ impl TimeBuffer {
             .collect()
     }
 
+    pub fn all_flows_for_application(
+        &self,
+        application: &str,
+    ) -> Vec<(FlowbeeKey, FlowbeeLocalData, FlowAnalysis)> {
+        let buffer = self.buffer.lock();
+        buffer
+            .iter()
+            .filter(|flow| {
+                flow.data.2.application.0 != 0 && flow.data.2.application.name() == application
+            })
+            .map(|flow| flow.data.clone())
+            .collect()
+    }
+
     /// Builds a list of all ASNs with recent data, and how many flows they have.
     pub fn asn_list(&self) -> Vec<AsnListEntry> {
         // 1: Clone: large operation, don't keep the buffer locked longer than we have to
@@ -425,6 +449,51 @@ impl TimeBuffer {
             .collect()
     }
 
+    /// Builds a list of classified applications with recent data: how many
+    /// flows they have, their traffic and median RTT.
+    pub fn application_list(&self) -> Vec<ApplicationListEntry> {
+        let buffer = self.buffer.lock();
+
+        let mut results: FxHashMap<ApplicationId, (usize, DownUpOrder<u64>, [Vec<u64>; 2])> =
+            FxHashMap::default();
+        buffer
+            .iter()
+            .filter(|flow| flow.data.2.application.0 != 0)
+            .for_each(|flow| {
+                let (_key, data, analysis) = &flow.data;
+                let entry = results
+                    .entry(analysis.application)
+                    .or_insert_with(|| (0, DownUpOrder::zeroed(), [Vec::new(), Vec::new()]));
+                entry.0 += 1;
+                entry.1.checked_add(data.bytes_sent);
+                for (i, direction) in [
+                    FlowbeeEffectiveDirection::Download,
+                    FlowbeeEffectiveDirection::Upload,
+                ]
+                .into_iter()
+                .enumerate()
+                {
+                    let rtt = data.get_summary_rtt_as_nanos(direction);
+                    if rtt > 0 {
+                        entry.2[i].push(rtt);
+                    }
+                }
+            });
+        drop(buffer);
+
+        let mut results: Vec<ApplicationListEntry> = results
+            .into_iter()
+            .map(|(application, (count, bytes, rtt))| ApplicationListEntry {
+                count,
+                application: application.name(),
+                bytes,
+                median_rtt_nanos: DownUpOrder::new(Self::median(&rtt[0]), Self::median(&rtt[1])),
+            })
+            .collect();
+        results.sort_by(|a, b| b.bytes.down.cmp(&a.bytes.down));
+        results
+    }
+
     #[allow(dead_code)]
     pub fn len_and_capacity(&self) -> (usize, usize) {
         let buffer = self.buffer.lock();
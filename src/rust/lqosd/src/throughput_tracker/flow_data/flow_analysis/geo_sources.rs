@@ -0,0 +1,275 @@
+//! The ASN/GeoIP sources flow analysis consults: local MMDB or IPinfo CSV
+//! files from `[geoip] databases`, then (if enabled) the downloaded
+//! `geo2.bin`. Each lookup takes the first source with an answer.
+
+use super::asn::{AsnNameCountryFlag, GeoLocation, GeoLookup, GeoTable};
+use super::ipinfo_csv;
+use super::mmdb::{MmdbReader, MmdbValue};
+use fxhash::FxHashMap;
+use parking_lot::Mutex;
+use std::net::IpAddr;
+use std::path::Path;
+use tracing::debug;
+
+/// A MaxMind DB file. ASN names are remembered as they are looked up, so
+/// `asn_name` can answer for any ASN this source has returned.
+pub struct MmdbSource {
+    reader: MmdbReader,
+    asn_names: Mutex<FxHashMap<u32, String>>,
+}
+
+impl MmdbSource {
+    fn record(&self, ip: IpAddr) -> Option<MmdbValue> {
+        match self.reader.lookup(ip) {
+            Ok(record) => record,
+            Err(e) => {
+                debug!("MMDB lookup for {ip} failed: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// ASN in MaxMind/DB-IP (`autonomous_system_number`) or IPinfo (`asn`,
+/// as "AS64500") layout.
+fn mmdb_owner(record: &MmdbValue) -> Option<(u32, String)> {
+    let asn = match record.get("autonomous_system_number") {
+        Some(value) => u32::try_from(value.as_u64()?).ok()?,
+        None => match record.get("asn")? {
+            MmdbValue::String(s) => s.trim_start_matches("AS").parse().ok()?,
+            value => u32::try_from(value.as_u64()?).ok()?,
+        },
+    };
+    let name = ["autonomous_system_organization", "as_name", "name"]
+        .iter()
+        .find_map(|key| record.get(key).and_then(MmdbValue::as_str))
+        .unwrap_or_default()
+        .to_string();
+    Some((asn, name))
+}
+
+/// Location in MaxMind/DB-IP (nested `city`/`country`/`location` maps) or
+/// IPinfo (flat `city`/`country`/`lat`/`lng` strings) layout.
+fn mmdb_location(record: &MmdbValue) -> Option<GeoLocation> {
+    let text = |paths: &[&[&str]]| {
+        paths
+            .iter()
+            .find_map(|path| record.path(path).and_then(MmdbValue::as_str))
+            .unwrap_or_default()
+            .to_string()
+    };
+    let number = |paths: &[&[&str]]| {
+        paths
+            .iter()
+            .find_map(|path| record.path(path).and_then(MmdbValue::as_f64))
+            .unwrap_or_default()
+    };
+    let country_iso_code = text(&[&["country", "iso_code"], &["country"], &["country_code"]]);
+    let country = text(&[&["country", "names", "en"], &["country_name"]]);
+    let city = text(&[&["city", "names", "en"], &["city"]]);
+    if country_iso_code.is_empty() && country.is_empty() && city.is_empty() {
+        return None;
+    }
+    let country = if country.is_empty() {
+        country_iso_code.clone()
+    } else {
+        country
+    };
+    Some(GeoLocation {
+        description: format!("{city}, {country}")
+            .trim_matches(|c: char| c == ',' || c.is_whitespace())
+            .to_string(),
+        country_iso_code,
+        latitude: number(&[&["location", "latitude"], &["lat"], &["latitude"]]),
+        longitude: number(&[&["location", "longitude"], &["lng"], &["longitude"]]),
+    })
+}
+
+impl GeoLookup for MmdbSource {
+    fn owner(&self, ip: IpAddr) -> Option<(u32, String)> {
+        let owner = mmdb_owner(&self.record(ip)?)?;
+        self.asn_names.lock().insert(owner.0, owner.1.clone());
+        Some(owner)
+    }
+
+    fn location(&self, ip: IpAddr) -> Option<GeoLocation> {
+        mmdb_location(&self.record(ip)?)
+    }
+
+    fn asn_name(&self, asn: u32) -> Option<String> {
+        self.asn_names.lock().get(&asn).cloned()
+    }
+}
+
+/// One loaded source.
+pub enum GeoSource {
+    Table(Box<GeoTable>),
+    Mmdb(MmdbSource),
+}
+
+impl GeoSource {
+    /// Loads a `.csv` file as IPinfo CSV and anything else as MaxMind DB.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let is_csv = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+        if is_csv {
+            Ok(Self::Table(Box::new(ipinfo_csv::load(path)?)))
+        } else {
+            let reader = MmdbReader::open(path)?;
+            debug!(
+                "{} is a {} database",
+                path.display(),
+                reader.database_type()
+            );
+            Ok(Self::Mmdb(MmdbSource {
+                reader,
+                asn_names: Mutex::new(FxHashMap::default()),
+            }))
+        }
+    }
+
+    fn lookup(&self) -> &dyn GeoLookup {
+        match self {
+            Self::Table(table) => table.as_ref(),
+            Self::Mmdb(mmdb) => mmdb,
+        }
+    }
+}
+
+/// A local database and the path it was loaded from.
+pub struct LocalDatabase {
+    pub path: String,
+    pub source: GeoSource,
+}
+
+/// Every loaded source, in the order they are consulted.
+#[derive(Default)]
+pub struct GeoDatabases {
+    pub local: Vec<LocalDatabase>,
+    pub downloaded: Option<GeoTable>,
+}
+
+impl GeoDatabases {
+    fn sources(&self) -> impl Iterator<Item = &dyn GeoLookup> {
+        self.local
+            .iter()
+            .map(|db| db.source.lookup())
+            .chain(self.downloaded.iter().map(|t| t as &dyn GeoLookup))
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> (usize, usize, usize, usize) {
+        self.downloaded
+            .as_ref()
+            .map(GeoTable::len)
+            .unwrap_or_default()
+    }
+
+    pub fn find_asn(&self, ip: IpAddr) -> Option<u32> {
+        self.sources()
+            .find_map(|source| source.owner(ip))
+            .map(|(asn, _)| asn)
+    }
+
+    pub fn find_owners_by_ip(&self, ip: IpAddr) -> AsnNameCountryFlag {
+        let owner = self.sources().find_map(|source| source.owner(ip));
+        let location = self.sources().find_map(|source| source.location(ip));
+        let unknown = || "Unknown".to_string();
+        AsnNameCountryFlag {
+            name: owner.map(|(_, name)| name).unwrap_or_else(unknown),
+            country: location
+                .as_ref()
+                .map(|l| l.description.clone())
+                .unwrap_or_else(unknown),
+            flag: location.map(|l| l.country_iso_code).unwrap_or_else(unknown),
+        }
+    }
+
+    pub fn find_lat_lon_by_ip(&self, ip: IpAddr) -> (f64, f64) {
+        self.sources()
+            .find_map(|source| source.location(ip))
+            .map(|l| (l.latitude, l.longitude))
+            .unwrap_or((0.0, 0.0))
+    }
+
+    pub fn find_name_by_id(&self, id: u32) -> String {
+        self.sources()
+            .find_map(|source| source.asn_name(id))
+            .unwrap_or_else(|| "Unknown".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mmdb::tests::{build_ipv4_db, double, map, string, uint32};
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn mmdb(record: Vec<u8>) -> GeoSource {
+        GeoSource::Mmdb(MmdbSource {
+            reader: MmdbReader::from_bytes(build_ipv4_db(Ipv4Addr::new(192, 0, 2, 0), 24, record))
+                .expect("database opens"),
+            asn_names: Mutex::new(FxHashMap::default()),
+        })
+    }
+
+    #[test]
+    fn sources_are_consulted_in_order_per_field() {
+        let asn_db = mmdb(map(&[
+            ("autonomous_system_number", uint32(64_500)),
+            ("autonomous_system_organization", string("Example Networks")),
+        ]));
+        let city_db = mmdb(map(&[
+            ("city", map(&[("names", map(&[("en", string("London"))]))])),
+            (
+                "country",
+                map(&[
+                    ("iso_code", string("GB")),
+                    ("names", map(&[("en", string("United Kingdom"))])),
+                ]),
+            ),
+            (
+                "location",
+                map(&[("latitude", double(51.5)), ("longitude", double(-0.1))]),
+            ),
+        ]));
+        let ipinfo_db = mmdb(map(&[
+            ("asn", string("AS64501")),
+            ("as_name", string("Shadowed")),
+            ("country", string("FR")),
+        ]));
+        let databases = GeoDatabases {
+            local: [asn_db, city_db, ipinfo_db]
+                .into_iter()
+                .map(|source| LocalDatabase {
+                    path: String::new(),
+                    source,
+                })
+                .collect(),
+            downloaded: None,
+        };
+
+        let ip: IpAddr = "192.0.2.1".parse().expect("ip");
+        assert_eq!(databases.find_asn(ip), Some(64_500));
+        assert_eq!(databases.find_name_by_id(64_500), "Example Networks");
+        let owners = databases.find_owners_by_ip(ip);
+        assert_eq!(owners.name, "Example Networks");
+        assert_eq!(owners.country, "London, United Kingdom");
+        assert_eq!(owners.flag, "GB");
+        assert_eq!(databases.find_lat_lon_by_ip(ip), (51.5, -0.1));
+
+        let other: IpAddr = "198.51.100.1".parse().expect("ip");
+        assert_eq!(databases.find_asn(other), None);
+        assert_eq!(databases.find_owners_by_ip(other).name, "Unknown");
+        assert_eq!(databases.find_name_by_id(1), "Unknown");
+
+        assert_eq!(
+            mmdb_owner(&MmdbValue::Map(vec![(
+                "asn".to_string(),
+                MmdbValue::String("AS64501".to_string())
+            )])),
+            Some((64_501, String::new()))
+        );
+    }
+}
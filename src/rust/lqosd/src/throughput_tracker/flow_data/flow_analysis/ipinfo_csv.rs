@@ -0,0 +1,230 @@
+//! Loads IPinfo CSV downloads (ASN, country/ASN and location tables) into a
+//! `GeoTable`.
+//!
+//! Rows are either `network` (CIDR) or `start_ip`/`end_ip` ranges. Any of
+//! `asn`, `as_name`/`name`, `country`, `country_name`, `city`,
+//! `lat`/`latitude` and `lng`/`longitude` are used when present.
+
+use super::asn::{AsnEncoded, GeoIpLocation, GeoTable};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+/// Column positions found in the header row.
+struct Columns {
+    network: Option<usize>,
+    start_ip: Option<usize>,
+    end_ip: Option<usize>,
+    asn: Option<usize>,
+    as_name: Option<usize>,
+    country: Option<usize>,
+    country_name: Option<usize>,
+    city: Option<usize>,
+    latitude: Option<usize>,
+    longitude: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &csv::StringRecord) -> Self {
+        let find = |names: &[&str]| {
+            header
+                .iter()
+                .position(|column| names.contains(&column.trim().to_ascii_lowercase().as_str()))
+        };
+        Self {
+            network: find(&["network", "cidr"]),
+            start_ip: find(&["start_ip"]),
+            end_ip: find(&["end_ip"]),
+            asn: find(&["asn"]),
+            as_name: find(&["as_name", "name", "organization"]),
+            country: find(&["country", "country_code"]),
+            country_name: find(&["country_name"]),
+            city: find(&["city"]),
+            latitude: find(&["lat", "latitude"]),
+            longitude: find(&["lng", "lon", "longitude"]),
+        }
+    }
+}
+
+/// Loads an IPinfo CSV file.
+pub fn load(path: &Path) -> anyhow::Result<GeoTable> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    let columns = Columns::from_header(reader.headers()?);
+    if columns.network.is_none() && (columns.start_ip.is_none() || columns.end_ip.is_none()) {
+        anyhow::bail!("no network or start_ip/end_ip columns");
+    }
+    let mut asn = Vec::new();
+    let mut geo = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|i| record.get(i))
+                .filter(|value| !value.is_empty())
+        };
+        let networks = match columns.network {
+            Some(column) => parse_cidr(record.get(column).unwrap_or_default())
+                .into_iter()
+                .collect(),
+            None => match (
+                field(columns.start_ip).and_then(|ip| ip.parse().ok()),
+                field(columns.end_ip).and_then(|ip| ip.parse().ok()),
+            ) {
+                (Some(start), Some(end)) => range_to_cidrs(start, end),
+                _ => Vec::new(),
+            },
+        };
+        if networks.is_empty() {
+            continue;
+        }
+
+        let asn_number = field(columns.asn).and_then(parse_asn);
+        let latitude = field(columns.latitude).and_then(|v| v.parse::<f64>().ok());
+        let longitude = field(columns.longitude).and_then(|v| v.parse::<f64>().ok());
+        let country_iso_code = field(columns.country).unwrap_or_default();
+        let has_geo = !country_iso_code.is_empty() || field(columns.city).is_some();
+        for (network, prefix) in networks {
+            if let Some(asn_number) = asn_number {
+                asn.push(AsnEncoded {
+                    network,
+                    prefix,
+                    asn: asn_number,
+                    organization: field(columns.as_name).unwrap_or_default().to_string(),
+                });
+            }
+            if has_geo {
+                geo.push(GeoIpLocation {
+                    network,
+                    prefix,
+                    latitude: latitude.unwrap_or_default(),
+                    longitude: longitude.unwrap_or_default(),
+                    city: field(columns.city).unwrap_or_default().to_string(),
+                    country: field(columns.country_name)
+                        .unwrap_or(country_iso_code)
+                        .to_string(),
+                    country_iso_code: country_iso_code.to_string(),
+                });
+            }
+        }
+    }
+    Ok(GeoTable::from_records(asn, geo))
+}
+
+/// `AS15169` or `15169`.
+fn parse_asn(value: &str) -> Option<u32> {
+    let digits = value
+        .strip_prefix("AS")
+        .or_else(|| value.strip_prefix("as"))
+        .unwrap_or(value);
+    digits.parse().ok()
+}
+
+fn parse_cidr(value: &str) -> Option<(IpAddr, u8)> {
+    let (ip, prefix) = value.split_once('/').unwrap_or((value, ""));
+    let ip: IpAddr = ip.parse().ok()?;
+    let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        "" => max_prefix,
+        prefix => prefix.parse().ok().filter(|p| *p <= max_prefix)?,
+    };
+    Some((ip, prefix))
+}
+
+/// The smallest set of CIDR blocks covering `start..=end`.
+fn range_to_cidrs(start: IpAddr, end: IpAddr) -> Vec<(IpAddr, u8)> {
+    let (start, end, width, to_ip): (u128, u128, u32, fn(u128) -> IpAddr) = match (start, end) {
+        (IpAddr::V4(start), IpAddr::V4(end)) => (
+            u128::from(u32::from(start)),
+            u128::from(u32::from(end)),
+            32,
+            |n| IpAddr::V4(Ipv4Addr::from(n as u32)),
+        ),
+        (IpAddr::V6(start), IpAddr::V6(end)) => (u128::from(start), u128::from(end), 128, |n| {
+            IpAddr::V6(Ipv6Addr::from(n))
+        }),
+        _ => return Vec::new(),
+    };
+    let mut blocks = Vec::new();
+    let mut current = start;
+    while current <= end {
+        // Largest block aligned at `current` that does not pass `end`.
+        let mut size_bits = current.trailing_zeros().min(width);
+        while size_bits > 0 {
+            if size_bits < 128 && current.saturating_add((1u128 << size_bits) - 1) <= end {
+                break;
+            }
+            size_bits -= 1;
+        }
+        blocks.push((to_ip(current), (width - size_bits) as u8));
+        let step = 1u128 << size_bits.min(127);
+        match current.checked_add(step) {
+            Some(next) => current = next,
+            None => break,
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::asn::GeoLookup;
+    use super::*;
+
+    #[test]
+    fn ranges_become_minimal_cidr_blocks() {
+        let v4 = |s: &str| s.parse::<IpAddr>().expect("ip");
+        assert_eq!(
+            range_to_cidrs(v4("10.0.0.0"), v4("10.0.0.255")),
+            vec![(v4("10.0.0.0"), 24)]
+        );
+        assert_eq!(
+            range_to_cidrs(v4("10.0.0.1"), v4("10.0.0.4")),
+            vec![
+                (v4("10.0.0.1"), 32),
+                (v4("10.0.0.2"), 31),
+                (v4("10.0.0.4"), 32)
+            ]
+        );
+        assert_eq!(
+            range_to_cidrs(v4("2001:db8::"), v4("2001:db8::ffff")),
+            vec![(v4("2001:db8::"), 112)]
+        );
+        assert!(range_to_cidrs(v4("10.0.0.1"), v4("::1")).is_empty());
+        assert_eq!(parse_asn("AS64500"), Some(64_500));
+        assert_eq!(parse_asn("64500"), Some(64_500));
+    }
+
+    #[test]
+    fn loads_ipinfo_country_asn_csv() {
+        let path =
+            std::env::temp_dir().join(format!("lqosd-ipinfo-test-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "start_ip,end_ip,country,country_name,continent,continent_name,asn,as_name,as_domain\n\
+             192.0.2.0,192.0.2.255,GB,United Kingdom,EU,Europe,AS64500,Example Networks,example.net\n\
+             198.51.100.0,198.51.100.127,FR,France,EU,Europe,,,\n",
+        )
+        .expect("write test csv");
+        let table = load(&path).expect("csv loads");
+        let _ = std::fs::remove_file(&path);
+
+        let ip = "192.0.2.10".parse().expect("ip");
+        assert_eq!(
+            table.owner(ip),
+            Some((64_500, "Example Networks".to_string()))
+        );
+        assert_eq!(table.asn_name(64_500).as_deref(), Some("Example Networks"));
+        let location = table.location(ip).expect("location");
+        assert_eq!(location.description, "United Kingdom");
+        assert_eq!(location.country_iso_code, "GB");
+
+        let ip = "198.51.100.5".parse().expect("ip");
+        assert_eq!(table.owner(ip), None);
+        assert_eq!(
+            table.location(ip).map(|l| l.country_iso_code).as_deref(),
+            Some("FR")
+        );
+    }
+}
@@ -0,0 +1,491 @@
+//! A minimal reader for MaxMind DB (`.mmdb`) files, as published by MaxMind
+//! (GeoLite2/GeoIP2), DB-IP and IPinfo. Only lookups are supported; the
+//! whole file is held in memory and searched in place.
+//!
+//! Format reference: <https://maxmind.github.io/MaxMind-DB/>
+
+use std::net::IpAddr;
+use std::path::Path;
+
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+/// The metadata section is at most 128KiB from the end of the file.
+const METADATA_MAX_SIZE: usize = 128 * 1024;
+const DATA_SECTION_SEPARATOR: usize = 16;
+/// Nesting limit for maps and arrays, to bound recursion on corrupt files.
+const MAX_DEPTH: usize = 32;
+
+/// A decoded value from the data section.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MmdbValue {
+    String(String),
+    Double(f64),
+    Bytes(Vec<u8>),
+    Uint(u128),
+    Int(i32),
+    Bool(bool),
+    Map(Vec<(String, MmdbValue)>),
+    Array(Vec<MmdbValue>),
+}
+
+impl MmdbValue {
+    /// Looks up a key in a map value.
+    pub fn get(&self, key: &str) -> Option<&MmdbValue> {
+        match self {
+            MmdbValue::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Follows a path of map keys, e.g. `["city", "names", "en"]`.
+    pub fn path(&self, keys: &[&str]) -> Option<&MmdbValue> {
+        keys.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            MmdbValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            MmdbValue::Uint(n) => u64::try_from(*n).ok(),
+            MmdbValue::Int(n) => u64::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+
+    /// Numbers, or strings holding a number (IPinfo stores coordinates as
+    /// strings).
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            MmdbValue::Double(n) => Some(*n),
+            MmdbValue::Uint(n) => Some(*n as f64),
+            MmdbValue::Int(n) => Some(f64::from(*n)),
+            MmdbValue::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Why an MMDB file could not be used.
+#[derive(Debug, thiserror::Error)]
+pub enum MmdbError {
+    #[error("unable to read the file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("no MaxMind DB metadata found")]
+    NoMetadata,
+    #[error("invalid metadata: {0}")]
+    InvalidMetadata(&'static str),
+    #[error("unsupported record size {0}")]
+    UnsupportedRecordSize(u64),
+    #[error("corrupt data section at offset {0}")]
+    CorruptData(usize),
+}
+
+/// An opened MaxMind DB.
+pub struct MmdbReader {
+    buffer: Vec<u8>,
+    node_count: usize,
+    record_size: usize,
+    ip_version: u64,
+    data_start: usize,
+    /// Node at which IPv4 lookups start in an IPv6 tree (after 96 zero bits).
+    ipv4_start: usize,
+    database_type: String,
+}
+
+impl MmdbReader {
+    pub fn open(path: &Path) -> Result<Self, MmdbError> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(buffer: Vec<u8>) -> Result<Self, MmdbError> {
+        let search_from = buffer.len().saturating_sub(METADATA_MAX_SIZE);
+        let marker = buffer[search_from..]
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or(MmdbError::NoMetadata)?;
+        let metadata_start = search_from + marker + METADATA_MARKER.len();
+        let metadata = Decoder {
+            data: &buffer[metadata_start..],
+        }
+        .decode(0, 0)?
+        .0;
+
+        let field = |name: &'static str| {
+            metadata
+                .get(name)
+                .and_then(MmdbValue::as_u64)
+                .ok_or(MmdbError::InvalidMetadata(name))
+        };
+        let node_count = field("node_count")? as usize;
+        let record_size = field("record_size")?;
+        let ip_version = field("ip_version")?;
+        if !matches!(record_size, 24 | 28 | 32) {
+            return Err(MmdbError::UnsupportedRecordSize(record_size));
+        }
+        let record_size = record_size as usize;
+        let tree_size = node_count
+            .checked_mul(record_size / 4)
+            .ok_or(MmdbError::InvalidMetadata("node_count"))?;
+        let data_start = tree_size + DATA_SECTION_SEPARATOR;
+        if data_start > metadata_start {
+            return Err(MmdbError::InvalidMetadata("node_count"));
+        }
+        let database_type = metadata
+            .get("database_type")
+            .and_then(MmdbValue::as_str)
+            .unwrap_or_default()
+            .to_string();
+
+        let mut reader = Self {
+            buffer,
+            node_count,
+            record_size,
+            ip_version,
+            data_start,
+            ipv4_start: 0,
+            database_type,
+        };
+        if ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= node_count {
+                    break;
+                }
+                node = reader.read_record(node, 0)?;
+            }
+            reader.ipv4_start = node;
+        }
+        Ok(reader)
+    }
+
+    /// The `database_type` from the metadata, e.g. `GeoLite2-ASN`.
+    pub fn database_type(&self) -> &str {
+        &self.database_type
+    }
+
+    fn read_record(&self, node: usize, bit: u8) -> Result<usize, MmdbError> {
+        let node_bytes = self.record_size / 4;
+        let offset = node * node_bytes;
+        let bytes = self
+            .buffer
+            .get(offset..offset + node_bytes)
+            .ok_or(MmdbError::CorruptData(offset))?;
+        let be = |b: &[u8]| b.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        Ok(match (self.record_size, bit) {
+            (24, 0) => be(&bytes[0..3]),
+            (24, _) => be(&bytes[3..6]),
+            (28, 0) => ((bytes[3] as usize & 0xF0) << 20) | be(&bytes[0..3]),
+            (28, _) => ((bytes[3] as usize & 0x0F) << 24) | be(&bytes[4..7]),
+            (_, 0) => be(&bytes[0..4]),
+            (_, _) => be(&bytes[4..8]),
+        })
+    }
+
+    /// The record for the network containing `ip`, if any.
+    pub fn lookup(&self, ip: IpAddr) -> Result<Option<MmdbValue>, MmdbError> {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            IpAddr::V4(_) => ip,
+        };
+        let (bits, bit_count, mut node): (u128, usize, usize) = match ip {
+            IpAddr::V4(v4) => (u128::from(u32::from(v4)) << 96, 32, self.ipv4_start),
+            IpAddr::V6(_) if self.ip_version == 4 => return Ok(None),
+            IpAddr::V6(v6) => (u128::from(v6), 128, 0),
+        };
+        for i in 0..bit_count {
+            if node >= self.node_count {
+                break;
+            }
+            let bit = ((bits >> (127 - i)) & 1) as u8;
+            node = self.read_record(node, bit)?;
+        }
+        if node <= self.node_count {
+            return Ok(None);
+        }
+        // Pointers into the separator between the tree and the data are corrupt.
+        let offset = (node - self.node_count)
+            .checked_sub(DATA_SECTION_SEPARATOR)
+            .ok_or(MmdbError::CorruptData(node))?;
+        let data = self
+            .buffer
+            .get(self.data_start..)
+            .ok_or(MmdbError::CorruptData(self.data_start))?;
+        Ok(Some(Decoder { data }.decode(offset, 0)?.0))
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8], MmdbError> {
+        self.data
+            .get(offset..offset + len)
+            .ok_or(MmdbError::CorruptData(offset))
+    }
+
+    fn uint(&self, offset: usize, len: usize) -> Result<u128, MmdbError> {
+        Ok(self
+            .bytes(offset, len)?
+            .iter()
+            .fold(0u128, |acc, b| (acc << 8) | u128::from(*b)))
+    }
+
+    /// Decodes the value at `offset`, returning it and the offset just past it.
+    fn decode(&self, offset: usize, depth: usize) -> Result<(MmdbValue, usize), MmdbError> {
+        if depth > MAX_DEPTH {
+            return Err(MmdbError::CorruptData(offset));
+        }
+        let control = *self.bytes(offset, 1)?.first().unwrap_or(&0);
+        let mut pos = offset + 1;
+        let mut kind = control >> 5;
+        if kind == 0 {
+            kind = 7 + *self.bytes(pos, 1)?.first().unwrap_or(&0);
+            pos += 1;
+        }
+        if kind == 1 {
+            let size = ((control >> 3) & 0x3) as usize;
+            let high = u128::from(control & 0x7);
+            let target = match size {
+                0 => (high << 8 | self.uint(pos, 1)?) as usize,
+                1 => ((high << 16 | self.uint(pos, 2)?) + 2048) as usize,
+                2 => ((high << 24 | self.uint(pos, 3)?) + 526_336) as usize,
+                _ => self.uint(pos, 4)? as usize,
+            };
+            let (value, _) = self.decode(target, depth + 1)?;
+            return Ok((value, pos + size + 1));
+        }
+        let mut size = (control & 0x1f) as usize;
+        match size {
+            29 => {
+                size = 29 + self.uint(pos, 1)? as usize;
+                pos += 1;
+            }
+            30 => {
+                size = 285 + self.uint(pos, 2)? as usize;
+                pos += 2;
+            }
+            31 => {
+                size = 65_821 + self.uint(pos, 3)? as usize;
+                pos += 3;
+            }
+            _ => {}
+        }
+        let value = match kind {
+            2 => MmdbValue::String(String::from_utf8_lossy(self.bytes(pos, size)?).into_owned()),
+            3 => {
+                let raw: [u8; 8] = self
+                    .bytes(pos, 8)?
+                    .try_into()
+                    .map_err(|_| MmdbError::CorruptData(pos))?;
+                size = 8;
+                MmdbValue::Double(f64::from_be_bytes(raw))
+            }
+            4 => MmdbValue::Bytes(self.bytes(pos, size)?.to_vec()),
+            5 | 6 | 9 | 10 => MmdbValue::Uint(self.uint(pos, size.min(16))?),
+            8 => MmdbValue::Int(self.uint(pos, size.min(4))? as u32 as i32),
+            7 => {
+                let mut entries = Vec::with_capacity(size.min(64));
+                for _ in 0..size {
+                    let (key, next) = self.decode(pos, depth + 1)?;
+                    let (value, next) = self.decode(next, depth + 1)?;
+                    pos = next;
+                    if let MmdbValue::String(key) = key {
+                        entries.push((key, value));
+                    }
+                }
+                return Ok((MmdbValue::Map(entries), pos));
+            }
+            11 => {
+                let mut items = Vec::with_capacity(size.min(64));
+                for _ in 0..size {
+                    let (value, next) = self.decode(pos, depth + 1)?;
+                    pos = next;
+                    items.push(value);
+                }
+                return Ok((MmdbValue::Array(items), pos));
+            }
+            14 => {
+                let value = MmdbValue::Bool(size != 0);
+                size = 0;
+                value
+            }
+            15 => {
+                let raw: [u8; 4] = self
+                    .bytes(pos, 4)?
+                    .try_into()
+                    .map_err(|_| MmdbError::CorruptData(pos))?;
+                size = 4;
+                MmdbValue::Double(f64::from(f32::from_be_bytes(raw)))
+            }
+            _ => return Err(MmdbError::CorruptData(offset)),
+        };
+        Ok((value, pos + size))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn control(kind: u8, size: usize) -> Vec<u8> {
+        assert!(size < 285);
+        let (size_bits, extra) = if size < 29 {
+            (size as u8, None)
+        } else {
+            (29, Some((size - 29) as u8))
+        };
+        let mut out = if kind <= 7 {
+            vec![(kind << 5) | size_bits]
+        } else {
+            vec![size_bits, kind - 7]
+        };
+        out.extend(extra);
+        out
+    }
+
+    pub(crate) fn string(s: &str) -> Vec<u8> {
+        let mut out = control(2, s.len());
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    pub(crate) fn uint32(n: u32) -> Vec<u8> {
+        let mut out = control(6, 4);
+        out.extend_from_slice(&n.to_be_bytes());
+        out
+    }
+
+    pub(crate) fn double(n: f64) -> Vec<u8> {
+        let mut out = control(3, 8);
+        out.extend_from_slice(&n.to_be_bytes());
+        out
+    }
+
+    pub(crate) fn map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut out = control(7, entries.len());
+        for (key, value) in entries {
+            out.extend(string(key));
+            out.extend_from_slice(value);
+        }
+        out
+    }
+
+    /// Builds an IPv4 database (24-bit records) holding one record for
+    /// `network/prefix`.
+    pub(crate) fn build_ipv4_db(network: Ipv4Addr, prefix: usize, record: Vec<u8>) -> Vec<u8> {
+        let node_count = prefix;
+        let data_pointer = node_count + DATA_SECTION_SEPARATOR;
+        let bits = u32::from(network);
+        let mut out = Vec::new();
+        for node in 0..node_count {
+            let bit = (bits >> (31 - node)) & 1;
+            let next = if node + 1 == node_count {
+                data_pointer
+            } else {
+                node + 1
+            };
+            let (left, right) = if bit == 0 {
+                (next, node_count)
+            } else {
+                (node_count, next)
+            };
+            out.extend_from_slice(&(left as u32).to_be_bytes()[1..]);
+            out.extend_from_slice(&(right as u32).to_be_bytes()[1..]);
+        }
+        out.extend_from_slice(&[0; DATA_SECTION_SEPARATOR]);
+        out.extend(record);
+        out.extend_from_slice(METADATA_MARKER);
+        out.extend(map(&[
+            ("node_count", uint32(node_count as u32)),
+            ("record_size", uint32(24)),
+            ("ip_version", uint32(4)),
+            ("database_type", string("Test-ASN")),
+        ]));
+        out
+    }
+
+    #[test]
+    fn looks_up_records_in_the_search_tree() {
+        let record = map(&[
+            ("autonomous_system_number", uint32(64_500)),
+            ("autonomous_system_organization", string("Example Networks")),
+            ("location", map(&[("latitude", double(51.5))])),
+        ]);
+        let reader = MmdbReader::from_bytes(build_ipv4_db(Ipv4Addr::new(192, 0, 2, 0), 24, record))
+            .expect("database opens");
+        assert_eq!(reader.database_type(), "Test-ASN");
+
+        let found = reader
+            .lookup("192.0.2.77".parse().expect("ip"))
+            .expect("lookup works")
+            .expect("record found");
+        assert_eq!(
+            found
+                .get("autonomous_system_number")
+                .and_then(MmdbValue::as_u64),
+            Some(64_500)
+        );
+        assert_eq!(
+            found
+                .get("autonomous_system_organization")
+                .and_then(MmdbValue::as_str),
+            Some("Example Networks")
+        );
+        assert_eq!(
+            found
+                .path(&["location", "latitude"])
+                .and_then(MmdbValue::as_f64),
+            Some(51.5)
+        );
+        // IPv4-mapped IPv6 addresses find the IPv4 record.
+        assert!(
+            reader
+                .lookup("::ffff:192.0.2.1".parse().expect("ip"))
+                .expect("lookup works")
+                .is_some()
+        );
+        assert!(
+            reader
+                .lookup("198.51.100.1".parse().expect("ip"))
+                .expect("lookup works")
+                .is_none()
+        );
+        assert!(
+            reader
+                .lookup("2001:db8::1".parse().expect("ip"))
+                .expect("lookup works")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn rejects_records_pointing_into_the_data_section_separator() {
+        let mut bytes = build_ipv4_db(Ipv4Addr::new(192, 0, 2, 0), 24, uint32(1));
+        let data_pointer = (24 + DATA_SECTION_SEPARATOR) as u32;
+        for record in bytes[23 * 6..24 * 6].chunks_mut(3) {
+            if record == &data_pointer.to_be_bytes()[1..] {
+                record.copy_from_slice(&(24u32 + 5).to_be_bytes()[1..]);
+            }
+        }
+        let reader = MmdbReader::from_bytes(bytes).expect("database opens");
+        assert!(matches!(
+            reader.lookup("192.0.2.1".parse().expect("ip")),
+            Err(MmdbError::CorruptData(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_files_without_metadata() {
+        assert!(matches!(
+            MmdbReader::from_bytes(vec![0; 64]),
+            Err(MmdbError::NoMetadata)
+        ));
+    }
+}
@@ -4,59 +4,138 @@ use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use serde::Serialize;
 use std::net::IpAddr;
-use tracing::error;
+use std::path::PathBuf;
+use tracing::{error, info, warn};
 
+mod applications;
 mod asn;
+mod geo_sources;
+mod ipinfo_csv;
+mod mmdb;
 mod protocol;
 use super::AsnId;
+pub use applications::ApplicationId;
 pub use protocol::FlowProtocol;
 mod finished_flows;
 pub use finished_flows::FinishedFlowAnalysis;
 pub use finished_flows::RECENT_FLOWS;
 mod kernel_ringbuffer;
 use crate::throughput_tracker::flow_data::flow_analysis::asn::AsnNameCountryFlag;
-pub use finished_flows::{AsnCountryListEntry, AsnListEntry, AsnProtocolListEntry};
+pub use finished_flows::{
+    ApplicationListEntry, AsnCountryListEntry, AsnListEntry, AsnProtocolListEntry,
+};
+use geo_sources::{GeoDatabases, GeoSource, LocalDatabase};
 pub use kernel_ringbuffer::*;
+use lqos_bus::{UrgentSeverity, UrgentSource};
+use lqos_utils::file_watcher::FileWatcher;
 pub use lqos_utils::rtt::{FlowbeeEffectiveDirection, RttBuffer, RttData};
 
 static ANALYSIS: Lazy<FlowAnalysisSystem> = Lazy::new(FlowAnalysisSystem::new);
 
 pub struct FlowAnalysisSystem {
-    asn_table: Mutex<Option<asn::GeoTable>>,
+    asn_table: Mutex<GeoDatabases>,
 }
 
 impl FlowAnalysisSystem {
     pub fn new() -> Self {
-        // Moved from being periodically updated to being updated on startup
+        // Local databases are loaded at startup and whenever they change;
+        // geo2.bin, if enabled, is refreshed hourly.
         let _ = std::thread::Builder::new()
             .name("GeoTable Updater".to_string())
             .spawn(|| {
+                reload_local_geo_databases();
+                watch_local_geo_databases();
                 loop {
-                    let result = asn::GeoTable::load();
-                    match result {
-                        Ok(table) => {
-                            ANALYSIS.asn_table.lock().replace(table);
-                        }
-                        Err(e) => {
-                            error!("Failed to update ASN table: {e}");
+                    let download = lqos_config::load_config()
+                        .map(|config| config.geoip.download_enabled())
+                        .unwrap_or(true);
+                    if download {
+                        match asn::GeoTable::load() {
+                            Ok(table) => {
+                                ANALYSIS.asn_table.lock().downloaded = Some(table);
+                            }
+                            Err(e) => {
+                                error!("Failed to update ASN table: {e}");
+                            }
                         }
+                    } else {
+                        ANALYSIS.asn_table.lock().downloaded = None;
                     }
                     std::thread::sleep(std::time::Duration::from_secs(60 * 60));
                 }
             });
 
         Self {
-            asn_table: Mutex::new(None),
+            asn_table: Mutex::new(GeoDatabases::default()),
         }
     }
 
     #[allow(dead_code)]
     pub fn len_and_capacity() -> (usize, usize, usize, usize) {
-        let lock = ANALYSIS.asn_table.lock();
-        if let Some(table) = lock.as_ref() {
-            table.len()
-        } else {
-            (0, 0, 0, 0)
+        ANALYSIS.asn_table.lock().len()
+    }
+}
+
+/// (Re)loads every `[geoip] databases` entry. A database that fails to load
+/// keeps its previously loaded contents, if any.
+fn reload_local_geo_databases() {
+    let paths = lqos_config::load_config()
+        .map(|config| config.geoip.databases.clone())
+        .unwrap_or_default();
+    let loaded: Vec<(String, anyhow::Result<GeoSource>)> = paths
+        .into_iter()
+        .map(|path| {
+            let source = GeoSource::load(std::path::Path::new(&path));
+            (path, source)
+        })
+        .collect();
+
+    let mut tables = ANALYSIS.asn_table.lock();
+    let mut previous = std::mem::take(&mut tables.local);
+    for (path, source) in loaded {
+        match source {
+            Ok(source) => {
+                info!("Loaded ASN/GeoIP database {path}");
+                tables.local.push(LocalDatabase { path, source });
+            }
+            Err(e) => {
+                let message = format!("Unable to load ASN/GeoIP database {path}: {e}");
+                warn!("{message}");
+                crate::urgent::submit(
+                    UrgentSource::System,
+                    UrgentSeverity::Warning,
+                    "GEOIP_DATABASE_FAILED".to_string(),
+                    message,
+                    None,
+                    Some(format!("geoip_{path}")),
+                );
+                if let Some(index) = previous.iter().position(|db| db.path == path) {
+                    tables.local.push(previous.swap_remove(index));
+                }
+            }
+        }
+    }
+}
+
+/// Starts a watcher per local database that reloads them on change.
+fn watch_local_geo_databases() {
+    let paths = lqos_config::load_config()
+        .map(|config| config.geoip.databases.clone())
+        .unwrap_or_default();
+    for path in paths {
+        let spawned = std::thread::Builder::new()
+            .name("GeoIP Watcher".to_string())
+            .spawn(move || {
+                let mut watcher = FileWatcher::new(&path, PathBuf::from(&path));
+                watcher.set_file_created_callback(reload_local_geo_databases);
+                watcher.set_file_changed_callback(reload_local_geo_databases);
+                loop {
+                    let result = watcher.watch();
+                    info!("GeoIP database watcher returned: {result:?}");
+                }
+            });
+        if let Err(e) = spawned {
+            warn!("Unable to watch ASN/GeoIP databases for changes: {e}");
         }
     }
 }
@@ -66,6 +145,7 @@ pub fn setup_flow_analysis() -> anyhow::Result<()> {
     // data.
     let black_box = ANALYSIS.asn_table.lock();
     drop(black_box);
+    applications::start_application_rules();
     Ok(())
 }
 
@@ -73,47 +153,36 @@ pub fn setup_flow_analysis() -> anyhow::Result<()> {
 pub struct FlowAnalysis {
     pub asn_id: AsnId,
     pub protocol_analysis: FlowProtocol,
+    pub application: ApplicationId,
 }
 
 impl FlowAnalysis {
     pub fn new(key: &FlowbeeKey) -> Self {
-        let asn_id = lookup_asn_id(key.remote_ip.as_ip());
+        let remote_ip = key.remote_ip.as_ip();
+        let asn_id = lookup_asn_id(remote_ip).unwrap_or(0);
         let protocol_analysis = FlowProtocol::new(key);
+        let application =
+            applications::classify_application(remote_ip, asn_id, key.ip_protocol, key.src_port);
         Self {
-            asn_id: AsnId(asn_id.unwrap_or(0)),
+            asn_id: AsnId(asn_id),
             protocol_analysis,
+            application,
         }
     }
 }
 
 pub fn lookup_asn_id(ip: IpAddr) -> Option<u32> {
-    let table_lock = ANALYSIS.asn_table.lock();
-    if let Some(table) = table_lock.as_ref() {
-        return table.find_asn(ip);
-    }
-    None
+    ANALYSIS.asn_table.lock().find_asn(ip)
 }
 
 pub fn get_asn_name_and_country(ip: IpAddr) -> AsnNameCountryFlag {
-    let table_lock = ANALYSIS.asn_table.lock();
-    if let Some(table) = table_lock.as_ref() {
-        return table.find_owners_by_ip(ip);
-    }
-    AsnNameCountryFlag::default()
+    ANALYSIS.asn_table.lock().find_owners_by_ip(ip)
 }
 
 pub fn get_asn_lat_lon(ip: IpAddr) -> (f64, f64) {
-    let table_lock = ANALYSIS.asn_table.lock();
-    if let Some(table) = table_lock.as_ref() {
-        return table.find_lat_lon_by_ip(ip);
-    }
-    (0.0, 0.0)
+    ANALYSIS.asn_table.lock().find_lat_lon_by_ip(ip)
 }
 
 pub fn get_asn_name_by_id(id: u32) -> String {
-    let table_lock = ANALYSIS.asn_table.lock();
-    if let Some(table) = table_lock.as_ref() {
-        return table.find_name_by_id(id);
-    }
-    "Unknown".to_string()
+    ANALYSIS.asn_table.lock().find_name_by_id(id)
 }
@@ -0,0 +1,407 @@
+//! IPFIX (NetFlow v10) exporter.
+//!
+//! Unlike the v5/v9 exporters, IPFIX records carry the TCP RTT, retransmit,
+//! circuit and ASN data computed by the flow tracker, using enterprise-specific
+//! information elements under the configured Private Enterprise Number.
+
+mod protocol;
+
+use self::protocol::{
+    FlowEndReason, IpfixFlowRecord, MAX_MESSAGE_LEN, MESSAGE_HEADER_LEN, data_set, message,
+    template_set,
+};
+use super::export_timing::{ExportTiming, ExportTrigger, ExportedFlow, TemplateRefresh};
+use super::{FlowAnalysis, FlowbeeEffectiveDirection, FlowbeeLocalData};
+use crate::shaped_devices_tracker::{SHAPED_DEVICE_HASH_CACHE, SHAPED_DEVICES};
+use crossbeam_channel::{RecvTimeoutError, Sender};
+use lqos_sys::flowbee_data::FlowbeeKey;
+use lqos_utils::unix_time::time_since_boot;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const SET_HEADER_LEN: usize = 4;
+
+pub(crate) struct Ipfix {}
+
+impl Ipfix {
+    pub(crate) fn start(
+        target: String,
+        enterprise_number: u32,
+        timing: ExportTiming,
+    ) -> anyhow::Result<Sender<ExportedFlow>> {
+        let (tx, rx) = crossbeam_channel::bounded::<ExportedFlow>(65535);
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+
+        std::thread::Builder::new()
+            .name("IPFIX".to_string())
+            .spawn(move || {
+                let mut exporter = IpfixExporter::new(socket, target, enterprise_number, timing);
+                loop {
+                    match rx.recv_timeout(timing.flush_interval) {
+                        Ok((key, (data, analysis), trigger)) => {
+                            exporter.push(&key, &data, &analysis, trigger)
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                    if exporter.should_flush() {
+                        exporter.flush();
+                    }
+                }
+                exporter.flush();
+            })?;
+
+        Ok(tx)
+    }
+}
+
+struct IpfixExporter {
+    socket: UdpSocket,
+    target: String,
+    enterprise_number: u32,
+    /// Data records sent so far; IPFIX sequence numbers count records, not
+    /// messages.
+    sequence: u32,
+    flush_interval: Duration,
+    /// Collectors restarting mid-stream can't decode data sets until they
+    /// have seen the templates again.
+    templates: TemplateRefresh,
+    last_flush: Instant,
+    pending: Vec<(u16, Vec<u8>)>,
+    pending_bytes: usize,
+}
+
+impl IpfixExporter {
+    fn new(
+        socket: UdpSocket,
+        target: String,
+        enterprise_number: u32,
+        timing: ExportTiming,
+    ) -> Self {
+        Self {
+            socket,
+            target,
+            enterprise_number,
+            sequence: 0,
+            flush_interval: timing.flush_interval,
+            templates: timing.template_refresh(),
+            last_flush: Instant::now(),
+            pending: Vec::new(),
+            pending_bytes: 0,
+        }
+    }
+
+    fn push(
+        &mut self,
+        key: &FlowbeeKey,
+        data: &FlowbeeLocalData,
+        analysis: &FlowAnalysis,
+        trigger: ExportTrigger,
+    ) {
+        // Exclude flows that never carried data
+        if data.bytes_sent.sum() == 0 {
+            return;
+        }
+        let circuit_id = circuit_id_for(data.circuit_hash);
+        for record in flow_records(key, data, analysis, trigger, &circuit_id, boot_epoch_ms()) {
+            let Some(template_id) = record.template_id() else {
+                continue;
+            };
+            let mut encoded = Vec::new();
+            record.encode(&mut encoded);
+            self.pending_bytes += encoded.len();
+            self.pending.push((template_id, encoded));
+        }
+    }
+
+    fn should_flush(&self) -> bool {
+        self.pending_bytes >= MAX_MESSAGE_LEN
+            || (!self.pending.is_empty() && self.last_flush.elapsed() >= self.flush_interval)
+    }
+
+    fn flush(&mut self) {
+        self.last_flush = Instant::now();
+        if self.pending.is_empty() {
+            return;
+        }
+        if self.templates.due(Instant::now()) {
+            let templates = message(
+                export_time_secs(),
+                self.sequence,
+                0,
+                &[template_set(self.enterprise_number)],
+            );
+            if self.send(&templates) {
+                self.templates.templates_sent(Instant::now());
+            }
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        self.pending_bytes = 0;
+        for (sets, record_count) in pack_messages(pending) {
+            let bytes = message(export_time_secs(), self.sequence, 0, &sets);
+            if self.send(&bytes) {
+                self.sequence = self.sequence.wrapping_add(record_count);
+                self.templates.packet_sent();
+            }
+        }
+    }
+
+    fn send(&self, bytes: &[u8]) -> bool {
+        match self.socket.send_to(bytes, &self.target) {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::error!("Failed to send IPFIX data to {}: {}", self.target, e);
+                false
+            }
+        }
+    }
+}
+
+/// Packs encoded records into as few messages as possible, each below
+/// `MAX_MESSAGE_LEN`. Returns the sets for each message and its record count.
+fn pack_messages(mut records: Vec<(u16, Vec<u8>)>) -> Vec<(Vec<Vec<u8>>, u32)> {
+    records.sort_by_key(|(template_id, _)| *template_id);
+
+    let mut messages = Vec::new();
+    let mut sets: Vec<Vec<u8>> = Vec::new();
+    let mut message_len = MESSAGE_HEADER_LEN;
+    let mut message_records = 0u32;
+    let mut current: Option<(u16, Vec<u8>)> = None;
+
+    for (template_id, record) in records {
+        let needs_new_set = current.as_ref().is_none_or(|(id, _)| *id != template_id);
+        let added = record.len() + if needs_new_set { SET_HEADER_LEN } else { 0 };
+        if message_records > 0 && message_len + added > MAX_MESSAGE_LEN {
+            if let Some((id, body)) = current.take() {
+                sets.push(data_set(id, &body));
+            }
+            messages.push((std::mem::take(&mut sets), message_records));
+            message_len = MESSAGE_HEADER_LEN;
+            message_records = 0;
+        }
+        match &mut current {
+            Some((id, body)) if *id == template_id => {
+                body.extend_from_slice(&record);
+                message_len += record.len();
+            }
+            _ => {
+                if let Some((id, body)) = current.take() {
+                    sets.push(data_set(id, &body));
+                }
+                message_len += SET_HEADER_LEN + record.len();
+                current = Some((template_id, record));
+            }
+        }
+        message_records += 1;
+    }
+    if let Some((id, body)) = current.take() {
+        sets.push(data_set(id, &body));
+    }
+    if message_records > 0 {
+        messages.push((sets, message_records));
+    }
+    messages
+}
+
+/// Splits a bidirectional flow into one record per direction that carried
+/// packets. "Down" traffic flows from the remote (Internet) address to the
+/// local (customer) address.
+fn flow_records(
+    key: &FlowbeeKey,
+    data: &FlowbeeLocalData,
+    analysis: &FlowAnalysis,
+    trigger: ExportTrigger,
+    circuit_id: &str,
+    boot_epoch_ms: u64,
+) -> Vec<IpfixFlowRecord> {
+    let local = key.local_ip.as_ip();
+    let remote = key.remote_ip.as_ip();
+    let end_reason = match trigger {
+        ExportTrigger::ActiveTimeout => FlowEndReason::ActiveTimeout,
+        ExportTrigger::Finished if data.end_status == 0 => FlowEndReason::IdleTimeout,
+        ExportTrigger::Finished => FlowEndReason::EndOfFlowDetected,
+    };
+    let start_ms = boot_epoch_ms + data.start_time / 1_000_000;
+    let end_ms = boot_epoch_ms + data.last_seen / 1_000_000;
+
+    let directions = [
+        (
+            remote,
+            key.src_port,
+            local,
+            key.dst_port,
+            data.bytes_sent.down,
+            data.packets_sent.down,
+            data.tcp_retransmits.down,
+            FlowbeeEffectiveDirection::Download,
+        ),
+        (
+            local,
+            key.dst_port,
+            remote,
+            key.src_port,
+            data.bytes_sent.up,
+            data.packets_sent.up,
+            data.tcp_retransmits.up,
+            FlowbeeEffectiveDirection::Upload,
+        ),
+    ];
+    directions
+        .into_iter()
+        .filter(|direction| direction.5 > 0)
+        .map(
+            |(src_ip, src_port, dst_ip, dst_port, octets, packets, retransmits, rtt_direction)| {
+                IpfixFlowRecord {
+                    src_ip,
+                    dst_ip,
+                    src_port,
+                    dst_port,
+                    protocol: key.ip_protocol,
+                    tos: data.tos,
+                    tcp_flags: data.get_flags(),
+                    octets,
+                    packets,
+                    start_ms,
+                    end_ms,
+                    end_reason,
+                    rtt_micros: data.get_rtt(rtt_direction).as_micros() as u32,
+                    retransmits: u32::from(retransmits),
+                    remote_asn: analysis.asn_id.0,
+                    circuit_id: circuit_id.to_string(),
+                }
+            },
+        )
+        .collect()
+}
+
+fn circuit_id_for(circuit_hash: Option<i64>) -> String {
+    let Some(circuit_hash) = circuit_hash else {
+        return String::new();
+    };
+    let shaped = SHAPED_DEVICES.load();
+    SHAPED_DEVICE_HASH_CACHE
+        .load()
+        .index_by_circuit_hash(&shaped, circuit_hash)
+        .and_then(|idx| shaped.devices.get(idx))
+        .map(|device| device.circuit_id.clone())
+        .unwrap_or_default()
+}
+
+/// Flow timestamps are nanoseconds since boot; IPFIX wants milliseconds since
+/// the UNIX epoch.
+fn boot_epoch_ms() -> u64 {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let since_boot_ms = time_since_boot()
+        .map(|t| Duration::from(t).as_millis() as u64)
+        .unwrap_or(0);
+    now_ms.saturating_sub(since_boot_ms)
+}
+
+fn export_time_secs() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::protocol::{TEMPLATE_ID_IPV4, TEMPLATE_ID_IPV6};
+    use super::*;
+    use crate::throughput_tracker::flow_data::AsnId;
+    use crate::throughput_tracker::flow_data::flow_analysis::{ApplicationId, FlowProtocol};
+    use lqos_utils::units::DownUpOrder;
+
+    fn flow(end_status: u8) -> FlowbeeLocalData {
+        FlowbeeLocalData {
+            start_time: 1_000_000,
+            last_seen: 2_000_000,
+            bytes_sent: DownUpOrder::new(3000, 1000),
+            packets_sent: DownUpOrder::new(3, 2),
+            rate_estimate_bps: DownUpOrder::zeroed(),
+            display_rate_bps: None,
+            tcp_retransmits: DownUpOrder::new(0, 0),
+            end_status,
+            tos: 0,
+            tc_handle: 0,
+            cpu: 0,
+            circuit_hash: None,
+            device_hash: None,
+            tcp_info: None,
+        }
+    }
+
+    #[test]
+    fn end_reason_follows_the_export_trigger() {
+        let mut key = FlowbeeKey::default();
+        key.src_port = 443;
+        key.dst_port = 50000;
+        key.ip_protocol = 6;
+        let analysis = FlowAnalysis {
+            asn_id: AsnId(0),
+            protocol_analysis: FlowProtocol::Https,
+            application: ApplicationId::default(),
+        };
+        let end_reasons = |end_status, trigger| {
+            flow_records(&key, &flow(end_status), &analysis, trigger, "c1", 0)
+                .iter()
+                .map(|record| record.end_reason)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            end_reasons(0, ExportTrigger::ActiveTimeout),
+            [FlowEndReason::ActiveTimeout; 2]
+        );
+        // An interim record for a flow that has since sent FIN is still interim.
+        assert_eq!(
+            end_reasons(1, ExportTrigger::ActiveTimeout),
+            [FlowEndReason::ActiveTimeout; 2]
+        );
+        assert_eq!(
+            end_reasons(0, ExportTrigger::Finished),
+            [FlowEndReason::IdleTimeout; 2]
+        );
+        assert_eq!(
+            end_reasons(2, ExportTrigger::Finished),
+            [FlowEndReason::EndOfFlowDetected; 2]
+        );
+    }
+
+    #[test]
+    fn packs_records_into_mtu_sized_messages() {
+        let records: Vec<(u16, Vec<u8>)> = (0..40)
+            .map(|i| {
+                let template = if i % 2 == 0 {
+                    TEMPLATE_ID_IPV4
+                } else {
+                    TEMPLATE_ID_IPV6
+                };
+                (template, vec![0u8; 100])
+            })
+            .collect();
+        let messages = pack_messages(records);
+
+        let total_records: u32 = messages.iter().map(|(_, count)| count).sum();
+        assert_eq!(total_records, 40);
+        for (sets, _) in &messages {
+            let len = MESSAGE_HEADER_LEN + sets.iter().map(Vec::len).sum::<usize>();
+            assert!(
+                len <= MAX_MESSAGE_LEN,
+                "message of {len} bytes is too large"
+            );
+        }
+        // 13 records of 100 bytes fit alongside the headers.
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].0.len(), 1);
+    }
+
+    #[test]
+    fn empty_batches_produce_no_messages() {
+        assert!(pack_messages(Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,349 @@
+//! IPFIX (RFC 7011) message encoding.
+//!
+//! Two templates are defined (IPv4 and IPv6), each combining IANA information
+//! elements with LibreQoS enterprise-specific elements for TCP RTT,
+//! retransmits, circuit ID and remote ASN.
+
+use std::net::IpAddr;
+
+pub(crate) const IPFIX_VERSION: u16 = 10;
+pub(crate) const MESSAGE_HEADER_LEN: usize = 16;
+const SET_HEADER_LEN: usize = 4;
+const TEMPLATE_SET_ID: u16 = 2;
+pub(crate) const TEMPLATE_ID_IPV4: u16 = 256;
+pub(crate) const TEMPLATE_ID_IPV6: u16 = 257;
+/// Keeps messages inside a typical 1500-byte MTU once IP/UDP headers are added.
+pub(crate) const MAX_MESSAGE_LEN: usize = 1400;
+const VARIABLE_LENGTH: u16 = 0xFFFF;
+const ENTERPRISE_BIT: u16 = 0x8000;
+
+/// An information element: IANA id or enterprise-specific id, and its
+/// encoded length (`VARIABLE_LENGTH` for strings).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct InformationElement {
+    id: u16,
+    length: u16,
+    enterprise: bool,
+}
+
+const fn iana(id: u16, length: u16) -> InformationElement {
+    InformationElement {
+        id,
+        length,
+        enterprise: false,
+    }
+}
+
+const fn enterprise(id: u16, length: u16) -> InformationElement {
+    InformationElement {
+        id,
+        length,
+        enterprise: true,
+    }
+}
+
+const OCTET_DELTA_COUNT: InformationElement = iana(1, 8);
+const PACKET_DELTA_COUNT: InformationElement = iana(2, 8);
+const PROTOCOL_IDENTIFIER: InformationElement = iana(4, 1);
+const IP_CLASS_OF_SERVICE: InformationElement = iana(5, 1);
+const TCP_CONTROL_BITS: InformationElement = iana(6, 2);
+const SOURCE_TRANSPORT_PORT: InformationElement = iana(7, 2);
+const SOURCE_IPV4_ADDRESS: InformationElement = iana(8, 4);
+const DESTINATION_TRANSPORT_PORT: InformationElement = iana(11, 2);
+const DESTINATION_IPV4_ADDRESS: InformationElement = iana(12, 4);
+const SOURCE_IPV6_ADDRESS: InformationElement = iana(27, 16);
+const DESTINATION_IPV6_ADDRESS: InformationElement = iana(28, 16);
+const FLOW_END_REASON: InformationElement = iana(136, 1);
+const FLOW_START_MILLISECONDS: InformationElement = iana(152, 8);
+const FLOW_END_MILLISECONDS: InformationElement = iana(153, 8);
+
+/// Median TCP round-trip time for this direction, in microseconds.
+const LQOS_TCP_RTT_MICROSECONDS: InformationElement = enterprise(1, 4);
+/// TCP retransmits observed in this direction.
+const LQOS_TCP_RETRANSMITS: InformationElement = enterprise(2, 4);
+/// Circuit ID from `ShapedDevices.csv` (empty if the flow is unshaped).
+const LQOS_CIRCUIT_ID: InformationElement = enterprise(3, VARIABLE_LENGTH);
+/// ASN of the remote (Internet-side) address, 0 if unknown.
+const LQOS_REMOTE_ASN: InformationElement = enterprise(4, 4);
+
+const TEMPLATE_IPV4: [InformationElement; 16] = [
+    OCTET_DELTA_COUNT,
+    PACKET_DELTA_COUNT,
+    FLOW_START_MILLISECONDS,
+    FLOW_END_MILLISECONDS,
+    SOURCE_IPV4_ADDRESS,
+    DESTINATION_IPV4_ADDRESS,
+    SOURCE_TRANSPORT_PORT,
+    DESTINATION_TRANSPORT_PORT,
+    PROTOCOL_IDENTIFIER,
+    IP_CLASS_OF_SERVICE,
+    TCP_CONTROL_BITS,
+    FLOW_END_REASON,
+    LQOS_TCP_RTT_MICROSECONDS,
+    LQOS_TCP_RETRANSMITS,
+    LQOS_REMOTE_ASN,
+    LQOS_CIRCUIT_ID,
+];
+
+const TEMPLATE_IPV6: [InformationElement; 16] = [
+    OCTET_DELTA_COUNT,
+    PACKET_DELTA_COUNT,
+    FLOW_START_MILLISECONDS,
+    FLOW_END_MILLISECONDS,
+    SOURCE_IPV6_ADDRESS,
+    DESTINATION_IPV6_ADDRESS,
+    SOURCE_TRANSPORT_PORT,
+    DESTINATION_TRANSPORT_PORT,
+    PROTOCOL_IDENTIFIER,
+    IP_CLASS_OF_SERVICE,
+    TCP_CONTROL_BITS,
+    FLOW_END_REASON,
+    LQOS_TCP_RTT_MICROSECONDS,
+    LQOS_TCP_RETRANSMITS,
+    LQOS_REMOTE_ASN,
+    LQOS_CIRCUIT_ID,
+];
+
+/// IPFIX `flowEndReason` values (IANA registry).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum FlowEndReason {
+    IdleTimeout = 1,
+    ActiveTimeout = 2,
+    EndOfFlowDetected = 3,
+}
+
+/// One direction of a flow, ready to be encoded as an IPFIX data record.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct IpfixFlowRecord {
+    pub(crate) src_ip: IpAddr,
+    pub(crate) dst_ip: IpAddr,
+    pub(crate) src_port: u16,
+    pub(crate) dst_port: u16,
+    pub(crate) protocol: u8,
+    pub(crate) tos: u8,
+    pub(crate) tcp_flags: u8,
+    pub(crate) octets: u64,
+    pub(crate) packets: u64,
+    pub(crate) start_ms: u64,
+    pub(crate) end_ms: u64,
+    pub(crate) end_reason: FlowEndReason,
+    pub(crate) rtt_micros: u32,
+    pub(crate) retransmits: u32,
+    pub(crate) remote_asn: u32,
+    pub(crate) circuit_id: String,
+}
+
+impl IpfixFlowRecord {
+    /// The template this record is encoded with, or `None` if the addresses
+    /// mix IPv4 and IPv6.
+    pub(crate) fn template_id(&self) -> Option<u16> {
+        match (self.src_ip, self.dst_ip) {
+            (IpAddr::V4(_), IpAddr::V4(_)) => Some(TEMPLATE_ID_IPV4),
+            (IpAddr::V6(_), IpAddr::V6(_)) => Some(TEMPLATE_ID_IPV6),
+            _ => None,
+        }
+    }
+
+    /// Encodes the record in template field order.
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.octets.to_be_bytes());
+        out.extend_from_slice(&self.packets.to_be_bytes());
+        out.extend_from_slice(&self.start_ms.to_be_bytes());
+        out.extend_from_slice(&self.end_ms.to_be_bytes());
+        match (self.src_ip, self.dst_ip) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                out.extend_from_slice(&src.octets());
+                out.extend_from_slice(&dst.octets());
+            }
+            (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                out.extend_from_slice(&src.octets());
+                out.extend_from_slice(&dst.octets());
+            }
+            _ => {}
+        }
+        out.extend_from_slice(&self.src_port.to_be_bytes());
+        out.extend_from_slice(&self.dst_port.to_be_bytes());
+        out.push(self.protocol);
+        out.push(self.tos);
+        out.extend_from_slice(&u16::from(self.tcp_flags).to_be_bytes());
+        out.push(self.end_reason as u8);
+        out.extend_from_slice(&self.rtt_micros.to_be_bytes());
+        out.extend_from_slice(&self.retransmits.to_be_bytes());
+        out.extend_from_slice(&self.remote_asn.to_be_bytes());
+        encode_variable_length(self.circuit_id.as_bytes(), out);
+    }
+}
+
+/// RFC 7011 section 7: one length byte below 255, otherwise 255 followed by
+/// a 16-bit length.
+fn encode_variable_length(value: &[u8], out: &mut Vec<u8>) {
+    let value = &value[..value.len().min(u16::MAX as usize)];
+    if value.len() < 255 {
+        out.push(value.len() as u8);
+    } else {
+        out.push(255);
+        out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    }
+    out.extend_from_slice(value);
+}
+
+fn encode_template(template_id: u16, fields: &[InformationElement], pen: u32, out: &mut Vec<u8>) {
+    out.extend_from_slice(&template_id.to_be_bytes());
+    out.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+    for field in fields {
+        if field.enterprise {
+            out.extend_from_slice(&(field.id | ENTERPRISE_BIT).to_be_bytes());
+            out.extend_from_slice(&field.length.to_be_bytes());
+            out.extend_from_slice(&pen.to_be_bytes());
+        } else {
+            out.extend_from_slice(&field.id.to_be_bytes());
+            out.extend_from_slice(&field.length.to_be_bytes());
+        }
+    }
+}
+
+/// Builds a template set describing both templates.
+pub(crate) fn template_set(pen: u32) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_template(TEMPLATE_ID_IPV4, &TEMPLATE_IPV4, pen, &mut body);
+    encode_template(TEMPLATE_ID_IPV6, &TEMPLATE_IPV6, pen, &mut body);
+    let mut set = Vec::with_capacity(SET_HEADER_LEN + body.len());
+    set.extend_from_slice(&TEMPLATE_SET_ID.to_be_bytes());
+    set.extend_from_slice(&((SET_HEADER_LEN + body.len()) as u16).to_be_bytes());
+    set.extend_from_slice(&body);
+    set
+}
+
+/// Wraps encoded data records for one template in a data set.
+pub(crate) fn data_set(template_id: u16, records: &[u8]) -> Vec<u8> {
+    let mut set = Vec::with_capacity(SET_HEADER_LEN + records.len());
+    set.extend_from_slice(&template_id.to_be_bytes());
+    set.extend_from_slice(&((SET_HEADER_LEN + records.len()) as u16).to_be_bytes());
+    set.extend_from_slice(records);
+    set
+}
+
+/// Prefixes `sets` with an IPFIX message header. `sequence` is the number of
+/// data records sent before this message.
+pub(crate) fn message(
+    export_time_secs: u32,
+    sequence: u32,
+    observation_domain: u32,
+    sets: &[Vec<u8>],
+) -> Vec<u8> {
+    let length = MESSAGE_HEADER_LEN + sets.iter().map(Vec::len).sum::<usize>();
+    let mut out = Vec::with_capacity(length);
+    out.extend_from_slice(&IPFIX_VERSION.to_be_bytes());
+    out.extend_from_slice(&(length as u16).to_be_bytes());
+    out.extend_from_slice(&export_time_secs.to_be_bytes());
+    out.extend_from_slice(&sequence.to_be_bytes());
+    out.extend_from_slice(&observation_domain.to_be_bytes());
+    for set in sets {
+        out.extend_from_slice(set);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn record(src: IpAddr, dst: IpAddr, circuit_id: &str) -> IpfixFlowRecord {
+        IpfixFlowRecord {
+            src_ip: src,
+            dst_ip: dst,
+            src_port: 443,
+            dst_port: 51000,
+            protocol: 6,
+            tos: 0,
+            tcp_flags: 0x18,
+            octets: 1500,
+            packets: 3,
+            start_ms: 1_700_000_000_000,
+            end_ms: 1_700_000_001_000,
+            end_reason: FlowEndReason::EndOfFlowDetected,
+            rtt_micros: 12_500,
+            retransmits: 2,
+            remote_asn: 13335,
+            circuit_id: circuit_id.to_string(),
+        }
+    }
+
+    fn read_u16(bytes: &[u8], at: usize) -> u16 {
+        u16::from_be_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    #[test]
+    fn template_set_marks_enterprise_elements() {
+        let set = template_set(32473);
+        assert_eq!(read_u16(&set, 0), TEMPLATE_SET_ID);
+        assert_eq!(read_u16(&set, 2) as usize, set.len());
+        assert_eq!(read_u16(&set, 4), TEMPLATE_ID_IPV4);
+        assert_eq!(read_u16(&set, 6), 16);
+
+        // 12 IANA fields (4 bytes each) precede the first enterprise field.
+        let first_enterprise = 8 + 12 * 4;
+        assert_eq!(read_u16(&set, first_enterprise), 0x8001);
+        assert_eq!(read_u16(&set, first_enterprise + 2), 4);
+        assert_eq!(
+            &set[first_enterprise + 4..first_enterprise + 8],
+            &32473u32.to_be_bytes()
+        );
+        // 2 + 2 + 12 * 4 + 4 * 8 bytes per template
+        assert_eq!(set.len(), 4 + 2 * (4 + 12 * 4 + 4 * 8));
+    }
+
+    #[test]
+    fn encodes_fixed_and_variable_length_fields() {
+        let v4 = record(
+            IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+            IpAddr::V4(Ipv4Addr::new(100, 64, 0, 2)),
+            "circuit-1",
+        );
+        assert_eq!(v4.template_id(), Some(TEMPLATE_ID_IPV4));
+        let mut out = Vec::new();
+        v4.encode(&mut out);
+        // 8*4 counters/timestamps + 8 addresses + 4 ports + 1 + 1 + 2 + 1
+        // + 12 enterprise fixed fields + 1 length byte + 9 circuit bytes
+        assert_eq!(out.len(), 32 + 8 + 4 + 5 + 12 + 1 + 9);
+        assert_eq!(&out[32..36], &[1, 1, 1, 1]);
+        assert_eq!(out[61], 9);
+        assert_eq!(&out[62..], b"circuit-1");
+
+        let v6 = record(
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            &"x".repeat(300),
+        );
+        assert_eq!(v6.template_id(), Some(TEMPLATE_ID_IPV6));
+        let mut out = Vec::new();
+        v6.encode(&mut out);
+        let length_at = 32 + 32 + 4 + 5 + 12;
+        assert_eq!(out[length_at], 255);
+        assert_eq!(read_u16(&out, length_at + 1), 300);
+        assert_eq!(out.len(), length_at + 3 + 300);
+
+        let mixed = record(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+            "",
+        );
+        assert_eq!(mixed.template_id(), None);
+    }
+
+    #[test]
+    fn message_header_counts_all_sets() {
+        let records = vec![0u8; 10];
+        let sets = vec![template_set(1), data_set(TEMPLATE_ID_IPV4, &records)];
+        let msg = message(1_700_000_000, 42, 7, &sets);
+        assert_eq!(read_u16(&msg, 0), IPFIX_VERSION);
+        assert_eq!(read_u16(&msg, 2) as usize, msg.len());
+        assert_eq!(&msg[8..12], &42u32.to_be_bytes());
+        assert_eq!(&msg[12..16], &7u32.to_be_bytes());
+        let data_at = MESSAGE_HEADER_LEN + sets[0].len();
+        assert_eq!(read_u16(&msg, data_at), TEMPLATE_ID_IPV4);
+        assert_eq!(read_u16(&msg, data_at + 2), 14);
+    }
+}
@@ -2,31 +2,49 @@
 //! of netflow protocols.
 
 mod asn_heatmap;
+mod export_filter;
+mod export_timing;
 mod flow_analysis;
 mod flow_tracker;
+mod ipfix;
 mod netflow5;
 mod netflow9;
 
 use crate::throughput_tracker::flow_data::{
-    flow_analysis::FinishedFlowAnalysis, netflow5::Netflow5, netflow9::Netflow9,
+    flow_analysis::FinishedFlowAnalysis, ipfix::Ipfix, netflow5::Netflow5, netflow9::Netflow9,
 };
 use anyhow::Result;
 pub(crate) use asn_heatmap::{AsnAggregate, snapshot_asn_heatmaps, update_asn_heatmaps};
 use crossbeam_channel::Sender;
+use export_filter::ExportFilter;
+pub(crate) use export_timing::crossed_active_timeout;
+use export_timing::{ExportTiming, ExportTrigger, ExportedFlow, FlowDeltas};
 pub(crate) use flow_analysis::{
-    AsnCountryListEntry, AsnListEntry, AsnProtocolListEntry, FlowActor, FlowAnalysis,
-    FlowbeeEffectiveDirection, RECENT_FLOWS, RttBuffer, RttData, expire_rtt_flows,
+    ApplicationListEntry, AsnCountryListEntry, AsnListEntry, AsnProtocolListEntry, FlowActor,
+    FlowAnalysis, FlowbeeEffectiveDirection, RECENT_FLOWS, RttBuffer, RttData, expire_rtt_flows,
     flowbee_handle_events, flowbee_rtt_map, get_asn_name_and_country, get_asn_name_by_id,
     get_flowbee_event_count_and_reset, get_rtt_events_per_second, setup_flow_analysis,
 };
 pub(crate) use flow_tracker::{ALL_FLOWS, AsnId, FlowbeeLocalData};
 use lqos_sys::flowbee_data::FlowbeeKey;
+use std::time::Duration;
 use tracing::{debug, error, info};
 
+type FlowSender = Sender<ExportedFlow>;
+
+/// A flow handed to the export back-end by the throughput tracker.
+pub(crate) enum FlowExportEvent {
+    /// The flow ended, or has been idle for longer than the inactive timeout
+    /// (`flow_timeout_seconds`).
+    Finished(FlowbeeKey, FlowbeeLocalData, FlowAnalysis),
+    /// The flow is still running and has passed another active timeout.
+    /// Only sent to the flow exporters.
+    Active(FlowbeeKey, FlowbeeLocalData, FlowAnalysis),
+}
+
 // Creates the netflow tracker and returns the sender
-pub fn setup_netflow_tracker() -> Result<Sender<(FlowbeeKey, (FlowbeeLocalData, FlowAnalysis))>> {
-    let (tx, rx) =
-        crossbeam_channel::bounded::<(FlowbeeKey, (FlowbeeLocalData, FlowAnalysis))>(65535);
+pub(crate) fn setup_netflow_tracker() -> Result<Sender<FlowExportEvent>> {
+    let (tx, rx) = crossbeam_channel::bounded::<FlowExportEvent>(65535);
     let config =
         lqos_config::load_config().inspect_err(|e| error!("Failed to load configuration: {e}"))?;
 
@@ -35,46 +53,86 @@ pub fn setup_netflow_tracker() -> Result<Sender<(FlowbeeKey, (FlowbeeLocalData,
         .spawn(move || {
             debug!("Starting the network flow tracker back-end");
 
-            // Build the endpoints list
-            let mut endpoints: Vec<Sender<(FlowbeeKey, (FlowbeeLocalData, FlowAnalysis))>> =
-                Vec::new();
-            endpoints.push(FinishedFlowAnalysis::start());
+            // Finished flows are always analyzed locally; the exporters get
+            // both interim and final records.
+            let analysis_endpoint = FinishedFlowAnalysis::start();
+            let mut endpoints: Vec<(ExportFilter, FlowSender)> = Vec::new();
+            let mut deltas = FlowDeltas::default();
+            let mut prune_after = Duration::from_secs(600);
 
-            if let Some(flow_config) = &config.flows
-                && let (Some(ip), Some(port), Some(version)) = (
-                    flow_config.netflow_ip.clone(),
-                    flow_config.netflow_port,
-                    flow_config.netflow_version,
+            if let Some(flow_config) = &config.flows {
+                let timing = ExportTiming::from_config(flow_config);
+                prune_after = Duration::from_secs(
+                    (flow_config.active_timeout_seconds + flow_config.flow_timeout_seconds) * 2,
                 )
-            {
-                info!("Setting up netflow target: {ip}:{port}, version: {version}");
-                let target = format!("{ip}:{port}", ip = ip, port = port);
-                match version {
-                    5 => {
-                        let endpoint =
-                            Netflow5::start(target).expect("Cannot parse endpoint for netflow v5");
-                        endpoints.push(endpoint);
-                        info!("Netflow 5 endpoint added");
+                .max(prune_after);
+                for export in flow_config.all_export_targets() {
+                    let version = export.version;
+                    let target = format!("{}:{}", export.ip, export.port);
+                    info!(
+                        "Setting up netflow target: {target}, version: {version}, sampling 1:{}",
+                        export.sampling_rate.max(1)
+                    );
+                    let endpoint = match version {
+                        5 => Netflow5::start(target.clone(), timing),
+                        9 => Netflow9::start(target.clone(), timing),
+                        10 => Ipfix::start(
+                            target.clone(),
+                            flow_config.ipfix_enterprise_number,
+                            timing,
+                        ),
+                        _ => {
+                            error!("Unsupported netflow version: {version}");
+                            continue;
+                        }
+                    };
+                    match endpoint {
+                        Ok(endpoint) => {
+                            endpoints.push((ExportFilter::from_target(&export), endpoint));
+                            info!("Netflow {version} endpoint added for {target}");
+                        }
+                        Err(e) => error!("Cannot start netflow v{version} endpoint {target}: {e}"),
                     }
-                    9 => {
-                        let endpoint =
-                            Netflow9::start(target).expect("Cannot parse endpoint for netflow v9");
-                        endpoints.push(endpoint);
-                        info!("Netflow 9 endpoint added");
-                    }
-                    _ => error!("Unsupported netflow version: {version}"),
                 }
             }
-            debug!("Flow Endpoints: {}", endpoints.len());
+            debug!("Flow Endpoints: {}", endpoints.len() + 1);
 
             // Send to all endpoints upon receipt
-            while let Ok((key, (value, analysis))) = rx.recv() {
-                endpoints.iter_mut().for_each(|f| {
+            let mut last_prune = std::time::Instant::now();
+            while let Ok(event) = rx.recv() {
+                let (key, record, analysis, trigger) = match event {
+                    FlowExportEvent::Finished(key, value, analysis) => {
+                        let record = deltas.finished(key, &value);
+                        if let Err(e) = analysis_endpoint.try_send((key, (value, analysis))) {
+                            tracing::warn!("Failed to send flow data to flow analysis: {e}");
+                        }
+                        (key, record, analysis, ExportTrigger::Finished)
+                    }
+                    FlowExportEvent::Active(key, value, analysis) => (
+                        key,
+                        deltas.active(key, &value),
+                        analysis,
+                        ExportTrigger::ActiveTimeout,
+                    ),
+                };
+
+                let local = key.local_ip.as_ip();
+                let remote = key.remote_ip.as_ip();
+                let flow_hash = fxhash::hash64(&key);
+                endpoints.iter().for_each(|(filter, f)| {
+                    if !filter.accepts(local, remote, flow_hash) {
+                        return;
+                    }
                     //log::debug!("Enqueueing flow data for {key:?}");
-                    if let Err(e) = f.try_send((key, (value.clone(), analysis))) {
+                    if let Err(e) = f.try_send((key, (record.clone(), analysis), trigger)) {
                         tracing::warn!("Failed to send flow data to endpoint: {e}");
                     }
                 });
+
+                if last_prune.elapsed() >= prune_after {
+                    deltas.prune(prune_after);
+                    last_prune = std::time::Instant::now();
+                }
             }
             info!("Network flow tracker back-end has stopped")
         })?;
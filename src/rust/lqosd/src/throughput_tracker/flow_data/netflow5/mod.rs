@@ -1,20 +1,26 @@
 //! Support for the Netflow 5 protocol
 //! Mostly taken from: https://netflow.caligare.com/netflow_v5.htm
 mod protocol;
+use super::export_timing::{ExportTiming, ExportedFlow};
 use super::{FlowAnalysis, FlowbeeLocalData};
-use crossbeam_channel::Sender;
+use crossbeam_channel::{RecvTimeoutError, Sender};
 use lqos_sys::flowbee_data::FlowbeeKey;
 pub(crate) use protocol::*;
+use std::time::Instant;
 use std::{net::UdpSocket, sync::atomic::AtomicU32};
 
+/// Flows per export packet. Each flow produces two records and v5 allows at
+/// most 30 records per packet.
+const FLOWS_PER_PACKET: usize = 15;
+
 pub(crate) struct Netflow5 {}
 
 impl Netflow5 {
     pub(crate) fn start(
         target: String,
-    ) -> anyhow::Result<Sender<(FlowbeeKey, (FlowbeeLocalData, FlowAnalysis))>> {
-        let (tx, rx) =
-            crossbeam_channel::bounded::<(FlowbeeKey, (FlowbeeLocalData, FlowAnalysis))>(65535);
+        timing: ExportTiming,
+    ) -> anyhow::Result<Sender<ExportedFlow>> {
+        let (tx, rx) = crossbeam_channel::bounded::<ExportedFlow>(65535);
 
         std::thread::Builder::new()
             .name("Netflow5".to_string())
@@ -29,29 +35,36 @@ impl Netflow5 {
                 };
 
                 let sequence = AtomicU32::new(0);
-                let mut accumulator = Vec::with_capacity(15);
-                let mut last_sent = std::time::Instant::now();
-                while let Ok((key, (data, analysis))) = rx.recv() {
-                    // Exclude one-way flows
-                    if (data.bytes_sent.sum()) == 0 {
-                        continue;
+                let mut accumulator = Vec::with_capacity(FLOWS_PER_PACKET);
+                let mut last_sent = Instant::now();
+                loop {
+                    match rx.recv_timeout(timing.flush_interval) {
+                        // Exclude flows that didn't carry any data
+                        Ok((key, (data, analysis), _)) => {
+                            if data.bytes_sent.sum() > 0 {
+                                accumulator.push((key, (data, analysis)));
+                            }
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
                     }
 
-                    accumulator.push((key, (data, analysis)));
-
-                    // Send if there is more than 15 records AND it has been more than 1 second since the last send
-                    if accumulator.len() >= 15 && last_sent.elapsed().as_secs() > 1 {
-                        for chunk in accumulator.chunks(15) {
+                    // Send full packets right away, and partial ones once they
+                    // have waited for the flush interval.
+                    let flush_due =
+                        !accumulator.is_empty() && last_sent.elapsed() >= timing.flush_interval;
+                    if accumulator.len() >= FLOWS_PER_PACKET || flush_due {
+                        for chunk in accumulator.chunks(FLOWS_PER_PACKET) {
                             Self::queue_handler(chunk, &socket, &target, &sequence);
                         }
                         accumulator.clear();
-                        last_sent = std::time::Instant::now();
+                        last_sent = Instant::now();
                     }
                 }
 
                 // Handle any remaining flows when shutting down
                 if !accumulator.is_empty() {
-                    for chunk in accumulator.chunks(15) {
+                    for chunk in accumulator.chunks(FLOWS_PER_PACKET) {
                         Self::queue_handler(chunk, &socket, &target, &sequence);
                     }
                 }
@@ -1,51 +1,65 @@
 use self::protocol::to_netflow_9;
+use super::export_timing::{ExportTiming, ExportedFlow, TemplateRefresh};
 use super::{FlowAnalysis, FlowbeeLocalData};
 use crate::throughput_tracker::flow_data::netflow9::protocol::{
     header::Netflow9Header, template_ipv4::template_data_ipv4, template_ipv6::template_data_ipv6,
 };
-use crossbeam_channel::Sender;
+use crossbeam_channel::{RecvTimeoutError, Sender};
 use lqos_sys::flowbee_data::FlowbeeKey;
+use std::time::Instant;
 use std::{net::UdpSocket, sync::atomic::AtomicU32};
 mod protocol;
 
+/// Flows per export packet. Each flow produces two records (one per
+/// direction), which keeps packets below a 1500 byte MTU.
+const FLOWS_PER_PACKET: usize = 14;
+
 pub(crate) struct Netflow9 {}
 
 impl Netflow9 {
     pub(crate) fn start(
         target: String,
-    ) -> anyhow::Result<Sender<(FlowbeeKey, (FlowbeeLocalData, FlowAnalysis))>> {
-        let (tx, rx) =
-            crossbeam_channel::bounded::<(FlowbeeKey, (FlowbeeLocalData, FlowAnalysis))>(65535);
+        timing: ExportTiming,
+    ) -> anyhow::Result<Sender<ExportedFlow>> {
+        let (tx, rx) = crossbeam_channel::bounded::<ExportedFlow>(65535);
         let socket = UdpSocket::bind("0.0.0.0:0")?;
 
         std::thread::Builder::new()
             .name("Netflow9".to_string())
             .spawn(move || {
-                let mut accumulator = Vec::with_capacity(14);
+                let mut accumulator = Vec::with_capacity(FLOWS_PER_PACKET);
                 let sequence = AtomicU32::new(0);
-                let mut last_sent = std::time::Instant::now();
-                while let Ok((key, (data, analysis))) = rx.recv() {
-                    // Exclude one-way flows
-                    if (data.bytes_sent.sum()) == 0 {
-                        continue;
+                let mut templates = timing.template_refresh();
+                let mut last_sent = Instant::now();
+                loop {
+                    match rx.recv_timeout(timing.flush_interval) {
+                        // Exclude flows that didn't carry any data
+                        Ok((key, (data, analysis), _)) => {
+                            if data.bytes_sent.sum() > 0 {
+                                accumulator.push((key, (data, analysis)));
+                            }
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
                     }
 
-                    accumulator.push((key, (data, analysis)));
-
-                    // Send if there is more than 15 records AND it has been more than 1 second since the last send
-                    if accumulator.len() >= 14 && last_sent.elapsed().as_secs() > 1 {
-                        for chunk in accumulator.chunks(14) {
-                            Self::queue_handler(chunk, &socket, &target, &sequence);
+                    // Send full packets right away, and partial ones once they
+                    // have waited for the flush interval.
+                    let flush_due =
+                        !accumulator.is_empty() && last_sent.elapsed() >= timing.flush_interval;
+                    if accumulator.len() >= FLOWS_PER_PACKET || flush_due {
+                        for chunk in accumulator.chunks(FLOWS_PER_PACKET) {
+                            Self::queue_handler(chunk, &socket, &target, &sequence, &mut templates);
                         }
                         accumulator.clear();
-                        last_sent = std::time::Instant::now();
+                        last_sent = Instant::now();
                     }
                 }
 
                 // Handle any remaining flows when shutting down
                 if !accumulator.is_empty() {
-                    for chunk in accumulator.chunks(14) {
-                        Self::queue_handler(chunk, &socket, &target, &sequence);
+                    for chunk in accumulator.chunks(FLOWS_PER_PACKET) {
+                        Self::queue_handler(chunk, &socket, &target, &sequence, &mut templates);
                     }
                 }
             })?;
@@ -58,8 +72,13 @@ impl Netflow9 {
         socket: &UdpSocket,
         target: &str,
         sequence: &AtomicU32,
+        templates: &mut TemplateRefresh,
     ) {
-        let num_records = (accumulator.len() * 2) as u16 + 2; // +2 to include templates
+        // Templates go out with the first packet, then whenever the refresh
+        // interval or packet count is reached (RFC 3954 section 9).
+        let send_templates = templates.due(Instant::now());
+        let template_records = if send_templates { 2 } else { 0 };
+        let num_records = (accumulator.len() * 2) as u16 + template_records;
         let sequence_num = sequence.load(std::sync::atomic::Ordering::Relaxed);
         let header = Netflow9Header::new(sequence_num, num_records);
         let header_bytes = unsafe {
@@ -68,14 +87,12 @@ impl Netflow9 {
                 std::mem::size_of::<Netflow9Header>(),
             )
         };
-        let template1 = template_data_ipv4();
-        let template2 = template_data_ipv6();
-        let mut buffer = Vec::with_capacity(
-            header_bytes.len() + template1.len() + template2.len() + (num_records as usize * 140),
-        );
+        let mut buffer = Vec::with_capacity(header_bytes.len() + (num_records as usize * 140));
         buffer.extend_from_slice(header_bytes);
-        buffer.extend_from_slice(&template1);
-        buffer.extend_from_slice(&template2);
+        if send_templates {
+            buffer.extend_from_slice(&template_data_ipv4());
+            buffer.extend_from_slice(&template_data_ipv6());
+        }
 
         for (key, (data, _)) in accumulator {
             if let Ok((packet1, packet2)) = to_netflow_9(key, data) {
@@ -88,6 +105,10 @@ impl Netflow9 {
             // Don't increment sequence on failure to maintain consistency
         } else {
             sequence.fetch_add(num_records as u32, std::sync::atomic::Ordering::Relaxed);
+            if send_templates {
+                templates.templates_sent(Instant::now());
+            }
+            templates.packet_sent();
         }
     }
 }
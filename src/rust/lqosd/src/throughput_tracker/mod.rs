@@ -4,8 +4,8 @@ mod throughput_entry;
 mod tracking_data;
 
 use self::flow_data::{
-    ALL_FLOWS, FlowAnalysis, FlowbeeLocalData, get_asn_name_and_country, get_asn_name_by_id,
-    snapshot_asn_heatmaps,
+    ALL_FLOWS, FlowAnalysis, FlowExportEvent, FlowbeeLocalData, get_asn_name_and_country,
+    get_asn_name_by_id, snapshot_asn_heatmaps,
 };
 use crate::system_stats::SystemStats;
 use crate::throughput_tracker::flow_data::FlowbeeEffectiveDirection;
@@ -20,8 +20,9 @@ pub(crate) use flow_data::RttBuffer;
 use fxhash::{FxHashMap, FxHashSet};
 use lqos_bakery::{BakeryCommands, full_reload_in_progress};
 use lqos_bus::{
-    AsnHeatmapData, BusResponse, CircuitHeatmapData, ExecutiveSummaryHeader, FlowbeeProtocol,
-    IpStats, SiteHeatmapData, TcHandle, TopFlowType, XdpPpingResult,
+    AsnHeatmapData, BusResponse, BusTopic, BusUpdate, CircuitActivityEntry, CircuitHeatmapData,
+    ExecutiveSummaryHeader, FlowbeeProtocol, IpStats, SiteHeatmapData, TcHandle, TopFlowType,
+    XdpPpingResult, has_bus_subscribers, publish_bus_update,
 };
 use lqos_queue_tracker::{ALL_QUEUE_SUMMARY, queue_stats_stale};
 use lqos_sys::flowbee_data::FlowbeeKey;
@@ -29,6 +30,7 @@ use lqos_utils::units::{DownUpOrder, TcpRetransmitSample, down_up_retransmit_sam
 use lqos_utils::{XdpIpAddress, hash_to_i64, unix_time::time_since_boot};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+pub(crate) use stats_submission::{TickBatches, gather_tick_batches};
 use std::net::IpAddr;
 use std::sync::Arc;
 use timerfd::{SetTimeFlags, TimerFd, TimerState};
@@ -50,7 +52,7 @@ pub(crate) static CIRCUIT_RTT_BUFFERS: Lazy<ArcSwap<FxHashMap<i64, RttBuffer>>>
 /// * `long_term_stats_tx` - an optional MPSC sender to notify the
 ///   collection thread that there is fresh data.
 pub fn spawn_throughput_monitor(
-    netflow_sender: crossbeam_channel::Sender<(FlowbeeKey, (FlowbeeLocalData, FlowAnalysis))>,
+    netflow_sender: crossbeam_channel::Sender<FlowExportEvent>,
     system_usage_actor: crossbeam_channel::Sender<tokio::sync::oneshot::Sender<SystemStats>>,
     bakery_sender: crossbeam_channel::Sender<lqos_bakery::BakeryCommands>,
 ) -> anyhow::Result<()> {
@@ -112,7 +114,7 @@ impl ThroughputTaskTimeMetrics {
 }
 
 fn throughput_task(
-    netflow_sender: crossbeam_channel::Sender<(FlowbeeKey, (FlowbeeLocalData, FlowAnalysis))>,
+    netflow_sender: crossbeam_channel::Sender<FlowExportEvent>,
     system_usage_actor: crossbeam_channel::Sender<tokio::sync::oneshot::Sender<SystemStats>>,
     bakery_sender: crossbeam_channel::Sender<BakeryCommands>,
 ) {
@@ -239,6 +241,16 @@ fn throughput_task(
             }
             timer_metrics.apply_queue_stats = timer_metrics.start.elapsed().as_secs_f64();
             THROUGHPUT_TRACKER.update_totals();
+            if crate::usage_quotas::is_enabled() {
+                crate::usage_quotas::record(THROUGHPUT_TRACKER.circuit_byte_deltas());
+            }
+            publish_tick_updates();
+            if crate::anomaly_detection::is_enabled() {
+                crate::anomaly_detection::observe(
+                    THROUGHPUT_TRACKER.anomaly_samples(),
+                    &net_json_calc,
+                );
+            }
             timer_metrics.update_totals = timer_metrics.start.elapsed().as_secs_f64();
             THROUGHPUT_TRACKER.next_cycle();
             timer_metrics.next_cycle = timer_metrics.start.elapsed().as_secs_f64();
@@ -328,6 +340,41 @@ pub fn current_throughput() -> BusResponse {
     }
 }
 
+/// Pushes this tick's throughput and circuit activity to bus subscribers.
+fn publish_tick_updates() {
+    if has_bus_subscribers(BusTopic::Throughput) {
+        publish_bus_update(BusUpdate::Throughput {
+            bits_per_second: THROUGHPUT_TRACKER.bits_per_second(),
+            packets_per_second: THROUGHPUT_TRACKER.packets_per_second(),
+            tcp_packets_per_second: THROUGHPUT_TRACKER.tcp_packets_per_second(),
+            udp_packets_per_second: THROUGHPUT_TRACKER.udp_packets_per_second(),
+            icmp_packets_per_second: THROUGHPUT_TRACKER.icmp_packets_per_second(),
+            shaped_bits_per_second: THROUGHPUT_TRACKER.shaped_bits_per_second(),
+        });
+    }
+    if has_bus_subscribers(BusTopic::CircuitActivity) {
+        let shaped = SHAPED_DEVICES.load();
+        let circuit_ids: FxHashMap<i64, &str> = shaped
+            .devices
+            .iter()
+            .map(|device| (device.circuit_hash, device.circuit_id.as_str()))
+            .collect();
+        let activity = THROUGHPUT_TRACKER
+            .circuit_byte_deltas()
+            .into_iter()
+            .map(|(circuit_hash, bytes)| CircuitActivityEntry {
+                circuit_hash,
+                circuit_id: circuit_ids.get(&circuit_hash).map(|id| id.to_string()),
+                bits_per_second: DownUpOrder::new(
+                    bytes.down.saturating_mul(8),
+                    bytes.up.saturating_mul(8),
+                ),
+            })
+            .collect();
+        publish_bus_update(BusUpdate::CircuitActivity(activity));
+    }
+}
+
 pub fn host_counters() -> BusResponse {
     let mut result = Vec::new();
     THROUGHPUT_TRACKER
@@ -1,5 +1,8 @@
 use crate::lts2_sys::get_lts_license_status;
-use crate::lts2_sys::shared_types::{CircuitCakeDrops, CircuitCakeMarks, LtsStatus};
+use crate::lts2_sys::shared_types::{
+    CircuitCakeDrops, CircuitCakeMarks, CircuitRetransmits, CircuitRtt, CircuitThroughput,
+    LtsStatus, SiteCakeDrops, SiteCakeMarks, SiteRetransmits, SiteRtt, SiteThroughput,
+};
 use crate::shaped_devices_tracker::{NETWORK_JSON, SHAPED_DEVICES};
 use crate::system_stats::SystemStats;
 use crate::throughput_tracker::flow_data::ALL_FLOWS;
@@ -47,6 +50,10 @@ pub(crate) fn submit_throughput_stats(
         return;
     };
 
+    // InfluxDB export and local history are independent of Insight licensing
+    crate::influxdb::maybe_submit_sample(&config, scale);
+    crate::local_history::maybe_submit_sample(&config, scale);
+
     // Bail out if we don't have gather stats or a license key
     if !config.long_term_stats.gather_stats {
         return;
@@ -245,279 +252,47 @@ pub(crate) fn submit_throughput_stats(
             debug!("Error sending message to LTS2. {e:?}");
         }
 
-        // Send per-circuit stats to LTS2
-        // Start by combining the throughput data for each circuit as a whole
-        struct CircuitThroughputTemp {
-            bytes: DownUpOrder<u64>,
-            packets: DownUpOrder<u64>,
-            tcp_packets: DownUpOrder<u64>,
-            udp_packets: DownUpOrder<u64>,
-            icmp_packets: DownUpOrder<u64>,
-        }
-
-        let mut crazy_values: FxHashSet<i64> = FxHashSet::default(); // Circuits to skip because the numbers are too high
-        let mut circuit_throughput: FxHashMap<i64, CircuitThroughputTemp> = FxHashMap::default();
-        let mut circuit_retransmits: FxHashMap<i64, DownUpOrder<u64>> = FxHashMap::default();
-
-        let shaped_devices = SHAPED_DEVICES.load();
-        const CRAZY_LIMIT: u64 = 8; // 8x the max bandwidth
-        let plan_lookup: FxHashMap<i64, (u64, u64)> = shaped_devices
-            .devices
-            .iter()
-            // Bandwidth: mbps * 1_000_000 to bytes
-            .map(|d| {
-                (
-                    d.circuit_hash,
-                    (
-                        d.download_max_mbps.round() as u64 * 1_000_000 * CRAZY_LIMIT,
-                        d.upload_max_mbps.round() as u64 * 1_000_000 * CRAZY_LIMIT,
-                    ),
-                )
-            })
-            .collect();
-
-        THROUGHPUT_TRACKER
-            .raw_data
-            .lock()
-            .iter()
-            .filter(|(_k, h)| h.circuit_id.is_some() && h.bytes_per_second.not_zero())
-            .for_each(|(_k, h)| {
-                let mut crazy = false;
-                if let Some((dl, ul)) = plan_lookup.get(&h.circuit_hash.unwrap_or(0))
-                    && (h.bytes_per_second.down > *dl || h.bytes_per_second.up > *ul)
-                {
-                    crazy_values.insert(h.circuit_hash.unwrap_or(0));
-                    crazy = true;
-                }
-
-                if crazy {
-                    return;
-                }
-                if let Some(c) = circuit_throughput.get_mut(&h.circuit_hash.unwrap_or(0)) {
-                    c.bytes += h.bytes_per_second;
-                    c.packets += h.packets_per_second;
-                    c.tcp_packets += h.tcp_packets;
-                    c.udp_packets += h.udp_packets;
-                    c.icmp_packets += h.icmp_packets;
-                } else {
-                    circuit_throughput.insert(
-                        h.circuit_hash.unwrap_or(0),
-                        CircuitThroughputTemp {
-                            bytes: h.bytes_per_second,
-                            packets: h.packets_per_second,
-                            tcp_packets: h.tcp_packets,
-                            udp_packets: h.udp_packets,
-                            icmp_packets: h.icmp_packets,
-                        },
-                    );
-                }
-            });
-
-        THROUGHPUT_TRACKER
-            .raw_data
-            .lock()
-            .iter()
-            .filter(|(_k, h)| {
-                h.circuit_id.is_some()
-                    && h.tcp_retransmits.not_zero()
-                    && !crazy_values.contains(&h.circuit_hash.unwrap_or(0))
-            })
-            .for_each(|(_k, h)| {
-                if let Some(c) = circuit_retransmits.get_mut(&h.circuit_hash.unwrap_or(0)) {
-                    *c += h.tcp_retransmits;
-                } else {
-                    circuit_retransmits.insert(h.circuit_hash.unwrap_or(0), h.tcp_retransmits);
-                }
-            });
-
-        // And now we send it
-        let circuit_throughput_batch = circuit_throughput
-            .into_iter()
-            .map(|(k, v)| crate::lts2_sys::shared_types::CircuitThroughput {
-                timestamp: now,
-                circuit_hash: k,
-                download_bytes: scale_u64_by_f64(v.bytes.down, scale),
-                upload_bytes: scale_u64_by_f64(v.bytes.up, scale),
-                packets_down: scale_u64_by_f64(v.packets.down, scale),
-                packets_up: scale_u64_by_f64(v.packets.up, scale),
-                tcp_packets_down: scale_u64_by_f64(v.tcp_packets.down, scale),
-                tcp_packets_up: scale_u64_by_f64(v.tcp_packets.up, scale),
-                udp_packets_down: scale_u64_by_f64(v.udp_packets.down, scale),
-                udp_packets_up: scale_u64_by_f64(v.udp_packets.up, scale),
-                icmp_packets_down: scale_u64_by_f64(v.icmp_packets.down, scale),
-                icmp_packets_up: scale_u64_by_f64(v.icmp_packets.up, scale),
-            })
-            .collect::<Vec<_>>();
-        if crate::lts2_sys::circuit_throughput(&circuit_throughput_batch).is_err() {
+        // Send per-circuit and per-site stats to LTS2
+        let batches = gather_tick_batches(now, scale);
+        if crate::lts2_sys::circuit_throughput(&batches.circuit_throughput).is_err() {
             warn!("Error sending message to LTS2.");
         }
-
-        let circuit_retransmits_batch = circuit_retransmits
-            .into_iter()
-            .map(|(k, v)| crate::lts2_sys::shared_types::CircuitRetransmits {
-                timestamp: now,
-                circuit_hash: k,
-                tcp_retransmits_down: v.down as u32,
-                tcp_retransmits_up: v.up as u32,
-            })
-            .collect::<Vec<_>>();
-        if crate::lts2_sys::circuit_retransmits(&circuit_retransmits_batch).is_err() {
+        if crate::lts2_sys::circuit_retransmits(&batches.circuit_retransmits).is_err() {
             warn!("Error sending message to LTS2.");
         }
-
-        let circuit_rtt_snapshot = CIRCUIT_RTT_BUFFERS.load();
-        let circuit_rtt_batch = circuit_rtt_snapshot
-            .iter()
-            .filter(|(circuit_hash, _)| !crazy_values.contains(circuit_hash))
-            .filter_map(|(circuit_hash, rtt_buffer)| {
-                let download = rtt_buffer
-                    .median_new_data(FlowbeeEffectiveDirection::Download)
-                    .as_nanos();
-                let upload = rtt_buffer
-                    .median_new_data(FlowbeeEffectiveDirection::Upload)
-                    .as_nanos();
-
-                let median_nanos = match (download, upload) {
-                    (0, 0) => return None,
-                    (d, 0) => d,
-                    (0, u) => u,
-                    (d, u) => d.saturating_add(u) / 2,
-                };
-
-                Some(crate::lts2_sys::shared_types::CircuitRtt {
-                    timestamp: now,
-                    circuit_hash: *circuit_hash,
-                    median_rtt: (median_nanos as f64 / 1_000_000.0) as f32,
-                })
-            })
-            .collect::<Vec<_>>();
-        if crate::lts2_sys::circuit_rtt(&circuit_rtt_batch).is_err() {
+        if crate::lts2_sys::circuit_rtt(&batches.circuit_rtt).is_err() {
             warn!("Error sending message to LTS2.");
         }
-
-        // Per host CAKE stats
-        let mut cake_drops: Vec<CircuitCakeDrops> = Vec::new();
-        let mut cake_marks: Vec<CircuitCakeMarks> = Vec::new();
-        ALL_QUEUE_SUMMARY.iterate_queues(|circuit_hash, drops, marks| {
-            if drops.not_zero() {
-                cake_drops.push(CircuitCakeDrops {
-                    timestamp: now,
-                    circuit_hash,
-                    cake_drops_down: drops.get_down() as u32,
-                    cake_drops_up: drops.get_up() as u32,
-                });
-            }
-            if marks.not_zero() {
-                cake_marks.push(CircuitCakeMarks {
-                    timestamp: now,
-                    circuit_hash,
-                    cake_marks_down: marks.get_down() as u32,
-                    cake_marks_up: marks.get_up() as u32,
-                });
-            }
-        });
-        if !cake_drops.is_empty() && crate::lts2_sys::circuit_cake_drops(&cake_drops).is_err() {
+        if !batches.circuit_cake_drops.is_empty()
+            && crate::lts2_sys::circuit_cake_drops(&batches.circuit_cake_drops).is_err()
+        {
             warn!("Error sending message to LTS2.");
         }
-        if !cake_marks.is_empty() && crate::lts2_sys::circuit_cake_marks(&cake_marks).is_err() {
+        if !batches.circuit_cake_marks.is_empty()
+            && crate::lts2_sys::circuit_cake_marks(&batches.circuit_cake_marks).is_err()
+        {
             warn!("Error sending message to LTS2.");
         }
-
-        // Network tree stats
-        let tree = {
-            let reader = NETWORK_JSON.read();
-            reader.get_nodes_when_ready().clone()
-        };
-        let mut site_throughput: Vec<crate::lts2_sys::shared_types::SiteThroughput> = Vec::new();
-        let mut site_retransmits: Vec<crate::lts2_sys::shared_types::SiteRetransmits> = Vec::new();
-        let mut site_rtt: Vec<crate::lts2_sys::shared_types::SiteRtt> = Vec::new();
-        let mut site_cake_drops: Vec<crate::lts2_sys::shared_types::SiteCakeDrops> = Vec::new();
-        let mut site_cake_marks: Vec<crate::lts2_sys::shared_types::SiteCakeMarks> = Vec::new();
-        tree.iter().for_each(|node| {
-            let site_hash = hash_to_i64(&node.name);
-            if node.current_throughput.not_zero() {
-                site_throughput.push(crate::lts2_sys::shared_types::SiteThroughput {
-                    timestamp: now,
-                    site_hash,
-                    download_bytes: scale_u64_by_f64(node.current_throughput.down, scale),
-                    upload_bytes: scale_u64_by_f64(node.current_throughput.up, scale),
-                    packets_down: scale_u64_by_f64(node.current_packets.down, scale),
-                    packets_up: scale_u64_by_f64(node.current_packets.up, scale),
-                    packets_tcp_down: scale_u64_by_f64(node.current_tcp_packets.down, scale),
-                    packets_tcp_up: scale_u64_by_f64(node.current_tcp_packets.up, scale),
-                    packets_udp_down: scale_u64_by_f64(node.current_udp_packets.down, scale),
-                    packets_udp_up: scale_u64_by_f64(node.current_udp_packets.up, scale),
-                    packets_icmp_down: scale_u64_by_f64(node.current_icmp_packets.down, scale),
-                    packets_icmp_up: scale_u64_by_f64(node.current_icmp_packets.up, scale),
-                });
-            }
-            if node.current_tcp_retransmits.not_zero() {
-                site_retransmits.push(crate::lts2_sys::shared_types::SiteRetransmits {
-                    timestamp: now,
-                    site_hash,
-                    tcp_retransmits_down: node.current_tcp_retransmits.down as u32,
-                    tcp_retransmits_up: node.current_tcp_retransmits.up as u32,
-                });
-            }
-            if node.current_drops.not_zero() {
-                site_cake_drops.push(crate::lts2_sys::shared_types::SiteCakeDrops {
-                    timestamp: now,
-                    site_hash,
-                    cake_drops_down: node.current_drops.get_down() as u32,
-                    cake_drops_up: node.current_drops.get_up() as u32,
-                });
-            }
-            if node.current_marks.not_zero() {
-                site_cake_marks.push(crate::lts2_sys::shared_types::SiteCakeMarks {
-                    timestamp: now,
-                    site_hash,
-                    cake_marks_down: node.current_marks.get_down() as u32,
-                    cake_marks_up: node.current_marks.get_up() as u32,
-                });
-            }
-            let download = node
-                .rtt_buffer
-                .median_new_data(FlowbeeEffectiveDirection::Download)
-                .as_nanos();
-            let upload = node
-                .rtt_buffer
-                .median_new_data(FlowbeeEffectiveDirection::Upload)
-                .as_nanos();
-            let median_nanos = match (download, upload) {
-                (0, 0) => None,
-                (d, 0) => Some(d),
-                (0, u) => Some(u),
-                (d, u) => Some(d.saturating_add(u) / 2),
-            };
-
-            if let Some(median_nanos) = median_nanos {
-                site_rtt.push(crate::lts2_sys::shared_types::SiteRtt {
-                    timestamp: now,
-                    site_hash,
-                    median_rtt: (median_nanos as f64 / 1_000_000.0) as f32,
-                });
-            }
-        });
-        if !site_throughput.is_empty()
-            && crate::lts2_sys::site_throughput(&site_throughput).is_err()
+        if !batches.site_throughput.is_empty()
+            && crate::lts2_sys::site_throughput(&batches.site_throughput).is_err()
         {
             warn!("Error sending message to LTS2.");
         }
-        if !site_retransmits.is_empty()
-            && crate::lts2_sys::site_retransmits(&site_retransmits).is_err()
+        if !batches.site_retransmits.is_empty()
+            && crate::lts2_sys::site_retransmits(&batches.site_retransmits).is_err()
         {
             warn!("Error sending message to LTS2.");
         }
-        if !site_rtt.is_empty() && crate::lts2_sys::site_rtt(&site_rtt).is_err() {
+        if !batches.site_rtt.is_empty() && crate::lts2_sys::site_rtt(&batches.site_rtt).is_err() {
             warn!("Error sending message to LTS2.");
         }
-        if !site_cake_drops.is_empty()
-            && crate::lts2_sys::site_cake_drops(&site_cake_drops).is_err()
+        if !batches.site_cake_drops.is_empty()
+            && crate::lts2_sys::site_cake_drops(&batches.site_cake_drops).is_err()
         {
             warn!("Error sending message to LTS2.");
         }
-        if !site_cake_marks.is_empty()
-            && crate::lts2_sys::site_cake_marks(&site_cake_marks).is_err()
+        if !batches.site_cake_marks.is_empty()
+            && crate::lts2_sys::site_cake_marks(&batches.site_cake_marks).is_err()
         {
             warn!("Error sending message to LTS2.");
         }
@@ -543,6 +318,276 @@ pub(crate) fn submit_throughput_stats(
     }
 }
 
+/// Per-circuit and per-site statistics for a single tick. Shared by the
+/// Insight ingestor and the InfluxDB exporter so both see the same numbers.
+#[derive(Default)]
+pub(crate) struct TickBatches {
+    pub(crate) circuit_throughput: Vec<CircuitThroughput>,
+    pub(crate) circuit_retransmits: Vec<CircuitRetransmits>,
+    pub(crate) circuit_rtt: Vec<CircuitRtt>,
+    pub(crate) circuit_cake_drops: Vec<CircuitCakeDrops>,
+    pub(crate) circuit_cake_marks: Vec<CircuitCakeMarks>,
+    pub(crate) site_throughput: Vec<SiteThroughput>,
+    pub(crate) site_retransmits: Vec<SiteRetransmits>,
+    pub(crate) site_rtt: Vec<SiteRtt>,
+    pub(crate) site_cake_drops: Vec<SiteCakeDrops>,
+    pub(crate) site_cake_marks: Vec<SiteCakeMarks>,
+}
+
+/// Gathers per-circuit and per-site statistics, scaled to per-second rates.
+pub(crate) fn gather_tick_batches(now: u64, scale: f64) -> TickBatches {
+    // Start by combining the throughput data for each circuit as a whole
+    struct CircuitThroughputTemp {
+        bytes: DownUpOrder<u64>,
+        packets: DownUpOrder<u64>,
+        tcp_packets: DownUpOrder<u64>,
+        udp_packets: DownUpOrder<u64>,
+        icmp_packets: DownUpOrder<u64>,
+    }
+
+    let mut crazy_values: FxHashSet<i64> = FxHashSet::default(); // Circuits to skip because the numbers are too high
+    let mut circuit_throughput: FxHashMap<i64, CircuitThroughputTemp> = FxHashMap::default();
+    let mut circuit_retransmits: FxHashMap<i64, DownUpOrder<u64>> = FxHashMap::default();
+
+    let shaped_devices = SHAPED_DEVICES.load();
+    const CRAZY_LIMIT: u64 = 8; // 8x the max bandwidth
+    let plan_lookup: FxHashMap<i64, (u64, u64)> = shaped_devices
+        .devices
+        .iter()
+        // Bandwidth: mbps * 1_000_000 to bytes
+        .map(|d| {
+            (
+                d.circuit_hash,
+                (
+                    d.download_max_mbps.round() as u64 * 1_000_000 * CRAZY_LIMIT,
+                    d.upload_max_mbps.round() as u64 * 1_000_000 * CRAZY_LIMIT,
+                ),
+            )
+        })
+        .collect();
+
+    THROUGHPUT_TRACKER
+        .raw_data
+        .lock()
+        .iter()
+        .filter(|(_k, h)| h.circuit_id.is_some() && h.bytes_per_second.not_zero())
+        .for_each(|(_k, h)| {
+            let mut crazy = false;
+            if let Some((dl, ul)) = plan_lookup.get(&h.circuit_hash.unwrap_or(0))
+                && (h.bytes_per_second.down > *dl || h.bytes_per_second.up > *ul)
+            {
+                crazy_values.insert(h.circuit_hash.unwrap_or(0));
+                crazy = true;
+            }
+
+            if crazy {
+                return;
+            }
+            if let Some(c) = circuit_throughput.get_mut(&h.circuit_hash.unwrap_or(0)) {
+                c.bytes += h.bytes_per_second;
+                c.packets += h.packets_per_second;
+                c.tcp_packets += h.tcp_packets;
+                c.udp_packets += h.udp_packets;
+                c.icmp_packets += h.icmp_packets;
+            } else {
+                circuit_throughput.insert(
+                    h.circuit_hash.unwrap_or(0),
+                    CircuitThroughputTemp {
+                        bytes: h.bytes_per_second,
+                        packets: h.packets_per_second,
+                        tcp_packets: h.tcp_packets,
+                        udp_packets: h.udp_packets,
+                        icmp_packets: h.icmp_packets,
+                    },
+                );
+            }
+        });
+
+    THROUGHPUT_TRACKER
+        .raw_data
+        .lock()
+        .iter()
+        .filter(|(_k, h)| {
+            h.circuit_id.is_some()
+                && h.tcp_retransmits.not_zero()
+                && !crazy_values.contains(&h.circuit_hash.unwrap_or(0))
+        })
+        .for_each(|(_k, h)| {
+            if let Some(c) = circuit_retransmits.get_mut(&h.circuit_hash.unwrap_or(0)) {
+                *c += h.tcp_retransmits;
+            } else {
+                circuit_retransmits.insert(h.circuit_hash.unwrap_or(0), h.tcp_retransmits);
+            }
+        });
+
+    // And now we send it
+    let circuit_throughput_batch = circuit_throughput
+        .into_iter()
+        .map(|(k, v)| crate::lts2_sys::shared_types::CircuitThroughput {
+            timestamp: now,
+            circuit_hash: k,
+            download_bytes: scale_u64_by_f64(v.bytes.down, scale),
+            upload_bytes: scale_u64_by_f64(v.bytes.up, scale),
+            packets_down: scale_u64_by_f64(v.packets.down, scale),
+            packets_up: scale_u64_by_f64(v.packets.up, scale),
+            tcp_packets_down: scale_u64_by_f64(v.tcp_packets.down, scale),
+            tcp_packets_up: scale_u64_by_f64(v.tcp_packets.up, scale),
+            udp_packets_down: scale_u64_by_f64(v.udp_packets.down, scale),
+            udp_packets_up: scale_u64_by_f64(v.udp_packets.up, scale),
+            icmp_packets_down: scale_u64_by_f64(v.icmp_packets.down, scale),
+            icmp_packets_up: scale_u64_by_f64(v.icmp_packets.up, scale),
+        })
+        .collect::<Vec<_>>();
+
+    let circuit_retransmits_batch = circuit_retransmits
+        .into_iter()
+        .map(|(k, v)| crate::lts2_sys::shared_types::CircuitRetransmits {
+            timestamp: now,
+            circuit_hash: k,
+            tcp_retransmits_down: v.down as u32,
+            tcp_retransmits_up: v.up as u32,
+        })
+        .collect::<Vec<_>>();
+
+    let circuit_rtt_snapshot = CIRCUIT_RTT_BUFFERS.load();
+    let circuit_rtt_batch = circuit_rtt_snapshot
+        .iter()
+        .filter(|(circuit_hash, _)| !crazy_values.contains(circuit_hash))
+        .filter_map(|(circuit_hash, rtt_buffer)| {
+            let download = rtt_buffer
+                .median_new_data(FlowbeeEffectiveDirection::Download)
+                .as_nanos();
+            let upload = rtt_buffer
+                .median_new_data(FlowbeeEffectiveDirection::Upload)
+                .as_nanos();
+
+            let median_nanos = match (download, upload) {
+                (0, 0) => return None,
+                (d, 0) => d,
+                (0, u) => u,
+                (d, u) => d.saturating_add(u) / 2,
+            };
+
+            Some(crate::lts2_sys::shared_types::CircuitRtt {
+                timestamp: now,
+                circuit_hash: *circuit_hash,
+                median_rtt: (median_nanos as f64 / 1_000_000.0) as f32,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Per host CAKE stats
+    let mut cake_drops: Vec<CircuitCakeDrops> = Vec::new();
+    let mut cake_marks: Vec<CircuitCakeMarks> = Vec::new();
+    ALL_QUEUE_SUMMARY.iterate_queues(|circuit_hash, drops, marks| {
+        if drops.not_zero() {
+            cake_drops.push(CircuitCakeDrops {
+                timestamp: now,
+                circuit_hash,
+                cake_drops_down: drops.get_down() as u32,
+                cake_drops_up: drops.get_up() as u32,
+            });
+        }
+        if marks.not_zero() {
+            cake_marks.push(CircuitCakeMarks {
+                timestamp: now,
+                circuit_hash,
+                cake_marks_down: marks.get_down() as u32,
+                cake_marks_up: marks.get_up() as u32,
+            });
+        }
+    });
+
+    // Network tree stats
+    let tree = {
+        let reader = NETWORK_JSON.read();
+        reader.get_nodes_when_ready().clone()
+    };
+    let mut site_throughput: Vec<crate::lts2_sys::shared_types::SiteThroughput> = Vec::new();
+    let mut site_retransmits: Vec<crate::lts2_sys::shared_types::SiteRetransmits> = Vec::new();
+    let mut site_rtt: Vec<crate::lts2_sys::shared_types::SiteRtt> = Vec::new();
+    let mut site_cake_drops: Vec<crate::lts2_sys::shared_types::SiteCakeDrops> = Vec::new();
+    let mut site_cake_marks: Vec<crate::lts2_sys::shared_types::SiteCakeMarks> = Vec::new();
+    tree.iter().for_each(|node| {
+        let site_hash = hash_to_i64(&node.name);
+        if node.current_throughput.not_zero() {
+            site_throughput.push(crate::lts2_sys::shared_types::SiteThroughput {
+                timestamp: now,
+                site_hash,
+                download_bytes: scale_u64_by_f64(node.current_throughput.down, scale),
+                upload_bytes: scale_u64_by_f64(node.current_throughput.up, scale),
+                packets_down: scale_u64_by_f64(node.current_packets.down, scale),
+                packets_up: scale_u64_by_f64(node.current_packets.up, scale),
+                packets_tcp_down: scale_u64_by_f64(node.current_tcp_packets.down, scale),
+                packets_tcp_up: scale_u64_by_f64(node.current_tcp_packets.up, scale),
+                packets_udp_down: scale_u64_by_f64(node.current_udp_packets.down, scale),
+                packets_udp_up: scale_u64_by_f64(node.current_udp_packets.up, scale),
+                packets_icmp_down: scale_u64_by_f64(node.current_icmp_packets.down, scale),
+                packets_icmp_up: scale_u64_by_f64(node.current_icmp_packets.up, scale),
+            });
+        }
+        if node.current_tcp_retransmits.not_zero() {
+            site_retransmits.push(crate::lts2_sys::shared_types::SiteRetransmits {
+                timestamp: now,
+                site_hash,
+                tcp_retransmits_down: node.current_tcp_retransmits.down as u32,
+                tcp_retransmits_up: node.current_tcp_retransmits.up as u32,
+            });
+        }
+        if node.current_drops.not_zero() {
+            site_cake_drops.push(crate::lts2_sys::shared_types::SiteCakeDrops {
+                timestamp: now,
+                site_hash,
+                cake_drops_down: node.current_drops.get_down() as u32,
+                cake_drops_up: node.current_drops.get_up() as u32,
+            });
+        }
+        if node.current_marks.not_zero() {
+            site_cake_marks.push(crate::lts2_sys::shared_types::SiteCakeMarks {
+                timestamp: now,
+                site_hash,
+                cake_marks_down: node.current_marks.get_down() as u32,
+                cake_marks_up: node.current_marks.get_up() as u32,
+            });
+        }
+        let download = node
+            .rtt_buffer
+            .median_new_data(FlowbeeEffectiveDirection::Download)
+            .as_nanos();
+        let upload = node
+            .rtt_buffer
+            .median_new_data(FlowbeeEffectiveDirection::Upload)
+            .as_nanos();
+        let median_nanos = match (download, upload) {
+            (0, 0) => None,
+            (d, 0) => Some(d),
+            (0, u) => Some(u),
+            (d, u) => Some(d.saturating_add(u) / 2),
+        };
+
+        if let Some(median_nanos) = median_nanos {
+            site_rtt.push(crate::lts2_sys::shared_types::SiteRtt {
+                timestamp: now,
+                site_hash,
+                median_rtt: (median_nanos as f64 / 1_000_000.0) as f32,
+            });
+        }
+    });
+
+    TickBatches {
+        circuit_throughput: circuit_throughput_batch,
+        circuit_retransmits: circuit_retransmits_batch,
+        circuit_rtt: circuit_rtt_batch,
+        circuit_cake_drops: cake_drops,
+        circuit_cake_marks: cake_marks,
+        site_throughput,
+        site_retransmits,
+        site_rtt,
+        site_cake_drops,
+        site_cake_marks,
+    }
+}
+
 fn ip4_to_bytes(ip: (Ipv4Addr, u32)) -> ([u8; 4], u8) {
     let bytes = ip.0.octets();
     (bytes, ip.1 as u8)
@@ -32,6 +32,8 @@ pub(crate) struct ThroughputEntry {
     pub(crate) tcp_retransmits: DownUpOrder<u64>,
     pub(crate) tcp_retransmit_packets: DownUpOrder<u64>,
     pub(crate) qoq: QoqScores,
+    pub(crate) new_flows: u64,     // Flows first seen this cycle
+    pub(crate) new_tcp_flows: u64, // TCP flows first seen this cycle
 }
 
 impl ThroughputEntry {
@@ -1,15 +1,16 @@
 use super::{
     RETIRE_AFTER_SECONDS,
     flow_data::{
-        ALL_FLOWS, AsnAggregate, FlowAnalysis, FlowbeeLocalData, RttBuffer, RttData,
-        get_flowbee_event_count_and_reset, update_asn_heatmaps,
+        ALL_FLOWS, AsnAggregate, FlowAnalysis, FlowExportEvent, FlowbeeLocalData, RttBuffer,
+        RttData, crossed_active_timeout, get_flowbee_event_count_and_reset, update_asn_heatmaps,
     },
     throughput_entry::ThroughputEntry,
 };
+use crate::anomaly_detection::{HostTraffic, TrafficCounters};
 use crate::throughput_tracker::CIRCUIT_RTT_BUFFERS;
 use crate::{
     shaped_devices_tracker::{SHAPED_DEVICE_HASH_CACHE, SHAPED_DEVICES},
-    stats::HIGH_WATERMARK,
+    stats::{FLOWS_TRACKED, HIGH_WATERMARK},
     throughput_tracker::flow_data::{FlowbeeEffectiveDirection, expire_rtt_flows, flowbee_rtt_map},
 };
 use fxhash::FxHashMap;
@@ -40,7 +41,7 @@ const MIN_QOO_FLOW_BYTES: u64 = 1_000_000;
 
 pub(crate) struct FlowApplyContext<'a> {
     pub(crate) timeout_seconds: u64,
-    pub(crate) sender: crossbeam_channel::Sender<(FlowbeeKey, (FlowbeeLocalData, FlowAnalysis))>,
+    pub(crate) sender: crossbeam_channel::Sender<FlowExportEvent>,
     pub(crate) net_json_calc: &'a mut NetworkJson,
     pub(crate) rtt_circuit_tracker: &'a mut FxHashMap<XdpIpAddress, RttBuffer>,
     pub(crate) rtt_by_circuit: &'a mut FxHashMap<i64, RttBuffer>,
@@ -375,6 +376,8 @@ impl ThroughputTracker {
             v.prev_tcp_packets = v.tcp_packets;
             v.prev_udp_packets = v.udp_packets;
             v.prev_icmp_packets = v.icmp_packets;
+            v.new_flows = 0;
+            v.new_tcp_flows = 0;
 
             // Roll out stale RTT data
             if self_cycle > RETIRE_AFTER_SECONDS
@@ -586,6 +589,8 @@ impl ThroughputTracker {
                     tcp_retransmits: DownUpOrder::zeroed(),
                     tcp_retransmit_packets: DownUpOrder::zeroed(),
                     qoq: QoqScores::default(),
+                    new_flows: 0,
+                    new_tcp_flows: 0,
                 };
                 raw_data.insert(*xdp_ip, entry);
             }
@@ -636,9 +641,18 @@ impl ThroughputTracker {
         } = ctx;
         //log::debug!("Flowbee events this second: {}", get_flowbee_event_count_and_reset());
         let self_cycle = self.cycle.load(std::sync::atomic::Ordering::Relaxed);
-        let enable_asn_heatmaps = lqos_config::load_config()
+        let config = lqos_config::load_config().ok();
+        let enable_asn_heatmaps = config
+            .as_ref()
             .map(|config| config.enable_asn_heatmaps)
             .unwrap_or(true);
+        // Interim records are only useful if something exports them.
+        let active_timeout_nanos = config
+            .as_ref()
+            .and_then(|config| config.flows.as_ref())
+            .filter(|flows| !flows.all_export_targets().is_empty())
+            .map(|flows| Duration::from_secs(flows.active_timeout_seconds).as_nanos() as u64)
+            .unwrap_or(0);
         let qoo_profile = lqos_config::active_qoo_profile().ok();
         let mut asn_aggregates: FxHashMap<u32, AsnAggregate> = FxHashMap::default();
         let mut add_asn_sample = |asn: u32,
@@ -710,6 +724,14 @@ impl ThroughputTracker {
                         //this_flow.0.throughput_buffer.push(change_since_last_time);
                         //println!("{change_since_last_time:?}");
 
+                        let active_timeout_reached = data.end_status == 0
+                            && crossed_active_timeout(
+                                this_flow.0.start_time,
+                                this_flow.0.last_seen,
+                                data.last_seen,
+                                active_timeout_nanos,
+                            );
+
                         this_flow.0.set_last_seen(data.last_seen);
                         this_flow.0.set_bytes_sent(data.bytes_sent);
                         this_flow.0.set_packets_sent(data.packets_sent);
@@ -804,7 +826,21 @@ impl ThroughputTracker {
                                 .or_insert_with(DownUpOrder::zeroed)
                                 .checked_add(delta_packets);
                         }
+                        if active_timeout_reached {
+                            let _ = sender.send(FlowExportEvent::Active(
+                                *key,
+                                this_flow.0.clone(),
+                                this_flow.1,
+                            ));
+                        }
                     } else {
+                        // Counted before the flow limit, which a flood can exhaust
+                        if let Some(tracker) = raw_data.get_mut(&key.local_ip) {
+                            tracker.new_flows += 1;
+                            if key.ip_protocol == 6 {
+                                tracker.new_tcp_flows += 1;
+                            }
+                        }
                         // Check if we've hit the flow limit
                         if all_flows_lock.flow_data.len() >= MAX_FLOWS {
                             // Log warning once per second to avoid spam
@@ -1021,7 +1057,7 @@ impl ThroughputTracker {
                 for key in expired_keys.iter() {
                     // Send it off to netperf for analysis if we are supporting doing so.
                     if let Some(d) = all_flows_lock.flow_data.remove(key) {
-                        let _ = sender.send((*key, (d.0.clone(), d.1)));
+                        let _ = sender.send(FlowExportEvent::Finished(*key, d.0, d.1));
                     }
                 }
 
@@ -1036,6 +1072,10 @@ impl ThroughputTracker {
                 .flow_data
                 .retain(|_k, v| v.0.last_seen >= expire);
             all_flows_lock.flow_data.shrink_to_fit();
+            FLOWS_TRACKED.store(
+                all_flows_lock.flow_data.len() as u64,
+                std::sync::atomic::Ordering::Relaxed,
+            );
             expire_rtt_flows();
         }
 
@@ -1116,6 +1156,53 @@ impl ThroughputTracker {
         }
     }
 
+    /// Bytes each shaped circuit transferred during the current cycle, keyed
+    /// by circuit hash. Uses the same entries as `update_totals`.
+    pub(crate) fn circuit_byte_deltas(&self) -> FxHashMap<i64, DownUpOrder<u64>> {
+        let current_cycle = self.cycle.load(std::sync::atomic::Ordering::Relaxed);
+        let mut deltas: FxHashMap<i64, DownUpOrder<u64>> = FxHashMap::default();
+        let raw_data = self.raw_data.lock();
+        raw_data
+            .values()
+            .filter(|v| v.most_recent_cycle == current_cycle && v.first_cycle + 2 < current_cycle)
+            .filter_map(|v| Some((v.circuit_hash?, v)))
+            .for_each(|(circuit_hash, v)| {
+                let entry = deltas.entry(circuit_hash).or_default();
+                entry.down = entry
+                    .down
+                    .saturating_add(v.bytes.down.saturating_sub(v.prev_bytes.down));
+                entry.up = entry
+                    .up
+                    .saturating_add(v.bytes.up.saturating_sub(v.prev_bytes.up));
+            });
+        deltas
+    }
+
+    /// This cycle's traffic for every host that sent or received any, for
+    /// the anomaly detector.
+    pub(crate) fn anomaly_samples(&self) -> Vec<HostTraffic> {
+        let current_cycle = self.cycle.load(std::sync::atomic::Ordering::Relaxed);
+        let raw_data = self.raw_data.lock();
+        raw_data
+            .iter()
+            .filter(|(_, v)| v.most_recent_cycle == current_cycle && v.first_cycle < current_cycle)
+            .map(|(ip, v)| HostTraffic {
+                ip: *ip,
+                circuit_hash: v.circuit_hash,
+                network_json_parents: v.network_json_parents.clone().unwrap_or_default(),
+                counters: TrafficCounters {
+                    bytes: v.bytes.checked_sub_or_zero(v.prev_bytes),
+                    packets: v.packets.checked_sub_or_zero(v.prev_packets),
+                    tcp_packets: v.tcp_packets.checked_sub_or_zero(v.prev_tcp_packets),
+                    udp_packets: v.udp_packets.checked_sub_or_zero(v.prev_udp_packets),
+                    icmp_packets: v.icmp_packets.checked_sub_or_zero(v.prev_icmp_packets),
+                    new_flows: v.new_flows,
+                    new_tcp_flows: v.new_tcp_flows,
+                },
+            })
+            .collect()
+    }
+
     pub(crate) fn next_cycle(&self) {
         self.cycle
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
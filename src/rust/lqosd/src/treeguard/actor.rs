@@ -17,7 +17,8 @@ use crate::treeguard::{bakery, decisions, overrides};
 use crossbeam_channel::{Receiver, Sender};
 use fxhash::{FxHashMap, FxHashSet};
 use lqos_bakery::{BakeryRuntimeNodeOperationFailureReason, BakeryRuntimeNodeOperationStatus};
-use lqos_config::{NetworkJsonNode, ShapedDevice, load_config};
+use lqos_bus::{BusTopic, BusUpdate, TreeGuardDecision, has_bus_subscribers, publish_bus_update};
+use lqos_config::{AuditActor, AuditEntry, NetworkJsonNode, ShapedDevice, load_config};
 use lqos_overrides::{NetworkAdjustment, OverrideFile, OverrideLayer, OverrideStore};
 use lqos_utils::hash_to_i64;
 use lqos_utils::units::DownUpOrder;
@@ -1779,14 +1780,18 @@ fn run_tick(
                     {
                         let parsed = decisions::parse_directional_sqm_override(&token);
                         if let Some(down) = parsed.down {
-                            state.down.desired = down;
+                            state.down.desired = down.state;
                         }
                         if let Some(up) = parsed.up {
-                            state.up.desired = up;
+                            state.up.desired = up.state;
                         }
                     }
                     state
                 });
+            let cake_profiles =
+                base_circuit_cake_profiles(&entry.devices, operator_overrides_snapshot.as_ref());
+            state.down.cake_profile = cake_profiles.down;
+            state.up.cake_profile = cake_profiles.up;
 
             let operator_conflict = entry
                 .device_ids
@@ -2534,6 +2539,7 @@ fn overrides_device_sqm(overrides: &OverrideFile, device_id: &str) -> Option<Str
         if let lqos_overrides::CircuitAdjustment::DeviceAdjustSqm {
             device_id: current,
             sqm_override,
+            ..
         } = adj
         {
             if current != device_id {
@@ -2570,6 +2576,7 @@ fn overrides_sqm_device_ids(overrides: &OverrideFile) -> FxHashSet<String> {
         if let lqos_overrides::CircuitAdjustment::DeviceAdjustSqm {
             device_id,
             sqm_override,
+            ..
         } = adj
             && sqm_override
                 .as_deref()
@@ -2648,7 +2655,7 @@ fn default_sqm_state_for_rate(rate_mbps: f32, config: &lqos_config::Config) -> C
             CircuitSqmState::Cake
         }
     } else {
-        CircuitSqmState::FqCodel
+        decisions::parse_sqm_kind(&default_sqm).unwrap_or(CircuitSqmState::FqCodel)
     }
 }
 
@@ -2668,16 +2675,34 @@ fn base_circuit_sqm_state(
     if let Some(token) = infer_circuit_sqm_override_token(devices, operator_overrides) {
         let parsed = decisions::parse_directional_sqm_override(&token);
         if let Some(v) = parsed.down {
-            down = v;
+            down = v.state;
         }
         if let Some(v) = parsed.up {
-            up = v;
+            up = v.state;
         }
     }
 
     DownUpOrder { down, up }
 }
 
+/// Returns the CAKE profile each direction of a circuit's base SQM token names, so that
+/// TreeGuard writes `cake:<profile>` rather than plain `cake` when it restores CAKE.
+///
+/// This function is pure: it has no side effects.
+fn base_circuit_cake_profiles(
+    devices: &[lqos_config::ShapedDevice],
+    operator_overrides: Option<&OverrideFile>,
+) -> DownUpOrder<Option<String>> {
+    let Some(token) = infer_circuit_sqm_override_token(devices, operator_overrides) else {
+        return DownUpOrder::default();
+    };
+    let parsed = decisions::parse_directional_sqm_override(&token);
+    DownUpOrder {
+        down: parsed.down.and_then(|sqm| sqm.cake_profile),
+        up: parsed.up.and_then(|sqm| sqm.cake_profile),
+    }
+}
+
 /// Appends an entry to the activity ring buffer.
 ///
 /// This function is not pure: it mutates `activity`.
@@ -2693,12 +2718,43 @@ fn push_activity(activity: &mut VecDeque<TreeguardActivityEntry>, entry: Treegua
             entry.entity_type, entry.entity_id, entry.action, entry.persisted, entry.reason
         );
     }
+    if activity_changes_state(&entry.action) {
+        let detail = if entry.persisted {
+            format!("{} (persisted)", entry.reason)
+        } else {
+            entry.reason.clone()
+        };
+        crate::audit::record(
+            AuditEntry::new(AuditActor::subsystem("treeguard"), &entry.action)
+                .target(format!("{}:{}", entry.entity_type, entry.entity_id))
+                .detail(detail),
+        );
+    }
+    if has_bus_subscribers(BusTopic::TreeGuard) {
+        publish_bus_update(BusUpdate::TreeGuardDecision(TreeGuardDecision {
+            time: entry.time.clone(),
+            entity_type: entry.entity_type.clone(),
+            entity_id: entry.entity_id.clone(),
+            action: entry.action.clone(),
+            persisted: entry.persisted,
+            reason: entry.reason.clone(),
+        }));
+    }
     if activity.len() >= ACTIVITY_RING_CAPACITY {
         activity.pop_front();
     }
     activity.push_back(entry);
 }
 
+/// Activity worth keeping in the audit log: actions TreeGuard carried out,
+/// not the ones it skipped, deferred or failed to apply.
+pub(crate) fn activity_changes_state(action: &str) -> bool {
+    !(action.starts_with("skip")
+        || action.contains("failed")
+        || action.contains("rejected")
+        || action.contains("deferred"))
+}
+
 struct CircuitSqmApplyContext<'a> {
     status: &'a mut TreeguardStatusData,
     activity: &'a mut VecDeque<TreeguardActivityEntry>,
@@ -2835,7 +2891,16 @@ fn apply_circuit_sqm_change<P, C, L>(
         changed_up,
     } = transition;
 
-    let token = decisions::format_directional_sqm_override(proposed_down, proposed_up);
+    let token = decisions::format_directional_sqm_override(
+        &decisions::DirectionalSqm {
+            state: proposed_down,
+            cake_profile: state.down.cake_profile.clone(),
+        },
+        &decisions::DirectionalSqm {
+            state: proposed_up,
+            cake_profile: state.up.cake_profile.clone(),
+        },
+    );
     let returning_to_base = proposed_down == base_sqm.down && proposed_up == base_sqm.up;
     let live_token = if returning_to_base {
         "/"
@@ -4036,6 +4101,63 @@ mod tests {
         assert!(!treeguard_manages_circuit_direction(
             CircuitSqmState::FqCodel
         ));
+        assert!(!treeguard_manages_circuit_direction(CircuitSqmState::FqPie));
+        assert!(!treeguard_manages_circuit_direction(CircuitSqmState::Sfq));
+    }
+
+    #[test]
+    fn base_cake_profiles_come_from_the_operator_token() {
+        let mut shaped_devices = vec![ShapedDevice {
+            circuit_id: "circuit-dsl".to_string(),
+            device_id: "device-dsl".to_string(),
+            sqm_override: Some("cake:dsl/fq_codel".to_string()),
+            ..ShapedDevice::default()
+        }];
+        let profiles = base_circuit_cake_profiles(&shaped_devices, None);
+        assert_eq!(profiles.down.as_deref(), Some("dsl"));
+        assert_eq!(profiles.up, None);
+
+        let config = lqos_config::Config::default();
+        let base = base_circuit_sqm_state(&shaped_devices, None, &config, 50.0, 20.0);
+        assert_eq!(base.down, CircuitSqmState::Cake);
+        assert_eq!(base.up, CircuitSqmState::FqCodel);
+
+        shaped_devices[0].sqm_override = None;
+        assert_eq!(
+            base_circuit_cake_profiles(&shaped_devices, None),
+            DownUpOrder::default()
+        );
+    }
+
+    #[test]
+    fn operator_fq_pie_and_sfq_circuits_keep_their_queues() {
+        let config = lqos_config::Config::default();
+        let shaped_devices = vec![ShapedDevice {
+            circuit_id: "circuit-pie".to_string(),
+            device_id: "device-pie".to_string(),
+            sqm_override: Some("fq_pie/sfq".to_string()),
+            ..ShapedDevice::default()
+        }];
+        let base = base_circuit_sqm_state(&shaped_devices, None, &config, 50.0, 20.0);
+        assert_eq!(base.down, CircuitSqmState::FqPie);
+        assert_eq!(base.up, CircuitSqmState::Sfq);
+
+        let mut state = crate::treeguard::state::CircuitState::default();
+        state.down.desired = base.down;
+        state.up.desired = base.up;
+        let transition = circuit_sqm_transition_from_decision(
+            &state,
+            base,
+            decisions::CircuitSqmDecision {
+                down: Some(CircuitSqmState::FqCodel),
+                up: Some(CircuitSqmState::Cake),
+            },
+        );
+
+        assert_eq!(transition.proposed_down, CircuitSqmState::FqPie);
+        assert_eq!(transition.proposed_up, CircuitSqmState::Sfq);
+        assert!(!transition.changed_down);
+        assert!(!transition.changed_up);
     }
 
     #[test]
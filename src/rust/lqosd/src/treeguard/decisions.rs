@@ -318,6 +318,7 @@ pub fn decide_circuit_sqm(input: CircuitSqmInput<'_>) -> CircuitSqmDecision {
                     None
                 }
             }
+            CircuitSqmState::FqPie | CircuitSqmState::Sfq => None,
         }
     };
 
@@ -372,6 +373,7 @@ pub fn decide_circuit_sqm(input: CircuitSqmInput<'_>) -> CircuitSqmDecision {
                     None
                 }
             }
+            CircuitSqmState::FqPie | CircuitSqmState::Sfq => None,
         };
 
         if let Some(s) = proposed {
@@ -383,40 +385,85 @@ pub fn decide_circuit_sqm(input: CircuitSqmInput<'_>) -> CircuitSqmDecision {
     decision
 }
 
-/// Formats an SQM override token from per-direction desired states.
+/// One direction of an SQM override token.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DirectionalSqm {
+    /// The queue kind.
+    pub state: CircuitSqmState,
+    /// The profile named by a `cake:<profile>` token.
+    pub cake_profile: Option<String>,
+}
+
+impl From<CircuitSqmState> for DirectionalSqm {
+    fn from(state: CircuitSqmState) -> Self {
+        Self {
+            state,
+            cake_profile: None,
+        }
+    }
+}
+
+/// Formats an SQM override token from per-direction desired states. A CAKE
+/// profile is only written for directions that use CAKE.
 ///
 /// This function is pure: it has no side effects.
-pub fn format_directional_sqm_override(down: CircuitSqmState, up: CircuitSqmState) -> String {
-    let down_s = match down {
-        CircuitSqmState::Cake => "cake",
-        CircuitSqmState::FqCodel => "fq_codel",
-    };
-    let up_s = match up {
+pub fn format_directional_sqm_override(down: &DirectionalSqm, up: &DirectionalSqm) -> String {
+    fn format_one(sqm: &DirectionalSqm) -> String {
+        match (sqm.state, &sqm.cake_profile) {
+            (CircuitSqmState::Cake, Some(profile)) => format!("cake:{profile}"),
+            (state, _) => sqm_kind_token(state).to_string(),
+        }
+    }
+
+    format!("{}/{}", format_one(down), format_one(up))
+}
+
+fn sqm_kind_token(state: CircuitSqmState) -> &'static str {
+    match state {
         CircuitSqmState::Cake => "cake",
         CircuitSqmState::FqCodel => "fq_codel",
-    };
-    format!("{down_s}/{up_s}")
+        CircuitSqmState::FqPie => "fq_pie",
+        CircuitSqmState::Sfq => "sfq",
+    }
+}
+
+/// Parses a single-direction queue kind such as `fq_codel`.
+///
+/// This function is pure: it has no side effects.
+pub fn parse_sqm_kind(kind: &str) -> Option<CircuitSqmState> {
+    [
+        CircuitSqmState::Cake,
+        CircuitSqmState::FqCodel,
+        CircuitSqmState::FqPie,
+        CircuitSqmState::Sfq,
+    ]
+    .into_iter()
+    .find(|state| kind.trim().eq_ignore_ascii_case(sqm_kind_token(*state)))
 }
 
 /// Parses an SQM override token into per-direction SQM states.
 ///
 /// The token may be a single value (applies to both directions) or a `down/up` token.
-/// Empty and `"none"` tokens map to `None` for that direction.
+/// Empty and `"none"` tokens map to `None` for that direction. `cake:<profile>` keeps
+/// its profile.
 ///
 /// This function is pure: it has no side effects.
-pub fn parse_directional_sqm_override(token: &str) -> DownUpOrder<Option<CircuitSqmState>> {
-    fn parse_one(t: &str) -> Option<CircuitSqmState> {
+pub fn parse_directional_sqm_override(token: &str) -> DownUpOrder<Option<DirectionalSqm>> {
+    fn parse_one(t: &str) -> Option<DirectionalSqm> {
         let t = t.trim();
         if t.is_empty() || t.eq_ignore_ascii_case("none") {
             return None;
         }
-        if t.eq_ignore_ascii_case("cake") {
-            return Some(CircuitSqmState::Cake);
-        }
-        if t.eq_ignore_ascii_case("fq_codel") {
-            return Some(CircuitSqmState::FqCodel);
+        if let Some((kind, profile)) = t.split_once(':') {
+            let profile = profile.trim();
+            return (kind.trim().eq_ignore_ascii_case("cake") && !profile.is_empty()).then(|| {
+                DirectionalSqm {
+                    state: CircuitSqmState::Cake,
+                    cake_profile: Some(profile.to_string()),
+                }
+            });
         }
-        None
+        parse_sqm_kind(t).map(DirectionalSqm::from)
     }
 
     let token = token.trim();
@@ -435,7 +482,10 @@ pub fn parse_directional_sqm_override(token: &str) -> DownUpOrder<Option<Circuit
     }
 
     let v = parse_one(token);
-    DownUpOrder { down: v, up: v }
+    DownUpOrder {
+        down: v.clone(),
+        up: v,
+    }
 }
 
 #[cfg(test)]
@@ -1116,17 +1166,21 @@ mod tests {
     #[test]
     fn directional_token_format_and_parse() {
         assert_eq!(
-            format_directional_sqm_override(CircuitSqmState::Cake, CircuitSqmState::FqCodel),
+            format_directional_sqm_override(
+                &CircuitSqmState::Cake.into(),
+                &CircuitSqmState::FqCodel.into()
+            ),
             "cake/fq_codel"
         );
 
+        let state = |sqm: Option<DirectionalSqm>| sqm.map(|sqm| sqm.state);
         let parsed = parse_directional_sqm_override("cake/fq_codel");
-        assert_eq!(parsed.down, Some(CircuitSqmState::Cake));
-        assert_eq!(parsed.up, Some(CircuitSqmState::FqCodel));
+        assert_eq!(state(parsed.down), Some(CircuitSqmState::Cake));
+        assert_eq!(state(parsed.up), Some(CircuitSqmState::FqCodel));
 
         let parsed = parse_directional_sqm_override("fq_codel");
-        assert_eq!(parsed.down, Some(CircuitSqmState::FqCodel));
-        assert_eq!(parsed.up, Some(CircuitSqmState::FqCodel));
+        assert_eq!(state(parsed.down), Some(CircuitSqmState::FqCodel));
+        assert_eq!(state(parsed.up), Some(CircuitSqmState::FqCodel));
 
         let parsed = parse_directional_sqm_override("none");
         assert_eq!(parsed.down, None);
@@ -1134,6 +1188,47 @@ mod tests {
 
         let parsed = parse_directional_sqm_override("/fq_codel");
         assert_eq!(parsed.down, None);
-        assert_eq!(parsed.up, Some(CircuitSqmState::FqCodel));
+        assert_eq!(state(parsed.up), Some(CircuitSqmState::FqCodel));
+    }
+
+    #[test]
+    fn operator_only_queue_kinds_parse_and_round_trip() {
+        let parsed = parse_directional_sqm_override("fq_pie/SFQ");
+        let (down, up) = (parsed.down.expect("down"), parsed.up.expect("up"));
+        assert_eq!(down.state, CircuitSqmState::FqPie);
+        assert_eq!(up.state, CircuitSqmState::Sfq);
+        assert_eq!(format_directional_sqm_override(&down, &up), "fq_pie/sfq");
+    }
+
+    #[test]
+    fn cake_profiles_survive_a_round_trip() {
+        let parsed = parse_directional_sqm_override("cake:dsl/fq_codel");
+        let (down, up) = (parsed.down.expect("down"), parsed.up.expect("up"));
+        assert_eq!(down.state, CircuitSqmState::Cake);
+        assert_eq!(down.cake_profile.as_deref(), Some("dsl"));
+        assert_eq!(
+            format_directional_sqm_override(&down, &up),
+            "cake:dsl/fq_codel"
+        );
+
+        let parsed = parse_directional_sqm_override("cake:fibre-1");
+        let (down, up) = (parsed.down.expect("down"), parsed.up.expect("up"));
+        assert_eq!(
+            format_directional_sqm_override(&down, &up),
+            "cake:fibre-1/cake:fibre-1"
+        );
+
+        // TreeGuard moving a direction to fq_codel drops that direction's profile.
+        let switched = DirectionalSqm {
+            state: CircuitSqmState::FqCodel,
+            ..down.clone()
+        };
+        assert_eq!(
+            format_directional_sqm_override(&switched, &up),
+            "fq_codel/cake:fibre-1"
+        );
+
+        assert_eq!(parse_directional_sqm_override("cake:").down, None);
+        assert_eq!(parse_directional_sqm_override("fq_codel:x").down, None);
     }
 }
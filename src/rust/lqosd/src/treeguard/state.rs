@@ -58,6 +58,10 @@ pub enum CircuitSqmState {
     Cake,
     /// Use fq_codel (lower CPU cost).
     FqCodel,
+    /// Use fq_pie. Only ever the operator's choice; TreeGuard leaves it alone.
+    FqPie,
+    /// Use SFQ. Only ever the operator's choice; TreeGuard leaves it alone.
+    Sfq,
 }
 
 /// Per-direction link tracking state.
@@ -117,6 +121,9 @@ pub struct LinkState {
 pub struct CircuitDirectionState {
     /// Current desired SQM profile for this direction.
     pub desired: CircuitSqmState,
+    /// CAKE profile from the operator's `cake:<profile>` token, kept when
+    /// TreeGuard writes CAKE back for this direction.
+    pub cake_profile: Option<String>,
     /// Last state change time (seconds since UNIX epoch), if any.
     pub last_change_unix: Option<u64>,
     /// History of recent SQM switches (seconds since UNIX epoch), newest at the back.
@@ -1,7 +1,7 @@
 use std::collections::VecDeque;
 use std::sync::atomic::{AtomicU64, Ordering};
 
-use lqos_bus::{UrgentIssue, UrgentSeverity, UrgentSource};
+use lqos_bus::{BusUpdate, UrgentIssue, UrgentSeverity, UrgentSource};
 use parking_lot::Mutex;
 
 use lqos_utils::unix_time::unix_now;
@@ -67,8 +67,10 @@ pub fn submit(
         context,
         dedupe_key: Some(key),
     };
-    guard.push_back(issue);
+    guard.push_back(issue.clone());
     prune_expired(&mut guard);
+    drop(guard);
+    lqos_bus::publish_bus_update(BusUpdate::UrgentIssue(issue));
 }
 
 pub fn list() -> Vec<UrgentIssue> {
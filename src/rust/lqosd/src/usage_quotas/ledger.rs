@@ -0,0 +1,177 @@
+//! The persisted per-circuit usage ledger and billing period arithmetic.
+
+use chrono::{Datelike, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Bytes a circuit transferred during one billing period.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeriodUsage {
+    /// First day of the period.
+    pub period_start: NaiveDate,
+    /// First day of the next period.
+    pub period_end: NaiveDate,
+    pub download_bytes: u64,
+    pub upload_bytes: u64,
+}
+
+impl PeriodUsage {
+    fn new(today: NaiveDate, anchor_day: u8) -> Self {
+        let (period_start, period_end) = billing_period(today, anchor_day);
+        Self {
+            period_start,
+            period_end,
+            download_bytes: 0,
+            upload_bytes: 0,
+        }
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.download_bytes.saturating_add(self.upload_bytes)
+    }
+}
+
+/// Usage for one circuit: the open period and the most recent closed ones.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitUsage {
+    pub current: PeriodUsage,
+    /// Closed periods, newest first.
+    #[serde(default)]
+    pub history: VecDeque<PeriodUsage>,
+    /// When the circuit was throttled for exceeding its quota (unix seconds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throttled_since: Option<u64>,
+}
+
+/// Usage for every circuit, keyed by circuit ID.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageLedger {
+    #[serde(default)]
+    pub circuits: BTreeMap<String, CircuitUsage>,
+}
+
+/// The billing period containing `date`, as `(start, end)` where `end` is the
+/// start of the following period. Periods start on `anchor_day` of each month.
+pub fn billing_period(date: NaiveDate, anchor_day: u8) -> (NaiveDate, NaiveDate) {
+    let anchor_day = u32::from(anchor_day.clamp(1, 28));
+    let this_month = date.with_day(anchor_day).unwrap_or(date);
+    let start = if date.day() >= anchor_day {
+        this_month
+    } else {
+        this_month
+            .checked_sub_months(Months::new(1))
+            .unwrap_or(this_month)
+    };
+    let end = start.checked_add_months(Months::new(1)).unwrap_or(start);
+    (start, end)
+}
+
+impl UsageLedger {
+    /// Adds traffic for a circuit, opening a new period first if needed.
+    pub fn add(
+        &mut self,
+        circuit_id: &str,
+        today: NaiveDate,
+        anchor_day: u8,
+        history_periods: usize,
+        download_bytes: u64,
+        upload_bytes: u64,
+    ) {
+        let usage = self
+            .circuits
+            .entry(circuit_id.to_string())
+            .or_insert_with(|| CircuitUsage {
+                current: PeriodUsage::new(today, anchor_day),
+                history: VecDeque::new(),
+                throttled_since: None,
+            });
+        usage.roll(today, anchor_day, history_periods);
+        usage.current.download_bytes = usage.current.download_bytes.saturating_add(download_bytes);
+        usage.current.upload_bytes = usage.current.upload_bytes.saturating_add(upload_bytes);
+    }
+
+    /// Closes every period that has ended by `today`. Returns the IDs of
+    /// circuits that moved into a new period.
+    pub fn roll_all(
+        &mut self,
+        today: NaiveDate,
+        anchor_day: impl Fn(&str) -> u8,
+        history_periods: usize,
+    ) -> Vec<String> {
+        self.circuits
+            .iter_mut()
+            .filter_map(|(circuit_id, usage)| {
+                usage
+                    .roll(today, anchor_day(circuit_id), history_periods)
+                    .then(|| circuit_id.clone())
+            })
+            .collect()
+    }
+}
+
+impl CircuitUsage {
+    /// Starts a new period if `today` is outside the current one (including
+    /// when the anchor day changed). Returns true if it did.
+    fn roll(&mut self, today: NaiveDate, anchor_day: u8, history_periods: usize) -> bool {
+        let (start, _) = billing_period(today, anchor_day);
+        if start == self.current.period_start {
+            return false;
+        }
+        let closed = std::mem::replace(&mut self.current, PeriodUsage::new(today, anchor_day));
+        self.history.push_front(closed);
+        self.history.truncate(history_periods);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).expect("valid date")
+    }
+
+    #[test]
+    fn periods_start_on_the_anchor_day() {
+        assert_eq!(
+            billing_period(date(2026, 3, 14), 15),
+            (date(2026, 2, 15), date(2026, 3, 15))
+        );
+        assert_eq!(
+            billing_period(date(2026, 3, 15), 15),
+            (date(2026, 3, 15), date(2026, 4, 15))
+        );
+        assert_eq!(
+            billing_period(date(2026, 1, 5), 10),
+            (date(2025, 12, 10), date(2026, 1, 10))
+        );
+        assert_eq!(
+            billing_period(date(2026, 12, 31), 1),
+            (date(2026, 12, 1), date(2027, 1, 1))
+        );
+    }
+
+    #[test]
+    fn usage_rolls_into_history() {
+        let mut ledger = UsageLedger::default();
+        ledger.add("c1", date(2026, 3, 20), 1, 2, 100, 10);
+        ledger.add("c1", date(2026, 3, 21), 1, 2, 50, 5);
+        let usage = &ledger.circuits["c1"];
+        assert_eq!(usage.current.total_bytes(), 165);
+        assert!(usage.history.is_empty());
+
+        assert!(ledger.roll_all(date(2026, 3, 31), |_| 1, 2).is_empty());
+        assert_eq!(ledger.roll_all(date(2026, 4, 1), |_| 1, 2), vec!["c1"]);
+        let usage = &ledger.circuits["c1"];
+        assert_eq!(usage.current.total_bytes(), 0);
+        assert_eq!(usage.current.period_start, date(2026, 4, 1));
+        assert_eq!(usage.history[0].download_bytes, 150);
+
+        ledger.add("c1", date(2026, 5, 2), 1, 2, 1, 1);
+        ledger.add("c1", date(2026, 6, 2), 1, 2, 1, 1);
+        let usage = &ledger.circuits["c1"];
+        assert_eq!(usage.history.len(), 2);
+        assert_eq!(usage.history[0].period_start, date(2026, 5, 1));
+    }
+}
@@ -0,0 +1,529 @@
+//! Per-circuit data usage accounting and quota enforcement.
+//!
+//! When `[usage_quotas] enabled` is set, the throughput tracker hands every
+//! circuit's byte counts to `record` once a second. A background thread folds
+//! them into a ledger of billing periods (anchored on a configurable day of
+//! the month), saved as `usage_ledger.json` in the LibreQoS directory.
+//!
+//! Each pass also checks the quota policies. A circuit that has used more
+//! than its cap is held to the policy's fallback rates through the
+//! `bandwidth_schedules` overlay, which moves the class live and keeps the
+//! throttle across reloads. The throttle lifts when the circuit enters a new
+//! billing period or stops being covered by a policy.
+
+mod ledger;
+
+use crate::bandwidth_schedules::{self, QuotaThrottle};
+use crate::shaped_devices_tracker::SHAPED_DEVICES;
+use crate::{audit, urgent};
+use chrono::NaiveDate;
+use fxhash::FxHashMap;
+use ledger::{CircuitUsage, PeriodUsage, UsageLedger};
+use lqos_bus::{UrgentSeverity, UrgentSource};
+use lqos_config::{AuditActor, AuditEntry, QuotaDirection, QuotaPolicy, UsageQuotaConfig};
+use lqos_utils::units::DownUpOrder;
+use lqos_utils::unix_time::unix_now;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often usage is folded into the ledger and quotas are checked.
+const TICK_INTERVAL: Duration = Duration::from_secs(60);
+/// The ledger is written every this many ticks, or sooner if a period
+/// rolled over or a throttle changed.
+const SAVE_EVERY_TICKS: u32 = 5;
+const LEDGER_FILE: &str = "usage_ledger.json";
+const URGENT_CODE: &str = "USAGE_QUOTA_ENFORCEMENT_FAILED";
+
+/// Bytes by circuit hash, accumulated since the last tick.
+static PENDING: Lazy<Mutex<FxHashMap<i64, DownUpOrder<u64>>>> =
+    Lazy::new(|| Mutex::new(FxHashMap::default()));
+static LEDGER: Lazy<Mutex<UsageLedger>> = Lazy::new(|| Mutex::new(UsageLedger::default()));
+
+/// One circuit's usage in one billing period, for the UI and CSV export.
+#[derive(Clone, Debug, Serialize)]
+pub struct UsageLedgerRow {
+    pub circuit_id: String,
+    pub circuit_name: String,
+    pub parent_node: String,
+    pub period_start: String,
+    pub period_end: String,
+    /// False for closed periods.
+    pub current: bool,
+    pub download_bytes: u64,
+    pub upload_bytes: u64,
+    pub policy: Option<String>,
+    pub cap_bytes: Option<u64>,
+    /// Share of the cap used, counting only the policy's direction.
+    pub percent_used: Option<f64>,
+    pub throttled: bool,
+    pub throttled_since: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct CircuitInfo {
+    circuit_id: String,
+    circuit_name: String,
+    parent_node: String,
+}
+
+/// Throttle changes from one enforcement pass.
+#[derive(Debug, Default)]
+struct Enforcement {
+    /// Circuits to hold at fallback rates, keyed by circuit hash.
+    throttles: HashMap<i64, QuotaThrottle>,
+    /// (circuit ID, policy name) of circuits throttled during this pass.
+    started: Vec<(String, String)>,
+    /// Circuits whose throttle lifted during this pass.
+    ended: Vec<String>,
+}
+
+/// Is usage being accounted?
+pub(crate) fn is_enabled() -> bool {
+    lqos_config::load_config().is_ok_and(|config| config.usage_quotas.enabled)
+}
+
+/// Called once per throughput tick with each circuit's bytes for that tick.
+pub(crate) fn record(deltas: FxHashMap<i64, DownUpOrder<u64>>) {
+    let mut pending = PENDING.lock();
+    for (circuit_hash, bytes) in deltas {
+        let entry = pending.entry(circuit_hash).or_default();
+        entry.down = entry.down.saturating_add(bytes.down);
+        entry.up = entry.up.saturating_add(bytes.up);
+    }
+}
+
+/// Loads the saved ledger and starts the accounting thread.
+pub fn start_usage_quotas() -> anyhow::Result<()> {
+    *LEDGER.lock() = load_ledger();
+    std::thread::Builder::new()
+        .name("Usage Quotas".to_string())
+        .spawn(|| {
+            let mut ticks_since_save = 0;
+            let mut throttling = false;
+            loop {
+                std::thread::sleep(TICK_INTERVAL);
+                ticks_since_save += 1;
+                if tick(&mut throttling) || ticks_since_save >= SAVE_EVERY_TICKS {
+                    save_ledger(&LEDGER.lock());
+                    ticks_since_save = 0;
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// One accounting and enforcement pass. Returns true if the ledger should be
+/// saved right away.
+fn tick(throttling: &mut bool) -> bool {
+    let Ok(config) = lqos_config::load_config() else {
+        return false;
+    };
+    let quotas = &config.usage_quotas;
+    let pending = std::mem::take(&mut *PENDING.lock());
+    if !quotas.enabled {
+        if *throttling {
+            *throttling = false;
+            apply_throttles(HashMap::new());
+        }
+        return false;
+    }
+    let circuits = shaped_circuits();
+    let today = chrono::Local::now().date_naive();
+    let now = unix_now().unwrap_or(0);
+    let (enforcement, rolled) = {
+        let mut ledger = LEDGER.lock();
+        add_usage(&mut ledger, quotas, &circuits, pending, today);
+        let rolled = roll_periods(&mut ledger, quotas, &circuits, today);
+        (enforce(&mut ledger, quotas, &circuits, now), rolled)
+    };
+    record_transitions(&enforcement);
+    let changed = rolled || !enforcement.started.is_empty() || !enforcement.ended.is_empty();
+    *throttling = !enforcement.throttles.is_empty();
+    apply_throttles(enforcement.throttles);
+    changed
+}
+
+fn shaped_circuits() -> FxHashMap<i64, CircuitInfo> {
+    let shaped = SHAPED_DEVICES.load();
+    let mut circuits = FxHashMap::default();
+    for device in &shaped.devices {
+        circuits
+            .entry(device.circuit_hash)
+            .or_insert_with(|| CircuitInfo {
+                circuit_id: device.circuit_id.clone(),
+                circuit_name: device.circuit_name.clone(),
+                parent_node: device.parent_node.clone(),
+            });
+    }
+    circuits
+}
+
+fn anchor_day(quotas: &UsageQuotaConfig, circuit: &CircuitInfo) -> u8 {
+    quotas.anchor_day_for(quotas.policy_for(&circuit.circuit_id, &circuit.parent_node))
+}
+
+/// Adds pending bytes to each circuit's current period. Traffic for circuits
+/// that are no longer shaped is dropped.
+fn add_usage(
+    ledger: &mut UsageLedger,
+    quotas: &UsageQuotaConfig,
+    circuits: &FxHashMap<i64, CircuitInfo>,
+    pending: FxHashMap<i64, DownUpOrder<u64>>,
+    today: NaiveDate,
+) {
+    for (circuit_hash, bytes) in pending {
+        let Some(circuit) = circuits.get(&circuit_hash) else {
+            continue;
+        };
+        ledger.add(
+            &circuit.circuit_id,
+            today,
+            anchor_day(quotas, circuit),
+            quotas.history_periods,
+            bytes.down,
+            bytes.up,
+        );
+    }
+}
+
+fn roll_periods(
+    ledger: &mut UsageLedger,
+    quotas: &UsageQuotaConfig,
+    circuits: &FxHashMap<i64, CircuitInfo>,
+    today: NaiveDate,
+) -> bool {
+    let by_id: HashMap<&str, &CircuitInfo> = circuits
+        .values()
+        .map(|circuit| (circuit.circuit_id.as_str(), circuit))
+        .collect();
+    let rolled = ledger.roll_all(
+        today,
+        |circuit_id| match by_id.get(circuit_id) {
+            Some(circuit) => anchor_day(quotas, circuit),
+            None => quotas.billing_anchor_day,
+        },
+        quotas.history_periods,
+    );
+    for circuit_id in &rolled {
+        info!("Usage for circuit '{circuit_id}' rolled into a new billing period");
+    }
+    !rolled.is_empty()
+}
+
+fn used_bytes(period: &PeriodUsage, direction: QuotaDirection) -> u64 {
+    match direction {
+        QuotaDirection::Total => period.total_bytes(),
+        QuotaDirection::Download => period.download_bytes,
+        QuotaDirection::Upload => period.upload_bytes,
+    }
+}
+
+fn over_quota(usage: &CircuitUsage, policy: &QuotaPolicy) -> bool {
+    used_bytes(&usage.current, policy.direction) > policy.cap_bytes()
+}
+
+/// Decides which circuits are over quota and updates their throttle state.
+fn enforce(
+    ledger: &mut UsageLedger,
+    quotas: &UsageQuotaConfig,
+    circuits: &FxHashMap<i64, CircuitInfo>,
+    now: u64,
+) -> Enforcement {
+    let mut enforcement = Enforcement::default();
+    let mut still_throttled = Vec::new();
+    for (circuit_hash, circuit) in circuits {
+        let Some(policy) = quotas.policy_for(&circuit.circuit_id, &circuit.parent_node) else {
+            continue;
+        };
+        let Some(usage) = ledger.circuits.get_mut(&circuit.circuit_id) else {
+            continue;
+        };
+        if !over_quota(usage, policy) {
+            continue;
+        }
+        if usage.throttled_since.is_none() {
+            usage.throttled_since = Some(now);
+            enforcement
+                .started
+                .push((circuit.circuit_id.clone(), policy.name.clone()));
+        }
+        still_throttled.push(circuit.circuit_id.as_str());
+        enforcement.throttles.insert(
+            *circuit_hash,
+            QuotaThrottle {
+                download_max: policy.fallback_download_mbps,
+                upload_max: policy.fallback_upload_mbps,
+            },
+        );
+    }
+    for (circuit_id, usage) in ledger.circuits.iter_mut() {
+        if usage.throttled_since.is_some() && !still_throttled.contains(&circuit_id.as_str()) {
+            usage.throttled_since = None;
+            enforcement.ended.push(circuit_id.clone());
+        }
+    }
+    enforcement
+}
+
+fn record_transitions(enforcement: &Enforcement) {
+    let actor = AuditActor::subsystem("usage_quotas");
+    for (circuit_id, policy) in &enforcement.started {
+        info!("Circuit '{circuit_id}' exceeded quota '{policy}' and is now throttled");
+        audit::record(
+            AuditEntry::new(actor.clone(), "quota_throttle_started")
+                .target(circuit_id.clone())
+                .detail(policy.clone()),
+        );
+    }
+    for circuit_id in &enforcement.ended {
+        info!("Quota throttle for circuit '{circuit_id}' lifted");
+        audit::record(
+            AuditEntry::new(actor.clone(), "quota_throttle_ended").target(circuit_id.clone()),
+        );
+    }
+}
+
+fn apply_throttles(throttles: HashMap<i64, QuotaThrottle>) {
+    let count = throttles.len();
+    if let Err(e) = bandwidth_schedules::set_quota_throttles(throttles) {
+        let message = format!("Unable to apply data quota throttles: {e}");
+        warn!("{message}");
+        urgent::submit(
+            UrgentSource::System,
+            UrgentSeverity::Error,
+            URGENT_CODE.to_string(),
+            message,
+            Some(format!("{count} circuits are over quota")),
+            Some("usage_quotas_apply".to_string()),
+        );
+    }
+}
+
+fn ledger_path() -> Option<PathBuf> {
+    let config = lqos_config::load_config().ok()?;
+    Some(PathBuf::from(&config.lqos_directory).join(LEDGER_FILE))
+}
+
+fn load_ledger() -> UsageLedger {
+    let Some(path) = ledger_path() else {
+        return UsageLedger::default();
+    };
+    let Ok(raw) = std::fs::read_to_string(&path) else {
+        return UsageLedger::default();
+    };
+    serde_json::from_str(&raw).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable {}: {e}", path.display());
+        UsageLedger::default()
+    })
+}
+
+fn save_ledger(ledger: &UsageLedger) {
+    let Some(path) = ledger_path() else {
+        return;
+    };
+    let raw = match serde_json::to_string(ledger) {
+        Ok(raw) => raw,
+        Err(e) => {
+            warn!("Unable to serialize the usage ledger: {e}");
+            return;
+        }
+    };
+    let tmp = path.with_extension("json.tmp");
+    if let Err(e) = std::fs::write(&tmp, raw).and_then(|_| std::fs::rename(&tmp, &path)) {
+        warn!("Unable to write {}: {e}", path.display());
+    }
+}
+
+/// Usage rows for every circuit in the ledger, current period first.
+/// Closed periods are included when `history` is set.
+pub fn usage_rows(history: bool) -> Vec<UsageLedgerRow> {
+    let config = lqos_config::load_config().ok();
+    let quotas = config
+        .as_ref()
+        .map(|config| config.usage_quotas.clone())
+        .unwrap_or_default();
+    let circuits: HashMap<String, CircuitInfo> = shaped_circuits()
+        .into_values()
+        .map(|circuit| (circuit.circuit_id.clone(), circuit))
+        .collect();
+    let ledger = LEDGER.lock();
+    let mut rows = Vec::new();
+    for (circuit_id, usage) in &ledger.circuits {
+        let (circuit_name, parent_node) = circuits
+            .get(circuit_id)
+            .map(|c| (c.circuit_name.clone(), c.parent_node.clone()))
+            .unwrap_or_default();
+        let policy = quotas.policy_for(circuit_id, &parent_node);
+        let row = |period: &PeriodUsage, current: bool| UsageLedgerRow {
+            circuit_id: circuit_id.clone(),
+            circuit_name: circuit_name.clone(),
+            parent_node: parent_node.clone(),
+            period_start: period.period_start.to_string(),
+            period_end: period.period_end.to_string(),
+            current,
+            download_bytes: period.download_bytes,
+            upload_bytes: period.upload_bytes,
+            policy: policy.map(|p| p.name.clone()),
+            cap_bytes: policy.map(QuotaPolicy::cap_bytes),
+            percent_used: policy.map(|p| {
+                used_bytes(period, p.direction) as f64 * 100.0 / p.cap_bytes().max(1) as f64
+            }),
+            throttled: current && usage.throttled_since.is_some(),
+            throttled_since: usage.throttled_since.filter(|_| current),
+        };
+        rows.push(row(&usage.current, true));
+        if history {
+            rows.extend(usage.history.iter().map(|period| row(period, false)));
+        }
+    }
+    rows
+}
+
+/// Every recorded period as CSV. `month` (`YYYY-MM`) keeps only periods that
+/// start in that month.
+pub fn usage_csv(month: Option<&str>) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let _ = writer.write_record([
+        "Circuit ID",
+        "Circuit Name",
+        "Parent Node",
+        "Period Start",
+        "Period End",
+        "Download (bytes)",
+        "Upload (bytes)",
+        "Total (bytes)",
+        "Policy",
+        "Cap (bytes)",
+        "Throttled",
+    ]);
+    for row in usage_rows(true)
+        .into_iter()
+        .filter(|row| month.is_none_or(|month| row.period_start.starts_with(month)))
+    {
+        let _ = writer.write_record([
+            row.circuit_id,
+            row.circuit_name,
+            row.parent_node,
+            row.period_start,
+            row.period_end,
+            row.download_bytes.to_string(),
+            row.upload_bytes.to_string(),
+            row.download_bytes
+                .saturating_add(row.upload_bytes)
+                .to_string(),
+            row.policy.unwrap_or_default(),
+            row.cap_bytes.map(|cap| cap.to_string()).unwrap_or_default(),
+            row.throttled.to_string(),
+        ]);
+    }
+    writer
+        .into_inner()
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).expect("valid date")
+    }
+
+    fn quotas() -> UsageQuotaConfig {
+        UsageQuotaConfig {
+            enabled: true,
+            policies: vec![QuotaPolicy {
+                name: "basic".to_string(),
+                circuit_ids: Vec::new(),
+                parent_nodes: vec!["Tower".to_string()],
+                billing_anchor_day: None,
+                cap_gigabytes: 1.0,
+                direction: QuotaDirection::Download,
+                fallback_download_mbps: 5.0,
+                fallback_upload_mbps: 1.0,
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn circuit(circuit_id: &str, parent_node: &str) -> (i64, CircuitInfo) {
+        (
+            lqos_utils::hash_to_i64(circuit_id),
+            CircuitInfo {
+                circuit_id: circuit_id.to_string(),
+                circuit_name: circuit_id.to_uppercase(),
+                parent_node: parent_node.to_string(),
+            },
+        )
+    }
+
+    #[test]
+    fn circuits_over_quota_are_throttled_until_the_period_rolls() {
+        let quotas = quotas();
+        let circuits: FxHashMap<i64, CircuitInfo> =
+            [circuit("c1", "Tower"), circuit("c2", "Elsewhere")]
+                .into_iter()
+                .collect();
+        let c1 = lqos_utils::hash_to_i64("c1");
+        let c2 = lqos_utils::hash_to_i64("c2");
+        let mut ledger = UsageLedger::default();
+        let over = |down| -> FxHashMap<i64, DownUpOrder<u64>> {
+            [c1, c2]
+                .into_iter()
+                .map(|hash| (hash, DownUpOrder::new(down, 2_000_000_000)))
+                .collect()
+        };
+
+        // Upload doesn't count towards a download-only cap.
+        add_usage(
+            &mut ledger,
+            &quotas,
+            &circuits,
+            over(900_000_000),
+            date(2026, 3, 10),
+        );
+        let enforcement = enforce(&mut ledger, &quotas, &circuits, 100);
+        assert!(enforcement.throttles.is_empty());
+
+        add_usage(
+            &mut ledger,
+            &quotas,
+            &circuits,
+            over(200_000_000),
+            date(2026, 3, 11),
+        );
+        let enforcement = enforce(&mut ledger, &quotas, &circuits, 200);
+        assert_eq!(
+            enforcement.started,
+            vec![("c1".to_string(), "basic".to_string())]
+        );
+        assert_eq!(
+            enforcement.throttles.get(&c1),
+            Some(&QuotaThrottle {
+                download_max: 5.0,
+                upload_max: 1.0
+            })
+        );
+        assert!(!enforcement.throttles.contains_key(&c2));
+        let enforcement = enforce(&mut ledger, &quotas, &circuits, 300);
+        assert!(enforcement.started.is_empty());
+        assert_eq!(ledger.circuits["c1"].throttled_since, Some(200));
+
+        assert!(roll_periods(
+            &mut ledger,
+            &quotas,
+            &circuits,
+            date(2026, 4, 1)
+        ));
+        let enforcement = enforce(&mut ledger, &quotas, &circuits, 400);
+        assert!(enforcement.throttles.is_empty());
+        assert_eq!(enforcement.ended, vec!["c1".to_string()]);
+    }
+}
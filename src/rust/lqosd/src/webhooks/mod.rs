@@ -0,0 +1,438 @@
+//! Outbound webhook notifications.
+//!
+//! A dispatcher thread subscribes to the bus topics behind the event kinds
+//! the `[webhooks]` destinations ask for, turns each update into
+//! notifications and hands them to one sender thread per destination. The
+//! sender POSTs them in the destination's format, signs them when a secret
+//! is set and retries with exponential backoff, so a slow or unreachable
+//! destination only delays its own notifications. Configuration is re-read
+//! for every update and at least every `CONFIG_POLL_INTERVAL`.
+
+mod payload;
+mod sender;
+
+use crate::treeguard::actor::activity_changes_state;
+use lqos_bus::{
+    BakeryEvent, BusTopic, BusUpdate, CircuitStatusChange, LocalBusSubscription, StormGuardAction,
+    TreeGuardDecision, UrgentIssue, UrgentSeverity, subscribe_bus_updates,
+};
+use lqos_config::{WebhookEventKind, WebhookSeverity, WebhooksConfig};
+use lqos_utils::unix_time::unix_now;
+use payload::NodeInfo;
+use sender::DestinationWorker;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Circuit changes from a single check beyond this many are summarized in
+/// one notification, so an outage upstream doesn't send hundreds.
+const MAX_CIRCUIT_NOTIFICATIONS: usize = 10;
+
+/// Something worth telling a webhook about.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Notification {
+    pub(crate) kind: WebhookEventKind,
+    pub(crate) severity: WebhookSeverity,
+    /// Stable short code, e.g. `circuit_down` or an urgent issue's code.
+    pub(crate) code: String,
+    pub(crate) title: String,
+    pub(crate) message: String,
+    /// Unix timestamp in seconds.
+    pub(crate) ts: u64,
+    /// Extra detail, in display order.
+    pub(crate) fields: Vec<(&'static str, String)>,
+}
+
+/// Starts the dispatcher thread. It is idle unless `[webhooks]` is enabled
+/// with at least one destination.
+pub fn start_webhooks() -> anyhow::Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()?;
+    std::thread::Builder::new()
+        .name("Webhooks".to_string())
+        .spawn(move || {
+            let mut active: Option<Dispatcher> = None;
+            loop {
+                let wanted = wanted_settings();
+                if active.as_ref().map(|d| &d.settings) != wanted.as_ref() {
+                    active = wanted.map(Dispatcher::start);
+                }
+                let Some(dispatcher) = active.as_mut() else {
+                    std::thread::sleep(CONFIG_POLL_INTERVAL);
+                    continue;
+                };
+                let next =
+                    tokio::time::timeout(CONFIG_POLL_INTERVAL, dispatcher.subscription.recv());
+                match runtime.block_on(next) {
+                    Ok(Some(update)) => dispatcher.handle(&update),
+                    Ok(None) => {
+                        warn!("Bus updates have stopped; webhook dispatcher exiting");
+                        return;
+                    }
+                    // Quiet for a while; check whether the config changed.
+                    Err(_) => {}
+                }
+            }
+        })?;
+    Ok(())
+}
+
+fn wanted_settings() -> Option<(WebhooksConfig, NodeInfo)> {
+    let config = lqos_config::load_config().ok()?;
+    if !config.webhooks.enabled || config.webhooks.destinations.is_empty() {
+        return None;
+    }
+    let node = NodeInfo {
+        node_id: config.node_id.clone(),
+        node_name: config.node_name.clone(),
+    };
+    Some((config.webhooks.clone(), node))
+}
+
+struct Dispatcher {
+    settings: (WebhooksConfig, NodeInfo),
+    subscription: LocalBusSubscription,
+    workers: Vec<DestinationWorker>,
+}
+
+impl Dispatcher {
+    fn start(settings: (WebhooksConfig, NodeInfo)) -> Self {
+        let (config, node) = &settings;
+        let mut workers = Vec::new();
+        let mut topics = Vec::new();
+        for destination in &config.destinations {
+            match DestinationWorker::start(destination.clone(), node.clone()) {
+                Ok(worker) => workers.push(worker),
+                Err(e) => warn!("Unable to start webhook {}: {e:?}", destination.name),
+            }
+            for topic in destination.events.iter().map(|kind| topic_for(*kind)) {
+                if !topics.contains(&topic) {
+                    topics.push(topic);
+                }
+            }
+        }
+        info!(
+            "Sending webhook notifications to {} destination(s)",
+            workers.len()
+        );
+        Self {
+            subscription: subscribe_bus_updates(&topics),
+            settings,
+            workers,
+        }
+    }
+
+    fn handle(&self, update: &BusUpdate) {
+        if let BusUpdate::Lagged { missed } = update {
+            warn!("Webhook dispatcher fell behind and skipped {missed} updates");
+            return;
+        }
+        for notification in notifications_for(update) {
+            let notification = Arc::new(notification);
+            for worker in &self.workers {
+                worker.offer(&notification);
+            }
+        }
+    }
+}
+
+fn topic_for(kind: WebhookEventKind) -> BusTopic {
+    match kind {
+        WebhookEventKind::Urgent => BusTopic::UrgentIssues,
+        WebhookEventKind::Bakery => BusTopic::Bakery,
+        WebhookEventKind::Treeguard => BusTopic::TreeGuard,
+        WebhookEventKind::Stormguard => BusTopic::StormGuard,
+        WebhookEventKind::Circuit => BusTopic::CircuitStatus,
+    }
+}
+
+/// The notifications an update should produce. Most updates produce none.
+fn notifications_for(update: &BusUpdate) -> Vec<Notification> {
+    match update {
+        BusUpdate::UrgentIssue(issue) => vec![urgent(issue)],
+        BusUpdate::BakeryEvent(event) => bakery(event).into_iter().collect(),
+        BusUpdate::TreeGuardDecision(decision) => treeguard(decision).into_iter().collect(),
+        BusUpdate::StormGuardAction(action) => vec![stormguard(action)],
+        BusUpdate::CircuitStatus(changes) => circuits(changes),
+        _ => Vec::new(),
+    }
+}
+
+fn urgent(issue: &UrgentIssue) -> Notification {
+    let mut fields = vec![("source", format!("{:?}", issue.source))];
+    if let Some(context) = &issue.context {
+        fields.push(("context", context.clone()));
+    }
+    Notification {
+        kind: WebhookEventKind::Urgent,
+        severity: match issue.severity {
+            UrgentSeverity::Error => WebhookSeverity::Error,
+            UrgentSeverity::Warning => WebhookSeverity::Warning,
+        },
+        code: issue.code.clone(),
+        title: format!("Urgent issue: {}", issue.code),
+        message: issue.message.clone(),
+        ts: issue.ts,
+        fields,
+    }
+}
+
+/// Only the reload-required state is reported; the rest of the Bakery's
+/// activity log is routine.
+fn bakery(event: &BakeryEvent) -> Option<Notification> {
+    let (severity, title) = match event.event.as_str() {
+        "reload_required" => (WebhookSeverity::Error, "Bakery needs a full reload"),
+        "reload_required_cleared" => (WebhookSeverity::Info, "Bakery no longer needs a reload"),
+        _ => return None,
+    };
+    let fields = event
+        .site_name
+        .iter()
+        .map(|site| ("site", site.clone()))
+        .collect();
+    Some(Notification {
+        kind: WebhookEventKind::Bakery,
+        severity,
+        code: event.event.clone(),
+        title: title.to_string(),
+        message: event.summary.clone(),
+        ts: event.ts,
+        fields,
+    })
+}
+
+/// Changes TreeGuard made, and changes it failed to make. Skipped and
+/// deferred actions are left out.
+fn treeguard(decision: &TreeGuardDecision) -> Option<Notification> {
+    let (severity, title) = if decision.action.contains("failed") {
+        (
+            WebhookSeverity::Warning,
+            format!("TreeGuard action failed: {}", decision.action),
+        )
+    } else if activity_changes_state(&decision.action) {
+        (
+            WebhookSeverity::Info,
+            format!("TreeGuard: {}", decision.action),
+        )
+    } else {
+        return None;
+    };
+    Some(Notification {
+        kind: WebhookEventKind::Treeguard,
+        severity,
+        code: decision.action.clone(),
+        title,
+        message: decision.reason.clone(),
+        ts: unix_now().unwrap_or_default(),
+        fields: vec![
+            (
+                "target",
+                format!("{}:{}", decision.entity_type, decision.entity_id),
+            ),
+            ("persisted", decision.persisted.to_string()),
+        ],
+    })
+}
+
+fn stormguard(action: &StormGuardAction) -> Notification {
+    let title = if action.dry_run {
+        format!("StormGuard (dry run) would adjust {}", action.site)
+    } else {
+        format!("StormGuard adjusted {}", action.site)
+    };
+    Notification {
+        kind: WebhookEventKind::Stormguard,
+        severity: WebhookSeverity::Info,
+        code: if action.dry_run {
+            "stormguard_dry_run".to_string()
+        } else {
+            "stormguard_change".to_string()
+        },
+        title,
+        message: action.summary.clone(),
+        ts: action.ts,
+        fields: vec![
+            ("site", action.site.clone()),
+            ("download_mbps", action.download_mbps.to_string()),
+            ("upload_mbps", action.upload_mbps.to_string()),
+        ],
+    }
+}
+
+fn circuits(changes: &[CircuitStatusChange]) -> Vec<Notification> {
+    let ts = unix_now().unwrap_or_default();
+    if changes.len() <= MAX_CIRCUIT_NOTIFICATIONS {
+        return changes.iter().map(|change| circuit(change, ts)).collect();
+    }
+    let (up, down): (Vec<_>, Vec<_>) = changes.iter().partition(|change| change.online);
+    let mut notifications = Vec::new();
+    if !down.is_empty() {
+        notifications.push(circuit_summary(&down, false, ts));
+    }
+    if !up.is_empty() {
+        notifications.push(circuit_summary(&up, true, ts));
+    }
+    notifications
+}
+
+fn circuit(change: &CircuitStatusChange, ts: u64) -> Notification {
+    let (severity, code, title, message) = if change.online {
+        (
+            WebhookSeverity::Info,
+            "circuit_up",
+            format!("Circuit up: {}", change.circuit_name),
+            format!("{} is passing traffic again.", change.circuit_name),
+        )
+    } else {
+        (
+            WebhookSeverity::Warning,
+            "circuit_down",
+            format!("Circuit down: {}", change.circuit_name),
+            format!(
+                "{} has passed no traffic for {} seconds.",
+                change.circuit_name, change.idle_seconds
+            ),
+        )
+    };
+    Notification {
+        kind: WebhookEventKind::Circuit,
+        severity,
+        code: code.to_string(),
+        title,
+        message,
+        ts,
+        fields: vec![
+            ("circuit_id", change.circuit_id.clone()),
+            ("parent_node", change.parent_node.clone()),
+        ],
+    }
+}
+
+fn circuit_summary(changes: &[&CircuitStatusChange], online: bool, ts: u64) -> Notification {
+    let mut names: Vec<&str> = changes
+        .iter()
+        .take(MAX_CIRCUIT_NOTIFICATIONS)
+        .map(|change| change.circuit_name.as_str())
+        .collect();
+    let more = changes.len() - names.len();
+    let more = (more > 0).then(|| format!("and {more} more"));
+    names.extend(more.as_deref());
+    let mut parents: Vec<&str> = changes
+        .iter()
+        .map(|change| change.parent_node.as_str())
+        .collect();
+    parents.sort_unstable();
+    parents.dedup();
+    let (severity, code, title) = if online {
+        (
+            WebhookSeverity::Info,
+            "circuits_up",
+            format!("{} circuits up", changes.len()),
+        )
+    } else {
+        (
+            WebhookSeverity::Warning,
+            "circuits_down",
+            format!("{} circuits down", changes.len()),
+        )
+    };
+    Notification {
+        kind: WebhookEventKind::Circuit,
+        severity,
+        code: code.to_string(),
+        title,
+        message: names.join(", "),
+        ts,
+        fields: vec![("parent_nodes", parents.join(", "))],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lqos_bus::UrgentSource;
+
+    fn change(circuit_id: &str, online: bool) -> CircuitStatusChange {
+        CircuitStatusChange {
+            circuit_id: circuit_id.to_string(),
+            circuit_name: format!("Customer {circuit_id}"),
+            parent_node: "Tower".to_string(),
+            online,
+            idle_seconds: if online { 0 } else { 600 },
+        }
+    }
+
+    #[test]
+    fn updates_map_to_notifications() {
+        let issue = UrgentIssue {
+            id: 1,
+            ts: 10,
+            source: UrgentSource::System,
+            severity: UrgentSeverity::Warning,
+            code: "TC_U16_OVERFLOW".to_string(),
+            message: "Too many queues".to_string(),
+            context: None,
+            dedupe_key: None,
+        };
+        let urgent = notifications_for(&BusUpdate::UrgentIssue(issue));
+        assert_eq!(urgent[0].severity, WebhookSeverity::Warning);
+        assert_eq!(urgent[0].code, "TC_U16_OVERFLOW");
+
+        let bakery = |event: &str| {
+            notifications_for(&BusUpdate::BakeryEvent(BakeryEvent {
+                ts: 1,
+                event: event.to_string(),
+                status: "info".to_string(),
+                site_hash: None,
+                site_name: None,
+                summary: String::new(),
+            }))
+        };
+        assert_eq!(
+            bakery("reload_required")[0].severity,
+            WebhookSeverity::Error
+        );
+        assert_eq!(
+            bakery("reload_required_cleared")[0].severity,
+            WebhookSeverity::Info
+        );
+        assert!(bakery("full_reload_trigger").is_empty());
+
+        let treeguard = |action: &str| {
+            notifications_for(&BusUpdate::TreeGuardDecision(TreeGuardDecision {
+                time: String::new(),
+                entity_type: "node".to_string(),
+                entity_id: "Tower".to_string(),
+                action: action.to_string(),
+                persisted: true,
+                reason: "idle".to_string(),
+            }))
+        };
+        assert_eq!(treeguard("virtualize")[0].severity, WebhookSeverity::Info);
+        assert_eq!(
+            treeguard("unvirtualize_failed")[0].severity,
+            WebhookSeverity::Warning
+        );
+        assert!(treeguard("skip_cooldown").is_empty());
+    }
+
+    #[test]
+    fn large_circuit_batches_are_summarized() {
+        let few: Vec<_> = (0..3).map(|i| change(&i.to_string(), false)).collect();
+        let notifications = circuits(&few);
+        assert_eq!(notifications.len(), 3);
+        assert_eq!(notifications[0].code, "circuit_down");
+        assert_eq!(notifications[0].severity, WebhookSeverity::Warning);
+
+        let mut many: Vec<_> = (0..25).map(|i| change(&i.to_string(), false)).collect();
+        many.push(change("back", true));
+        let notifications = circuits(&many);
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(notifications[0].title, "25 circuits down");
+        assert!(notifications[0].message.ends_with("and 15 more"));
+        assert_eq!(notifications[1].code, "circuits_up");
+        assert_eq!(notifications[1].severity, WebhookSeverity::Info);
+    }
+}
@@ -0,0 +1,169 @@
+//! Request bodies for each webhook format, and their signatures.
+
+use super::Notification;
+use hmac::{Hmac, Mac};
+use lqos_config::{WebhookFormat, WebhookSeverity};
+use serde_json::{Map, Value, json};
+use sha2::Sha256;
+
+/// Identifies the shaper a notification came from.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct NodeInfo {
+    pub(crate) node_id: String,
+    pub(crate) node_name: String,
+}
+
+/// Builds the body a destination of `format` expects.
+pub(crate) fn body(format: WebhookFormat, node: &NodeInfo, notification: &Notification) -> Value {
+    match format {
+        WebhookFormat::Json => json_body(node, notification),
+        WebhookFormat::Slack => slack_body(node, notification),
+        WebhookFormat::Teams => teams_body(node, notification),
+    }
+}
+
+fn json_body(node: &NodeInfo, notification: &Notification) -> Value {
+    let details: Map<String, Value> = notification
+        .fields
+        .iter()
+        .map(|(name, value)| (name.to_string(), Value::String(value.clone())))
+        .collect();
+    json!({
+        "version": 1,
+        "event": notification.kind,
+        "severity": notification.severity,
+        "code": notification.code,
+        "title": notification.title,
+        "message": notification.message,
+        "ts": notification.ts,
+        "node_id": node.node_id,
+        "node_name": node.node_name,
+        "details": details,
+    })
+}
+
+fn slack_body(node: &NodeInfo, notification: &Notification) -> Value {
+    let emoji = match notification.severity {
+        WebhookSeverity::Error => ":red_circle:",
+        WebhookSeverity::Warning => ":warning:",
+        WebhookSeverity::Info => ":information_source:",
+    };
+    let mut text = format!(
+        "{emoji} *{}* ({})\n{}",
+        notification.title, node.node_name, notification.message
+    );
+    for (name, value) in &notification.fields {
+        text.push_str(&format!("\n• *{name}*: {value}"));
+    }
+    json!({ "text": text })
+}
+
+fn teams_body(node: &NodeInfo, notification: &Notification) -> Value {
+    let color = match notification.severity {
+        WebhookSeverity::Error => "Attention",
+        WebhookSeverity::Warning => "Warning",
+        WebhookSeverity::Info => "Default",
+    };
+    let mut facts = vec![json!({ "title": "Node", "value": node.node_name })];
+    facts.extend(
+        notification
+            .fields
+            .iter()
+            .map(|(name, value)| json!({ "title": name, "value": value })),
+    );
+    json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": [
+                    {
+                        "type": "TextBlock",
+                        "text": notification.title,
+                        "weight": "Bolder",
+                        "size": "Medium",
+                        "color": color,
+                        "wrap": true,
+                    },
+                    { "type": "TextBlock", "text": notification.message, "wrap": true },
+                    { "type": "FactSet", "facts": facts },
+                ],
+            },
+        }],
+    })
+}
+
+/// `sha256=<hex>`: the HMAC-SHA256 of `"<timestamp>.<body>"` keyed with
+/// the destination's secret. Covering the timestamp lets receivers reject
+/// replayed requests.
+pub(crate) fn signature(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("sha256={digest}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lqos_config::WebhookEventKind;
+
+    fn node() -> NodeInfo {
+        NodeInfo {
+            node_id: "abc123".to_string(),
+            node_name: "Edge 1".to_string(),
+        }
+    }
+
+    fn notification() -> Notification {
+        Notification {
+            kind: WebhookEventKind::Circuit,
+            severity: WebhookSeverity::Warning,
+            code: "circuit_down".to_string(),
+            title: "Circuit down: Jane Doe".to_string(),
+            message: "No traffic for 600 seconds.".to_string(),
+            ts: 1_700_000_000,
+            fields: vec![("circuit_id", "c1".to_string())],
+        }
+    }
+
+    #[test]
+    fn each_format_carries_the_notification() {
+        let json = body(WebhookFormat::Json, &node(), &notification());
+        assert_eq!(json["event"], "circuit");
+        assert_eq!(json["severity"], "warning");
+        assert_eq!(json["node_id"], "abc123");
+        assert_eq!(json["details"]["circuit_id"], "c1");
+
+        let slack = body(WebhookFormat::Slack, &node(), &notification());
+        let text = slack["text"].as_str().expect("text");
+        assert!(text.starts_with(":warning: *Circuit down: Jane Doe* (Edge 1)"));
+        assert!(text.contains("*circuit_id*: c1"));
+
+        let teams = body(WebhookFormat::Teams, &node(), &notification());
+        let card = &teams["attachments"][0]["content"];
+        assert_eq!(card["type"], "AdaptiveCard");
+        assert_eq!(card["body"][0]["color"], "Warning");
+        assert_eq!(card["body"][2]["facts"][1]["value"], "c1");
+    }
+
+    #[test]
+    fn signature_matches_reference_hmac() {
+        // echo -n '1700000000.{}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(
+            signature("secret", 1_700_000_000, b"{}"),
+            "sha256=b8569b78799ff9e3cbff0fc2d63a33a2b57f3282abd07c37ae5e8e7d79a5f163"
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Delivers notifications to one destination from its own thread.
+
+use super::Notification;
+use super::payload::{NodeInfo, body, signature};
+use crossbeam_channel::{Sender, TrySendError};
+use lqos_config::WebhookDestination;
+use lqos_utils::unix_time::unix_now;
+use reqwest::StatusCode;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Notifications waiting for a destination before new ones are dropped.
+const QUEUE_DEPTH: usize = 256;
+/// Cap on the backoff between attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub(crate) enum PostError {
+    /// The request might succeed later (network failure, 5xx, 429).
+    #[error("webhook request failed (retryable): {0}")]
+    Retryable(String),
+    /// The receiver rejected the request; retrying will not help.
+    #[error("webhook request rejected: {0}")]
+    Rejected(String),
+}
+
+/// Hands notifications to a destination's delivery thread. The thread
+/// exits once this is dropped and its queue is drained.
+pub(crate) struct DestinationWorker {
+    destination: WebhookDestination,
+    sender: Sender<Arc<Notification>>,
+}
+
+impl DestinationWorker {
+    pub(crate) fn start(destination: WebhookDestination, node: NodeInfo) -> anyhow::Result<Self> {
+        let client = WebhookClient::new(&destination, node)?;
+        let (sender, receiver) = crossbeam_channel::bounded::<Arc<Notification>>(QUEUE_DEPTH);
+        std::thread::Builder::new()
+            .name("Webhook Sender".to_string())
+            .spawn(move || {
+                while let Ok(notification) = receiver.recv() {
+                    client.deliver(&notification);
+                }
+                debug!("Webhook sender for {} exiting", client.destination.name);
+            })?;
+        Ok(Self {
+            destination,
+            sender,
+        })
+    }
+
+    /// Queues `notification` if this destination wants it. Never blocks.
+    pub(crate) fn offer(&self, notification: &Arc<Notification>) {
+        if !self
+            .destination
+            .accepts(notification.kind, notification.severity)
+        {
+            return;
+        }
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(notification.clone()) {
+            warn!(
+                "Webhook {} is not keeping up; dropping {} notification",
+                self.destination.name, notification.code
+            );
+        }
+    }
+}
+
+struct WebhookClient {
+    client: reqwest::blocking::Client,
+    destination: WebhookDestination,
+    node: NodeInfo,
+}
+
+impl WebhookClient {
+    fn new(destination: &WebhookDestination, node: NodeInfo) -> anyhow::Result<Self> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(destination.timeout_seconds))
+            .build()?;
+        Ok(Self {
+            client,
+            destination: destination.clone(),
+            node,
+        })
+    }
+
+    /// Sends a notification, retrying up to `max_retries` times with
+    /// exponential backoff. Returns `true` if it was delivered.
+    fn deliver(&self, notification: &Notification) -> bool {
+        let payload = body(self.destination.format, &self.node, notification).to_string();
+        let mut delay = Duration::from_secs(1);
+        for attempt in 0..=self.destination.max_retries {
+            match self.post(notification, &payload) {
+                Ok(()) => return true,
+                Err(PostError::Rejected(e)) => {
+                    warn!(
+                        "Webhook {} rejected {}: {e}",
+                        self.destination.name, notification.code
+                    );
+                    return false;
+                }
+                Err(PostError::Retryable(e)) => {
+                    debug!(
+                        "Webhook {} attempt {} failed: {e}",
+                        self.destination.name,
+                        attempt + 1
+                    );
+                    if attempt < self.destination.max_retries {
+                        std::thread::sleep(delay);
+                        delay = (delay * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+        }
+        warn!(
+            "Webhook {} unreachable; dropping {} notification",
+            self.destination.name, notification.code
+        );
+        false
+    }
+
+    /// Sends one request. The signature is recomputed for each attempt so
+    /// its timestamp stays current.
+    fn post(&self, notification: &Notification, payload: &str) -> Result<(), PostError> {
+        let timestamp = unix_now().unwrap_or_default();
+        let mut request = self
+            .client
+            .post(&self.destination.url)
+            .header("Content-Type", "application/json")
+            .header("X-LibreQoS-Event", notification.code.as_str())
+            .header("X-LibreQoS-Timestamp", timestamp.to_string())
+            .body(payload.to_string());
+        if let Some(secret) = &self.destination.secret {
+            request = request.header(
+                "X-LibreQoS-Signature",
+                signature(secret, timestamp, payload.as_bytes()),
+            );
+        }
+        let response = request
+            .send()
+            .map_err(|e| PostError::Retryable(e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let detail = format!("{status}: {}", response.text().unwrap_or_default());
+        if status.is_server_error()
+            || status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::REQUEST_TIMEOUT
+        {
+            Err(PostError::Retryable(detail))
+        } else {
+            Err(PostError::Rejected(detail))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::influxdb::test_server;
+    use lqos_config::{WebhookEventKind, WebhookFormat, WebhookSeverity};
+
+    fn client_for(url: &str, max_retries: u32) -> WebhookClient {
+        let destination = WebhookDestination {
+            name: "noc".to_string(),
+            url: format!("{url}/hook"),
+            format: WebhookFormat::Json,
+            min_severity: WebhookSeverity::Info,
+            events: WebhookEventKind::ALL.to_vec(),
+            secret: Some("secret".to_string()),
+            max_retries,
+            timeout_seconds: 5,
+        };
+        let node = NodeInfo {
+            node_id: "abc123".to_string(),
+            node_name: "Edge 1".to_string(),
+        };
+        WebhookClient::new(&destination, node).expect("client")
+    }
+
+    fn notification() -> Notification {
+        Notification {
+            kind: WebhookEventKind::Urgent,
+            severity: WebhookSeverity::Error,
+            code: "TC_U16_OVERFLOW".to_string(),
+            title: "Urgent issue: TC_U16_OVERFLOW".to_string(),
+            message: "Too many queues.".to_string(),
+            ts: 1,
+            fields: Vec::new(),
+        }
+    }
+
+    fn header<'a>(request: &'a test_server::ReceivedRequest, name: &str) -> Option<&'a str> {
+        request
+            .headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn retries_until_delivered_and_signs_each_attempt() {
+        let _ = lqos_utils::rustls::ensure_rustls_crypto_provider();
+        let (url, received) = test_server::spawn(vec![503, 200]);
+        assert!(client_for(&url, 1).deliver(&notification()));
+
+        let received = received.lock().expect("lock");
+        assert_eq!(received.len(), 2);
+        let request = &received[1];
+        assert!(request.request_line.starts_with("POST /hook "));
+        let timestamp: u64 = header(request, "x-libreqos-timestamp")
+            .expect("timestamp")
+            .parse()
+            .expect("numeric timestamp");
+        assert_eq!(
+            header(request, "x-libreqos-signature"),
+            Some(signature("secret", timestamp, request.body.as_bytes()).as_str())
+        );
+        let body: serde_json::Value = serde_json::from_str(&request.body).expect("json");
+        assert_eq!(body["code"], "TC_U16_OVERFLOW");
+    }
+
+    #[test]
+    fn rejected_requests_are_not_retried() {
+        let _ = lqos_utils::rustls::ensure_rustls_crypto_provider();
+        let (url, received) = test_server::spawn(vec![400]);
+        assert!(!client_for(&url, 3).deliver(&notification()));
+        assert_eq!(received.lock().expect("lock").len(), 1);
+    }
+}
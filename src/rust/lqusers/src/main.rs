@@ -1,8 +1,9 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use lqos_bus::{BusRequest, bus_request};
-use lqos_config::{UserRole, WebUsers};
+use lqos_config::{ApiTokenScope, UserRole, WebUsers};
 use std::process::exit;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Parser)]
 #[command()]
@@ -34,6 +35,37 @@ enum Commands {
     },
     /// List users
     List,
+    /// Manage API tokens for automation
+    Token {
+        #[command(subcommand)]
+        command: TokenCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokenCommands {
+    /// Create a token and print its secret
+    Add {
+        /// Description of what uses the token
+        #[arg(long)]
+        name: String,
+
+        /// Scope: read-only, shaped-devices-write, overrides-write or reload.
+        /// Repeat to grant several.
+        #[arg(long = "scope", required = true)]
+        scopes: Vec<ApiTokenScope>,
+
+        /// Days until the token expires. Omit for a token that never expires.
+        #[arg(long)]
+        expires_days: Option<u64>,
+    },
+    /// Revoke a token
+    Del {
+        /// Token id, as shown by `token list`
+        id: String,
+    },
+    /// List tokens
+    List,
 }
 
 fn notify_auth_cache_invalidated() {
@@ -70,6 +102,29 @@ fn main() -> Result<()> {
             println!("All Users\n");
             users.print_users()?;
         }
+        Some(Commands::Token { command }) => match command {
+            TokenCommands::Add {
+                name,
+                scopes,
+                expires_days,
+            } => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                let expires = expires_days.map(|days| now + days * 86_400);
+                let (token, secret) = users.create_api_token(&name, &scopes, expires)?;
+                notify_auth_cache_invalidated();
+                println!("Created token {}", token.id);
+                println!("{secret}");
+                println!("This secret will not be shown again.");
+            }
+            TokenCommands::Del { id } => {
+                users.revoke_api_token(&id)?;
+                notify_auth_cache_invalidated();
+            }
+            TokenCommands::List => {
+                println!("API Tokens\n");
+                users.print_api_tokens();
+            }
+        },
         None => {
             println!("Run with --help to see instructions");
             exit(0);